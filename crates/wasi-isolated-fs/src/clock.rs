@@ -5,11 +5,13 @@ use std::time::{Duration, Instant};
 use anyhow::Result as AnyResult;
 
 use crate::bindings::wasi;
-use crate::errors;
 
 const MAX_TIMEOUT: Duration = Duration::from_millis(100);
 
-#[derive(Debug)]
+/// Monotonic clock source.
+///
+/// Clones share the same epoch, so time observed through any of them never goes backwards.
+#[derive(Debug, Clone)]
 pub struct ClockController {
     epoch: Instant,
 }
@@ -31,34 +33,45 @@ impl ClockController {
         self.epoch.elapsed().as_nanos() as _
     }
 
-    pub fn poll_for(&self, dur: u64) -> AnyResult<ClockPollable> {
-        match Instant::now().checked_add(Duration::from_nanos(dur)) {
-            Some(until) => Ok(ClockPollable { until }),
-            None => Err(errors::MonotonicClockError.into()),
+    /// Pollable ready after `dur` nanoseconds.
+    pub fn poll_for(&self, dur: u64) -> ClockPollable {
+        ClockPollable {
+            until: deadline(Instant::now(), dur),
         }
     }
 
-    pub fn poll_until(&self, stamp: u64) -> AnyResult<ClockPollable> {
-        match self.epoch.checked_add(Duration::from_nanos(stamp)) {
-            Some(until) => Ok(ClockPollable { until }),
-            None => Err(errors::MonotonicClockError.into()),
+    /// Pollable ready at `stamp` nanoseconds since epoch.
+    pub fn poll_until(&self, stamp: u64) -> ClockPollable {
+        ClockPollable {
+            until: deadline(self.epoch, stamp),
         }
     }
 }
 
+/// Adds guest nanoseconds to instant.
+///
+/// Returns `None` if it's unrepresentable, which means deadline is never reached.
+pub(crate) fn deadline(t: Instant, nanos: u64) -> Option<Instant> {
+    t.checked_add(Duration::from_nanos(nanos))
+}
+
 #[derive(Debug)]
 pub struct ClockPollable {
-    pub(crate) until: Instant,
+    /// Deadline of pollable. `None` if it's too far in the future and never ready.
+    pub(crate) until: Option<Instant>,
 }
 
 impl ClockPollable {
     pub fn is_ready(&self) -> bool {
-        Instant::now() >= self.until
+        self.until.is_some_and(|t| Instant::now() >= t)
     }
 
     pub fn block(&self, timeout: Option<Instant>) -> AnyResult<()> {
         loop {
-            let d = self.until.saturating_duration_since(Instant::now());
+            let d = match self.until {
+                Some(t) => t.saturating_duration_since(Instant::now()),
+                None => MAX_TIMEOUT,
+            };
             if d.is_zero() {
                 return Ok(());
             }
@@ -90,3 +103,70 @@ impl wasi::clocks::timezone::Host for UTCClock {
         Ok(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(20);
+
+    fn assert_never(p: &ClockPollable) {
+        assert!(!p.is_ready());
+        let t = Instant::now();
+        let e = p.block(Some(t + TIMEOUT)).unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<IoError>().map(|e| e.kind()),
+            Some(ErrorKind::TimedOut)
+        );
+        assert!(t.elapsed() >= TIMEOUT);
+    }
+
+    #[test]
+    fn test_poll_zero() {
+        let clock = ClockController::new();
+        let p = clock.poll_for(0);
+        assert!(p.is_ready());
+        p.block(None).unwrap();
+        assert!(clock.poll_until(0).is_ready());
+    }
+
+    #[test]
+    fn test_poll_one_nanosecond() {
+        let clock = ClockController::new();
+        let p = clock.poll_for(1);
+        p.block(Some(Instant::now() + TIMEOUT)).unwrap();
+        assert!(p.is_ready());
+
+        let p = clock.poll_until(clock.now() + 1);
+        p.block(Some(Instant::now() + TIMEOUT)).unwrap();
+        assert!(p.is_ready());
+    }
+
+    #[test]
+    fn test_poll_far_future() {
+        let clock = ClockController::new();
+        for v in [u64::MAX / 2, u64::MAX - 1, u64::MAX] {
+            assert_never(&clock.poll_for(v));
+            assert_never(&clock.poll_until(v));
+        }
+    }
+
+    #[test]
+    fn test_deadline_overflow() {
+        let t = Instant::now();
+        assert_eq!(deadline(t, 0), Some(t));
+        assert_eq!(deadline(t, 1), Some(t + Duration::from_nanos(1)));
+        for v in [u64::MAX - 1, u64::MAX] {
+            // Does not panic, representable or not.
+            if let Some(d) = deadline(t, v) {
+                assert!(d > t);
+            }
+        }
+    }
+
+    #[test]
+    fn test_never_block_without_deadline() {
+        // Unrepresentable deadline still respects timeout.
+        assert_never(&ClockPollable { until: None });
+    }
+}
@@ -1,28 +1,33 @@
 use std::borrow::{Borrow, ToOwned};
 use std::collections::btree_map::{BTreeMap, Entry};
 use std::collections::hash_map::{HashMap, RandomState};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
-use anyhow::Result as AnyResult;
+use anyhow::{Context as _, Error as AnyError, Result as AnyResult};
 use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
 use cap_std::ambient_authority;
-use cap_std::fs::Dir as CapDir;
+use cap_std::fs::{Dir as CapDir, File as CapFile, OpenOptions};
 use rand::prelude::*;
 use rand::rngs::OsRng;
 use rand::TryRngCore;
 use rand_xoshiro::Xoshiro512StarStar;
+use tracing::warn;
 use wasmtime::component::Resource;
 
 use crate::bindings::wasi;
 use crate::clock::{ClockController, UTCClock};
 use crate::errors;
-use crate::fs_host::{CapWrapper as HostCapWrapper, Descriptor};
-use crate::fs_isolated::{AccessMode, CapWrapper, Dir, IsolatedFSController, Node, ILLEGAL_CHARS};
+use crate::fs_host::{normalize_host_path, CapWrapper as HostCapWrapper, Descriptor};
+use crate::fs_isolated::{
+    AccessMode, CapWrapper, Dir, File, IsolatedFSController, Node, ILLEGAL_CHARS, LINK_DEPTH,
+};
 use crate::items::Items;
 pub use crate::items::{Item, MaybeBorrowMut};
+use crate::nondet::NondetTap;
 use crate::preview1::{P1File, P1Item, P1Items};
-use crate::stdio::{HostStdin, HostStdout, NullStdio, StdinProvider, StdinSignal};
+use crate::stdio::{HostStdin, HostStdout, NullStdio, Pipe, StdinProvider, StdinSignal};
 
 pub struct WasiContext {
     pub(crate) hasher: RandomState,
@@ -30,6 +35,7 @@ pub struct WasiContext {
     pub(crate) items: Items,
     pub(crate) p1_items: P1Items,
     pub(crate) preopens: Vec<(Utf8PathBuf, FilePreopen)>,
+    pub(crate) host_files: HostFiles,
     pub(crate) cwd: Utf8PathBuf,
     pub(crate) envs: Vec<(String, String)>,
     pub(crate) args: Vec<String>,
@@ -40,6 +46,9 @@ pub struct WasiContext {
     pub(crate) stdin: Option<Stdin>,
     pub(crate) stdout: Option<Arc<dyn Send + Sync + HostStdout>>,
     pub(crate) stderr: Option<Arc<dyn Send + Sync + HostStdout>>,
+    pub(crate) nondet: Option<Arc<dyn NondetTap>>,
+    pub(crate) named_streams: Vec<(String, u32)>,
+    pub(crate) _tmp: Option<TmpDir>,
 
     pub(crate) timeout: Option<Instant>,
 }
@@ -47,32 +56,72 @@ pub struct WasiContext {
 pub struct WasiContextBuilder {
     iso_fs: BuilderIsoFS,
     fs_readonly: bool,
-    preopen_dirs: BTreeMap<Utf8PathBuf, (Utf8PathBuf, FilePreopenTy)>,
+    preopen_policy: PreopenPolicy,
+    preopen_dirs: BTreeMap<Utf8PathBuf, FilePreopenTy>,
+    preopen_files: BTreeMap<Utf8PathBuf, (PathBuf, bool)>,
     cwd: Utf8PathBuf,
     envs: HashMap<String, String>,
     args: Vec<String>,
+    clock: Option<ClockController>,
     clock_tz: Box<dyn Send + Sync + wasi::clocks::timezone::Host>,
     insecure_rng: Option<Box<dyn Send + Sync + RngCore>>,
     secure_rng: Option<Box<dyn Send + Sync + CryptoRng>>,
     stdin: Option<BuilderStdin>,
     stdout: Option<Arc<dyn Send + Sync + HostStdout>>,
     stderr: Option<Arc<dyn Send + Sync + HostStdout>>,
+    extra_streams: Vec<Pipe>,
+    nondet: Option<Arc<dyn NondetTap>>,
+    tmp_dir: Option<String>,
 }
 
 enum BuilderIsoFS {
     None,
-    New { max_size: usize, max_node: usize },
+    New {
+        max_size: usize,
+        max_node: usize,
+        link_depth: usize,
+    },
     Exist(IsolatedFSController),
 }
 
 enum BuilderStdin {
     Signal(Box<dyn Fn() + Send + Sync>),
+    /// Signal with bounded buffer and drain handler.
+    LimitedSignal(
+        Box<dyn Fn() + Send + Sync>,
+        usize,
+        Box<dyn Fn() + Send + Sync>,
+    ),
     Host(Arc<dyn Send + Sync + HostStdin>),
 }
 
+/// Behavior of host preopen with missing directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PreopenPolicy {
+    /// Fails the build.
+    #[default]
+    Strict,
+    /// Logs a warning and skips the preopen.
+    SkipMissing,
+}
+
+/// Options of host directory preopen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HostPreopenOptions {
+    /// Resolves path components case-insensitively if exact match does not exist.
+    ///
+    /// Useful for guests that assume case-insensitive filesystem.
+    /// Opening a path that matches more than one entry fails.
+    pub case_insensitive: bool,
+}
+
 enum FilePreopenTy {
-    IsoFS,
-    HostFS,
+    /// Isolated FS directory.
+    IsoFS(Utf8PathBuf),
+    /// Normalized host directory.
+    HostFS(PathBuf, HostPreopenOptions),
+    /// Read-only isolated FS directory, synthesized as parent of host files.
+    Synthetic(Utf8PathBuf),
 }
 
 pub(crate) enum FilePreopen {
@@ -89,6 +138,81 @@ impl<'a> From<&'a FilePreopen> for Item {
     }
 }
 
+/// Host files mounted into isolated FS.
+///
+/// Each file has a placeholder node in isolated FS, so it's listed in directory.
+/// Opening the placeholder opens host file instead.
+#[derive(Default)]
+pub(crate) struct HostFiles(pub(crate) Vec<(Arc<Node>, HostCapWrapper)>);
+
+impl HostFiles {
+    /// Resolves host file mounted at `path`, relative to `dir`.
+    pub(crate) fn resolve(
+        &self,
+        controller: &IsolatedFSController,
+        dir: &CapWrapper,
+        path: &Utf8Path,
+        follow_symlink: bool,
+    ) -> Option<&HostCapWrapper> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let v = dir
+            .open(controller, path, follow_symlink, None, AccessMode::NA)
+            .ok()?;
+        self.0
+            .iter()
+            .find(|(k, _)| Arc::ptr_eq(k, v.node()))
+            .map(|(_, v)| v)
+    }
+
+    /// Opens mounted host file with open flags.
+    ///
+    /// Fails with permission denied if writing to read-only file.
+    pub(crate) fn open(
+        file: &HostCapWrapper,
+        access: AccessMode,
+        exclusive: bool,
+        is_dir: bool,
+        is_truncate: bool,
+    ) -> Result<HostCapWrapper, errors::StreamError> {
+        if exclusive {
+            return Err(std::io::ErrorKind::AlreadyExists.into());
+        } else if is_dir {
+            return Err(std::io::ErrorKind::NotADirectory.into());
+        }
+        if access.is_write() || is_truncate {
+            file.write()?;
+        }
+        let ret = HostCapWrapper::new(file.desc().clone(), access & file.access());
+        if is_truncate {
+            ret.file()?.set_len(0)?;
+        }
+        Ok(ret)
+    }
+}
+
+/// Per-instance temporary directory, removed when context is dropped.
+///
+/// Nodes still held by open handles (eg. from shared context) are freed
+/// when the last handle is closed, returning their quota then.
+pub(crate) struct TmpDir {
+    parent: Arc<Node>,
+    name: String,
+}
+
+impl Drop for TmpDir {
+    fn drop(&mut self) {
+        match self.parent.try_dir() {
+            Ok(mut v) => _ = v.remove(&self.name),
+            Err(e) => warn!(
+                name = self.name,
+                "Failed to remove temporary directory: {e}"
+            ),
+        }
+    }
+}
+
 pub(crate) enum Stdin {
     Signal((Arc<StdinSignal>, StdinProvider)),
     Host(Arc<dyn Send + Sync + HostStdin>),
@@ -138,7 +262,7 @@ fn preopen_dir_iso_fs(
     Ok(node)
 }
 
-fn preopen_dir_host_fs(path: Utf8PathBuf) -> AnyResult<Arc<Descriptor>> {
+fn preopen_dir_host_fs(path: &Path) -> AnyResult<Arc<Descriptor>> {
     Ok(Arc::new(Descriptor::Dir(CapDir::open_ambient_dir(
         path,
         ambient_authority(),
@@ -162,6 +286,7 @@ impl WasiContextBuilder {
             self.iso_fs = BuilderIsoFS::New {
                 max_size: 0x8000_0000,
                 max_node: 0x8000_0000,
+                link_depth: LINK_DEPTH,
             };
         }
     }
@@ -170,16 +295,22 @@ impl WasiContextBuilder {
         Self {
             iso_fs: BuilderIsoFS::None,
             fs_readonly: false,
+            preopen_policy: PreopenPolicy::Strict,
             preopen_dirs: BTreeMap::new(),
+            preopen_files: BTreeMap::new(),
             cwd: Utf8PathBuf::new(),
             envs: HashMap::new(),
             args: Vec::new(),
+            clock: None,
             clock_tz: Box::new(UTCClock),
             insecure_rng: None,
             secure_rng: None,
             stdin: None,
             stdout: None,
             stderr: None,
+            extra_streams: Vec::new(),
+            nondet: None,
+            tmp_dir: None,
         }
     }
 
@@ -201,6 +332,19 @@ impl WasiContextBuilder {
         Ok(self)
     }
 
+    /// Sets maximum depth of symbolic link resolution. Must be at least 1.
+    pub fn max_link_depth(&mut self, depth: usize) -> AnyResult<&mut Self> {
+        if depth == 0 {
+            return Err(AnyError::msg("Link depth must be at least 1"));
+        }
+        self.new_iso_fs();
+        let BuilderIsoFS::New { link_depth, .. } = &mut self.iso_fs else {
+            return Err(errors::BuilderIsoFSDefinedError.into());
+        };
+        *link_depth = depth;
+        Ok(self)
+    }
+
     pub fn isolated_fs_controller(
         &mut self,
         controller: &IsolatedFSController,
@@ -217,6 +361,13 @@ impl WasiContextBuilder {
         self
     }
 
+    /// Sets policy for missing host preopen directory.
+    /// Must be set before calling [`Self::preopen_dir_host`].
+    pub fn preopen_policy(&mut self, value: PreopenPolicy) -> &mut Self {
+        self.preopen_policy = value;
+        self
+    }
+
     pub fn preopen_dir_isolated(
         &mut self,
         mut host: Utf8PathBuf,
@@ -226,26 +377,119 @@ impl WasiContextBuilder {
         match self.preopen_dirs.entry(assert_absolute_path(guest)?) {
             Entry::Occupied(v) => Err(errors::PathAlreadyExistError(v.key().to_string()).into()),
             Entry::Vacant(v) => {
-                v.insert((host, FilePreopenTy::IsoFS));
+                v.insert(FilePreopenTy::IsoFS(host));
                 Ok(self)
             }
         }
     }
 
+    /// Creates per-instance temporary directory `/tmp/<name>` in isolated FS,
+    /// preopened to guest as `/tmp`. Also sets `TMPDIR` if not set.
+    ///
+    /// Directory is deleted when context is dropped. Skipped if `/tmp` is already preopened.
+    pub fn instance_tmp(&mut self, name: String) -> AnyResult<&mut Self> {
+        let mut it = Utf8Path::new(&name).components();
+        if !matches!(
+            (it.next(), it.next()),
+            (Some(Utf8Component::Normal(s)), None) if !s.contains(ILLEGAL_CHARS)
+        ) {
+            return Err(errors::InvalidPathError(name).into());
+        }
+        self.new_iso_fs();
+        self.tmp_dir = Some(name);
+        Ok(self)
+    }
+
     pub fn preopen_dir_host(
         &mut self,
-        host: Utf8PathBuf,
+        host: impl AsRef<Path>,
         guest: Utf8PathBuf,
     ) -> AnyResult<&mut Self> {
-        match self.preopen_dirs.entry(assert_absolute_path(guest)?) {
+        self.preopen_dir_host_with(host, guest, HostPreopenOptions::default())
+    }
+
+    /// Preopens host directory.
+    ///
+    /// Host path is normalized with [`normalize_host_path`].
+    pub fn preopen_dir_host_with(
+        &mut self,
+        host: impl AsRef<Path>,
+        guest: Utf8PathBuf,
+        options: HostPreopenOptions,
+    ) -> AnyResult<&mut Self> {
+        let guest = assert_absolute_path(guest)?;
+        let host = normalize_host_path(host.as_ref())?;
+        if !host.is_dir() {
+            let e = errors::PreopenMissingError {
+                guest: guest.to_string(),
+                host: host.display().to_string(),
+            };
+            match self.preopen_policy {
+                PreopenPolicy::Strict => return Err(e.into()),
+                PreopenPolicy::SkipMissing => {
+                    warn!(%guest, host = %host.display(), "{e}, skipping");
+                    return Ok(self);
+                }
+            }
+        }
+
+        match self.preopen_dirs.entry(guest) {
+            Entry::Occupied(v) => Err(errors::PathAlreadyExistError(v.key().to_string()).into()),
+            Entry::Vacant(v) => {
+                v.insert(FilePreopenTy::HostFS(host, options));
+                Ok(self)
+            }
+        }
+    }
+
+    /// Preopens single host file.
+    ///
+    /// Host path is normalized with [`normalize_host_path`].
+    /// It's parent directories are synthesized (read-only) in isolated FS, unless it's inside isolated FS preopen.
+    /// Opening the file in guest opens host file instead. If `readonly` is `true`, it can't be written.
+    pub fn preopen_file_host(
+        &mut self,
+        host: impl AsRef<Path>,
+        guest: Utf8PathBuf,
+        readonly: bool,
+    ) -> AnyResult<&mut Self> {
+        let guest = assert_absolute_path(guest)?;
+        if guest.parent().is_none() {
+            return Err(errors::InvalidPathError(guest.into()).into());
+        }
+        let host = normalize_host_path(host.as_ref())?;
+        if !host.is_file() {
+            let e = errors::PreopenMissingError {
+                guest: guest.to_string(),
+                host: host.display().to_string(),
+            };
+            match self.preopen_policy {
+                PreopenPolicy::Strict => return Err(e.into()),
+                PreopenPolicy::SkipMissing => {
+                    warn!(%guest, host = %host.display(), "{e}, skipping");
+                    return Ok(self);
+                }
+            }
+        }
+
+        self.new_iso_fs();
+        match self.preopen_files.entry(guest) {
             Entry::Occupied(v) => Err(errors::PathAlreadyExistError(v.key().to_string()).into()),
             Entry::Vacant(v) => {
-                v.insert((host, FilePreopenTy::HostFS));
+                v.insert((host, readonly));
                 Ok(self)
             }
         }
     }
 
+    /// Sets monotonic clock source. Contexts built with the same clock observe the same time.
+    ///
+    /// By default, every context has it's own clock starting at 0.
+    pub fn clock_controller(&mut self, clock: &ClockController) -> &mut Self {
+        self.clock = Some(clock.clone());
+        self
+    }
+
     pub fn clock_timezone(
         &mut self,
         tz: Box<dyn Send + Sync + wasi::clocks::timezone::Host>,
@@ -264,6 +508,12 @@ impl WasiContextBuilder {
         self
     }
 
+    /// Sets tap for nondeterministic sources (clocks, random, host file reads).
+    pub fn nondet_tap(&mut self, tap: Arc<dyn NondetTap>) -> &mut Self {
+        self.nondet = Some(tap);
+        self
+    }
+
     pub fn stdin_signal(&mut self, f: Box<dyn Fn() + Send + Sync>) -> AnyResult<&mut Self> {
         if self.stdin.is_some() {
             return Err(errors::BuilderStdioDefinedError.into());
@@ -272,6 +522,22 @@ impl WasiContextBuilder {
         Ok(self)
     }
 
+    /// Like [`stdin_signal`](Self::stdin_signal), but buffer is bounded to `cap` bytes.
+    ///
+    /// See [`StdinSignal::with_limit`].
+    pub fn stdin_signal_limit(
+        &mut self,
+        f: Box<dyn Fn() + Send + Sync>,
+        cap: usize,
+        drained: Box<dyn Fn() + Send + Sync>,
+    ) -> AnyResult<&mut Self> {
+        if self.stdin.is_some() {
+            return Err(errors::BuilderStdioDefinedError.into());
+        }
+        self.stdin = Some(BuilderStdin::LimitedSignal(f, cap, drained));
+        Ok(self)
+    }
+
     pub fn stdin(&mut self, v: Arc<dyn Send + Sync + HostStdin>) -> AnyResult<&mut Self> {
         if self.stdin.is_some() {
             return Err(errors::BuilderStdioDefinedError.into());
@@ -296,17 +562,27 @@ impl WasiContextBuilder {
         Ok(self)
     }
 
-    pub fn env(&mut self, key: String, val: String) -> &mut Self {
-        if !key.contains('=') {
-            self.envs.insert(key, val);
-        }
-        self
+    /// Adds extra stream, returning it's file descriptor number.
+    ///
+    /// Extra streams are numbered sequentially after standard streams (starting at 3),
+    /// followed by preopened directories.
+    /// It's only accessible to preview 1 guests.
+    pub fn push_extra_stream(&mut self, v: Pipe) -> u32 {
+        self.extra_streams.push(v);
+        self.extra_streams.len() as u32 + 2
     }
 
-    pub fn envs(&mut self, it: impl IntoIterator<Item = (String, String)>) -> &mut Self {
-        self.envs
-            .extend(it.into_iter().filter(|(k, _)| !k.contains('=')));
-        self
+    pub fn env(&mut self, key: String, val: String) -> AnyResult<&mut Self> {
+        validate_env(&key, &val)?;
+        self.envs.insert(key, val);
+        Ok(self)
+    }
+
+    pub fn envs(&mut self, it: impl IntoIterator<Item = (String, String)>) -> AnyResult<&mut Self> {
+        for (k, v) in it {
+            self.env(k, v)?;
+        }
+        Ok(self)
     }
 
     pub fn cwd(
@@ -321,12 +597,15 @@ impl WasiContextBuilder {
         Ok(self)
     }
 
-    pub fn args(&mut self, args: impl IntoIterator<Item = String>) -> &mut Self {
-        self.args.extend(args);
-        self
+    pub fn args(&mut self, args: impl IntoIterator<Item = String>) -> AnyResult<&mut Self> {
+        for v in args {
+            validate_arg(&v)?;
+            self.args.push(v);
+        }
+        Ok(self)
     }
 
-    pub fn build(self) -> AnyResult<WasiContext> {
+    pub fn build(mut self) -> AnyResult<WasiContext> {
         let access = if self.fs_readonly {
             AccessMode::R
         } else {
@@ -334,37 +613,139 @@ impl WasiContextBuilder {
         };
         let iso_fs = match self.iso_fs {
             BuilderIsoFS::None => None,
-            BuilderIsoFS::New { max_size, max_node } => {
-                Some(IsolatedFSController::new(max_size, max_node)?)
+            BuilderIsoFS::New {
+                max_size,
+                max_node,
+                link_depth,
+            } => {
+                let v = IsolatedFSController::new(max_size, max_node)?;
+                v.set_link_depth(link_depth)?;
+                Some(v)
             }
             BuilderIsoFS::Exist(controller) => Some(controller),
         };
 
+        let tmp = match (self.tmp_dir, &iso_fs) {
+            (Some(name), Some(controller)) => match self.preopen_dirs.entry("/tmp".into()) {
+                Entry::Occupied(_) => {
+                    warn!("/tmp is already preopened, skipping instance temporary directory");
+                    None
+                }
+                Entry::Vacant(v) => {
+                    let parent = preopen_dir_iso_fs(controller, "/tmp".into())
+                        .context("failed to create /tmp")?;
+                    // Stale directory from previous instance with the same name.
+                    parent.try_dir()?.remove(&name);
+                    v.insert(FilePreopenTy::IsoFS(format!("/tmp/{name}").into()));
+                    self.envs
+                        .entry("TMPDIR".into())
+                        .or_insert_with(|| "/tmp".into());
+                    Some(TmpDir { parent, name })
+                }
+            },
+            _ => None,
+        };
+
+        let mut host_files = HostFiles::default();
+        for (guest, (host, readonly)) in self.preopen_files {
+            let (Some(parent), Some(name)) = (guest.parent(), guest.file_name()) else {
+                return Err(errors::InvalidPathError(guest.into()).into());
+            };
+            if self.preopen_dirs.contains_key(&guest) {
+                return Err(errors::PathAlreadyExistError(guest.into()).into());
+            }
+            let controller = iso_fs.as_ref().ok_or(errors::BuilderIsoFSNotDefinedError)?;
+
+            // Put it inside innermost isolated FS preopen, or synthesize parent preopen.
+            let src = match self
+                .preopen_dirs
+                .iter()
+                .filter(|(k, _)| parent.starts_with(k))
+                .max_by_key(|(k, _)| k.components().count())
+            {
+                Some((k, FilePreopenTy::IsoFS(src) | FilePreopenTy::Synthetic(src))) => {
+                    src.join(parent.strip_prefix(k)?)
+                }
+                _ => {
+                    self.preopen_dirs
+                        .insert(parent.into(), FilePreopenTy::Synthetic(parent.into()));
+                    parent.into()
+                }
+            };
+            let dir = preopen_dir_iso_fs(controller, src)
+                .with_context(|| format!("failed to preopen {guest:?} (host path {host:?})"))?;
+            let node = {
+                let mut d = dir.try_dir()?;
+                match d.get(name) {
+                    Some(v) if v.is_file() => v,
+                    Some(_) => return Err(errors::PathAlreadyExistError(guest.into()).into()),
+                    None => d
+                        .add::<AnyError>(name, || {
+                            Ok(Arc::new(Node::from((
+                                File::new(controller)?,
+                                Arc::downgrade(&dir),
+                            ))))
+                        })?
+                        .ok_or_else(|| errors::PathAlreadyExistError(guest.to_string()))?,
+                }
+            };
+
+            let mut opts = OpenOptions::new();
+            opts.read(true).write(!readonly);
+            let file = CapFile::open_ambient_with(&host, &opts, ambient_authority())
+                .with_context(|| format!("failed to preopen {guest:?} (host path {host:?})"))?;
+            let file_access = if readonly {
+                AccessMode::R
+            } else {
+                AccessMode::RW
+            };
+            host_files.0.push((
+                node,
+                HostCapWrapper::new(Arc::new(Descriptor::File(file)), access & file_access),
+            ));
+        }
+
         let preopens = self
             .preopen_dirs
             .into_iter()
-            .map(|(dst, (src, ty))| {
-                Ok((
-                    dst,
-                    match ty {
-                        FilePreopenTy::IsoFS => FilePreopen::IsoFS(CapWrapper::new(
-                            preopen_dir_iso_fs(
-                                iso_fs.as_ref().ok_or(errors::BuilderIsoFSNotDefinedError)?,
-                                src,
-                            )?,
+            .map(|(dst, ty)| {
+                let v = match ty {
+                    FilePreopenTy::IsoFS(src) => FilePreopen::IsoFS(CapWrapper::new(
+                        preopen_dir_iso_fs(
+                            iso_fs.as_ref().ok_or(errors::BuilderIsoFSNotDefinedError)?,
+                            src.clone(),
+                        )
+                        .with_context(|| {
+                            format!("failed to preopen {dst:?} (host path {src:?})")
+                        })?,
+                        access,
+                    )),
+                    FilePreopenTy::Synthetic(src) => FilePreopen::IsoFS(CapWrapper::new(
+                        preopen_dir_iso_fs(
+                            iso_fs.as_ref().ok_or(errors::BuilderIsoFSNotDefinedError)?,
+                            src,
+                        )?,
+                        AccessMode::R,
+                    )),
+                    FilePreopenTy::HostFS(src, o) => FilePreopen::HostFS(
+                        HostCapWrapper::new(
+                            preopen_dir_host_fs(&src).with_context(|| {
+                                format!("failed to preopen {dst:?} (host path {src:?})")
+                            })?,
                             access,
-                        )),
-                        FilePreopenTy::HostFS => FilePreopen::HostFS(HostCapWrapper::new(
-                            preopen_dir_host_fs(src)?,
-                            access,
-                        )),
-                    },
-                ))
+                        )
+                        .with_case_insensitive(o.case_insensitive),
+                    ),
+                };
+                Ok((dst, v))
             })
             .collect::<AnyResult<Vec<_>>>()?;
 
         let mut stdin = self.stdin.map(|v| match v {
             BuilderStdin::Signal(f) => Stdin::Signal(StdinSignal::new(f)),
+            BuilderStdin::LimitedSignal(f, cap, drained) => {
+                Stdin::Signal(StdinSignal::with_limit(f, cap, drained))
+            }
             BuilderStdin::Host(v) => Stdin::Host(v),
         });
 
@@ -384,6 +765,11 @@ impl WasiContextBuilder {
             },
         ]
         .into_iter()
+        .chain(
+            self.extra_streams
+                .into_iter()
+                .map(|v| P1Item::from(Box::new(v))),
+        )
         .chain(preopens.iter().map(|(k, v)| {
             Box::new(P1File::with_preopen(
                 match v {
@@ -401,10 +787,11 @@ impl WasiContextBuilder {
             iso_fs,
             p1_items,
             preopens,
+            host_files,
             cwd: self.cwd,
             envs: self.envs.into_iter().collect(),
             args: self.args,
-            clock: ClockController::new(),
+            clock: self.clock.unwrap_or_default(),
             clock_tz: self.clock_tz,
             insecure_rng: match self.insecure_rng {
                 Some(v) => v,
@@ -416,6 +803,9 @@ impl WasiContextBuilder {
             stdin,
             stdout: self.stdout,
             stderr: self.stderr,
+            nondet: self.nondet,
+            named_streams: Vec::new(),
+            _tmp: tmp,
             hasher: RandomState::new(),
             timeout: None,
         })
@@ -428,6 +818,11 @@ impl WasiContext {
         WasiContextBuilder::new()
     }
 
+    /// Guest paths of all applied preopens.
+    pub fn preopen_paths(&self) -> impl Iterator<Item = &Utf8Path> {
+        self.preopens.iter().map(|(k, _)| &**k)
+    }
+
     #[inline(always)]
     pub fn iso_fs_controller(&self) -> Option<&IsolatedFSController> {
         self.iso_fs.as_ref()
@@ -438,6 +833,11 @@ impl WasiContext {
         &self.clock
     }
 
+    #[inline(always)]
+    pub fn nondet_tap(&self) -> Option<&Arc<dyn NondetTap>> {
+        self.nondet.as_ref()
+    }
+
     #[inline(always)]
     pub fn stdin_provider(&self) -> Option<&StdinProvider> {
         match &self.stdin {
@@ -451,6 +851,26 @@ impl WasiContext {
         &mut self.p1_items
     }
 
+    /// Inserts named stream into running context. Returns the file descriptor.
+    ///
+    /// Fails if name is already used.
+    pub fn insert_stream(&mut self, name: String, v: Pipe) -> AnyResult<u32> {
+        if self.stream_fd(&name).is_some() {
+            return Err(errors::StreamAlreadyExistError(name).into());
+        }
+        let fd = u32::from(self.p1_items.register(P1Item::from(Box::new(v)))?);
+        self.named_streams.push((name, fd));
+        Ok(fd)
+    }
+
+    /// Gets file descriptor of stream inserted with [`Self::insert_stream`].
+    pub fn stream_fd(&self, name: &str) -> Option<u32> {
+        self.named_streams
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|&(_, v)| v)
+    }
+
     #[inline(always)]
     pub fn set_timeout(&mut self, timeout: Instant) {
         self.timeout = Some(timeout);
@@ -500,3 +920,229 @@ pub(crate) fn try_iso_fs(
         .as_ref()
         .ok_or_else(|| errors::BuilderIsoFSNotDefinedError.into())
 }
+
+/// Checks if environment variable can be passed to guest.
+///
+/// Key must not contain `'='`, and both key and value must not contain NUL.
+pub fn validate_env(key: &str, val: &str) -> AnyResult<()> {
+    if key.contains('\0') {
+        Err(errors::InvalidEnvArgError::EnvKeyNul(key.into()).into())
+    } else if key.contains('=') {
+        Err(errors::InvalidEnvArgError::EnvKeyEq(key.into()).into())
+    } else if val.contains('\0') {
+        Err(errors::InvalidEnvArgError::EnvValueNul(key.into()).into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks if argument can be passed to guest.
+///
+/// Argument must not contain NUL.
+pub fn validate_arg(arg: &str) -> AnyResult<()> {
+    if arg.contains('\0') {
+        Err(errors::InvalidEnvArgError::ArgNul(arg.into()).into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    #[test]
+    fn test_case_insensitive_mount() {
+        use std::fs::write;
+        use std::path::Path;
+
+        let dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!("wasi-case-{}", std::process::id()));
+        create_dir_all(dir.join("data/sub")).unwrap();
+        write(dir.join("data/config.ini"), b"").unwrap();
+        write(dir.join("data/sub/Exact.txt"), b"").unwrap();
+        write(dir.join("data/sub/exact.TXT"), b"").unwrap();
+        write(dir.join("data/sub/dup.txt"), b"").unwrap();
+        write(dir.join("data/sub/DUP.txt"), b"").unwrap();
+
+        let _guard = scopeguard::guard((), |_| {
+            let _ = remove_dir_all(&dir);
+        });
+
+        let build = |case_insensitive| {
+            let mut builder = WasiContext::builder();
+            builder
+                .preopen_dir_host_with(
+                    dir.clone(),
+                    "/".into(),
+                    HostPreopenOptions { case_insensitive },
+                )
+                .unwrap();
+            let mut ctx = builder.build().unwrap();
+            match ctx.preopens.pop() {
+                Some((_, FilePreopen::HostFS(v))) => v,
+                _ => unreachable!(),
+            }
+        };
+        let resolve = |v: &HostCapWrapper, p: &str| {
+            v.resolve_path(Path::new(p))
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+        };
+
+        // Off by default, path is untouched.
+        let v = build(false);
+        assert!(!v.case_insensitive());
+        assert_eq!(resolve(&v, "Data/Config.INI").unwrap(), "Data/Config.INI");
+
+        let v = build(true);
+        // Hit
+        assert_eq!(resolve(&v, "Data/Config.INI").unwrap(), "data/config.ini");
+        assert_eq!(
+            resolve(&v, "DATA/SUB/../config.ini").unwrap(),
+            "data/sub/../config.ini"
+        );
+        v.dir()
+            .unwrap()
+            .open(&*v.resolve_path(Path::new("DATA/CONFIG.ini")).unwrap())
+            .unwrap();
+        // Exact match is preferred, even if other entries match case-insensitively.
+        assert_eq!(
+            resolve(&v, "data/sub/Exact.txt").unwrap(),
+            "data/sub/Exact.txt"
+        );
+        assert_eq!(
+            resolve(&v, "data/sub/exact.TXT").unwrap(),
+            "data/sub/exact.TXT"
+        );
+        // Miss, kept as-is so file can be created.
+        assert_eq!(resolve(&v, "Data/New.txt").unwrap(), "data/New.txt");
+        assert_eq!(resolve(&v, "Missing/File.txt").unwrap(), "Missing/File.txt");
+
+        // Ambiguous, only testable on case-sensitive host filesystem.
+        if std::fs::read_dir(dir.join("data/sub")).unwrap().count() == 4 {
+            let e = resolve(&v, "data/sub/Dup.txt").unwrap_err();
+            assert_eq!(
+                e.fs_error_code(),
+                Some(wasi::filesystem::types::ErrorCode::Invalid)
+            );
+            assert!(resolve(&v, "data/sub/EXACT.txt").is_err());
+        }
+    }
+
+    fn mixed_mounts(policy: PreopenPolicy) -> AnyResult<Vec<String>> {
+        let dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!("wasi-preopen-{}-{policy:?}", std::process::id()));
+        create_dir_all(dir.join("a")).unwrap();
+        create_dir_all(dir.join("c")).unwrap();
+
+        let _guard = scopeguard::guard((), |_| {
+            let _ = remove_dir_all(&dir);
+        });
+
+        let mut builder = WasiContext::builder();
+        builder.preopen_policy(policy);
+        builder.preopen_dir_host(dir.join("a"), "/a".into())?;
+        builder.preopen_dir_host(dir.join("b"), "/b".into())?;
+        builder.preopen_dir_host(dir.join("c"), "/c".into())?;
+        let ctx = builder.build()?;
+
+        Ok(ctx.preopen_paths().map(|p| p.to_string()).collect())
+    }
+
+    #[test]
+    fn test_preopen_strict() {
+        let e = mixed_mounts(PreopenPolicy::Strict).unwrap_err().to_string();
+        assert!(e.contains("b\" for preopen \"/b\""), "{e}");
+        assert!(e.ends_with("does not exist"), "{e}");
+    }
+
+    #[test]
+    fn test_preopen_skip_missing() {
+        assert_eq!(
+            mixed_mounts(PreopenPolicy::SkipMissing).unwrap(),
+            ["/a".to_string(), "/c".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_instance_tmp() {
+        let controller = IsolatedFSController::new(1 << 16, 4).unwrap();
+        let build = |name: &str| {
+            let mut builder = WasiContext::builder();
+            builder.isolated_fs_controller(&controller).unwrap();
+            builder.instance_tmp(name.into()).unwrap();
+            builder.build().unwrap()
+        };
+        let tmp = |ctx: &WasiContext| match &ctx.preopens[..] {
+            [(k, FilePreopen::IsoFS(v))] if k == "/tmp" => v.node().clone(),
+            _ => panic!("/tmp is not preopened"),
+        };
+
+        // Root, /tmp, and instance directory.
+        let ctx = build(".inst-1");
+        assert!(ctx.envs.iter().any(|(k, v)| k == "TMPDIR" && v == "/tmp"));
+        let file = tmp(&ctx)
+            .try_dir()
+            .unwrap()
+            .add("a", || -> AnyResult<_> {
+                Ok(Arc::new(Node::from((
+                    File::new(&controller)?,
+                    Arc::downgrade(&tmp(&ctx)),
+                ))))
+            })
+            .unwrap()
+            .unwrap();
+        drop(ctx);
+        let parent = preopen_dir_iso_fs(&controller, "/tmp".into()).unwrap();
+        assert!(parent.try_dir().unwrap().items.is_empty());
+
+        // Open file is still counted until closed.
+        let ctx = build(".inst-2");
+        assert!(File::new(&controller).is_err());
+        drop(file);
+        File::new(&controller).unwrap();
+        drop(ctx);
+        assert!(parent.try_dir().unwrap().items.is_empty());
+
+        let mut builder = WasiContext::builder();
+        assert!(builder.instance_tmp("a/b".into()).is_err());
+        assert!(builder.instance_tmp("..".into()).is_err());
+    }
+
+    #[test]
+    fn test_clock_reload() {
+        const DEADLINE: u64 = 20_000_000;
+
+        let clock = ClockController::new();
+        let build = || {
+            let mut builder = WasiContext::builder();
+            builder.clock_controller(&clock);
+            builder.build().unwrap()
+        };
+
+        let ctx = build();
+        let t = ctx.clock_controller().now();
+        let p = ctx.clock_controller().poll_until(t + DEADLINE);
+        sleep(Duration::from_millis(1));
+        drop(ctx);
+
+        // Reloaded context continues from old time.
+        let ctx = build();
+        assert!(ctx.clock_controller().now() > t);
+        let p2 = ctx.clock_controller().poll_until(t + DEADLINE);
+        assert_eq!(p.until, p2.until);
+        p.block(None).unwrap();
+        assert!(p2.is_ready());
+        assert!(ctx.clock_controller().now() >= t + DEADLINE);
+
+        // Unshared clock restarts.
+        let ctx = WasiContext::builder().build().unwrap();
+        assert!(ctx.clock_controller().now() < t + DEADLINE);
+    }
+}
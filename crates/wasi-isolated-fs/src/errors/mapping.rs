@@ -0,0 +1,340 @@
+//! Mapping between I/O error kinds and WASI error codes.
+//!
+//! Every conversion of error into preview1 [`Errno`] and preview2 [`FSErrorCode`]
+//! goes through [`ERROR_TABLE`], so both WASI versions report the same error.
+
+use std::io::ErrorKind;
+
+use anyhow::Error as AnyError;
+
+use super::{AmbiguousPathError, FileLimitError, SymlinkLoopError};
+use crate::bindings::types::Errno;
+use crate::bindings::wasi::filesystem::types::ErrorCode as FSErrorCode;
+
+/// Error code of preview2, it's preview1 counterpart, and I/O error kinds mapped into it.
+///
+/// Error kind not listed here is mapped to [`FSErrorCode::Io`],
+/// except [`ErrorKind::Other`] which is not mapped (trap).
+static ERROR_TABLE: &[(FSErrorCode, Errno, &[ErrorKind])] = &[
+    (FSErrorCode::Access, Errno::Acces, &[]),
+    (
+        FSErrorCode::WouldBlock,
+        Errno::Again,
+        &[ErrorKind::WouldBlock],
+    ),
+    (FSErrorCode::Already, Errno::Already, &[]),
+    (FSErrorCode::BadDescriptor, Errno::Badf, &[]),
+    (FSErrorCode::Busy, Errno::Busy, &[ErrorKind::ResourceBusy]),
+    (FSErrorCode::Deadlock, Errno::Deadlk, &[ErrorKind::Deadlock]),
+    (
+        FSErrorCode::Quota,
+        Errno::Dquot,
+        &[ErrorKind::QuotaExceeded],
+    ),
+    (
+        FSErrorCode::Exist,
+        Errno::Exist,
+        &[ErrorKind::AlreadyExists],
+    ),
+    (
+        FSErrorCode::FileTooLarge,
+        Errno::Fbig,
+        &[ErrorKind::FileTooLarge],
+    ),
+    // Invalid UTF-8 is the only source of invalid data.
+    (
+        FSErrorCode::IllegalByteSequence,
+        Errno::Ilseq,
+        &[ErrorKind::InvalidData],
+    ),
+    (FSErrorCode::InProgress, Errno::Inprogress, &[]),
+    (
+        FSErrorCode::Interrupted,
+        Errno::Intr,
+        &[ErrorKind::Interrupted],
+    ),
+    (
+        FSErrorCode::Invalid,
+        Errno::Inval,
+        &[ErrorKind::InvalidInput],
+    ),
+    (FSErrorCode::Io, Errno::Io, &[]),
+    (
+        FSErrorCode::IsDirectory,
+        Errno::Isdir,
+        &[ErrorKind::IsADirectory],
+    ),
+    (FSErrorCode::Loop, Errno::Loop, &[]),
+    (
+        FSErrorCode::TooManyLinks,
+        Errno::Mlink,
+        &[ErrorKind::TooManyLinks],
+    ),
+    (FSErrorCode::MessageSize, Errno::Msgsize, &[]),
+    // Std maps ENAMETOOLONG into invalid filename.
+    (
+        FSErrorCode::NameTooLong,
+        Errno::Nametoolong,
+        &[ErrorKind::InvalidFilename],
+    ),
+    (FSErrorCode::NoDevice, Errno::Nodev, &[]),
+    (FSErrorCode::NoEntry, Errno::Noent, &[ErrorKind::NotFound]),
+    (FSErrorCode::NoLock, Errno::Nolck, &[]),
+    (
+        FSErrorCode::InsufficientMemory,
+        Errno::Nomem,
+        &[ErrorKind::OutOfMemory],
+    ),
+    (
+        FSErrorCode::InsufficientSpace,
+        Errno::Nospc,
+        &[ErrorKind::StorageFull],
+    ),
+    (
+        FSErrorCode::Unsupported,
+        Errno::Notsup,
+        &[ErrorKind::Unsupported],
+    ),
+    (
+        FSErrorCode::NotDirectory,
+        Errno::Notdir,
+        &[ErrorKind::NotADirectory],
+    ),
+    (
+        FSErrorCode::NotEmpty,
+        Errno::Notempty,
+        &[ErrorKind::DirectoryNotEmpty],
+    ),
+    (FSErrorCode::NotRecoverable, Errno::Notrecoverable, &[]),
+    (FSErrorCode::NoTty, Errno::Notty, &[]),
+    (FSErrorCode::NoSuchDevice, Errno::Nxio, &[]),
+    (FSErrorCode::Overflow, Errno::Overflow, &[]),
+    // Std maps both EACCES and EPERM into permission denied.
+    // Sandbox denials are reported as EPERM, which guests treat as non-retryable.
+    (
+        FSErrorCode::NotPermitted,
+        Errno::Perm,
+        &[ErrorKind::PermissionDenied],
+    ),
+    (FSErrorCode::Pipe, Errno::Pipe, &[ErrorKind::BrokenPipe]),
+    (
+        FSErrorCode::ReadOnly,
+        Errno::Rofs,
+        &[ErrorKind::ReadOnlyFilesystem],
+    ),
+    (
+        FSErrorCode::InvalidSeek,
+        Errno::Spipe,
+        &[ErrorKind::NotSeekable],
+    ),
+    (
+        FSErrorCode::TextFileBusy,
+        Errno::Txtbsy,
+        &[ErrorKind::ExecutableFileBusy],
+    ),
+    (
+        FSErrorCode::CrossDevice,
+        Errno::Xdev,
+        &[ErrorKind::CrossesDevices],
+    ),
+];
+
+/// Converts I/O error kind into preview2 error code.
+///
+/// Returns `None` if error should not be mapped (trap).
+pub(crate) fn error_kind_code(kind: ErrorKind) -> Option<FSErrorCode> {
+    if kind == ErrorKind::Other {
+        return None;
+    }
+
+    Some(
+        ERROR_TABLE
+            .iter()
+            .find(|(.., k)| k.contains(&kind))
+            .map_or(FSErrorCode::Io, |&(v, ..)| v),
+    )
+}
+
+/// Converts preview2 error code into preview1 errno.
+pub(crate) fn code_errno(code: FSErrorCode) -> Errno {
+    ERROR_TABLE
+        .iter()
+        .find(|&&(v, ..)| v == code)
+        .map_or(Errno::Io, |&(_, v, _)| v)
+}
+
+/// Converts internal error into preview2 error code.
+///
+/// Returns `None` if it's not an internal error with error code.
+pub(crate) fn any_error_code(e: &AnyError) -> Option<FSErrorCode> {
+    if e.is::<FileLimitError>() {
+        // Filesystem limit is always reported as no space, regardless of WASI version.
+        Some(FSErrorCode::InsufficientSpace)
+    } else if e.is::<SymlinkLoopError>() {
+        Some(FSErrorCode::Loop)
+    } else if e.is::<AmbiguousPathError>() {
+        // No better code for path matching multiple entries.
+        Some(FSErrorCode::Invalid)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Error as IoError;
+
+    use crate::errors::StreamError;
+
+    fn convert(e: impl Into<StreamError> + Clone) -> (FSErrorCode, Errno) {
+        (
+            <Result<FSErrorCode, AnyError>>::from(e.clone().into()).unwrap(),
+            <Result<Errno, AnyError>>::from(e.into()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_table_unique() {
+        for (i, (c, e, k)) in ERROR_TABLE.iter().enumerate() {
+            for (c_, e_, k_) in &ERROR_TABLE[i + 1..] {
+                assert_ne!(c, c_);
+                assert_ne!(e, e_);
+                assert!(!k.iter().any(|k| k_.contains(k)), "{k:?} is mapped twice");
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_kind() {
+        // Every error kind produced by the crate.
+        for (kind, code, errno) in [
+            (ErrorKind::AlreadyExists, FSErrorCode::Exist, Errno::Exist),
+            (ErrorKind::BrokenPipe, FSErrorCode::Pipe, Errno::Pipe),
+            (
+                ErrorKind::DirectoryNotEmpty,
+                FSErrorCode::NotEmpty,
+                Errno::Notempty,
+            ),
+            (
+                ErrorKind::Interrupted,
+                FSErrorCode::Interrupted,
+                Errno::Intr,
+            ),
+            (
+                ErrorKind::InvalidData,
+                FSErrorCode::IllegalByteSequence,
+                Errno::Ilseq,
+            ),
+            (ErrorKind::InvalidInput, FSErrorCode::Invalid, Errno::Inval),
+            (
+                ErrorKind::IsADirectory,
+                FSErrorCode::IsDirectory,
+                Errno::Isdir,
+            ),
+            (
+                ErrorKind::NotADirectory,
+                FSErrorCode::NotDirectory,
+                Errno::Notdir,
+            ),
+            (ErrorKind::NotFound, FSErrorCode::NoEntry, Errno::Noent),
+            (
+                ErrorKind::PermissionDenied,
+                FSErrorCode::NotPermitted,
+                Errno::Perm,
+            ),
+            (ErrorKind::TimedOut, FSErrorCode::Io, Errno::Io),
+            (ErrorKind::UnexpectedEof, FSErrorCode::Io, Errno::Io),
+            (
+                ErrorKind::Unsupported,
+                FSErrorCode::Unsupported,
+                Errno::Notsup,
+            ),
+            // Produced by host filesystem.
+            (ErrorKind::WouldBlock, FSErrorCode::WouldBlock, Errno::Again),
+            (ErrorKind::ResourceBusy, FSErrorCode::Busy, Errno::Busy),
+            (ErrorKind::Deadlock, FSErrorCode::Deadlock, Errno::Deadlk),
+            (
+                ErrorKind::ReadOnlyFilesystem,
+                FSErrorCode::ReadOnly,
+                Errno::Rofs,
+            ),
+            (
+                ErrorKind::NotSeekable,
+                FSErrorCode::InvalidSeek,
+                Errno::Spipe,
+            ),
+            (
+                ErrorKind::StorageFull,
+                FSErrorCode::InsufficientSpace,
+                Errno::Nospc,
+            ),
+            (ErrorKind::QuotaExceeded, FSErrorCode::Quota, Errno::Dquot),
+            (
+                ErrorKind::FileTooLarge,
+                FSErrorCode::FileTooLarge,
+                Errno::Fbig,
+            ),
+            (
+                ErrorKind::InvalidFilename,
+                FSErrorCode::NameTooLong,
+                Errno::Nametoolong,
+            ),
+            (
+                ErrorKind::TooManyLinks,
+                FSErrorCode::TooManyLinks,
+                Errno::Mlink,
+            ),
+            (
+                ErrorKind::CrossesDevices,
+                FSErrorCode::CrossDevice,
+                Errno::Xdev,
+            ),
+            (
+                ErrorKind::ExecutableFileBusy,
+                FSErrorCode::TextFileBusy,
+                Errno::Txtbsy,
+            ),
+            (
+                ErrorKind::OutOfMemory,
+                FSErrorCode::InsufficientMemory,
+                Errno::Nomem,
+            ),
+        ] {
+            assert_eq!(convert(kind), (code, errno), "{kind:?}");
+        }
+
+        // Unknown errors trap.
+        let e = StreamError::from(IoError::other("unknown"));
+        assert!(<Result<FSErrorCode, AnyError>>::from(e).is_err());
+        let e = StreamError::from(ErrorKind::Other);
+        assert!(<Result<Errno, AnyError>>::from(e).is_err());
+    }
+
+    #[test]
+    fn test_internal_error() {
+        let f = |e: AnyError| {
+            let e = StreamError::from(e);
+            let c = e.fs_error_code();
+            (c, <Result<FSErrorCode, AnyError>>::from(e).ok())
+        };
+
+        assert_eq!(
+            f(FileLimitError::Size(1).into()),
+            (
+                Some(FSErrorCode::InsufficientSpace),
+                Some(FSErrorCode::InsufficientSpace)
+            )
+        );
+        assert_eq!(
+            f(SymlinkLoopError {
+                path: "/a".into(),
+                depth: 1,
+            }
+            .into()),
+            (Some(FSErrorCode::Loop), Some(FSErrorCode::Loop))
+        );
+        assert_eq!(f(AnyError::msg("unknown")), (None, None));
+    }
+}
@@ -5,8 +5,11 @@ use std::io::{Error as IoError, ErrorKind};
 use std::num::TryFromIntError;
 
 use anyhow::Error as AnyError;
+use tracing::warn;
 use wiggle::GuestError;
 
+pub(crate) mod mapping;
+
 use crate::bindings::types::Errno;
 use crate::bindings::wasi::filesystem::types::ErrorCode as FSErrorCode;
 use crate::bindings::wasi::io::streams::StreamError as WasiStreamError;
@@ -141,6 +144,79 @@ impl Display for FileLimitError {
 
 impl Error for FileLimitError {}
 
+pub(crate) struct SymlinkLoopError {
+    pub(crate) path: String,
+    pub(crate) depth: usize,
+}
+
+impl Debug for SymlinkLoopError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for SymlinkLoopError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "too many levels of symbolic links (limit {}) while resolving path {:?}",
+            self.depth, self.path
+        )
+    }
+}
+
+impl Error for SymlinkLoopError {}
+
+pub(crate) struct AmbiguousPathError {
+    pub(crate) path: String,
+    pub(crate) a: String,
+    pub(crate) b: String,
+}
+
+impl Debug for AmbiguousPathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for AmbiguousPathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "path {:?} is ambiguous, both {:?} and {:?} match case-insensitively",
+            self.path, self.a, self.b
+        )
+    }
+}
+
+impl Error for AmbiguousPathError {}
+
+pub(crate) enum InvalidEnvArgError {
+    EnvKeyNul(String),
+    EnvKeyEq(String),
+    EnvValueNul(String),
+    ArgNul(String),
+}
+
+impl Debug for InvalidEnvArgError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for InvalidEnvArgError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::EnvKeyNul(k) => write!(f, "environment variable name {k:?} contains NUL"),
+            Self::EnvKeyEq(k) => write!(f, "environment variable name {k:?} contains '='"),
+            Self::EnvValueNul(k) => write!(f, "value of environment variable {k:?} contains NUL"),
+            Self::ArgNul(v) => write!(f, "argument {v:?} contains NUL"),
+        }
+    }
+}
+
+impl Error for InvalidEnvArgError {}
+
 pub(crate) struct InvalidPathError(pub(crate) String);
 
 impl Debug for InvalidPathError {
@@ -173,6 +249,123 @@ impl Display for PathAlreadyExistError {
 
 impl Error for PathAlreadyExistError {}
 
+pub(crate) struct StreamAlreadyExistError(pub(crate) String);
+
+impl Debug for StreamAlreadyExistError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for StreamAlreadyExistError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "stream {:?} already exist", self.0)
+    }
+}
+
+impl Error for StreamAlreadyExistError {}
+
+pub(crate) struct PreopenMissingError {
+    pub(crate) guest: String,
+    pub(crate) host: String,
+}
+
+impl Debug for PreopenMissingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for PreopenMissingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "host directory {:?} for preopen {:?} does not exist",
+            self.host, self.guest
+        )
+    }
+}
+
+impl Error for PreopenMissingError {}
+
+pub(crate) struct DriveRelativePathError(pub(crate) String);
+
+impl Debug for DriveRelativePathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for DriveRelativePathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "host path {:?} is relative to current directory of drive, use absolute path instead",
+            self.0
+        )
+    }
+}
+
+impl Error for DriveRelativePathError {}
+
+/// Import into isolated filesystem is cancelled.
+pub struct ImportCancelledError;
+
+impl Debug for ImportCancelledError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for ImportCancelledError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "import is cancelled")
+    }
+}
+
+impl Error for ImportCancelledError {}
+
+pub(crate) struct InvalidReplayError(pub(crate) &'static str);
+
+impl Debug for InvalidReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for InvalidReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "invalid replay: {}", self.0)
+    }
+}
+
+impl Error for InvalidReplayError {}
+
+pub(crate) struct ReplayMismatchError {
+    pub(crate) index: u64,
+    pub(crate) recorded: Option<String>,
+    pub(crate) actual: String,
+}
+
+impl Debug for ReplayMismatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for ReplayMismatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(f, "replay mismatch at call #{}", self.index)?;
+        match &self.recorded {
+            Some(v) => writeln!(f, "- {v}")?,
+            None => writeln!(f, "- <end of recording>")?,
+        }
+        write!(f, "+ {}", self.actual)
+    }
+}
+
+impl Error for ReplayMismatchError {}
+
 #[derive(Default)]
 pub(crate) struct InvalidResourceIDError {
     ids: [u32; 32],
@@ -266,22 +459,6 @@ impl Display for StreamClosedError {
 
 impl Error for StreamClosedError {}
 
-pub(crate) struct MonotonicClockError;
-
-impl Debug for MonotonicClockError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        Display::fmt(self, f)
-    }
-}
-
-impl Display for MonotonicClockError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "unknown monotonic clock error")
-    }
-}
-
-impl Error for MonotonicClockError {}
-
 pub(crate) struct NetworkUnsupportedError;
 
 impl Debug for NetworkUnsupportedError {
@@ -410,6 +587,11 @@ impl From<TryFromIntError> for StreamError {
 
 impl From<AnyError> for StreamError {
     fn from(v: AnyError) -> Self {
+        if v.is::<FileLimitError>() {
+            if let Some(c) = mapping::any_error_code(&v) {
+                return Self(StreamErrorInner::Wasi(c));
+            }
+        }
         Self(StreamErrorInner::Any(v))
     }
 }
@@ -460,25 +642,19 @@ impl From<GuestError> for StreamError {
 impl From<StreamError> for Result<FSErrorCode, AnyError> {
     fn from(v: StreamError) -> Self {
         Ok(match v.0 {
-            StreamErrorInner::Any(v) => return Err(v),
+            StreamErrorInner::Any(v) => match mapping::any_error_code(&v) {
+                Some(c) => {
+                    warn!("{v}");
+                    c
+                }
+                None => return Err(v),
+            },
             StreamErrorInner::Closed => return Err(StreamClosedError.into()),
             StreamErrorInner::Wasi(v) => v,
             StreamErrorInner::WasiP1(v) => return Err(WasiP1Error(v).into()),
-            StreamErrorInner::Io(v) => match v.kind() {
-                ErrorKind::Other => return Err(v.into()),
-                ErrorKind::NotFound => FSErrorCode::NoEntry,
-                ErrorKind::PermissionDenied => FSErrorCode::NotPermitted,
-                ErrorKind::AlreadyExists => FSErrorCode::Exist,
-                ErrorKind::InvalidInput => FSErrorCode::Invalid,
-                ErrorKind::Deadlock => FSErrorCode::Deadlock,
-                ErrorKind::DirectoryNotEmpty => FSErrorCode::NotEmpty,
-                ErrorKind::IsADirectory => FSErrorCode::IsDirectory,
-                ErrorKind::NotADirectory => FSErrorCode::NotDirectory,
-                ErrorKind::ReadOnlyFilesystem => FSErrorCode::ReadOnly,
-                ErrorKind::NotSeekable => FSErrorCode::InvalidSeek,
-                ErrorKind::Unsupported => FSErrorCode::Unsupported,
-                ErrorKind::InvalidData => FSErrorCode::IllegalByteSequence,
-                _ => FSErrorCode::Io,
+            StreamErrorInner::Io(v) => match mapping::error_kind_code(v.kind()) {
+                Some(c) => c,
+                None => return Err(v.into()),
             },
         })
     }
@@ -490,45 +666,7 @@ impl From<StreamError> for Result<Errno, AnyError> {
             return Ok(v);
         }
 
-        Ok(match <Result<FSErrorCode, AnyError>>::from(v)? {
-            FSErrorCode::Access => Errno::Acces,
-            FSErrorCode::WouldBlock => Errno::Again,
-            FSErrorCode::Already => Errno::Already,
-            FSErrorCode::BadDescriptor => Errno::Badf,
-            FSErrorCode::Busy => Errno::Busy,
-            FSErrorCode::Deadlock => Errno::Deadlk,
-            FSErrorCode::Quota => Errno::Dquot,
-            FSErrorCode::Exist => Errno::Exist,
-            FSErrorCode::FileTooLarge => Errno::Fbig,
-            FSErrorCode::IllegalByteSequence => Errno::Ilseq,
-            FSErrorCode::InProgress => Errno::Inprogress,
-            FSErrorCode::Interrupted => Errno::Intr,
-            FSErrorCode::Invalid => Errno::Inval,
-            FSErrorCode::Io => Errno::Io,
-            FSErrorCode::IsDirectory => Errno::Isdir,
-            FSErrorCode::Loop => Errno::Loop,
-            FSErrorCode::TooManyLinks => Errno::Mlink,
-            FSErrorCode::MessageSize => Errno::Msgsize,
-            FSErrorCode::NameTooLong => Errno::Nametoolong,
-            FSErrorCode::NoDevice => Errno::Nodev,
-            FSErrorCode::NoEntry => Errno::Noent,
-            FSErrorCode::NoLock => Errno::Nolck,
-            FSErrorCode::InsufficientMemory => Errno::Nomem,
-            FSErrorCode::InsufficientSpace => Errno::Nospc,
-            FSErrorCode::Unsupported => Errno::Notsup,
-            FSErrorCode::NotDirectory => Errno::Notdir,
-            FSErrorCode::NotEmpty => Errno::Notempty,
-            FSErrorCode::NotRecoverable => Errno::Notrecoverable,
-            FSErrorCode::NoTty => Errno::Notty,
-            FSErrorCode::NoSuchDevice => Errno::Nxio,
-            FSErrorCode::Overflow => Errno::Overflow,
-            FSErrorCode::NotPermitted => Errno::Perm,
-            FSErrorCode::Pipe => Errno::Pipe,
-            FSErrorCode::ReadOnly => Errno::Rofs,
-            FSErrorCode::InvalidSeek => Errno::Spipe,
-            FSErrorCode::TextFileBusy => Errno::Txtbsy,
-            FSErrorCode::CrossDevice => Errno::Xdev,
-        })
+        <Result<FSErrorCode, AnyError>>::from(v).map(mapping::code_errno)
     }
 }
 
@@ -557,6 +695,15 @@ impl From<StreamError> for AnyError {
 }
 
 impl StreamError {
+    /// Filesystem error code of error, if it's not a trap.
+    pub(crate) fn fs_error_code(&self) -> Option<FSErrorCode> {
+        match &self.0 {
+            StreamErrorInner::Wasi(v) => Some(*v),
+            StreamErrorInner::Any(v) => mapping::any_error_code(v),
+            _ => None,
+        }
+    }
+
     #[inline(always)]
     pub fn io(&self) -> Option<&IoError> {
         match &self.0 {
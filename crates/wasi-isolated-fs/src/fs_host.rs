@@ -1,11 +1,18 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
 use std::hash::{BuildHasher, Hasher};
-use std::io::{Error as IoError, ErrorKind};
-use std::path::Path;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use anyhow::Result as AnyResult;
+use anyhow::{Error as AnyError, Result as AnyResult};
+use camino::Utf8Path;
 use cap_fs_ext::MetadataExt;
-use cap_std::fs::{Dir as CapDir, DirEntry, File as CapFile, Metadata, ReadDir as CapReadDir};
+use cap_std::ambient_authority;
+use cap_std::fs::{
+    Dir as CapDir, DirEntry, File as CapFile, Metadata, OpenOptions, ReadDir as CapReadDir,
+};
 use cfg_if::cfg_if;
 use parking_lot::Mutex;
 use system_interface::fs::FileIoExt;
@@ -78,16 +85,117 @@ fn err_sync_handle(e: IoError) -> Result<(), IoError> {
     }
 }
 
+/// Atomically writes file at `path`, relative to host directory `root`.
+///
+/// Data is written into temporary file next to it, which is then renamed over `path`.
+/// Path can't escape `root`. Temporary file is removed on failure.
+/// Normalizes host path.
+///
+/// Repeated separators and `.` components are removed.
+/// On Windows, forward slashes are converted into backslashes (except in verbatim paths),
+/// so UNC paths (`//server/share`) are recognized.
+///
+/// Drive-relative paths (eg. `D:foo`) are rejected, as they depend on current directory of the drive.
+pub fn normalize_host_path(path: &Path) -> AnyResult<PathBuf> {
+    #[cfg(windows)]
+    let buf;
+    #[cfg(windows)]
+    let path = match path.to_str() {
+        Some(s) if !s.starts_with(r"\\?\") => {
+            buf = PathBuf::from(s.replace('/', "\\"));
+            &*buf
+        }
+        _ => path,
+    };
+
+    if let Some(Component::Prefix(p)) = path.components().next() {
+        if matches!(p.kind(), Prefix::Disk(_)) && !path.has_root() {
+            return Err(errors::DriveRelativePathError(path.display().to_string()).into());
+        }
+    }
+    Ok(path.components().collect())
+}
+
+pub fn write_file_atomic(
+    root: &Path,
+    path: &Utf8Path,
+    data: &[u8],
+) -> Result<(), errors::StreamError> {
+    write_file_atomic_with(
+        &CapDir::open_ambient_dir(root, ambient_authority())?,
+        path.as_std_path(),
+        |f| f.write_all(data),
+    )
+}
+
+fn write_file_atomic_with(
+    dir: &CapDir,
+    path: &Path,
+    f: impl FnOnce(&mut CapFile) -> IoResult<()>,
+) -> Result<(), errors::StreamError> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let (parent, Some(name)) = (path.parent().unwrap_or(Path::new("")), path.file_name()) else {
+        return Err(ErrorKind::InvalidInput.into());
+    };
+
+    let mut opts = OpenOptions::new();
+    opts.write(true).create_new(true);
+    let (tmp, mut file) = loop {
+        let mut tmp = OsString::from(".");
+        tmp.push(name);
+        tmp.push(format!(
+            ".{}-{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp = parent.join(tmp);
+        match dir.open_with(&tmp, &opts) {
+            Ok(v) => break (tmp, v),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+            Err(e) => return Err(e.into()),
+        }
+    };
+    let guard = scopeguard::guard((), |_| {
+        let _ = dir.remove_file(&tmp);
+    });
+
+    f(&mut file)?;
+    file.sync_all()?;
+    drop(file);
+    dir.rename(&tmp, dir, path)?;
+    scopeguard::ScopeGuard::into_inner(guard);
+    Ok(())
+}
+
 #[derive(Clone, Debug)]
 pub struct CapWrapper {
     desc: Arc<Descriptor>,
     access: AccessMode,
+    case_insensitive: bool,
 }
 
 impl CapWrapper {
     #[inline(always)]
     pub fn new(desc: Arc<Descriptor>, access: AccessMode) -> Self {
-        Self { desc, access }
+        Self {
+            desc,
+            access,
+            case_insensitive: false,
+        }
+    }
+
+    /// Sets case-insensitive path resolution when opening files.
+    #[inline(always)]
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Creates wrapper of descriptor opened from this one, inheriting path resolution.
+    #[inline(always)]
+    pub(crate) fn child(&self, desc: Descriptor, access: AccessMode) -> Self {
+        Self::new(Arc::new(desc), access).with_case_insensitive(self.case_insensitive)
     }
 
     #[inline(always)]
@@ -100,6 +208,11 @@ impl CapWrapper {
         self.access
     }
 
+    #[inline(always)]
+    pub fn case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
     pub(crate) fn read(&self) -> Result<&Self, errors::StreamError> {
         self.access.read_or_err()?;
         Ok(self)
@@ -222,6 +335,69 @@ impl CapWrapper {
         }
     }
 
+    /// Resolves path relative to directory.
+    ///
+    /// If case-insensitive, each component that does not exist is matched case-insensitively
+    /// against directory entries. Exact match is always preferred.
+    /// Components without any match are kept as-is, so that files can still be created.
+    pub(crate) fn resolve_path<'a>(
+        &self,
+        path: &'a Path,
+    ) -> Result<Cow<'a, Path>, errors::StreamError> {
+        let dir = self.dir()?;
+        if !self.case_insensitive || dir.symlink_metadata(path).is_ok() {
+            return Ok(path.into());
+        }
+
+        let mut ret = PathBuf::new();
+        for c in path.components() {
+            let Component::Normal(name) = c else {
+                ret.push(c);
+                continue;
+            };
+            ret.push(name);
+            if dir.symlink_metadata(&ret).is_ok() {
+                continue;
+            }
+            ret.pop();
+
+            let (Some(lower), Ok(entries)) = (
+                name.to_str().map(str::to_lowercase),
+                if ret.as_os_str().is_empty() {
+                    dir.entries()
+                } else {
+                    dir.read_dir(&ret)
+                },
+            ) else {
+                ret.push(name);
+                continue;
+            };
+
+            let mut found = None;
+            for e in entries {
+                let e = e?.file_name();
+                if e.to_str().is_none_or(|s| s.to_lowercase() != lower) {
+                    continue;
+                }
+                if let Some(prev) = &found {
+                    return Err(AnyError::from(errors::AmbiguousPathError {
+                        path: path.to_string_lossy().into_owned(),
+                        a: ret.join(prev).to_string_lossy().into_owned(),
+                        b: ret.join(e).to_string_lossy().into_owned(),
+                    })
+                    .into());
+                }
+                found = Some(e);
+            }
+            match found {
+                Some(v) => ret.push(v),
+                None => ret.push(name),
+            }
+        }
+
+        Ok(ret.into())
+    }
+
     pub(crate) fn read_at(
         file: &CapFile,
         buf: &mut [u8],
@@ -342,3 +518,97 @@ impl Iterator for &'_ ReadDir {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, read, read_dir, remove_dir_all, write};
+
+    #[test]
+    fn test_write_file_atomic() {
+        let root = std::env::temp_dir().join(format!("wasi-atomic-{}", std::process::id()));
+        create_dir_all(root.join("sub")).unwrap();
+        write(root.join("sub/save"), b"old").unwrap();
+        let _guard = scopeguard::guard((), |_| {
+            let _ = remove_dir_all(&root);
+        });
+        let names = || {
+            read_dir(root.join("sub"))
+                .unwrap()
+                .map(|v| v.unwrap().file_name())
+                .collect::<Vec<_>>()
+        };
+
+        // Failure between write and rename.
+        let dir = CapDir::open_ambient_dir(&root, ambient_authority()).unwrap();
+        let e = write_file_atomic_with(&dir, Path::new("sub/save"), |f| {
+            f.write_all(b"partial new")?;
+            Err(ErrorKind::Interrupted.into())
+        })
+        .unwrap_err();
+        assert_eq!(e.io().map(|e| e.kind()), Some(ErrorKind::Interrupted));
+        assert_eq!(read(root.join("sub/save")).unwrap(), b"old");
+        assert_eq!(names(), ["save"]);
+
+        write_file_atomic(&root, Utf8Path::new("sub/save"), b"new data").unwrap();
+        assert_eq!(read(root.join("sub/save")).unwrap(), b"new data");
+        write_file_atomic(&root, Utf8Path::new("sub/other"), b"created").unwrap();
+        assert_eq!(read(root.join("sub/other")).unwrap(), b"created");
+        let mut v = names();
+        v.sort();
+        assert_eq!(v, ["other", "save"]);
+
+        // Can't escape root.
+        write_file_atomic(&root, Utf8Path::new("../escaped"), b"").unwrap_err();
+        write_file_atomic(&root, Utf8Path::new(""), b"").unwrap_err();
+    }
+
+    #[test]
+    fn test_normalize_host_path() {
+        let f = |p: &str| normalize_host_path(Path::new(p)).unwrap();
+
+        #[cfg(not(windows))]
+        {
+            assert_eq!(f("/a//b/./c/"), Path::new("/a/b/c"));
+            assert_eq!(f("a/./b"), Path::new("a/b"));
+            assert_eq!(f("/a/../b"), Path::new("/a/../b"));
+            assert_eq!(f("D:foo"), Path::new("D:foo"));
+        }
+
+        #[cfg(windows)]
+        {
+            assert_eq!(f(r"D:\Mods\foo"), Path::new(r"D:\Mods\foo"));
+            assert_eq!(f(r"D:/Mods\./foo/"), Path::new(r"D:\Mods\foo"));
+            assert_eq!(f(r"\\server\share\mods"), Path::new(r"\\server\share\mods"));
+            assert_eq!(f("//server/share/mods"), Path::new(r"\\server\share\mods"));
+            assert_eq!(f(r"\\?\C:\Mods"), Path::new(r"\\?\C:\Mods"));
+            assert_eq!(
+                f(r"\\?\UNC\server\share\mods"),
+                Path::new(r"\\?\UNC\server\share\mods")
+            );
+
+            let e = normalize_host_path(Path::new("D:foo")).unwrap_err();
+            assert!(e.is::<errors::DriveRelativePathError>(), "{e}");
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_normalize_unc_mount() {
+        let root = std::env::temp_dir().join(format!("wasi-unc-{}", std::process::id()));
+        create_dir_all(root.join("sub")).unwrap();
+        write(root.join("sub/file.txt"), b"data").unwrap();
+        let _guard = scopeguard::guard((), |_| {
+            let _ = remove_dir_all(&root);
+        });
+
+        // Simulate share with verbatim UNC-like path to local directory, with mixed separators.
+        let s = root.to_str().unwrap().replace('\\', "/");
+        for p in [format!(r"\\?\{}", root.display()), s] {
+            let p = normalize_host_path(Path::new(&p)).unwrap();
+            let dir = CapDir::open_ambient_dir(&p, ambient_authority()).unwrap();
+            assert_eq!(dir.read("sub/file.txt").unwrap(), b"data");
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Imports host directory into isolated filesystem.
+//!
+//! Imported tree is built detached from the filesystem, then linked into place at once.
+//! Guest never observes partially imported directory, and failed or cancelled import
+//! returns it's quota as the detached tree is dropped.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fs::{read_dir, File as HostFile};
+use std::io::{Error as IoError, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{bail, Result as AnyResult};
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::{debug, instrument};
+
+use crate::errors::ImportCancelledError;
+use crate::fs_isolated::{Dir, File, IsolatedFSController, Node, ILLEGAL_CHARS};
+
+/// Size of buffer used to copy file content.
+const COPY_BUF_SIZE: usize = 1 << 20;
+
+/// Host directory tree, scanned before importing.
+///
+/// Symbolic links and special files are skipped.
+#[derive(Debug)]
+pub struct ImportPlan {
+    host: PathBuf,
+    /// Relative path and size of every entry (`None` for directory).
+    /// Directory always precedes it's content.
+    entries: Vec<(Utf8PathBuf, Option<u64>)>,
+    total_bytes: u64,
+}
+
+fn check_name(name: &str) -> AnyResult<()> {
+    if matches!(name, "" | "." | "..") || name.contains(ILLEGAL_CHARS) {
+        bail!("Invalid file name {name:?}");
+    }
+    Ok(())
+}
+
+impl ImportPlan {
+    #[instrument]
+    pub fn scan(host: &Path) -> AnyResult<Self> {
+        let mut ret = Self {
+            host: host.to_owned(),
+            entries: Vec::new(),
+            total_bytes: 0,
+        };
+        ret.scan_dir(Utf8Path::new(""))?;
+        debug!(
+            entries = ret.entries.len(),
+            total_bytes = ret.total_bytes,
+            "Scanned host directory"
+        );
+        Ok(ret)
+    }
+
+    fn scan_dir(&mut self, rel: &Utf8Path) -> AnyResult<()> {
+        let mut items = read_dir(self.host.join(rel))?.collect::<Result<Vec<_>, _>>()?;
+        items.sort_by_key(|e| e.file_name());
+        for e in items {
+            let name = e.file_name();
+            let Some(name) = name.to_str() else {
+                bail!("Non UTF-8 file name {name:?}");
+            };
+            check_name(name)?;
+
+            let path = rel.join(name);
+            let ty = e.file_type()?;
+            if ty.is_dir() {
+                self.entries.push((path.clone(), None));
+                self.scan_dir(&path)?;
+            } else if ty.is_file() {
+                let len = e.metadata()?.len();
+                self.total_bytes += len;
+                self.entries.push((path, Some(len)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Total size of files, in bytes.
+    #[inline]
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Imports tree into directory `parent` as `name`. Fails if `name` already exists.
+    ///
+    /// `progress` is called with number of bytes copied so far.
+    /// If `cancel` is set, import fails with [`ImportCancelledError`].
+    #[instrument(skip(self, controller, cancel, progress), fields(host = ?self.host))]
+    pub fn import(
+        &self,
+        controller: &IsolatedFSController,
+        parent: &Arc<Node>,
+        name: &str,
+        cancel: &AtomicBool,
+        progress: impl FnMut(u64),
+    ) -> AnyResult<()> {
+        check_name(name)?;
+        parent.try_dir()?;
+
+        let node = self.build(controller, parent, cancel, progress)?;
+        if parent
+            .try_dir()?
+            .add(name, || Ok::<_, Infallible>(node))?
+            .is_none()
+        {
+            return Err(IoError::from(ErrorKind::AlreadyExists).into());
+        }
+        Ok(())
+    }
+
+    /// Builds detached tree, returning it's root.
+    fn build(
+        &self,
+        controller: &IsolatedFSController,
+        parent: &Arc<Node>,
+        cancel: &AtomicBool,
+        mut progress: impl FnMut(u64),
+    ) -> AnyResult<Arc<Node>> {
+        let check_cancel = || {
+            if cancel.load(Ordering::Relaxed) {
+                Err(ImportCancelledError)
+            } else {
+                Ok(())
+            }
+        };
+
+        let root = Arc::new(Node::from((Dir::new(controller)?, Arc::downgrade(parent))));
+        let mut dirs = HashMap::from([(Utf8PathBuf::new(), root.clone())]);
+        let mut buf = Vec::new();
+        let mut done = 0u64;
+        for (path, size) in &self.entries {
+            check_cancel()?;
+            let (Some(p), Some(name)) = (path.parent(), path.file_name()) else {
+                unreachable!("entry path should be non-empty");
+            };
+            let dir = &dirs[p];
+
+            let node = if size.is_some() {
+                let mut file = File::new(controller)?;
+                let mut f = HostFile::open(self.host.join(path))?;
+                buf.resize(COPY_BUF_SIZE, 0);
+                let mut off = 0;
+                loop {
+                    check_cancel()?;
+                    let n = match f.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => n,
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e.into()),
+                    };
+                    file.write(&buf[..n], off)?;
+                    off += n;
+                    done += n as u64;
+                    progress(done);
+                }
+                Arc::new(Node::from((file, Arc::downgrade(dir))))
+            } else {
+                Arc::new(Node::from((Dir::new(controller)?, Arc::downgrade(dir))))
+            };
+
+            dir.try_dir()?
+                .add(name, || Ok::<_, Infallible>(node.clone()))?;
+            if size.is_none() {
+                dirs.insert(path.clone(), node);
+            }
+        }
+        Ok(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    use crate::fs_isolated::tests::lookup;
+
+    fn host_tree(name: &str) -> (PathBuf, impl Drop) {
+        let root = std::env::temp_dir().join(format!("wasi-import-{name}-{}", std::process::id()));
+        create_dir_all(root.join("a/b")).unwrap();
+        create_dir_all(root.join("empty")).unwrap();
+        write(root.join("top.txt"), b"top").unwrap();
+        write(root.join("a/mid.bin"), vec![7u8; 3 << 20]).unwrap();
+        write(root.join("a/b/leaf.txt"), b"leaf").unwrap();
+        let guard = scopeguard::guard(root.clone(), |root| {
+            let _ = remove_dir_all(root);
+        });
+        (root, guard)
+    }
+
+    fn read_all(node: &Node) -> Vec<u8> {
+        let mut f = node.try_file().unwrap();
+        let mut ret = Vec::new();
+        while ret.len() < f.len() {
+            let off = ret.len();
+            let (s, l) = f.read(usize::MAX, off);
+            ret.extend_from_slice(s);
+            ret.resize(off + l, 0);
+        }
+        ret
+    }
+
+    #[test]
+    fn test_import_dir() {
+        let (host, _guard) = host_tree("ok");
+        let plan = ImportPlan::scan(&host).unwrap();
+        assert_eq!(plan.total_bytes(), 3 + (3 << 20) + 4);
+
+        let cont = IsolatedFSController::new(16 << 20, 16).unwrap();
+        let root = cont.root();
+        let mut reported = Vec::new();
+        plan.import(&cont, &root, "mod", &AtomicBool::new(false), |v| {
+            reported.push(v)
+        })
+        .unwrap();
+        assert!(reported.windows(2).all(|v| v[0] < v[1]), "{reported:?}");
+        assert_eq!(reported.last(), Some(&plan.total_bytes()));
+
+        assert_eq!(read_all(&lookup(&root, "mod/top.txt").unwrap()), b"top");
+        assert_eq!(
+            read_all(&lookup(&root, "mod/a/mid.bin").unwrap()),
+            vec![7u8; 3 << 20]
+        );
+        assert_eq!(
+            read_all(&lookup(&root, "mod/a/b/leaf.txt").unwrap()),
+            b"leaf"
+        );
+        assert!(lookup(&root, "mod/empty")
+            .unwrap()
+            .try_dir()
+            .unwrap()
+            .is_empty());
+        let b = lookup(&root, "mod/a/b").unwrap();
+        assert!(Arc::ptr_eq(
+            &b.parent().unwrap(),
+            &lookup(&root, "mod/a").unwrap()
+        ));
+        let m = lookup(&root, "mod").unwrap();
+        assert!(Arc::ptr_eq(&m.parent().unwrap(), &root));
+
+        // Existing name is never replaced.
+        let e = plan
+            .import(&cont, &root, "mod", &AtomicBool::new(false), |_| ())
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<IoError>().map(|e| e.kind()),
+            Some(ErrorKind::AlreadyExists)
+        );
+        assert!(plan
+            .import(&cont, &root, "a/b", &AtomicBool::new(false), |_| ())
+            .is_err());
+    }
+
+    #[test]
+    fn test_import_cancel() {
+        let (host, _guard) = host_tree("cancel");
+        let plan = ImportPlan::scan(&host).unwrap();
+        // Quota is only enough for a single import.
+        let cont = IsolatedFSController::new(4 << 20, 8).unwrap();
+        let root = cont.root();
+
+        for _ in 0..3 {
+            let cancel = AtomicBool::new(false);
+            let e = plan
+                .import(&cont, &root, "mod", &cancel, |v| {
+                    if v > 1 << 20 {
+                        cancel.store(true, Ordering::Relaxed);
+                    }
+                })
+                .unwrap_err();
+            assert!(e.is::<ImportCancelledError>(), "{e:?}");
+            // Staged tree is never visible.
+            assert!(lookup(&root, "mod").is_none());
+        }
+
+        // Quota is returned, so the whole tree still fits.
+        plan.import(&cont, &root, "mod", &AtomicBool::new(false), |_| ())
+            .unwrap();
+        assert_eq!(
+            read_all(&lookup(&root, "mod/a/b/leaf.txt").unwrap()),
+            b"leaf"
+        );
+    }
+
+    #[test]
+    fn test_import_quota() {
+        let (host, _guard) = host_tree("quota");
+        let plan = ImportPlan::scan(&host).unwrap();
+        let cont = IsolatedFSController::new(2 << 20, 16).unwrap();
+        let root = cont.root();
+
+        assert!(plan
+            .import(&cont, &root, "mod", &AtomicBool::new(false), |_| ())
+            .is_err());
+        assert!(lookup(&root, "mod").is_none());
+        // Partially imported files are freed.
+        let mut f = File::new(&cont).unwrap();
+        f.write(&vec![1u8; 2 << 20], 0).unwrap();
+    }
+}
@@ -1,15 +1,16 @@
 use std::collections::btree_map::{BTreeMap, Entry};
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter, Result as FmtResult};
-use std::hash::{BuildHasher, Hash, Hasher};
+use std::hash::{BuildHasher, DefaultHasher, Hash, Hasher};
 use std::io::ErrorKind;
 use std::mem::replace;
 use std::ops::{BitAnd, BitOr, Deref, DerefMut};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock, Weak};
 use std::time::SystemTime;
 
 use anyhow::{Error, Result as AnyResult};
-use camino::{Utf8Component, Utf8Path};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
 use cfg_if::cfg_if;
 use parking_lot::{Mutex, MutexGuard, RwLock, RwLockWriteGuard};
 use smallvec::SmallVec;
@@ -18,6 +19,7 @@ use tracing::instrument;
 use crate::bindings::wasi;
 use crate::errors;
 
+/// Default maximum depth of symbolic link resolution.
 pub const LINK_DEPTH: usize = 10;
 
 pub(crate) static ILLEGAL_CHARS: &[char] = &['\\', '/', ':', '*', '?', '\"', '\'', '<', '>', '|'];
@@ -36,10 +38,11 @@ impl IsolatedFSController {
 
         Ok(Self {
             root: Arc::new_cyclic(|this| {
+                _ = limits.root.set(this.clone());
                 Node::from((
                     Dir {
                         limits: AcqNode {
-                            limits: Arc::downgrade(&limits),
+                            limits: limits.clone(),
                             inode: limits.get_inode(),
                         },
                         stamp: Timestamp::new(),
@@ -58,18 +61,201 @@ impl IsolatedFSController {
         self.root.clone()
     }
 
-    pub(crate) fn dup(&self) -> Self {
+    /// Creates another handle to the same filesystem.
+    pub fn dup(&self) -> Self {
         Self {
             limits: self.limits.clone(),
             root: self.root.clone(),
         }
     }
+
+    /// Sets behavior of unlinking file with open handles.
+    pub fn set_unlink_open(&self, policy: UnlinkOpenPolicy) {
+        self.limits
+            .unlink_deny
+            .store(policy == UnlinkOpenPolicy::Deny, Ordering::Relaxed);
+    }
+
+    pub fn unlink_open(&self) -> UnlinkOpenPolicy {
+        if self.limits.unlink_deny.load(Ordering::Relaxed) {
+            UnlinkOpenPolicy::Deny
+        } else {
+            UnlinkOpenPolicy::Allow
+        }
+    }
+
+    /// Sets behavior of writing file past size limit.
+    pub fn set_quota_policy(&self, policy: QuotaPolicy) {
+        self.limits
+            .quota_policy
+            .store(policy as u8, Ordering::Relaxed);
+    }
+
+    pub fn quota_policy(&self) -> QuotaPolicy {
+        QuotaPolicy::from_int(self.limits.quota_policy.load(Ordering::Relaxed))
+    }
+
+    /// Sets minimum and maximum size of file chunks.
+    ///
+    /// Both must be power of two, with `32 <= min <= max <= 2^30`.
+    /// Only affects files created afterwards.
+    pub fn set_chunk_size(&self, min: usize, max: usize) -> AnyResult<()> {
+        if !(min.is_power_of_two()
+            && max.is_power_of_two()
+            && 32 <= min
+            && min <= max
+            && max <= 1 << 30)
+        {
+            return Err(Error::msg(format!(
+                "Invalid chunk size (min: {min}, max: {max})"
+            )));
+        }
+
+        self.limits
+            .chunk_min_shift
+            .store(min.trailing_zeros() as u8, Ordering::Relaxed);
+        self.limits
+            .chunk_max_shift
+            .store(max.trailing_zeros() as u8, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns minimum and maximum size of file chunks.
+    pub fn chunk_size(&self) -> (usize, usize) {
+        let v = self.limits.chunk_size();
+        (v.min(), v.max())
+    }
+
+    /// Sets maximum depth of symbolic link resolution. Must be at least 1.
+    pub fn set_link_depth(&self, depth: usize) -> AnyResult<()> {
+        if depth == 0 {
+            return Err(Error::msg("Link depth must be at least 1"));
+        }
+
+        self.limits.link_depth.store(depth, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns maximum depth of symbolic link resolution.
+    pub fn link_depth(&self) -> usize {
+        self.limits.link_depth.load(Ordering::Relaxed)
+    }
+
+    /// Sets content deduplication of sealed files.
+    ///
+    /// If enabled, files are sealed when writable handle to it is closed,
+    /// and share chunks with identical sealed file.
+    pub fn set_dedup(&self, dedup: bool) {
+        self.limits.dedup.store(dedup, Ordering::Relaxed);
+    }
+
+    pub fn dedup(&self) -> bool {
+        self.limits.dedup.load(Ordering::Relaxed)
+    }
+
+    /// Returns statistics of content deduplication.
+    pub fn dedup_stats(&self) -> DedupStats {
+        let nodes: Vec<_> = {
+            let mut sealed = self.limits.sealed.lock();
+            sealed.retain(|_, v| {
+                v.retain(|v| v.strong_count() > 0);
+                !v.is_empty()
+            });
+            sealed
+                .values()
+                .flatten()
+                .filter_map(Weak::upgrade)
+                .collect()
+        };
+
+        let mut ret = DedupStats::default();
+        let mut seen = HashSet::new();
+        // Keep chunks alive, so it's address is not reused.
+        let mut chunks = Vec::new();
+        for n in nodes {
+            if !seen.insert(Arc::as_ptr(&n) as usize) {
+                continue;
+            }
+            let Some(f) = n.file() else { continue };
+            if f.sealed.is_none() {
+                continue;
+            }
+
+            ret.files += 1;
+            for c in &f.data[..f.chunk.count(f.size)] {
+                if seen.insert(Arc::as_ptr(c) as usize) {
+                    chunks.push(c.clone());
+                } else {
+                    ret.saved += c.charge;
+                }
+            }
+        }
+
+        ret
+    }
+}
+
+/// Statistics of content deduplication.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DedupStats {
+    /// Number of sealed files.
+    pub files: usize,
+    /// Number of bytes not charged because it's shared between sealed files.
+    pub saved: usize,
+}
+
+/// Behavior of unlinking file with open handles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnlinkOpenPolicy {
+    /// Unlink succeeds, file lives until last handle is dropped (POSIX behavior).
+    #[default]
+    Allow,
+    /// Unlink fails with busy error.
+    Deny,
+}
+
+/// Behavior of writing file past size limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum QuotaPolicy {
+    /// Write fails with no space error.
+    #[default]
+    Fail = 0,
+    /// Write fails with no space error, and partially written file is removed.
+    FailAndTruncate = 1,
+    /// Least recently accessed files without open handles are removed, then write is retried once.
+    EvictLru = 2,
+}
+
+impl QuotaPolicy {
+    const fn from_int(v: u8) -> Self {
+        match v {
+            1 => Self::FailAndTruncate,
+            2 => Self::EvictLru,
+            _ => Self::Fail,
+        }
+    }
 }
 
+/// Limits and shared state of filesystem.
+///
+/// Every node and chunk holds a strong reference, so open handles stay usable
+/// after controller is dropped.
 struct FSLimits {
     cur_size: AtomicUsize,
     cur_node: AtomicUsize,
     inode: AtomicUsize,
+    unlink_deny: AtomicBool,
+    quota_policy: AtomicU8,
+    chunk_min_shift: AtomicU8,
+    chunk_max_shift: AtomicU8,
+    link_depth: AtomicUsize,
+    dedup: AtomicBool,
+    /// Sealed files keyed by content hash.
+    sealed: Mutex<HashMap<u64, SmallVec<[Weak<Node>; 1]>>>,
+    /// Serializes cross-directory moves, so ancestry is stable while checked.
+    rename: Mutex<()>,
+    root: OnceLock<Weak<Node>>,
 }
 
 impl FSLimits {
@@ -78,6 +264,15 @@ impl FSLimits {
             cur_size: AtomicUsize::new(max_size),
             cur_node: AtomicUsize::new(max_node),
             inode: AtomicUsize::new(0),
+            unlink_deny: AtomicBool::new(false),
+            quota_policy: AtomicU8::new(QuotaPolicy::Fail as u8),
+            chunk_min_shift: AtomicU8::new(MIN_SHIFT),
+            chunk_max_shift: AtomicU8::new(MAX_SHIFT),
+            link_depth: AtomicUsize::new(LINK_DEPTH),
+            dedup: AtomicBool::new(false),
+            sealed: Mutex::new(HashMap::new()),
+            rename: Mutex::new(()),
+            root: OnceLock::new(),
         }
     }
 
@@ -116,29 +311,79 @@ impl FSLimits {
         Self::take_val(&self.cur_node, size)
     }
 
-    fn weak_take_size(this: &Weak<Self>, size: usize) -> bool {
-        match this.upgrade() {
-            Some(v) => v.take_size(size),
-            None => false,
+    fn chunk_size(&self) -> ChunkSize {
+        ChunkSize {
+            min_shift: self.chunk_min_shift.load(Ordering::Relaxed),
+            max_shift: self.chunk_max_shift.load(Ordering::Relaxed),
         }
     }
 
-    fn put_size_node(this: &Weak<Self>, size: usize, node: usize) {
-        if let Some(v) = this.upgrade() {
-            Self::put_val(&v.cur_size, size);
-            Self::put_val(&v.cur_node, node);
+    fn unlink_deny(&self) -> bool {
+        self.unlink_deny.load(Ordering::Relaxed)
+    }
+
+    fn quota_policy(&self) -> QuotaPolicy {
+        QuotaPolicy::from_int(self.quota_policy.load(Ordering::Relaxed))
+    }
+
+    /// Removes least recently accessed files without open handles until `size` bytes is available.
+    ///
+    /// Returns `true` if any file is removed.
+    fn evict_lru(&self, size: usize, keep: &Arc<Node>) -> bool {
+        let Some(root) = self.root.get().and_then(Weak::upgrade) else {
+            return false;
+        };
+
+        let mut files = Vec::new();
+        let mut stack = vec![root];
+        while let Some(n) = stack.pop() {
+            let Some(d) = n.dir() else { continue };
+            for (k, v) in d.items.iter() {
+                match &v.0 {
+                    NodeItem::Dir(_) => stack.push(v.clone()),
+                    NodeItem::File(f) if v.handles() == 0 && !Arc::ptr_eq(v, keep) => files.push((
+                        f.lock().stamp.atime,
+                        n.clone(),
+                        k.clone(),
+                        Arc::downgrade(v),
+                    )),
+                    _ => (),
+                }
+            }
         }
+        files.sort_unstable_by_key(|(t, ..)| *t);
+
+        let mut ret = false;
+        for (_, parent, k, v) in files {
+            if self.cur_size.load(Ordering::Acquire) >= size {
+                break;
+            }
+            let Some(mut d) = parent.dir() else { continue };
+            match (d.items.get(&k), v.upgrade()) {
+                (Some(n), Some(v)) if Arc::ptr_eq(n, &v) && v.handles() == 0 => (),
+                _ => continue,
+            }
+            d.remove(&k);
+            ret = true;
+        }
+
+        ret
+    }
+
+    fn put_size_node(&self, size: usize, node: usize) {
+        Self::put_val(&self.cur_size, size);
+        Self::put_val(&self.cur_node, node);
     }
 }
 
 struct AcqNode {
-    limits: Weak<FSLimits>,
+    limits: Arc<FSLimits>,
     inode: usize,
 }
 
 impl Drop for AcqNode {
     fn drop(&mut self) {
-        FSLimits::put_size_node(&self.limits, 0, 1);
+        self.limits.put_size_node(0, 1);
     }
 }
 
@@ -148,7 +393,7 @@ impl AcqNode {
             Err(errors::FileLimitError::Node.into())
         } else {
             Ok(Self {
-                limits: Arc::downgrade(&controller.limits),
+                limits: controller.limits.clone(),
                 inode: controller.limits.get_inode(),
             })
         }
@@ -201,23 +446,95 @@ cfg_if! {
     }
 }
 
-const MAX_SECTOR: usize = 1 << MAX_SHIFT;
-const MIN_SECTOR: usize = 1 << MIN_SHIFT;
-const MASK: usize = MAX_SECTOR - 1;
+/// Minimum and maximum size of file chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ChunkSize {
+    min_shift: u8,
+    max_shift: u8,
+}
+
+impl Default for ChunkSize {
+    fn default() -> Self {
+        Self {
+            min_shift: MIN_SHIFT,
+            max_shift: MAX_SHIFT,
+        }
+    }
+}
+
+impl ChunkSize {
+    #[inline(always)]
+    const fn min(self) -> usize {
+        1 << self.min_shift
+    }
+
+    #[inline(always)]
+    const fn max(self) -> usize {
+        1 << self.max_shift
+    }
+
+    #[inline(always)]
+    const fn mask(self) -> usize {
+        self.max() - 1
+    }
+
+    /// Number of chunks needed for file with size `size`.
+    #[inline(always)]
+    const fn count(self, size: usize) -> usize {
+        (size + self.mask()) >> self.max_shift
+    }
+
+    /// Clamped chunk size.
+    fn clamped(self, v: usize) -> usize {
+        match v {
+            0 => 0,
+            1..=16 => 16,
+            v if v <= self.min() => self.min(),
+            v if v < self.max() => v.next_power_of_two(),
+            _ => self.max(),
+        }
+    }
+
+    /// Charged size of chunk at index `i` of file with size `size`.
+    fn charge(self, i: usize, size: usize) -> usize {
+        match size.saturating_sub(i << self.max_shift) {
+            v if v < self.max() => self.clamped(v),
+            _ => self.max(),
+        }
+    }
+}
+
+/// Chunk of file data.
+///
+/// Chunks are shared between copy-on-write clones, and its size is charged once
+/// until the last owner drops it.
+struct Chunk {
+    limits: Arc<FSLimits>,
+    charge: usize,
+    data: FileChunk,
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        self.limits.put_size_node(self.charge, 0);
+    }
+}
 
 pub struct File {
-    limits: Weak<FSLimits>,
+    limits: Arc<FSLimits>,
     inode: usize,
     stamp: Timestamp,
+    chunk: ChunkSize,
 
     size: usize,
-    size_chunks: usize,
-    data: SmallVec<[FileChunk; 4]>,
+    data: SmallVec<[Arc<Chunk>; 4]>,
+    /// Content hash, if file is sealed and not modified since.
+    sealed: Option<u64>,
 }
 
 impl Drop for File {
     fn drop(&mut self) {
-        FSLimits::put_size_node(&self.limits, self.size_chunks, 1);
+        self.limits.put_size_node(0, 1);
     }
 }
 
@@ -228,13 +545,34 @@ impl File {
         }
 
         Ok(Self {
-            limits: Arc::downgrade(&controller.limits),
+            limits: controller.limits.clone(),
             inode: controller.limits.get_inode(),
             stamp: Timestamp::new(),
+            chunk: controller.limits.chunk_size(),
 
             size: 0,
-            size_chunks: 0,
             data: Default::default(),
+            sealed: None,
+        })
+    }
+
+    /// Creates copy-on-write clone of file.
+    ///
+    /// Data is shared until either side writes into it.
+    pub fn try_clone(&self) -> AnyResult<Self> {
+        if !self.limits.take_node(1) {
+            return Err(errors::FileLimitError::Node.into());
+        }
+
+        Ok(Self {
+            limits: self.limits.clone(),
+            inode: self.limits.get_inode(),
+            stamp: Timestamp::new(),
+            chunk: self.chunk,
+
+            size: self.size,
+            data: self.data.clone(),
+            sealed: None,
         })
     }
 
@@ -263,23 +601,35 @@ impl File {
         self.size == 0
     }
 
-    #[inline(always)]
+    /// Total size of chunks, including ones shared with other files.
     pub fn capacity(&self) -> usize {
-        self.size_chunks
+        self.data.iter().map(|v| v.charge).sum()
+    }
+
+    /// Size of chunks shared with other files.
+    pub fn shared_capacity(&self) -> usize {
+        self.data
+            .iter()
+            .filter(|&v| Arc::strong_count(v) > 1)
+            .map(|v| v.charge)
+            .sum()
     }
 
     pub fn read(&mut self, len: usize, off: usize) -> (&[u8], usize) {
+        let chunk = self.chunk;
         let ret: (&[_], _) = if len == 0 || off >= self.size {
             (&[], 0)
-        } else if let Some(v) = self.data.get(off >> MAX_SHIFT) {
-            let o = off & MASK;
+        } else if let Some(v) = self.data.get(off >> chunk.max_shift) {
+            let o = off & chunk.mask();
             let e = o
                 .saturating_add(len)
-                .min(self.size - (off & !MASK))
-                .min(MAX_SECTOR);
+                .min(self.size - (off & !chunk.mask()))
+                .min(chunk.max());
             let l = e - o;
             (
-                v.get(o..).map_or(&[] as &[_], |v| v.get(..l).unwrap_or(v)),
+                v.data
+                    .get(o..)
+                    .map_or(&[] as &[_], |v| v.get(..l).unwrap_or(v)),
                 l,
             )
         } else {
@@ -295,31 +645,22 @@ impl File {
             return Ok(());
         }
 
+        let chunk = self.chunk;
         let end = off + buf.len();
-        if end > self.size {
-            let ec = (end & !MASK) + Self::clamped_size(end & MASK);
-            let v = ec.saturating_sub(self.size_chunks);
-            if v > 0 {
-                if !FSLimits::weak_take_size(&self.limits, v) {
-                    return Err(errors::FileLimitError::Size(v).into());
-                }
-                self.size_chunks = ec;
-            }
-
-            self.size = end;
-        }
+        self.prepare(end.max(self.size), off >> chunk.max_shift, chunk.count(end))?;
+        self.size = self.size.max(end);
 
         self.stamp.modify();
-        let (mut d, mut r) = (off >> MAX_SHIFT, off & MASK);
+        self.sealed = None;
+        let (mut d, mut r) = (off >> chunk.max_shift, off & chunk.mask());
         while !buf.is_empty() {
-            let Some(v) = self.data.get_mut(d) else {
-                self.data.push(FileChunk::from_buf(Default::default()));
-                continue;
-            };
+            let v = &mut Arc::get_mut(&mut self.data[d])
+                .expect("chunk should be unique")
+                .data;
 
-            let s = r.saturating_add(buf.len()).min(MAX_SECTOR);
+            let s = r.saturating_add(buf.len()).min(chunk.max());
             if s > v.len() && s > 16 {
-                let s = Self::clamped_size(s);
+                let s = chunk.clamped(s);
                 v.reserve_exact(s - v.len());
                 v.resize(s, 0);
             }
@@ -328,69 +669,189 @@ impl File {
             v[r..s].copy_from_slice(a);
             (buf, d, r) = (b, d + 1, 0);
         }
-        debug_assert_eq!(self.data.len(), (self.size + MASK) >> MAX_SHIFT);
+        debug_assert!(self.data.len() >= chunk.count(self.size));
 
         Ok(())
     }
 
     pub fn resize(&mut self, size: usize) -> AnyResult<()> {
         if size <= self.size {
-            self.truncate(size);
-            return Ok(());
+            return self.truncate(size);
         }
         self.stamp.modify();
+        self.sealed = None;
 
-        let ec = (size & !MASK) + Self::clamped_size(size & MASK);
-        let v = ec.saturating_sub(self.size_chunks);
-        if v > 0 {
-            if !FSLimits::weak_take_size(&self.limits, v) {
-                return Err(errors::FileLimitError::Size(v).into());
-            }
-            self.size_chunks = ec;
-        }
-
-        for _ in (self.size + MASK) >> MAX_SHIFT..(size + MASK) >> MAX_SHIFT {
-            self.data.push(FileChunk::from_buf(Default::default()));
-        }
+        let n = self.chunk.count(self.size);
+        self.prepare(size, n, n)?;
         self.size = size;
-        debug_assert_eq!(self.data.len(), (size + MASK) >> MAX_SHIFT);
+        debug_assert!(self.data.len() >= self.chunk.count(size));
 
         Ok(())
     }
 
-    pub fn truncate(&mut self, size: usize) {
+    pub fn truncate(&mut self, size: usize) -> AnyResult<()> {
         self.stamp.modify();
-        if size >= self.size {
-            return;
+        if size > self.size {
+            return Ok(());
+        }
+        if size < self.size {
+            self.sealed = None;
         }
 
-        let new_chunks = size.saturating_add(MASK) & !MASK;
-        let v = self.size_chunks.saturating_sub(new_chunks);
-        if v > 0 {
-            FSLimits::put_size_node(&self.limits, v, 0);
-            self.size_chunks = new_chunks;
+        // Also releases reserved chunks past new size.
+        let n = self.chunk.count(size);
+        let i = size - (n.saturating_sub(1) << self.chunk.max_shift);
+        if let Some(v) = n.checked_sub(1).and_then(|n| self.data.get_mut(n)) {
+            if v.data.get(i..).is_some_and(|v| v.iter().any(|&b| b != 0)) {
+                Self::make_unique(&self.limits, v)?.data[i..].fill(0);
+            }
         }
+        self.data.truncate(n);
         self.size = size;
-        self.data.truncate(new_chunks >> MAX_SHIFT);
-        let i = self.size - self.data.len().saturating_sub(1) * MAX_SECTOR;
-        if let Some(v) = self.data.last_mut() {
-            if i < v.len() {
-                v[i..].fill(0);
+
+        debug_assert_eq!(self.data.len(), self.chunk.count(size));
+        Ok(())
+    }
+
+    /// Reserves space for file to grow up to `len` bytes, without changing its size.
+    ///
+    /// Space is charged immediately and chunk buffers are allocated at full size,
+    /// so subsequent writes up to `len` do not reallocate.
+    /// Reserved space is released when file is truncated below it or dropped.
+    pub fn reserve(&mut self, len: usize) -> AnyResult<()> {
+        if len <= self.size {
+            return Ok(());
+        }
+
+        let n = self.chunk.count(self.size);
+        self.prepare(len, n, n)?;
+
+        let first = self.size >> self.chunk.max_shift;
+        for v in self.data[first..self.chunk.count(len)].iter_mut() {
+            let Some(v) = Arc::get_mut(v) else { continue };
+            if v.charge > 16 && v.charge > v.data.capacity() {
+                v.data.reserve_exact(v.charge - v.data.len());
             }
         }
 
-        debug_assert_eq!(self.data.len(), (size + MASK) >> MAX_SHIFT);
+        Ok(())
     }
 
-    /// Clamped chunk size.
-    fn clamped_size(v: usize) -> usize {
-        match v {
-            0 => 0,
-            1..=16 => 16,
-            17..=MIN_SECTOR => MIN_SECTOR,
-            v @ ..MAX_SECTOR => v.next_power_of_two(),
-            MAX_SECTOR.. => MAX_SECTOR,
+    /// Returns `true` if file is sealed and not modified since.
+    #[inline(always)]
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.is_some()
+    }
+
+    /// Content of chunk at index `i` with trailing zeroes stripped, and it's length in file.
+    fn chunk_content(&self, i: usize) -> (&[u8], usize) {
+        let len = (self.size - (i << self.chunk.max_shift)).min(self.chunk.max());
+        let v = &self.data[i].data;
+        let v = &v[..v.len().min(len)];
+        (
+            &v[..v.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1)],
+            len,
+        )
+    }
+
+    fn content_hash(&self) -> u64 {
+        let mut h = DefaultHasher::new();
+        (self.size, self.chunk.max_shift).hash(&mut h);
+        for i in 0..self.chunk.count(self.size) {
+            self.chunk_content(i).hash(&mut h);
         }
+        h.finish()
+    }
+
+    fn content_eq(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self.chunk.max_shift == other.chunk.max_shift
+            && (0..self.chunk.count(self.size)).all(|i| {
+                Arc::ptr_eq(&self.data[i], &other.data[i])
+                    || self.chunk_content(i) == other.chunk_content(i)
+            })
+    }
+
+    /// Makes chunk unique, copying it if it's shared.
+    fn make_unique<'a>(limits: &Arc<FSLimits>, v: &'a mut Arc<Chunk>) -> AnyResult<&'a mut Chunk> {
+        if Arc::get_mut(v).is_none() {
+            if !limits.take_size(v.charge) {
+                return Err(errors::FileLimitError::Size(v.charge).into());
+            }
+            *v = Arc::new(Chunk {
+                limits: limits.clone(),
+                charge: v.charge,
+                data: v.data.clone(),
+            });
+        }
+
+        Ok(Arc::get_mut(v).expect("chunk should be unique"))
+    }
+
+    /// Prepares chunks for file with size `size`.
+    ///
+    /// Chunks in range `start..end` are made unique for writing, copying shared chunks.
+    /// All size needed is acquired at once, so it fails without modifying file.
+    fn prepare(&mut self, size: usize, start: usize, end: usize) -> AnyResult<()> {
+        let chunk = self.chunk;
+        let n = chunk.count(size);
+        // Only last chunk might need to grow, reserved chunks past it are already charged.
+        let from = start.min(chunk.count(self.size).saturating_sub(1));
+        // Returns size needed to prepare chunk, if it needs to be changed.
+        let needed = |i: usize, v: Option<&Arc<Chunk>>| -> Option<usize> {
+            let c = chunk.charge(i, size);
+            match v {
+                None => Some(c),
+                Some(v) if (start..end).contains(&i) || c > v.charge => {
+                    Some(if Arc::strong_count(v) > 1 {
+                        c.max(v.charge)
+                    } else {
+                        c.saturating_sub(v.charge)
+                    })
+                }
+                Some(_) => None,
+            }
+        };
+
+        let total: usize = (from..n).filter_map(|i| needed(i, self.data.get(i))).sum();
+        if !self.limits.take_size(total) {
+            return Err(errors::FileLimitError::Size(total).into());
+        }
+
+        let mut used = 0;
+        for i in from..n {
+            let Some(v) = self.data.get_mut(i) else {
+                let charge = chunk.charge(i, size);
+                used += charge;
+                self.data.push(Arc::new(Chunk {
+                    limits: self.limits.clone(),
+                    charge,
+                    data: FileChunk::from_buf(Default::default()),
+                }));
+                continue;
+            };
+            let c = chunk.charge(i, size);
+            if !(start..end).contains(&i) && c <= v.charge {
+                continue;
+            }
+
+            if let Some(v) = Arc::get_mut(v) {
+                used += c.saturating_sub(v.charge);
+                v.charge = v.charge.max(c);
+            } else {
+                let charge = c.max(v.charge);
+                used += charge;
+                *v = Arc::new(Chunk {
+                    limits: self.limits.clone(),
+                    charge,
+                    data: v.data.clone(),
+                });
+            }
+        }
+        // Other file might drop shared chunk in the meantime, return unused size.
+        self.limits.put_size_node(total.saturating_sub(used), 0);
+
+        Ok(())
     }
 }
 
@@ -655,7 +1116,7 @@ pub(crate) enum NodeItem {
     Link(RwLock<Link>),
 }
 
-pub struct Node(pub(crate) NodeItem, RwLock<Weak<Node>>);
+pub struct Node(pub(crate) NodeItem, RwLock<Weak<Node>>, AtomicUsize);
 
 impl Debug for Node {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
@@ -683,6 +1144,22 @@ impl Node {
         matches!(self.0, NodeItem::Dir(_))
     }
 
+    /// Number of open handles (descriptors and streams) to this node.
+    #[inline(always)]
+    pub fn handles(&self) -> usize {
+        self.2.load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    fn acquire_handle(&self) {
+        self.2.fetch_add(1, Ordering::AcqRel);
+    }
+
+    #[inline(always)]
+    fn release_handle(&self) {
+        self.2.fetch_sub(1, Ordering::AcqRel);
+    }
+
     #[inline(always)]
     pub fn is_file(&self) -> bool {
         matches!(self.0, NodeItem::File(_))
@@ -751,6 +1228,126 @@ impl Node {
         self.1.read().upgrade()
     }
 
+    /// Checks if this node is `node` or one of it's ancestors.
+    fn is_ancestor_of(self: &Arc<Self>, node: &Arc<Self>) -> bool {
+        let mut p = node.clone();
+        loop {
+            if Arc::ptr_eq(self, &p) {
+                return true;
+            }
+            // Root is it's own parent.
+            match p.parent() {
+                Some(v) if !Arc::ptr_eq(&v, &p) => p = v,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Writes into file at offset `off`, or at the end of file if it's `None`.
+    ///
+    /// If size limit is reached, applies quota policy of the filesystem.
+    fn write_file(
+        self: &Arc<Self>,
+        buf: &[u8],
+        off: Option<usize>,
+    ) -> Result<(), errors::StreamError> {
+        let mut retried = false;
+        loop {
+            let mut v = self.file().ok_or(ErrorKind::IsADirectory)?;
+            let o = off.unwrap_or_else(|| v.len());
+            let Err(e) = v.write(buf, o) else {
+                return Ok(());
+            };
+            let Some(&errors::FileLimitError::Size(size)) =
+                e.downcast_ref::<errors::FileLimitError>()
+            else {
+                return Err(e.into());
+            };
+            let limits = v.limits.clone();
+            drop(v);
+
+            match limits.quota_policy() {
+                QuotaPolicy::Fail => (),
+                QuotaPolicy::FailAndTruncate => self.remove_partial(),
+                QuotaPolicy::EvictLru if !retried => {
+                    retried = true;
+                    if limits.evict_lru(size, self) {
+                        continue;
+                    }
+                }
+                QuotaPolicy::EvictLru => (),
+            }
+            return Err(wasi::filesystem::types::ErrorCode::InsufficientSpace.into());
+        }
+    }
+
+    /// Seals file, sharing it's chunks with identical sealed file if deduplication is enabled.
+    ///
+    /// Modifying file afterwards unseals it and copies shared chunks.
+    /// Returns `false` if deduplication is disabled, or if file is busy.
+    pub fn seal(self: &Arc<Self>) -> bool {
+        let NodeItem::File(file) = &self.0 else {
+            return false;
+        };
+        let Some(mut file) = file.try_lock() else {
+            return false;
+        };
+        if file.sealed.is_some() {
+            return true;
+        }
+        let limits = file.limits.clone();
+        if !limits.dedup.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let hash = file.content_hash();
+        let mut sealed = limits.sealed.lock();
+        let v = sealed.entry(hash).or_default();
+        // Other files never block on sealed table, so trying to lock them can't deadlock.
+        v.retain(|v| match v.upgrade() {
+            None => false,
+            Some(v) if Arc::ptr_eq(&v, self) => false,
+            Some(v) => match &v.0 {
+                NodeItem::File(v) => v.try_lock().is_none_or(|v| v.sealed == Some(hash)),
+                _ => false,
+            },
+        });
+        for v in v.iter().filter_map(Weak::upgrade) {
+            let NodeItem::File(v) = &v.0 else { continue };
+            let Some(v) = v.try_lock() else { continue };
+            if v.sealed != Some(hash) || !file.content_eq(&v) {
+                continue;
+            }
+
+            // Dropping own chunks returns it's size.
+            let n = file.chunk.count(file.size);
+            file.data = v.data[..n].iter().cloned().collect();
+            break;
+        }
+        v.push(Arc::downgrade(self));
+        file.sealed = Some(hash);
+
+        true
+    }
+
+    /// Truncates file and unlinks it from it's parent.
+    fn remove_partial(self: &Arc<Self>) {
+        if let Some(mut v) = self.file() {
+            // Truncating to zero never allocates.
+            _ = v.truncate(0);
+        }
+        let Some(p) = self.parent() else { return };
+        let Some(mut d) = p.dir() else { return };
+        let k = d
+            .items
+            .iter()
+            .find(|(_, v)| Arc::ptr_eq(v, self))
+            .map(|(k, _)| k.clone());
+        if let Some(k) = k {
+            d.remove(&k);
+        }
+    }
+
     pub fn stamp(&self) -> impl '_ + DerefMut<Target = Timestamp> {
         enum NodeItemRef<'a> {
             File(MutexGuard<'a, File>),
@@ -830,18 +1427,24 @@ impl Node {
         }
     }
 
+    /// Resolves symbolic link. `path` is the path being resolved, for error reporting.
     fn follow_link(
         self: Arc<Self>,
         controller: &IsolatedFSController,
         depth: usize,
+        path: &Utf8Path,
     ) -> Result<Arc<Node>, errors::StreamError> {
         let (d, n) = match &self.0 {
-            NodeItem::Link(v) => (
-                depth
-                    .checked_sub(1)
-                    .ok_or(wasi::filesystem::types::ErrorCode::Loop)?,
-                v.read(),
-            ),
+            NodeItem::Link(v) => match depth.checked_sub(1) {
+                Some(d) => (d, v.read()),
+                None => {
+                    return Err(Error::from(errors::SymlinkLoopError {
+                        path: path.to_string(),
+                        depth: controller.link_depth(),
+                    })
+                    .into())
+                }
+            },
             _ => return Ok(self),
         };
 
@@ -855,7 +1458,7 @@ impl Node {
                     ret.parent_or_root(controller).ok_or(ErrorKind::NotFound)?
                 }
                 Utf8Component::Normal(p) => ret
-                    .follow_link(controller, d)?
+                    .follow_link(controller, d, path)?
                     .dir()
                     .ok_or(ErrorKind::NotADirectory)?
                     .get(p)
@@ -863,32 +1466,48 @@ impl Node {
             };
         }
 
-        ret.follow_link(controller, d)
+        ret.follow_link(controller, d, path)
     }
 
     pub fn follow_symlink(
         self: Arc<Self>,
         controller: &IsolatedFSController,
     ) -> Result<Arc<Self>, errors::StreamError> {
-        self.follow_link(controller, LINK_DEPTH)
+        let path = match &self.0 {
+            NodeItem::Link(v) => Utf8PathBuf::from(v.read().get()),
+            _ => return Ok(self),
+        };
+        self.follow_link(controller, controller.link_depth(), &path)
     }
 }
 
 impl From<(File, Weak<Node>)> for Node {
     fn from((v, p): (File, Weak<Node>)) -> Self {
-        Self(NodeItem::File(Mutex::new(v)), RwLock::new(p))
+        Self(
+            NodeItem::File(Mutex::new(v)),
+            RwLock::new(p),
+            AtomicUsize::new(0),
+        )
     }
 }
 
 impl From<(Dir, Weak<Node>)> for Node {
     fn from((v, p): (Dir, Weak<Node>)) -> Self {
-        Self(NodeItem::Dir(Mutex::new(v)), RwLock::new(p))
+        Self(
+            NodeItem::Dir(Mutex::new(v)),
+            RwLock::new(p),
+            AtomicUsize::new(0),
+        )
     }
 }
 
 impl From<(Link, Weak<Node>)> for Node {
     fn from((v, p): (Link, Weak<Node>)) -> Self {
-        Self(NodeItem::Link(RwLock::new(v)), RwLock::new(p))
+        Self(
+            NodeItem::Link(RwLock::new(v)),
+            RwLock::new(p),
+            AtomicUsize::new(0),
+        )
     }
 }
 
@@ -992,12 +1611,27 @@ impl CreateParams {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct CapWrapper {
     access: AccessMode,
     node: Arc<Node>,
 }
 
+impl Clone for CapWrapper {
+    fn clone(&self) -> Self {
+        Self::new(self.node.clone(), self.access)
+    }
+}
+
+impl Drop for CapWrapper {
+    fn drop(&mut self) {
+        if self.access.is_write() {
+            self.node.seal();
+        }
+        self.node.release_handle();
+    }
+}
+
 impl CapWrapper {
     fn to_datetime(t: SystemTime) -> wasi::filesystem::types::Datetime {
         let (mut s, mut n);
@@ -1022,6 +1656,7 @@ impl CapWrapper {
     #[instrument]
     #[inline(always)]
     pub fn new(node: Arc<Node>, access: AccessMode) -> Self {
+        node.acquire_handle();
         Self { node, access }
     }
 
@@ -1157,11 +1792,14 @@ impl CapWrapper {
         }
 
         match &self.node.0 {
-            NodeItem::File(_) => Ok(FileAccessor {
-                file: self.node.clone(),
-                mode,
-                closed: false,
-            }),
+            NodeItem::File(_) => {
+                self.node.acquire_handle();
+                Ok(FileAccessor {
+                    file: self.node.clone(),
+                    mode,
+                    closed: false,
+                })
+            }
             NodeItem::Dir(_) => Err(ErrorKind::IsADirectory.into()),
             NodeItem::Link(_) => Err(wasi::filesystem::types::ErrorCode::Loop.into()),
         }
@@ -1207,7 +1845,7 @@ impl CapWrapper {
             };
 
             if follow_symlink {
-                node = node.follow_link(controller, LINK_DEPTH)?;
+                node = node.follow_link(controller, controller.link_depth(), path)?;
             }
 
             let mut v = node.dir().ok_or(ErrorKind::NotADirectory)?;
@@ -1235,7 +1873,7 @@ impl CapWrapper {
         }
 
         if follow_symlink {
-            node = node.follow_link(controller, LINK_DEPTH)?;
+            node = node.follow_link(controller, controller.link_depth(), path)?;
         }
 
         Ok(Self::new(node, access))
@@ -1246,7 +1884,10 @@ impl CapWrapper {
         mut self,
         controller: &IsolatedFSController,
     ) -> Result<Self, errors::StreamError> {
-        self.node = self.node.follow_symlink(controller)?;
+        let node = self.node.clone().follow_symlink(controller)?;
+        node.acquire_handle();
+        self.node.release_handle();
+        self.node = node;
         Ok(self)
     }
 
@@ -1265,11 +1906,14 @@ impl CapWrapper {
     pub fn write(&self, buf: &[u8], off: usize) -> Result<(), errors::StreamError> {
         self.access.write_or_err()?;
 
-        self.node
-            .file()
-            .ok_or(ErrorKind::IsADirectory)?
-            .write(buf, off)?;
-        Ok(())
+        self.node.write_file(buf, Some(off))
+    }
+
+    #[instrument(skip(buf), fields(buf.len = buf.len()))]
+    pub fn append(&self, buf: &[u8]) -> Result<(), errors::StreamError> {
+        self.access.write_or_err()?;
+
+        self.node.write_file(buf, None)
     }
 
     #[instrument]
@@ -1277,11 +1921,26 @@ impl CapWrapper {
         let mut v = self.node.file().ok_or(ErrorKind::IsADirectory)?;
         if v.len() != size {
             self.access.write_or_err()?;
+            v.reserve(size)?;
             v.resize(size)?;
         }
         Ok(())
     }
 
+    /// Allocates space for range `off..off + len`, extending file if needed.
+    #[instrument]
+    pub fn allocate(&self, off: usize, len: usize) -> Result<(), errors::StreamError> {
+        self.access.write_or_err()?;
+
+        let end = off.checked_add(len).ok_or(ErrorKind::InvalidInput)?;
+        let mut v = self.node.file().ok_or(ErrorKind::IsADirectory)?;
+        if v.len() < end {
+            v.reserve(end)?;
+            v.resize(end)?;
+        }
+        Ok(())
+    }
+
     #[instrument(skip(controller, name), fields(name = ?name.as_ref()))]
     pub fn create_dir(
         &self,
@@ -1366,6 +2025,43 @@ impl CapWrapper {
         ))
     }
 
+    /// Checks if `dst` can be replaced by `src`.
+    ///
+    /// `locked` is an already locked directory, it's never empty.
+    fn check_replace(
+        src: &Node,
+        dst: Option<&Arc<Node>>,
+        locked: &Arc<Node>,
+        unlink_deny: bool,
+    ) -> Result<(), errors::StreamError> {
+        let Some(dst) = dst else {
+            return Ok(());
+        };
+        match (src.is_dir(), &dst.0) {
+            (true, NodeItem::Dir(v)) => {
+                if Arc::ptr_eq(dst, locked) || !v.lock().is_empty() {
+                    return Err(ErrorKind::DirectoryNotEmpty.into());
+                }
+            }
+            (true, _) => return Err(ErrorKind::NotADirectory.into()),
+            (false, NodeItem::Dir(_)) => return Err(ErrorKind::IsADirectory.into()),
+            (false, _) if dst.handles() > 0 && unlink_deny => {
+                return Err(wasi::filesystem::types::ErrorCode::Busy.into())
+            }
+            (false, _) => (),
+        }
+        Ok(())
+    }
+
+    /// Moves `src_file` in directory `src` into `dst_file` in this directory.
+    ///
+    /// Follows POSIX `rename` semantics:
+    /// - Existing file at destination is atomically replaced.
+    /// - Existing directory is replaced only by directory, and only if it's empty.
+    /// - Directory can't be moved into itself or it's subdirectory.
+    ///
+    /// Moves between directories are serialized, so ancestry can't change while checked.
+    /// Both directories are then locked, ancestor first, otherwise ordered by inode.
     #[instrument(skip(dst_file), fields(dst_file = ?dst_file.as_ref()))]
     pub fn move_file(
         &self,
@@ -1378,26 +2074,202 @@ impl CapWrapper {
         }
         self.access.write_or_err()?;
 
-        let mut n = self.node.dir().ok_or(ErrorKind::NotADirectory)?;
-
-        if Arc::ptr_eq(src, &self.node) {
-            if n.items.contains_key(dst_file.as_ref()) {
-                return Err(ErrorKind::AlreadyExists.into());
+        // Replaced node is freed outside of directory lock.
+        let _old = if Arc::ptr_eq(src, &self.node) {
+            let mut n = self.node.dir().ok_or(ErrorKind::NotADirectory)?;
+            let v = n.items.get(src_file).ok_or(ErrorKind::NotFound)?.clone();
+            if src_file == dst_file.as_ref() {
+                return Ok(());
             }
-            let src = n.items.remove(src_file).ok_or(ErrorKind::NotFound)?;
-            n.items.insert(dst_file.into(), src);
+            Self::check_replace(
+                &v,
+                n.items.get(dst_file.as_ref()),
+                &self.node,
+                n.limits.limits.unlink_deny(),
+            )?;
+
+            n.items.remove(src_file);
+            let old = n.items.insert(dst_file.into(), v);
+            n.stamp.modify();
+            old
         } else {
-            let Entry::Vacant(dst) = n.items.entry(dst_file.into()) else {
-                return Err(ErrorKind::AlreadyExists.into());
+            let limits = self
+                .node
+                .dir()
+                .ok_or(ErrorKind::NotADirectory)?
+                .limits
+                .limits
+                .clone();
+            if !Arc::ptr_eq(
+                &limits,
+                &src.dir().ok_or(ErrorKind::NotADirectory)?.limits.limits,
+            ) {
+                return Err(ErrorKind::CrossesDevices.into());
+            }
+            let _guard = limits.rename.lock();
+
+            let (mut s, mut d) = if src.is_ancestor_of(&self.node)
+                || (!self.node.is_ancestor_of(src) && src.inode() < self.node.inode())
+            {
+                let s = src.dir().ok_or(ErrorKind::NotADirectory)?;
+                (s, self.node.dir().ok_or(ErrorKind::NotADirectory)?)
+            } else {
+                let d = self.node.dir().ok_or(ErrorKind::NotADirectory)?;
+                (src.dir().ok_or(ErrorKind::NotADirectory)?, d)
             };
-            let mut v = src.dir().ok_or(ErrorKind::NotADirectory)?;
-            let src = v.items.remove(src_file).ok_or(ErrorKind::NotFound)?;
-            v.stamp.modify();
-            drop(v);
-            *dst.insert(src).1.write() = Arc::downgrade(&self.node);
+            let v = s.items.get(src_file).ok_or(ErrorKind::NotFound)?.clone();
+            if v.is_dir() && v.is_ancestor_of(&self.node) {
+                return Err(ErrorKind::InvalidInput.into());
+            }
+            Self::check_replace(
+                &v,
+                d.items.get(dst_file.as_ref()),
+                src,
+                limits.unlink_deny(),
+            )?;
+
+            s.items.remove(src_file);
+            s.stamp.modify();
+            *v.1.write() = Arc::downgrade(&self.node);
+            let old = d.items.insert(dst_file.into(), v);
+            d.stamp.modify();
+            old
+        };
+
+        Ok(())
+    }
+
+    /// Copies file `src_file` in directory `src` into `dst_file` in this directory.
+    ///
+    /// Copy is copy-on-write, data is shared until either file is written.
+    /// Existing file at destination is atomically replaced.
+    #[instrument(skip(dst_file), fields(dst_file = ?dst_file.as_ref()))]
+    pub fn copy_file(
+        &self,
+        src: &Arc<Node>,
+        src_file: &str,
+        dst_file: impl Into<Arc<str>> + AsRef<str>,
+    ) -> Result<(), errors::StreamError> {
+        if dst_file.as_ref().contains(ILLEGAL_CHARS) {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+        self.access.write_or_err()?;
+
+        let v = src
+            .dir()
+            .ok_or(ErrorKind::NotADirectory)?
+            .get(src_file)
+            .ok_or(ErrorKind::NotFound)?;
+        if Arc::ptr_eq(src, &self.node) && src_file == dst_file.as_ref() {
+            return Ok(());
         }
+        let file = match &v.0 {
+            NodeItem::File(v) => v.lock().try_clone()?,
+            NodeItem::Dir(_) => return Err(ErrorKind::IsADirectory.into()),
+            NodeItem::Link(_) => return Err(ErrorKind::InvalidInput.into()),
+        };
+        let v = Arc::new(Node::from((file, Arc::downgrade(&self.node))));
+
+        // Replaced node is freed outside of directory lock.
+        let _old = {
+            let mut n = self.node.dir().ok_or(ErrorKind::NotADirectory)?;
+            if !Arc::ptr_eq(&n.limits.limits, &v.file().unwrap().limits) {
+                return Err(ErrorKind::CrossesDevices.into());
+            }
+            Self::check_replace(
+                &v,
+                n.items.get(dst_file.as_ref()),
+                &self.node,
+                n.limits.limits.unlink_deny(),
+            )?;
+
+            let old = n.items.insert(dst_file.into(), v);
+            n.stamp.modify();
+            old
+        };
+
+        Ok(())
+    }
+
+    /// Replaces `dst_file` with `src_file`, both in this directory.
+    ///
+    /// Swap is done under directory lock, so `dst_file` is never missing.
+    /// Replaced node is returned, so it's freed outside of the lock.
+    #[instrument(skip(dst_file), fields(dst_file = ?dst_file.as_ref()))]
+    pub fn replace_file(
+        &self,
+        src_file: &str,
+        dst_file: impl Into<Arc<str>> + AsRef<str>,
+    ) -> Result<Option<Arc<Node>>, errors::StreamError> {
+        if dst_file.as_ref().contains(ILLEGAL_CHARS) {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+        self.access.write_or_err()?;
+
+        let mut n = self.node.dir().ok_or(ErrorKind::NotADirectory)?;
+        if n.items.get(src_file).ok_or(ErrorKind::NotFound)?.is_dir() {
+            return Err(ErrorKind::IsADirectory.into());
+        }
+        if let Some(v) = n.items.get(dst_file.as_ref()) {
+            if v.is_dir() {
+                return Err(ErrorKind::IsADirectory.into());
+            } else if v.handles() > 0 && n.limits.limits.unlink_deny() {
+                return Err(wasi::filesystem::types::ErrorCode::Busy.into());
+            }
+        }
+
+        let src = n.items.remove(src_file).ok_or(ErrorKind::NotFound)?;
+        let ret = n.items.insert(dst_file.into(), src);
         n.stamp.modify();
+        Ok(ret)
+    }
 
+    /// Atomically writes file `name` in this directory.
+    ///
+    /// Data is written into temporary file, which then replaces `name`.
+    /// Readers observe either old or new content, never partially written file.
+    /// Temporary file is removed on failure.
+    pub fn write_file_atomic(
+        &self,
+        controller: &IsolatedFSController,
+        name: &str,
+        data: &[u8],
+    ) -> Result<(), errors::StreamError> {
+        self.write_file_atomic_with(controller, name, |f| f.write(data, 0))
+    }
+
+    fn write_file_atomic_with(
+        &self,
+        controller: &IsolatedFSController,
+        name: &str,
+        f: impl FnOnce(&mut File) -> AnyResult<()>,
+    ) -> Result<(), errors::StreamError> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        if matches!(name, "" | "." | "..") || name.contains(ILLEGAL_CHARS) {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+
+        let (tmp_name, tmp) = loop {
+            let tmp_name = format!(".{name}.{}.tmp", COUNTER.fetch_add(1, Ordering::Relaxed));
+            match self.create_file(controller, &*tmp_name) {
+                Ok(v) => break (tmp_name, v),
+                Err(e) if e.io().is_some_and(|e| e.kind() == ErrorKind::AlreadyExists) => (),
+                Err(e) => return Err(e),
+            }
+        };
+        let guard = scopeguard::guard((), |_| {
+            if let Some(mut n) = self.node.dir() {
+                n.remove(&tmp_name);
+            }
+        });
+
+        f(&mut *tmp.node().try_file()?)?;
+        drop(tmp);
+        let old = self.replace_file(&tmp_name, name)?;
+        scopeguard::ScopeGuard::into_inner(guard);
+        // Frees replaced file (if it has no handles) outside of directory lock.
+        drop(old);
         Ok(())
     }
 
@@ -1414,6 +2286,8 @@ impl CapWrapper {
             }
         } else if v.is_dir() {
             return Err(ErrorKind::IsADirectory.into());
+        } else if v.handles() > 0 && n.limits.limits.unlink_deny() {
+            return Err(wasi::filesystem::types::ErrorCode::Busy.into());
         }
         n.items.remove(file);
 
@@ -1518,23 +2392,25 @@ impl FileAccessor {
             return Err(errors::StreamError::closed());
         }
 
-        let mut v = self.file.try_file()?;
         match &mut self.mode {
             OpenMode::Read(_) => return Err(ErrorKind::PermissionDenied.into()),
             OpenMode::Write(cursor) => {
-                v.write(buf, *cursor)?;
+                self.file.write_file(buf, Some(*cursor))?;
                 *cursor += buf.len();
             }
-            OpenMode::Append => {
-                let i = v.len();
-                v.write(buf, i)?;
-            }
+            OpenMode::Append => self.file.write_file(buf, None)?,
         }
         Ok(())
     }
 
     #[inline(always)]
     pub fn close(&mut self) {
+        if !self.closed {
+            if !matches!(self.mode, OpenMode::Read(_)) {
+                self.file.seal();
+            }
+            self.file.release_handle();
+        }
         self.closed = true;
     }
 
@@ -1544,6 +2420,12 @@ impl FileAccessor {
     }
 }
 
+impl Drop for FileAccessor {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
 #[derive(Debug)]
 pub struct DirEntryAccessor {
     node: Option<Arc<Node>>,
@@ -1588,13 +2470,17 @@ impl Iterator for DirEntryAccessor {
 pub type Pollable = crate::NullPollable;
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
     use camino::{Utf8Component, Utf8PathBuf};
     use proptest::collection::vec;
     use proptest::prelude::*;
 
+    const MAX_SECTOR: usize = 1 << MAX_SHIFT;
+    const MIN_SECTOR: usize = 1 << MIN_SHIFT;
+    const MASK: usize = MAX_SECTOR - 1;
+
     #[test]
     fn test_link() {
         fn f(s: String) {
@@ -1666,7 +2552,7 @@ mod tests {
 
             let mut end = len + off;
             assert_eq!(file.len(), if ret.is_ok() && len > 0 { end } else { 0 });
-            end = (end & !MASK) + File::clamped_size(end & MASK);
+            end = (end & !MASK) + ChunkSize::default().clamped(end & MASK);
             assert_eq!(len == 0 || end <= limit, ret.is_ok(), "end: {end}");
         }
 
@@ -1677,6 +2563,52 @@ mod tests {
         )| f(limit, len, off));
     }
 
+    #[test]
+    fn test_handle_count() {
+        let controller = IsolatedFSController::new(1 << 20, 16).unwrap();
+        let root = CapWrapper::new(controller.root(), AccessMode::RW);
+        let open = || {
+            root.open(
+                &controller,
+                Utf8Path::new("a"),
+                false,
+                Some(CreateParams::new()),
+                AccessMode::RW,
+            )
+            .unwrap()
+        };
+
+        let f = open();
+        let node = f.node().clone();
+        assert_eq!(node.handles(), 1);
+        let f2 = f.clone();
+        let mut s = f.open_file(OpenMode::Read(0)).unwrap();
+        assert_eq!(node.handles(), 3);
+        s.close();
+        assert_eq!(node.handles(), 2);
+        drop(s);
+        drop(f2);
+        assert_eq!(node.handles(), 1);
+
+        controller.set_unlink_open(UnlinkOpenPolicy::Deny);
+        let e: Result<wasi::filesystem::types::ErrorCode, Error> =
+            root.unlink("a", false).unwrap_err().into();
+        assert_eq!(e.unwrap(), wasi::filesystem::types::ErrorCode::Busy);
+
+        controller.set_unlink_open(UnlinkOpenPolicy::Allow);
+        let s = f.open_file(OpenMode::Append).unwrap();
+        root.unlink("a", false).unwrap();
+        // Node still lives until last handle drops.
+        drop(f);
+        assert_eq!(node.handles(), 1);
+        drop(s);
+        assert_eq!(node.handles(), 0);
+
+        drop(open());
+        controller.set_unlink_open(UnlinkOpenPolicy::Deny);
+        root.unlink("a", false).unwrap();
+    }
+
     #[test]
     fn test_file_resize_truncate() {
         let cont = IsolatedFSController::new(MAX_SECTOR * 16, 2).unwrap();
@@ -1687,7 +2619,7 @@ mod tests {
             for (s, t) in sizes {
                 if t {
                     let old = file.len();
-                    file.truncate(s);
+                    file.truncate(s).unwrap();
                     assert_eq!(file.len(), old.min(s));
                 } else {
                     file.resize(s).unwrap();
@@ -1707,6 +2639,7 @@ mod tests {
             Write { b: u8, len: usize, off: usize },
             Resize(usize),
             Truncate(usize),
+            Reserve(usize),
         }
 
         let cont = IsolatedFSController::new(MAX_SECTOR * (64 + 5), 2).unwrap();
@@ -1757,10 +2690,16 @@ mod tests {
                     }
                     Op::Truncate(v) => {
                         rfile.truncate(v);
-                        file.truncate(v);
+                        file.truncate(v).unwrap();
 
                         assert_eq!(file.len(), rfile.len());
                     }
+                    Op::Reserve(v) => {
+                        file.reserve(v).unwrap();
+
+                        assert_eq!(file.len(), rfile.len());
+                        assert!(file.capacity() >= v);
+                    }
                 }
             }
         };
@@ -1771,8 +2710,765 @@ mod tests {
                 (0..MAX_SECTOR * 4, 0..MAX_SECTOR * 64, any::<u8>()).prop_map(|(len, off, b)| Op::Write {b, len, off}),
                 (0..MAX_SECTOR * 64).prop_map(Op::Resize),
                 (0..MAX_SECTOR * 64).prop_map(Op::Truncate),
+                (0..MAX_SECTOR * 64).prop_map(Op::Reserve),
             ],
             0..32,
         ))| f(v));
     }
+
+    #[test]
+    fn test_file_clone() {
+        fn read_all(file: &mut File) -> Vec<u8> {
+            let mut ret = vec![0; file.len()];
+            let mut o = 0;
+            while o < ret.len() {
+                let (s, l) = file.read(ret.len() - o, o);
+                assert!(l > 0);
+                ret[o..o + s.len()].copy_from_slice(s);
+                o += l;
+            }
+            ret
+        }
+
+        const TOTAL: usize = MAX_SECTOR * 8;
+        let cont = IsolatedFSController::new(TOTAL, 4).unwrap();
+        let avail = || cont.limits.cur_size.load(Ordering::Relaxed);
+
+        let len = MAX_SECTOR * 2 + 100;
+        let mut orig = File::new(&cont).unwrap();
+        orig.write(&vec![0xaa; len], 0).unwrap();
+        let base = avail();
+        assert_eq!(TOTAL - base, orig.capacity());
+
+        // Clone shares all chunks, no size is charged.
+        let mut clone = orig.try_clone().unwrap();
+        assert_eq!(avail(), base);
+        assert_eq!(clone.len(), len);
+        assert_eq!(clone.shared_capacity(), orig.capacity());
+        assert_eq!(read_all(&mut clone), vec![0xaa; len]);
+
+        // Diverge by writing to clone.
+        clone.write(&[0xbb; 10], 0).unwrap();
+        assert_eq!(avail(), base - MAX_SECTOR);
+        assert_eq!(read_all(&mut orig), vec![0xaa; len]);
+        let mut expect = vec![0xaa; len];
+        expect[..10].fill(0xbb);
+        assert_eq!(read_all(&mut clone), expect);
+
+        // Diverge by writing to original.
+        orig.write(&[0xcc; 10], MAX_SECTOR).unwrap();
+        assert_eq!(avail(), base - MAX_SECTOR * 2);
+        assert_eq!(read_all(&mut clone), expect);
+        assert_eq!(orig.shared_capacity(), ChunkSize::default().clamped(100));
+
+        // Writing to unshared chunk does not charge anything.
+        clone.write(&[0xdd; 10], 20).unwrap();
+        expect[20..30].fill(0xdd);
+        assert_eq!(avail(), base - MAX_SECTOR * 2);
+
+        // Delete original, shared chunk still lives in clone.
+        drop(orig);
+        assert_eq!(avail(), base);
+        assert_eq!(clone.shared_capacity(), 0);
+        assert_eq!(read_all(&mut clone), expect);
+
+        // Truncating shared chunk zeroes only its own copy.
+        let mut other = clone.try_clone().unwrap();
+        other.truncate(MAX_SECTOR + 5).unwrap();
+        assert_eq!(avail(), base - MAX_SECTOR);
+        other.resize(MAX_SECTOR * 2).unwrap();
+        let mut r = expect[..MAX_SECTOR + 5].to_vec();
+        r.resize(MAX_SECTOR * 2, 0);
+        assert_eq!(read_all(&mut other), r);
+        assert_eq!(read_all(&mut clone), expect);
+
+        drop(clone);
+        drop(other);
+        assert_eq!(avail(), TOTAL);
+    }
+
+    #[test]
+    fn test_file_clone_limit() {
+        let cont = IsolatedFSController::new(MAX_SECTOR * 3, 3).unwrap();
+        let mut orig = File::new(&cont).unwrap();
+        orig.write(&vec![0xaa; MAX_SECTOR * 2], 0).unwrap();
+
+        let mut clone = orig.try_clone().unwrap();
+        clone.write(&[0xbb], 0).unwrap();
+        // Not enough space to copy second chunk.
+        let e = clone.write(&[0xbb; 2], MAX_SECTOR - 1).unwrap_err();
+        assert!(e.downcast_ref::<errors::FileLimitError>().is_some());
+        let (s, _) = clone.read(1, MAX_SECTOR);
+        assert_eq!(s[0], 0xaa);
+
+        // Out of node.
+        assert!(orig.try_clone().is_err());
+    }
+
+    #[test]
+    fn test_dedup() {
+        let cont = IsolatedFSController::new(MAX_SECTOR * 6, 8).unwrap();
+        cont.set_dedup(true);
+        let root = CapWrapper::new(cont.root(), AccessMode::RW);
+        let data: Vec<u8> = (0..MAX_SECTOR * 2 + 100).map(|i| i as u8).collect();
+        let size = || cont.limits.cur_size.load(Ordering::Relaxed);
+        let import = |name: &str| {
+            let f = root.create_file(&cont, name).unwrap();
+            f.write(&data, 0).unwrap();
+            let n = f.node().clone();
+            drop(f);
+            n
+        };
+        let read = |n: &Node| {
+            let mut f = n.file().unwrap();
+            let mut ret = Vec::new();
+            while ret.len() < f.len() {
+                let (s, l) = f.read(usize::MAX, ret.len());
+                ret.extend_from_slice(s);
+                ret.resize(ret.len() - s.len() + l, 0);
+            }
+            ret
+        };
+
+        // Two identical imports share content.
+        let a = import("a");
+        let used = size();
+        let b = import("b");
+        assert!(a.file().unwrap().is_sealed());
+        assert_eq!(size(), used);
+        let cap = a.file().unwrap().capacity();
+        assert_eq!(b.file().unwrap().shared_capacity(), cap);
+        assert_eq!(
+            cont.dedup_stats(),
+            DedupStats {
+                files: 2,
+                saved: cap
+            }
+        );
+
+        // Modifying one copies only modified chunk.
+        b.write_file(&[0xff], Some(0)).unwrap();
+        assert!(!b.file().unwrap().is_sealed());
+        assert_eq!(size(), used - MAX_SECTOR);
+        assert_eq!(read(&a), data);
+        assert_eq!(read(&b)[0], 0xff);
+        assert_eq!(read(&b)[1..], data[1..]);
+        assert_eq!(cont.dedup_stats().files, 1);
+
+        // Deleting original keeps shared content.
+        let c = import("c");
+        drop(a);
+        root.unlink("a", false).unwrap();
+        assert_eq!(read(&c), data);
+        assert!(c.file().unwrap().is_sealed());
+        assert_eq!(cont.dedup_stats(), DedupStats { files: 1, saved: 0 });
+        let d = import("d");
+        assert_eq!(read(&d), data);
+        assert_eq!(
+            cont.dedup_stats(),
+            DedupStats {
+                files: 2,
+                saved: cap
+            }
+        );
+
+        // Disabled dedup does not seal.
+        cont.set_dedup(false);
+        let e = import("e");
+        assert!(!e.file().unwrap().is_sealed());
+    }
+
+    #[test]
+    fn test_quota_policy() {
+        let cont = IsolatedFSController::new(MAX_SECTOR * 2, 8).unwrap();
+        let root = CapWrapper::new(cont.root(), AccessMode::RW);
+        let exists = |k: &str| root.node().dir().unwrap().items.contains_key(k);
+        let no_space = |e: errors::StreamError| {
+            assert_eq!(
+                e.fs_error_code(),
+                Some(wasi::filesystem::types::ErrorCode::InsufficientSpace)
+            );
+        };
+
+        // Fail keeps file.
+        let a = root.create_file(&cont, "a").unwrap();
+        a.write(&[1; 10], 0).unwrap();
+        no_space(a.write(&vec![1; MAX_SECTOR * 3], 0).unwrap_err());
+        assert!(exists("a"));
+
+        // Fail and truncate removes file.
+        cont.set_quota_policy(QuotaPolicy::FailAndTruncate);
+        no_space(a.write(&vec![1; MAX_SECTOR * 3], 0).unwrap_err());
+        assert!(!exists("a"));
+        assert_eq!(a.node().file().unwrap().len(), 0);
+        drop(a);
+
+        // Evict removes oldest file without open handle.
+        cont.set_quota_policy(QuotaPolicy::EvictLru);
+        root.create_file(&cont, "b")
+            .unwrap()
+            .write(&vec![2; MAX_SECTOR], 0)
+            .unwrap();
+        root.create_file(&cont, "c")
+            .unwrap()
+            .write(&vec![3; MAX_SECTOR / 2], 0)
+            .unwrap();
+        let b = root.node().dir().unwrap().get("b").unwrap();
+        b.file().unwrap().stamp_mut().atime = SystemTime::UNIX_EPOCH;
+        drop(b);
+        let d = root.create_file(&cont, "d").unwrap();
+        d.write(&vec![4; MAX_SECTOR], 0).unwrap();
+        assert!(!exists("b"));
+        assert!(exists("c"));
+
+        // Open files are never evicted.
+        let c = root
+            .open(&cont, Utf8Path::new("c"), false, None, AccessMode::R)
+            .unwrap();
+        no_space(d.write(&vec![4; MAX_SECTOR * 2], 0).unwrap_err());
+        assert!(exists("c"));
+        drop(c);
+    }
+
+    #[test]
+    fn test_controller_drop_open_file() {
+        let cont = IsolatedFSController::new(MAX_SECTOR * 2, 8).unwrap();
+        let root = CapWrapper::new(cont.root(), AccessMode::RW);
+        let a = root.create_file(&cont, "a").unwrap();
+        a.write(&[1; 10], 0).unwrap();
+        drop(root);
+        drop(cont);
+
+        // Open file keeps limits alive, so it can still grow up to the original limit.
+        a.write(&[2; 10], 10).unwrap();
+        assert_eq!(a.node().file().unwrap().len(), 20);
+        a.write(&vec![3; MAX_SECTOR], 20).unwrap();
+        assert_eq!(
+            a.write(&vec![4; MAX_SECTOR * 3], 0)
+                .unwrap_err()
+                .fs_error_code(),
+            Some(wasi::filesystem::types::ErrorCode::InsufficientSpace)
+        );
+        let mut f = a.node().file().unwrap();
+        let (s, _) = f.read(20, 0);
+        assert_eq!(s[..10], [1; 10]);
+        assert_eq!(f.len(), MAX_SECTOR + 20);
+    }
+
+    #[test]
+    fn test_chunk_size_tiny_file() {
+        let cont = IsolatedFSController::new(MAX_SECTOR * 16, 64).unwrap();
+        assert_eq!(cont.chunk_size(), (MIN_SECTOR, MAX_SECTOR));
+        assert!(cont.set_chunk_size(16, MAX_SECTOR).is_err());
+        assert!(cont.set_chunk_size(100, MAX_SECTOR).is_err());
+        assert!(cont.set_chunk_size(MAX_SECTOR, MIN_SECTOR).is_err());
+
+        let mut a = File::new(&cont).unwrap();
+        a.write(&[1; 20], 0).unwrap();
+        assert_eq!(a.capacity(), MIN_SECTOR);
+
+        cont.set_chunk_size(32, MAX_SECTOR).unwrap();
+        let mut files = Vec::new();
+        for _ in 0..32 {
+            let mut f = File::new(&cont).unwrap();
+            f.write(&[1; 20], 0).unwrap();
+            assert_eq!(f.capacity(), 32);
+            files.push(f);
+        }
+        let avail = cont.limits.cur_size.load(Ordering::Relaxed);
+        assert_eq!(MAX_SECTOR * 16 - avail, MIN_SECTOR + 32 * 32);
+
+        // Existing file keeps its chunk size.
+        a.write(&[1; 10], 20).unwrap();
+        assert_eq!(a.capacity(), MIN_SECTOR);
+    }
+
+    #[test]
+    fn test_reserve_sequential_write() {
+        const LEN: usize = 10 << 20;
+        const CHUNK: usize = 1 << 20;
+
+        // Counts chunk buffer (re)allocations since last call.
+        fn allocs(file: &File, caps: &mut Vec<usize>) -> usize {
+            caps.resize(file.data.len(), 0);
+            let mut ret = 0;
+            for (v, c) in file.data.iter().zip(caps.iter_mut()) {
+                let n = if v.data.spilled() {
+                    v.data.capacity()
+                } else {
+                    0
+                };
+                if n != *c {
+                    *c = n;
+                    ret += 1;
+                }
+            }
+            ret
+        }
+
+        fn write_all(file: &mut File) -> usize {
+            let mut caps = Vec::new();
+            let mut n = allocs(file, &mut caps);
+            let buf = [0x55; 4000];
+            let mut o = 0;
+            while o < LEN {
+                let l = buf.len().min(LEN - o);
+                file.write(&buf[..l], o).unwrap();
+                n += allocs(file, &mut caps);
+                o += l;
+            }
+            assert_eq!(file.len(), LEN);
+            n
+        }
+
+        let cont = IsolatedFSController::new(LEN + CHUNK, 4).unwrap();
+        cont.set_chunk_size(MIN_SECTOR, CHUNK).unwrap();
+        let avail = || cont.limits.cur_size.load(Ordering::Relaxed);
+
+        let mut file = File::new(&cont).unwrap();
+        let unreserved = write_all(&mut file);
+        drop(file);
+        assert_eq!(avail(), LEN + CHUNK);
+
+        let mut file = File::new(&cont).unwrap();
+        file.reserve(LEN).unwrap();
+        // Reserved space is charged, but file is still empty.
+        assert_eq!(file.len(), 0);
+        assert_eq!(avail(), CHUNK);
+        // Reserving past limit fails without changing reservation.
+        assert!(file.reserve(LEN + CHUNK * 2).is_err());
+        assert_eq!(avail(), CHUNK);
+
+        let reserved = write_all(&mut file);
+        assert_eq!(reserved, LEN / CHUNK);
+        assert!(reserved < unreserved, "{reserved} >= {unreserved}");
+        assert_eq!(avail(), CHUNK);
+
+        // Truncating releases reservation.
+        file.truncate(0).unwrap();
+        file.reserve(CHUNK).unwrap();
+        file.truncate(0).unwrap();
+        assert_eq!(avail(), LEN + CHUNK);
+    }
+
+    fn add_link(cont: &IsolatedFSController, name: &str, target: &str) {
+        let root = cont.root();
+        root.dir()
+            .unwrap()
+            .add::<Error>(name, || {
+                Ok(Arc::new(Node::from((
+                    Link::new(cont, Utf8Path::new(target))?,
+                    Arc::downgrade(&root),
+                ))))
+            })
+            .unwrap()
+            .unwrap();
+    }
+
+    fn open_link(
+        cont: &IsolatedFSController,
+        path: &str,
+    ) -> Result<CapWrapper, errors::StreamError> {
+        CapWrapper::new(cont.root(), AccessMode::RW).open(
+            cont,
+            Utf8Path::new(path),
+            true,
+            None,
+            AccessMode::R,
+        )
+    }
+
+    fn assert_loop(r: Result<CapWrapper, errors::StreamError>, path: &str) {
+        let e = r.unwrap_err();
+        assert_eq!(
+            e.fs_error_code(),
+            Some(wasi::filesystem::types::ErrorCode::Loop)
+        );
+        let msg = Error::from(e).to_string();
+        assert!(msg.contains(&format!("{path:?}")), "{msg}");
+    }
+
+    #[test]
+    fn test_symlink_loop_self() {
+        let cont = IsolatedFSController::new(MAX_SECTOR, 16).unwrap();
+        add_link(&cont, "a", "a");
+        assert_loop(open_link(&cont, "a"), "a");
+        assert_loop(open_link(&cont, "./a"), "./a");
+    }
+
+    #[test]
+    fn test_symlink_loop_cycle() {
+        let cont = IsolatedFSController::new(MAX_SECTOR, 16).unwrap();
+        add_link(&cont, "a", "b");
+        add_link(&cont, "b", "/a");
+        assert_loop(open_link(&cont, "a"), "a");
+        assert_loop(open_link(&cont, "b"), "b");
+
+        let e = open_link(&cont, "a").unwrap_err();
+        assert_eq!(
+            <Result<crate::bindings::types::Errno, Error>>::from(e).unwrap(),
+            crate::bindings::types::Errno::Loop,
+        );
+    }
+
+    #[test]
+    fn test_symlink_chain_depth() {
+        let cont = IsolatedFSController::new(MAX_SECTOR, 16).unwrap();
+        assert!(cont.set_link_depth(0).is_err());
+        cont.set_link_depth(4).unwrap();
+        assert_eq!(cont.link_depth(), 4);
+
+        let root = cont.root();
+        root.dir()
+            .unwrap()
+            .add::<Error>("file", || {
+                Ok(Arc::new(Node::from((
+                    File::new(&cont)?,
+                    Arc::downgrade(&root),
+                ))))
+            })
+            .unwrap()
+            .unwrap();
+        add_link(&cont, "l3", "file");
+        add_link(&cont, "l2", "l3");
+        add_link(&cont, "l1", "l2");
+        add_link(&cont, "l0", "l1");
+
+        // Chain of 4 links is at the limit.
+        assert!(open_link(&cont, "l0").unwrap().node().is_file());
+        let l0 = root.dir().unwrap().get("l0").unwrap();
+        assert!(CapWrapper::new(l0, AccessMode::R)
+            .follow_symlink(&cont)
+            .unwrap()
+            .node()
+            .is_file());
+
+        add_link(&cont, "l", "l0");
+        assert_loop(open_link(&cont, "l"), "l");
+
+        cont.set_link_depth(5).unwrap();
+        assert!(open_link(&cont, "l").unwrap().node().is_file());
+    }
+
+    #[test]
+    fn test_write_file_atomic() {
+        let cont = IsolatedFSController::new(MAX_SECTOR * 4, 16).unwrap();
+        let root = CapWrapper::new(cont.root(), AccessMode::RW);
+        fn read(node: &Node) -> Vec<u8> {
+            let mut f = node.file().unwrap();
+            let (s, l) = f.read(usize::MAX, 0);
+            assert_eq!(s.len(), l);
+            s.to_vec()
+        }
+        let get = |name: &str| root.node().dir().unwrap().get(name).unwrap();
+        let names = || {
+            root.node()
+                .dir()
+                .unwrap()
+                .iter()
+                .map(|(k, _)| k.to_owned())
+                .collect::<Vec<_>>()
+        };
+        let usage = || {
+            (
+                cont.limits.cur_size.load(Ordering::Relaxed),
+                cont.limits.cur_node.load(Ordering::Relaxed),
+            )
+        };
+
+        root.write_file_atomic(&cont, "save", b"old").unwrap();
+        assert_eq!(read(&get("save")), b"old");
+        assert_eq!(names(), ["save"]);
+        let used = usage();
+
+        // Failure between write and rename.
+        let reader = root
+            .open(&cont, Utf8Path::new("save"), false, None, AccessMode::R)
+            .unwrap();
+        let e = root
+            .write_file_atomic_with(&cont, "save", |f| {
+                f.write(b"partial new", 0)?;
+                Err(Error::msg("killed"))
+            })
+            .unwrap_err();
+        assert!(e.to_string().contains("killed"), "{e}");
+        assert_eq!(read(&get("save")), b"old");
+        assert_eq!(names(), ["save"]);
+        assert_eq!(usage(), used);
+
+        // Open handle keeps old content.
+        root.write_file_atomic(&cont, "save", b"new data").unwrap();
+        assert_eq!(read(&get("save")), b"new data");
+        assert_eq!(read(reader.node()), b"old");
+        assert_eq!(names(), ["save"]);
+        drop(reader);
+        assert_eq!(usage().1, used.1);
+
+        // Replacing open file is denied with policy.
+        cont.set_unlink_open(UnlinkOpenPolicy::Deny);
+        let reader = root
+            .open(&cont, Utf8Path::new("save"), false, None, AccessMode::R)
+            .unwrap();
+        let e = root
+            .write_file_atomic(&cont, "save", b"denied")
+            .unwrap_err();
+        assert_eq!(
+            e.fs_error_code(),
+            Some(wasi::filesystem::types::ErrorCode::Busy)
+        );
+        assert_eq!(read(&get("save")), b"new data");
+        assert_eq!(names(), ["save"]);
+        drop(reader);
+        cont.set_unlink_open(UnlinkOpenPolicy::Allow);
+
+        // Directory can't be replaced.
+        root.create_dir(&cont, "dir").unwrap();
+        let e = root.write_file_atomic(&cont, "dir", b"").unwrap_err();
+        assert_eq!(e.io().map(|e| e.kind()), Some(ErrorKind::IsADirectory));
+        assert_eq!(names(), ["dir", "save"]);
+        root.write_file_atomic(&cont, "..", b"").unwrap_err();
+    }
+
+    use wasi::filesystem::types::ErrorCode as FSErrorCode;
+
+    /// Rename cases of [`check_rename`], as source, destination, and expected error.
+    const RENAME_CASES: &[(&str, &str, Option<FSErrorCode>)] = &[
+        ("x", "z", None),
+        ("x", "a/b/x", None),
+        ("a/f", "x", None),
+        ("x", "y", None),
+        ("x", "x", None),
+        ("a", "a", None),
+        ("e", "a/b/c", None),
+        ("a/b", "e", None),
+        ("a/b/c", "c", None),
+        ("x", "e", Some(FSErrorCode::IsDirectory)),
+        ("e", "x", Some(FSErrorCode::NotDirectory)),
+        ("e", "a", Some(FSErrorCode::NotEmpty)),
+        ("a/b", "a", Some(FSErrorCode::NotEmpty)),
+        ("a", "a/b/a", Some(FSErrorCode::Invalid)),
+        ("a", "a/b/c", Some(FSErrorCode::Invalid)),
+        ("a/b", "a/b/c/b", Some(FSErrorCode::Invalid)),
+        ("missing", "z", Some(FSErrorCode::NoEntry)),
+        ("x", "missing/x", Some(FSErrorCode::NoEntry)),
+        ("x", "y/x", Some(FSErrorCode::NotDirectory)),
+    ];
+
+    pub(crate) fn lookup(root: &Arc<Node>, path: &str) -> Option<Arc<Node>> {
+        path.split('/')
+            .try_fold(root.clone(), |n, s| n.dir()?.items.get(s).cloned())
+    }
+
+    fn list_tree(node: &Arc<Node>, prefix: &str, out: &mut Vec<String>) {
+        for (k, v) in node.dir().unwrap().iter() {
+            let p = format!("{prefix}{k}");
+            if v.is_dir() {
+                out.push(format!("{p}/"));
+                list_tree(v, &format!("{p}/"), out);
+            } else {
+                out.push(p);
+            }
+        }
+    }
+
+    /// Runs every rename case on a fresh filesystem.
+    ///
+    /// Filesystem contains directories `a/b/c` and `e`, and files `x`, `y`, and `a/f`.
+    /// Error code is converted with `map` before compared.
+    pub(crate) fn check_rename<E: PartialEq + Debug>(
+        map: impl Fn(FSErrorCode) -> E,
+        mut rename: impl FnMut(&IsolatedFSController, &str, &str) -> Result<(), E>,
+    ) {
+        for &(src, dst, err) in RENAME_CASES {
+            let cont = IsolatedFSController::new(MAX_SECTOR, 16).unwrap();
+            let root = CapWrapper::new(cont.root(), AccessMode::RW);
+            let a = root.create_dir(&cont, "a").unwrap();
+            a.create_dir(&cont, "b")
+                .unwrap()
+                .create_dir(&cont, "c")
+                .unwrap();
+            a.create_file(&cont, "f").unwrap();
+            root.create_dir(&cont, "e").unwrap();
+            root.create_file(&cont, "x").unwrap();
+            root.create_file(&cont, "y").unwrap();
+            drop(a);
+
+            let mut before = Vec::new();
+            list_tree(root.node(), "", &mut before);
+            let node = lookup(root.node(), src);
+            let replaced = src != dst && lookup(root.node(), dst).is_some();
+
+            let ret = rename(&cont, src, dst);
+            let mut after = Vec::new();
+            list_tree(root.node(), "", &mut after);
+            match err {
+                Some(e) => {
+                    assert_eq!(ret, Err(map(e)), "{src} -> {dst}");
+                    assert_eq!(before, after, "{src} -> {dst}");
+                }
+                None => {
+                    assert_eq!(ret, Ok(()), "{src} -> {dst}");
+                    let node = node.unwrap();
+                    let n = lookup(root.node(), dst).unwrap();
+                    assert!(Arc::ptr_eq(&n, &node), "{src} -> {dst}");
+                    let parent = match dst.rsplit_once('/') {
+                        Some((p, _)) => lookup(root.node(), p).unwrap(),
+                        None => root.node().clone(),
+                    };
+                    assert!(Arc::ptr_eq(&n.parent().unwrap(), &parent), "{src} -> {dst}");
+                    if src != dst {
+                        assert!(lookup(root.node(), src).is_none(), "{src} -> {dst}");
+                    }
+                    assert_eq!(after.len(), before.len() - replaced as usize);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_move_file() {
+        let cont = IsolatedFSController::new(MAX_SECTOR * 8, 16).unwrap();
+        let root = CapWrapper::new(cont.root(), AccessMode::RW);
+        let a = root.create_dir(&cont, "a").unwrap();
+        let b = a.create_dir(&cont, "b").unwrap();
+        root.create_file(&cont, "x")
+            .unwrap()
+            .write(b"x", 0)
+            .unwrap();
+        root.create_file(&cont, "y")
+            .unwrap()
+            .write(b"y", 0)
+            .unwrap();
+        let kind = |e: errors::StreamError| e.io().map(|e| e.kind());
+
+        // Moved node is reparented.
+        b.move_file(root.node(), "x", "x").unwrap();
+        let x = b.node().dir().unwrap().get("x").unwrap();
+        assert!(Arc::ptr_eq(&x.parent().unwrap(), b.node()));
+        assert!(root.node().dir().unwrap().get("x").is_none());
+        root.move_file(b.node(), "x", "x").unwrap();
+        assert!(Arc::ptr_eq(&x.parent().unwrap(), root.node()));
+
+        // File replaced while open keeps it's content.
+        let y = root.node().dir().unwrap().get("y").unwrap();
+        let reader = CapWrapper::new(y.clone(), AccessMode::R);
+        root.move_file(root.node(), "x", "y").unwrap();
+        assert_eq!(reader.read(1, 0).unwrap(), b"y");
+        assert!(Arc::ptr_eq(
+            &root.node().dir().unwrap().get("y").unwrap(),
+            &x
+        ));
+        drop((reader, y));
+
+        // Directory moved into it's own subtree.
+        let e = b.move_file(root.node(), "a", "a").unwrap_err();
+        assert_eq!(kind(e), Some(ErrorKind::InvalidInput));
+        let e = a.move_file(root.node(), "a", "c").unwrap_err();
+        assert_eq!(kind(e), Some(ErrorKind::InvalidInput));
+        // Directory onto it's non-empty parent.
+        let e = root.move_file(a.node(), "b", "a").unwrap_err();
+        assert_eq!(kind(e), Some(ErrorKind::DirectoryNotEmpty));
+        assert!(Arc::ptr_eq(&b.node().parent().unwrap(), a.node()));
+
+        // Different filesystem.
+        let other = IsolatedFSController::new(MAX_SECTOR, 4).unwrap();
+        let other = CapWrapper::new(other.root(), AccessMode::RW);
+        let e = other.move_file(root.node(), "y", "y").unwrap_err();
+        assert_eq!(kind(e), Some(ErrorKind::CrossesDevices));
+        let e = other.copy_file(root.node(), "y", "y").unwrap_err();
+        assert_eq!(kind(e), Some(ErrorKind::CrossesDevices));
+        assert!(other.node().dir().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_move_file_concurrent() {
+        let cont = IsolatedFSController::new(MAX_SECTOR, 64).unwrap();
+        let root = CapWrapper::new(cont.root(), AccessMode::RW);
+        let a = root.create_dir(&cont, "a").unwrap();
+        let b = a.create_dir(&cont, "b").unwrap();
+        let c = root.create_dir(&cont, "c").unwrap();
+        for i in 0..8 {
+            for d in [&root, &a, &b, &c] {
+                d.create_file(&cont, format!("f{i}")).unwrap();
+            }
+        }
+
+        // Moves in opposite directions, including between ancestor and descendant.
+        let pairs = [
+            (&a, &b),
+            (&b, &a),
+            (&a, &c),
+            (&c, &a),
+            (&root, &b),
+            (&b, &root),
+        ];
+        let cont = &cont;
+        std::thread::scope(|s| {
+            for (src, dst) in pairs {
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        let name = format!("f{}", i % 8);
+                        let _ = dst.move_file(src.node(), &name, &*name);
+                        let _ = src.unlink(&name, false);
+                        let _ = src.create_dir(cont, &*name);
+                        let _ = src.unlink(&name, true);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_copy_file() {
+        let cont = IsolatedFSController::new(MAX_SECTOR * 4, 8).unwrap();
+        let avail = || cont.limits.cur_size.load(Ordering::Relaxed);
+        let root = CapWrapper::new(cont.root(), AccessMode::RW);
+        let a = root.create_dir(&cont, "a").unwrap();
+        let f = root.create_file(&cont, "f").unwrap();
+        f.write(&[0xaa; 100], 0).unwrap();
+        let base = avail();
+
+        // Copy shares data.
+        a.copy_file(root.node(), "f", "g").unwrap();
+        let g = a.node().dir().unwrap().get("g").unwrap();
+        assert!(Arc::ptr_eq(&g.parent().unwrap(), a.node()));
+        assert_eq!(avail(), base);
+        let g = CapWrapper::new(g, AccessMode::RW);
+        g.write(&[0xbb], 0).unwrap();
+        assert_eq!(f.read(1, 0).unwrap(), [0xaa]);
+        assert_eq!(g.read(1, 0).unwrap(), [0xbb]);
+
+        // Existing file is replaced, open handle keeps old content.
+        a.copy_file(root.node(), "f", "g").unwrap();
+        assert_eq!(g.read(1, 0).unwrap(), [0xbb]);
+        let n = a.node().dir().unwrap().get("g").unwrap();
+        assert_eq!(
+            CapWrapper::new(n, AccessMode::R).read(1, 0).unwrap(),
+            [0xaa]
+        );
+        drop(g);
+
+        // Copy onto itself does nothing.
+        root.copy_file(root.node(), "f", "f").unwrap();
+        assert!(Arc::ptr_eq(
+            &root.node().dir().unwrap().get("f").unwrap(),
+            f.node()
+        ));
+
+        let kind = |e: errors::StreamError| e.io().map(|e| e.kind());
+        let e = root.copy_file(root.node(), "a", "b").unwrap_err();
+        assert_eq!(kind(e), Some(ErrorKind::IsADirectory));
+        let e = root.copy_file(root.node(), "f", "a").unwrap_err();
+        assert_eq!(kind(e), Some(ErrorKind::IsADirectory));
+        let e = root.copy_file(root.node(), "missing", "b").unwrap_err();
+        assert_eq!(kind(e), Some(ErrorKind::NotFound));
+
+        // Replacing open file is denied with policy.
+        cont.set_unlink_open(UnlinkOpenPolicy::Deny);
+        let e = root.copy_file(a.node(), "g", "f").unwrap_err();
+        assert_eq!(
+            e.fs_error_code(),
+            Some(wasi::filesystem::types::ErrorCode::Busy)
+        );
+    }
 }
@@ -197,6 +197,9 @@ item_def! {
         IsoFSReaddir(Box<DirEntryAccessor> |v| v),
         HostFSReaddir(Box<HostReadDir> |v| v),
     },
+    IOError | IOErrorR(wasi::io::error::Error) {
+        FSError(wasi::filesystem::types::ErrorCode |v| v),
+    },
     Poll | PollR(wasi::io::poll::Pollable) {
         NullPoll(NullPollable |v| v),
         StdinPoll(StdinSignalPollable |v| v),
@@ -2,8 +2,10 @@ pub mod clock;
 pub mod context;
 pub mod errors;
 pub mod fs_host;
+pub mod fs_import;
 pub mod fs_isolated;
 mod items;
+pub mod nondet;
 mod poll;
 pub mod preview1;
 pub mod stdio;
@@ -0,0 +1,349 @@
+//! Recording and replaying of nondeterministic host-call results.
+//!
+//! Replay file format (all integers are little-endian):
+//! - Header: [`MAGIC`] followed by `u32` format version.
+//! - Records, in call order: `u16` source length, source name,
+//!   `u32` data length, data.
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+use anyhow::{Error as AnyError, Result as AnyResult};
+use parking_lot::Mutex;
+use tracing::{instrument, trace, Level};
+
+use crate::errors;
+
+/// Magic bytes of replay file.
+pub const MAGIC: [u8; 8] = *b"GWREPLAY";
+/// Current replay format version.
+pub const VERSION: u32 = 1;
+
+/// Source name of monotonic clock.
+pub const SOURCE_CLOCK_MONOTONIC: &str = "clock.monotonic";
+/// Source name of wall clock.
+pub const SOURCE_CLOCK_WALL: &str = "clock.wall";
+/// Source name of insecure random.
+pub const SOURCE_RANDOM_INSECURE: &str = "random.insecure";
+/// Source name of secure random.
+pub const SOURCE_RANDOM_SECURE: &str = "random.secure";
+/// Source name of file read from host mount.
+pub const SOURCE_HOST_FS_READ: &str = "fs.host.read";
+
+/// Tap for values coming from nondeterministic sources.
+///
+/// Shared by WASI context and other host implementations,
+/// so that one recording covers every source in call order.
+pub trait NondetTap: Send + Sync {
+    /// Returns recorded value of next call, or `None` if real source should be used.
+    fn replay(&self, source: &str) -> AnyResult<Option<Vec<u8>>>;
+
+    /// Records value produced by real source.
+    fn record(&self, source: &str, data: &[u8]) -> AnyResult<()>;
+}
+
+/// Passes bytes from nondeterministic source through tap.
+///
+/// Failed calls to real source are not recorded.
+pub fn tap_bytes<E: From<AnyError>>(
+    tap: Option<&dyn NondetTap>,
+    source: &str,
+    f: impl FnOnce() -> Result<Vec<u8>, E>,
+) -> Result<Vec<u8>, E> {
+    let Some(tap) = tap else { return f() };
+    if let Some(v) = tap.replay(source)? {
+        return Ok(v);
+    }
+    let v = f()?;
+    tap.record(source, &v)?;
+    Ok(v)
+}
+
+/// Passes fixed-size value from nondeterministic source through tap.
+pub fn tap_value<const N: usize, T, E: From<AnyError>>(
+    tap: Option<&dyn NondetTap>,
+    source: &str,
+    f: impl FnOnce() -> Result<T, E>,
+    to_bytes: impl FnOnce(T) -> [u8; N],
+    from_bytes: impl FnOnce([u8; N]) -> T,
+) -> Result<T, E> {
+    let Some(tap) = tap else { return f() };
+    if let Some(v) = tap.replay(source)? {
+        let v = <[u8; N]>::try_from(&v[..]).map_err(|_| {
+            AnyError::from(errors::InvalidReplayError(
+                "recorded value has wrong length",
+            ))
+        })?;
+        return Ok(from_bytes(v));
+    }
+    let v = to_bytes(f()?);
+    tap.record(source, &v)?;
+    Ok(from_bytes(v))
+}
+
+/// Passes `u64` from nondeterministic source through tap.
+pub fn tap_u64<E: From<AnyError>>(
+    tap: Option<&dyn NondetTap>,
+    source: &str,
+    f: impl FnOnce() -> Result<u64, E>,
+) -> Result<u64, E> {
+    tap_value(tap, source, f, u64::to_le_bytes, u64::from_le_bytes)
+}
+
+/// Passes [`Duration`] from nondeterministic source through tap.
+pub fn tap_duration<E: From<AnyError>>(
+    tap: Option<&dyn NondetTap>,
+    source: &str,
+    f: impl FnOnce() -> Result<Duration, E>,
+) -> Result<Duration, E> {
+    tap_value(
+        tap,
+        source,
+        f,
+        |v| {
+            let mut r = [0u8; 12];
+            r[..8].copy_from_slice(&v.as_secs().to_le_bytes());
+            r[8..].copy_from_slice(&v.subsec_nanos().to_le_bytes());
+            r
+        },
+        |v| {
+            let (s, n) = v.split_at(8);
+            Duration::new(
+                u64::from_le_bytes(s.try_into().unwrap()),
+                u32::from_le_bytes(n.try_into().unwrap()),
+            )
+        },
+    )
+}
+
+/// Fills buffer from nondeterministic source through tap.
+///
+/// Recorded value must have the same length as buffer.
+pub fn tap_fill<E: From<AnyError>>(
+    tap: Option<&dyn NondetTap>,
+    source: &str,
+    buf: &mut [u8],
+    f: impl FnOnce(&mut [u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    let Some(tap) = tap else { return f(buf) };
+    if let Some(v) = tap.replay(source)? {
+        if v.len() != buf.len() {
+            return Err(AnyError::from(errors::InvalidReplayError(
+                "recorded value has wrong length",
+            ))
+            .into());
+        }
+        buf.copy_from_slice(&v);
+        return Ok(());
+    }
+    f(buf)?;
+    tap.record(source, buf)?;
+    Ok(())
+}
+
+/// Records every tapped value into writer.
+pub struct NondetRecorder<W: Write + Send>(Mutex<W>);
+
+impl<W: Write + Send> NondetRecorder<W> {
+    pub fn new(mut writer: W) -> AnyResult<Self> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self(Mutex::new(writer)))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.0.into_inner()
+    }
+}
+
+impl<W: Write + Send> NondetTap for NondetRecorder<W> {
+    fn replay(&self, _: &str) -> AnyResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    #[instrument(level = Level::TRACE, skip(self, data), fields(data.len = data.len()), err)]
+    fn record(&self, source: &str, data: &[u8]) -> AnyResult<()> {
+        let mut w = self.0.lock();
+        w.write_all(&u16::try_from(source.len())?.to_le_bytes())?;
+        w.write_all(source.as_bytes())?;
+        w.write_all(&u32::try_from(data.len())?.to_le_bytes())?;
+        w.write_all(data)?;
+        w.flush()?;
+        Ok(())
+    }
+}
+
+struct ReplayerInner<R> {
+    reader: R,
+    index: u64,
+}
+
+/// Feeds recorded values back instead of using real sources.
+pub struct NondetReplayer<R: Read + Send>(Mutex<ReplayerInner<R>>);
+
+impl<R: Read + Send> NondetReplayer<R> {
+    pub fn new(mut reader: R) -> AnyResult<Self> {
+        let mut header = [0u8; MAGIC.len() + 4];
+        reader.read_exact(&mut header)?;
+        let (magic, version) = header.split_at(MAGIC.len());
+        if magic != MAGIC {
+            return Err(errors::InvalidReplayError("not a replay file").into());
+        }
+        if u32::from_le_bytes(version.try_into()?) != VERSION {
+            return Err(errors::InvalidReplayError("unsupported replay format version").into());
+        }
+
+        Ok(Self(Mutex::new(ReplayerInner { reader, index: 0 })))
+    }
+}
+
+impl<R: Read> ReplayerInner<R> {
+    fn read_next(&mut self) -> AnyResult<Option<(String, Vec<u8>)>> {
+        let mut len = [0u8; 2];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut source = vec![0u8; u16::from_le_bytes(len).into()];
+        self.reader.read_exact(&mut source)?;
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let mut data = vec![0u8; u32::from_le_bytes(len).try_into()?];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some((String::from_utf8(source)?, data)))
+    }
+}
+
+impl<R: Read + Send> NondetTap for NondetReplayer<R> {
+    #[instrument(level = Level::TRACE, skip(self), err)]
+    fn replay(&self, source: &str) -> AnyResult<Option<Vec<u8>>> {
+        let mut inner = self.0.lock();
+        let index = inner.index;
+        inner.index += 1;
+        match inner.read_next()? {
+            Some((s, data)) if s == source => {
+                trace!(index, data.len = data.len(), "Replayed value");
+                Ok(Some(data))
+            }
+            recorded => Err(errors::ReplayMismatchError {
+                index,
+                recorded: recorded.map(|(s, _)| s),
+                actual: source.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    fn record(&self, _: &str, _: &[u8]) -> AnyResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fmt::{Debug, Formatter, Result as FmtResult};
+    use std::io::{Cursor, Result as IoResult};
+    use std::sync::Arc;
+
+    use wasmtime::{Engine, Linker, Module, Store};
+
+    use crate::bindings::wasi_snapshot_preview1::add_to_linker;
+    use crate::context::WasiContext;
+    use crate::stdio::HostStdout;
+
+    const GUEST: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "clock_time_get"
+    (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get"
+    (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 0)))
+    (drop (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 8)))
+    (drop (call $random_get (i32.const 16) (i32.const 16)))
+    (i32.store (i32.const 64) (i32.const 0))
+    (i32.store (i32.const 68) (i32.const 32))
+    (drop (call $fd_write (i32.const 1) (i32.const 64) (i32.const 1) (i32.const 72)))))
+"#;
+
+    #[derive(Default)]
+    struct Capture(Mutex<Vec<u8>>);
+
+    impl Debug for Capture {
+        fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+            f.debug_struct("Capture").finish_non_exhaustive()
+        }
+    }
+
+    impl HostStdout for Capture {
+        fn write(&self, buf: &[u8]) -> IoResult<()> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(())
+        }
+
+        fn flush(&self) -> IoResult<()> {
+            Ok(())
+        }
+    }
+
+    fn run_guest(tap: Arc<dyn NondetTap>) -> AnyResult<Vec<u8>> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, GUEST)?;
+        let stdout = Arc::new(Capture::default());
+
+        let mut builder = WasiContext::builder();
+        builder.stdout(stdout.clone())?;
+        builder.nondet_tap(tap);
+        let mut store = Store::new(&engine, builder.build()?);
+        let mut linker = Linker::new(&engine);
+        add_to_linker(&mut linker, |v| v)?;
+        linker
+            .instantiate(&mut store, &module)?
+            .get_typed_func::<(), ()>(&mut store, "_start")?
+            .call(&mut store, ())?;
+
+        let ret = stdout.0.lock().clone();
+        Ok(ret)
+    }
+
+    #[test]
+    fn test_record_replay_roundtrip() {
+        let recorder = Arc::new(NondetRecorder::new(Vec::new()).unwrap());
+        let recorded = run_guest(recorder.clone()).unwrap();
+        assert_eq!(recorded.len(), 32);
+        let data = Arc::into_inner(recorder).unwrap().into_inner();
+
+        let replayer = Arc::new(NondetReplayer::new(Cursor::new(data)).unwrap());
+        let replayed = run_guest(replayer).unwrap();
+        assert_eq!(recorded, replayed);
+    }
+
+    #[test]
+    fn test_replay_mismatch() {
+        let recorder = NondetRecorder::new(Vec::new()).unwrap();
+        recorder.record(SOURCE_CLOCK_WALL, &[0; 8]).unwrap();
+        let replayer = NondetReplayer::new(Cursor::new(recorder.into_inner())).unwrap();
+
+        let e = replayer
+            .replay(SOURCE_RANDOM_SECURE)
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("- clock.wall"), "{e}");
+        assert!(e.contains("+ random.secure"), "{e}");
+        let e = replayer.replay(SOURCE_CLOCK_WALL).unwrap_err().to_string();
+        assert!(e.contains("- <end of recording>"), "{e}");
+    }
+
+    #[test]
+    fn test_replay_bad_header() {
+        assert!(NondetReplayer::new(Cursor::new(b"GWREPLAY\x02\0\0\0".to_vec())).is_err());
+        assert!(NondetReplayer::new(Cursor::new(b"notareplay\0\0".to_vec())).is_err());
+    }
+}
@@ -19,10 +19,15 @@ use tracing::{debug, debug_span, info, instrument, warn, Level};
 use wiggle::{GuestError, GuestMemory, GuestPtr, GuestType, Region};
 
 use crate::bindings::types::*;
-use crate::context::{try_iso_fs, WasiContext};
+use crate::clock::deadline;
+use crate::context::{try_iso_fs, HostFiles, WasiContext};
 use crate::errors::StreamError;
 use crate::fs_host::Descriptor;
 use crate::fs_isolated::{AccessMode, CreateParams, NodeItem};
+use crate::nondet::{
+    tap_bytes, tap_duration, tap_fill, tap_u64, SOURCE_CLOCK_MONOTONIC, SOURCE_CLOCK_WALL,
+    SOURCE_HOST_FS_READ, SOURCE_RANDOM_SECURE,
+};
 use crate::stdio::{HostStdin, HostStdout};
 use crate::{print_byte_array, EMPTY_BUF};
 
@@ -238,6 +243,24 @@ impl P1File {
     pub fn preopen(&self) -> Option<&str> {
         self.preopen.as_deref()
     }
+
+    /// Gets current position. Append mode always reports end of file.
+    pub fn tell(&self) -> Result<u64, StreamError> {
+        match self.cursor {
+            Some(c) => Ok(c),
+            None => self.desc.file_len(),
+        }
+    }
+}
+
+impl P1Desc {
+    /// Gets file length.
+    pub fn file_len(&self) -> Result<u64, StreamError> {
+        Ok(match self {
+            Self::IsoFS(v) => v.node().file().ok_or(ErrorKind::IsADirectory)?.len() as u64,
+            Self::HostFS(v) => v.file()?.metadata()?.len(),
+        })
+    }
 }
 
 macro_rules! p1item_gen {
@@ -291,6 +314,7 @@ p1item_gen! {
     HostStdout(Arc<dyn Send + Sync + HostStdout>, (&'a (dyn Send + Sync + HostStdout), &**v), (&'a (dyn Send + Sync + HostStdout), &**v)),
     HostStdin(Arc<dyn Send + Sync + HostStdin>, (&'a (dyn Send + Sync + HostStdin), &**v), (&'a (dyn Send + Sync + HostStdin), &**v)),
     NullStdio(crate::stdio::NullStdio, (&'a mut crate::stdio::NullStdio, v), (&'a crate::stdio::NullStdio, v)),
+    Pipe(Box<crate::stdio::Pipe>, (&'a crate::stdio::Pipe, v), (&'a crate::stdio::Pipe, v)),
 }
 
 struct MemIO<'a, 'b, T> {
@@ -749,8 +773,15 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
         _resolution: Timestamp,
     ) -> Result<Timestamp, StreamError> {
         match id {
-            Clockid::Realtime => Ok(to_timestamp(SystemTime::now())),
-            Clockid::Monotonic => Ok(self.clock.now()),
+            Clockid::Realtime => tap_duration(self.nondet.as_deref(), SOURCE_CLOCK_WALL, || {
+                Ok(SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or(Duration::ZERO))
+            })
+            .map(|t| t.as_nanos() as _),
+            Clockid::Monotonic => tap_u64(self.nondet.as_deref(), SOURCE_CLOCK_MONOTONIC, || {
+                Ok(self.clock.now())
+            }),
             _ => Err(Errno::Badf.into()),
         }
     }
@@ -784,7 +815,8 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
             | FdItem::StdinSignal(_)
             | FdItem::HostStdin(_)
             | FdItem::HostStdout(_)
-            | FdItem::NullStdio(_) => (),
+            | FdItem::NullStdio(_)
+            | FdItem::Pipe(_) => (),
         }
         Ok(())
     }
@@ -794,11 +826,24 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
         &mut self,
         _: &mut GuestMemory<'_>,
         fd: Fd,
-        _offset: Filesize,
-        _len: Filesize,
+        off: Filesize,
+        len: Filesize,
     ) -> Result<(), StreamError> {
-        self.p1_items.get_item(fd)?;
-        Err(ErrorKind::Unsupported.into())
+        match self.p1_items.get_item(fd)? {
+            FdItem::P1File(P1File {
+                desc: P1Desc::IsoFS(v),
+                ..
+            }) => v.allocate(
+                off.try_into().map_err(AnyError::from)?,
+                len.try_into().map_err(AnyError::from)?,
+            )?,
+            FdItem::P1File(P1File {
+                desc: P1Desc::HostFS(v),
+                ..
+            }) => v.write()?.file()?.allocate(off, len)?,
+            _ => return Err(Errno::Badf.into()),
+        }
+        Ok(())
     }
 
     #[instrument(skip(self), err(level = Level::WARN))]
@@ -963,7 +1008,7 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                     fs_rights_inheriting: rights,
                 }
             }
-            FdItem::NullStdio(_) => {
+            FdItem::NullStdio(_) | FdItem::Pipe(_) => {
                 let rights = Rights::FD_READ | Rights::FD_WRITE;
                 Fdstat {
                     fs_filetype: Filetype::Unknown,
@@ -1016,7 +1061,8 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
             FdItem::StdinSignal(_)
             | FdItem::HostStdin(_)
             | FdItem::HostStdout(_)
-            | FdItem::NullStdio(_) => Ok(Filestat {
+            | FdItem::NullStdio(_)
+            | FdItem::Pipe(_) => Ok(Filestat {
                 dev: 0,
                 ino: 0,
                 filetype: Filetype::Unknown,
@@ -1098,6 +1144,7 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
     ) -> Result<Size, StreamError> {
         let memio = MemIO::new_read(mem, iovs)?;
 
+        // Positional read, cursor must not be touched.
         match self.p1_items.get_item(fd)? {
             FdItem::P1File(P1File {
                 desc: P1Desc::IsoFS(v),
@@ -1117,14 +1164,20 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
             }) => {
                 // Only try to read once per syscall.
                 let mut has_read = false;
+                let tap = self.nondet.as_deref();
                 memio.read(v.read()?.file()?, |v, len| {
                     if len == 0 || has_read {
                         return Ok(((&[]).into(), 0));
                     }
 
-                    let mut ret = vec![0; EMPTY_BUF.len().min(len.try_into()?)];
-                    let l = crate::fs_host::CapWrapper::read_at(v, &mut ret, offset)?;
-                    ret.truncate(l);
+                    let n = EMPTY_BUF.len().min(len.try_into()?);
+                    let ret = tap_bytes(tap, SOURCE_HOST_FS_READ, || {
+                        let mut ret = vec![0; n];
+                        let l = crate::fs_host::CapWrapper::read_at(v, &mut ret, offset)?;
+                        ret.truncate(l);
+                        Ok::<_, StreamError>(ret)
+                    })?;
+                    let l = ret.len();
                     has_read = true;
                     Ok((ret.into(), l as Size))
                 })
@@ -1181,13 +1234,12 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
     ) -> Result<Size, StreamError> {
         let memio = MemIO::new_write(mem, iovs)?;
 
+        // Positional write, cursor must not be touched.
         match self.p1_items.get_item(fd)? {
             FdItem::P1File(P1File {
                 desc: P1Desc::IsoFS(v),
                 ..
             }) => {
-                v.access().write_or_err()?;
-                let mut v = v.node().file().ok_or(ErrorKind::IsADirectory)?;
                 let mut off = usize::try_from(offset)?;
                 memio.write(|s| {
                     v.write(s, off)?;
@@ -1253,15 +1305,20 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                 let Some(c) = cursor else { return Ok(0) };
                 let old = *c;
 
+                let tap = self.nondet.as_deref();
                 let r = memio.read(v.read()?.file()?, |v, len| {
                     if len == 0 || has_read {
                         return Ok(((&[]).into(), 0));
                     }
 
-                    let mut ret =
-                        vec![0; EMPTY_BUF.len().min(len.try_into().unwrap_or(usize::MAX))];
-                    let l = crate::fs_host::CapWrapper::read_at(v, &mut ret, *c as _)?;
-                    ret.truncate(l);
+                    let n = EMPTY_BUF.len().min(len.try_into().unwrap_or(usize::MAX));
+                    let ret = tap_bytes(tap, SOURCE_HOST_FS_READ, || {
+                        let mut ret = vec![0; n];
+                        let l = crate::fs_host::CapWrapper::read_at(v, &mut ret, *c as _)?;
+                        ret.truncate(l);
+                        Ok::<_, StreamError>(ret)
+                    })?;
+                    let l = ret.len();
                     *c += l as u64;
                     has_read = true;
                     Ok((ret.into(), l as Size))
@@ -1286,6 +1343,17 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                 let l = ret.len() as Size;
                 Ok((ret.into(), l))
             }),
+            FdItem::Pipe(v) => memio.read((v.input(), true), |(v, b), len| {
+                let len = usize::try_from(len).unwrap_or(usize::MAX);
+                let ret = if len > 0 && *b {
+                    *b = false;
+                    v.read_block(len, self.timeout)
+                } else {
+                    v.read(len)
+                }?;
+                let l = ret.len() as Size;
+                Ok((ret.into(), l))
+            }),
             FdItem::HostStdin(v) => memio.read(v, |v, len| {
                 let ret = v.read_block(len.try_into().unwrap_or(usize::MAX), self.timeout)?;
                 let l = ret.len() as Size;
@@ -1468,6 +1536,10 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
         self.p1_items.rename(fd, to)
     }
 
+    /// Seeks file.
+    ///
+    /// Append mode files have no cursor, so only `fd_seek(Cur, 0)` and `fd_seek(End, 0)`
+    /// are allowed (returning end of file). Other seeks returns `ESPIPE`.
     #[instrument(skip(self), err(level = Level::WARN))]
     fn fd_seek(
         &mut self,
@@ -1505,6 +1577,10 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                 .ok_or(ErrorKind::InvalidInput)?;
                 *c as _
             }
+            FdItem::P1File(v @ P1File { cursor: None, .. }) => match (whence, offset) {
+                (Whence::Cur | Whence::End, 0) => v.tell()?,
+                _ => return Err(Errno::Spipe.into()),
+            },
             _ => return Err(Errno::Badf.into()),
         })
     }
@@ -1528,7 +1604,7 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
     #[instrument(skip(self), err(level = Level::WARN))]
     fn fd_tell(&mut self, _: &mut GuestMemory<'_>, fd: Fd) -> Result<Filesize, StreamError> {
         match self.p1_items.get_item(fd)? {
-            FdItem::P1File(P1File { cursor: c, .. }) => Ok(c.unwrap_or(0) as _),
+            FdItem::P1File(v) => Ok(v.tell()?),
             _ => Err(Errno::Badf.into()),
         }
     }
@@ -1548,9 +1624,6 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                 cursor,
                 ..
             }) => {
-                v.access().write_or_err()?;
-                let mut v = v.node().file().ok_or(ErrorKind::IsADirectory)?;
-
                 if let Some(c) = cursor {
                     let old = *c;
                     let r = memio.write(|s| {
@@ -1564,8 +1637,7 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                     r
                 } else {
                     memio.write(|s| {
-                        let i = v.len();
-                        v.write(s, i)?;
+                        v.append(s)?;
                         Ok(s.len() as Size)
                     })
                 }
@@ -1604,6 +1676,12 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                 v.flush()?;
                 Ok(r)
             }
+            FdItem::Pipe(v) => {
+                if v.is_closed() {
+                    return Err(Errno::Pipe.into());
+                }
+                memio.write(|s| Ok(v.write(s, self.timeout)? as _))
+            }
             _ => Err(Errno::Badf.into()),
         }
     }
@@ -1659,13 +1737,23 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
             FdItem::P1File(P1File {
                 desc: P1Desc::IsoFS(v),
                 ..
-            }) => Ok(iso_filestat(&v.open(
-                try_iso_fs(&self.iso_fs)?,
-                &to_utf8_path(path),
-                follow_symlink,
-                None,
-                AccessMode::R,
-            )?)),
+            }) => {
+                let controller = try_iso_fs(&self.iso_fs)?;
+                let path = to_utf8_path(path);
+                if let Some(f) = self
+                    .host_files
+                    .resolve(controller, v, &path, follow_symlink)
+                {
+                    return Ok(host_filestat(f.file()?.metadata()?, &self.hasher));
+                }
+                Ok(iso_filestat(&v.open(
+                    controller,
+                    &path,
+                    follow_symlink,
+                    None,
+                    AccessMode::R,
+                )?))
+            }
             FdItem::P1File(P1File {
                 desc: P1Desc::HostFS(v),
                 ..
@@ -1798,34 +1886,36 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                 desc: P1Desc::IsoFS(v),
                 ..
             }) => {
-                let create = if create {
-                    access = access | AccessMode::W;
-                    Some(CreateParams {
-                        dir: is_dir,
-                        exclusive,
-                    })
+                let controller = try_iso_fs(&self.iso_fs)?;
+                let path = to_utf8_path(path);
+                if let Some(f) = self
+                    .host_files
+                    .resolve(controller, v, &path, follow_symlink)
+                {
+                    HostFiles::open(f, access, create && exclusive, is_dir, is_truncate)?.into()
                 } else {
-                    None
-                };
+                    let create = if create {
+                        access = access | AccessMode::W;
+                        Some(CreateParams {
+                            dir: is_dir,
+                            exclusive,
+                        })
+                    } else {
+                        None
+                    };
+
+                    let v = v
+                        .open(controller, &path, follow_symlink, create, access)?
+                        .follow_symlink(controller)?;
+                    if is_dir && !v.node().is_dir() {
+                        return Err(ErrorKind::NotADirectory.into());
+                    }
+                    if is_truncate {
+                        v.resize(0)?;
+                    }
 
-                let controller = try_iso_fs(&self.iso_fs)?;
-                let v = v
-                    .open(
-                        controller,
-                        &to_utf8_path(path),
-                        follow_symlink,
-                        create,
-                        access,
-                    )?
-                    .follow_symlink(controller)?;
-                if is_dir && !v.node().is_dir() {
-                    return Err(ErrorKind::NotADirectory.into());
-                }
-                if is_truncate {
-                    v.resize(0)?;
+                    v.into()
                 }
-
-                v.into()
             }
             FdItem::P1File(P1File {
                 desc: P1Desc::HostFS(v),
@@ -1858,16 +1948,16 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                     opts.maybe_dir(true);
                 }
 
-                let v = v.dir()?.open_with(to_path(path), &opts)?;
-                let v = if v.metadata()?.is_dir() {
-                    crate::fs_host::Descriptor::Dir(cap_std::fs::Dir::from_std_file(v.into_std()))
+                let f = v.dir()?.open_with(v.resolve_path(&to_path(path))?, &opts)?;
+                let f = if f.metadata()?.is_dir() {
+                    crate::fs_host::Descriptor::Dir(cap_std::fs::Dir::from_std_file(f.into_std()))
                 } else if is_dir {
                     return Err(ErrorKind::NotADirectory.into());
                 } else {
-                    crate::fs_host::Descriptor::File(v)
+                    crate::fs_host::Descriptor::File(f)
                 };
 
-                crate::fs_host::CapWrapper::new(Arc::new(v), access).into()
+                v.child(f, access).into()
             }
             _ => return Err(Errno::Badf.into()),
         };
@@ -2101,6 +2191,7 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
 
         enum Poll {
             Always,
+            Never,
             Instant(Instant),
             SystemTime(SystemTime),
             Signal(crate::stdio::StdinSignalPollable),
@@ -2118,14 +2209,16 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                             Clockid::Monotonic | Clockid::Realtime
                                 if !v.flags.contains(Subclockflags::SUBSCRIPTION_CLOCK_ABSTIME) =>
                             {
-                                Poll::Instant(now + Duration::from_nanos(v.timeout))
-                            }
-                            Clockid::Monotonic => {
-                                Poll::Instant(self.clock.poll_until(v.timeout)?.until)
+                                deadline(now, v.timeout).map_or(Poll::Never, Poll::Instant)
                             }
-                            Clockid::Realtime => Poll::SystemTime(
-                                SystemTime::UNIX_EPOCH + Duration::from_nanos(v.timeout),
-                            ),
+                            Clockid::Monotonic => self
+                                .clock
+                                .poll_until(v.timeout)
+                                .until
+                                .map_or(Poll::Never, Poll::Instant),
+                            Clockid::Realtime => SystemTime::UNIX_EPOCH
+                                .checked_add(Duration::from_nanos(v.timeout))
+                                .map_or(Poll::Never, Poll::SystemTime),
                             _ => return Err(ErrorKind::InvalidInput.into()),
                         },
                         SubscriptionU::FdRead(v) => {
@@ -2135,6 +2228,7 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                                     Poll::Always
                                 }
                                 FdItem::StdinSignal(v) => Poll::Signal(v.poll()?),
+                                FdItem::Pipe(v) => Poll::Signal(v.input().poll()?),
                                 _ => return Err(Errno::Badf.into()),
                             }
                         }
@@ -2143,7 +2237,8 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                                 // File is always ready
                                 FdItem::P1File(_)
                                 | FdItem::NullStdio(_)
-                                | FdItem::HostStdout(_) => Poll::Always,
+                                | FdItem::HostStdout(_)
+                                | FdItem::Pipe(_) => Poll::Always,
                                 _ => return Err(Errno::Badf.into()),
                             }
                         }
@@ -2161,6 +2256,7 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
             for (p, s) in &polls {
                 if !match p {
                     Poll::Always => true,
+                    Poll::Never => false,
                     Poll::Instant(t) => *t <= now,
                     Poll::SystemTime(t) => *t <= now_st,
                     Poll::Signal(v) => {
@@ -2205,7 +2301,7 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
                 let mut c = crate::poll::PollController::new(self.timeout);
                 for (p, _) in &polls {
                     match p {
-                        Poll::Always => (),
+                        Poll::Always | Poll::Never => (),
                         Poll::Instant(t) => c.set_instant(*t),
                         Poll::SystemTime(t) => c.set_systime(*t),
                         Poll::Signal(v) => c.add_signal(&v.0),
@@ -2259,8 +2355,10 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
             }),
         };
         if let Some(src) = src {
-            self.secure_rng.fill(src);
-            Ok(())
+            tap_fill(self.nondet.as_deref(), SOURCE_RANDOM_SECURE, src, |b| {
+                self.secure_rng.fill(b);
+                Ok(())
+            })
         } else {
             Err(GuestError::PtrOutOfBounds(Region {
                 start: buf,
@@ -2312,3 +2410,638 @@ impl crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiConte
         Err(Errno::Notsock.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasmtime::{Engine, Linker, Module, Store};
+
+    use crate::bindings::wasi_snapshot_preview1::add_to_linker;
+    use crate::context::WasiContextBuilder;
+    use crate::fs_isolated::IsolatedFSController;
+
+    const APPEND_GUEST: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_tell"
+    (func $fd_tell (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_seek"
+    (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "f.txt")
+  (data (i32.const 16) "hello")
+  (func (export "_start")
+    (local $fd i32)
+    (i32.store (i32.const 32) (i32.const 16))
+    (i32.store (i32.const 36) (i32.const 5))
+    ;; Open /f.txt with O_CREAT and O_APPEND, all rights like wasi-libc does
+    (i32.store (i32.const 100)
+      (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 5)
+        (i32.const 1) (i64.const 0x3fffffff) (i64.const 0x3fffffff) (i32.const 1) (i32.const 64)))
+    (local.set $fd (i32.load (i32.const 64)))
+    (i32.store (i32.const 104)
+      (call $fd_write (local.get $fd) (i32.const 32) (i32.const 1) (i32.const 72)))
+    (i32.store (i32.const 108)
+      (call $fd_write (local.get $fd) (i32.const 32) (i32.const 1) (i32.const 72)))
+    (i32.store (i32.const 112) (call $fd_tell (local.get $fd) (i32.const 80)))
+    (i32.store (i32.const 116)
+      (call $fd_seek (local.get $fd) (i64.const 0) (i32.const 1) (i32.const 88)))
+    (i32.store (i32.const 120)
+      (call $fd_seek (local.get $fd) (i64.const 0) (i32.const 0) (i32.const 96))))
+)
+"#;
+
+    const POSITIONAL_GUEST: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_pwrite"
+    (func $fd_pwrite (param i32 i32 i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_pread"
+    (func $fd_pread (param i32 i32 i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_seek"
+    (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_tell"
+    (func $fd_tell (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "f.txt")
+  (data (i32.const 16) "hello world")
+  (data (i32.const 32) "XY")
+  (data (i32.const 40) "_")
+  ;; Iovecs at 48.., lengths at 128.., offsets at 160.., errno at 400..
+  ;; Read buffers at 256 (pread), 272 (read), 288 (read), and 304 (final read)
+  (func (export "_start")
+    (local $fd i32)
+    (i32.store (i32.const 48) (i32.const 16))
+    (i32.store (i32.const 52) (i32.const 11))
+    (i32.store (i32.const 56) (i32.const 32))
+    (i32.store (i32.const 60) (i32.const 2))
+    (i32.store (i32.const 64) (i32.const 40))
+    (i32.store (i32.const 68) (i32.const 1))
+    (i32.store (i32.const 72) (i32.const 256))
+    (i32.store (i32.const 76) (i32.const 5))
+    (i32.store (i32.const 80) (i32.const 272))
+    (i32.store (i32.const 84) (i32.const 5))
+    (i32.store (i32.const 88) (i32.const 288))
+    (i32.store (i32.const 92) (i32.const 5))
+    (i32.store (i32.const 96) (i32.const 304))
+    (i32.store (i32.const 100) (i32.const 32))
+
+    ;; Open /f.txt with O_CREAT and all rights like wasi-libc does
+    (i32.store (i32.const 400)
+      (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 5)
+        (i32.const 1) (i64.const 0x3fffffff) (i64.const 0x3fffffff) (i32.const 0) (i32.const 120)))
+    (local.set $fd (i32.load (i32.const 120)))
+    ;; Cursor 11 -> 0
+    (i32.store (i32.const 404)
+      (call $fd_write (local.get $fd) (i32.const 48) (i32.const 1) (i32.const 128)))
+    (i32.store (i32.const 408)
+      (call $fd_seek (local.get $fd) (i64.const 0) (i32.const 0) (i32.const 160)))
+    ;; pread does not move cursor, read continues from 0
+    (i32.store (i32.const 412)
+      (call $fd_pread (local.get $fd) (i32.const 72) (i32.const 1) (i64.const 6) (i32.const 132)))
+    (i32.store (i32.const 416)
+      (call $fd_read (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 136)))
+    ;; pwrite does not move cursor either
+    (i32.store (i32.const 420)
+      (call $fd_pwrite (local.get $fd) (i32.const 56) (i32.const 1) (i64.const 0) (i32.const 140)))
+    (i32.store (i32.const 424) (call $fd_tell (local.get $fd) (i32.const 168)))
+    (i32.store (i32.const 428)
+      (call $fd_write (local.get $fd) (i32.const 64) (i32.const 1) (i32.const 144)))
+    (i32.store (i32.const 432)
+      (call $fd_read (local.get $fd) (i32.const 88) (i32.const 1) (i32.const 148)))
+    (i32.store (i32.const 436) (call $fd_tell (local.get $fd) (i32.const 176)))
+    ;; pread past end of file reads nothing
+    (i32.store (i32.const 440)
+      (call $fd_pread (local.get $fd) (i32.const 72) (i32.const 1) (i64.const 100) (i32.const 152)))
+    (i32.store (i32.const 444) (call $fd_tell (local.get $fd) (i32.const 184)))
+    (i32.store (i32.const 448)
+      (call $fd_seek (local.get $fd) (i64.const 0) (i32.const 0) (i32.const 192)))
+    (i32.store (i32.const 452)
+      (call $fd_read (local.get $fd) (i32.const 96) (i32.const 1) (i32.const 156))))
+)
+"#;
+
+    const HOST_FILE_GUEST: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_readdir"
+    (func $fd_readdir (param i32 i32 i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_filestat_get"
+    (func $path_filestat_get (param i32 i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "app.cfg")
+  ;; Read buffer at 256, readdir buffer at 512, filestat at 1024, errno at 400..
+  (func (export "_start")
+    (i32.store (i32.const 32) (i32.const 256))
+    (i32.store (i32.const 36) (i32.const 32))
+    ;; Open for reading
+    (i32.store (i32.const 400)
+      (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 7)
+        (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 64)))
+    (i32.store (i32.const 404)
+      (call $fd_read (i32.load (i32.const 64)) (i32.const 32) (i32.const 1) (i32.const 128)))
+    ;; Open for writing, and truncate
+    (i32.store (i32.const 408)
+      (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 7)
+        (i32.const 0) (i64.const 64) (i64.const 0) (i32.const 0) (i32.const 68)))
+    (i32.store (i32.const 412)
+      (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 7)
+        (i32.const 8) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 68)))
+    (i32.store (i32.const 416)
+      (call $fd_readdir (i32.const 3) (i32.const 512) (i32.const 256) (i64.const 0) (i32.const 132)))
+    (i32.store (i32.const 420)
+      (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 7) (i32.const 1024))))
+)
+"#;
+
+    const ENV_GUEST: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "environ_sizes_get"
+    (func $environ_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "environ_get"
+    (func $environ_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get"
+    (func $args_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_get"
+    (func $args_get (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; [0] environ count, [4] environ size, [8] args count, [12] args size
+  ;; Pointers at 1024 (environ) and 2048 (args), strings at 4096 (environ) and 32768 (args)
+  (func (export "_start")
+    (i32.store (i32.const 16) (call $environ_sizes_get (i32.const 0) (i32.const 4)))
+    (i32.store (i32.const 20) (call $environ_get (i32.const 1024) (i32.const 4096)))
+    (i32.store (i32.const 24) (call $args_sizes_get (i32.const 8) (i32.const 12)))
+    (i32.store (i32.const 28) (call $args_get (i32.const 2048) (i32.const 32768))))
+)
+"#;
+
+    const PIPE_GUEST: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close"
+    (func $fd_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_prestat_get"
+    (func $fd_prestat_get (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "pong")
+  ;; [0] read length, [4] write length, read buffer at 256
+  (func $init
+    (i32.store (i32.const 32) (i32.const 256))
+    (i32.store (i32.const 36) (i32.const 64))
+    (i32.store (i32.const 40) (i32.const 16))
+    (i32.store (i32.const 44) (i32.const 4)))
+  (func (export "exchange")
+    (call $init)
+    (i32.store (i32.const 100)
+      (call $fd_read (i32.const 3) (i32.const 32) (i32.const 1) (i32.const 0)))
+    (i32.store (i32.const 104)
+      (call $fd_write (i32.const 3) (i32.const 40) (i32.const 1) (i32.const 4)))
+    (i32.store (i32.const 108) (call $fd_prestat_get (i32.const 3) (i32.const 8)))
+    (i32.store (i32.const 112) (call $fd_prestat_get (i32.const 4) (i32.const 8))))
+  (func (export "after_close")
+    (call $init)
+    (i32.store (i32.const 116)
+      (call $fd_read (i32.const 3) (i32.const 32) (i32.const 1) (i32.const 0)))
+    (i32.store (i32.const 120)
+      (call $fd_write (i32.const 3) (i32.const 40) (i32.const 1) (i32.const 4)))
+    (i32.store (i32.const 124) (call $fd_close (i32.const 3))))
+)
+"#;
+
+    // Writes 1 MiB of pattern to stdout in 64 KiB chunks. Errno at 16.
+    const CAPTURE_GUEST: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 17)
+  (func (export "_start")
+    (local $i i32) (local $e i32)
+    (loop $fill
+      (i32.store8 (i32.add (i32.const 65536) (local.get $i))
+        (i32.xor (local.get $i) (i32.shr_u (local.get $i) (i32.const 11))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $fill (i32.lt_u (local.get $i) (i32.const 1048576))))
+    (local.set $i (i32.const 0))
+    (block $done
+      (loop $write
+        (br_if $done (i32.ge_u (local.get $i) (i32.const 1048576)))
+        (i32.store (i32.const 0) (i32.add (i32.const 65536) (local.get $i)))
+        (i32.store (i32.const 4) (i32.const 65536))
+        (local.set $e
+          (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+        (i32.store (i32.const 16) (local.get $e))
+        (br_if $done (local.get $e))
+        (local.set $i (i32.add (local.get $i) (i32.load (i32.const 8))))
+        (br $write))))
+)
+"#;
+
+    fn read_u32(mem: &[u8], i: usize) -> u32 {
+        u32::from_le_bytes(mem[i..i + 4].try_into().unwrap())
+    }
+
+    fn read_u64(mem: &[u8], i: usize) -> u64 {
+        u64::from_le_bytes(mem[i..i + 8].try_into().unwrap())
+    }
+
+    /// Parses C strings the way guest libc would.
+    fn read_cstrs(mem: &[u8], ptrs: usize, buf: usize, count: u32, size: u32) -> Vec<String> {
+        let mut total = 0;
+        let ret = (0..count as usize)
+            .map(|i| {
+                let p = read_u32(mem, ptrs + i * 4) as usize;
+                let l = mem[p..].iter().position(|&b| b == 0).unwrap();
+                total = total.max(p + l + 1 - buf);
+                String::from_utf8(mem[p..p + l].to_vec()).unwrap()
+            })
+            .collect();
+        assert_eq!(total, size as usize, "buffer size mismatch");
+        ret
+    }
+
+    #[test]
+    fn test_environ_args_exact() {
+        let long = "x".repeat(5000);
+        let envs = [
+            ("EMOJI", "🦀 héllo 👋🏽"),
+            ("EQ", "a=b=c"),
+            ("EMPTY", ""),
+            ("LONG", &long),
+            ("ÜNICODE_KEY", "v"),
+        ];
+        let args = ["prog", "🦀", "a=b", "", &long];
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, ENV_GUEST).unwrap();
+        let mut builder = WasiContext::builder();
+        builder
+            .envs(envs.iter().map(|&(k, v)| (k.into(), v.into())))
+            .unwrap()
+            .args(args.iter().map(|&v| v.into()))
+            .unwrap();
+        let mut store = Store::new(&engine, builder.build().unwrap());
+        let mut linker = Linker::new(&engine);
+        add_to_linker(&mut linker, |v| v).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap();
+        instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+
+        let mem = instance
+            .get_memory(&mut store, "memory")
+            .unwrap()
+            .data(&store);
+        for i in 0..4 {
+            assert_eq!(read_u32(mem, 16 + i * 4), 0, "call #{i} failed");
+        }
+
+        let mut r = read_cstrs(mem, 1024, 4096, read_u32(mem, 0), read_u32(mem, 4));
+        r.sort();
+        let mut expect = envs.map(|(k, v)| format!("{k}={v}")).to_vec();
+        expect.sort();
+        assert_eq!(r, expect);
+
+        let r = read_cstrs(mem, 2048, 32768, read_u32(mem, 8), read_u32(mem, 12));
+        assert_eq!(r, args);
+    }
+
+    #[test]
+    fn test_environ_args_reject() {
+        let mut builder = WasiContext::builder();
+        for (k, v) in [("A\0B", "v"), ("A=B", "v"), ("K", "v\0w")] {
+            let e = builder.env(k.into(), v.into()).err().unwrap();
+            assert!(e.is::<crate::errors::InvalidEnvArgError>(), "{e}");
+        }
+        let e = builder.args(["ok".into(), "a\0".into()]).err().unwrap();
+        assert!(e.is::<crate::errors::InvalidEnvArgError>(), "{e}");
+    }
+
+    #[derive(Debug, Default)]
+    struct Collect(parking_lot::Mutex<Vec<u8>>);
+
+    impl HostStdout for Collect {
+        fn write(&self, buf: &[u8]) -> std::io::Result<()> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(())
+        }
+
+        fn flush(&self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_extra_stream() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        use crate::stdio::Pipe;
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, PIPE_GUEST).unwrap();
+        let controller = IsolatedFSController::new(1 << 20, 16).unwrap();
+        let output = Arc::new(Collect::default());
+        let closed = Arc::new(AtomicBool::new(false));
+        let (pipe, host) = Pipe::new(
+            output.clone(),
+            Box::new(|| ()),
+            Some(Box::new({
+                let closed = closed.clone();
+                move || closed.store(true, Ordering::SeqCst)
+            })),
+        );
+
+        let mut builder = WasiContext::builder();
+        builder.isolated_fs_controller(&controller).unwrap();
+        builder
+            .preopen_dir_isolated("/".into(), "/".into())
+            .unwrap();
+        assert_eq!(builder.push_extra_stream(pipe), 3);
+        let mut store = Store::new(&engine, builder.build().unwrap());
+        let mut linker = Linker::new(&engine);
+        add_to_linker(&mut linker, |v| v).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap();
+        let call = |store: &mut Store<WasiContext>, name| {
+            instance
+                .get_typed_func::<(), ()>(&mut *store, name)
+                .unwrap()
+                .call(&mut *store, ())
+                .unwrap();
+            instance.get_memory(&mut *store, "memory").unwrap()
+        };
+
+        host.write(b"ping");
+        let mem = call(&mut store, "exchange").data(&store);
+        assert_eq!(read_u32(mem, 100), 0);
+        assert_eq!(read_u32(mem, 104), 0);
+        assert_eq!(&mem[256..256 + read_u32(mem, 0) as usize], b"ping");
+        assert_eq!(read_u32(mem, 4), 4);
+        assert_eq!(&output.0.lock()[..], b"pong");
+        // Extra stream is not a preopen, preopen is moved after it.
+        assert_eq!(read_u32(mem, 108), Errno::Badf as u32);
+        assert_eq!(read_u32(mem, 112), 0);
+
+        host.close();
+        let mem = call(&mut store, "after_close").data(&store);
+        // Read gets EOF, write gets broken pipe.
+        assert_eq!(read_u32(mem, 116), 0);
+        assert_eq!(read_u32(mem, 0), 0);
+        assert_eq!(read_u32(mem, 120), Errno::Pipe as u32);
+        assert_eq!(read_u32(mem, 124), 0);
+        assert!(closed.load(Ordering::SeqCst));
+        assert_eq!(output.0.lock().len(), 4);
+    }
+
+    fn run_positional(builder: WasiContextBuilder) {
+        let engine = Engine::default();
+        let module = Module::new(&engine, POSITIONAL_GUEST).unwrap();
+        let mut store = Store::new(&engine, builder.build().unwrap());
+        let mut linker = Linker::new(&engine);
+        add_to_linker(&mut linker, |v| v).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap();
+        instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+
+        let mem = instance
+            .get_memory(&mut store, "memory")
+            .unwrap()
+            .data(&store);
+        for i in 0..14 {
+            assert_eq!(read_u32(mem, 400 + i * 4), 0, "call #{i} failed");
+        }
+        let lens = (0..8)
+            .map(|i| read_u32(mem, 128 + i * 4))
+            .collect::<Vec<_>>();
+        assert_eq!(lens, [11, 5, 5, 2, 1, 5, 0, 11]);
+        assert_eq!(&mem[256..261], b"world");
+        assert_eq!(&mem[272..277], b"hello");
+        assert_eq!(&mem[288..293], b"world");
+        assert_eq!(&mem[304..315], b"XYllo_world");
+        // Cursor after read, second read, pread past end
+        assert_eq!(read_u64(mem, 168), 5);
+        assert_eq!(read_u64(mem, 176), 11);
+        assert_eq!(read_u64(mem, 184), 11);
+    }
+
+    #[test]
+    fn test_positional_iso_fs() {
+        let controller = IsolatedFSController::new(1 << 20, 16).unwrap();
+        let mut builder = WasiContext::builder();
+        builder.isolated_fs_controller(&controller).unwrap();
+        builder
+            .preopen_dir_isolated("/".into(), "/".into())
+            .unwrap();
+        run_positional(builder);
+    }
+
+    #[test]
+    fn test_positional_host_fs() {
+        let dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!("wasi-positional-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let _guard = scopeguard::guard((), |_| {
+            let _ = std::fs::remove_dir_all(&dir);
+        });
+
+        let mut builder = WasiContext::builder();
+        builder.preopen_dir_host(dir.clone(), "/".into()).unwrap();
+        run_positional(builder);
+        assert_eq!(std::fs::read(dir.join("f.txt")).unwrap(), b"XYllo_world");
+    }
+
+    #[test]
+    fn test_append_tell() {
+        let engine = Engine::default();
+        let module = Module::new(&engine, APPEND_GUEST).unwrap();
+        let controller = IsolatedFSController::new(1 << 20, 16).unwrap();
+
+        let mut builder = WasiContext::builder();
+        builder.isolated_fs_controller(&controller).unwrap();
+        builder
+            .preopen_dir_isolated("/".into(), "/".into())
+            .unwrap();
+        let mut store = Store::new(&engine, builder.build().unwrap());
+        let mut linker = Linker::new(&engine);
+        add_to_linker(&mut linker, |v| v).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap();
+        instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+
+        let mem = instance
+            .get_memory(&mut store, "memory")
+            .unwrap()
+            .data(&store);
+        // path_open, fd_write, fd_write, fd_tell, fd_seek(Cur, 0)
+        for i in 0..5 {
+            assert_eq!(read_u32(mem, 100 + i * 4), 0, "call #{i} failed");
+        }
+        assert_eq!(read_u64(mem, 80), 10);
+        assert_eq!(read_u64(mem, 88), 10);
+        assert_eq!(read_u32(mem, 120), Errno::Spipe as u32);
+    }
+
+    #[test]
+    fn test_preopen_file_host() {
+        let dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!("wasi-host-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let _guard = scopeguard::guard((), |_| {
+            let _ = std::fs::remove_dir_all(&dir);
+        });
+        let path = dir.join("config.ini");
+        std::fs::write(&path, b"key=value").unwrap();
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, HOST_FILE_GUEST).unwrap();
+        let mut builder = WasiContext::builder();
+        builder
+            .preopen_file_host(&path, "/etc/app/app.cfg".into(), true)
+            .unwrap();
+        let ctx = builder.build().unwrap();
+        // Parent is synthesized.
+        assert_eq!(ctx.preopen_paths().collect::<Vec<_>>(), ["/etc/app"]);
+        let mut store = Store::new(&engine, ctx);
+        let mut linker = Linker::new(&engine);
+        add_to_linker(&mut linker, |v| v).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap();
+        instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+        let mem = instance
+            .get_memory(&mut store, "memory")
+            .unwrap()
+            .data(&store);
+
+        assert_eq!(read_u32(mem, 400), 0);
+        assert_eq!(read_u32(mem, 404), 0);
+        assert_eq!(&mem[256..256 + read_u32(mem, 128) as usize], b"key=value");
+        assert_eq!(read_u32(mem, 408), Errno::Perm as u32);
+        assert_eq!(read_u32(mem, 412), Errno::Perm as u32);
+        assert_eq!(read_u32(mem, 416), 0);
+        let used = read_u32(mem, 132) as usize;
+        assert!(mem[512..512 + used].windows(7).any(|v| v == b"app.cfg"));
+        assert_eq!(read_u32(mem, 420), 0);
+        // Filestat size is at offset 32.
+        assert_eq!(read_u64(mem, 1024 + 32), 9);
+        assert_eq!(std::fs::read(&path).unwrap(), b"key=value");
+
+        // Inside isolated FS preopen, no parent is synthesized.
+        let mut builder = WasiContext::builder();
+        builder
+            .preopen_dir_isolated("/".into(), "/".into())
+            .unwrap()
+            .preopen_file_host(&path, "/etc/app.cfg".into(), false)
+            .unwrap();
+        let ctx = builder.build().unwrap();
+        assert_eq!(ctx.preopen_paths().collect::<Vec<_>>(), ["/"]);
+        assert_eq!(ctx.host_files.0.len(), 1);
+
+        let mut builder = WasiContext::builder();
+        assert!(builder
+            .preopen_file_host(dir.join("missing"), "/a".into(), true)
+            .is_err());
+        assert!(builder.preopen_file_host(&path, "/".into(), true).is_err());
+    }
+
+    #[test]
+    fn test_stdout_capture() {
+        use crate::stdio::StdoutCapture;
+
+        let run = |capture: Arc<StdoutCapture>| {
+            let engine = Engine::default();
+            let module = Module::new(&engine, CAPTURE_GUEST).unwrap();
+            let mut builder = WasiContext::builder();
+            builder.stdout(capture).unwrap();
+            let mut store = Store::new(&engine, builder.build().unwrap());
+            let mut linker = Linker::new(&engine);
+            add_to_linker(&mut linker, |v| v).unwrap();
+            let instance = linker.instantiate(&mut store, &module).unwrap();
+            instance
+                .get_typed_func::<(), ()>(&mut store, "_start")
+                .unwrap()
+                .call(&mut store, ())
+                .unwrap();
+            let mem = instance
+                .get_memory(&mut store, "memory")
+                .unwrap()
+                .data(&store);
+            read_u32(mem, 16)
+        };
+        let expect = (0..1u32 << 20)
+            .map(|i| (i ^ (i >> 11)) as u8)
+            .collect::<Vec<_>>();
+
+        let capture = Arc::new(StdoutCapture::new(2 << 20));
+        assert_eq!(run(capture.clone()), 0);
+        assert!(!capture.is_overflow());
+        let (v, overflow) = capture.take();
+        assert!(!overflow);
+        assert!(v == expect, "captured bytes mismatch");
+        // Take drains buffer.
+        assert_eq!(capture.take(), (Vec::new(), false));
+
+        // Writes past the cap are dropped, guest still sees success.
+        let capture = Arc::new(StdoutCapture::new(1000));
+        assert_eq!(run(capture.clone()), 0);
+        assert!(capture.is_overflow());
+        let (v, overflow) = capture.take();
+        assert!(overflow);
+        assert_eq!(v, expect[..1000]);
+        assert!(!capture.is_overflow());
+    }
+
+    #[test]
+    fn test_path_rename() {
+        use crate::bindings::wasi_snapshot_preview1::WasiSnapshotPreview1;
+
+        crate::fs_isolated::tests::check_rename(
+            crate::errors::mapping::code_errno,
+            |controller, src, dst| {
+                let mut builder = WasiContext::builder();
+                builder.isolated_fs_controller(controller).unwrap();
+                builder
+                    .preopen_dir_isolated("/".into(), "/".into())
+                    .unwrap();
+                let mut ctx = builder.build().unwrap();
+
+                let mut buf = vec![0u8; 64];
+                buf[..src.len()].copy_from_slice(src.as_bytes());
+                buf[32..32 + dst.len()].copy_from_slice(dst.as_bytes());
+                let ret = ctx.path_rename(
+                    &mut GuestMemory::Unshared(&mut buf),
+                    Fd::from(3),
+                    GuestPtr::new((0, src.len() as u32)),
+                    Fd::from(3),
+                    GuestPtr::new((32, dst.len() as u32)),
+                );
+                ret.map_err(|e| <Result<Errno, _>>::from(e).unwrap())
+            },
+        );
+    }
+}
@@ -2,7 +2,7 @@ use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::io::{
     stderr, stdout, Error as IoError, ErrorKind, IoSlice, Result as IoResult, Stderr, Stdout, Write,
 };
-use std::mem::replace;
+use std::mem::{replace, take};
 use std::panic::{RefUnwindSafe, UnwindSafe};
 use std::ptr::null;
 use std::sync::Arc;
@@ -11,7 +11,7 @@ use std::time::{Duration, Instant};
 use anyhow::Result as AnyResult;
 use cfg_if::cfg_if;
 use memchr::memchr_iter;
-use parking_lot::{Condvar, Mutex};
+use parking_lot::{Condvar, Mutex, MutexGuard};
 use scopeguard::{defer, guard, guard_on_unwind};
 use smallvec::SmallVec;
 use tracing::instrument;
@@ -34,7 +34,11 @@ impl Debug for NullStdio {
 pub struct StdinSignal {
     pub(crate) inner: Mutex<StdinInner>,
     pub(crate) cond: Condvar,
+    /// Signalled when data is consumed, used by bounded writers.
+    write_cond: Condvar,
     f: Box<dyn Fn() + Send + Sync>,
+    /// Called when buffer drops to low-water mark after [`StdinProvider::try_write`] is refused.
+    drained: Option<Box<dyn Fn() + Send + Sync>>,
 }
 
 impl Debug for StdinSignal {
@@ -55,6 +59,10 @@ pub(crate) struct StdinInner {
     data: StdinInnerData,
     start: usize,
     end: usize,
+    /// Maximum number of buffered bytes for [`StdinProvider::write_block`] and [`StdinProvider::try_write`].
+    cap: usize,
+    /// Set if [`StdinProvider::try_write`] is refused, until buffer is drained.
+    drain_pending: bool,
 
     pub(crate) head: *const WaitData,
 }
@@ -71,18 +79,22 @@ impl Debug for StdinInner {
             .field("start", &self.start)
             .field("end", &self.end)
             .field("len", &self.len())
+            .field("cap", &self.cap)
+            .field("drain_pending", &self.drain_pending)
             .field("has_waiting", &!self.head.is_null())
             .finish_non_exhaustive()
     }
 }
 
 impl StdinInner {
-    fn new() -> Self {
+    fn new(cap: usize) -> Self {
         StdinInner {
             closed: false,
             data: StdinInnerData::from_buf(Default::default()),
             start: 0,
             end: 0,
+            cap,
+            drain_pending: false,
 
             head: null(),
         }
@@ -141,7 +153,7 @@ impl StdinInner {
         let old_size = self.len();
 
         let ret: (&[u8], &[u8]) = if self.end >= self.start {
-            let i = self.start + len.min(self.end);
+            let i = self.start + len.min(self.end - self.start);
             let s = &self.data[self.start..i];
             self.start = i;
             (s, &[])
@@ -172,6 +184,11 @@ impl StdinInner {
         self.closed || self.end != self.start
     }
 
+    /// Buffer is drained once it's at most half full.
+    fn low_water(&self) -> usize {
+        self.cap / 2
+    }
+
     #[instrument]
     fn notify(&mut self) {
         let mut p = replace(&mut self.head, null());
@@ -188,10 +205,39 @@ impl StdinInner {
 
 impl StdinSignal {
     pub fn new(f: Box<dyn Fn() + Send + Sync>) -> (Arc<Self>, StdinProvider) {
+        Self::with_capacity(f, usize::MAX)
+    }
+
+    /// Creates signal with bounded buffer.
+    ///
+    /// Only [`StdinProvider::write_block`] respects capacity.
+    pub fn with_capacity(f: Box<dyn Fn() + Send + Sync>, cap: usize) -> (Arc<Self>, StdinProvider) {
+        Self::build(f, cap, None)
+    }
+
+    /// Creates signal with bounded buffer and drain handler.
+    ///
+    /// Once [`StdinProvider::try_write`] can't accept all data,
+    /// `drained` is called after guest consumes buffer down to half of it's capacity.
+    pub fn with_limit(
+        f: Box<dyn Fn() + Send + Sync>,
+        cap: usize,
+        drained: Box<dyn Fn() + Send + Sync>,
+    ) -> (Arc<Self>, StdinProvider) {
+        Self::build(f, cap, Some(drained))
+    }
+
+    fn build(
+        f: Box<dyn Fn() + Send + Sync>,
+        cap: usize,
+        drained: Option<Box<dyn Fn() + Send + Sync>>,
+    ) -> (Arc<Self>, StdinProvider) {
         let ret = Arc::new(Self {
-            inner: Mutex::new(StdinInner::new()),
+            inner: Mutex::new(StdinInner::new(cap)),
             cond: Condvar::new(),
+            write_cond: Condvar::new(),
             f,
+            drained,
         });
 
         (ret.clone(), StdinProvider(ret))
@@ -201,6 +247,22 @@ impl StdinSignal {
         self.inner.lock().is_ready()
     }
 
+    /// Wakes writers after data is consumed.
+    ///
+    /// All reads must go through here, so consumption is accounted identically.
+    /// Drain handler is called after lock is released, so it can write more data.
+    fn consumed(&self, mut guard: MutexGuard<'_, StdinInner>) {
+        self.write_cond.notify_all();
+        if !guard.drain_pending || guard.len() > guard.low_water() {
+            return;
+        }
+        guard.drain_pending = false;
+        drop(guard);
+        if let Some(f) = &self.drained {
+            f();
+        }
+    }
+
     #[instrument]
     pub fn read(&self, len: usize) -> IoResult<Vec<u8>> {
         if len == 0 {
@@ -214,6 +276,7 @@ impl StdinSignal {
         let mut ret = vec![0u8; a.len() + b.len()];
         ret[..a.len()].copy_from_slice(a);
         ret[a.len()..].copy_from_slice(b);
+        self.consumed(guard);
         Ok(ret)
     }
 
@@ -245,6 +308,7 @@ impl StdinSignal {
         let mut ret = vec![0u8; a.len() + b.len()];
         ret[..a.len()].copy_from_slice(a);
         ret[a.len()..].copy_from_slice(b);
+        self.consumed(guard);
         Ok(ret)
     }
 
@@ -257,7 +321,9 @@ impl StdinSignal {
         let mut guard = self.inner.lock();
 
         let (a, b) = guard.pop_data(len);
-        Ok(a.len() + b.len())
+        let ret = a.len() + b.len();
+        self.consumed(guard);
+        Ok(ret)
     }
 
     #[instrument]
@@ -285,7 +351,9 @@ impl StdinSignal {
             }
         };
 
-        Ok(a.len() + b.len())
+        let ret = a.len() + b.len();
+        self.consumed(guard);
+        Ok(ret)
     }
 
     #[instrument]
@@ -318,12 +386,85 @@ impl StdinProvider {
         self.0.cond.notify_one();
     }
 
+    /// Writes as much data as buffer capacity allows, without blocking.
+    ///
+    /// Returns number of bytes accepted, 0 if buffer is full or closed.
+    /// If some data is refused, drain handler is called once buffer is drained.
+    #[instrument(skip(buf), fields(buf.len = buf.len()))]
+    pub fn try_write(&self, buf: &[u8]) -> usize {
+        let mut guard = self.0.inner.lock();
+        if guard.closed {
+            return 0;
+        }
+        let n = guard.cap.saturating_sub(guard.len()).min(buf.len());
+        if n < buf.len() {
+            guard.drain_pending = true;
+        }
+        if n > 0 {
+            guard.push_data(&buf[..n]);
+            guard.notify();
+            self.0.cond.notify_one();
+        }
+        n
+    }
+
+    /// Number of bytes buffered but not yet read.
+    pub fn buffered(&self) -> usize {
+        self.0.inner.lock().len()
+    }
+
+    /// Writes data, blocking while buffer is full.
+    ///
+    /// Returns number of bytes written, which may be less than `buf` if it times out.
+    /// Fails with broken pipe if closed, or times out if no data can be written.
+    #[instrument(skip(buf), fields(buf.len = buf.len()))]
+    pub fn write_block(&self, mut buf: &[u8], timeout: Option<Instant>) -> IoResult<usize> {
+        let mut t = Instant::now() + MAX_TIMEOUT;
+        if let Some(v) = timeout {
+            t = t.min(v);
+        }
+        let mut guard = self.0.inner.lock();
+        let mut n = 0;
+
+        while !buf.is_empty() {
+            if guard.closed {
+                return if n == 0 {
+                    Err(ErrorKind::BrokenPipe.into())
+                } else {
+                    Ok(n)
+                };
+            }
+
+            let i = guard.cap.saturating_sub(guard.len()).min(buf.len());
+            if i == 0 {
+                if self.0.write_cond.wait_until(&mut guard, t).timed_out() {
+                    return if n == 0 {
+                        Err(ErrorKind::TimedOut.into())
+                    } else {
+                        Ok(n)
+                    };
+                }
+                continue;
+            }
+
+            let (a, b) = buf.split_at(i);
+            guard.push_data(a);
+            guard.notify();
+            self.0.cond.notify_one();
+            buf = b;
+            n += i;
+        }
+
+        Ok(n)
+    }
+
     #[instrument]
     pub fn close(&self) {
         let mut guard = self.0.inner.lock();
         guard.closed = true;
         guard.notify();
         self.0.cond.notify_one();
+        self.0.write_cond.notify_all();
     }
 }
 
@@ -353,6 +494,134 @@ impl StdinSignalPollable {
     }
 }
 
+/// Bidirectional stream passed to guest as extra file descriptor.
+///
+/// Guest reads data written by [`PipeHost`], and guest writes are passed to output.
+/// Dropping it (eg. guest closes descriptor) calls close callback.
+pub struct Pipe {
+    input: Arc<StdinSignal>,
+    output: PipeOutput,
+    on_close: Option<Box<dyn FnOnce() + Send + Sync>>,
+}
+
+#[derive(Debug)]
+enum PipeOutput {
+    Host(Arc<dyn Send + Sync + HostStdout>),
+    /// Input of the other end of [`Pipe::pair`].
+    Linked(StdinProvider),
+}
+
+impl Debug for Pipe {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("Pipe")
+            .field("input", &self.input)
+            .field("output", &self.output)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        if let Some(f) = self.on_close.take() {
+            f();
+        }
+    }
+}
+
+/// Host side of [`Pipe`].
+#[derive(Debug)]
+pub struct PipeHost(StdinProvider);
+
+impl Pipe {
+    /// Creates new pipe.
+    ///
+    /// - `output` : Receives guest writes.
+    /// - `request` : Called when guest is waiting for data.
+    /// - `on_close` : Called when pipe is dropped.
+    pub fn new(
+        output: Arc<dyn Send + Sync + HostStdout>,
+        request: Box<dyn Fn() + Send + Sync>,
+        on_close: Option<Box<dyn FnOnce() + Send + Sync>>,
+    ) -> (Self, PipeHost) {
+        let (input, provider) = StdinSignal::new(request);
+        (
+            Self {
+                input,
+                output: PipeOutput::Host(output),
+                on_close,
+            },
+            PipeHost(provider),
+        )
+    }
+
+    /// Creates pair of connected pipes, each with buffer of `cap` bytes.
+    ///
+    /// Writes to one end are readable from the other. Writes block while buffer is full.
+    /// Dropping either end closes both directions.
+    pub fn pair(cap: usize) -> (Self, Self) {
+        let (a, a_provider) = StdinSignal::with_capacity(Box::new(|| ()), cap);
+        let (b, b_provider) = StdinSignal::with_capacity(Box::new(|| ()), cap);
+        let close = |a: &StdinProvider, b: &StdinProvider| -> Box<dyn FnOnce() + Send + Sync> {
+            let (a, b) = (a.dup(), b.dup());
+            Box::new(move || {
+                a.close();
+                b.close();
+            })
+        };
+
+        (
+            Self {
+                input: a,
+                output: PipeOutput::Linked(b_provider.dup()),
+                on_close: Some(close(&a_provider, &b_provider)),
+            },
+            Self {
+                input: b,
+                output: PipeOutput::Linked(a_provider.dup()),
+                on_close: Some(close(&a_provider, &b_provider)),
+            },
+        )
+    }
+
+    pub(crate) fn input(&self) -> &Arc<StdinSignal> {
+        &self.input
+    }
+
+    /// Returns `true` if host side is closed.
+    pub fn is_closed(&self) -> bool {
+        self.input.inner.lock().closed
+    }
+
+    /// Writes guest data. Fails with broken pipe if host side is closed.
+    ///
+    /// Returns number of bytes written. Only linked pipe may write less than `buf`.
+    pub fn write(&self, buf: &[u8], timeout: Option<Instant>) -> IoResult<usize> {
+        if self.is_closed() {
+            return Err(ErrorKind::BrokenPipe.into());
+        }
+        match &self.output {
+            PipeOutput::Host(v) => {
+                v.write(buf)?;
+                v.flush()?;
+                Ok(buf.len())
+            }
+            PipeOutput::Linked(v) => v.write_block(buf, timeout),
+        }
+    }
+}
+
+impl PipeHost {
+    /// Sends data to guest. Does nothing if pipe is closed.
+    pub fn write(&self, buf: &[u8]) {
+        self.0.write(buf)
+    }
+
+    /// Closes pipe. Guest reads get end of file, and guest writes fail with broken pipe.
+    pub fn close(&self) {
+        self.0.close()
+    }
+}
+
 pub trait HostStdin: Debug {
     fn read(&self, len: usize) -> IoResult<Vec<u8>>;
     fn read_block(&self, len: usize, timeout: Option<Instant>) -> IoResult<Vec<u8>>;
@@ -650,6 +919,112 @@ impl StdoutCbLineBufferedInner {
     }
 }
 
+pub type StdoutCbLinesFn = Box<dyn Send + Sync + FnMut(Vec<String>)>;
+
+/// Line-buffered stdout that emits lines in batch.
+///
+/// Batch is emitted at most once per interval, or when it's size exceeds limit.
+/// There is no timer, interval is only checked on write and flush.
+/// Remaining data is emitted when dropped.
+#[derive(Debug)]
+pub struct StdoutCbLineCoalesced(Mutex<StdoutCbLineCoalescedInner>);
+
+struct StdoutCbLineCoalescedInner {
+    buf: LineBuffer,
+    lines: Vec<String>,
+    size: usize,
+    last: Option<Instant>,
+    interval: Duration,
+    max_size: usize,
+    cb: StdoutCbLinesFn,
+}
+
+impl Debug for StdoutCbLineCoalescedInner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("StdoutCbLineCoalescedInner")
+            .field("buf", &self.buf)
+            .field("lines", &self.lines.len())
+            .field("size", &self.size)
+            .field("interval", &self.interval)
+            .field("max_size", &self.max_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StdoutCbLineCoalesced {
+    pub fn new(cb: StdoutCbLinesFn, interval: Duration, max_size: usize) -> Self {
+        Self(Mutex::new(StdoutCbLineCoalescedInner {
+            buf: Default::default(),
+            lines: Vec::new(),
+            size: 0,
+            last: None,
+            interval,
+            max_size,
+            cb,
+        }))
+    }
+}
+
+impl HostStdout for StdoutCbLineCoalesced {
+    #[instrument(skip(buf), fields(buf.len = buf.len()))]
+    fn write(&self, buf: &[u8]) -> IoResult<()> {
+        let mut g = self.0.lock();
+        let StdoutCbLineCoalescedInner {
+            buf: lb,
+            lines,
+            size,
+            ..
+        } = &mut *g;
+        lb.write(
+            |s| {
+                lines.push(s.to_string());
+                *size += s.len();
+                Ok::<_, IoError>(())
+            },
+            buf,
+        )?;
+        g.tick();
+        Ok(())
+    }
+
+    #[instrument]
+    fn flush(&self) -> IoResult<()> {
+        self.0.lock().tick();
+        Ok(())
+    }
+}
+
+impl Drop for StdoutCbLineCoalesced {
+    fn drop(&mut self) {
+        let this = self.0.get_mut();
+        let StdoutCbLineCoalescedInner { buf, lines, .. } = this;
+        let _ = buf.flush(|s| {
+            lines.push(s.to_string());
+            Ok::<_, IoError>(())
+        });
+        this.emit();
+    }
+}
+
+impl StdoutCbLineCoalescedInner {
+    fn emit(&mut self) {
+        if self.lines.is_empty() {
+            return;
+        }
+        self.size = 0;
+        self.last = Some(Instant::now());
+        (self.cb)(take(&mut self.lines));
+    }
+
+    fn tick(&mut self) {
+        if self.size >= self.max_size
+            || !matches!(self.last, Some(t) if t.elapsed() < self.interval)
+        {
+            self.emit();
+        }
+    }
+}
+
 pub type StdoutCbBlockFn = Box<dyn Send + Sync + FnMut(&[u8])>;
 
 #[derive(Debug)]
@@ -823,6 +1198,57 @@ impl HostStdout for StderrBypass {
     }
 }
 
+/// Accumulates output in memory, up to a byte cap.
+///
+/// Bytes past the cap are dropped and overflow flag is set.
+#[derive(Debug)]
+pub struct StdoutCapture(Mutex<StdoutCaptureInner>);
+
+#[derive(Debug)]
+struct StdoutCaptureInner {
+    buf: Vec<u8>,
+    max: usize,
+    overflow: bool,
+}
+
+impl StdoutCapture {
+    pub fn new(max: usize) -> Self {
+        Self(Mutex::new(StdoutCaptureInner {
+            buf: Vec::new(),
+            max,
+            overflow: false,
+        }))
+    }
+
+    /// Returns `true` if any bytes has been dropped since last take.
+    pub fn is_overflow(&self) -> bool {
+        self.0.lock().overflow
+    }
+
+    /// Takes captured bytes and resets overflow flag.
+    pub fn take(&self) -> (Vec<u8>, bool) {
+        let mut this = self.0.lock();
+        (take(&mut this.buf), replace(&mut this.overflow, false))
+    }
+}
+
+impl HostStdout for StdoutCapture {
+    #[instrument(skip(buf), fields(buf.len = buf.len()))]
+    fn write(&self, buf: &[u8]) -> IoResult<()> {
+        let this = &mut *self.0.lock();
+        let n = this.max.saturating_sub(this.buf.len()).min(buf.len());
+        this.buf.extend_from_slice(&buf[..n]);
+        if n < buf.len() {
+            this.overflow = true;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
 pub type NullPollable = crate::NullPollable;
 
 #[cfg(test)]
@@ -853,7 +1279,7 @@ mod tests {
     #[test]
     fn test_stdin_inner_rw() {
         fn f(v: Vec<Vec<u8>>) {
-            let mut inner = StdinInner::new();
+            let mut inner = StdinInner::new(usize::MAX);
 
             for src in v {
                 assert_eq!(inner.len(), 0);
@@ -874,7 +1300,7 @@ mod tests {
     #[test]
     fn test_stdin_inner_rw_uneq() {
         fn f(v: Vec<PushPop>) {
-            let mut inner = StdinInner::new();
+            let mut inner = StdinInner::new(usize::MAX);
             let mut buf = Vec::new();
 
             for i in v {
@@ -930,4 +1356,146 @@ mod tests {
 
         proptest!(|((seg, s) in "([^\n]{0,64}\n?){0,16}".prop_flat_map(|s| (btree_set(0..=s.len(), 0..16), Just(s))))| f(s, seg));
     }
+
+    #[test]
+    fn test_line_coalesced() {
+        let out = Arc::new(Mutex::new(Vec::<Vec<String>>::new()));
+        let v = StdoutCbLineCoalesced::new(
+            Box::new({
+                let out = out.clone();
+                move |v| out.lock().push(v)
+            }),
+            Duration::from_secs(3600),
+            16,
+        );
+
+        // First line is emitted immediately.
+        v.write(b"a\nb").unwrap();
+        assert_eq!(*out.lock(), [vec!["a\n"]]);
+
+        // Subsequent lines are batched until interval elapsed.
+        v.write(b"\nc\n").unwrap();
+        v.flush().unwrap();
+        assert_eq!(out.lock().len(), 1);
+
+        // Batch is emitted when size limit is hit.
+        v.write(b"0123456789abcdef\n").unwrap();
+        assert_eq!(out.lock()[1], ["b\n", "c\n", "0123456789abcdef\n"]);
+
+        // Remaining data is emitted on drop.
+        v.write(b"d\ne").unwrap();
+        assert_eq!(out.lock().len(), 2);
+        drop(v);
+        assert_eq!(out.lock()[2], ["d\n", "e"]);
+    }
+
+    #[test]
+    fn test_pipe_pair() {
+        let (a, b) = Pipe::pair(4);
+        let t = || Some(Instant::now() + Duration::from_millis(10));
+
+        // Buffer is bounded, so write is partial and then times out.
+        assert_eq!(a.write(b"hello", t()).unwrap(), 4);
+        assert_eq!(a.write(b"o", t()).unwrap_err().kind(), ErrorKind::TimedOut);
+        assert_eq!(b.input().read(8).unwrap(), b"hell");
+        assert!(!b.input().is_ready());
+
+        // Blocked writer is woken by reader.
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut buf = &b"world!"[..];
+                while !buf.is_empty() {
+                    buf = &buf[a.write(buf, None).unwrap()..];
+                }
+            });
+            let mut v = Vec::new();
+            while v.len() < 6 {
+                v.extend(b.input().read_block(6 - v.len(), None).unwrap());
+            }
+            assert_eq!(v, b"world!");
+        });
+
+        b.write(b"back", t()).unwrap();
+        assert_eq!(a.input().read(4).unwrap(), b"back");
+
+        // Dropping one end closes both directions.
+        drop(a);
+        assert!(b.is_closed());
+        assert_eq!(
+            b.write(b"x", t()).unwrap_err().kind(),
+            ErrorKind::BrokenPipe
+        );
+        assert!(b.input().read_block(1, t()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stdin_limit() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (input, provider) =
+            StdinSignal::with_limit(Box::new(|| ()), 8, Box::new(move || tx.send(()).unwrap()));
+
+        // Writes are capped, refused data is never buffered.
+        assert_eq!(provider.try_write(b"0123456789"), 8);
+        assert_eq!(provider.buffered(), 8);
+        assert_eq!(provider.try_write(b"x"), 0);
+
+        // Any kind of read counts toward draining.
+        assert_eq!(input.read(3).unwrap(), b"012");
+        assert!(rx.try_recv().is_err());
+        assert_eq!(input.skip(1).unwrap(), 1);
+        rx.try_recv().unwrap();
+        assert_eq!(provider.buffered(), 4);
+
+        // Drain handler is called once per refusal.
+        assert_eq!(input.read(1).unwrap(), b"4");
+        assert!(rx.try_recv().is_err());
+        assert_eq!(provider.try_write(b"abcdef"), 5);
+        assert_eq!(input.skip_block(4, None).unwrap(), 4);
+        rx.try_recv().unwrap();
+        assert_eq!(input.read_block(8, None).unwrap(), b"bcde");
+        assert!(rx.try_recv().is_err());
+
+        provider.close();
+        assert_eq!(provider.try_write(b"x"), 0);
+    }
+
+    #[test]
+    fn test_stdin_flow_control() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (input, provider) = StdinSignal::with_limit(
+            Box::new(|| ()),
+            64,
+            Box::new(move || {
+                let _ = tx.send(());
+            }),
+        );
+        let data = (0..100_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+        std::thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                let mut v = Vec::new();
+                loop {
+                    match input.read_block(17, None) {
+                        Ok(b) if b.is_empty() => break v,
+                        Ok(b) => v.extend(b),
+                        Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+                        Err(e) => panic!("{e}"),
+                    }
+                }
+            });
+
+            let mut buf = &data[..];
+            while !buf.is_empty() {
+                let n = provider.try_write(buf);
+                assert!(provider.buffered() <= 64);
+                buf = &buf[n..];
+                if !buf.is_empty() {
+                    // Producer resumes only after being notified.
+                    rx.recv_timeout(Duration::from_secs(10)).unwrap();
+                }
+            }
+            provider.close();
+            assert_eq!(consumer.join().unwrap(), data);
+        });
+    }
 }
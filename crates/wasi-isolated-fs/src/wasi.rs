@@ -1,5 +1,4 @@
 use std::io::{Error as IoError, ErrorKind};
-use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 use anyhow::{Error as AnyError, Result as AnyResult};
@@ -16,10 +15,14 @@ use tracing::{instrument, Level};
 use wasmtime::component::Resource;
 
 use crate::bindings::wasi;
-use crate::context::{try_iso_fs, Stdin, WasiContext};
+use crate::context::{try_iso_fs, HostFiles, Stdin, WasiContext};
 use crate::fs_host::{CapWrapper as HostCapWrapper, Descriptor};
 use crate::fs_isolated::{AccessMode, CreateParams, OpenMode};
 use crate::items::Item;
+use crate::nondet::{
+    tap_bytes, tap_duration, tap_fill, tap_u64, tap_value, SOURCE_CLOCK_MONOTONIC,
+    SOURCE_CLOCK_WALL, SOURCE_HOST_FS_READ, SOURCE_RANDOM_INSECURE, SOURCE_RANDOM_SECURE,
+};
 use crate::poll::PollController;
 use crate::stdio::NullStdio;
 use crate::{errors, items, NullPollable, EMPTY_BUF};
@@ -97,7 +100,11 @@ impl wasi::io::poll::Host for WasiContext {
                     match i {
                         items::Poll::NullPoll(_) => (),
                         items::Poll::StdinPoll(v) => c.add_signal(&v.0),
-                        items::Poll::ClockPoll(v) => c.set_instant(v.until),
+                        items::Poll::ClockPoll(v) => {
+                            if let Some(t) = v.until {
+                                c.set_instant(t)
+                            }
+                        }
                     }
                 }
 
@@ -115,13 +122,15 @@ impl wasi::io::poll::Host for WasiContext {
 impl wasi::io::error::HostError for WasiContext {
     #[instrument(skip(self), ret, err)]
     fn to_debug_string(&mut self, res: Resource<wasi::io::error::Error>) -> AnyResult<String> {
-        // No way to construct stream error
-        Err(errors::InvalidResourceIDError::from_iter([res.rep()]).into())
+        match self.items.get_item_ref(&res)? {
+            items::IOErrorR::FSError(v) => Ok(v.to_string()),
+        }
     }
 
     #[instrument(skip(self), err)]
     fn drop(&mut self, res: Resource<wasi::io::error::Error>) -> AnyResult<()> {
-        Err(errors::InvalidResourceIDError::from_iter([res.rep()]).into())
+        self.items.get_item(res)?;
+        Ok(())
     }
 }
 
@@ -138,7 +147,9 @@ impl wasi::io::streams::HostInputStream for WasiContext {
         Ok(match self.items.get_item(res)? {
             items::IOStream::NullStdio(_) => Vec::new(),
             items::IOStream::IsoFSAccess(mut v) => v.read(len)?,
-            items::IOStream::HostFSStream(mut v) => v.read(len)?,
+            items::IOStream::HostFSStream(mut v) => {
+                tap_bytes(self.nondet.as_deref(), SOURCE_HOST_FS_READ, || v.read(len))?
+            }
             items::IOStream::StdinSignal(v) => v.read(len)?,
             items::IOStream::HostStdin(v) => v.read(len)?,
             _ => return Err(ErrorKind::InvalidInput.into()),
@@ -155,7 +166,9 @@ impl wasi::io::streams::HostInputStream for WasiContext {
         Ok(match self.items.get_item(res)? {
             items::IOStream::NullStdio(_) => Vec::new(),
             items::IOStream::IsoFSAccess(mut v) => v.read(len)?,
-            items::IOStream::HostFSStream(mut v) => v.read(len)?,
+            items::IOStream::HostFSStream(mut v) => {
+                tap_bytes(self.nondet.as_deref(), SOURCE_HOST_FS_READ, || v.read(len))?
+            }
             items::IOStream::StdinSignal(v) => v.read_block(len, self.timeout)?,
             items::IOStream::HostStdin(v) => v.read_block(len, self.timeout)?,
             _ => return Err(ErrorKind::InvalidInput.into()),
@@ -388,7 +401,9 @@ impl wasi::io::streams::HostOutputStream for WasiContext {
 
             let b = match &mut input {
                 items::IOStream::IsoFSAccess(v) => v.read(i)?,
-                items::IOStream::HostFSStream(v) => v.read(i)?,
+                items::IOStream::HostFSStream(v) => {
+                    tap_bytes(self.nondet.as_deref(), SOURCE_HOST_FS_READ, || v.read(i))?
+                }
                 items::IOStream::StdinSignal(v) => v.read(i)?,
                 items::IOStream::HostStdin(v) => v.read(i)?,
                 _ => return Err(ErrorKind::InvalidInput.into()),
@@ -471,7 +486,12 @@ impl wasi::io::streams::Host for WasiContext {
         &mut self,
         e: errors::StreamError,
     ) -> AnyResult<wasi::io::streams::StreamError> {
-        e.into()
+        match e.fs_error_code() {
+            Some(v) => Ok(wasi::io::streams::StreamError::LastOperationFailed(
+                self.register(Item::FSError(v))?,
+            )),
+            None => e.into(),
+        }
     }
 }
 
@@ -721,12 +741,15 @@ impl wasi::filesystem::types::HostDescriptor for WasiContext {
             }
             items::Desc::HostFSDesc(v) => {
                 let v = v.read()?.file()?;
-                let mut ret = vec![0; len];
-                let i = HostCapWrapper::read_at(v, &mut ret, off)?;
-                if !ret.is_empty() && i == 0 {
+                let ret = tap_bytes(self.nondet.as_deref(), SOURCE_HOST_FS_READ, || {
+                    let mut ret = vec![0; len];
+                    let i = HostCapWrapper::read_at(v, &mut ret, off)?;
+                    ret.truncate(i);
+                    Ok::<_, errors::StreamError>(ret)
+                })?;
+                if len != 0 && ret.is_empty() {
                     (Vec::new(), true)
                 } else {
-                    ret.truncate(i);
                     (ret, false)
                 }
             }
@@ -817,15 +840,17 @@ impl wasi::filesystem::types::HostDescriptor for WasiContext {
         path: String,
     ) -> Result<wasi::filesystem::types::DescriptorStat, errors::StreamError> {
         match self.items.get_item(res)? {
-            items::Desc::IsoFSNode(v) => v
-                .open(
-                    try_iso_fs(&self.iso_fs)?,
-                    &Utf8PathBuf::from(path),
-                    path_flags.contains(wasi::filesystem::types::PathFlags::SYMLINK_FOLLOW),
-                    None,
-                    AccessMode::RW,
-                )?
-                .stat(),
+            items::Desc::IsoFSNode(v) => {
+                let controller = try_iso_fs(&self.iso_fs)?;
+                let path = Utf8PathBuf::from(path);
+                let symlink =
+                    path_flags.contains(wasi::filesystem::types::PathFlags::SYMLINK_FOLLOW);
+                if let Some(f) = self.host_files.resolve(controller, &v, &path, symlink) {
+                    return Ok(meta_to_stat(f.file()?.metadata()?));
+                }
+                v.open(controller, &path, symlink, None, AccessMode::RW)?
+                    .stat()
+            }
             items::Desc::HostFSDesc(v) => {
                 let v = v.dir()?;
                 Ok(meta_to_stat(if path_flags
@@ -924,6 +949,13 @@ impl wasi::filesystem::types::HostDescriptor for WasiContext {
 
         let ret: Item = match self.items.get_item(res)? {
             items::Desc::IsoFSNode(v) => {
+                let controller = try_iso_fs(&self.iso_fs)?;
+                let path = Utf8PathBuf::from(path);
+                if let Some(f) = self.host_files.resolve(controller, &v, &path, symlink) {
+                    let f = HostFiles::open(f, access, create && exclusive, is_dir, is_truncate)?;
+                    return Ok(self.register(Box::new(f))?);
+                }
+
                 let create = if create {
                     access = access | AccessMode::W;
                     Some(CreateParams {
@@ -934,15 +966,8 @@ impl wasi::filesystem::types::HostDescriptor for WasiContext {
                     None
                 };
 
-                let controller = try_iso_fs(&self.iso_fs)?;
                 let v = v
-                    .open(
-                        controller,
-                        &Utf8PathBuf::from(path),
-                        symlink,
-                        create,
-                        access,
-                    )?
+                    .open(controller, &path, symlink, create, access)?
                     .follow_symlink(controller)?;
                 if is_dir && !v.node().is_dir() {
                     return Err(ErrorKind::NotADirectory.into());
@@ -983,15 +1008,17 @@ impl wasi::filesystem::types::HostDescriptor for WasiContext {
                     opts.maybe_dir(true);
                 }
 
-                let v = v.dir()?.open_with(path, &opts)?;
-                let v = if v.metadata()?.is_dir() {
-                    Descriptor::Dir(CapDir::from_std_file(v.into_std()))
+                let f = v
+                    .dir()?
+                    .open_with(v.resolve_path(std::path::Path::new(&path))?, &opts)?;
+                let f = if f.metadata()?.is_dir() {
+                    Descriptor::Dir(CapDir::from_std_file(f.into_std()))
                 } else if is_dir {
                     return Err(ErrorKind::NotADirectory.into());
                 } else {
-                    Descriptor::File(v)
+                    Descriptor::File(f)
                 };
-                Box::new(HostCapWrapper::new(Arc::new(v), access)).into()
+                Box::new(v.child(f, access)).into()
             }
         };
         Ok(self.register(ret)?)
@@ -1240,8 +1267,9 @@ impl wasi::filesystem::types::Host for WasiContext {
         &mut self,
         res: Resource<wasi::filesystem::types::Error>,
     ) -> AnyResult<Option<wasi::filesystem::types::ErrorCode>> {
-        // No way to construct stream error
-        Err(errors::InvalidResourceIDError::from_iter([res.rep()]).into())
+        match self.items.get_item_ref(&res)? {
+            items::IOErrorR::FSError(v) => Ok(Some(*v)),
+        }
     }
 
     #[instrument(level = Level::DEBUG, skip(self), err)]
@@ -1277,7 +1305,9 @@ impl wasi::filesystem::preopens::Host for WasiContext {
 impl wasi::clocks::monotonic_clock::Host for WasiContext {
     #[instrument(skip(self), err)]
     fn now(&mut self) -> AnyResult<wasi::clocks::monotonic_clock::Instant> {
-        Ok(self.clock.now())
+        tap_u64(self.nondet.as_deref(), SOURCE_CLOCK_MONOTONIC, || {
+            Ok(self.clock.now())
+        })
     }
 
     #[instrument(skip(self), err)]
@@ -1290,7 +1320,7 @@ impl wasi::clocks::monotonic_clock::Host for WasiContext {
         &mut self,
         when: wasi::clocks::monotonic_clock::Instant,
     ) -> AnyResult<Resource<wasi::clocks::monotonic_clock::Pollable>> {
-        let ret = Item::from(Box::new(self.clock.poll_until(when)?));
+        let ret = Item::from(Box::new(self.clock.poll_until(when)));
         self.register(ret)
     }
 
@@ -1299,7 +1329,7 @@ impl wasi::clocks::monotonic_clock::Host for WasiContext {
         &mut self,
         when: wasi::clocks::monotonic_clock::Duration,
     ) -> AnyResult<Resource<wasi::clocks::monotonic_clock::Pollable>> {
-        let ret = Item::from(Box::new(self.clock.poll_for(when)?));
+        let ret = Item::from(Box::new(self.clock.poll_for(when)));
         self.register(ret)
     }
 }
@@ -1307,9 +1337,13 @@ impl wasi::clocks::monotonic_clock::Host for WasiContext {
 impl wasi::clocks::wall_clock::Host for WasiContext {
     #[instrument(skip(self), err)]
     fn now(&mut self) -> AnyResult<wasi::clocks::wall_clock::Datetime> {
-        let t = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or(Duration::ZERO);
+        let t = tap_duration(self.nondet.as_deref(), SOURCE_CLOCK_WALL, || {
+            Ok::<_, AnyError>(
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or(Duration::ZERO),
+            )
+        })?;
         Ok(wasi::clocks::wall_clock::Datetime {
             seconds: t.as_secs(),
             nanoseconds: t.subsec_nanos(),
@@ -1344,20 +1378,37 @@ impl wasi::random::insecure::Host for WasiContext {
     #[instrument(skip(self), err)]
     fn get_insecure_random_bytes(&mut self, len: u64) -> AnyResult<Vec<u8>> {
         let mut ret = vec![0u8; len.try_into()?];
-        self.insecure_rng.fill(&mut ret[..]);
+        tap_fill(
+            self.nondet.as_deref(),
+            SOURCE_RANDOM_INSECURE,
+            &mut ret,
+            |b| {
+                self.insecure_rng.fill(b);
+                Ok::<_, AnyError>(())
+            },
+        )?;
         Ok(ret)
     }
 
     #[instrument(skip(self), err)]
     fn get_insecure_random_u64(&mut self) -> AnyResult<u64> {
-        Ok(self.insecure_rng.random())
+        tap_u64(self.nondet.as_deref(), SOURCE_RANDOM_INSECURE, || {
+            Ok(self.insecure_rng.random())
+        })
     }
 }
 
 impl wasi::random::insecure_seed::Host for WasiContext {
     #[instrument(skip(self), err)]
     fn insecure_seed(&mut self) -> AnyResult<(u64, u64)> {
-        Ok(self.insecure_rng.random())
+        tap_value(
+            self.nondet.as_deref(),
+            SOURCE_RANDOM_INSECURE,
+            || Ok(self.insecure_rng.random::<u128>()),
+            u128::to_le_bytes,
+            u128::from_le_bytes,
+        )
+        .map(|v| (v as u64, (v >> 64) as u64))
     }
 }
 
@@ -1365,13 +1416,23 @@ impl wasi::random::random::Host for WasiContext {
     #[instrument(skip(self), err)]
     fn get_random_bytes(&mut self, len: u64) -> AnyResult<Vec<u8>> {
         let mut ret = vec![0u8; len.try_into()?];
-        self.secure_rng.fill(&mut ret[..]);
+        tap_fill(
+            self.nondet.as_deref(),
+            SOURCE_RANDOM_SECURE,
+            &mut ret,
+            |b| {
+                self.secure_rng.fill(b);
+                Ok::<_, AnyError>(())
+            },
+        )?;
         Ok(ret)
     }
 
     #[instrument(skip(self), err)]
     fn get_random_u64(&mut self) -> AnyResult<u64> {
-        Ok(self.secure_rng.random())
+        tap_u64(self.nondet.as_deref(), SOURCE_RANDOM_SECURE, || {
+            Ok(self.secure_rng.random())
+        })
     }
 }
 
@@ -2030,3 +2091,35 @@ impl wasi::cli::exit::Host for WasiContext {
         Err(errors::ProcessExit::new(code.into()).into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasi::filesystem::preopens::Host as _;
+    use wasi::filesystem::types::HostDescriptor as _;
+
+    #[test]
+    fn test_rename_at() {
+        crate::fs_isolated::tests::check_rename(
+            |e| e,
+            |controller, src, dst| {
+                let mut builder = WasiContext::builder();
+                builder.isolated_fs_controller(controller).unwrap();
+                builder
+                    .preopen_dir_isolated("/".into(), "/".into())
+                    .unwrap();
+                let mut ctx = builder.build().unwrap();
+                let (dir, _) = ctx.get_directories().unwrap().pop().unwrap();
+
+                let ret = ctx.rename_at(
+                    Resource::new_borrow(dir.rep()),
+                    src.into(),
+                    Resource::new_borrow(dir.rep()),
+                    dst.into(),
+                );
+                ret.map_err(|e| <Result<wasi::filesystem::types::ErrorCode, _>>::from(e).unwrap())
+            },
+        );
+    }
+}
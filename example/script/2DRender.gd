@@ -12,6 +12,13 @@ signal message_emitted(msg: String)
 @onready var _img := Image.new()
 @onready var _lbl: Label = $UI/Root/Panel/VBox/Label
 
+# ABI of ExportState expected by this script.
+const ABI := {
+	"major": 1,
+	"minor": 0,
+	"layout": "width:0:4\nheight:4:4\ncolors_ptr:8:4\ncolors_cnt:12:4\n",
+}
+
 var instance: WasmInstance = null
 var acc_delta := 0.0
 var task_id = null
@@ -69,6 +76,10 @@ func __start():
 	if !__instantiate():
 		return
 
+	if !__check_abi():
+		instance = null
+		return
+
 	var ret = instance.call_wasm(&"config", [])
 	if ret == null:
 		__log("Failed to call config")
@@ -124,14 +135,22 @@ func __update(delta: float) -> void:
 	var width: int = instance.get_32(p)
 	var height: int = instance.get_32(p + 4)
 	p = instance.get_32(p + 8)
-	var data = instance.memory_read(p, width * height * 4)
+
+	# Blit straight from guest memory into image. Previously it was
+	# memory_read() into PackedByteArray then Image.set_data(), which copies
+	# pixel data twice and passes it through GDScript. Blit time is shown
+	# next to WASM time, compare it on larger canvases.
+	var b := _img.get_width() == width and _img.get_height() == height
+	var ok := instance.blit_to_image(p, width, height, "RGBA8", _img)
+	var blit_end := Time.get_ticks_usec()
 
 	var c := func ():
-		_lbl.text = "WASM Time: %.3f ms" % ((end - start) / 1e3)
+		_lbl.text = "WASM Time: %.3f ms\nBlit Time: %.3f ms" % [
+			(end - start) / 1e3,
+			(blit_end - end) / 1e3,
+		]
 
-		if len(data) != 0:
-			var b := _img.get_width() == width and _img.get_height() == height
-			_img.set_data(width, height, false, Image.FORMAT_RGBA8, data)
+		if ok:
 			if b:
 				_tex.update(_img)
 			else:
@@ -152,6 +171,26 @@ func __ui_input(event: InputEvent):
 		p -= $Sprite.get_rect().position
 		instance.call_wasm("click", [p.x, p.y, event.button_index - 1])
 
+func __check_abi() -> bool:
+	var abi := instance.check_abi(ABI)
+	if abi.is_empty():
+		return false
+	match abi["status"]:
+		"missing":
+			__log("Module does not report ABI version, skipping check")
+			return true
+		"major_mismatch":
+			__log("Module ABI version %d.%d is incompatible with %d.%d, please rebuild it" % [
+				abi["version"][0], abi["version"][1], ABI["major"], ABI["minor"],
+			])
+			return false
+	var layout = abi["layout"]
+	if layout != null and not abi["compatible"]:
+		__log("Module ExportState layout changed (removed: %s, moved: %s), please rebuild it" % [
+			layout["removed"], layout["moved"],
+		])
+	return abi["compatible"]
+
 func __log(msg: String) -> void:
 	call_thread_safe(&"emit_signal", &"message_emitted", msg)
 
@@ -11,6 +11,13 @@ signal message_emitted(msg: String)
 @onready var _mesh := ArrayMesh.new()
 @onready var _lbl: Label = $UI/Root/Panel/VBox/Label
 
+# ABI of ExportState expected by this script.
+const ABI := {
+	"major": 1,
+	"minor": 0,
+	"layout": "vertex_ptr:0:4\nvertex_cnt:4:4\nnormal_ptr:8:4\nnormal_cnt:12:4\ntangent_ptr:16:4\ntangent_cnt:20:4\nuv_ptr:24:4\nuv_cnt:28:4\ncolor_ptr:32:4\ncolor_cnt:36:4\nindex_ptr:40:4\nindex_cnt:44:4\n",
+}
+
 var instance: WasmInstance = null
 var acc_delta := 0.0
 var task_id = null
@@ -67,6 +74,10 @@ func __start():
 	if !__instantiate():
 		return
 
+	if !__check_abi():
+		instance = null
+		return
+
 	var ret = instance.call_wasm(&"config", [])
 	if ret == null:
 		__log("Failed to call config")
@@ -183,6 +194,26 @@ func __ui_input(event: InputEvent) -> void:
 			],
 		)
 
+func __check_abi() -> bool:
+	var abi := instance.check_abi(ABI)
+	if abi.is_empty():
+		return false
+	match abi["status"]:
+		"missing":
+			__log("Module does not report ABI version, skipping check")
+			return true
+		"major_mismatch":
+			__log("Module ABI version %d.%d is incompatible with %d.%d, please rebuild it" % [
+				abi["version"][0], abi["version"][1], ABI["major"], ABI["minor"],
+			])
+			return false
+	var layout = abi["layout"]
+	if layout != null and not abi["compatible"]:
+		__log("Module ExportState layout changed (removed: %s, moved: %s), please rebuild it" % [
+			layout["removed"], layout["moved"],
+		])
+	return abi["compatible"]
+
 func __log(msg: String) -> void:
 	call_thread_safe(&"emit_signal", &"message_emitted", msg)
 
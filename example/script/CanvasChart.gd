@@ -0,0 +1,33 @@
+extends Node2D
+
+@warning_ignore("unused_signal")
+signal message_emitted(msg: String)
+
+@export var wasm_file: WasmModule
+
+var script_like: WasmScriptLike = null
+
+func _ready():
+	script_like = WasmScriptLike.new()
+	script_like.error_happened.connect(__log)
+	script_like = script_like.initialize(wasm_file, {
+		"epoch.enable": true,
+		"epoch.timeout": 1.0,
+	})
+
+	if script_like == null:
+		__log("Failed to instantiate module")
+
+func _process(_delta):
+	if script_like == null:
+		return
+
+	script_like.call_wasm([])
+	script_like.update_canvas(self)
+
+func _draw():
+	if script_like != null:
+		script_like.draw_canvas(self)
+
+func __log(msg: String) -> void:
+	call_thread_safe(&"emit_signal", &"message_emitted", msg)
@@ -0,0 +1,71 @@
+extends Node
+
+@warning_ignore("unused_signal")
+signal message_emitted(msg: String)
+
+@export var wasm_file: WasmModule
+
+# Expected value read by guest, for [top-level, nested] mutation.
+const EXPECTED := {
+	"reference": [2, 2],
+	"copy": [1, 1],
+	"shallow": [1, 2],
+}
+
+var data := {}
+
+func _ready():
+	for mode in EXPECTED:
+		__run(mode)
+
+func __run(mode: String) -> void:
+	var instance := wasm_file.instantiate({
+		"host": {
+			"mutate": {
+				params = [],
+				results = [],
+				callable = __mutate,
+			},
+		},
+	}, {
+		"extern.bindMode": "native",
+		"marshal.copyMode": mode,
+	})
+	if instance == null:
+		__log("Failed to instantiate module")
+		return
+	instance.error_happened.connect(__log)
+
+	__reset()
+	var top = instance.call_wasm(&"read", [data, "value"])
+	__reset()
+	var nested = instance.call_wasm(&"read_nested", [data, "inner", "value"])
+	if top == null or nested == null:
+		return
+	var ret := [top[0], nested[0]]
+	__log("Mode: %s Read: %s Expected: %s %s" % [
+		mode,
+		ret,
+		EXPECTED[mode],
+		"OK" if ret == EXPECTED[mode] else "FAIL",
+	])
+
+	# Explicit copy is independent of copy mode
+	if mode == "reference":
+		__reset()
+		var r = instance.call_wasm(&"read_duplicate", [data, "value"])
+		if r != null:
+			__log("Explicit duplicate: %s %s" % [r[0], "OK" if r[0] == 1 else "FAIL"])
+
+func __reset() -> void:
+	data = {
+		"value": 1,
+		"inner": {"value": 1},
+	}
+
+func __mutate() -> void:
+	data["value"] = 2
+	data["inner"]["value"] = 2
+
+func __log(msg: String) -> void:
+	call_thread_safe(&"emit_signal", &"message_emitted", msg)
@@ -36,6 +36,12 @@ func __start():
 	instance.error_happened.connect(__log)
 	instance.call_wasm(&"main", [])
 
+	# Converts packed (ptr, len) into String automatically.
+	instance.declare_return_convention(&"greeting", "utf8_ptr_len_i64")
+	var s = instance.call_wasm(&"greeting", [])
+	if s != null:
+		__log(s)
+
 func __write(ptr: int, sz: int) -> void:
 	var buf: PackedByteArray = instance.memory_read(ptr, sz)
 	__log(buf.get_string_from_utf8())
@@ -0,0 +1,35 @@
+extends Node2D
+
+@warning_ignore("unused_signal")
+signal message_emitted(msg: String)
+
+@export var wasm_file: WasmModule
+@export var speed := 60.0
+
+var instance: WasmInstance = null
+var direction := Vector2.RIGHT
+
+func _ready():
+	instance = wasm_file.instantiate({}, {
+		"extern.bindMode": "native",
+	})
+	if instance == null:
+		__log("Failed to instantiate module")
+		return
+	instance.error_happened.connect(__log)
+	instance.call_wasm(&"init", [])
+
+func _process(delta):
+	if instance == null:
+		return
+
+	var pos: Vector2 = $Sprite.position
+	if pos.x < 0 or pos.x > get_viewport_rect().size.x:
+		direction = -direction
+	var d := direction * speed * delta
+	var ret = instance.call_wasm(&"step", [$Sprite, d.x, d.y])
+	if ret != null and ret[0] != OK:
+		__log("Error: %s" % error_string(ret[0]))
+
+func __log(msg: String) -> void:
+	call_thread_safe(&"emit_signal", &"message_emitted", msg)
@@ -30,7 +30,10 @@ func __start():
 				callable = __recurse,
 			},
 		},
-	}, {})
+	}, {
+		# Each level adds 2 to call chain (call_wasm + host callable)
+		"reentrancy.maxDepth": 160,
+	})
 
 	if instance == null:
 		return
@@ -43,6 +46,10 @@ func __start():
 			return
 		__log("Input: %s Value: %s" % [i, ret[0]])
 
+	# Too deep, should trap instead of crashing
+	if instance.call_wasm("recurse", [100, 0]) == null:
+		__log("Recursion limit hit (depth: %s)" % instance.get_reentrancy_depth())
+
 func __recurse(n: int, a: int):
 	__log("Called host with n: %s a: %s" % [n, a])
 	if n <= 0:
@@ -111,7 +111,7 @@ func __select_exec_file(path):
 	exec_file_box.text = path
 
 func __list_tree_item(path: String, tree: TreeItem = null):
-	if wasi_ctx.file_is_exist(path, null) != 2:
+	if wasi_ctx.file_is_exist(path, null) != WasiContext.FILE_TYPE_DIR:
 		return
 	var items = wasi_ctx.file_dir_list(path, false)
 	if items == null:
@@ -140,7 +140,7 @@ func __open_file_context(mouse_position: Vector2, mouse_button_index: int):
 		return
 	var path: String = t.get_metadata(0)
 
-	var is_not_dir: bool = wasi_ctx.file_is_exist(path, null) != 2
+	var is_not_dir: bool = wasi_ctx.file_is_exist(path, null) != WasiContext.FILE_TYPE_DIR
 	file_popup.set_item_disabled(0, is_not_dir)
 	file_popup.set_item_disabled(1, is_not_dir)
 
@@ -196,7 +196,7 @@ func __open_file():
 		return
 	var path: String = t.get_metadata(0)
 
-	if wasi_ctx.file_is_exist(path, null) != 1:
+	if wasi_ctx.file_is_exist(path, null) != WasiContext.FILE_TYPE_FILE:
 		return
 
 	var content = wasi_ctx.file_read(path, 1_000_000, 0, true)
@@ -0,0 +1,44 @@
+extends Node
+
+@warning_ignore("unused_signal")
+signal message_emitted(msg: String)
+
+@export var producer_file: WasmModule
+@export var consumer_file: WasmModule
+
+func _ready():
+	var mem := WasmMemory.create(1, 1, true)
+	if mem == null:
+		__log("Failed to create memory")
+		return
+
+	var config := {
+		"memory.imports": {"env.shared_buf": mem},
+	}
+	var producer := producer_file.instantiate({}, config)
+	var consumer := consumer_file.instantiate({}, config)
+	if producer == null or consumer == null:
+		__log("Failed to instantiate module")
+		return
+	producer.error_happened.connect(__log)
+	consumer.error_happened.connect(__log)
+
+	for i in 5:
+		producer.call_wasm(&"push", [i * 10])
+	__log("Head/tail: %s" % [mem.read_struct("2I", 0)])
+
+	# GDScript can also produce into the same buffer
+	var head := mem.get_32(0)
+	mem.put_32(8 + (head % 16) * 4, 999)
+	mem.put_32(0, head + 1)
+
+	var ret := []
+	while true:
+		var r = consumer.call_wasm(&"pop", [])
+		if r == null or r[0] < 0:
+			break
+		ret.append(r[0])
+	__log("Consumed: %s %s" % [ret, "OK" if ret == [0, 10, 20, 30, 40, 999] else "FAIL"])
+
+func __log(msg: String) -> void:
+	call_thread_safe(&"emit_signal", &"message_emitted", msg)
@@ -0,0 +1,39 @@
+extends Control
+
+@warning_ignore("unused_signal")
+signal message_emitted(msg: String)
+
+@export var wasm_file: WasmModule
+
+@onready var button: Button = $Button
+@onready var label: Label = $Label
+
+var script_like: WasmScriptLike = null
+
+func _ready():
+	script_like = WasmScriptLike.new()
+	script_like.error_happened.connect(__log)
+	script_like = script_like.initialize(wasm_file, {
+		"epoch.enable": true,
+		"epoch.timeout": 1.0,
+	})
+
+	if script_like == null:
+		__log("Failed to instantiate module")
+		return
+
+	# Guest builds Callable(self, "call_wasm"), binds arguments to it,
+	# then connects it to button pressed signal.
+	if not script_like.call_wasm(["connect", button, label]):
+		__log("Failed to connect button")
+		return
+
+	# End-to-end check: emitting signal runs guest handler.
+	button.pressed.emit()
+	if label.text != "Pressed 1 time(s)":
+		__log("Handler is not called (label: %s)" % label.text)
+		return
+	__log("Button connected to guest handler")
+
+func __log(msg: String) -> void:
+	call_thread_safe(&"emit_signal", &"message_emitted", msg)
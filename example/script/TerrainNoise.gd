@@ -0,0 +1,64 @@
+extends Node3D
+
+@warning_ignore("unused_signal")
+signal message_emitted(msg: String)
+
+@export var wasm_file: WasmModule
+@export var size: int = 128
+@export var height_scale: float = 16.0
+
+var script_like: WasmScriptLike = null
+var terrain_seed: int = 0
+
+@onready var mesh_instance: MeshInstance3D = $Terrain
+
+func _ready():
+	script_like = WasmScriptLike.new()
+	script_like.error_happened.connect(__log)
+	script_like = script_like.initialize(wasm_file, {
+		"epoch.enable": true,
+		"epoch.timeout": 5.0,
+	})
+
+	if script_like == null:
+		__log("Failed to instantiate module")
+		return
+
+	__generate()
+
+func _unhandled_input(event):
+	if event is InputEventMouseButton and event.pressed and event.button_index == MOUSE_BUTTON_LEFT:
+		terrain_seed += 1
+		__generate()
+
+func __generate() -> void:
+	if script_like == null:
+		return
+
+	var heights = script_like.call_wasm([size, terrain_seed])
+	if not heights is PackedFloat32Array or len(heights) != size * size:
+		__log("Invalid heightmap")
+		return
+
+	var st := SurfaceTool.new()
+	st.begin(Mesh.PRIMITIVE_TRIANGLES)
+	var offset := (size - 1) * 0.5
+	for z in range(size):
+		for x in range(size):
+			var h: float = heights[z * size + x]
+			st.set_color(Color(h, 0.6 + h * 0.4, h * 0.5))
+			st.add_vertex(Vector3(x - offset, h * height_scale, z - offset))
+	for z in range(size - 1):
+		for x in range(size - 1):
+			var i := z * size + x
+			st.add_index(i)
+			st.add_index(i + 1)
+			st.add_index(i + size)
+			st.add_index(i + 1)
+			st.add_index(i + size + 1)
+			st.add_index(i + size)
+	st.generate_normals()
+	mesh_instance.mesh = st.commit()
+
+func __log(msg: String) -> void:
+	call_thread_safe(&"emit_signal", &"message_emitted", msg)
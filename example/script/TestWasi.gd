@@ -57,7 +57,7 @@ func __post_load(arr: Array[String]) -> void:
 		item_list.add_item(i, null, false)
 
 func __list_tree_item(path: String, tree: TreeItem = null) -> void:
-	if wasi_ctx.file_is_exist(path, null) != 2:
+	if wasi_ctx.file_is_exist(path, null) != WasiContext.FILE_TYPE_DIR:
 		return
 	var items = wasi_ctx.file_dir_list(path, false)
 	if items == null:
@@ -84,7 +84,7 @@ func __open_file() -> void:
 		return
 	var path: String = t.get_metadata(0)
 
-	if wasi_ctx.file_is_exist(path, null) != 1:
+	if wasi_ctx.file_is_exist(path, null) != WasiContext.FILE_TYPE_FILE:
 		return
 
 	var content = wasi_ctx.file_read(path, 1_000_000, 0, true)
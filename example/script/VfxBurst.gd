@@ -0,0 +1,35 @@
+extends Node2D
+
+@warning_ignore("unused_signal")
+signal message_emitted(msg: String)
+
+@export var wasm_file: WasmModule
+@export var preset: PackedScene
+
+var script_like: WasmScriptLike = null
+
+func _ready():
+	script_like = WasmScriptLike.new()
+	script_like.error_happened.connect(__log)
+	script_like = script_like.initialize(wasm_file, {
+		"epoch.enable": true,
+		"epoch.timeout": 1.0,
+		"vfx.maxLiveEffects": 16,
+	})
+
+	if script_like == null:
+		__log("Failed to instantiate module")
+		return
+
+	WasmInstance.register_vfx_preset("burst", preset)
+	script_like.set_vfx_parent(self)
+
+func _unhandled_input(event):
+	if script_like == null:
+		return
+
+	if event is InputEventMouseButton and event.pressed and event.button_index == MOUSE_BUTTON_LEFT:
+		script_like.call_wasm([event.position])
+
+func __log(msg: String) -> void:
+	call_thread_safe(&"emit_signal", &"message_emitted", msg)
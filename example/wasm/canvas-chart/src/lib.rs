@@ -0,0 +1,113 @@
+use std::f32::consts::TAU;
+
+wit_bindgen::generate!({
+    path: "../../../wit",
+    world: "godot-wasm:script/script",
+});
+
+use godot::core::core::GodotVar;
+use godot::core::primitive::{Color, Rect2, Vector2};
+use godot::global::{canvas, time};
+
+const WIDTH: f32 = 640.0;
+const HEIGHT: f32 = 360.0;
+const MARGIN: f32 = 24.0;
+const BARS: usize = 12;
+const POINTS: usize = 128;
+
+const BG: Color = Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.12,
+    a: 1.0,
+};
+const AXIS: Color = Color {
+    r: 0.8,
+    g: 0.8,
+    b: 0.8,
+    a: 1.0,
+};
+const BAR: Color = Color {
+    r: 0.2,
+    g: 0.5,
+    b: 0.9,
+    a: 0.8,
+};
+const LINE: Color = Color {
+    r: 1.0,
+    g: 0.6,
+    b: 0.2,
+    a: 1.0,
+};
+
+fn v(x: f32, y: f32) -> Vector2 {
+    Vector2 { x, y }
+}
+
+fn value(x: f32, t: f32) -> f32 {
+    (x * TAU + t).sin() * 0.5 + (x * TAU * 3.0 - t * 1.7).sin() * 0.25
+}
+
+struct Chart;
+
+impl Guest for Chart {
+    fn call(_args: &GodotVar) -> Option<GodotVar> {
+        let t = time::get_ticks_msec() as f32 / 1000.0;
+        let (w, h) = (WIDTH - MARGIN * 2.0, HEIGHT - MARGIN * 2.0);
+        let mid = MARGIN + h * 0.5;
+
+        canvas::draw_rect(
+            Rect2 {
+                position: v(0.0, 0.0),
+                size: v(WIDTH, HEIGHT),
+            },
+            BG,
+            true,
+            -1.0,
+        );
+
+        // Bars
+        let bw = w / BARS as f32;
+        for i in 0..BARS {
+            let y = value((i as f32 + 0.5) / BARS as f32, t) * h * 0.5;
+            let (top, size) = if y >= 0.0 { (mid - y, y) } else { (mid, -y) };
+            canvas::draw_rect(
+                Rect2 {
+                    position: v(MARGIN + bw * i as f32 + 2.0, top),
+                    size: v(bw - 4.0, size),
+                },
+                BAR,
+                true,
+                -1.0,
+            );
+        }
+
+        // Line
+        let points: Vec<_> = (0..POINTS)
+            .map(|i| {
+                let x = i as f32 / (POINTS - 1) as f32;
+                v(MARGIN + x * w, mid - value(x, t) * h * 0.5)
+            })
+            .collect();
+        canvas::draw_polyline(&points, LINE, 2.0);
+        for p in points.iter().step_by(POINTS / 8) {
+            canvas::draw_circle(*p, 4.0, LINE, true, -1.0);
+        }
+
+        // Axes
+        canvas::draw_polyline(
+            &[
+                v(MARGIN, MARGIN),
+                v(MARGIN, MARGIN + h),
+                v(MARGIN + w, MARGIN + h),
+            ],
+            AXIS,
+            1.0,
+        );
+        canvas::draw_polyline(&[v(MARGIN, mid), v(MARGIN + w, mid)], AXIS, 1.0);
+
+        None
+    }
+}
+
+export!(Chart);
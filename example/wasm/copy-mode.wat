@@ -0,0 +1,36 @@
+(module
+    ;; Example of container copy mode, host mutates dictionary while guest holds it
+    (import "host" "mutate" (func $mutate))
+    (import "godot_object_v2" "dictionary.get" (func $dict_get (param externref externref) (result externref)))
+    (import "godot_object_v2" "variant.duplicate" (func $duplicate (param externref i32) (result externref)))
+
+    ;; Returns d[k] after host mutates it.
+    (func (export "read") (param $d externref) (param $k externref) (result externref)
+        call $mutate
+        local.get $d
+        local.get $k
+        call $dict_get
+    )
+
+    ;; Returns d[k1][k2] after host mutates it.
+    (func (export "read_nested") (param $d externref) (param $k1 externref) (param $k2 externref) (result externref)
+        call $mutate
+        local.get $d
+        local.get $k1
+        call $dict_get
+        local.get $k2
+        call $dict_get
+    )
+
+    ;; Same as read, but explicitly copy the dictionary before host mutates it.
+    (func (export "read_duplicate") (param $d externref) (param $k externref) (result externref)
+        local.get $d
+        i32.const 1
+        call $duplicate
+        local.set $d
+        call $mutate
+        local.get $d
+        local.get $k
+        call $dict_get
+    )
+)
@@ -14,6 +14,14 @@ fn write_log(s: &str) {
     }
 }
 
+static GREETING: &str = "Hello from WASM!";
+
+/// Returns greeting packed as `(len << 32) | ptr`.
+#[unsafe(no_mangle)]
+pub extern "C" fn greeting() -> u64 {
+    ((GREETING.len() as u64) << 32) | GREETING.as_ptr() as usize as u64
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn main() {
     let mut s = String::new();
@@ -0,0 +1,56 @@
+(module
+    ;; Example of moving node using cached property names
+    (import "godot_object_v2" "object.intern_name" (func $intern_name (param i32 i32) (result i32)))
+    (import "godot_object_v2" "object.get_cached" (func $get_cached (param externref i32) (result externref i32)))
+    (import "godot_object_v2" "object.set_cached" (func $set_cached (param externref i32 externref) (result i32)))
+    (import "godot_object_v2" "vector2.get" (func $vector2_get (param externref) (result f32 f32)))
+    (import "godot_object_v2" "vector2.new" (func $vector2_new (param f32 f32) (result externref)))
+
+    (memory (export "memory") 1)
+    (data (i32.const 0) "position")
+
+    (global $position (mut i32) (i32.const -1))
+
+    ;; Intern property name once, instead of converting it every frame
+    (func (export "init")
+        i32.const 0
+        i32.const 8
+        call $intern_name
+        global.set $position
+    )
+
+    ;; Move node by (dx, dy). Returns error code (0 if succeed).
+    (func (export "step") (param $node externref) (param $dx f32) (param $dy f32) (result i32)
+        (local $v externref)
+        (local $e i32)
+        (local $x f32)
+        (local $y f32)
+
+        local.get $node
+        global.get $position
+        call $get_cached
+        local.set $e
+        local.set $v
+        local.get $e
+        if
+            local.get $e
+            return
+        end
+
+        local.get $v
+        call $vector2_get
+        local.set $y
+        local.set $x
+
+        local.get $node
+        global.get $position
+        local.get $x
+        local.get $dx
+        f32.add
+        local.get $y
+        local.get $dy
+        f32.add
+        call $vector2_new
+        call $set_cached
+    )
+)
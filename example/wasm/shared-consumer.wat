@@ -0,0 +1,21 @@
+(module
+    ;; Consumer side of ring buffer in shared memory
+    ;; Layout: [0] head (next write), [4] tail (next read), [8..72] 16 i32 slots
+    (import "env" "shared_buf" (memory 1 1 shared))
+
+    ;; Pops value from buffer. Returns -1 if buffer is empty.
+    (func (export "pop") (result i64)
+        (local $tail i32)
+        (local $v i32)
+        (local.set $tail (i32.atomic.load (i32.const 4)))
+        (if (i32.eq (local.get $tail) (i32.atomic.load (i32.const 0)))
+            (then (return (i64.const -1))))
+        (local.set $v
+            (i32.load
+                (i32.add
+                    (i32.const 8)
+                    (i32.shl (i32.and (local.get $tail) (i32.const 15)) (i32.const 2)))))
+        (i32.atomic.store (i32.const 4) (i32.add (local.get $tail) (i32.const 1)))
+        (i64.extend_i32_u (local.get $v))
+    )
+)
@@ -0,0 +1,22 @@
+(module
+    ;; Producer side of ring buffer in shared memory
+    ;; Layout: [0] head (next write), [4] tail (next read), [8..72] 16 i32 slots
+    (import "env" "shared_buf" (memory 1 1 shared))
+
+    ;; Pushes value into buffer. Returns 0 if buffer is full.
+    (func (export "push") (param $v i32) (result i32)
+        (local $head i32)
+        (local.set $head (i32.atomic.load (i32.const 0)))
+        (if (i32.eq
+                (i32.sub (local.get $head) (i32.atomic.load (i32.const 4)))
+                (i32.const 16))
+            (then (return (i32.const 0))))
+        (i32.store
+            (i32.add
+                (i32.const 8)
+                (i32.shl (i32.and (local.get $head) (i32.const 15)) (i32.const 2)))
+            (local.get $v))
+        (i32.atomic.store (i32.const 0) (i32.add (local.get $head) (i32.const 1)))
+        (i32.const 1)
+    )
+)
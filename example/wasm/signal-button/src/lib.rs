@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+wit_bindgen::generate!({
+    path: "../../../wit",
+    world: "godot-wasm:script/script",
+});
+
+use godot::core::core::GodotVar;
+use godot::core::{array, callable, object, primitive, signal};
+use godot::reflection::this;
+
+static PRESSED: AtomicU32 = AtomicU32::new(0);
+
+/// Connects button `pressed` signal to `call_wasm` of this object.
+///
+/// The handler is bound with `["pressed", label]`, so it arrives as `call` arguments.
+fn connect(button: &GodotVar, label: &GodotVar) -> Option<GodotVar> {
+    let handler =
+        callable::from_object_method(&this::get_this(), &primitive::from_stringname("call_wasm"));
+    let args = array::from_list(&[Some(&primitive::from_string("pressed")), Some(label)]);
+    let handler = callable::bind(&handler, &[Some(&args)]);
+    if !callable::is_valid(&handler) || callable::get_bound_arguments_count(&handler) != 1 {
+        return None;
+    }
+
+    let pressed = signal::from_object_signal(button, &primitive::from_stringname("pressed"));
+    signal::connect(&pressed, &handler, 0).ok()?;
+    Some(primitive::from_bool(signal::is_connected(
+        &pressed, &handler,
+    )))
+}
+
+fn pressed(label: &GodotVar) -> Option<GodotVar> {
+    let n = PRESSED.fetch_add(1, Ordering::Relaxed) + 1;
+    object::set(
+        label,
+        &primitive::from_stringname("text"),
+        Some(&primitive::from_string(&format!("Pressed {n} time(s)"))),
+    );
+    Some(primitive::from_int(n.into()))
+}
+
+struct SignalButton;
+
+impl Guest for SignalButton {
+    /// Arguments are either:
+    /// - `["connect", button, label]` : Connects button to this guest.
+    /// - `["pressed", label]` : Button is pressed.
+    fn call(args: &GodotVar) -> Option<GodotVar> {
+        let cmd = primitive::to_string(&array::get(args, 0)?);
+        match &*cmd {
+            "connect" => connect(&array::get(args, 1)?, &array::get(args, 2)?),
+            "pressed" => pressed(&array::get(args, 1)?),
+            _ => None,
+        }
+    }
+}
+
+export!(SignalButton);
@@ -0,0 +1,47 @@
+wit_bindgen::generate!({
+    path: "../../../wit",
+    world: "godot-wasm:script/script",
+});
+
+use godot::core::core::GodotVar;
+use godot::core::{array, float32_array, primitive};
+use godot::global::noise;
+
+const DEFAULT_SIZE: u32 = 128;
+const MAX_SIZE: u32 = 1024;
+
+struct TerrainNoise;
+
+impl Guest for TerrainNoise {
+    /// Arguments: `[size, seed]`. Returns `size * size` heights in range 0-1.
+    fn call(args: &GodotVar) -> Option<GodotVar> {
+        let size = array::get(args, 0).map_or(DEFAULT_SIZE, |v| {
+            primitive::to_int(&v).clamp(2, MAX_SIZE.into()) as u32
+        });
+        let seed = array::get(args, 1).unwrap_or_else(|| primitive::from_int(0));
+
+        let noise_type = primitive::from_int(3); // TYPE_PERLIN
+        let fractal_octaves = primitive::from_int(5);
+        let frequency = primitive::from_float(2.0 / f64::from(size));
+        let params = [
+            ("seed".to_string(), &seed),
+            ("noise_type".to_string(), &noise_type),
+            ("fractal_octaves".to_string(), &fractal_octaves),
+            ("frequency".to_string(), &frequency),
+        ];
+        let id = noise::create(&params).ok()?;
+
+        // Whole heightmap in a single host call.
+        let image = noise::get_image(id, size, size);
+        noise::free(id);
+
+        let heights = image
+            .ok()?
+            .into_iter()
+            .map(|v| f32::from(v) / 255.0)
+            .collect::<Vec<_>>();
+        Some(float32_array::from(&heights))
+    }
+}
+
+export!(TerrainNoise);
@@ -4,6 +4,7 @@ mod wave;
 
 use std::cell::RefCell;
 use std::fmt::{Arguments, Write as _};
+use std::mem::offset_of;
 use std::ptr::null;
 
 use getrandom::Error as RandError;
@@ -101,6 +102,51 @@ pub struct ExportState {
     pub index_cnt: usize,
 }
 
+impl ExportState {
+    /// Layout descriptor, see `__abi_layout`.
+    fn layout() -> String {
+        fn size<T, F>(_: impl Fn(&T) -> &F) -> usize {
+            size_of::<F>()
+        }
+
+        macro_rules! layout {
+            ($($f:ident,)*) => {
+                [$(format!(
+                    "{}:{}:{}\n",
+                    stringify!($f),
+                    offset_of!(ExportState, $f),
+                    size(|v: &ExportState| &v.$f),
+                )),*]
+                .concat()
+            };
+        }
+
+        layout!(
+            vertex_ptr,
+            vertex_cnt,
+            normal_ptr,
+            normal_cnt,
+            tangent_ptr,
+            tangent_cnt,
+            uv_ptr,
+            uv_cnt,
+            color_ptr,
+            color_cnt,
+            index_ptr,
+            index_cnt,
+        )
+    }
+}
+
+/// ABI version of exported structs. Bump major on incompatible change.
+#[repr(C)]
+pub struct AbiVersion {
+    major: u32,
+    minor: u32,
+}
+
+static ABI_VERSION: AbiVersion = AbiVersion { major: 1, minor: 0 };
+
 #[repr(C)]
 pub struct ConfigItem {
     str_ptr: *const u8,
@@ -206,6 +252,24 @@ static mut STATE_EXPORT: ExportState = ExportState {
 };
 static mut T: f64 = 0.0;
 
+static mut ABI_LAYOUT: ConfigItem = ConfigItem::from_str("");
+
+#[unsafe(no_mangle)]
+pub extern "C" fn __abi_version() -> *const AbiVersion {
+    &raw const ABI_VERSION
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn __abi_layout() -> *const ConfigItem {
+    unsafe {
+        let p = &raw mut ABI_LAYOUT;
+        if (*p).str_len == 0 {
+            *p = ConfigItem::from_str(ExportState::layout().leak());
+        }
+        p
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn config() -> *const Config {
     RenderData::config()
@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+wit_bindgen::generate!({
+    path: "../../../wit",
+    world: "godot-wasm:script/script",
+});
+
+use godot::core::array;
+use godot::core::core::{Error, GodotVar};
+use godot::core::primitive;
+use godot::global::vfx;
+
+/// Live effects, oldest first.
+static EFFECTS: Mutex<VecDeque<u32>> = Mutex::new(VecDeque::new());
+
+struct VfxBurst;
+
+impl Guest for VfxBurst {
+    fn call(args: &GodotVar) -> Option<GodotVar> {
+        let position = array::get(args, 0)?;
+        let amount = primitive::from_int(48);
+        let params = [("amount".to_string(), &amount)];
+
+        let mut effects = EFFECTS.lock().unwrap();
+        loop {
+            match vfx::spawn_particles("burst", &position, &params) {
+                Ok(id) => {
+                    effects.push_back(id);
+                    break;
+                }
+                // Too many effects, free the oldest one.
+                Err(Error::ErrBusy) => match effects.pop_front() {
+                    Some(id) => {
+                        vfx::free(id);
+                    }
+                    None => break,
+                },
+                Err(_) => break,
+            }
+        }
+
+        None
+    }
+}
+
+export!(VfxBurst);
@@ -13,6 +13,20 @@ func _enter_tree():
 	v.priority = 1.0
 	import_plugins.push_back(v)
 
+	# WASM-implemented importers (only registered in editor)
+	if ClassDB.class_exists(&"WasmImporterPlugin"):
+		for path in ProjectSettings.get_setting("godot_wasm/importers", []):
+			var m = load(path)
+			if not m is WasmModule:
+				push_error("Importer %s is not a WASM module" % path)
+				continue
+			var name: String = path.get_file().get_basename()
+			var p = ClassDB.instantiate(&"WasmImporterPlugin")
+			p.module = m
+			p.importer_name = "godot_wasm.importer.%s" % name
+			p.visible_name = name
+			import_plugins.push_back(p)
+
 	for i in import_plugins:
 		add_import_plugin(i)
 
@@ -0,0 +1,371 @@
+use std::mem;
+use std::sync::Arc;
+
+use anyhow::Result as AnyResult;
+use godot::prelude::*;
+use parking_lot::Mutex;
+use wasmtime::component::types::ResourceType;
+use wasmtime::component::{Resource as WasmResource, ResourceAny, Type, Val};
+use wasmtime::{AsContextMut, StoreContextMut};
+
+use super::script_like::WasmScriptLike;
+use crate::bail_with_site;
+use crate::godot_component::guest::{GuestHandle, GuestResources};
+use crate::godot_component::GodotCtx;
+use crate::godot_util::from_var_any;
+
+/// Own handle of guest-defined resource, returned by component call.
+///
+/// Guest resource is dropped when it's freed.
+#[derive(GodotClass)]
+#[class(base=RefCounted, no_init, tool)]
+pub struct WasmGuestResource {
+    base: Base<RefCounted>,
+    script: Gd<WasmScriptLike>,
+    handle: Mutex<GuestHandle>,
+}
+
+impl Drop for WasmGuestResource {
+    fn drop(&mut self) {
+        self.handle.get_mut().release();
+        self.script.bind().drop_guest_resources();
+    }
+}
+
+#[godot_api]
+impl WasmGuestResource {
+    /// Calls method of resource.
+    ///
+    /// Returns array of results, or `null` if call failed.
+    ///
+    /// Arguments:
+    /// - `name` : Method name, without resource prefix.
+    /// - `args` : Arguments, excluding `self`.
+    #[func]
+    fn call_method(&self, name: GString, args: VariantArray) -> Variant {
+        self.script
+            .bind()
+            .call_guest_method(self.to_gd(), &name.to_string(), args)
+    }
+
+    /// Returns `true` if handle is not yet moved into guest.
+    #[func]
+    fn is_valid(&self) -> bool {
+        self.handle.lock().ty().is_some()
+    }
+}
+
+impl WasmGuestResource {
+    pub fn ty(&self) -> Option<ResourceType> {
+        self.handle.lock().ty()
+    }
+}
+
+/// Converts values of component call, guided by function signature.
+pub struct ValConvert<'a> {
+    script: Gd<WasmScriptLike>,
+    guest: &'a Arc<GuestResources>,
+    /// Handles lent to call, they're dropped after it.
+    borrowed: Vec<ResourceAny>,
+    /// Handles moved into call, they're dropped if it's not called.
+    moved: Vec<ResourceAny>,
+}
+
+impl<'a> ValConvert<'a> {
+    pub fn new(script: Gd<WasmScriptLike>, guest: &'a Arc<GuestResources>) -> Self {
+        Self {
+            script,
+            guest,
+            borrowed: Vec::new(),
+            moved: Vec::new(),
+        }
+    }
+
+    /// Drops temporary handles.
+    pub fn finish<T: AsMut<GodotCtx>>(
+        &mut self,
+        mut store: StoreContextMut<'_, T>,
+        called: bool,
+    ) -> AnyResult<()> {
+        let moved = mem::take(&mut self.moved);
+        let it = mem::take(&mut self.borrowed).into_iter();
+        for r in it.chain(if called { Vec::new() } else { moved }) {
+            if r.ty() == ResourceType::host::<Variant>() {
+                let r = r.try_into_resource::<Variant>(store.as_context_mut())?;
+                store.data_mut().as_mut().get_var(r)?;
+            } else {
+                r.resource_drop(store.as_context_mut())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lower<T: AsMut<GodotCtx>>(
+        &mut self,
+        mut store: StoreContextMut<'_, T>,
+        ty: &Type,
+        v: &Variant,
+    ) -> AnyResult<Val> {
+        fn int<T: TryFrom<i64>>(v: &Variant) -> AnyResult<T> {
+            let i: i64 = from_var_any(v)?;
+            match T::try_from(i) {
+                Ok(v) => Ok(v),
+                Err(_) => bail_with_site!("Integer {i} is out of range"),
+            }
+        }
+
+        fn string(v: &Variant) -> AnyResult<String> {
+            Ok(from_var_any::<GString>(v)?.to_string())
+        }
+
+        Ok(match ty {
+            Type::Bool => Val::Bool(from_var_any(v)?),
+            Type::S8 => Val::S8(int(v)?),
+            Type::U8 => Val::U8(int(v)?),
+            Type::S16 => Val::S16(int(v)?),
+            Type::U16 => Val::U16(int(v)?),
+            Type::S32 => Val::S32(int(v)?),
+            Type::U32 => Val::U32(int(v)?),
+            Type::S64 => Val::S64(int(v)?),
+            Type::U64 => Val::U64(int(v)?),
+            Type::Float32 => Val::Float32(from_var_any::<f64>(v)? as f32),
+            Type::Float64 => Val::Float64(from_var_any(v)?),
+            Type::Char => {
+                let s = string(v)?;
+                let mut it = s.chars();
+                match (it.next(), it.next()) {
+                    (Some(c), None) => Val::Char(c),
+                    _ => bail_with_site!("String {s:?} is not a single character"),
+                }
+            }
+            Type::String => Val::String(string(v)?),
+            Type::List(t)
+                if t.ty() == Type::U8 && v.get_type() == VariantType::PACKED_BYTE_ARRAY =>
+            {
+                let v = from_var_any::<PackedByteArray>(v)?;
+                Val::List(v.as_slice().iter().map(|&v| Val::U8(v)).collect())
+            }
+            Type::List(t) => {
+                let t = t.ty();
+                Val::List(
+                    from_var_any::<VariantArray>(v)?
+                        .iter_shared()
+                        .map(|v| self.lower(store.as_context_mut(), &t, &v))
+                        .collect::<AnyResult<_>>()?,
+                )
+            }
+            Type::Record(t) => {
+                let v = from_var_any::<Dictionary>(v)?;
+                Val::Record(
+                    t.fields()
+                        .map(|f| {
+                            let Some(e) = v.get(f.name) else {
+                                bail_with_site!("Missing field {}", f.name)
+                            };
+                            Ok((
+                                f.name.to_string(),
+                                self.lower(store.as_context_mut(), &f.ty, &e)?,
+                            ))
+                        })
+                        .collect::<AnyResult<_>>()?,
+                )
+            }
+            Type::Tuple(t) => {
+                let v = from_var_any::<VariantArray>(v)?;
+                if v.len() != t.types().len() {
+                    bail_with_site!("Expected {} elements, got {}", t.types().len(), v.len());
+                }
+                Val::Tuple(
+                    t.types()
+                        .zip(v.iter_shared())
+                        .map(|(t, v)| self.lower(store.as_context_mut(), &t, &v))
+                        .collect::<AnyResult<_>>()?,
+                )
+            }
+            Type::Variant(t) => {
+                // Case without payload can be passed as string.
+                let (name, payload) = if v.get_type() == VariantType::DICTIONARY {
+                    let v = from_var_any::<Dictionary>(v)?;
+                    let mut it = v.iter_shared();
+                    match (it.next(), it.next()) {
+                        (Some((k, v)), None) => (string(&k)?, v),
+                        _ => bail_with_site!("Variant must have exactly one case"),
+                    }
+                } else {
+                    (string(v)?, Variant::nil())
+                };
+                let Some(c) = t.cases().find(|c| c.name == name) else {
+                    bail_with_site!("Unknown variant case {name}")
+                };
+                let payload = match c.ty {
+                    Some(t) => Some(Box::new(self.lower(store, &t, &payload)?)),
+                    None => None,
+                };
+                Val::Variant(name, payload)
+            }
+            Type::Enum(t) => {
+                let name = string(v)?;
+                if !t.names().any(|n| n == name) {
+                    bail_with_site!("Unknown enum case {name}");
+                }
+                Val::Enum(name)
+            }
+            Type::Option(_) if v.is_nil() => Val::Option(None),
+            Type::Option(t) => Val::Option(Some(Box::new(self.lower(store, &t.ty(), v)?))),
+            Type::Result(t) => {
+                let v = from_var_any::<Dictionary>(v)?;
+                let lower = |this: &mut Self, t: Option<Type>, v: Option<Variant>| match t {
+                    Some(t) => this
+                        .lower(store, &t, &v.unwrap_or_default())
+                        .map(|v| Some(Box::new(v))),
+                    None => Ok(None),
+                };
+                if v.contains_key("err") {
+                    Val::Result(Err(lower(self, t.err(), v.get("err"))?))
+                } else if v.contains_key("ok") {
+                    Val::Result(Ok(lower(self, t.ok(), v.get("ok"))?))
+                } else {
+                    bail_with_site!("Result must have either ok or err key")
+                }
+            }
+            Type::Flags(t) => {
+                let v = from_var_any::<VariantArray>(v)?
+                    .iter_shared()
+                    .map(|v| string(&v))
+                    .collect::<AnyResult<Vec<_>>>()?;
+                if let Some(n) = v.iter().find(|n| !t.names().any(|k| k == *n)) {
+                    bail_with_site!("Unknown flag {n}");
+                }
+                Val::Flags(v)
+            }
+            Type::Own(t) | Type::Borrow(t) if *t == ResourceType::host::<Variant>() => {
+                let rep = store.data_mut().as_mut().try_insert(v.clone())?;
+                let r = WasmResource::<Variant>::new_own(rep).try_into_resource_any(store)?;
+                if let Type::Own(_) = ty {
+                    self.moved.push(r);
+                } else {
+                    self.borrowed.push(r);
+                }
+                Val::Resource(r)
+            }
+            Type::Own(_) | Type::Borrow(_) => {
+                let v = from_var_any::<Gd<WasmGuestResource>>(v)?;
+                let v = v.bind();
+                let mut h = v.handle.lock();
+                if !h.is_owned_by(self.guest) {
+                    bail_with_site!("Resource is owned by another instance");
+                }
+                let ret = h.lower(ty)?;
+                if let (Type::Own(_), Val::Resource(r)) = (ty, &ret) {
+                    self.moved.push(*r);
+                }
+                ret
+            }
+        })
+    }
+
+    pub fn lift<T: AsMut<GodotCtx>>(
+        &self,
+        mut store: StoreContextMut<'_, T>,
+        ty: &Type,
+        v: Val,
+    ) -> AnyResult<Variant> {
+        Ok(match (ty, v) {
+            (_, Val::Bool(v)) => v.to_variant(),
+            (_, Val::S8(v)) => v.to_variant(),
+            (_, Val::U8(v)) => v.to_variant(),
+            (_, Val::S16(v)) => v.to_variant(),
+            (_, Val::U16(v)) => v.to_variant(),
+            (_, Val::S32(v)) => v.to_variant(),
+            (_, Val::U32(v)) => v.to_variant(),
+            (_, Val::S64(v)) => v.to_variant(),
+            (_, Val::U64(v)) => match i64::try_from(v) {
+                Ok(v) => v.to_variant(),
+                Err(_) => bail_with_site!("Integer {v} is out of range"),
+            },
+            (_, Val::Float32(v)) => v.to_variant(),
+            (_, Val::Float64(v)) => v.to_variant(),
+            (_, Val::Char(v)) => GString::from(v.to_string()).to_variant(),
+            (_, Val::String(v)) => GString::from(v).to_variant(),
+            (Type::List(t), Val::List(v)) if t.ty() == Type::U8 => v
+                .into_iter()
+                .map(|v| match v {
+                    Val::U8(v) => v,
+                    _ => 0,
+                })
+                .collect::<PackedByteArray>()
+                .to_variant(),
+            (Type::List(t), Val::List(v)) => {
+                let t = t.ty();
+                v.into_iter()
+                    .map(|v| self.lift(store.as_context_mut(), &t, v))
+                    .collect::<AnyResult<VariantArray>>()?
+                    .to_variant()
+            }
+            (Type::Record(t), Val::Record(v)) => t
+                .fields()
+                .zip(v)
+                .map(|(f, (k, v))| {
+                    Ok((
+                        GString::from(k),
+                        self.lift(store.as_context_mut(), &f.ty, v)?,
+                    ))
+                })
+                .collect::<AnyResult<Dictionary>>()?
+                .to_variant(),
+            (Type::Tuple(t), Val::Tuple(v)) => t
+                .types()
+                .zip(v)
+                .map(|(t, v)| self.lift(store.as_context_mut(), &t, v))
+                .collect::<AnyResult<VariantArray>>()?
+                .to_variant(),
+            (Type::Variant(t), Val::Variant(name, payload)) => {
+                let payload = match (
+                    t.cases().find(|c| c.name == name).and_then(|c| c.ty),
+                    payload,
+                ) {
+                    (Some(t), Some(v)) => self.lift(store, &t, *v)?,
+                    _ => Variant::nil(),
+                };
+                let mut ret = Dictionary::new();
+                ret.set(GString::from(name), payload);
+                ret.to_variant()
+            }
+            (_, Val::Enum(v)) => GString::from(v).to_variant(),
+            (_, Val::Option(None)) => Variant::nil(),
+            (Type::Option(t), Val::Option(Some(v))) => self.lift(store, &t.ty(), *v)?,
+            (Type::Result(t), Val::Result(v)) => {
+                let (key, t, v) = match v {
+                    Ok(v) => ("ok", t.ok(), v),
+                    Err(v) => ("err", t.err(), v),
+                };
+                let v = match (t, v) {
+                    (Some(t), Some(v)) => self.lift(store, &t, *v)?,
+                    _ => Variant::nil(),
+                };
+                let mut ret = Dictionary::new();
+                ret.set(key, v);
+                ret.to_variant()
+            }
+            (_, Val::Flags(v)) => v
+                .into_iter()
+                .map(|v| GString::from(v).to_variant())
+                .collect::<VariantArray>()
+                .to_variant(),
+            (_, Val::Resource(r)) if r.ty() == ResourceType::host::<Variant>() => {
+                let r = r.try_into_resource::<Variant>(store.as_context_mut())?;
+                store.data_mut().as_mut().get_var(r)?
+            }
+            (_, Val::Resource(r)) => {
+                let handle = self.guest.hold(r);
+                Gd::from_init_fn(|base| WasmGuestResource {
+                    base,
+                    script: self.script.clone(),
+                    handle: Mutex::new(handle),
+                })
+                .to_variant()
+            }
+            (ty, v) => bail_with_site!("Value {v:?} does not match type {ty:?}"),
+        })
+    }
+}
@@ -1,2 +1,3 @@
 // Submodules goes here
+mod guest_resource;
 mod script_like;
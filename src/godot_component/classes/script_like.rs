@@ -1,23 +1,34 @@
+use std::iter;
+use std::sync::Arc;
+
 use anyhow::Result as AnyResult;
+use godot::classes::{CanvasItem, Node};
 use godot::prelude::*;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 #[cfg(feature = "wasi")]
 use wasi_isolated_fs::context::WasiContext as WasiCtx;
-use wasmtime::component::{Linker, Resource as WasmResource};
+use wasmtime::component::{Func, Linker, Resource as WasmResource};
 use wasmtime::{AsContextMut, Store};
 
-use crate::godot_component::filter::Filter;
+use super::guest_resource::{ValConvert, WasmGuestResource};
+use crate::godot_component::compat::resolve_imports;
+use crate::godot_component::filter::{filter_from_config, warn_denied_imports, Filter};
+use crate::godot_component::global::engine::ServiceScope;
+use crate::godot_component::guest::{call_func, GuestExports, GuestResources};
 use crate::godot_component::{add_to_linker, bindgen, GodotCtx};
 use crate::godot_util::PhantomProperty;
 use crate::wasm_config::Config;
-use crate::wasm_engine::WasmModule;
+#[cfg(feature = "epoch-timeout")]
+use crate::wasm_engine::{arm_deadline, enter_priority, EpochDeadline};
+use crate::wasm_engine::{get_linker, LinkerKey, WasmModule};
 #[cfg(feature = "memory-limiter")]
 use crate::wasm_instance::MemoryLimit;
-use crate::wasm_instance::{InnerLock, InstanceData, InstanceType};
-use crate::wasm_util::HasEpochTimeout;
+use crate::wasm_instance::{enter_guest, CallFrame, InnerLock, InstanceData, InstanceType};
+use crate::wasm_schema::ComponentExports;
 #[cfg(feature = "epoch-timeout")]
 use crate::wasm_util::{config_store_epoch, reset_epoch};
+use crate::wasm_util::{config_store_fuel, format_error, open_nondet_tap, HasEpochTimeout};
 use crate::{bail_with_site, site_context};
 
 #[derive(Default)]
@@ -25,6 +36,12 @@ struct ScriptConfig {
     config: Config,
 
     filter: Filter,
+    warn_denied: bool,
+    settings_prefixes: Vec<String>,
+    vfx_max_live: Option<usize>,
+    strict_input_snapshot: bool,
+    services_scope: ServiceScope,
+    allow_missing_optional: bool,
 }
 
 impl GodotConvert for ScriptConfig {
@@ -40,8 +57,33 @@ impl FromGodot for ScriptConfig {
     }
 
     fn try_from_godot(via: Self::Via) -> Result<Self, ConvertError> {
-        let filter = via
-            .get("component.godot.filter")
+        let filter = filter_from_config(&via)?;
+        let warn_denied = via
+            .get("component.godot.warnDeniedImports")
+            .or_else(|| via.get("filter.warn_denied_imports"))
+            .map(|v| v.try_to())
+            .transpose()?
+            .unwrap_or_default();
+        let settings_prefixes = via
+            .get("component.settings_prefixes")
+            .map(|v| v.try_to::<PackedStringArray>())
+            .transpose()?
+            .map_or_else(Vec::new, |v| {
+                v.as_slice().iter().map(|s| s.to_string()).collect()
+            });
+        let vfx_max_live = via
+            .get("vfx.maxLiveEffects")
+            .map(|v| v.try_to::<u32>())
+            .transpose()?
+            .map(|v| v as usize);
+        let strict_input_snapshot = via
+            .get("component.godot.strictInputSnapshot")
+            .map(|v| v.try_to())
+            .transpose()?
+            .unwrap_or_default();
+        let services_scope = ServiceScope::from_config(&via)?;
+        let allow_missing_optional = via
+            .get("compat.allow_missing_optional")
             .map(|v| v.try_to())
             .transpose()?
             .unwrap_or_default();
@@ -49,6 +91,12 @@ impl FromGodot for ScriptConfig {
         Ok(Self {
             config: Config::try_from_godot(via)?,
             filter,
+            warn_denied,
+            settings_prefixes,
+            vfx_max_live,
+            strict_input_snapshot,
+            services_scope,
+            allow_missing_optional,
         })
     }
 }
@@ -67,11 +115,15 @@ pub struct WasmScriptLike {
 pub struct WasmScriptLikeData {
     instance: InstanceData<WasmScriptLikeStore>,
     bindings: bindgen::Script,
+    exports: GuestExports,
+    guest_resources: Arc<GuestResources>,
 }
 
 pub struct WasmScriptLikeStore {
     #[cfg(feature = "epoch-timeout")]
     epoch_timeout: u64,
+    #[cfg(feature = "epoch-timeout")]
+    epoch_deadline: EpochDeadline,
 
     #[cfg(feature = "memory-limiter")]
     memory_limits: MemoryLimit,
@@ -103,6 +155,11 @@ impl HasEpochTimeout for WasmScriptLikeStore {
         self.epoch_timeout
     }
 
+    #[cfg(feature = "epoch-timeout")]
+    fn get_epoch_deadline(&mut self) -> &mut EpochDeadline {
+        &mut self.epoch_deadline
+    }
+
     #[cfg(feature = "wasi")]
     fn get_wasi_ctx(&mut self) -> Option<&mut WasiCtx> {
         None
@@ -119,13 +176,49 @@ impl WasmScriptLike {
 
     fn instantiate(
         inst_id: InstanceId,
-        ScriptConfig { config, filter }: ScriptConfig,
+        ScriptConfig {
+            config,
+            filter,
+            warn_denied,
+            settings_prefixes,
+            vfx_max_live,
+            strict_input_snapshot,
+            services_scope,
+            allow_missing_optional,
+        }: ScriptConfig,
         module: Gd<WasmModule>,
     ) -> AnyResult<WasmScriptLikeData> {
+        if config.sandbox_pure {
+            bail_with_site!("Pure sandbox is only supported by WasmInstance");
+        }
         let comp = site_context!(module.bind().get_data()?.module.get_component())?.clone();
 
+        if warn_denied {
+            warn_denied_imports(
+                module.instance_id(),
+                &filter,
+                &site_context!(module.bind().get_data()?.module.get_component_imports())?,
+            );
+        }
+
         let mut godot_ctx = GodotCtx::new(inst_id);
         godot_ctx.filter = filter;
+        godot_ctx.settings_prefixes = settings_prefixes;
+        godot_ctx.nondet = open_nondet_tap(&config)?;
+        godot_ctx.input.strict = strict_input_snapshot;
+        #[cfg(feature = "wasi")]
+        let context = config.wasi_context.as_ref().map(|c| c.instance_id());
+        #[cfg(not(feature = "wasi"))]
+        let context = None;
+        godot_ctx.services.configure(
+            inst_id,
+            module.bind().get_data()?.name.to_string(),
+            services_scope,
+            context,
+        );
+        if let Some(v) = vfx_max_live {
+            godot_ctx.vfx.max_live = v;
+        }
         let mut store = Store::new(
             comp.engine(),
             WasmScriptLikeStore {
@@ -135,32 +228,55 @@ impl WasmScriptLike {
                 } else {
                     0
                 },
+                #[cfg(feature = "epoch-timeout")]
+                epoch_deadline: EpochDeadline::default(),
 
                 #[cfg(feature = "memory-limiter")]
-                memory_limits: MemoryLimit::from_config(&config),
+                memory_limits: MemoryLimit::from_config(&config, Some(inst_id)),
 
                 godot_ctx,
             },
         );
+        let lock = AsMut::<InnerLock>::as_mut(store.data_mut());
+        lock.object_calls = config.object_calls;
+        if let Some(v) = config.max_reentrancy {
+            lock.reentrancy.max_depth = v;
+        }
+        config_store_fuel(&mut store)?;
         #[cfg(feature = "epoch-timeout")]
         config_store_epoch(&mut store, &config)?;
         #[cfg(feature = "memory-limiter")]
         store.limiter(|data| &mut data.memory_limits);
 
-        let mut linker = <Linker<WasmScriptLikeStore>>::new(store.engine());
-        site_context!(add_to_linker(&mut linker, |v| v))?;
-
-        let bindings = site_context!(bindgen::Script::instantiate(&mut store, &comp, &linker))?;
+        let key = LinkerKey {
+            godot_component: true,
+            ..LinkerKey::default()
+        };
+        let linker = get_linker(store.engine(), key, || {
+            let mut linker = <Linker<WasmScriptLikeStore>>::new(store.engine());
+            site_context!(add_to_linker(&mut linker, |v| v))?;
+            Ok(linker)
+        })?;
+
+        let stubbed = site_context!(resolve_imports(&linker, &comp, allow_missing_optional))?;
+        let linker = stubbed.as_ref().unwrap_or(&linker);
+        #[cfg(feature = "epoch-timeout")]
+        let _armed = config.with_epoch.then(arm_deadline);
+        let instance = site_context!(linker.instantiate(&mut store, &comp))?;
+        let bindings = site_context!(bindgen::Script::new(&mut store, &instance))?;
+        let exports = GuestExports::new(&mut store, &instance, &ComponentExports::new(&comp));
 
         Ok(WasmScriptLikeData {
             instance: InstanceData {
                 store: Mutex::new(store),
-                instance: InstanceType::NoInstance,
+                instance: InstanceType::Component(instance),
                 module,
 
                 wasi_stdin: None,
             },
             bindings,
+            exports,
+            guest_resources: Arc::default(),
         })
     }
 
@@ -179,7 +295,7 @@ impl WasmScriptLike {
         match self.get_data().and_then(f) {
             Ok(v) => Some(v),
             Err(e) => {
-                let s = format!("{e:?}");
+                let s = format_error(&e);
                 /*
                 error(
                     e.downcast_ref::<Site>()
@@ -195,6 +311,97 @@ impl WasmScriptLike {
         }
     }
 
+    /// Calls component function with arguments converted by it's signature.
+    fn call_component_func(
+        &self,
+        m: &WasmScriptLikeData,
+        name: &str,
+        func: Func,
+        args: &[Variant],
+    ) -> AnyResult<VariantArray> {
+        m.instance.acquire_store(move |_, mut store| {
+            #[cfg(feature = "epoch-timeout")]
+            let _guard = enter_priority(&store.data().epoch_deadline);
+            #[cfg(feature = "epoch-timeout")]
+            reset_epoch(store.as_context_mut());
+
+            site_context!(m.guest_resources.drop_released(store.as_context_mut()))?;
+            let params = func.params(&store);
+            if args.len() != params.len() {
+                bail_with_site!(
+                    "Function {name} expects {} arguments, got {}",
+                    params.len(),
+                    args.len()
+                );
+            }
+
+            let mut conv = ValConvert::new(self.to_gd(), &m.guest_resources);
+            let vals = params
+                .iter()
+                .zip(args)
+                .map(|((_, ty), v)| conv.lower(store.as_context_mut(), ty, v))
+                .collect::<AnyResult<Vec<_>>>();
+            let ret = vals.map(|vals| {
+                let frame = CallFrame::Guest {
+                    inst: self.base().instance_id(),
+                    name: StringName::from(name),
+                };
+                enter_guest(store.as_context_mut(), frame, |store| {
+                    call_func(store, func, &vals)
+                })
+            });
+            // Temporary handles are dropped even if call failed.
+            let finished = conv.finish(store.as_context_mut(), ret.is_ok());
+            let ret = ret??;
+            finished?;
+
+            func.results(&store)
+                .iter()
+                .zip(ret)
+                .map(|(ty, v)| conv.lift(store.as_context_mut(), ty, v))
+                .collect()
+        })
+    }
+
+    /// Calls method of guest resource.
+    pub fn call_guest_method(
+        &self,
+        this: Gd<WasmGuestResource>,
+        method: &str,
+        args: VariantArray,
+    ) -> Variant {
+        self.unwrap_data(move |m| {
+            let Some(ty) = this.bind().ty() else {
+                bail_with_site!("Resource handle is already moved or released")
+            };
+            let func = m.exports.method(&ty, method)?;
+            let args = iter::once(this.to_variant())
+                .chain(args.iter_shared())
+                .collect::<Vec<_>>();
+            self.call_component_func(m, method, func, &args)
+        })
+        .map_or_else(Variant::nil, |v| v.to_variant())
+    }
+
+    /// Drops guest resources released by wrapper, unless store is in use.
+    pub fn drop_guest_resources(&self) {
+        let Some(m) = self.data.get() else { return };
+        if m.instance.store.is_locked() {
+            return;
+        }
+        let r = m.instance.acquire_store(|_, mut store| {
+            #[cfg(feature = "epoch-timeout")]
+            let _guard = enter_priority(&store.data().epoch_deadline);
+            #[cfg(feature = "epoch-timeout")]
+            reset_epoch(store.as_context_mut());
+
+            m.guest_resources.drop_released(store)
+        });
+        if let Err(e) = r {
+            godot_error!("{}", format_error(&e));
+        }
+    }
+
     pub fn initialize_(&self, module: Gd<WasmModule>, config: Option<Variant>) -> bool {
         match self.data.get_or_try_init(move || {
             Self::instantiate(
@@ -213,7 +420,7 @@ impl WasmScriptLike {
         }) {
             Ok(_) => true,
             Err(e) => {
-                let s = format!("{e:?}");
+                let s = format_error(&e);
                 godot_error!("{s}");
                 self.emit_error_wrapper(s);
                 false
@@ -226,6 +433,9 @@ impl WasmScriptLike {
 impl WasmScriptLike {
     #[signal]
     fn error_happened();
+    /// Emitted the first time guest calls each method blocked by filter.
+    #[signal]
+    fn filter_denied(interface: GString, method: GString);
 
     /// Initialize and loads module.
     /// MUST be called for the first time and only once.
@@ -249,13 +459,20 @@ impl WasmScriptLike {
     fn call_wasm(&self, args: VariantArray) -> Variant {
         self.unwrap_data(move |m| {
             m.instance.acquire_store(move |_, mut store| {
+                #[cfg(feature = "epoch-timeout")]
+                let _guard = enter_priority(&store.data().epoch_deadline);
                 #[cfg(feature = "epoch-timeout")]
                 reset_epoch(store.as_context_mut());
 
                 let res = store.data_mut().godot_ctx.set_into_var(args)?;
-                let ret = m
-                    .bindings
-                    .call_call(&mut store, WasmResource::new_borrow(res.rep()));
+                let frame = CallFrame::Guest {
+                    inst: self.base().instance_id(),
+                    name: StringName::from(c"call"),
+                };
+                let ret = enter_guest(store.as_context_mut(), frame, |store| {
+                    m.bindings
+                        .call_call(store, WasmResource::new_borrow(res.rep()))
+                });
                 let ctx = &mut store.data_mut().godot_ctx;
                 ctx.get_var(res)?;
 
@@ -264,4 +481,100 @@ impl WasmScriptLike {
         })
         .unwrap_or_default()
     }
+
+    /// Calls exported component function.
+    /// Arguments and results are converted by function signature.
+    ///
+    /// Returns array of results, or `null` if call failed.
+    ///
+    /// Arguments:
+    /// - `name` : Function name. Function inside interface is named `interface#function`.
+    /// - `args` : Arguments of function.
+    #[func]
+    fn call_component(&self, name: GString, args: VariantArray) -> Variant {
+        self.unwrap_data(move |m| {
+            let name = name.to_string();
+            let func = m.exports.func(&name)?;
+            let args = args.iter_shared().collect::<Vec<_>>();
+            self.call_component_func(m, &name, func, &args)
+        })
+        .map_or_else(Variant::nil, |v| v.to_variant())
+    }
+
+    /// Returns number of guest resource handles held by `WasmGuestResource`.
+    /// Handles of freed wrappers are counted until guest drops them.
+    #[func]
+    fn get_guest_resource_count(&self) -> i64 {
+        self.drop_guest_resources();
+        self.unwrap_data(|m| Ok(m.guest_resources.count()))
+            .unwrap_or_default() as _
+    }
+
+    /// Commits draw commands submitted by guest since last update.
+    /// Call it once per frame, after guest finished drawing.
+    ///
+    /// Queues redraw of `item` only if commands changed. Returns `true` if it does.
+    ///
+    /// Arguments:
+    /// - `item` : Canvas item to be redrawn.
+    #[func]
+    fn update_canvas(&self, item: Gd<CanvasItem>) -> bool {
+        let changed = self
+            .unwrap_data(|m| {
+                Ok(m.instance
+                    .acquire_store(|_, mut store| store.data_mut().godot_ctx.canvas.commit()))
+            })
+            .unwrap_or_default();
+        if changed {
+            item.clone().queue_redraw();
+        }
+        changed
+    }
+
+    /// Replays committed draw commands. Must be called inside `_draw()`.
+    ///
+    /// Arguments:
+    /// - `item` : Canvas item to draw into.
+    #[func]
+    fn draw_canvas(&self, mut item: Gd<CanvasItem>) {
+        self.unwrap_data(|m| {
+            m.instance
+                .acquire_store(|_, store| store.data().godot_ctx.canvas.replay(&mut item));
+            Ok(())
+        });
+    }
+
+    /// Returns number of draw commands in last update.
+    #[func]
+    fn get_last_command_count(&self) -> i64 {
+        self.unwrap_data(|m| {
+            Ok(m.instance
+                .acquire_store(|_, store| store.data().godot_ctx.canvas.last_command_count()))
+        })
+        .unwrap_or_default() as _
+    }
+
+    /// Sets node which spawned effects are added into.
+    /// Effects can't be spawned until it's set.
+    ///
+    /// Arguments:
+    /// - `parent` : Parent node, or `null` to unset it.
+    #[func]
+    fn set_vfx_parent(&self, parent: Option<Gd<Node>>) {
+        self.unwrap_data(|m| {
+            m.instance
+                .acquire_store(|_, mut store| store.data_mut().godot_ctx.vfx.set_parent(parent));
+            Ok(())
+        });
+    }
+
+    /// Returns number of live effects spawned by guest.
+    #[func]
+    fn get_vfx_count(&self) -> i64 {
+        self.unwrap_data(|m| {
+            Ok(m.instance
+                .acquire_store(|_, mut store| store.data_mut().godot_ctx.vfx.live_count()))
+        })
+        .unwrap_or_default() as _
+    }
 }
@@ -0,0 +1,472 @@
+//! Import compatibility with components built against newer `godot:*` WIT.
+//!
+//! When a component imports interfaces or functions this plugin does not provide,
+//! instantiation fails with a list of every missing item and the version that introduced it.
+//! Missing interfaces marked optional can instead be replaced with trapping stubs.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use anyhow::{bail, Error as AnyError, Result as AnyResult};
+use tracing::warn;
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, Linker, LinkerInstance, ResourceType};
+
+/// Version of this plugin.
+pub const PLUGIN_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Version of `godot:*` WIT packages provided by this plugin.
+pub const WIT_VERSION: &str = "0.1.0";
+
+/// Compatibility entry of package, interface, or function.
+#[derive(Debug)]
+pub struct CompatEntry {
+    /// Name without version, either package (`godot:global`),
+    /// interface (`godot:global/noise`), or function (`godot:core/byte-array#compress`).
+    pub name: &'static str,
+    /// Plugin version that introduced it.
+    pub since: &'static str,
+    /// If `true`, missing interface can be replaced with trapping stubs.
+    pub optional: bool,
+}
+
+const fn entry(name: &'static str, since: &'static str, optional: bool) -> CompatEntry {
+    CompatEntry {
+        name,
+        since,
+        optional,
+    }
+}
+
+/// Registry of provided items.
+///
+/// Lookup falls back from function to interface to package,
+/// so unknown items inherit optionality of the closest known parent.
+/// Add an entry whenever an interface or function is added to an existing interface.
+pub static REGISTRY: &[CompatEntry] = &[
+    entry("godot:core", "0.1.0", false),
+    entry("godot:core/core", "0.1.0", false),
+    entry("godot:core/typeis", "0.1.0", false),
+    entry("godot:core/primitive", "0.1.0", false),
+    entry("godot:core/byte-array", "0.1.0", false),
+    entry("godot:core/byte-array#compress", "0.1.0", false),
+    entry("godot:core/byte-array#decompress", "0.1.0", false),
+    entry("godot:core/int32-array", "0.1.0", false),
+    entry("godot:core/int64-array", "0.1.0", false),
+    entry("godot:core/float32-array", "0.1.0", false),
+    entry("godot:core/float64-array", "0.1.0", false),
+    entry("godot:core/vector2-array", "0.1.0", false),
+    entry("godot:core/vector3-array", "0.1.0", false),
+    entry("godot:core/color-array", "0.1.0", false),
+    entry("godot:core/string-array", "0.1.0", false),
+    entry("godot:core/array", "0.1.0", false),
+    entry("godot:core/dictionary", "0.1.0", false),
+    entry("godot:core/object", "0.1.0", false),
+    entry("godot:core/callable", "0.1.0", false),
+    entry("godot:core/signal", "0.1.0", false),
+    entry("godot:core/signal#connect-flags", "0.1.0", false),
+    entry("godot:core/transform", "0.1.0", false),
+    entry("godot:core/task", "0.1.0", true),
+    entry("godot:reflection", "0.1.0", false),
+    entry("godot:reflection/this", "0.1.0", false),
+    entry("godot:global", "0.1.0", true),
+    entry("godot:global/globalscope", "0.1.0", true),
+    entry("godot:global/classdb", "0.1.0", true),
+    entry("godot:global/engine", "0.1.0", true),
+    entry("godot:global/input", "0.1.0", true),
+    entry("godot:global/input-map", "0.1.0", true),
+    entry("godot:global/project-settings", "0.1.0", true),
+    entry("godot:global/ip", "0.1.0", true),
+    entry("godot:global/canvas", "0.1.0", true),
+    entry("godot:global/vfx", "0.1.0", true),
+    entry("godot:global/noise", "0.1.0", true),
+    entry("godot:global/translation", "0.1.0", true),
+    entry("godot:global/display", "0.1.0", true),
+];
+
+fn find(name: &str) -> Option<&'static CompatEntry> {
+    REGISTRY.iter().find(|e| e.name == name)
+}
+
+fn strip_version(name: &str) -> (&str, Option<&str>) {
+    match name.split_once('@') {
+        Some((a, b)) => (a, Some(b)),
+        None => (name, None),
+    }
+}
+
+/// Checks if two versions resolve to the same linker entry.
+///
+/// Mirrors semver matching of component linker: `1.2.3` matches `1.x.y`, `0.2.3` matches `0.2.x`.
+fn version_compatible(a: &str, b: &str) -> bool {
+    fn key(v: &str) -> Option<(&str, Option<&str>)> {
+        if v.contains(['-', '+']) {
+            return None;
+        }
+        let mut it = v.split('.');
+        match (it.next()?, it.next()?) {
+            ("0", "0") => None,
+            ("0", minor) => Some(("0", Some(minor))),
+            (major, _) => Some((major, None)),
+        }
+    }
+
+    a == b || key(a).is_some_and(|k| key(b) == Some(k))
+}
+
+/// Import not provided by this plugin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingImport {
+    /// Interface name, as imported by component.
+    pub interface: String,
+    /// Missing function. `None` if the entire interface is missing.
+    pub func: Option<String>,
+}
+
+impl MissingImport {
+    /// Gets versionless name of missing item.
+    fn name(&self) -> String {
+        let (i, _) = strip_version(&self.interface);
+        match &self.func {
+            Some(f) => format!("{i}#{f}"),
+            None => i.to_string(),
+        }
+    }
+
+    /// Gets plugin version that introduced it, if known.
+    pub fn since(&self) -> Option<&'static str> {
+        find(&self.name()).map(|e| e.since)
+    }
+
+    /// Returns `true` if it can be replaced with trapping stubs.
+    ///
+    /// Only entire interfaces can be stubbed,
+    /// functions missing from provided interface are always required.
+    pub fn is_optional(&self) -> bool {
+        if self.func.is_some() {
+            return false;
+        }
+        let (i, _) = strip_version(&self.interface);
+        find(i)
+            .or_else(|| find(i.split_once('/').map_or(i, |(p, _)| p)))
+            .is_some_and(|e| e.optional)
+    }
+}
+
+impl Display for MissingImport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match &self.func {
+            Some(n) => write!(f, "function `{}#{n}`", self.interface)?,
+            None => write!(f, "interface `{}`", self.interface)?,
+        }
+        match self.since() {
+            Some(v) => write!(f, " (since {v}")?,
+            None => write!(f, " (newer than {PLUGIN_VERSION}")?,
+        }
+        if self.is_optional() {
+            write!(f, ", optional)")
+        } else {
+            write!(f, ", required)")
+        }
+    }
+}
+
+/// Error of missing imports.
+#[derive(Debug, Clone)]
+pub struct MissingImportsError(pub Vec<MissingImport>);
+
+impl Display for MissingImportsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Component imports items not provided by plugin version {PLUGIN_VERSION}:"
+        )?;
+        for i in &self.0 {
+            write!(f, "\n- {i}")?;
+        }
+        if self.0.iter().any(|i| i.is_optional()) {
+            write!(
+                f,
+                "\nSet compat.allow_missing_optional to stub optional interfaces."
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for MissingImportsError {}
+
+/// Defines trapping stub of interface export.
+fn stub_item<T: 'static>(
+    inst: &mut LinkerInstance<'_, T>,
+    interface: &str,
+    name: &str,
+    item: ComponentItem,
+) -> AnyResult<()> {
+    match item {
+        ComponentItem::ComponentFunc(_) => {
+            let msg = format!(
+                "Import `{interface}#{name}` is not provided by plugin version {PLUGIN_VERSION}"
+            );
+            inst.func_new(name, move |_, _, _| bail!("{msg}"))
+        }
+        ComponentItem::Resource(_) => {
+            inst.resource(name, ResourceType::host::<()>(), |_, _| Ok(()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks if linker defines interface, possibly with semver-compatible version.
+fn is_defined<T>(linker: &Linker<T>, name: &str) -> bool {
+    let (base, version) = strip_version(name);
+    // Defining instance only fails if it's already defined.
+    if linker.clone().instance(name).is_err() {
+        return true;
+    }
+    match version {
+        Some(v) if v != WIT_VERSION && version_compatible(v, WIT_VERSION) => linker
+            .clone()
+            .instance(&format!("{base}@{WIT_VERSION}"))
+            .is_err(),
+        _ => false,
+    }
+}
+
+/// Extracts missing function from linker type check error.
+///
+/// Type check error is formatted as
+/// ``component imports instance `<interface>` ...`` ->
+/// ``instance export `<function>` has the wrong type`` ->
+/// `function implementation is missing`.
+fn missing_func(e: &AnyError) -> Option<(String, String)> {
+    fn quoted(s: &str) -> Option<&str> {
+        let (_, s) = s.split_once('`')?;
+        Some(s.split_once('`')?.0)
+    }
+
+    let chain = e.chain().map(|e| e.to_string()).collect::<Vec<_>>();
+    let [i, f, c] = &chain[..] else {
+        return None;
+    };
+    if !i.starts_with("component imports instance")
+        || !f.starts_with("instance export")
+        || c != "function implementation is missing"
+    {
+        return None;
+    }
+    Some((quoted(i)?.to_string(), quoted(f)?.to_string()))
+}
+
+/// Resolves `godot:*` imports of component.
+///
+/// Returns `None` if linker satisfies all imports.
+/// If there are missing imports and all of them are optional (with `allow_missing_optional`),
+/// returns new linker with trapping stubs. Otherwise errors with [`MissingImportsError`].
+pub fn resolve_imports<T: 'static>(
+    linker: &Linker<T>,
+    component: &Component,
+    allow_missing_optional: bool,
+) -> AnyResult<Option<Linker<T>>> {
+    let Err(err) = linker.substituted_component_type(component) else {
+        return Ok(None);
+    };
+    let engine = linker.engine();
+
+    let mut stubbed = linker.clone();
+    let mut missing = Vec::new();
+    for (name, item) in component.component_type().imports(engine) {
+        let ComponentItem::ComponentInstance(ty) = item else {
+            continue;
+        };
+        if !name.starts_with("godot:") || is_defined(linker, name) {
+            continue;
+        }
+
+        let mut inst = stubbed.instance(name)?;
+        for (k, v) in ty.exports(engine) {
+            stub_item(&mut inst, name, k, v)?;
+        }
+        missing.push(MissingImport {
+            interface: name.to_string(),
+            func: None,
+        });
+    }
+
+    // Functions can't be added into existing interface, so only report them.
+    // Only the first error is known, so stub the interface entirely to find the next one.
+    let mut probe = stubbed.clone();
+    probe.allow_shadowing(true);
+    while let Err(e) = probe.substituted_component_type(component) {
+        let Some((interface, func)) = missing_func(&e).filter(|(i, _)| i.starts_with("godot:"))
+        else {
+            // Not caused by missing godot imports.
+            return Err(if missing.is_empty() { err } else { e });
+        };
+        let Some(ComponentItem::ComponentInstance(ty)) =
+            component.component_type().get_import(engine, &interface)
+        else {
+            return Err(e);
+        };
+        if missing
+            .iter()
+            .any(|i: &MissingImport| i.interface == interface)
+        {
+            return Err(e);
+        }
+
+        let mut inst = probe.instance(&interface)?;
+        for (k, v) in ty.exports(engine) {
+            stub_item(&mut inst, &interface, k, v)?;
+        }
+        missing.push(MissingImport {
+            interface,
+            func: Some(func),
+        });
+    }
+
+    if allow_missing_optional && missing.iter().all(|i| i.is_optional()) {
+        for i in &missing {
+            warn!(import = %i, "Stubbing missing import");
+        }
+        return Ok(Some(stubbed));
+    }
+    Err(MissingImportsError(missing).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasmtime::{Config, Engine, Store};
+
+    const HOST: &str = r#"
+(component
+  (import "godot:core/typeis@0.1.0" (instance $typeis
+    (export "is-int" (func (result u32)))))
+  (import "godot:global/noise@0.1.0" (instance $noise
+    (export "seed" (func (result u32)))))
+
+  (core func $is-int (canon lower (func $typeis "is-int")))
+  (core func $seed (canon lower (func $noise "seed")))
+  (core module $m
+    (import "" "is-int" (func $is-int (result i32)))
+    (import "" "seed" (func $seed (result i32)))
+    (func (export "is-int") (result i32) call $is-int)
+    (func (export "seed") (result i32) call $seed))
+  (core instance $i (instantiate $m (with "" (instance
+    (export "is-int" (func $is-int))
+    (export "seed" (func $seed))))))
+
+  (func (export "is-int") (result u32) (canon lift (core func $i "is-int")))
+  (func (export "seed") (result u32) (canon lift (core func $i "seed")))
+)
+"#;
+
+    const NEWER: &str = r#"
+(component
+  (import "godot:core/typeis@0.1.0" (instance
+    (export "is-int" (func (result u32)))
+    (export "is-vector4" (func (result u32)))))
+  (import "godot:core/future@0.1.0" (instance
+    (export "poll" (func))))
+  (import "godot:global/future@0.1.0" (instance
+    (export "poll" (func))))
+)
+"#;
+
+    fn engine() -> Engine {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        Engine::new(&config).unwrap()
+    }
+
+    fn linker(engine: &Engine) -> Linker<()> {
+        let mut linker = Linker::new(engine);
+        linker
+            .instance("godot:core/typeis@0.1.0")
+            .unwrap()
+            .func_wrap("is-int", |_, ()| Ok((1u32,)))
+            .unwrap();
+        linker
+    }
+
+    #[test]
+    fn test_resolved() {
+        let engine = engine();
+        let comp = Component::new(&engine, HOST).unwrap();
+        let mut linker = linker(&engine);
+        linker
+            .instance("godot:global/noise@0.1.0")
+            .unwrap()
+            .func_wrap("seed", |_, ()| Ok((2u32,)))
+            .unwrap();
+
+        assert!(resolve_imports(&linker, &comp, false).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_missing_interface() {
+        let engine = engine();
+        let comp = Component::new(&engine, HOST).unwrap();
+        let linker = linker(&engine);
+
+        let e = resolve_imports(&linker, &comp, false).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "Component imports items not provided by plugin version {PLUGIN_VERSION}:\n\
+                 - interface `godot:global/noise@0.1.0` (since 0.1.0, optional)\n\
+                 Set compat.allow_missing_optional to stub optional interfaces."
+            )
+        );
+
+        let linker = resolve_imports(&linker, &comp, true).unwrap().unwrap();
+        let mut store = Store::new(&engine, ());
+        let inst = linker.instantiate(&mut store, &comp).unwrap();
+        let f = inst
+            .get_typed_func::<(), (u32,)>(&mut store, "is-int")
+            .unwrap();
+        assert_eq!(f.call(&mut store, ()).unwrap(), (1,));
+        f.post_return(&mut store).unwrap();
+
+        let f = inst
+            .get_typed_func::<(), (u32,)>(&mut store, "seed")
+            .unwrap();
+        let e = f.call(&mut store, ()).unwrap_err();
+        assert!(
+            format!("{e:?}").contains(&format!(
+                "Import `godot:global/noise@0.1.0#seed` is not provided by plugin version {PLUGIN_VERSION}"
+            )),
+            "{e:?}"
+        );
+    }
+
+    #[test]
+    fn test_missing_required() {
+        let engine = engine();
+        let comp = Component::new(&engine, NEWER).unwrap();
+        let linker = linker(&engine);
+
+        let e = resolve_imports(&linker, &comp, true).err().unwrap();
+        let MissingImportsError(v) = e.downcast_ref().unwrap();
+        assert_eq!(
+            v.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+            [
+                format!("interface `godot:core/future@0.1.0` (newer than {PLUGIN_VERSION}, required)"),
+                format!("interface `godot:global/future@0.1.0` (newer than {PLUGIN_VERSION}, optional)"),
+                format!("function `godot:core/typeis@0.1.0#is-vector4` (newer than {PLUGIN_VERSION}, required)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_version_compatible() {
+        assert!(version_compatible("0.1.0", "0.1.0"));
+        assert!(version_compatible("0.1.3", "0.1.0"));
+        assert!(!version_compatible("0.2.0", "0.1.0"));
+        assert!(version_compatible("1.2.0", "1.0.5"));
+        assert!(!version_compatible("0.0.1", "0.0.2"));
+        assert!(!version_compatible("0.1.0-pre", "0.1.0"));
+    }
+}
@@ -3,6 +3,7 @@ use godot::prelude::*;
 use wasmtime::component::Resource as WasmResource;
 
 use crate::filter_macro;
+use crate::wasm_instance::CallFrame;
 
 filter_macro! {method [
     empty -> "empty",
@@ -27,10 +28,14 @@ filter_macro! {method [
     remove -> "remove",
     erase -> "erase",
     fill -> "fill",
-    contains -> "rfind",
-    count -> "find",
-    find -> "count",
-    rfind -> "contains",
+    contains -> "contains",
+    count -> "count",
+    find -> "find",
+    rfind -> "rfind",
+    sort -> "sort",
+    sort_custom -> "sort-custom",
+    bsearch -> "bsearch",
+    slice -> "slice",
 ]}
 
 impl crate::godot_component::bindgen::godot::core::array::Host
@@ -290,4 +295,66 @@ impl crate::godot_component::bindgen::godot::core::array::Host
         let i = self.maybe_get_var_borrow(item)?;
         Ok(v.rfind(&*i, from.map(|v| v as _)).map(|v| v as _))
     }
+
+    fn sort(&mut self, var: WasmResource<Variant>) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, array, sort)?;
+        let mut v: VariantArray = self.get_value(var)?;
+        v.sort_unstable();
+        Ok(())
+    }
+
+    fn sort_custom(
+        &mut self,
+        var: WasmResource<Variant>,
+        compare: WasmResource<Variant>,
+    ) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, array, sort_custom)?;
+        let mut v: VariantArray = self.get_value(var)?;
+        let c: Callable = self.get_value(compare)?;
+        // Comparator might call into guest.
+        self.release_store_call(CallFrame::Callable(c.clone()), move || {
+            v.sort_unstable_custom(&c)
+        })?;
+        Ok(())
+    }
+
+    fn bsearch(
+        &mut self,
+        var: WasmResource<Variant>,
+        item: Option<WasmResource<Variant>>,
+        before: bool,
+    ) -> AnyResult<u32> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, array, bsearch)?;
+        let v: VariantArray = self.get_value(var)?;
+        let i = self.maybe_get_var(item)?;
+        let r = v
+            .to_variant()
+            .call(&StringName::from(c"bsearch"), &[i, before.to_variant()]);
+        Ok(r.try_to::<i64>().map_err(|e| e.into_erased())? as _)
+    }
+
+    fn slice(
+        &mut self,
+        var: WasmResource<Variant>,
+        begin: i32,
+        end: i32,
+        step: i32,
+        deep: bool,
+    ) -> AnyResult<WasmResource<Variant>> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, array, slice)?;
+        if step == 0 {
+            bail!("slice step cannot be zero")
+        }
+        let v: VariantArray = self.get_value(var)?;
+        let r = v.to_variant().call(
+            &StringName::from(c"slice"),
+            &[
+                begin.to_variant(),
+                end.to_variant(),
+                step.to_variant(),
+                deep.to_variant(),
+            ],
+        );
+        self.set_into_var(r.try_to::<VariantArray>().map_err(|e| e.into_erased())?)
+    }
 }
@@ -3,6 +3,7 @@ use godot::prelude::*;
 use wasmtime::component::Resource as WasmResource;
 
 use crate::filter_macro;
+use crate::wasm_instance::CallFrame;
 
 filter_macro! {method [
     invalid -> "invalid",
@@ -79,7 +80,7 @@ impl crate::godot_component::bindgen::godot::core::callable::Host
             .into_iter()
             .map(|v| self.maybe_get_var(v))
             .collect::<AnyResult<Vec<_>>>()?;
-        let r = self.release_store(move || v.call(&a));
+        let r = self.release_store_call(CallFrame::Callable(v.clone()), move || v.call(&a))?;
         self.set_var(r)
     }
 
@@ -94,7 +95,7 @@ impl crate::godot_component::bindgen::godot::core::callable::Host
             .into_iter()
             .map(|v| self.maybe_get_var(v))
             .collect::<AnyResult<Vec<_>>>()?;
-        self.release_store(move || v.call_deferred(&a));
+        self.release_store_main(move || v.call_deferred(&a))?;
         Ok(())
     }
 
@@ -106,7 +107,7 @@ impl crate::godot_component::bindgen::godot::core::callable::Host
         filter_macro!(filter self.filter.as_ref(), godot_core, callable, callv)?;
         let v: Callable = self.get_value(var)?;
         let args: VariantArray = self.get_value(args)?;
-        let r = self.release_store(move || v.callv(&args));
+        let r = self.release_store_call(CallFrame::Callable(v.clone()), move || v.callv(&args))?;
         self.set_var(r)
     }
 
@@ -173,7 +174,7 @@ impl crate::godot_component::bindgen::godot::core::callable::Host
             .into_iter()
             .map(|v| self.maybe_get_var(v))
             .collect::<AnyResult<Vec<_>>>()?;
-        self.release_store(move || v.rpc(&a));
+        self.release_store_main(move || v.rpc(&a))?;
         Ok(())
     }
 
@@ -189,7 +190,7 @@ impl crate::godot_component::bindgen::godot::core::callable::Host
             .into_iter()
             .map(|v| self.maybe_get_var(v))
             .collect::<AnyResult<Vec<_>>>()?;
-        self.release_store(move || v.rpc_id(peer_id, &a));
+        self.release_store_main(move || v.rpc_id(peer_id, &a))?;
         Ok(())
     }
 }
@@ -0,0 +1,186 @@
+use std::io::{Read, Write};
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+
+/// Compression algorithm of `byte-array` interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Gzip,
+    Deflate,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressError {
+    /// Unknown algorithm name.
+    UnknownAlgorithm,
+    /// Compression level is out of range.
+    InvalidLevel,
+    /// Decompressed data is larger than maximum size.
+    TooLarge,
+    /// Compressed data is corrupted.
+    InvalidData,
+}
+
+impl Algorithm {
+    pub fn from_name(s: &str) -> Result<Self, CompressError> {
+        match s {
+            "gzip" => Ok(Self::Gzip),
+            "deflate" => Ok(Self::Deflate),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(CompressError::UnknownAlgorithm),
+        }
+    }
+
+    /// Checks level, `None` selects default level.
+    fn level(self, level: Option<u32>) -> Result<u32, CompressError> {
+        let (default, max) = match self {
+            Self::Gzip | Self::Deflate => (6, 9),
+            Self::Zstd => (3, 22),
+        };
+        match level {
+            None => Ok(default),
+            // Zstd level 0 means default, disallow it to prevent confusion.
+            Some(0) if self == Self::Zstd => Err(CompressError::InvalidLevel),
+            Some(v) if v <= max => Ok(v),
+            Some(_) => Err(CompressError::InvalidLevel),
+        }
+    }
+
+    pub fn compress(self, data: &[u8], level: Option<u32>) -> Result<Vec<u8>, CompressError> {
+        let level = self.level(level)?;
+        // Writing into Vec should never fail.
+        let ret = match self {
+            Self::Gzip => {
+                let mut w = GzEncoder::new(Vec::new(), Compression::new(level));
+                w.write_all(data).and_then(|_| w.finish())
+            }
+            Self::Deflate => {
+                let mut w = DeflateEncoder::new(Vec::new(), Compression::new(level));
+                w.write_all(data).and_then(|_| w.finish())
+            }
+            Self::Zstd => zstd::bulk::compress(data, level as _),
+        };
+        ret.map_err(|_| CompressError::InvalidData)
+    }
+
+    /// Decompresses data.
+    ///
+    /// Output is never allowed to grow larger than `max_size`,
+    /// so compressed data with enormous ratio can't exhaust memory.
+    pub fn decompress(self, data: &[u8], max_size: u32) -> Result<Vec<u8>, CompressError> {
+        let r: Box<dyn Read + '_> = match self {
+            Self::Gzip => Box::new(GzDecoder::new(data)),
+            Self::Deflate => Box::new(DeflateDecoder::new(data)),
+            Self::Zstd => Box::new(
+                zstd::stream::read::Decoder::with_buffer(data)
+                    .map_err(|_| CompressError::InvalidData)?,
+            ),
+        };
+
+        // Read one more byte to detect overflow.
+        let mut ret = Vec::new();
+        r.take(u64::from(max_size) + 1)
+            .read_to_end(&mut ret)
+            .map_err(|_| CompressError::InvalidData)?;
+        if ret.len() > max_size as usize {
+            return Err(CompressError::TooLarge);
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [Algorithm; 3] = [Algorithm::Gzip, Algorithm::Deflate, Algorithm::Zstd];
+
+    fn sample() -> Vec<u8> {
+        (0..100_000u32)
+            .map(|i| (i % 251) as u8 ^ (i >> 10) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_algorithm_name() {
+        assert_eq!(Algorithm::from_name("gzip"), Ok(Algorithm::Gzip));
+        assert_eq!(Algorithm::from_name("deflate"), Ok(Algorithm::Deflate));
+        assert_eq!(Algorithm::from_name("zstd"), Ok(Algorithm::Zstd));
+        assert_eq!(
+            Algorithm::from_name("brotli"),
+            Err(CompressError::UnknownAlgorithm)
+        );
+        assert_eq!(
+            Algorithm::from_name("GZIP"),
+            Err(CompressError::UnknownAlgorithm)
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data = sample();
+        for a in ALGORITHMS {
+            for level in [None, Some(1), Some(9)] {
+                let c = a.compress(&data, level).unwrap();
+                assert!(c.len() < data.len(), "{a:?} {level:?}");
+                assert_eq!(a.decompress(&c, data.len() as _).unwrap(), data);
+            }
+
+            let c = a.compress(&[], None).unwrap();
+            assert_eq!(a.decompress(&c, 0).unwrap(), []);
+        }
+    }
+
+    #[test]
+    fn test_invalid_level() {
+        assert_eq!(
+            Algorithm::Gzip.compress(b"abc", Some(10)),
+            Err(CompressError::InvalidLevel)
+        );
+        assert_eq!(
+            Algorithm::Deflate.compress(b"abc", Some(10)),
+            Err(CompressError::InvalidLevel)
+        );
+        assert_eq!(
+            Algorithm::Zstd.compress(b"abc", Some(23)),
+            Err(CompressError::InvalidLevel)
+        );
+        assert_eq!(
+            Algorithm::Zstd.compress(b"abc", Some(0)),
+            Err(CompressError::InvalidLevel)
+        );
+        assert!(Algorithm::Zstd.compress(b"abc", Some(22)).is_ok());
+        assert!(Algorithm::Gzip.compress(b"abc", Some(0)).is_ok());
+    }
+
+    #[test]
+    fn test_max_size() {
+        // Highly compressible, like a zip bomb.
+        let data = vec![0u8; 1 << 20];
+        for a in ALGORITHMS {
+            let c = a.compress(&data, Some(9)).unwrap();
+            assert_eq!(
+                a.decompress(&c, data.len() as u32 - 1),
+                Err(CompressError::TooLarge),
+                "{a:?}"
+            );
+            assert_eq!(a.decompress(&c, 0), Err(CompressError::TooLarge));
+            assert_eq!(a.decompress(&c, data.len() as _).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        // Bad magic for gzip and zstd, reserved block type for deflate.
+        for a in ALGORITHMS {
+            assert_eq!(
+                a.decompress(&[0xff; 16], 1024),
+                Err(CompressError::InvalidData),
+                "{a:?}"
+            );
+        }
+    }
+}
@@ -1,10 +1,13 @@
 mod array;
 mod callable;
+mod compression;
 mod dictionary;
 mod object;
 mod packed_array;
 mod primitive;
-mod signal;
+pub(crate) mod signal;
+mod task;
+mod transform;
 mod typeis;
 
 crate::filter_macro! {interface [
@@ -25,6 +28,8 @@ crate::filter_macro! {interface [
     dictionary <dictionary> -> "dictionary",
     object <object> -> "object",
     signal <signal> -> "signal",
+    transform <transform> -> "transform",
+    task <task> -> "task",
 ]}
 
 mod core_filter {
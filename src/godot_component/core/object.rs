@@ -1,9 +1,16 @@
+use std::ops::DerefMut;
+
+use std::error::Error;
+
 use anyhow::{bail, Result as AnyResult};
+use godot::meta::error::CallError as GodotCallError;
 use godot::prelude::*;
 use wasmtime::component::Resource as WasmResource;
 
 use crate::filter_macro;
+use crate::godot_component::bindgen::godot::core::object::CallError;
 use crate::godot_component::{bindgen, wrap_error, ErrorRes, GodotCtx};
+use crate::wasm_instance::CallFrame;
 use crate::wasm_util::get_godot_param_cache;
 
 filter_macro! {method [
@@ -25,6 +32,7 @@ filter_macro! {method [
     get_method_argument_count -> "get-method-argument-count",
     has_signal -> "has-signal",
     call -> "call",
+    call_strict -> "call-strict",
     callv -> "callv",
     call_deferred -> "call-deferred",
     connect -> "connect",
@@ -47,9 +55,71 @@ filter_macro! {method [
     tr_n -> "tr-n",
 ]}
 
+/// Start of reason in gdext call error message.
+const REASON_PREFIX: &str = "\n    Reason: ";
+
+/// Parses number at the start of `s`.
+fn leading_number(s: &str) -> Option<u32> {
+    s[..s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len())]
+        .parse()
+        .ok()
+}
+
+/// Converts gdext call error into [`CallError`].
+///
+/// Godot call error status is not exposed by gdext, so it's reconstructed from reason.
+/// Error with source comes from inside called method, so it's always script error.
+fn to_call_error(e: GodotCallError) -> CallError {
+    let msg = e.to_string();
+    if e.source().is_some() {
+        return CallError::ScriptError(msg);
+    }
+    from_reason(&msg).unwrap_or(CallError::ScriptError(msg))
+}
+
+/// Parses reason of call error message (without source).
+///
+/// Reasons are formatted by `CallError::failed_varcall_inner()` of gdext revision pinned in `Cargo.toml`.
+/// Reason is at the end of message, so arguments in call expression can't be mistaken as reason.
+fn from_reason(msg: &str) -> Option<CallError> {
+    let reason = &msg[msg.rfind(REASON_PREFIX)? + REASON_PREFIX.len()..];
+    Some(if reason == "method not found" {
+        CallError::MethodNotFound
+    } else if let Some(s) = reason
+        .strip_prefix("parameter #")
+        .filter(|s| s.contains(" -- cannot convert from "))
+    {
+        CallError::InvalidArgumentType(leading_number(s)?.saturating_sub(1))
+    } else if let Some(s) = reason
+        .strip_prefix("function has ")
+        .filter(|s| s.contains(", but received "))
+    {
+        CallError::InvalidArgumentCount(leading_number(s)?)
+    } else {
+        return None;
+    })
+}
+
+impl GodotCtx {
+    fn call_args(
+        &mut self,
+        var: WasmResource<Variant>,
+        name: WasmResource<Variant>,
+        args: Vec<Option<WasmResource<Variant>>>,
+    ) -> AnyResult<(Gd<Object>, StringName, impl DerefMut<Target = [Variant]>)> {
+        let o: Gd<Object> = self.get_value(var)?;
+        let name: StringName = self.get_value(name)?;
+        let mut a = get_godot_param_cache(args.len());
+        for (i, v) in args.into_iter().enumerate() {
+            a[i] = self.maybe_get_var(v)?;
+        }
+        Ok((o, name, a))
+    }
+}
+
 impl bindgen::godot::core::object::Host for GodotCtx {
     fn from_instance_id(&mut self, id: i64) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, from_instance_id)?;
+        filter_macro!(filter ctx self, godot_core, object, from_instance_id)?;
         let Some(id) = InstanceId::try_from_i64(id) else {
             bail!("Instance ID is 0")
         };
@@ -58,41 +128,41 @@ impl bindgen::godot::core::object::Host for GodotCtx {
     }
 
     fn instance_id(&mut self, var: WasmResource<Variant>) -> AnyResult<i64> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, instance_id)?;
+        filter_macro!(filter ctx self, godot_core, object, instance_id)?;
         self.get_value::<Gd<Object>>(var)
             .map(|v| v.instance_id().to_i64())
     }
 
     fn free(&mut self, var: WasmResource<Variant>) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, free)?;
+        filter_macro!(filter ctx self, godot_core, object, free)?;
         let o: Gd<Object> = self.get_value(var)?;
-        self.release_store(move || o.free());
+        self.release_store_main(move || o.free())?;
         Ok(())
     }
 
     // It's weird that is_queued_for_deletion and cancel_free are object method, but queue_free is node method.
     // So for symmetry reason upgrade it to object method.
     fn queue_free(&mut self, var: WasmResource<Variant>) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, queue_free)?;
+        filter_macro!(filter ctx self, godot_core, object, queue_free)?;
         let mut o: Gd<Node> = self.get_value(var)?;
-        self.release_store(move || o.queue_free());
+        self.release_store_main(move || o.queue_free())?;
         Ok(())
     }
 
     fn is_queued_for_deletion(&mut self, var: WasmResource<Variant>) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, is_queued_for_deletion)?;
+        filter_macro!(filter ctx self, godot_core, object, is_queued_for_deletion)?;
         self.get_value::<Gd<Object>>(var)
             .map(|o| o.is_queued_for_deletion())
     }
 
     fn cancel_free(&mut self, var: WasmResource<Variant>) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, cancel_free)?;
+        filter_macro!(filter ctx self, godot_core, object, cancel_free)?;
         self.get_value::<Gd<Object>>(var)
             .map(|mut o| o.cancel_free())
     }
 
     fn get_class(&mut self, var: WasmResource<Variant>) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get_class)?;
+        filter_macro!(filter ctx self, godot_core, object, get_class)?;
         let o: Gd<Object> = self.get_value(var)?;
         self.set_into_var(o.get_class())
     }
@@ -102,7 +172,7 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         class: WasmResource<Variant>,
     ) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, is_class)?;
+        filter_macro!(filter ctx self, godot_core, object, is_class)?;
         let o: Gd<Object> = self.get_value(var)?;
         let c: GString = self.get_value(class)?;
         Ok(o.is_class(&c))
@@ -112,7 +182,7 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         &mut self,
         var: WasmResource<Variant>,
     ) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get_script)?;
+        filter_macro!(filter ctx self, godot_core, object, get_script)?;
         let o: Gd<Object> = self.get_value(var)?;
         self.set_var(o.get_script())
     }
@@ -121,30 +191,30 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         &mut self,
         var: WasmResource<Variant>,
     ) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get_property_list)?;
+        filter_macro!(filter ctx self, godot_core, object, get_property_list)?;
         let o: Gd<Object> = self.get_value(var)?;
-        let r = self.release_store(move || o.get_property_list());
+        let r = self.release_store_main(move || o.get_property_list())?;
         self.set_into_var(r)
     }
 
     fn get_meta_list(&mut self, var: WasmResource<Variant>) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get_meta_list)?;
+        filter_macro!(filter ctx self, godot_core, object, get_meta_list)?;
         let o: Gd<Object> = self.get_value(var)?;
-        let r = self.release_store(move || o.get_meta_list());
+        let r = self.release_store_main(move || o.get_meta_list())?;
         self.set_into_var(r)
     }
 
     fn get_method_list(&mut self, var: WasmResource<Variant>) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get_method_list)?;
+        filter_macro!(filter ctx self, godot_core, object, get_method_list)?;
         let o: Gd<Object> = self.get_value(var)?;
-        let r = self.release_store(move || o.get_method_list());
+        let r = self.release_store_main(move || o.get_method_list())?;
         self.set_into_var(r)
     }
 
     fn get_signal_list(&mut self, var: WasmResource<Variant>) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get_signal_list)?;
+        filter_macro!(filter ctx self, godot_core, object, get_signal_list)?;
         let o: Gd<Object> = self.get_value(var)?;
-        let r = self.release_store(move || o.get_signal_list());
+        let r = self.release_store_main(move || o.get_signal_list())?;
         self.set_into_var(r)
     }
 
@@ -153,10 +223,10 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         name: WasmResource<Variant>,
     ) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, has_meta)?;
+        filter_macro!(filter ctx self, godot_core, object, has_meta)?;
         let o: Gd<Object> = self.get_value(var)?;
         let n: StringName = self.get_value(name)?;
-        Ok(self.release_store(move || o.has_meta(&n)))
+        self.release_store_main(move || o.has_meta(&n))
     }
 
     fn has_method(
@@ -164,10 +234,10 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         name: WasmResource<Variant>,
     ) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, has_method)?;
+        filter_macro!(filter ctx self, godot_core, object, has_method)?;
         let o: Gd<Object> = self.get_value(var)?;
         let n: StringName = self.get_value(name)?;
-        Ok(self.release_store(move || o.has_method(&n)))
+        self.release_store_main(move || o.has_method(&n))
     }
 
     fn get_method_argument_count(
@@ -175,10 +245,10 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         name: WasmResource<Variant>,
     ) -> AnyResult<i32> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get_method_argument_count)?;
+        filter_macro!(filter ctx self, godot_core, object, get_method_argument_count)?;
         let o: Gd<Object> = self.get_value(var)?;
         let n: StringName = self.get_value(name)?;
-        Ok(self.release_store(move || o.get_method_argument_count(&n)))
+        self.release_store_main(move || o.get_method_argument_count(&n))
     }
 
     fn has_signal(
@@ -186,10 +256,10 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         name: WasmResource<Variant>,
     ) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, has_signal)?;
+        filter_macro!(filter ctx self, godot_core, object, has_signal)?;
         let o: Gd<Object> = self.get_value(var)?;
         let n: StringName = self.get_value(name)?;
-        Ok(self.release_store(move || o.has_signal(&n)))
+        self.release_store_main(move || o.has_signal(&n))
     }
 
     fn call(
@@ -197,15 +267,39 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         name: WasmResource<Variant>,
         args: Vec<Option<WasmResource<Variant>>>,
-    ) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, call)?;
-        let mut o: Gd<Object> = self.get_value(var)?;
-        let name: StringName = self.get_value(name)?;
-        let mut a = get_godot_param_cache(args.len());
-        for (i, v) in args.into_iter().enumerate() {
-            a[i] = self.maybe_get_var(v)?;
+    ) -> AnyResult<Result<Option<WasmResource<Variant>>, CallError>> {
+        filter_macro!(filter ctx self, godot_core, object, call)?;
+        let (mut o, name, a) = self.call_args(var, name, args)?;
+        let frame = CallFrame::Method {
+            obj: o.instance_id(),
+            method: name.clone(),
+        };
+        let r = self.release_store_call(frame, move || {
+            if o.has_method(&name) {
+                o.try_call(&name, &a).map_err(to_call_error)
+            } else {
+                Err(CallError::MethodNotFound)
+            }
+        })?;
+        match r {
+            Ok(v) => self.set_var(v).map(Ok),
+            Err(e) => Ok(Err(e)),
         }
-        let r = self.release_store(move || o.try_call(&name, &a))?;
+    }
+
+    fn call_strict(
+        &mut self,
+        var: WasmResource<Variant>,
+        name: WasmResource<Variant>,
+        args: Vec<Option<WasmResource<Variant>>>,
+    ) -> AnyResult<Option<WasmResource<Variant>>> {
+        filter_macro!(filter ctx self, godot_core, object, call_strict)?;
+        let (mut o, name, a) = self.call_args(var, name, args)?;
+        let frame = CallFrame::Method {
+            obj: o.instance_id(),
+            method: name.clone(),
+        };
+        let r = self.release_store_call(frame, move || o.try_call(&name, &a))??;
         self.set_var(r)
     }
 
@@ -215,11 +309,15 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         name: WasmResource<Variant>,
         args: WasmResource<Variant>,
     ) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, callv)?;
+        filter_macro!(filter ctx self, godot_core, object, callv)?;
         let mut o: Gd<Object> = self.get_value(var)?;
         let name: StringName = self.get_value(name)?;
         let args: VariantArray = self.get_value(args)?;
-        let r = self.release_store(move || o.callv(&name, &args));
+        let frame = CallFrame::Method {
+            obj: o.instance_id(),
+            method: name.clone(),
+        };
+        let r = self.release_store_call(frame, move || o.callv(&name, &args))?;
         self.set_var(r)
     }
 
@@ -228,16 +326,20 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         name: WasmResource<Variant>,
         args: Vec<Option<WasmResource<Variant>>>,
-    ) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, call_deferred)?;
-        let mut o: Gd<Object> = self.get_value(var)?;
-        let name: StringName = self.get_value(name)?;
-        let mut a = get_godot_param_cache(args.len());
-        for (i, v) in args.into_iter().enumerate() {
-            a[i] = self.maybe_get_var(v)?;
+    ) -> AnyResult<Result<Option<WasmResource<Variant>>, CallError>> {
+        filter_macro!(filter ctx self, godot_core, object, call_deferred)?;
+        let (mut o, name, a) = self.call_args(var, name, args)?;
+        let r = self.release_store_main(move || {
+            if o.has_method(&name) {
+                o.try_call_deferred(&name, &a).map_err(to_call_error)
+            } else {
+                Err(CallError::MethodNotFound)
+            }
+        })?;
+        match r {
+            Ok(v) => self.set_var(v).map(Ok),
+            Err(e) => Ok(Err(e)),
         }
-        let r = self.release_store(move || o.try_call_deferred(&name, &a))?;
-        self.set_var(r)
     }
 
     fn connect(
@@ -247,7 +349,7 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         callable: WasmResource<Variant>,
         flags: u32,
     ) -> ErrorRes {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, connect)?;
+        filter_macro!(filter res self, godot_core, object, connect);
         let mut o: Gd<Object> = self.get_value(var)?;
         wrap_error(
             o.connect_ex(
@@ -265,7 +367,7 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         name: WasmResource<Variant>,
         callable: WasmResource<Variant>,
     ) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, disconnect)?;
+        filter_macro!(filter ctx self, godot_core, object, disconnect)?;
         let mut o: Gd<Object> = self.get_value(var)?;
         o.disconnect(
             &self.get_value::<StringName>(name)?,
@@ -280,7 +382,7 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         name: WasmResource<Variant>,
         callable: WasmResource<Variant>,
     ) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, is_connected)?;
+        filter_macro!(filter ctx self, godot_core, object, is_connected)?;
         let o: Gd<Object> = self.get_value(var)?;
         Ok(o.is_connected(
             &self.get_value::<StringName>(name)?,
@@ -294,26 +396,26 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         name: WasmResource<Variant>,
         args: Vec<Option<WasmResource<Variant>>>,
     ) -> ErrorRes {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, emit_signal)?;
+        filter_macro!(filter res self, godot_core, object, emit_signal);
         let mut o: Gd<Object> = self.get_value(var)?;
         let name: StringName = self.get_value(name)?;
         let args = args
             .into_iter()
             .map(|v| self.maybe_get_var(v))
             .collect::<AnyResult<Vec<_>>>()?;
-        wrap_error(self.release_store(move || o.try_emit_signal(&name, &args))?)
+        wrap_error(self.release_store_main(move || o.try_emit_signal(&name, &args))??)
     }
 
     fn is_blocking_signals(&mut self, var: WasmResource<Variant>) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, is_blocking_signals)?;
+        filter_macro!(filter ctx self, godot_core, object, is_blocking_signals)?;
         let o: Gd<Object> = self.get_value(var)?;
-        Ok(self.release_store(move || o.is_blocking_signals()))
+        self.release_store_main(move || o.is_blocking_signals())
     }
 
     fn set_block_signals(&mut self, var: WasmResource<Variant>, val: bool) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, set_block_signals)?;
+        filter_macro!(filter ctx self, godot_core, object, set_block_signals)?;
         let mut o: Gd<Object> = self.get_value(var)?;
-        self.release_store(move || o.set_block_signals(val));
+        self.release_store_main(move || o.set_block_signals(val))?;
         Ok(())
     }
 
@@ -322,10 +424,10 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         name: WasmResource<Variant>,
     ) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get)?;
+        filter_macro!(filter ctx self, godot_core, object, get)?;
         let o: Gd<Object> = self.get_value(var)?;
         let name: StringName = self.get_value(name)?;
-        let r = self.release_store(move || o.get(&name));
+        let r = self.release_store_main(move || o.get(&name))?;
         self.set_var(r)
     }
 
@@ -335,11 +437,11 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         name: WasmResource<Variant>,
         val: Option<WasmResource<Variant>>,
     ) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, set)?;
+        filter_macro!(filter ctx self, godot_core, object, set)?;
         let mut o: Gd<Object> = self.get_value(var)?;
         let n: StringName = self.get_value(name)?;
         let v = self.maybe_get_var(val)?;
-        self.release_store(move || o.set(&n, &v));
+        self.release_store_main(move || o.set(&n, &v))?;
         Ok(())
     }
 
@@ -349,11 +451,11 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         name: WasmResource<Variant>,
         val: Option<WasmResource<Variant>>,
     ) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, set_deferred)?;
+        filter_macro!(filter ctx self, godot_core, object, set_deferred)?;
         let mut o: Gd<Object> = self.get_value(var)?;
         let n: StringName = self.get_value(name)?;
         let v = self.maybe_get_var(val)?;
-        self.release_store(move || o.set_deferred(&n, &v));
+        self.release_store_main(move || o.set_deferred(&n, &v))?;
         Ok(())
     }
 
@@ -362,10 +464,10 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         name: WasmResource<Variant>,
     ) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get_indexed)?;
+        filter_macro!(filter ctx self, godot_core, object, get_indexed)?;
         let o: Gd<Object> = self.get_value(var)?;
         let name: NodePath = self.get_value(name)?;
-        let r = self.release_store(move || o.get_indexed(&name));
+        let r = self.release_store_main(move || o.get_indexed(&name))?;
         self.set_var(r)
     }
 
@@ -375,11 +477,11 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         name: WasmResource<Variant>,
         val: Option<WasmResource<Variant>>,
     ) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, set_indexed)?;
+        filter_macro!(filter ctx self, godot_core, object, set_indexed)?;
         let mut o: Gd<Object> = self.get_value(var)?;
         let n: NodePath = self.get_value(name)?;
         let v = self.maybe_get_var(val)?;
-        self.release_store(move || o.set_indexed(&n, &v));
+        self.release_store_main(move || o.set_indexed(&n, &v))?;
         Ok(())
     }
 
@@ -389,11 +491,11 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         name: WasmResource<Variant>,
         default: Option<WasmResource<Variant>>,
     ) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, get_meta)?;
+        filter_macro!(filter ctx self, godot_core, object, get_meta)?;
         let o: Gd<Object> = self.get_value(var)?;
         let name: StringName = self.get_value(name)?;
         let default = self.maybe_get_var(default)?;
-        let r = self.release_store(move || o.get_meta_ex(&name).default(&default).done());
+        let r = self.release_store_main(move || o.get_meta_ex(&name).default(&default).done())?;
         self.set_var(r)
     }
 
@@ -403,11 +505,11 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         name: WasmResource<Variant>,
         val: Option<WasmResource<Variant>>,
     ) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, set_meta)?;
+        filter_macro!(filter ctx self, godot_core, object, set_meta)?;
         let mut o: Gd<Object> = self.get_value(var)?;
         let n: StringName = self.get_value(name)?;
         let v = self.maybe_get_var(val)?;
-        self.release_store(move || o.set_meta(&n, &v));
+        self.release_store_main(move || o.set_meta(&n, &v))?;
         Ok(())
     }
 
@@ -416,20 +518,20 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         var: WasmResource<Variant>,
         name: WasmResource<Variant>,
     ) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, remove_meta)?;
+        filter_macro!(filter ctx self, godot_core, object, remove_meta)?;
         let mut o: Gd<Object> = self.get_value(var)?;
         let name: StringName = self.get_value(name)?;
-        self.release_store(move || o.remove_meta(&name));
+        self.release_store_main(move || o.remove_meta(&name))?;
         Ok(())
     }
 
     fn can_translate_messages(&mut self, var: WasmResource<Variant>) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, can_translate_messages)?;
+        filter_macro!(filter ctx self, godot_core, object, can_translate_messages)?;
         Ok(self.get_value::<Gd<Object>>(var)?.can_translate_messages())
     }
 
     fn set_message_translation(&mut self, var: WasmResource<Variant>, val: bool) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, set_message_translation)?;
+        filter_macro!(filter ctx self, godot_core, object, set_message_translation)?;
         self.get_value::<Gd<Object>>(var)?
             .set_message_translation(val);
         Ok(())
@@ -441,7 +543,7 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         msg: WasmResource<Variant>,
         ctx: Option<WasmResource<Variant>>,
     ) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, tr)?;
+        filter_macro!(filter ctx self, godot_core, object, tr)?;
         let o: Gd<Object> = self.get_value(var)?;
         let m: StringName = self.get_value(msg)?;
         let r = if let Some(ctx) = ctx {
@@ -462,7 +564,7 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         n: i32,
         ctx: Option<WasmResource<Variant>>,
     ) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, object, tr_n)?;
+        filter_macro!(filter ctx self, godot_core, object, tr_n)?;
         let o: Gd<Object> = self.get_value(var)?;
         let m: StringName = self.get_value(msg)?;
         let p: StringName = self.get_value(plural)?;
@@ -476,3 +578,47 @@ impl bindgen::godot::core::object::Host for GodotCtx {
         self.set_into_var(r)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Messages as formatted by gdext.
+    #[test]
+    fn test_from_reason() {
+        assert!(matches!(
+            from_reason(
+                "godot-rust function call failed: Object::call(&\"foo\")\n    Reason: method not found"
+            ),
+            Some(CallError::MethodNotFound)
+        ));
+        assert!(matches!(
+            from_reason(
+                "godot-rust function call failed: Object::call(&\"foo\", [va] 1, 2)\n    Reason: parameter #2 -- cannot convert from INT to OBJECT"
+            ),
+            Some(CallError::InvalidArgumentType(1))
+        ));
+        assert!(matches!(
+            from_reason(
+                "godot-rust function call failed: Object::call(&\"foo\", [va] 1)\n    Reason: function has 3 parameters, but received 1 argument"
+            ),
+            Some(CallError::InvalidArgumentCount(3))
+        ));
+        assert!(matches!(
+            from_reason(
+                "godot-rust function call failed: Object::call(&\"foo\")\n    Reason: function has 1 parameter, but received 0 arguments"
+            ),
+            Some(CallError::InvalidArgumentCount(1))
+        ));
+        assert!(from_reason(
+            "godot-rust function call failed: Object::call(&\"foo\")\n    Reason: instance is null"
+        )
+        .is_none());
+        // Reason-like argument is ignored.
+        assert!(from_reason(
+            "godot-rust function call failed: Object::call(&\"foo\", [va] \"\n    Reason: method not found\")\n    Reason: method is not const"
+        )
+        .is_none());
+        assert!(from_reason("method not found").is_none());
+    }
+}
@@ -2,11 +2,32 @@ use anyhow::{bail, Result as AnyResult};
 use godot::prelude::*;
 use wasmtime::component::Resource as WasmResource;
 
+use super::compression::{Algorithm, CompressError};
 use crate::filter_macro;
-use crate::godot_component::GodotCtx;
+use crate::godot_component::bindgen::godot::core::core::Error as RetError;
+use crate::godot_component::{ErrorRes, GodotCtx};
+
+/// Gets at most `len` elements starting at `begin`.
+///
+/// Unlike `slice`, range is clamped to the end, so guest can read array in chunks until it's empty.
+fn clamp_range<T>(s: &[T], begin: u32, len: u32) -> AnyResult<&[T]> {
+    let Some(s) = s.get(begin as usize..) else {
+        bail!("index {begin} out of bound")
+    };
+    Ok(&s[..s.len().min(len as usize)])
+}
+
+fn compress_error(e: CompressError) -> RetError {
+    match e {
+        CompressError::UnknownAlgorithm => RetError::ErrInvalidParameter,
+        CompressError::InvalidLevel => RetError::ErrParameterRangeError,
+        CompressError::TooLarge => RetError::ErrOutOfMemory,
+        CompressError::InvalidData => RetError::ErrInvalidData,
+    }
+}
 
 macro_rules! impl_packed_array {
-    ($m:ident $s:ident <$t:ty>) => {
+    ($m:ident $s:ident <$t:ty> $([$($fi:ident -> $fs:literal),* $(,)?] {$($extra:tt)*})?) => {
         use crate::godot_component::bindgen::godot::core::$m;
 
         pub mod $s {
@@ -14,6 +35,7 @@ macro_rules! impl_packed_array {
                 from -> "from",
                 to -> "to",
                 slice -> "slice",
+                to_list_range -> "to-list-range",
                 len -> "len",
                 is_empty -> "is-empty",
                 get -> "get",
@@ -22,6 +44,7 @@ macro_rules! impl_packed_array {
                 find -> "find",
                 rfind -> "rfind",
                 subarray -> "subarray",
+                $($($fi -> $fs,)*)?
             ]}
         }
 
@@ -50,6 +73,17 @@ macro_rules! impl_packed_array {
                 Ok(v.to_owned())
             }
 
+            fn to_list_range(
+                &mut self,
+                var: WasmResource<Variant>,
+                begin: u32,
+                len: u32,
+            ) -> AnyResult<Vec<$m::Elem>> {
+                filter_macro!(filter self.filter.as_ref(), godot_core, $m, to_list_range)?;
+                let v: $t = self.get_value(var)?;
+                Ok(clamp_range(v.as_slice(), begin, len)?.to_owned())
+            }
+
             fn len(&mut self, var: WasmResource<Variant>) -> AnyResult<u32> {
                 filter_macro!(filter self.filter.as_ref(), godot_core, $m, len)?;
                 Ok(self.get_value::<$t>(var)?.len() as _)
@@ -109,6 +143,8 @@ macro_rules! impl_packed_array {
                 let v: $t = self.get_value(var)?;
                 self.set_into_var(v.subarray(begin as _, end as _))
             }
+
+            $($($extra)*)?
         }
     };
     ($m:ident $s:ident <$t:ty> |$v:ident|($e1:expr, $e2:expr)) => {
@@ -119,6 +155,7 @@ macro_rules! impl_packed_array {
                 from -> "from",
                 to -> "to",
                 slice -> "slice",
+                to_list_range -> "to-list-range",
                 len -> "len",
                 is_empty -> "is-empty",
                 get -> "get",
@@ -155,6 +192,17 @@ macro_rules! impl_packed_array {
                 Ok(v.iter().map(|$v| $e2).collect())
             }
 
+            fn to_list_range(
+                &mut self,
+                var: WasmResource<Variant>,
+                begin: u32,
+                len: u32,
+            ) -> AnyResult<Vec<$m::Elem>> {
+                filter_macro!(filter self.filter.as_ref(), godot_core, $m, to_list_range)?;
+                let v: $t = self.get_value(var)?;
+                Ok(clamp_range(v.as_slice(), begin, len)?.iter().map(|$v| $e2).collect())
+            }
+
             fn len(&mut self, var: WasmResource<Variant>) -> AnyResult<u32> {
                 filter_macro!(filter self.filter.as_ref(), godot_core, $m, len)?;
                 Ok(self.get_value::<$t>(var)?.len() as _)
@@ -218,7 +266,38 @@ macro_rules! impl_packed_array {
     };
 }
 
-impl_packed_array! {byte_array byte_array_filter <PackedByteArray>}
+impl_packed_array! {byte_array byte_array_filter <PackedByteArray> [
+    compress -> "compress",
+    decompress -> "decompress",
+] {
+    fn compress(
+        &mut self,
+        var: WasmResource<Variant>,
+        algorithm: String,
+        level: Option<u32>,
+    ) -> ErrorRes<WasmResource<Variant>> {
+        filter_macro!(filter res self, godot_core, byte_array, compress);
+        let v: PackedByteArray = self.get_value(var)?;
+        match Algorithm::from_name(&algorithm).and_then(|a| a.compress(v.as_slice(), level)) {
+            Ok(v) => self.set_into_var(PackedByteArray::from(&*v)).map(Ok),
+            Err(e) => Ok(Err(compress_error(e))),
+        }
+    }
+
+    fn decompress(
+        &mut self,
+        var: WasmResource<Variant>,
+        algorithm: String,
+        max_size: u32,
+    ) -> ErrorRes<WasmResource<Variant>> {
+        filter_macro!(filter res self, godot_core, byte_array, decompress);
+        let v: PackedByteArray = self.get_value(var)?;
+        match Algorithm::from_name(&algorithm).and_then(|a| a.decompress(v.as_slice(), max_size)) {
+            Ok(v) => self.set_into_var(PackedByteArray::from(&*v)).map(Ok),
+            Err(e) => Ok(Err(compress_error(e))),
+        }
+    }
+}}
 impl_packed_array! {int32_array int32_array_filter <PackedInt32Array>}
 impl_packed_array! {int64_array int64_array_filter <PackedInt64Array>}
 impl_packed_array! {float32_array float32_array_filter <PackedFloat32Array>}
@@ -234,6 +313,7 @@ pub mod string_array_filter {
         from -> "from",
         to -> "to",
         slice -> "slice",
+        to_list_range -> "to-list-range",
         len -> "len",
         is_empty -> "is-empty",
         get -> "get",
@@ -279,6 +359,20 @@ impl string_array::Host for GodotCtx {
         Ok(v.iter().map(|v| v.to_string()).collect())
     }
 
+    fn to_list_range(
+        &mut self,
+        var: WasmResource<Variant>,
+        begin: u32,
+        len: u32,
+    ) -> AnyResult<Vec<String>> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, string_array, to_list_range)?;
+        let v: PackedStringArray = self.get_value(var)?;
+        Ok(clamp_range(v.as_slice(), begin, len)?
+            .iter()
+            .map(|v| v.to_string())
+            .collect())
+    }
+
     fn len(&mut self, var: WasmResource<Variant>) -> AnyResult<u32> {
         filter_macro!(filter self.filter.as_ref(), godot_core, string_array, len)?;
         Ok(self.get_value::<PackedStringArray>(var)?.len() as _)
@@ -345,3 +439,34 @@ impl string_array::Host for GodotCtx {
         self.set_into_var(v.subarray(begin as _, end as _))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only `to-list-range` is new, `from` and `to` already move whole list in one call.
+    // Host methods need Godot packed arrays, so this tests chunked reading they're built on.
+    #[test]
+    fn test_clamp_range_chunked() {
+        const N: usize = 1_000_000;
+        const CHUNK: u32 = 1 << 16;
+
+        let src = (0..N).map(|i| i as f32 * 0.5).collect::<Vec<_>>();
+        let mut dst = Vec::new();
+        let mut calls = 0;
+        loop {
+            let v = clamp_range(&src, dst.len() as u32, CHUNK).unwrap();
+            calls += 1;
+            if v.is_empty() {
+                break;
+            }
+            dst.extend_from_slice(v);
+        }
+        assert_eq!(dst, src);
+        // One call per chunk, plus the final empty one.
+        assert_eq!(calls, N.div_ceil(CHUNK as usize) + 1);
+
+        assert_eq!(clamp_range(&src, N as u32 - 2, 10).unwrap(), &src[N - 2..]);
+        assert!(clamp_range(&src, N as u32 + 1, 1).is_err());
+    }
+}
@@ -1,9 +1,14 @@
 use anyhow::Result as AnyResult;
+use godot::classes::object::ConnectFlags;
+use godot::global::Error;
 use godot::prelude::*;
 use wasmtime::component::Resource as WasmResource;
 
 use crate::filter_macro;
+use crate::godot_component::bindgen::godot::core::signal::ConnectionFlags;
 use crate::godot_component::{bindgen, wrap_error, ErrorRes, GodotCtx};
+use crate::godot_util::SendSyncWrapper;
+use crate::wasm_instance::CallFrame;
 use crate::wasm_util::get_godot_param_cache;
 
 filter_macro! {method [
@@ -11,11 +16,75 @@ filter_macro! {method [
     object -> "object",
     name -> "name",
     connect -> "connect",
+    connect_flags -> "connect-flags",
     disconnect -> "disconnect",
     is_connected -> "is-connected",
     emit -> "emit",
 ]}
 
+/// Connections made by guest.
+///
+/// Callables of guest are useless once instance is freed,
+/// so all connections that are still alive are disconnected on drop.
+#[derive(Default)]
+pub struct SignalState {
+    connections: Vec<SendSyncWrapper<(Signal, Callable)>>,
+}
+
+impl Drop for SignalState {
+    fn drop(&mut self) {
+        for v in self.connections.drain(..) {
+            let (s, c) = &*v;
+            // Freed object has no connection left.
+            if s.object().is_some() && s.is_connected(c) {
+                s.disconnect(c);
+            }
+        }
+    }
+}
+
+impl SignalState {
+    fn connect(&mut self, signal: Signal, callable: Callable, flags: u32) -> Error {
+        let ret = signal.connect(&callable, flags as _);
+        if ret == Error::OK {
+            // One-shot connections disconnects by itself, prune them here.
+            self.connections
+                .retain(|v| v.0.object().is_some() && v.0.is_connected(&v.1));
+            self.connections
+                .push(SendSyncWrapper::new((signal, callable)));
+        }
+        ret
+    }
+
+    fn disconnect(&mut self, signal: &Signal, callable: &Callable) {
+        signal.disconnect(callable);
+        // Reference counted connection may be tracked multiple times, remove only one.
+        if let Some(i) = self
+            .connections
+            .iter()
+            .position(|v| v.0 == *signal && v.1 == *callable)
+        {
+            self.connections.swap_remove(i);
+        }
+    }
+}
+
+/// Converts WIT connection flags into Godot's.
+fn to_connect_flags(v: ConnectionFlags) -> u32 {
+    [
+        (ConnectionFlags::DEFERRED, ConnectFlags::DEFERRED),
+        (ConnectionFlags::PERSIST, ConnectFlags::PERSIST),
+        (ConnectionFlags::ONE_SHOT, ConnectFlags::ONE_SHOT),
+        (
+            ConnectionFlags::REFERENCE_COUNTED,
+            ConnectFlags::REFERENCE_COUNTED,
+        ),
+    ]
+    .into_iter()
+    .filter(|(f, _)| v.contains(*f))
+    .fold(0, |a, (_, f)| a | f.ord() as u32)
+}
+
 impl bindgen::godot::core::signal::Host for GodotCtx {
     fn from_object_signal(
         &mut self,
@@ -51,7 +120,20 @@ impl bindgen::godot::core::signal::Host for GodotCtx {
     ) -> ErrorRes {
         filter_macro!(filter self.filter.as_ref(), godot_core, signal, connect)?;
         let v: Signal = self.get_value(var)?;
-        wrap_error(v.connect(&self.get_value(callable)?, flags as _))
+        let c: Callable = self.get_value(callable)?;
+        wrap_error(self.signals.connect(v, c, flags))
+    }
+
+    fn connect_flags(
+        &mut self,
+        var: WasmResource<Variant>,
+        callable: WasmResource<Variant>,
+        flags: ConnectionFlags,
+    ) -> ErrorRes {
+        filter_macro!(filter self.filter.as_ref(), godot_core, signal, connect_flags)?;
+        let v: Signal = self.get_value(var)?;
+        let c: Callable = self.get_value(callable)?;
+        wrap_error(self.signals.connect(v, c, to_connect_flags(flags)))
     }
 
     fn disconnect(
@@ -61,7 +143,8 @@ impl bindgen::godot::core::signal::Host for GodotCtx {
     ) -> AnyResult<()> {
         filter_macro!(filter self.filter.as_ref(), godot_core, signal, disconnect)?;
         let v: Signal = self.get_value(var)?;
-        v.disconnect(&self.get_value(callable)?);
+        let c: Callable = self.get_value(callable)?;
+        self.signals.disconnect(&v, &c);
         Ok(())
     }
 
@@ -86,7 +169,28 @@ impl bindgen::godot::core::signal::Host for GodotCtx {
         for (i, v) in args.into_iter().enumerate() {
             a[i] = self.maybe_get_var(v)?;
         }
-        self.release_store(move || v.emit(&a));
+        self.release_store_call(CallFrame::Signal(v.clone()), move || v.emit(&a))?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_flags() {
+        assert_eq!(to_connect_flags(ConnectionFlags::empty()), 0);
+        assert_eq!(to_connect_flags(ConnectionFlags::DEFERRED), 1);
+        assert_eq!(to_connect_flags(ConnectionFlags::ONE_SHOT), 4);
+        assert_eq!(
+            to_connect_flags(
+                ConnectionFlags::DEFERRED
+                    | ConnectionFlags::PERSIST
+                    | ConnectionFlags::ONE_SHOT
+                    | ConnectionFlags::REFERENCE_COUNTED
+            ),
+            15
+        );
+    }
+}
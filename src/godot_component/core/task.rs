@@ -0,0 +1,15 @@
+use anyhow::Result as AnyResult;
+
+use crate::filter_macro;
+use crate::godot_component::bindgen::godot::core::task;
+
+filter_macro! {method [
+    cancelled -> "cancelled",
+]}
+
+impl task::Host for crate::godot_component::GodotCtx {
+    fn cancelled(&mut self) -> AnyResult<bool> {
+        filter_macro!(filter ctx self, godot_core, task, cancelled)?;
+        Ok(self.activity.as_ref().is_some_and(|v| v.is_cancelled()))
+    }
+}
@@ -0,0 +1,451 @@
+use anyhow::Result as AnyResult;
+use godot::classes::{Node2D, Node3D};
+use godot::prelude::*;
+use wasmtime::component::Resource as WasmResource;
+
+use crate::godot_component::bindgen::godot::core::primitive;
+use crate::godot_component::{bindgen, GodotCtx};
+use crate::{bail_with_site, filter_macro};
+
+filter_macro! {method [
+    node2d_get_transform -> "node2d-get-transform",
+    node2d_set_transform -> "node2d-set-transform",
+    node2d_get_global_transform -> "node2d-get-global-transform",
+    node2d_set_global_transform -> "node2d-set-global-transform",
+    node3d_get_transform -> "node3d-get-transform",
+    node3d_set_transform -> "node3d-set-transform",
+    node3d_get_global_transform -> "node3d-get-global-transform",
+    node3d_set_global_transform -> "node3d-set-global-transform",
+    xform2d -> "xform2d",
+    xform_inv2d -> "xform-inv2d",
+    mul2d -> "mul2d",
+    affine_inverse2d -> "affine-inverse2d",
+    xform3d -> "xform3d",
+    xform_inv3d -> "xform-inv3d",
+    mul3d -> "mul3d",
+    affine_inverse3d -> "affine-inverse3d",
+    looking_at -> "looking-at",
+]}
+
+fn check_finite(v: f32) -> AnyResult<f32> {
+    if !v.is_finite() {
+        bail_with_site!("Value {v} is not finite");
+    }
+    Ok(v)
+}
+
+fn from_vector2(primitive::Vector2 { x, y }: primitive::Vector2) -> AnyResult<Vector2> {
+    Ok(Vector2 {
+        x: check_finite(x)?,
+        y: check_finite(y)?,
+    })
+}
+
+fn to_vector2(Vector2 { x, y }: Vector2) -> primitive::Vector2 {
+    primitive::Vector2 { x, y }
+}
+
+fn from_vector3(primitive::Vector3 { x, y, z }: primitive::Vector3) -> AnyResult<Vector3> {
+    Ok(Vector3 {
+        x: check_finite(x)?,
+        y: check_finite(y)?,
+        z: check_finite(z)?,
+    })
+}
+
+fn to_vector3(Vector3 { x, y, z }: Vector3) -> primitive::Vector3 {
+    primitive::Vector3 { x, y, z }
+}
+
+fn from_transform2d(
+    primitive::Transform2d { a, b, origin }: primitive::Transform2d,
+) -> AnyResult<Transform2D> {
+    Ok(Transform2D {
+        a: from_vector2(a)?,
+        b: from_vector2(b)?,
+        origin: from_vector2(origin)?,
+    })
+}
+
+fn to_transform2d(Transform2D { a, b, origin }: Transform2D) -> primitive::Transform2d {
+    primitive::Transform2d {
+        a: to_vector2(a),
+        b: to_vector2(b),
+        origin: to_vector2(origin),
+    }
+}
+
+fn from_transform3d(
+    primitive::Transform3d {
+        basis: primitive::Basis {
+            col_a,
+            col_b,
+            col_c,
+        },
+        origin,
+    }: primitive::Transform3d,
+) -> AnyResult<Transform3D> {
+    Ok(Transform3D {
+        basis: Basis::from_cols(
+            from_vector3(col_a)?,
+            from_vector3(col_b)?,
+            from_vector3(col_c)?,
+        ),
+        origin: from_vector3(origin)?,
+    })
+}
+
+fn to_transform3d(Transform3D { basis, origin }: Transform3D) -> primitive::Transform3d {
+    primitive::Transform3d {
+        basis: primitive::Basis {
+            col_a: to_vector3(basis.col_a()),
+            col_b: to_vector3(basis.col_b()),
+            col_c: to_vector3(basis.col_c()),
+        },
+        origin: to_vector3(origin),
+    }
+}
+
+/// Inverts transform, failing instead of panicking if it's singular.
+fn affine_inverse2d(t: Transform2D) -> AnyResult<Transform2D> {
+    if t.a.cross(t.b) == 0.0 {
+        bail_with_site!("Transform is not invertible (determinant is zero)");
+    }
+    Ok(t.affine_inverse())
+}
+
+/// Inverts transform, failing instead of panicking if it's singular.
+fn affine_inverse3d(t: Transform3D) -> AnyResult<Transform3D> {
+    if t.basis.determinant() == 0.0 {
+        bail_with_site!("Transform is not invertible (determinant is zero)");
+    }
+    Ok(t.affine_inverse())
+}
+
+/// Rotates transform to look at target, failing instead of panicking on degenerate input.
+fn looking_at(
+    t: Transform3D,
+    target: Vector3,
+    up: Vector3,
+    use_model_front: bool,
+) -> AnyResult<Transform3D> {
+    let dir = target - t.origin;
+    if dir.is_zero_approx() {
+        bail_with_site!("Target {target} is at transform origin");
+    }
+    if up.cross(dir.normalized()).is_zero_approx() {
+        bail_with_site!("Up vector {up} is zero or colinear with direction {dir}");
+    }
+    Ok(t.looking_at(target, up, use_model_front))
+}
+
+impl bindgen::godot::core::transform::Host for GodotCtx {
+    fn node2d_get_transform(
+        &mut self,
+        obj: WasmResource<Variant>,
+    ) -> AnyResult<primitive::Transform2d> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, node2d_get_transform)?;
+        let o: Gd<Node2D> = self.get_value(obj)?;
+        Ok(to_transform2d(
+            self.release_store_main(move || o.get_transform())?,
+        ))
+    }
+
+    fn node2d_set_transform(
+        &mut self,
+        obj: WasmResource<Variant>,
+        val: primitive::Transform2d,
+    ) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, node2d_set_transform)?;
+        let mut o: Gd<Node2D> = self.get_value(obj)?;
+        let v = from_transform2d(val)?;
+        self.release_store_main(move || o.set_transform(v))?;
+        Ok(())
+    }
+
+    fn node2d_get_global_transform(
+        &mut self,
+        obj: WasmResource<Variant>,
+    ) -> AnyResult<primitive::Transform2d> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, node2d_get_global_transform)?;
+        let o: Gd<Node2D> = self.get_value(obj)?;
+        Ok(to_transform2d(
+            self.release_store_main(move || o.get_global_transform())?,
+        ))
+    }
+
+    fn node2d_set_global_transform(
+        &mut self,
+        obj: WasmResource<Variant>,
+        val: primitive::Transform2d,
+    ) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, node2d_set_global_transform)?;
+        let mut o: Gd<Node2D> = self.get_value(obj)?;
+        let v = from_transform2d(val)?;
+        self.release_store_main(move || o.set_global_transform(v))?;
+        Ok(())
+    }
+
+    fn node3d_get_transform(
+        &mut self,
+        obj: WasmResource<Variant>,
+    ) -> AnyResult<primitive::Transform3d> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, node3d_get_transform)?;
+        let o: Gd<Node3D> = self.get_value(obj)?;
+        Ok(to_transform3d(
+            self.release_store_main(move || o.get_transform())?,
+        ))
+    }
+
+    fn node3d_set_transform(
+        &mut self,
+        obj: WasmResource<Variant>,
+        val: primitive::Transform3d,
+    ) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, node3d_set_transform)?;
+        let mut o: Gd<Node3D> = self.get_value(obj)?;
+        let v = from_transform3d(val)?;
+        self.release_store_main(move || o.set_transform(v))?;
+        Ok(())
+    }
+
+    fn node3d_get_global_transform(
+        &mut self,
+        obj: WasmResource<Variant>,
+    ) -> AnyResult<primitive::Transform3d> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, node3d_get_global_transform)?;
+        let o: Gd<Node3D> = self.get_value(obj)?;
+        Ok(to_transform3d(
+            self.release_store_main(move || o.get_global_transform())?,
+        ))
+    }
+
+    fn node3d_set_global_transform(
+        &mut self,
+        obj: WasmResource<Variant>,
+        val: primitive::Transform3d,
+    ) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, node3d_set_global_transform)?;
+        let mut o: Gd<Node3D> = self.get_value(obj)?;
+        let v = from_transform3d(val)?;
+        self.release_store_main(move || o.set_global_transform(v))?;
+        Ok(())
+    }
+
+    fn xform2d(
+        &mut self,
+        t: primitive::Transform2d,
+        v: primitive::Vector2,
+    ) -> AnyResult<primitive::Vector2> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, xform2d)?;
+        Ok(to_vector2(from_transform2d(t)? * from_vector2(v)?))
+    }
+
+    fn xform_inv2d(
+        &mut self,
+        t: primitive::Transform2d,
+        v: primitive::Vector2,
+    ) -> AnyResult<primitive::Vector2> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, xform_inv2d)?;
+        Ok(to_vector2(
+            affine_inverse2d(from_transform2d(t)?)? * from_vector2(v)?,
+        ))
+    }
+
+    fn mul2d(
+        &mut self,
+        a: primitive::Transform2d,
+        b: primitive::Transform2d,
+    ) -> AnyResult<primitive::Transform2d> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, mul2d)?;
+        Ok(to_transform2d(from_transform2d(a)? * from_transform2d(b)?))
+    }
+
+    fn affine_inverse2d(&mut self, t: primitive::Transform2d) -> AnyResult<primitive::Transform2d> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, affine_inverse2d)?;
+        Ok(to_transform2d(affine_inverse2d(from_transform2d(t)?)?))
+    }
+
+    fn xform3d(
+        &mut self,
+        t: primitive::Transform3d,
+        v: primitive::Vector3,
+    ) -> AnyResult<primitive::Vector3> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, xform3d)?;
+        Ok(to_vector3(from_transform3d(t)? * from_vector3(v)?))
+    }
+
+    fn xform_inv3d(
+        &mut self,
+        t: primitive::Transform3d,
+        v: primitive::Vector3,
+    ) -> AnyResult<primitive::Vector3> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, xform_inv3d)?;
+        Ok(to_vector3(
+            affine_inverse3d(from_transform3d(t)?)? * from_vector3(v)?,
+        ))
+    }
+
+    fn mul3d(
+        &mut self,
+        a: primitive::Transform3d,
+        b: primitive::Transform3d,
+    ) -> AnyResult<primitive::Transform3d> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, mul3d)?;
+        Ok(to_transform3d(from_transform3d(a)? * from_transform3d(b)?))
+    }
+
+    fn affine_inverse3d(&mut self, t: primitive::Transform3d) -> AnyResult<primitive::Transform3d> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, affine_inverse3d)?;
+        Ok(to_transform3d(affine_inverse3d(from_transform3d(t)?)?))
+    }
+
+    fn looking_at(
+        &mut self,
+        t: primitive::Transform3d,
+        target: primitive::Vector3,
+        up: primitive::Vector3,
+        use_model_front: bool,
+    ) -> AnyResult<primitive::Transform3d> {
+        filter_macro!(filter self.filter.as_ref(), godot_core, transform, looking_at)?;
+        Ok(to_transform3d(looking_at(
+            from_transform3d(t)?,
+            from_vector3(target)?,
+            from_vector3(up)?,
+            use_model_front,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bindgen::godot::core::transform::Host;
+
+    const fn v2(x: f32, y: f32) -> primitive::Vector2 {
+        primitive::Vector2 { x, y }
+    }
+
+    const fn v3(x: f32, y: f32, z: f32) -> primitive::Vector3 {
+        primitive::Vector3 { x, y, z }
+    }
+
+    fn t2(a: primitive::Vector2, b: primitive::Vector2) -> primitive::Transform2d {
+        primitive::Transform2d {
+            a,
+            b,
+            origin: v2(1.0, 2.0),
+        }
+    }
+
+    fn t3(col_a: primitive::Vector3, col_b: primitive::Vector3) -> primitive::Transform3d {
+        primitive::Transform3d {
+            basis: primitive::Basis {
+                col_a,
+                col_b,
+                col_c: v3(0.0, 0.0, 1.0),
+            },
+            origin: v3(1.0, 2.0, 3.0),
+        }
+    }
+
+    #[test]
+    fn test_transform_conversion() {
+        let t = t2(v2(1.0, 2.0), v2(3.0, 4.0));
+        let v = from_transform2d(t).unwrap();
+        assert_eq!(v.a, Vector2::new(1.0, 2.0));
+        assert_eq!(v.b, Vector2::new(3.0, 4.0));
+        assert_eq!(v.origin, Vector2::new(1.0, 2.0));
+        assert_eq!(from_transform2d(to_transform2d(v)).unwrap(), v);
+
+        let t = t3(v3(1.0, 2.0, 3.0), v3(4.0, 5.0, 6.0));
+        let v = from_transform3d(t).unwrap();
+        assert_eq!(v.basis.col_a(), Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(v.basis.col_b(), Vector3::new(4.0, 5.0, 6.0));
+        assert_eq!(v.basis.col_c(), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(v.origin, Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(from_transform3d(to_transform3d(v)).unwrap(), v);
+
+        for v in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            from_transform2d(t2(v2(1.0, v), v2(0.0, 1.0))).unwrap_err();
+            from_transform3d(t3(v3(1.0, 0.0, 0.0), v3(0.0, v, 1.0))).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_transform_inverse() {
+        let mut ctx = GodotCtx::default();
+
+        let t = t2(v2(2.0, 0.0), v2(0.0, 4.0));
+        let v = ctx.xform_inv2d(t, v2(3.0, 6.0)).unwrap();
+        assert_eq!(from_vector2(v).unwrap(), Vector2::new(1.0, 1.0));
+        let v = ctx.xform2d(t, v).unwrap();
+        assert_eq!(from_vector2(v).unwrap(), Vector2::new(3.0, 6.0));
+        let v = ctx.affine_inverse2d(t).unwrap();
+        let v = ctx.mul2d(t, v).unwrap();
+        assert_eq!(from_transform2d(v).unwrap(), Transform2D::IDENTITY);
+
+        let t = t3(v3(2.0, 0.0, 0.0), v3(0.0, 4.0, 0.0));
+        let v = ctx.xform_inv3d(t, v3(3.0, 6.0, 3.0)).unwrap();
+        assert_eq!(from_vector3(v).unwrap(), Vector3::new(1.0, 1.0, 0.0));
+        let v = ctx.xform3d(t, v).unwrap();
+        assert_eq!(from_vector3(v).unwrap(), Vector3::new(3.0, 6.0, 3.0));
+        let v = ctx.affine_inverse3d(t).unwrap();
+        let v = ctx.mul3d(t, v).unwrap();
+        assert_eq!(from_transform3d(v).unwrap(), Transform3D::IDENTITY);
+
+        // Singular transform fails instead of panicking.
+        for t in [
+            t2(v2(0.0, 0.0), v2(0.0, 0.0)),
+            t2(v2(1.0, 2.0), v2(2.0, 4.0)),
+        ] {
+            let e = ctx.affine_inverse2d(t).unwrap_err().to_string();
+            assert!(e.contains("not invertible"), "{e}");
+            ctx.xform_inv2d(t, v2(0.0, 0.0)).unwrap_err();
+        }
+        for t in [
+            t3(v3(0.0, 0.0, 0.0), v3(0.0, 1.0, 0.0)),
+            t3(v3(1.0, 2.0, 3.0), v3(2.0, 4.0, 6.0)),
+        ] {
+            let e = ctx.affine_inverse3d(t).unwrap_err().to_string();
+            assert!(e.contains("not invertible"), "{e}");
+            ctx.xform_inv3d(t, v3(0.0, 0.0, 0.0)).unwrap_err();
+        }
+
+        ctx.xform_inv2d(t2(v2(1.0, 0.0), v2(0.0, 1.0)), v2(f32::NAN, 0.0))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_looking_at() {
+        let mut ctx = GodotCtx::default();
+        let t = t3(v3(1.0, 0.0, 0.0), v3(0.0, 1.0, 0.0));
+        let up = v3(0.0, 1.0, 0.0);
+
+        let v = from_transform3d(ctx.looking_at(t, v3(1.0, 2.0, 0.0), up, false).unwrap()).unwrap();
+        assert_eq!(v.origin, Vector3::new(1.0, 2.0, 3.0));
+        // Looks at -Z, which is towards the target.
+        assert!((-v.basis.col_c()).is_equal_approx(Vector3::new(0.0, 0.0, -1.0)));
+
+        // Target at origin.
+        let e = ctx
+            .looking_at(t, v3(1.0, 2.0, 3.0), up, false)
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("is at transform origin"), "{e}");
+        // Up is zero or colinear with direction.
+        for up in [v3(0.0, 0.0, 0.0), v3(0.0, 0.0, 1.0), v3(0.0, 0.0, -2.0)] {
+            let e = ctx
+                .looking_at(t, v3(1.0, 2.0, 0.0), up, false)
+                .unwrap_err()
+                .to_string();
+            assert!(e.contains("colinear"), "{e}");
+        }
+        // Not finite.
+        ctx.looking_at(t, v3(f32::NAN, 0.0, 0.0), up, false)
+            .unwrap_err();
+        ctx.looking_at(t, v3(1.0, 2.0, 0.0), v3(0.0, f32::INFINITY, 0.0), false)
+            .unwrap_err();
+    }
+}
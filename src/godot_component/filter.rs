@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult, Write as _};
 use std::ops::{Bound, Range, RangeBounds};
@@ -10,9 +11,11 @@ use nom::error::{ErrorKind, ParseError};
 use nom::sequence::preceded;
 use nom::{Err as NomErr, IResult, Parser};
 use rbitset::BitSet;
+use tracing::warn;
 
-use crate::godot_util::to_lower_inline_smol_str;
+use crate::godot_util::{should_warn, to_lower_inline_smol_str};
 use crate::rw_struct::{CharSlice, SingleError};
+use crate::wasm_engine::ComponentImport;
 
 #[derive(Debug, Clone)]
 pub struct FilterFlags<const N: usize>(BitSet<u8, N>);
@@ -273,13 +276,24 @@ macro_rules! filter_macro {
         pub const $i4: (usize, usize) = ($i3.0 + $i3.1, super::$i4::indices::filter_len);
         $crate::filter_macro!{#cp <$i4> $($i),*}
     };
+    (filter ctx $ctx:expr, $module:ident, $interface:ident, $method:ident) => {
+        $crate::site_context!($ctx.check_filter($crate::filter_macro!(#run $ctx.filter.as_ref(), $module, $interface, $method)))
+    };
+    (filter res $ctx:expr, $module:ident, $interface:ident, $method:ident) => {
+        if let Err(e) = $ctx.check_filter($crate::filter_macro!(#run $ctx.filter.as_ref(), $module, $interface, $method)) {
+            return $crate::godot_component::denied_error(e);
+        }
+    };
     (filter $e:expr, $module:ident, $interface:ident, $method:ident) => {
-        $crate::site_context!($crate::godot_component::filter_data::run_filter(
+        $crate::site_context!($crate::filter_macro!(#run $e, $module, $interface, $method))
+    };
+    (#run $e:expr, $module:ident, $interface:ident, $method:ident) => {
+        $crate::godot_component::filter_data::run_filter(
             $e,
             $crate::godot_component::filter_data::indices::$module.0 +
             $crate::godot_component::filter_data::$module::indices::$interface.0 +
             $crate::godot_component::filter_data::$module::$interface::indices::$method,
-        ))
+        ).map_err($crate::godot_component::filter::FilterDeniedError::from)
     };
     ($t:ident [$($i:ident -> $s:literal),* $(,)?]) => {
         pub mod filter_data {
@@ -397,6 +411,124 @@ impl FromGodot for Filter {
     }
 }
 
+/// Gets filter flag index of method, or `None` if it's not filterable.
+pub fn filter_index(module: &str, interface: &str, method: &str) -> Option<usize> {
+    let mut f = Filter::default();
+    parse_filter(
+        f.slice_mut(..ENDPOINT),
+        FilterItem {
+            allow: false,
+            module: Some(module),
+            interface: Some(interface),
+            method: Some(method),
+        },
+    );
+    (0..ENDPOINT).find(|&i| !f.get(i))
+}
+
+/// Denies all methods of an interface.
+pub fn deny_interface(filter: &mut Filter, module: &str, interface: &str) {
+    parse_filter(
+        filter.slice_mut(..ENDPOINT),
+        FilterItem {
+            allow: false,
+            module: Some(module),
+            interface: Some(interface),
+            method: None,
+        },
+    );
+}
+
+/// Generates filter dictionary containing all filterable imports.
+pub fn filter_template<'a>(
+    imports: impl IntoIterator<Item = &'a ComponentImport>,
+    allow: bool,
+) -> Dictionary {
+    let mut m = <BTreeMap<&str, BTreeMap<&str, Vec<&str>>>>::new();
+    for v in imports {
+        if filter_index(&v.module, &v.interface, &v.method).is_some() {
+            m.entry(&v.module)
+                .or_default()
+                .entry(&v.interface)
+                .or_default()
+                .push(&v.method);
+        }
+    }
+
+    m.into_iter()
+        .map(|(k, v)| {
+            let v = v
+                .into_iter()
+                .map(|(k, v)| {
+                    let v = v
+                        .into_iter()
+                        .map(|k| (k.to_variant(), allow.to_variant()))
+                        .collect::<Dictionary>();
+                    (k.to_variant(), v.to_variant())
+                })
+                .collect::<Dictionary>();
+            (k.to_variant(), v.to_variant())
+        })
+        .collect()
+}
+
+/// Reads filter from `component.godot.filter` config key. Defaults to allow all.
+pub fn filter_from_config(config: &Dictionary) -> Result<Filter, ConvertError> {
+    Ok(config
+        .get("component.godot.filter")
+        .map(|v| v.try_to())
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Splits filterable imports into allowed and denied by filter.
+///
+/// Imports that are not filterable are skipped.
+pub fn partition_imports<'a>(
+    filter: &Filter,
+    imports: &'a [ComponentImport],
+) -> (Vec<&'a ComponentImport>, Vec<&'a ComponentImport>) {
+    imports
+        .iter()
+        .filter_map(|v| Some((v, filter_index(&v.module, &v.interface, &v.method)?)))
+        .fold((Vec::new(), Vec::new()), |(mut a, mut d), (v, i)| {
+            if filter.get(i) {
+                a.push(v);
+            } else {
+                d.push(v);
+            }
+            (a, d)
+        })
+}
+
+/// Formats list of denied imports, if module has not been warned about it.
+fn denied_imports_warning(
+    module: InstanceId,
+    filter: &Filter,
+    imports: &[ComponentImport],
+) -> Option<String> {
+    let mut s = String::new();
+    for v in partition_imports(filter, imports).1 {
+        write!(s, "\n  {v}").unwrap();
+    }
+    if s.is_empty() || !should_warn(&format!("denied_imports:{module}{s}"), None) {
+        return None;
+    }
+    Some(s)
+}
+
+/// Warns about imported methods that are denied by filter.
+///
+/// Each module is warned once for the same set of denied methods.
+pub fn warn_denied_imports(module: InstanceId, filter: &Filter, imports: &[ComponentImport]) {
+    let Some(s) = denied_imports_warning(module, filter, imports) else {
+        return;
+    };
+
+    warn!(denied = %s, "Component imports denied methods.");
+    godot_warn!("Component imports methods that are denied by filter:{s}");
+}
+
 fn from_dict(d: Dictionary) -> Result<Filter, ConvertError> {
     let f = |s: &mut String, k: Variant| -> Result<(), ConvertError> {
         s.clear();
@@ -538,6 +670,53 @@ impl Display for FilterItem<'_> {
 
 impl Error for FilterItem<'_> {}
 
+/// Error returned when guest calls a method blocked by filter.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct FilterDeniedError {
+    /// Fully qualified interface name (eg. `godot:core/object`).
+    pub interface: String,
+    pub method: &'static str,
+}
+
+impl From<FilterItem<'static>> for FilterDeniedError {
+    fn from(v: FilterItem<'static>) -> Self {
+        static UNKNOWN: &str = "<unknown>";
+        Self {
+            interface: format!(
+                "{}/{}",
+                v.module.unwrap_or(UNKNOWN),
+                v.interface.unwrap_or(UNKNOWN)
+            ),
+            method: v.method.unwrap_or(UNKNOWN),
+        }
+    }
+}
+
+impl Debug for FilterDeniedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Calling {}.{} is blocked!", self.interface, self.method)
+    }
+}
+
+impl Display for FilterDeniedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        <Self as Debug>::fmt(self, f)
+    }
+}
+
+impl Error for FilterDeniedError {}
+
+/// Set of denied methods, used to report each of them once.
+#[derive(Default, Debug)]
+pub struct DeniedSet(HashSet<FilterDeniedError>);
+
+impl DeniedSet {
+    /// Returns `true` if method has not been denied before.
+    pub fn insert(&mut self, e: &FilterDeniedError) -> bool {
+        !self.0.contains(e) && self.0.insert(e.clone())
+    }
+}
+
 #[allow(clippy::type_complexity)]
 fn parse_line(
     i: CharSlice<'_>,
@@ -673,4 +852,172 @@ deny godot:core.primitive.to-vector2i";
         println!("{:?}", f);
         print_filter(f.as_ref(), FilterItem::default());
     }
+
+    #[test]
+    fn test_filter_index() {
+        let i = filter_index("godot:core", "primitive", "from-vector2i").unwrap();
+        assert_ne!(
+            filter_index("godot:core", "primitive", "to-vector2i"),
+            Some(i)
+        );
+        assert_eq!(filter_index("godot:core", "primitive", "nonexistent"), None);
+        assert_eq!(filter_index("wasi:cli", "stdin", "get-stdin"), None);
+
+        const SCRIPT: &str = "deny godot:core.primitive.from-vector2i";
+        let f = parse_script(CharSlice(&to_char_array(SCRIPT))).unwrap();
+        assert!(!f.get(i));
+    }
+
+    #[test]
+    fn test_deny_interface() {
+        let mut f = Filter::default();
+        deny_interface(&mut f, "godot:global", "canvas");
+        let i = filter_index("godot:global", "canvas", "draw-rect").unwrap();
+        assert!(!f.get(i));
+        let i = filter_index("godot:core", "primitive", "from-vector2i").unwrap();
+        assert!(f.get(i));
+    }
+
+    #[test]
+    fn test_denied_imports_warn_once() {
+        let imports = [ComponentImport {
+            module: "godot:core".into(),
+            interface: "primitive".into(),
+            method: "to-vector2i".into(),
+        }];
+        let module = InstanceId::from_i64(0x1890_0001);
+        let other = InstanceId::from_i64(0x1890_0002);
+
+        let deny = parse_script(CharSlice(&to_char_array("deny godot:core.primitive"))).unwrap();
+        let allow = Filter::default();
+        assert!(denied_imports_warning(module, &allow, &imports).is_none());
+
+        let s = denied_imports_warning(module, &deny, &imports).unwrap();
+        assert!(s.contains("to-vector2i"), "{s}");
+        // Instantiating same module again does not warn.
+        for _ in 0..3 {
+            assert!(denied_imports_warning(module, &deny, &imports).is_none());
+        }
+        // Other module does.
+        assert!(denied_imports_warning(other, &deny, &imports).is_some());
+    }
+
+    #[test]
+    fn test_filter_translation() {
+        let tr = filter_index("godot:global", "translation", "tr").unwrap();
+        let tr_n = filter_index("godot:global", "translation", "tr-n").unwrap();
+        let locale = filter_index("godot:global", "translation", "get-locale").unwrap();
+        let locales = filter_index("godot:global", "translation", "get-loaded-locales").unwrap();
+
+        const SCRIPT: &str = "deny godot:global.translation.tr";
+        let f = parse_script(CharSlice(&to_char_array(SCRIPT))).unwrap();
+        assert!(!f.get(tr));
+        assert!(f.get(tr_n));
+        assert!(f.get(locale));
+        assert!(f.get(locales));
+    }
+    #[test]
+    fn test_filter_array() {
+        let index = |m| filter_index("godot:core", "array", m).unwrap();
+        let sort = index("sort");
+        let sort_custom = index("sort-custom");
+        let contains = index("contains");
+        let rfind = index("rfind");
+        for m in ["bsearch", "slice", "count", "find"] {
+            index(m);
+        }
+
+        const SCRIPT: &str = "
+deny godot:core.array.sort-custom
+deny godot:core.array.contains";
+        let f = parse_script(CharSlice(&to_char_array(SCRIPT))).unwrap();
+        assert!(f.get(sort));
+        assert!(!f.get(sort_custom));
+        assert!(!f.get(contains));
+        assert!(f.get(rfind));
+        assert!(filter_macro!(filter f.as_ref(), godot_core, array, sort_custom).is_err());
+        assert!(filter_macro!(filter f.as_ref(), godot_core, array, contains).is_err());
+        assert!(filter_macro!(filter f.as_ref(), godot_core, array, rfind).is_ok());
+    }
+
+    #[test]
+    fn test_partition_imports() {
+        let import = |module: &str, interface: &str, method: &str| ComponentImport {
+            module: module.into(),
+            interface: interface.into(),
+            method: method.into(),
+        };
+        let imports = [
+            import("godot:core", "primitive", "from-vector2i"),
+            import("godot:core", "primitive", "to-vector2i"),
+            import("godot:global", "translation", "tr"),
+            import("godot:global", "translation", "tr-n"),
+            import("godot:global", "ip", "resolve-hostname"),
+            import("godot:reflection", "this", "get-this"),
+            import("wasi:cli", "stdin", "get-stdin"),
+        ];
+
+        const SCRIPT: &str = "
+deny godot:core.primitive.from-vector2i
+deny godot:global.translation
+allow godot:global.translation.tr-n
+deny godot:reflection";
+        let f = parse_script(CharSlice(&to_char_array(SCRIPT))).unwrap();
+        let (allowed, denied) = partition_imports(&f, &imports);
+        // Non-filterable import is skipped.
+        assert_eq!(allowed.len() + denied.len(), imports.len() - 1);
+
+        // Denials must match what host functions observe at runtime.
+        let observed = [
+            filter_macro!(filter f.as_ref(), godot_core, primitive, from_vector2i).is_err(),
+            filter_macro!(filter f.as_ref(), godot_core, primitive, to_vector2i).is_err(),
+            filter_macro!(filter f.as_ref(), godot_global, translation, tr).is_err(),
+            filter_macro!(filter f.as_ref(), godot_global, translation, tr_n).is_err(),
+            filter_macro!(filter f.as_ref(), godot_global, ip, resolve_hostname).is_err(),
+            filter_macro!(filter f.as_ref(), godot_reflection, this, get_this).is_err(),
+        ];
+        for (v, d) in imports.iter().zip(observed) {
+            assert_eq!(denied.contains(&v), d, "{v}");
+            assert_eq!(allowed.contains(&v), !d, "{v}");
+        }
+    }
+
+    #[test]
+    fn test_filter_denied_error() {
+        use crate::wasm_util::error_kind;
+
+        const SCRIPT: &str = "deny godot:core.object";
+        let f = parse_script(CharSlice(&to_char_array(SCRIPT))).unwrap();
+
+        let e = filter_macro!(filter f.as_ref(), godot_core, object, free).unwrap_err();
+        assert_eq!(error_kind(&e), Some("permission_denied"));
+        let e = e.downcast::<FilterDeniedError>().unwrap();
+        assert_eq!(e.interface, "godot:core/object");
+        assert_eq!(e.method, "free");
+        assert_eq!(e.to_string(), "Calling godot:core/object.free is blocked!");
+
+        filter_macro!(filter f.as_ref(), godot_core, core, var_hash).unwrap();
+        assert_eq!(error_kind(&anyhow::anyhow!("other error")), None);
+    }
+
+    #[test]
+    fn test_denied_set() {
+        const SCRIPT: &str = "deny godot:core.object";
+        let f = parse_script(CharSlice(&to_char_array(SCRIPT))).unwrap();
+        let denied = |e: anyhow::Error| e.downcast::<FilterDeniedError>().unwrap();
+
+        let mut set = DeniedSet::default();
+        let free = denied(filter_macro!(filter f.as_ref(), godot_core, object, free).unwrap_err());
+        let connect =
+            denied(filter_macro!(filter f.as_ref(), godot_core, object, connect).unwrap_err());
+
+        // Each method is only reported once.
+        assert!(set.insert(&free));
+        assert!(!set.insert(&free));
+        assert!(set.insert(&connect));
+        for _ in 0..3 {
+            let e = denied(filter_macro!(filter f.as_ref(), godot_core, object, free).unwrap_err());
+            assert!(!set.insert(&e));
+        }
+    }
 }
@@ -0,0 +1,379 @@
+use std::f32::consts::TAU;
+use std::mem;
+
+use anyhow::Result as AnyResult;
+use godot::classes::{CanvasItem, Texture2D};
+use godot::prelude::*;
+use wasmtime::component::Resource as WasmResource;
+
+use crate::godot_component::bindgen::godot::core::primitive;
+use crate::godot_component::{bindgen, GodotCtx};
+use crate::godot_util::SendSyncWrapper;
+use crate::{bail_with_site, filter_macro};
+
+filter_macro! {method [
+    draw_rect -> "draw-rect",
+    draw_circle -> "draw-circle",
+    draw_polyline -> "draw-polyline",
+    draw_texture_rect -> "draw-texture-rect",
+    set_transform -> "set-transform",
+    get_command_count -> "get-command-count",
+]}
+
+/// Maximum number of commands per frame.
+const MAX_COMMANDS: usize = 16384;
+/// Maximum number of points in a polyline.
+const MAX_POLYLINE_POINTS: usize = 4096;
+/// Number of points used to draw circle outline.
+const CIRCLE_POINTS: i32 = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+enum CanvasCommand {
+    Rect {
+        rect: Rect2,
+        color: Color,
+        filled: bool,
+        width: f32,
+    },
+    Circle {
+        position: Vector2,
+        radius: f32,
+        color: Color,
+        filled: bool,
+        width: f32,
+    },
+    Polyline {
+        points: Vec<Vector2>,
+        color: Color,
+        width: f32,
+    },
+    TextureRect {
+        texture: SendSyncWrapper<Gd<Texture2D>>,
+        rect: Rect2,
+        tile: bool,
+        modulate: Color,
+    },
+    SetTransform(Transform2D),
+}
+
+/// Per-frame draw command list submitted by guest.
+#[derive(Default)]
+pub struct CanvasState {
+    pending: Vec<CanvasCommand>,
+    committed: Vec<CanvasCommand>,
+}
+
+impl CanvasState {
+    /// Commits pending commands to be drawn.
+    ///
+    /// Returns `true` if commands changed since last commit (and thus requires redraw).
+    pub fn commit(&mut self) -> bool {
+        let changed = self.pending != self.committed;
+        self.committed = mem::take(&mut self.pending);
+        changed
+    }
+
+    /// Number of commands in last commit.
+    pub fn last_command_count(&self) -> usize {
+        self.committed.len()
+    }
+
+    /// Replays committed commands. Must be called inside `_draw()`.
+    pub fn replay(&self, item: &mut Gd<CanvasItem>) {
+        item.draw_set_transform_matrix(Transform2D::IDENTITY);
+        for c in &self.committed {
+            match c {
+                CanvasCommand::Rect {
+                    rect,
+                    color,
+                    filled,
+                    width,
+                } => {
+                    item.draw_rect_ex(*rect, *color)
+                        .filled(*filled)
+                        .width(*width)
+                        .done();
+                }
+                CanvasCommand::Circle {
+                    position,
+                    radius,
+                    color,
+                    filled: true,
+                    ..
+                } => item.draw_circle(*position, *radius, *color),
+                CanvasCommand::Circle {
+                    position,
+                    radius,
+                    color,
+                    width,
+                    ..
+                } => {
+                    item.draw_arc_ex(*position, *radius, 0.0, TAU, CIRCLE_POINTS, *color)
+                        .width(*width)
+                        .done();
+                }
+                CanvasCommand::Polyline {
+                    points,
+                    color,
+                    width,
+                } => {
+                    item.draw_polyline_ex(&PackedVector2Array::from(&points[..]), *color)
+                        .width(*width)
+                        .done();
+                }
+                CanvasCommand::TextureRect {
+                    texture,
+                    rect,
+                    tile,
+                    modulate,
+                } => {
+                    item.draw_texture_rect_ex(&**texture, *rect, *tile)
+                        .modulate(*modulate)
+                        .done();
+                }
+                CanvasCommand::SetTransform(t) => item.draw_set_transform_matrix(*t),
+            }
+        }
+    }
+
+    fn push(&mut self, cmd: CanvasCommand) -> AnyResult<()> {
+        if self.pending.len() >= MAX_COMMANDS {
+            bail_with_site!("Too many draw commands (maximum {MAX_COMMANDS})");
+        }
+        self.pending.push(cmd);
+        Ok(())
+    }
+}
+
+fn check_finite(v: f32) -> AnyResult<f32> {
+    if !v.is_finite() {
+        bail_with_site!("Value {v} is not finite");
+    }
+    Ok(v)
+}
+
+fn from_vector2(primitive::Vector2 { x, y }: primitive::Vector2) -> AnyResult<Vector2> {
+    Ok(Vector2 {
+        x: check_finite(x)?,
+        y: check_finite(y)?,
+    })
+}
+
+fn from_rect2(primitive::Rect2 { position, size }: primitive::Rect2) -> AnyResult<Rect2> {
+    Ok(Rect2 {
+        position: from_vector2(position)?,
+        size: from_vector2(size)?,
+    })
+}
+
+fn from_color(primitive::Color { r, g, b, a }: primitive::Color) -> AnyResult<Color> {
+    Ok(Color {
+        r: check_finite(r)?,
+        g: check_finite(g)?,
+        b: check_finite(b)?,
+        a: check_finite(a)?,
+    })
+}
+
+fn from_transform2d(
+    primitive::Transform2d { a, b, origin }: primitive::Transform2d,
+) -> AnyResult<Transform2D> {
+    Ok(Transform2D {
+        a: from_vector2(a)?,
+        b: from_vector2(b)?,
+        origin: from_vector2(origin)?,
+    })
+}
+
+impl bindgen::godot::global::canvas::Host for GodotCtx {
+    fn draw_rect(
+        &mut self,
+        rect: primitive::Rect2,
+        color: primitive::Color,
+        filled: bool,
+        width: f32,
+    ) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, canvas, draw_rect)?;
+        let cmd = CanvasCommand::Rect {
+            rect: from_rect2(rect)?,
+            color: from_color(color)?,
+            filled,
+            width: check_finite(width)?,
+        };
+        self.canvas.push(cmd)
+    }
+
+    fn draw_circle(
+        &mut self,
+        position: primitive::Vector2,
+        radius: f32,
+        color: primitive::Color,
+        filled: bool,
+        width: f32,
+    ) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, canvas, draw_circle)?;
+        let cmd = CanvasCommand::Circle {
+            position: from_vector2(position)?,
+            radius: check_finite(radius)?,
+            color: from_color(color)?,
+            filled,
+            width: check_finite(width)?,
+        };
+        self.canvas.push(cmd)
+    }
+
+    fn draw_polyline(
+        &mut self,
+        points: Vec<primitive::Vector2>,
+        color: primitive::Color,
+        width: f32,
+    ) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, canvas, draw_polyline)?;
+        if points.len() > MAX_POLYLINE_POINTS {
+            bail_with_site!(
+                "Too many polyline points ({} > {MAX_POLYLINE_POINTS})",
+                points.len()
+            );
+        }
+        let cmd = CanvasCommand::Polyline {
+            points: points
+                .into_iter()
+                .map(from_vector2)
+                .collect::<AnyResult<_>>()?,
+            color: from_color(color)?,
+            width: check_finite(width)?,
+        };
+        self.canvas.push(cmd)
+    }
+
+    fn draw_texture_rect(
+        &mut self,
+        texture: WasmResource<Variant>,
+        rect: primitive::Rect2,
+        tile: bool,
+        modulate: primitive::Color,
+    ) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, canvas, draw_texture_rect)?;
+        let cmd = CanvasCommand::TextureRect {
+            texture: SendSyncWrapper::new(self.get_object::<Texture2D>(texture)?),
+            rect: from_rect2(rect)?,
+            tile,
+            modulate: from_color(modulate)?,
+        };
+        self.canvas.push(cmd)
+    }
+
+    fn set_transform(&mut self, t: primitive::Transform2d) -> AnyResult<()> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, canvas, set_transform)?;
+        let cmd = CanvasCommand::SetTransform(from_transform2d(t)?);
+        self.canvas.push(cmd)
+    }
+
+    fn get_command_count(&mut self) -> AnyResult<u32> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, canvas, get_command_count)?;
+        Ok(self.canvas.pending.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bindgen::godot::global::canvas::Host;
+
+    const fn v2(x: f32, y: f32) -> primitive::Vector2 {
+        primitive::Vector2 { x, y }
+    }
+
+    const RED: primitive::Color = primitive::Color {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    fn rect(x: f32) -> primitive::Rect2 {
+        primitive::Rect2 {
+            position: v2(x, 0.0),
+            size: v2(1.0, 1.0),
+        }
+    }
+
+    #[test]
+    fn test_canvas_batching() {
+        let mut ctx = GodotCtx::default();
+
+        ctx.draw_rect(rect(0.0), RED, true, 1.0).unwrap();
+        ctx.draw_circle(v2(1.0, 2.0), 3.0, RED, false, 1.0).unwrap();
+        ctx.draw_polyline(vec![v2(0.0, 0.0), v2(1.0, 1.0)], RED, 2.0)
+            .unwrap();
+        assert_eq!(ctx.get_command_count().unwrap(), 3);
+        assert_eq!(ctx.canvas.last_command_count(), 0);
+
+        // New commands require redraw.
+        assert!(ctx.canvas.commit());
+        assert_eq!(ctx.canvas.last_command_count(), 3);
+        assert_eq!(ctx.get_command_count().unwrap(), 0);
+
+        // Identical frame does not.
+        ctx.draw_rect(rect(0.0), RED, true, 1.0).unwrap();
+        ctx.draw_circle(v2(1.0, 2.0), 3.0, RED, false, 1.0).unwrap();
+        ctx.draw_polyline(vec![v2(0.0, 0.0), v2(1.0, 1.0)], RED, 2.0)
+            .unwrap();
+        assert!(!ctx.canvas.commit());
+
+        // Changed or empty frame does.
+        ctx.draw_rect(rect(1.0), RED, true, 1.0).unwrap();
+        assert!(ctx.canvas.commit());
+        assert_eq!(ctx.canvas.last_command_count(), 1);
+        assert!(ctx.canvas.commit());
+        assert_eq!(ctx.canvas.last_command_count(), 0);
+    }
+
+    #[test]
+    fn test_canvas_transform() {
+        let mut ctx = GodotCtx::default();
+
+        ctx.set_transform(primitive::Transform2d {
+            a: v2(1.0, 0.0),
+            b: v2(0.0, 1.0),
+            origin: v2(5.0, 6.0),
+        })
+        .unwrap();
+        assert_eq!(
+            ctx.canvas.pending,
+            [CanvasCommand::SetTransform(Transform2D {
+                a: Vector2::new(1.0, 0.0),
+                b: Vector2::new(0.0, 1.0),
+                origin: Vector2::new(5.0, 6.0),
+            })]
+        );
+    }
+
+    #[test]
+    fn test_canvas_reject() {
+        let mut ctx = GodotCtx::default();
+
+        // Non-finite values are rejected without queueing command.
+        ctx.draw_rect(rect(f32::NAN), RED, true, 1.0).unwrap_err();
+        ctx.draw_circle(v2(0.0, 0.0), f32::INFINITY, RED, true, 1.0)
+            .unwrap_err();
+        let c = primitive::Color { a: f32::NAN, ..RED };
+        ctx.draw_polyline(vec![v2(0.0, 0.0)], c, 1.0).unwrap_err();
+        assert_eq!(ctx.get_command_count().unwrap(), 0);
+
+        let e = ctx
+            .draw_polyline(vec![v2(0.0, 0.0); MAX_POLYLINE_POINTS + 1], RED, 1.0)
+            .unwrap_err();
+        assert!(e.to_string().contains("Too many polyline points"), "{e}");
+        ctx.draw_polyline(vec![v2(0.0, 0.0); MAX_POLYLINE_POINTS], RED, 1.0)
+            .unwrap();
+
+        for _ in 1..MAX_COMMANDS {
+            ctx.draw_rect(rect(0.0), RED, false, 1.0).unwrap();
+        }
+        let e = ctx.draw_rect(rect(0.0), RED, false, 1.0).unwrap_err();
+        assert!(e.to_string().contains("Too many draw commands"), "{e}");
+        assert_eq!(ctx.get_command_count().unwrap(), MAX_COMMANDS as u32);
+    }
+}
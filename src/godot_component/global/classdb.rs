@@ -96,7 +96,7 @@ impl bindgen::godot::global::classdb::Host for GodotCtx {
     ) -> AnyResult<Option<WasmResource<Variant>>> {
         filter_macro!(filter self.filter.as_ref(), godot_global, classdb, instantiate)?;
         let c: StringName = self.get_value(class)?;
-        let r = self.release_store(move || ClassDb::singleton().instantiate(&c));
+        let r = self.release_store_main(move || ClassDb::singleton().instantiate(&c))?;
         self.set_var(r)
     }
 
@@ -247,7 +247,7 @@ impl bindgen::godot::global::classdb::Host for GodotCtx {
         filter_macro!(filter self.filter.as_ref(), godot_global, classdb, class_get_property)?;
         let o: Gd<Object> = self.get_value(object)?;
         let n: StringName = self.get_value(name)?;
-        let r = self.release_store(move || ClassDb::singleton().class_get_property(&o, &n));
+        let r = self.release_store_main(move || ClassDb::singleton().class_get_property(&o, &n))?;
         self.set_var(r)
     }
 
@@ -261,7 +261,9 @@ impl bindgen::godot::global::classdb::Host for GodotCtx {
         let o: Gd<Object> = self.get_value(object)?;
         let n: StringName = self.get_value(name)?;
         let v = self.maybe_get_var(value)?;
-        self.release_store(move || wrap_error(ClassDb::singleton().class_set_property(&o, &n, &v)))
+        wrap_error(
+            self.release_store_main(move || ClassDb::singleton().class_set_property(&o, &n, &v))?,
+        )
     }
 
     fn class_has_enum(
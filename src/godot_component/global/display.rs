@@ -0,0 +1,110 @@
+use anyhow::Result as AnyResult;
+use godot::classes::window::Mode as WindowMode;
+use godot::classes::{DisplayServer, Engine, Node, SceneTree, Viewport, Window};
+use godot::prelude::*;
+
+use crate::godot_component::bindgen::godot::global::display::Mode;
+use crate::godot_component::{bindgen, GodotCtx};
+use crate::{bail_with_site, filter_macro};
+
+filter_macro! {method [
+    viewport_size -> "viewport-size",
+    screen_dpi -> "screen-dpi",
+    content_scale_factor -> "content-scale-factor",
+    safe_area -> "safe-area",
+    window_mode -> "window-mode",
+]}
+
+/// Gets viewport and window of instance, falling back to main window.
+///
+/// Must be called from main thread.
+fn get_target(inst_id: Option<InstanceId>) -> AnyResult<(Gd<Viewport>, Gd<Window>)> {
+    if let Some(node) = inst_id.and_then(|id| Gd::<Node>::try_from_instance_id(id).ok()) {
+        if node.is_inside_tree() {
+            if let (Some(viewport), Some(window)) = (node.get_viewport(), node.get_window()) {
+                return Ok((viewport, window));
+            }
+        }
+    }
+
+    let Some(tree) = Engine::singleton()
+        .get_main_loop()
+        .and_then(|v| v.try_cast::<SceneTree>().ok())
+    else {
+        bail_with_site!("Main loop is not a SceneTree");
+    };
+    let Some(root) = tree.get_root() else {
+        bail_with_site!("Scene tree has no root window");
+    };
+    Ok((root.clone().upcast(), root))
+}
+
+impl bindgen::godot::global::display::Host for GodotCtx {
+    fn viewport_size(&mut self) -> AnyResult<(i32, i32)> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, display, viewport_size)?;
+        let inst_id = self.inst_id;
+        self.release_store_main(move || {
+            let (viewport, _) = get_target(inst_id)?;
+            let size = viewport.get_visible_rect().size;
+            Ok((size.x as i32, size.y as i32))
+        })?
+    }
+
+    fn screen_dpi(&mut self) -> AnyResult<f32> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, display, screen_dpi)?;
+        let inst_id = self.inst_id;
+        self.release_store_main(move || {
+            let (_, window) = get_target(inst_id)?;
+            let dpi = DisplayServer::singleton()
+                .screen_get_dpi_ex()
+                .screen(window.get_current_screen())
+                .done();
+            Ok(dpi as f32)
+        })?
+    }
+
+    fn content_scale_factor(&mut self) -> AnyResult<f32> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, display, content_scale_factor)?;
+        let inst_id = self.inst_id;
+        self.release_store_main(move || {
+            let (_, window) = get_target(inst_id)?;
+            Ok(window.get_content_scale_factor())
+        })?
+    }
+
+    fn safe_area(&mut self) -> AnyResult<(i32, i32, i32, i32)> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, display, safe_area)?;
+        let inst_id = self.inst_id;
+        self.release_store_main(move || {
+            let (_, window) = get_target(inst_id)?;
+            let mut ds = DisplayServer::singleton();
+            let screen = window.get_current_screen();
+            let pos = ds.screen_get_position_ex().screen(screen).done();
+            let end = pos + ds.screen_get_size_ex().screen(screen).done();
+            let safe = ds.get_display_safe_area();
+            let safe_end = safe.position + safe.size;
+            Ok((
+                (safe.position.x - pos.x).max(0),
+                (safe.position.y - pos.y).max(0),
+                (end.x - safe_end.x).max(0),
+                (end.y - safe_end.y).max(0),
+            ))
+        })?
+    }
+
+    fn window_mode(&mut self) -> AnyResult<Mode> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, display, window_mode)?;
+        let inst_id = self.inst_id;
+        self.release_store_main(move || {
+            let (_, window) = get_target(inst_id)?;
+            Ok(match window.get_mode() {
+                WindowMode::WINDOWED => Mode::Windowed,
+                WindowMode::MINIMIZED => Mode::Minimized,
+                WindowMode::MAXIMIZED => Mode::Maximized,
+                WindowMode::FULLSCREEN => Mode::Fullscreen,
+                WindowMode::EXCLUSIVE_FULLSCREEN => Mode::ExclusiveFullscreen,
+                v => bail_with_site!("Unknown window mode {v:?}"),
+            })
+        })?
+    }
+}
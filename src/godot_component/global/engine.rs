@@ -1,10 +1,16 @@
+use std::collections::HashMap;
+
 use anyhow::Result as AnyResult;
 use godot::classes::{Engine, ScriptLanguage};
 use godot::prelude::*;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use wasmtime::component::Resource as WasmResource;
 
 use crate::filter_macro;
+use crate::godot_component::bindgen::godot::core::core::Error as RetError;
 use crate::godot_component::{bindgen, wrap_error, ErrorRes, GodotCtx};
+use crate::godot_util::SendSyncWrapper;
 
 filter_macro! {method [
     singleton -> "singleton",
@@ -31,6 +37,8 @@ filter_macro! {method [
     get_frames_per_second -> "get-frames-per-second",
     get_physics_frames -> "get-physics-frames",
     get_process_frames -> "get-process-frames",
+    get_process_delta -> "get-process-delta",
+    get_physics_delta -> "get-physics-delta",
     get_physics_interpolation_fraction -> "get-physics-interpolation-fraction",
     is_in_physics_frame -> "is-in-physics-frame",
     get_main_loop -> "get-main-loop",
@@ -40,6 +48,8 @@ filter_macro! {method [
     get_singleton_list -> "get-singleton-list",
     register_singleton -> "register-singleton",
     unregister_singleton -> "unregister-singleton",
+    register_wasm_singleton -> "register-wasm-singleton",
+    get_wasm_singleton -> "get-wasm-singleton",
     get_script_language -> "get-script-language",
     get_script_language_count -> "get-script-language-count",
     register_script_language -> "register-script-language",
@@ -47,6 +57,133 @@ filter_macro! {method [
     get_write_movie_path -> "get-write-movie-path",
 ]}
 
+/// Scope of services registered with `register-wasm-singleton`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ServiceScope {
+    /// Services are shared between instances using the same `WasiContext`.
+    #[default]
+    Context,
+    /// Services are shared between all instances.
+    Global,
+}
+
+impl ServiceScope {
+    /// Reads `services.scope` config.
+    pub fn from_config(via: &Dictionary) -> Result<Self, ConvertError> {
+        let Some(v) = via.get("services.scope") else {
+            return Ok(Self::default());
+        };
+        match &*v.try_to::<GString>()?.to_string() {
+            "context" => Ok(Self::Context),
+            "global" => Ok(Self::Global),
+            _ => Err(ConvertError::with_error_value("Unknown value", v)),
+        }
+    }
+}
+
+struct ServiceEntry<T> {
+    owner: i64,
+    module: String,
+    value: T,
+}
+
+/// Registry of services, keyed by scope (`None` is global) and name.
+struct ServiceRegistry<T> {
+    entries: HashMap<(Option<i64>, String), ServiceEntry<T>>,
+}
+
+impl<T> Default for ServiceRegistry<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone> ServiceRegistry<T> {
+    /// Registers service. On collision, returns module name of the owning instance.
+    fn register(
+        &mut self,
+        scope: Option<i64>,
+        name: String,
+        owner: i64,
+        module: &str,
+        value: T,
+    ) -> Result<(), String> {
+        match self.entries.get_mut(&(scope, name.clone())) {
+            Some(e) if e.owner != owner => Err(e.module.clone()),
+            Some(e) => {
+                e.value = value;
+                Ok(())
+            }
+            None => {
+                self.entries.insert(
+                    (scope, name),
+                    ServiceEntry {
+                        owner,
+                        module: module.to_string(),
+                        value,
+                    },
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn get(&self, scope: Option<i64>, name: &str) -> Option<T> {
+        self.entries
+            .get(&(scope, name.to_string()))
+            .map(|e| e.value.clone())
+    }
+
+    /// Unregisters all services of instance. Returns number of services removed.
+    fn unregister_owner(&mut self, owner: i64) -> usize {
+        let n = self.entries.len();
+        self.entries.retain(|_, e| e.owner != owner);
+        n - self.entries.len()
+    }
+}
+
+static SERVICES: Lazy<Mutex<ServiceRegistry<SendSyncWrapper<Gd<Object>>>>> =
+    Lazy::new(Default::default);
+
+/// Services registered by instance.
+#[derive(Default)]
+pub struct ServicesState {
+    owner: Option<i64>,
+    module: String,
+    scope: Option<i64>,
+}
+
+impl Drop for ServicesState {
+    fn drop(&mut self) {
+        if let Some(owner) = self.owner {
+            SERVICES.lock().unregister_owner(owner);
+        }
+    }
+}
+
+impl ServicesState {
+    /// Sets owning instance and registry scope.
+    ///
+    /// `context` is the instance ID of `WasiContext`, if any.
+    /// Without context, context-scoped services are private to the instance.
+    pub fn configure(
+        &mut self,
+        owner: InstanceId,
+        module: String,
+        scope: ServiceScope,
+        context: Option<InstanceId>,
+    ) {
+        self.owner = Some(owner.to_i64());
+        self.module = module;
+        self.scope = match scope {
+            ServiceScope::Global => None,
+            ServiceScope::Context => Some(context.unwrap_or(owner).to_i64()),
+        };
+    }
+}
+
 impl bindgen::godot::global::engine::Host for GodotCtx {
     fn singleton(&mut self) -> AnyResult<WasmResource<Variant>> {
         filter_macro!(filter self.filter.as_ref(), godot_global, engine, singleton)?;
@@ -141,7 +278,7 @@ impl bindgen::godot::global::engine::Host for GodotCtx {
 
     fn get_frames_drawn(&mut self) -> AnyResult<i32> {
         filter_macro!(filter self.filter.as_ref(), godot_global, engine, get_frames_drawn)?;
-        Ok(Engine::singleton().get_frames_drawn())
+        Ok(self.inner_lock.frame_info().frames_drawn)
     }
 
     fn get_frames_per_second(&mut self) -> AnyResult<f64> {
@@ -166,7 +303,7 @@ impl bindgen::godot::global::engine::Host for GodotCtx {
 
     fn get_physics_frames(&mut self) -> AnyResult<u64> {
         filter_macro!(filter self.filter.as_ref(), godot_global, engine, get_physics_frames)?;
-        Ok(Engine::singleton().get_physics_frames())
+        Ok(self.inner_lock.frame_info().physics_frames)
     }
 
     fn get_physics_interpolation_fraction(&mut self) -> AnyResult<f64> {
@@ -176,7 +313,17 @@ impl bindgen::godot::global::engine::Host for GodotCtx {
 
     fn get_process_frames(&mut self) -> AnyResult<u64> {
         filter_macro!(filter self.filter.as_ref(), godot_global, engine, get_process_frames)?;
-        Ok(Engine::singleton().get_process_frames())
+        Ok(self.inner_lock.frame_info().process_frames)
+    }
+
+    fn get_process_delta(&mut self) -> AnyResult<f64> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, engine, get_process_delta)?;
+        Ok(self.inner_lock.frame_info().process_delta)
+    }
+
+    fn get_physics_delta(&mut self) -> AnyResult<f64> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, engine, get_physics_delta)?;
+        Ok(self.inner_lock.frame_info().physics_delta)
     }
 
     fn get_script_language(&mut self, ix: i32) -> AnyResult<Option<WasmResource<Variant>>> {
@@ -264,4 +411,92 @@ impl bindgen::godot::global::engine::Host for GodotCtx {
         Engine::singleton().unregister_singleton(&self.get_value::<StringName>(name)?);
         Ok(())
     }
+
+    fn register_wasm_singleton(&mut self, name: String, object: WasmResource<Variant>) -> ErrorRes {
+        filter_macro!(filter self.filter.as_ref(), godot_global, engine, register_wasm_singleton)?;
+        let object = self.get_object::<Object>(object)?;
+        let Some(owner) = self.services.owner else {
+            return Ok(Err(RetError::ErrUnconfigured));
+        };
+        let r = SERVICES.lock().register(
+            self.services.scope,
+            name.clone(),
+            owner,
+            &self.services.module,
+            SendSyncWrapper::new(object),
+        );
+        match r {
+            Ok(()) => Ok(Ok(())),
+            Err(module) => {
+                godot_error!("Service {name:?} is already registered by module {module:?}");
+                Ok(Err(RetError::ErrAlreadyInUse))
+            }
+        }
+    }
+
+    fn get_wasm_singleton(&mut self, name: String) -> AnyResult<Option<WasmResource<Variant>>> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, engine, get_wasm_singleton)?;
+        let Some(v) = SERVICES.lock().get(self.services.scope, &name) else {
+            return Ok(None);
+        };
+        let v = v.into_inner();
+        if v.is_instance_valid() {
+            self.set_var(v.to_variant())
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Counter(AtomicU32);
+
+    impl Counter {
+        fn incr(&self) -> u32 {
+            self.0.fetch_add(1, Ordering::SeqCst) + 1
+        }
+    }
+
+    #[test]
+    fn test_service_two_instances() {
+        let mut reg = ServiceRegistry::<Arc<Counter>>::default();
+        let (a, b) = (1, 2);
+        let ctx = Some(100);
+
+        // Mod A registers service, mod B calls it.
+        let svc = Arc::new(Counter::default());
+        reg.register(ctx, "counter".into(), a, "mod_a", svc.clone())
+            .unwrap();
+        assert_eq!(reg.get(ctx, "counter").unwrap().incr(), 1);
+        assert_eq!(svc.0.load(Ordering::SeqCst), 1);
+
+        // Collision reports owning module.
+        let e = reg
+            .register(ctx, "counter".into(), b, "mod_b", Arc::default())
+            .unwrap_err();
+        assert_eq!(e, "mod_a");
+        // Owner may replace its own service.
+        reg.register(ctx, "counter".into(), a, "mod_a", svc.clone())
+            .unwrap();
+
+        // Other scopes do not see it.
+        assert!(reg.get(None, "counter").is_none());
+        assert!(reg.get(Some(101), "counter").is_none());
+        reg.register(None, "counter".into(), b, "mod_b", Arc::default())
+            .unwrap();
+
+        // Freeing A unregisters its services only.
+        assert_eq!(reg.unregister_owner(a), 1);
+        assert!(reg.get(ctx, "counter").is_none());
+        assert!(reg.get(None, "counter").is_some());
+        reg.register(ctx, "counter".into(), b, "mod_b", Arc::default())
+            .unwrap();
+    }
 }
@@ -7,6 +7,7 @@ use wasmtime::component::Resource as WasmResource;
 use crate::filter_macro;
 use crate::godot_component::bindgen::godot::core::typeis::VariantType as CompVarType;
 use crate::godot_component::bindgen::godot::global::globalscope;
+use crate::godot_component::nondet::SOURCE_GODOT_RANDOM;
 use crate::godot_component::{wrap_error, ErrorRes, GodotCtx};
 
 pub fn from_joy_axis(v: globalscope::JoyAxis) -> JoyAxis {
@@ -141,31 +142,31 @@ filter_macro! {method [
 
 impl globalscope::Host for GodotCtx {
     fn print(&mut self, s: String) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, print)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, print)?;
         self.release_store(move || print(&[s.to_variant()]));
         Ok(())
     }
 
     fn print_rich(&mut self, s: String) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, print_rich)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, print_rich)?;
         self.release_store(move || print_rich(&[s.to_variant()]));
         Ok(())
     }
 
     fn printerr(&mut self, s: String) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, printerr)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, printerr)?;
         self.release_store(move || printerr(&[s.to_variant()]));
         Ok(())
     }
 
     fn push_error(&mut self, s: String) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, push_error)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, push_error)?;
         self.release_store(move || push_error(&[s.to_variant()]));
         Ok(())
     }
 
     fn push_warning(&mut self, s: String) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, push_warning)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, push_warning)?;
         self.release_store(move || push_warning(&[s.to_variant()]));
         Ok(())
     }
@@ -174,7 +175,7 @@ impl globalscope::Host for GodotCtx {
         &mut self,
         b: WasmResource<Variant>,
     ) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, bytes_to_var)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, bytes_to_var)?;
         let v = bytes_to_var(&self.get_value(b)?);
         self.set_var(v)
     }
@@ -183,7 +184,7 @@ impl globalscope::Host for GodotCtx {
         &mut self,
         b: WasmResource<Variant>,
     ) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, bytes_to_var_with_objects)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, bytes_to_var_with_objects)?;
         let v = bytes_to_var_with_objects(&self.get_value(b)?);
         self.set_var(v)
     }
@@ -192,7 +193,7 @@ impl globalscope::Host for GodotCtx {
         &mut self,
         v: Option<WasmResource<Variant>>,
     ) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, var_to_bytes)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, var_to_bytes)?;
         let v = self.maybe_get_var(v)?;
         let b = self.release_store(move || var_to_bytes(&v));
         self.set_into_var(b)
@@ -202,33 +203,33 @@ impl globalscope::Host for GodotCtx {
         &mut self,
         v: Option<WasmResource<Variant>>,
     ) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, var_to_bytes_with_objects)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, var_to_bytes_with_objects)?;
         let v = self.maybe_get_var(v)?;
         let b = self.release_store(move || var_to_bytes_with_objects(&v));
         self.set_into_var(b)
     }
 
     fn var_to_str(&mut self, v: Option<WasmResource<Variant>>) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, var_to_str)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, var_to_str)?;
         let v = self.maybe_get_var(v)?;
         let s = self.release_store(move || var_to_str(&v));
         self.set_into_var(s)
     }
 
     fn str_to_var(&mut self, s: WasmResource<Variant>) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, str_to_var)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, str_to_var)?;
         let v = str_to_var(&self.get_value::<GString>(s)?);
         self.set_var(v)
     }
 
     fn weakref(&mut self, v: WasmResource<Variant>) -> AnyResult<Option<WasmResource<Variant>>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, weakref)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, weakref)?;
         let v = weakref(&*self.get_var_borrow(v)?);
         self.set_var(v)
     }
 
     fn is_instance_valid(&mut self, v: WasmResource<Variant>) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, is_instance_valid)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, is_instance_valid)?;
         let v = self.get_var_borrow(v)?;
         Ok(if v.get_type() == VariantType::OBJECT {
             v.to::<Gd<Object>>().is_instance_valid()
@@ -238,7 +239,7 @@ impl globalscope::Host for GodotCtx {
     }
 
     fn is_instance_id_valid(&mut self, id: u64) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, is_instance_id_valid)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, is_instance_id_valid)?;
         match InstanceId::try_from_godot(id as _) {
             Ok(v) => Ok(v.lookup_validity()),
             Err(e) => Err(e.into_erased().into()),
@@ -246,7 +247,7 @@ impl globalscope::Host for GodotCtx {
     }
 
     fn is_same(&mut self, a: WasmResource<Variant>, b: WasmResource<Variant>) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, is_same)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, is_same)?;
         Ok(is_same(&self.get_var(a)?, &self.get_var(b)?))
     }
 
@@ -255,7 +256,7 @@ impl globalscope::Host for GodotCtx {
         v: WasmResource<Variant>,
         t: CompVarType,
     ) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, type_convert)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, type_convert)?;
         let t = match t {
             CompVarType::Bool => VariantType::BOOL,
             CompVarType::Int => VariantType::INT,
@@ -301,49 +302,49 @@ impl globalscope::Host for GodotCtx {
     }
 
     fn rand_from_seed(&mut self, seed: u64) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, rand_from_seed)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, rand_from_seed)?;
         self.set_into_var(rand_from_seed(seed as _))
     }
 
     fn randf(&mut self) -> AnyResult<f64> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, randf)?;
-        Ok(randf())
+        filter_macro!(filter ctx self, godot_global, globalscope, randf)?;
+        self.tap_nondet(SOURCE_GODOT_RANDOM, randf)
     }
 
     fn randf_range(&mut self, from: f64, to: f64) -> AnyResult<f64> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, randf_range)?;
-        Ok(randf_range(from, to))
+        filter_macro!(filter ctx self, godot_global, globalscope, randf_range)?;
+        self.tap_nondet(SOURCE_GODOT_RANDOM, || randf_range(from, to))
     }
 
     fn randfn(&mut self, mean: f64, deviation: f64) -> AnyResult<f64> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, randfn)?;
-        Ok(randfn(mean, deviation))
+        filter_macro!(filter ctx self, godot_global, globalscope, randfn)?;
+        self.tap_nondet(SOURCE_GODOT_RANDOM, || randfn(mean, deviation))
     }
 
     fn randi(&mut self) -> AnyResult<i64> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, randi)?;
-        Ok(randi())
+        filter_macro!(filter ctx self, godot_global, globalscope, randi)?;
+        self.tap_nondet(SOURCE_GODOT_RANDOM, randi)
     }
 
     fn randi_range(&mut self, from: i64, to: i64) -> AnyResult<i64> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, randi_range)?;
-        Ok(randi_range(from, to))
+        filter_macro!(filter ctx self, godot_global, globalscope, randi_range)?;
+        self.tap_nondet(SOURCE_GODOT_RANDOM, || randi_range(from, to))
     }
 
     fn randomize(&mut self) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, randomize)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, randomize)?;
         randomize();
         Ok(())
     }
 
     fn seed(&mut self, s: u64) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, seed)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, seed)?;
         seed(s as _);
         Ok(())
     }
 
     fn load(&mut self, path: String) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, load)?;
+        filter_macro!(filter ctx self, godot_global, globalscope, load)?;
         match self.release_store(|| ResourceLoader::singleton().load(&path)) {
             Some(v) => self.set_into_var(v),
             None => bail!("Cannot load resource {path}"),
@@ -351,7 +352,7 @@ impl globalscope::Host for GodotCtx {
     }
 
     fn save(&mut self, res: WasmResource<Variant>, path: String) -> ErrorRes {
-        filter_macro!(filter self.filter.as_ref(), godot_global, globalscope, save)?;
+        filter_macro!(filter res self, godot_global, globalscope, save);
         let o = self.get_object::<Resource>(res)?;
         self.release_store(move || {
             wrap_error(ResourceSaver::singleton().save_ex(&o).path(&path).done())
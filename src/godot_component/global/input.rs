@@ -1,8 +1,9 @@
 use anyhow::Result as AnyResult;
 use godot::classes::input::{CursorShape, MouseMode};
-use godot::classes::{Input, InputEvent};
+use godot::classes::{Input, InputEvent, InputMap};
 use godot::global::Key;
 use godot::prelude::*;
+use slab::Slab;
 use wasmtime::component::Resource as WasmResource;
 
 use crate::godot_component::bindgen::godot::core::primitive;
@@ -13,6 +14,9 @@ use crate::godot_component::bindgen::godot::global::input;
 use crate::godot_component::global::globalscope::{
     from_joy_axis, from_joy_button, from_mouse_button, to_mouse_button_mask,
 };
+use crate::godot_component::nondet::{NondetValue, SOURCE_GODOT_INPUT};
+use crate::godot_component::GodotCtx;
+use crate::godot_util::SendSyncWrapper;
 use crate::{bail_with_site, filter_macro};
 
 fn from_cursor_shape(v: input::CursorShape) -> CursorShape {
@@ -44,8 +48,112 @@ fn from_key(k: i32) -> AnyResult<Key> {
     }
 }
 
+/// Input snapshots created by guest.
+#[derive(Default)]
+pub struct InputState {
+    /// If set, snapshots expire after the call creating it returns.
+    pub strict: bool,
+    snapshots: Slab<InputSnapshot>,
+}
+
+/// Host representation of `input-state`.
+pub struct InputSnapshot {
+    /// Action name to it's state.
+    data: SendSyncWrapper<Dictionary>,
+    /// Serial of call frame owning snapshot, if strict.
+    owner: Option<u64>,
+}
+
+/// State of single action.
+#[derive(Debug, Clone, Copy)]
+struct ActionState {
+    pressed: bool,
+    just_pressed: bool,
+    just_released: bool,
+    strength: f32,
+    raw_strength: f32,
+}
+
+impl ActionState {
+    fn capture(input: &Gd<Input>, action: &StringName) -> Self {
+        Self {
+            pressed: input.is_action_pressed(action),
+            just_pressed: input.is_action_just_pressed(action),
+            just_released: input.is_action_just_released(action),
+            strength: input.get_action_strength(action),
+            raw_strength: input.get_action_raw_strength(action),
+        }
+    }
+
+    fn to_dictionary(self) -> Dictionary {
+        let mut ret = Dictionary::new();
+        ret.set("pressed", self.pressed);
+        ret.set("just_pressed", self.just_pressed);
+        ret.set("just_released", self.just_released);
+        ret.set("strength", self.strength);
+        ret.set("raw_strength", self.raw_strength);
+        ret
+    }
+}
+
+impl NondetValue for ActionState {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut r = vec![
+            self.pressed as u8 | (self.just_pressed as u8) << 1 | (self.just_released as u8) << 2,
+        ];
+        r.extend_from_slice(&self.strength.to_le_bytes());
+        r.extend_from_slice(&self.raw_strength.to_le_bytes());
+        r
+    }
+
+    fn from_bytes(v: &[u8]) -> Option<Self> {
+        let (&flags, v) = v.split_first()?;
+        let (strength, raw_strength) = v.split_at_checked(4)?;
+        if flags > 7 {
+            return None;
+        }
+        Some(Self {
+            pressed: flags & 1 != 0,
+            just_pressed: flags & 2 != 0,
+            just_released: flags & 4 != 0,
+            strength: f32::from_bytes(strength)?,
+            raw_strength: f32::from_bytes(raw_strength)?,
+        })
+    }
+}
+
+impl GodotCtx {
+    fn get_snapshot(&self, res: &WasmResource<InputSnapshot>) -> AnyResult<&Dictionary> {
+        let Some(v) = self.input.snapshots.get(res.rep() as usize) else {
+            bail_with_site!("Input snapshot is not valid");
+        };
+        if let Some(owner) = v.owner {
+            if !self.inner_lock.reentrancy.is_live(owner) {
+                bail_with_site!("Input snapshot is expired");
+            }
+        }
+        Ok(&v.data)
+    }
+
+    fn get_action_field<T: FromGodot>(
+        &mut self,
+        res: &WasmResource<InputSnapshot>,
+        action: WasmResource<Variant>,
+        key: &str,
+    ) -> AnyResult<T> {
+        let action = self.get_value::<StringName>(action)?;
+        let Some(v) = self.get_snapshot(res)?.get(action.clone()) else {
+            bail_with_site!("Action {action} is not in snapshot");
+        };
+        let v = v.try_to::<Dictionary>().map_err(|e| e.into_erased())?;
+        let v = v.get_or_nil(key).try_to().map_err(|e| e.into_erased())?;
+        Ok(v)
+    }
+}
+
 filter_macro! {method [
     singleton -> "singleton",
+    snapshot -> "snapshot",
     get_mouse_mode -> "get-mouse-mode",
     set_mouse_mode -> "set-mouse-mode",
     is_using_accumulated_input -> "is-using-accumulated-input",
@@ -103,6 +211,30 @@ impl input::Host for crate::godot_component::GodotCtx {
         self.set_into_var(Input::singleton())
     }
 
+    fn snapshot(&mut self) -> AnyResult<WasmResource<InputSnapshot>> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, input, snapshot)?;
+        let input = Input::singleton();
+        let mut data = Dictionary::new();
+        for action in InputMap::singleton().get_actions().iter_shared() {
+            let v =
+                self.tap_nondet(SOURCE_GODOT_INPUT, || ActionState::capture(&input, &action))?;
+            data.set(action, v.to_dictionary());
+        }
+
+        let owner = if self.input.strict {
+            self.inner_lock.reentrancy.current()
+        } else {
+            None
+        };
+        let entry = self.input.snapshots.vacant_entry();
+        let ret = u32::try_from(entry.key())?;
+        entry.insert(InputSnapshot {
+            data: SendSyncWrapper::new(data),
+            owner,
+        });
+        Ok(WasmResource::new_own(ret))
+    }
+
     fn get_mouse_mode(&mut self) -> AnyResult<input::MouseMode> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, get_mouse_mode)?;
         Ok(match Input::singleton().get_mouse_mode() {
@@ -176,26 +308,31 @@ impl input::Host for crate::godot_component::GodotCtx {
 
     fn get_action_raw_strength(&mut self, v: WasmResource<Variant>, m: bool) -> AnyResult<f32> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, get_action_raw_strength)?;
-        Ok(Input::singleton()
-            .get_action_raw_strength_ex(&self.get_value::<StringName>(v)?)
-            .exact_match(m)
-            .done())
+        let v = self.get_value::<StringName>(v)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton()
+                .get_action_raw_strength_ex(&v)
+                .exact_match(m)
+                .done()
+        })
     }
 
     fn get_action_strength(&mut self, v: WasmResource<Variant>, m: bool) -> AnyResult<f32> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, get_action_strength)?;
-        Ok(Input::singleton()
-            .get_action_strength_ex(&self.get_value::<StringName>(v)?)
-            .exact_match(m)
-            .done())
+        let v = self.get_value::<StringName>(v)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton()
+                .get_action_strength_ex(&v)
+                .exact_match(m)
+                .done()
+        })
     }
 
     fn get_axis(&mut self, n: WasmResource<Variant>, p: WasmResource<Variant>) -> AnyResult<f32> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, get_axis)?;
-        Ok(Input::singleton().get_axis(
-            &self.get_value::<StringName>(n)?,
-            &self.get_value::<StringName>(p)?,
-        ))
+        let n = self.get_value::<StringName>(n)?;
+        let p = self.get_value::<StringName>(p)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || Input::singleton().get_axis(&n, &p))
     }
 
     fn get_connected_joypads(&mut self) -> AnyResult<WasmResource<Variant>> {
@@ -241,7 +378,9 @@ impl input::Host for crate::godot_component::GodotCtx {
 
     fn get_joy_axis(&mut self, d: i32, a: JoyAxis) -> AnyResult<f32> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, get_joy_axis)?;
-        Ok(Input::singleton().get_joy_axis(d, from_joy_axis(a)))
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton().get_joy_axis(d, from_joy_axis(a))
+        })
     }
 
     fn get_joy_guid(&mut self, d: i32) -> AnyResult<WasmResource<Variant>> {
@@ -272,8 +411,10 @@ impl input::Host for crate::godot_component::GodotCtx {
 
     fn get_last_mouse_velocity(&mut self) -> AnyResult<primitive::Vector2> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, get_last_mouse_velocity)?;
-        let Vector2 { x, y } = Input::singleton().get_last_mouse_velocity();
-        Ok(primitive::Vector2 { x, y })
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            let Vector2 { x, y } = Input::singleton().get_last_mouse_velocity();
+            primitive::Vector2 { x, y }
+        })
     }
 
     fn get_magnetometer(&mut self) -> AnyResult<primitive::Vector3> {
@@ -298,50 +439,64 @@ impl input::Host for crate::godot_component::GodotCtx {
         d: f32,
     ) -> AnyResult<primitive::Vector2> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, get_vector)?;
-        let Vector2 { x, y } = Input::singleton()
-            .get_vector_ex(
-                &self.get_value::<StringName>(nx)?,
-                &self.get_value::<StringName>(px)?,
-                &self.get_value::<StringName>(ny)?,
-                &self.get_value::<StringName>(py)?,
-            )
-            .deadzone(d)
-            .done();
-        Ok(primitive::Vector2 { x, y })
+        let nx = self.get_value::<StringName>(nx)?;
+        let px = self.get_value::<StringName>(px)?;
+        let ny = self.get_value::<StringName>(ny)?;
+        let py = self.get_value::<StringName>(py)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            let Vector2 { x, y } = Input::singleton()
+                .get_vector_ex(&nx, &px, &ny, &py)
+                .deadzone(d)
+                .done();
+            primitive::Vector2 { x, y }
+        })
     }
 
     fn is_action_just_pressed(&mut self, a: WasmResource<Variant>, e: bool) -> AnyResult<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, is_action_just_pressed)?;
-        Ok(Input::singleton()
-            .is_action_just_pressed_ex(&self.get_value::<StringName>(a)?)
-            .exact_match(e)
-            .done())
+        let a = self.get_value::<StringName>(a)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton()
+                .is_action_just_pressed_ex(&a)
+                .exact_match(e)
+                .done()
+        })
     }
 
     fn is_action_just_released(&mut self, a: WasmResource<Variant>, e: bool) -> AnyResult<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, is_action_just_released)?;
-        Ok(Input::singleton()
-            .is_action_just_released_ex(&self.get_value::<StringName>(a)?)
-            .exact_match(e)
-            .done())
+        let a = self.get_value::<StringName>(a)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton()
+                .is_action_just_released_ex(&a)
+                .exact_match(e)
+                .done()
+        })
     }
 
     fn is_action_pressed(&mut self, a: WasmResource<Variant>, e: bool) -> AnyResult<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, is_action_pressed)?;
-        Ok(Input::singleton()
-            .is_action_pressed_ex(&self.get_value::<StringName>(a)?)
-            .exact_match(e)
-            .done())
+        let a = self.get_value::<StringName>(a)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton()
+                .is_action_pressed_ex(&a)
+                .exact_match(e)
+                .done()
+        })
     }
 
     fn is_anything_pressed(&mut self) -> AnyResult<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, is_anything_pressed)?;
-        Ok(Input::singleton().is_anything_pressed())
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton().is_anything_pressed()
+        })
     }
 
     fn is_joy_button_pressed(&mut self, d: i32, b: JoyButton) -> AnyResult<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, is_joy_button_pressed)?;
-        Ok(Input::singleton().is_joy_button_pressed(d, from_joy_button(b)))
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton().is_joy_button_pressed(d, from_joy_button(b))
+        })
     }
 
     fn is_joy_known(&mut self, d: i32) -> AnyResult<bool> {
@@ -351,22 +506,31 @@ impl input::Host for crate::godot_component::GodotCtx {
 
     fn is_key_label_pressed(&mut self, k: i32) -> AnyResult<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, is_key_label_pressed)?;
-        Ok(Input::singleton().is_key_label_pressed(from_key(k)?))
+        let k = from_key(k)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton().is_key_label_pressed(k)
+        })
     }
 
     fn is_key_pressed(&mut self, k: i32) -> AnyResult<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, is_key_pressed)?;
-        Ok(Input::singleton().is_key_pressed(from_key(k)?))
+        let k = from_key(k)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || Input::singleton().is_key_pressed(k))
     }
 
     fn is_mouse_button_pressed(&mut self, b: MouseButton) -> AnyResult<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, is_mouse_button_pressed)?;
-        Ok(Input::singleton().is_mouse_button_pressed(from_mouse_button(b)))
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton().is_mouse_button_pressed(from_mouse_button(b))
+        })
     }
 
     fn is_physical_key_pressed(&mut self, k: i32) -> AnyResult<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, input, is_physical_key_pressed)?;
-        Ok(Input::singleton().is_physical_key_pressed(from_key(k)?))
+        let k = from_key(k)?;
+        self.tap_nondet(SOURCE_GODOT_INPUT, || {
+            Input::singleton().is_physical_key_pressed(k)
+        })
     }
 
     fn parse_input_event(&mut self, v: WasmResource<Variant>) -> AnyResult<()> {
@@ -473,3 +637,76 @@ impl input::Host for crate::godot_component::GodotCtx {
         Ok(())
     }
 }
+
+impl input::HostInputState for GodotCtx {
+    fn drop(&mut self, rep: WasmResource<InputSnapshot>) -> AnyResult<()> {
+        if self
+            .input
+            .snapshots
+            .try_remove(rep.rep() as usize)
+            .is_none()
+        {
+            bail_with_site!("Input snapshot is not valid");
+        }
+        Ok(())
+    }
+
+    fn is_action_pressed(
+        &mut self,
+        this: WasmResource<InputSnapshot>,
+        v: WasmResource<Variant>,
+    ) -> AnyResult<bool> {
+        self.get_action_field(&this, v, "pressed")
+    }
+
+    fn is_action_just_pressed(
+        &mut self,
+        this: WasmResource<InputSnapshot>,
+        v: WasmResource<Variant>,
+    ) -> AnyResult<bool> {
+        self.get_action_field(&this, v, "just_pressed")
+    }
+
+    fn is_action_just_released(
+        &mut self,
+        this: WasmResource<InputSnapshot>,
+        v: WasmResource<Variant>,
+    ) -> AnyResult<bool> {
+        self.get_action_field(&this, v, "just_released")
+    }
+
+    fn get_action_strength(
+        &mut self,
+        this: WasmResource<InputSnapshot>,
+        v: WasmResource<Variant>,
+    ) -> AnyResult<f32> {
+        self.get_action_field(&this, v, "strength")
+    }
+
+    fn get_action_raw_strength(
+        &mut self,
+        this: WasmResource<InputSnapshot>,
+        v: WasmResource<Variant>,
+    ) -> AnyResult<f32> {
+        self.get_action_field(&this, v, "raw_strength")
+    }
+
+    fn get_axis(
+        &mut self,
+        this: WasmResource<InputSnapshot>,
+        n: WasmResource<Variant>,
+        p: WasmResource<Variant>,
+    ) -> AnyResult<f32> {
+        let n = self.get_action_field::<f32>(&this, n, "strength")?;
+        let p = self.get_action_field::<f32>(&this, p, "strength")?;
+        Ok(p - n)
+    }
+
+    fn to_dictionary(
+        &mut self,
+        this: WasmResource<InputSnapshot>,
+    ) -> AnyResult<WasmResource<Variant>> {
+        let v = self.get_snapshot(&this)?.duplicate_deep();
+        self.set_into_var(v)
+    }
+}
@@ -1,21 +1,31 @@
+pub mod canvas;
 mod classdb;
-mod engine;
+mod display;
+pub mod engine;
 mod globalscope;
-mod input;
+pub mod input;
 mod input_map;
 mod ip;
 mod marshalls;
+pub mod noise;
 mod project_settings;
 mod time;
+mod translation;
+pub mod vfx;
 
 crate::filter_macro! {interface [
+    canvas <canvas> -> "canvas",
     classdb <classdb> -> "classdb",
+    display <display> -> "display",
     engine <engine> -> "engine",
     input <input> -> "input",
     input_map <input_map> -> "input-map",
     ip <ip> -> "ip",
     marshalls <marshalls> -> "marshalls",
+    noise <noise> -> "noise",
     project_settings <project_settings> -> "project-settings",
     time <time> -> "time",
+    translation <translation> -> "translation",
+    vfx <vfx> -> "vfx",
     globalscope <globalscope> -> "globalscope",
 ]}
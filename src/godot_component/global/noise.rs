@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use anyhow::Result as AnyResult;
+use godot::classes::FastNoiseLite;
+use godot::prelude::*;
+use wasmtime::component::Resource as WasmResource;
+
+use crate::godot_component::bindgen::godot::core::core::Error as RetError;
+use crate::godot_component::{bindgen, ErrorRes, GodotCtx};
+use crate::godot_util::SendSyncWrapper;
+use crate::{bail_with_site, filter_macro};
+
+filter_macro! {method [
+    create -> "create",
+    get_noise_2d -> "get-noise-2d",
+    get_noise_3d -> "get-noise-3d",
+    get_image -> "get-image",
+    free -> "free",
+]}
+
+/// Maximum number of live noise generators per instance.
+pub const MAX_LIVE: usize = 256;
+
+/// Maximum number of pixels generated by `get-image`.
+const MAX_IMAGE_PIXELS: u64 = 1 << 24;
+
+/// Properties guest is allowed to set on noise generator.
+const ALLOWED_PARAMS: &[&str] = &[
+    "cellular_distance_function",
+    "cellular_jitter",
+    "cellular_return_type",
+    "domain_warp_amplitude",
+    "domain_warp_enabled",
+    "domain_warp_fractal_gain",
+    "domain_warp_fractal_lacunarity",
+    "domain_warp_fractal_octaves",
+    "domain_warp_fractal_type",
+    "domain_warp_frequency",
+    "domain_warp_type",
+    "fractal_gain",
+    "fractal_lacunarity",
+    "fractal_octaves",
+    "fractal_ping_pong_strength",
+    "fractal_type",
+    "fractal_weighted_strength",
+    "frequency",
+    "noise_type",
+    "offset",
+    "seed",
+];
+
+/// Noise generators created by guest.
+#[derive(Default)]
+pub struct NoiseState {
+    live: HashMap<u32, SendSyncWrapper<Gd<FastNoiseLite>>>,
+    next_id: u32,
+}
+
+impl NoiseState {
+    fn get(&self, id: u32) -> AnyResult<Gd<FastNoiseLite>> {
+        match self.live.get(&id) {
+            Some(v) => Ok((**v).clone()),
+            None => bail_with_site!("Noise {id} does not exist"),
+        }
+    }
+
+    fn insert(&mut self, noise: Gd<FastNoiseLite>) -> u32 {
+        loop {
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
+            if !self.live.contains_key(&id) {
+                self.live.insert(id, SendSyncWrapper::new(noise));
+                return id;
+            }
+        }
+    }
+}
+
+fn check_param(name: &str, value: &Variant) -> AnyResult<()> {
+    if !ALLOWED_PARAMS.contains(&name) {
+        bail_with_site!("Parameter {name} is not allowed");
+    }
+    match value.get_type() {
+        VariantType::BOOL
+        | VariantType::INT
+        | VariantType::FLOAT
+        | VariantType::VECTOR3
+        | VariantType::VECTOR3I => Ok(()),
+        t => bail_with_site!("Parameter {name} has disallowed type {t:?}"),
+    }
+}
+
+impl bindgen::godot::global::noise::Host for GodotCtx {
+    fn create(&mut self, params: Vec<(String, WasmResource<Variant>)>) -> ErrorRes<u32> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, noise, create)?;
+        let params = params
+            .into_iter()
+            .map(|(k, v)| {
+                let v = self.get_var(v)?;
+                check_param(&k, &v)?;
+                Ok((StringName::from(k.as_str()), v))
+            })
+            .collect::<AnyResult<Vec<_>>>()?;
+        if self.noise.live.len() >= MAX_LIVE {
+            return Ok(Err(RetError::ErrBusy));
+        }
+
+        let mut noise = FastNoiseLite::new_gd();
+        for (k, v) in params {
+            noise.set(&k, &v);
+        }
+        Ok(Ok(self.noise.insert(noise)))
+    }
+
+    fn get_noise_2d(&mut self, id: u32, x: f32, y: f32) -> AnyResult<f32> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, noise, get_noise_2d)?;
+        Ok(self.noise.get(id)?.get_noise_2d(x, y))
+    }
+
+    fn get_noise_3d(&mut self, id: u32, x: f32, y: f32, z: f32) -> AnyResult<f32> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, noise, get_noise_3d)?;
+        Ok(self.noise.get(id)?.get_noise_3d(x, y, z))
+    }
+
+    fn get_image(&mut self, id: u32, width: u32, height: u32) -> ErrorRes<Vec<u8>> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, noise, get_image)?;
+        let noise = self.noise.get(id)?;
+        if width == 0
+            || height == 0
+            || width > i32::MAX as u32
+            || height > i32::MAX as u32
+            || u64::from(width) * u64::from(height) > MAX_IMAGE_PIXELS
+        {
+            return Ok(Err(RetError::ErrParameterRangeError));
+        }
+
+        let r = self.release_store(move || {
+            noise
+                .get_image(width as i32, height as i32)
+                .map(|img| img.get_data().to_vec())
+        });
+        Ok(r.ok_or(RetError::Failed))
+    }
+
+    fn free(&mut self, id: u32) -> AnyResult<bool> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, noise, free)?;
+        Ok(self.noise.live.remove(&id).is_some())
+    }
+}
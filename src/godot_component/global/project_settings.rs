@@ -4,137 +4,139 @@ use godot::prelude::*;
 use wasmtime::component::Resource as WasmResource;
 
 use crate::filter_macro;
+use crate::godot_component::bindgen::godot::core::core::Error as RetError;
+use crate::godot_component::ErrorRes;
 
 filter_macro! {method [
-    singleton -> "singleton",
-    add_property_info -> "add-property-info",
     get_global_class_list -> "get-global-class-list",
     has_setting -> "has-setting",
-    clear -> "clear",
     get_setting -> "get-setting",
     get_setting_with_override -> "get-setting-with-override",
-    set_setting -> "set-setting",
+    get_setting_with_default -> "get-setting-with-default",
     get_order -> "get-order",
-    set_order -> "set-order",
-    set_as_basic -> "set-as-basic",
-    set_as_internal -> "set-as-internal",
-    set_restart_if_changed -> "set-restart-if-changed",
-    set_initial_value -> "set-initial-value",
     globalize_path -> "globalize-path",
     localize_path -> "localize-path",
 ]}
 
-impl crate::godot_component::bindgen::godot::global::project_settings::Host
-    for crate::godot_component::GodotCtx
-{
-    fn singleton(&mut self) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, singleton)?;
-        self.set_into_var(ProjectSettings::singleton())
-    }
+/// Returns `true` if setting name starts with any of the allowed prefixes.
+fn setting_allowed(prefixes: &[String], name: &str) -> bool {
+    prefixes.iter().any(|p| name.starts_with(p.as_str()))
+}
 
-    fn add_property_info(&mut self, val: WasmResource<Variant>) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, add_property_info)?;
-        let v: Dictionary = self.get_value(val)?;
-        self.release_store(move || ProjectSettings::singleton().add_property_info(&v));
-        Ok(())
+/// Reads setting if allowed. Returns `None` if setting does not exist.
+fn read_setting<T>(
+    prefixes: &[String],
+    name: &str,
+    has: impl FnOnce() -> bool,
+    get: impl FnOnce() -> T,
+) -> Result<Option<T>, RetError> {
+    if !setting_allowed(prefixes, name) {
+        Err(RetError::ErrUnauthorized)
+    } else if has() {
+        Ok(Some(get()))
+    } else {
+        Ok(None)
     }
+}
 
+impl crate::godot_component::bindgen::godot::global::project_settings::Host
+    for crate::godot_component::GodotCtx
+{
     fn get_global_class_list(&mut self) -> AnyResult<WasmResource<Variant>> {
         filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, get_global_class_list)?;
         let r = self.release_store(move || ProjectSettings::singleton().get_global_class_list());
         self.set_into_var(r)
     }
 
-    fn has_setting(&mut self, name: WasmResource<Variant>) -> AnyResult<bool> {
+    fn has_setting(&mut self, name: WasmResource<Variant>) -> ErrorRes<bool> {
         filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, has_setting)?;
         let n: GString = self.get_value(name)?;
-        Ok(self.release_store(move || ProjectSettings::singleton().has_setting(&n)))
-    }
-
-    fn clear(&mut self, name: WasmResource<Variant>) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, clear)?;
-        let n: GString = self.get_value(name)?;
-        self.release_store(move || ProjectSettings::singleton().clear(&n));
-        Ok(())
+        if !setting_allowed(&self.settings_prefixes, &n.to_string()) {
+            return Ok(Err(RetError::ErrUnauthorized));
+        }
+        Ok(Ok(self.release_store(move || {
+            ProjectSettings::singleton().has_setting(&n)
+        })))
     }
 
     fn get_setting(
         &mut self,
         name: WasmResource<Variant>,
-    ) -> AnyResult<Option<WasmResource<Variant>>> {
+    ) -> ErrorRes<Option<WasmResource<Variant>>> {
         filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, get_setting)?;
         let n: GString = self.get_value(name)?;
-        let r = self.release_store(move || ProjectSettings::singleton().get_setting(&n));
-        self.set_var(r)
+        let prefixes = self.settings_prefixes.clone();
+        let r = self.release_store(move || {
+            let s = ProjectSettings::singleton();
+            read_setting(
+                &prefixes,
+                &n.to_string(),
+                || s.has_setting(&n),
+                || s.get_setting(&n),
+            )
+        });
+        match r {
+            Ok(v) => Ok(Ok(self.set_var(v.unwrap_or_default())?)),
+            Err(e) => Ok(Err(e)),
+        }
     }
 
     fn get_setting_with_override(
         &mut self,
         name: WasmResource<Variant>,
-    ) -> AnyResult<Option<WasmResource<Variant>>> {
+    ) -> ErrorRes<Option<WasmResource<Variant>>> {
         filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, get_setting_with_override)?;
         let n: StringName = self.get_value(name)?;
-        let r =
-            self.release_store(move || ProjectSettings::singleton().get_setting_with_override(&n));
-        self.set_var(r)
-    }
-
-    fn set_setting(
+        let prefixes = self.settings_prefixes.clone();
+        let r = self.release_store(move || {
+            let s = ProjectSettings::singleton();
+            let g = GString::from(&n);
+            read_setting(
+                &prefixes,
+                &n.to_string(),
+                || s.has_setting(&g),
+                || s.get_setting_with_override(&n),
+            )
+        });
+        match r {
+            Ok(v) => Ok(Ok(self.set_var(v.unwrap_or_default())?)),
+            Err(e) => Ok(Err(e)),
+        }
+    }
+
+    fn get_setting_with_default(
         &mut self,
         name: WasmResource<Variant>,
-        val: Option<WasmResource<Variant>>,
-    ) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, set_setting)?;
+        default_value: Option<WasmResource<Variant>>,
+    ) -> ErrorRes<Option<WasmResource<Variant>>> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, get_setting_with_default)?;
         let n: GString = self.get_value(name)?;
-        let v = self.maybe_get_var(val)?;
-        self.release_store(move || ProjectSettings::singleton().set_setting(&n, &v));
-        Ok(())
-    }
-
-    fn get_order(&mut self, name: WasmResource<Variant>) -> AnyResult<i32> {
+        let d = self.maybe_get_var(default_value)?;
+        let prefixes = self.settings_prefixes.clone();
+        let r = self.release_store(move || {
+            let s = ProjectSettings::singleton();
+            read_setting(
+                &prefixes,
+                &n.to_string(),
+                || s.has_setting(&n),
+                || s.get_setting(&n),
+            )
+        });
+        match r {
+            Ok(v) => Ok(Ok(self.set_var(v.unwrap_or(d))?)),
+            Err(e) => Ok(Err(e)),
+        }
+    }
+
+    fn get_order(&mut self, name: WasmResource<Variant>) -> ErrorRes<i32> {
         filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, get_order)?;
         let n: GString = self.get_value(name)?;
-        Ok(self.release_store(move || ProjectSettings::singleton().get_order(&n)))
-    }
-
-    fn set_order(&mut self, name: WasmResource<Variant>, val: i32) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, set_order)?;
-        let n: GString = self.get_value(name)?;
-        self.release_store(move || ProjectSettings::singleton().set_order(&n, val));
-        Ok(())
-    }
-
-    fn set_as_basic(&mut self, name: WasmResource<Variant>, val: bool) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, set_as_basic)?;
-        let n: GString = self.get_value(name)?;
-        self.release_store(move || ProjectSettings::singleton().set_as_basic(&n, val));
-        Ok(())
-    }
-
-    fn set_as_internal(&mut self, name: WasmResource<Variant>, val: bool) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, set_as_internal)?;
-        let n: GString = self.get_value(name)?;
-        self.release_store(move || ProjectSettings::singleton().set_as_internal(&n, val));
-        Ok(())
-    }
-
-    fn set_restart_if_changed(&mut self, name: WasmResource<Variant>, val: bool) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, set_restart_if_changed)?;
-        let n: GString = self.get_value(name)?;
-        self.release_store(move || ProjectSettings::singleton().set_restart_if_changed(&n, val));
-        Ok(())
-    }
-
-    fn set_initial_value(
-        &mut self,
-        name: WasmResource<Variant>,
-        val: Option<WasmResource<Variant>>,
-    ) -> AnyResult<()> {
-        filter_macro!(filter self.filter.as_ref(), godot_global, project_settings, set_initial_value)?;
-        let n: GString = self.get_value(name)?;
-        let v = self.maybe_get_var(val)?;
-        self.release_store(move || ProjectSettings::singleton().set_initial_value(&n, &v));
-        Ok(())
+        if !setting_allowed(&self.settings_prefixes, &n.to_string()) {
+            return Ok(Err(RetError::ErrUnauthorized));
+        }
+        Ok(Ok(self.release_store(move || {
+            ProjectSettings::singleton().get_order(&n)
+        })))
     }
 
     fn globalize_path(&mut self, path: WasmResource<Variant>) -> AnyResult<WasmResource<Variant>> {
@@ -151,3 +153,65 @@ impl crate::godot_component::bindgen::godot::global::project_settings::Host
         self.set_into_var(r)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    fn read(
+        prefixes: &[String],
+        settings: &HashMap<&str, i64>,
+        name: &str,
+    ) -> Result<Option<i64>, RetError> {
+        read_setting(
+            prefixes,
+            name,
+            || settings.contains_key(name),
+            || settings[name],
+        )
+    }
+
+    #[test]
+    fn test_setting_allowlist() {
+        let prefixes = vec![
+            "physics/".to_string(),
+            "application/config/name".to_string(),
+        ];
+        let settings = HashMap::from([
+            ("physics/common/physics_ticks_per_second", 60),
+            ("application/config/name", 1),
+            ("display/window/size/viewport_width", 1152),
+        ]);
+
+        // Allowed read
+        assert_eq!(
+            read(
+                &prefixes,
+                &settings,
+                "physics/common/physics_ticks_per_second"
+            ),
+            Ok(Some(60))
+        );
+        assert_eq!(
+            read(&prefixes, &settings, "application/config/name"),
+            Ok(Some(1))
+        );
+
+        // Denied read, even if setting exists
+        assert_eq!(
+            read(&prefixes, &settings, "display/window/size/viewport_width"),
+            Err(RetError::ErrUnauthorized)
+        );
+        assert_eq!(
+            read(&[], &settings, "physics/common/physics_ticks_per_second"),
+            Err(RetError::ErrUnauthorized)
+        );
+
+        // Missing setting falls back to default
+        let r = read(&prefixes, &settings, "physics/common/missing");
+        assert_eq!(r, Ok(None));
+        assert_eq!(r.map(|v| v.unwrap_or(42)), Ok(42));
+    }
+}
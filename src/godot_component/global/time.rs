@@ -4,6 +4,7 @@ use godot::prelude::*;
 use wasmtime::component::Resource as WasmResource;
 
 use crate::filter_macro;
+use crate::godot_component::nondet::SOURCE_GODOT_TIME;
 
 filter_macro! {method [
     singleton -> "singleton",
@@ -142,12 +143,12 @@ impl crate::godot_component::bindgen::godot::global::time::Host
 
     fn get_ticks_msec(&mut self) -> AnyResult<u64> {
         filter_macro!(filter self.filter.as_ref(), godot_global, time, get_ticks_msec)?;
-        Ok(Time::singleton().get_ticks_msec())
+        self.tap_nondet(SOURCE_GODOT_TIME, || Time::singleton().get_ticks_msec())
     }
 
     fn get_ticks_usec(&mut self) -> AnyResult<u64> {
         filter_macro!(filter self.filter.as_ref(), godot_global, time, get_ticks_usec)?;
-        Ok(Time::singleton().get_ticks_usec())
+        self.tap_nondet(SOURCE_GODOT_TIME, || Time::singleton().get_ticks_usec())
     }
 
     fn get_time_dict_from_system(&mut self, utc: bool) -> AnyResult<WasmResource<Variant>> {
@@ -197,6 +198,8 @@ impl crate::godot_component::bindgen::godot::global::time::Host
 
     fn get_unix_time_from_system(&mut self) -> AnyResult<f64> {
         filter_macro!(filter self.filter.as_ref(), godot_global, time, get_unix_time_from_system)?;
-        Ok(Time::singleton().get_unix_time_from_system())
+        self.tap_nondet(SOURCE_GODOT_TIME, || {
+            Time::singleton().get_unix_time_from_system()
+        })
     }
 }
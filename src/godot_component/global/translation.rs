@@ -0,0 +1,63 @@
+use anyhow::Result as AnyResult;
+use godot::classes::TranslationServer;
+use godot::prelude::*;
+
+use crate::filter_macro;
+use crate::godot_component::{bindgen, GodotCtx};
+
+filter_macro! {method [
+    tr -> "tr",
+    tr_n -> "tr-n",
+    get_locale -> "get-locale",
+    get_loaded_locales -> "get-loaded-locales",
+]}
+
+impl GodotCtx {
+    /// Object that owns this instance, used for context-specific translation.
+    fn translation_owner(&self) -> Option<Gd<Object>> {
+        self.inst_id
+            .and_then(|id| <Gd<Object>>::try_from_instance_id(id).ok())
+    }
+}
+
+impl bindgen::godot::global::translation::Host for GodotCtx {
+    fn tr(&mut self, message: String) -> AnyResult<String> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, translation, tr)?;
+        let message = StringName::from(message.as_str());
+        let o = self.translation_owner();
+        let r = self.release_store_main(move || match o {
+            Some(o) => o.tr(&message),
+            None => TranslationServer::singleton().translate(&message).into(),
+        })?;
+        Ok(r.to_string())
+    }
+
+    fn tr_n(&mut self, singular: String, plural: String, n: i32) -> AnyResult<String> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, translation, tr_n)?;
+        let singular = StringName::from(singular.as_str());
+        let plural = StringName::from(plural.as_str());
+        let o = self.translation_owner();
+        let r = self.release_store_main(move || match o {
+            Some(o) => o.tr_n(&singular, &plural, n),
+            None => TranslationServer::singleton()
+                .translate_plural(&singular, &plural, n)
+                .into(),
+        })?;
+        Ok(r.to_string())
+    }
+
+    fn get_locale(&mut self) -> AnyResult<String> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, translation, get_locale)?;
+        Ok(TranslationServer::singleton().get_locale().to_string())
+    }
+
+    fn get_loaded_locales(&mut self) -> AnyResult<Vec<String>> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, translation, get_loaded_locales)?;
+        Ok(TranslationServer::singleton()
+            .get_loaded_locales()
+            .as_slice()
+            .iter()
+            .map(|s| s.to_string())
+            .collect())
+    }
+}
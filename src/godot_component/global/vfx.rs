@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use anyhow::Result as AnyResult;
+use godot::classes::{Node, PackedScene};
+use godot::prelude::*;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use wasmtime::component::Resource as WasmResource;
+
+use crate::godot_component::bindgen::godot::core::core::Error as RetError;
+use crate::godot_component::{bindgen, ErrorRes, GodotCtx};
+use crate::godot_util::SendSyncWrapper;
+use crate::{bail_with_site, filter_macro};
+
+filter_macro! {method [
+    spawn_particles -> "spawn-particles",
+    set_param -> "set-param",
+    free -> "free",
+]}
+
+/// Default maximum number of live effects per instance.
+pub const DEFAULT_MAX_LIVE: usize = 64;
+
+/// Properties guest is allowed to set on spawned effect.
+const ALLOWED_PARAMS: &[&str] = &[
+    "amount",
+    "amount_ratio",
+    "emitting",
+    "explosiveness",
+    "lifetime",
+    "modulate",
+    "one_shot",
+    "position",
+    "preprocess",
+    "randomness",
+    "rotation",
+    "scale",
+    "self_modulate",
+    "speed_scale",
+    "visible",
+];
+
+/// Host-approved effect presets, shared by all instances.
+static PRESETS: Lazy<RwLock<HashMap<String, SendSyncWrapper<Gd<PackedScene>>>>> =
+    Lazy::new(Default::default);
+
+/// Registers scene that guests can spawn. `None` unregisters it.
+pub fn register_preset(name: String, scene: Option<Gd<PackedScene>>) {
+    let mut guard = PRESETS.write();
+    match scene {
+        Some(v) => guard.insert(name, SendSyncWrapper::new(v)),
+        None => guard.remove(&name),
+    };
+}
+
+/// Unregisters all presets. Called when extension is deinitialized.
+pub fn clear_presets() {
+    PRESETS.write().clear();
+}
+
+/// Effects spawned by guest.
+pub struct VfxState {
+    parent: Option<SendSyncWrapper<Gd<Node>>>,
+    live: HashMap<u32, SendSyncWrapper<Gd<Node>>>,
+    next_id: u32,
+
+    pub max_live: usize,
+}
+
+impl Default for VfxState {
+    fn default() -> Self {
+        Self {
+            parent: None,
+            live: HashMap::new(),
+            next_id: 0,
+            max_live: DEFAULT_MAX_LIVE,
+        }
+    }
+}
+
+impl Drop for VfxState {
+    fn drop(&mut self) {
+        for (_, n) in self.live.drain() {
+            let mut n = n.into_inner();
+            if n.is_instance_valid() {
+                n.call_deferred(&StringName::from(c"queue_free"), &[]);
+            }
+        }
+    }
+}
+
+impl VfxState {
+    /// Sets node which spawned effects are attached to.
+    pub fn set_parent(&mut self, parent: Option<Gd<Node>>) {
+        self.parent = parent.map(SendSyncWrapper::new);
+    }
+
+    /// Number of effects that are still alive.
+    pub fn live_count(&mut self) -> usize {
+        self.live.retain(|_, n| n.is_instance_valid());
+        self.live.len()
+    }
+
+    fn get_node(&mut self, id: u32) -> Option<Gd<Node>> {
+        match self.live.get(&id) {
+            Some(n) if n.is_instance_valid() => Some((**n).clone()),
+            Some(_) => {
+                self.live.remove(&id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, node: Gd<Node>) -> u32 {
+        loop {
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
+            if !self.live.contains_key(&id) {
+                self.live.insert(id, SendSyncWrapper::new(node));
+                return id;
+            }
+        }
+    }
+}
+
+fn check_param(name: &str, value: &Variant) -> AnyResult<()> {
+    if !ALLOWED_PARAMS.contains(&name) {
+        bail_with_site!("Parameter {name} is not allowed");
+    }
+    match value.get_type() {
+        VariantType::OBJECT | VariantType::CALLABLE | VariantType::SIGNAL => {
+            bail_with_site!(
+                "Parameter {name} has disallowed type {:?}",
+                value.get_type()
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+impl bindgen::godot::global::vfx::Host for GodotCtx {
+    fn spawn_particles(
+        &mut self,
+        preset_name: String,
+        position: WasmResource<Variant>,
+        params: Vec<(String, WasmResource<Variant>)>,
+    ) -> ErrorRes<u32> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, vfx, spawn_particles)?;
+        let position = self.get_var(position)?;
+        if !matches!(
+            position.get_type(),
+            VariantType::VECTOR2 | VariantType::VECTOR3
+        ) {
+            bail_with_site!("Position must be Vector2 or Vector3");
+        }
+        let params = params
+            .into_iter()
+            .map(|(k, v)| {
+                let v = self.get_var(v)?;
+                check_param(&k, &v)?;
+                Ok((StringName::from(k.as_str()), v))
+            })
+            .collect::<AnyResult<Vec<_>>>()?;
+
+        let Some(scene) = PRESETS.read().get(&preset_name).map(|v| (**v).clone()) else {
+            return Ok(Err(RetError::ErrDoesNotExist));
+        };
+        let Some(parent) = &self.vfx.parent else {
+            return Ok(Err(RetError::ErrUnconfigured));
+        };
+        let mut parent = (**parent).clone();
+        if self.vfx.live_count() >= self.vfx.max_live {
+            return Ok(Err(RetError::ErrBusy));
+        }
+
+        let node = self.release_store_main(move || -> AnyResult<_> {
+            let Some(mut node) = scene.instantiate() else {
+                bail_with_site!("Cannot instantiate preset {preset_name}");
+            };
+            node.set(&StringName::from(c"position"), &position);
+            for (k, v) in params {
+                node.set(&k, &v);
+            }
+            parent.add_child(&node);
+            Ok(node)
+        })??;
+        Ok(Ok(self.vfx.insert(node)))
+    }
+
+    fn set_param(&mut self, id: u32, name: String, value: WasmResource<Variant>) -> ErrorRes {
+        filter_macro!(filter self.filter.as_ref(), godot_global, vfx, set_param)?;
+        let value = self.get_var(value)?;
+        check_param(&name, &value)?;
+        let Some(mut node) = self.vfx.get_node(id) else {
+            return Ok(Err(RetError::ErrDoesNotExist));
+        };
+        let name = StringName::from(name.as_str());
+        self.release_store_main(move || node.set(&name, &value))?;
+        Ok(Ok(()))
+    }
+
+    fn free(&mut self, id: u32) -> AnyResult<bool> {
+        filter_macro!(filter self.filter.as_ref(), godot_global, vfx, free)?;
+        let Some(node) = self.vfx.live.remove(&id) else {
+            return Ok(false);
+        };
+        let mut node = node.into_inner();
+        if !node.is_instance_valid() {
+            return Ok(false);
+        }
+        self.release_store_main(move || node.queue_free())?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,360 @@
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result as AnyResult;
+use parking_lot::Mutex;
+use wasmtime::component::types::ResourceType;
+use wasmtime::component::{Func, Instance, ResourceAny, Type, Val};
+use wasmtime::{AsContextMut, StoreContextMut};
+
+use crate::bail_with_site;
+use crate::wasm_schema::ComponentExports;
+
+/// Exported functions and resource types of component instance.
+pub struct GuestExports {
+    /// Functions with fully-qualified name, formatted as `interface#function`.
+    funcs: Vec<(String, Func)>,
+    /// Resources defined by guest, with fully-qualified name.
+    resources: Vec<(ResourceType, String)>,
+}
+
+impl GuestExports {
+    pub fn new(mut store: impl AsContextMut, inst: &Instance, exports: &ComponentExports) -> Self {
+        let mut store = store.as_context_mut();
+        let mut lookup = |name: &str| {
+            let (iface, name) = match name.split_once('#') {
+                Some((iface, name)) => (Some(inst.get_export(&mut store, None, iface)?), name),
+                None => (None, name),
+            };
+            inst.get_export(&mut store, iface.as_ref(), name)
+        };
+
+        let funcs = exports
+            .funcs
+            .iter()
+            .filter_map(|(name, _)| Some((name.clone(), lookup(name)?)))
+            .collect::<Vec<_>>();
+        let resources = exports
+            .resources
+            .names()
+            .filter_map(|name| Some((name.to_string(), lookup(name)?)))
+            .collect::<Vec<_>>();
+
+        Self {
+            funcs: funcs
+                .into_iter()
+                .filter_map(|(name, i)| Some((name, inst.get_func(&mut store, i)?)))
+                .collect(),
+            // Imported resources are not exported, so they're skipped.
+            resources: resources
+                .into_iter()
+                .filter_map(|(name, i)| Some((inst.get_resource(&mut store, i)?, name)))
+                .collect(),
+        }
+    }
+
+    pub fn func(&self, name: &str) -> AnyResult<Func> {
+        match self.funcs.iter().find(|(k, _)| k == name) {
+            Some(&(_, f)) => Ok(f),
+            None => bail_with_site!("Function {name} is not exported"),
+        }
+    }
+
+    /// Gets fully-qualified name of guest resource.
+    pub fn resource_name(&self, ty: &ResourceType) -> Option<&str> {
+        self.resources
+            .iter()
+            .find(|(k, _)| k == ty)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Gets method of guest resource.
+    pub fn method(&self, ty: &ResourceType, method: &str) -> AnyResult<Func> {
+        let Some(name) = self.resource_name(ty) else {
+            bail_with_site!("Resource type is not exported")
+        };
+        let name = match name.split_once('#') {
+            Some((iface, res)) => format!("{iface}#[method]{res}.{method}"),
+            None => format!("[method]{name}.{method}"),
+        };
+        self.func(&name)
+    }
+}
+
+/// Calls component function and returns it's results.
+pub fn call_func<T>(
+    mut store: StoreContextMut<'_, T>,
+    func: Func,
+    params: &[Val],
+) -> AnyResult<Vec<Val>> {
+    let mut results = vec![Val::Bool(false); func.results(&store).len()];
+    func.call(&mut store, params, &mut results)?;
+    func.post_return(&mut store)?;
+    Ok(results)
+}
+
+/// Own handles of guest resources held by host.
+///
+/// Handles are released by wrapper without access to store,
+/// so guest resources are dropped on the next store access.
+#[derive(Default)]
+pub struct GuestResources {
+    live: AtomicUsize,
+    released: Mutex<Vec<ResourceAny>>,
+}
+
+impl GuestResources {
+    /// Holds own handle of guest resource.
+    pub fn hold(self: &Arc<Self>, handle: ResourceAny) -> GuestHandle {
+        self.live.fetch_add(1, Ordering::Relaxed);
+        GuestHandle {
+            owner: self.clone(),
+            handle: Some(handle),
+        }
+    }
+
+    /// Number of handles not yet dropped, including released ones.
+    pub fn count(&self) -> usize {
+        self.live.load(Ordering::Relaxed) + self.released.lock().len()
+    }
+
+    /// Drops released handles, running guest destructors.
+    pub fn drop_released(&self, mut store: impl AsContextMut) -> AnyResult<()> {
+        for h in mem::take(&mut *self.released.lock()) {
+            h.resource_drop(&mut store)?;
+        }
+        Ok(())
+    }
+
+    fn take(&self, handle: &mut Option<ResourceAny>) -> Option<ResourceAny> {
+        let ret = handle.take();
+        if ret.is_some() {
+            self.live.fetch_sub(1, Ordering::Relaxed);
+        }
+        ret
+    }
+}
+
+/// Own handle of guest resource, released on drop.
+pub struct GuestHandle {
+    owner: Arc<GuestResources>,
+    handle: Option<ResourceAny>,
+}
+
+impl Drop for GuestHandle {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl GuestHandle {
+    /// Gets resource type, or `None` if handle is moved or released.
+    pub fn ty(&self) -> Option<ResourceType> {
+        self.handle.map(|h| h.ty())
+    }
+
+    pub fn is_owned_by(&self, owner: &Arc<GuestResources>) -> bool {
+        Arc::ptr_eq(&self.owner, owner)
+    }
+
+    /// Releases handle, it's dropped on the next store access.
+    pub fn release(&mut self) {
+        if let Some(h) = self.owner.take(&mut self.handle) {
+            self.owner.released.lock().push(h);
+        }
+    }
+
+    /// Lowers handle into parameter.
+    ///
+    /// Borrow parameter lends the handle, while own parameter moves it into guest.
+    pub fn lower(&mut self, ty: &Type) -> AnyResult<Val> {
+        let (Type::Own(t) | Type::Borrow(t)) = ty else {
+            bail_with_site!("Resource passed as non-resource parameter")
+        };
+        let Some(h) = self.handle else {
+            bail_with_site!("Resource handle is already moved or released")
+        };
+        if h.ty() != *t {
+            bail_with_site!("Resource type mismatch");
+        }
+        if let Type::Own(_) = ty {
+            self.owner.take(&mut self.handle);
+        }
+        Ok(Val::Resource(h))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasmtime::component::{Component, Linker};
+    use wasmtime::{Config, Engine, Store};
+
+    /// Component defining resource `counter`, destructor counts drops.
+    const COMPONENT: &str = r#"
+(component
+  (core module $state
+    (global (export "drops") (mut i32) (i32.const 0))
+    (func (export "dtor") (param i32)
+      global.get 0
+      i32.const 1
+      i32.add
+      global.set 0
+    )
+  )
+  (core instance $state (instantiate $state))
+  (type $counter (resource (rep i32) (dtor (func $state "dtor"))))
+  (core func $new (canon resource.new $counter))
+  (core func $drop (canon resource.drop $counter))
+
+  (core module $m
+    (import "" "new" (func $new (param i32) (result i32)))
+    (import "" "drop" (func $drop (param i32)))
+    (import "" "drops" (global $drops (mut i32)))
+    (func (export "make") (param i32) (result i32)
+      local.get 0
+      call $new
+    )
+    (func (export "get") (param i32) (result i32)
+      local.get 0
+    )
+    (func (export "consume") (param i32)
+      local.get 0
+      call $drop
+    )
+    (func (export "drops") (result i32)
+      global.get $drops
+    )
+  )
+  (core instance $m (instantiate $m
+    (with "" (instance
+      (export "new" (func $new))
+      (export "drop" (func $drop))
+      (export "drops" (global $state "drops"))
+    ))
+  ))
+
+  (export $c "counter" (type $counter))
+  (func (export "make") (param "v" u32) (result (own $c))
+    (canon lift (core func $m "make"))
+  )
+  (func (export "[method]counter.get") (param "self" (borrow $c)) (result u32)
+    (canon lift (core func $m "get"))
+  )
+  (func (export "consume") (param "v" (own $c))
+    (canon lift (core func $m "consume"))
+  )
+  (func (export "drops") (result u32)
+    (canon lift (core func $m "drops"))
+  )
+)
+"#;
+
+    fn instantiate() -> (Store<()>, GuestExports) {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config).unwrap();
+        let comp = Component::new(&engine, COMPONENT).unwrap();
+        let mut store = Store::new(&engine, ());
+        let inst = Linker::new(&engine).instantiate(&mut store, &comp).unwrap();
+        let exports = GuestExports::new(&mut store, &inst, &ComponentExports::new(&comp));
+        (store, exports)
+    }
+
+    fn make(store: &mut Store<()>, exports: &GuestExports, v: u32) -> ResourceAny {
+        let f = exports.func("make").unwrap();
+        match &call_func(store.as_context_mut(), f, &[Val::U32(v)]).unwrap()[..] {
+            [Val::Resource(r)] => *r,
+            v => panic!("unexpected results {v:?}"),
+        }
+    }
+
+    fn drops(store: &mut Store<()>, exports: &GuestExports) -> u32 {
+        let f = exports.func("drops").unwrap();
+        match &call_func(store.as_context_mut(), f, &[]).unwrap()[..] {
+            [Val::U32(v)] => *v,
+            v => panic!("unexpected results {v:?}"),
+        }
+    }
+
+    #[test]
+    fn test_guest_resource() {
+        let (mut store, exports) = instantiate();
+        let guest = Arc::new(GuestResources::default());
+
+        let r = make(&mut store, &exports, 5);
+        assert_eq!(exports.resource_name(&r.ty()), Some("counter"));
+        let mut h = guest.hold(r);
+        assert_eq!(guest.count(), 1);
+
+        // Handle is lent to method, so it stays valid.
+        let f = exports.method(&r.ty(), "get").unwrap();
+        let (_, ty) = &f.params(&store)[0];
+        for _ in 0..2 {
+            let p = h.lower(ty).unwrap();
+            let ret = call_func(store.as_context_mut(), f, &[p]).unwrap();
+            assert!(matches!(ret[..], [Val::U32(5)]), "{ret:?}");
+        }
+        exports.method(&r.ty(), "set").unwrap_err();
+        h.lower(&Type::U32).unwrap_err();
+
+        // Released handle is dropped on the next store access.
+        drop(h);
+        assert_eq!(guest.count(), 1);
+        assert_eq!(drops(&mut store, &exports), 0);
+        guest.drop_released(&mut store).unwrap();
+        assert_eq!(guest.count(), 0);
+        assert_eq!(drops(&mut store, &exports), 1);
+    }
+
+    #[test]
+    fn test_guest_resource_many() {
+        let (mut store, exports) = instantiate();
+        let guest = Arc::new(GuestResources::default());
+
+        let mut handles = (0..10)
+            .map(|i| guest.hold(make(&mut store, &exports, i)))
+            .collect::<Vec<_>>();
+        let other = Arc::new(GuestResources::default());
+        assert!(handles[0].is_owned_by(&guest));
+        assert!(!handles[0].is_owned_by(&other));
+
+        handles.truncate(4);
+        assert_eq!(guest.count(), 10);
+        guest.drop_released(&mut store).unwrap();
+        assert_eq!(guest.count(), 4);
+        assert_eq!(drops(&mut store, &exports), 6);
+
+        // Released twice is noop.
+        handles[0].release();
+        handles[0].release();
+        assert!(handles[0].ty().is_none());
+        handles.clear();
+        guest.drop_released(&mut store).unwrap();
+        assert_eq!(guest.count(), 0);
+        assert_eq!(drops(&mut store, &exports), 10);
+    }
+
+    #[test]
+    fn test_guest_resource_move() {
+        let (mut store, exports) = instantiate();
+        let guest = Arc::new(GuestResources::default());
+
+        let mut h = guest.hold(make(&mut store, &exports, 1));
+        let f = exports.func("consume").unwrap();
+        let (_, ty) = &f.params(&store)[0];
+        let p = h.lower(ty).unwrap();
+        assert!(h.ty().is_none());
+        assert_eq!(guest.count(), 0);
+        call_func(store.as_context_mut(), f, &[p]).unwrap();
+        assert_eq!(drops(&mut store, &exports), 1);
+
+        // Moved handle can't be passed again.
+        h.lower(ty).unwrap_err();
+        drop(h);
+        guest.drop_released(&mut store).unwrap();
+        assert_eq!(drops(&mut store, &exports), 1);
+    }
+}
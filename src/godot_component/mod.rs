@@ -1,18 +1,24 @@
 mod classes;
+pub mod compat;
 mod core;
 pub mod filter;
-mod global;
+pub mod global;
+mod guest;
+pub mod nondet;
+mod table;
 
 use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{bail, Result as AnyResult};
 use godot::global::Error;
 use godot::prelude::*;
-use slab::Slab;
+use wasi_isolated_fs::nondet::NondetTap;
 use wasmtime::component::{Linker, Resource as WasmResource};
 
-use crate::godot_util::{from_var_any, ErrorWrapper, SendSyncWrapper};
-use crate::wasm_instance::InnerLock;
+use crate::godot_util::{from_var_any, should_warn, ErrorWrapper, SendSyncWrapper};
+use crate::wasm_instance::{ActivityTracker, CallFrame, InnerLock};
 use crate::{bail_with_site, filter_macro};
 
 filter_macro! {module [
@@ -37,11 +43,34 @@ mod reflection_filter {
 pub struct GodotCtx {
     inner_lock: InnerLock,
 
-    table: Slab<SendSyncWrapper<Variant>>,
+    table: table::Table<SendSyncWrapper<Variant>>,
 
     pub inst_id: Option<InstanceId>,
 
     pub filter: filter::Filter,
+
+    /// Methods denied by filter, `filter_denied` is emitted once for each of them.
+    pub(crate) denied: filter::DeniedSet,
+
+    /// Project settings prefixes guest is allowed to read.
+    pub settings_prefixes: Vec<String>,
+
+    pub(crate) canvas: global::canvas::CanvasState,
+
+    pub(crate) vfx: global::vfx::VfxState,
+
+    pub(crate) noise: global::noise::NoiseState,
+
+    pub(crate) input: global::input::InputState,
+
+    pub(crate) services: global::engine::ServicesState,
+
+    pub(crate) signals: core::signal::SignalState,
+
+    pub nondet: Option<Arc<dyn NondetTap>>,
+
+    /// Activity of owning object, used for cancellation.
+    pub activity: Option<Arc<ActivityTracker>>,
 }
 
 impl AsMut<GodotCtx> for GodotCtx {
@@ -78,10 +107,56 @@ impl GodotCtx {
         self.inner_lock.release_store(f)
     }
 
+    #[inline]
+    pub(crate) fn release_store_main<F, R>(&mut self, f: F) -> AnyResult<R>
+    where
+        F: FnOnce() -> R,
+    {
+        self.inner_lock.release_store_main(f)
+    }
+
+    #[inline]
+    pub(crate) fn release_store_call<F, R>(&mut self, frame: CallFrame, f: F) -> AnyResult<R>
+    where
+        F: FnOnce() -> R,
+    {
+        self.inner_lock.release_store_call(frame, f)
+    }
+
+    /// Records filter denial and emits `filter_denied` signal the first time method is denied.
+    ///
+    /// It's called at the start of host call, so variant table is also compacted here.
+    pub(crate) fn check_filter(
+        &mut self,
+        r: Result<(), filter::FilterDeniedError>,
+    ) -> Result<(), filter::FilterDeniedError> {
+        self.table.maybe_compact();
+
+        let Err(e) = r else { return Ok(()) };
+        if let (true, Some(id)) = (self.denied.insert(&e), self.inst_id) {
+            let (interface, method) = (e.interface.clone(), e.method);
+            let r = self.release_store_main(move || {
+                if let Ok(mut o) = Gd::<Object>::try_from_instance_id(id) {
+                    o.emit_signal(
+                        &StringName::from(c"filter_denied"),
+                        &[
+                            GString::from(&interface).to_variant(),
+                            GString::from(method).to_variant(),
+                        ],
+                    );
+                }
+            });
+            if let Err(err) = r {
+                tracing::warn!(%err, "Cannot emit filter_denied");
+            }
+        }
+        Err(e)
+    }
+
     pub fn get_var_borrow(&mut self, res: WasmResource<Variant>) -> AnyResult<Cow<Variant>> {
-        let i = res.rep() as usize;
+        let i = res.rep();
         if res.owned() {
-            if let Some(v) = self.table.try_remove(i) {
+            if let Some(v) = self.table.remove(i) {
                 return Ok(Cow::Owned(v.into_inner()));
             }
         } else if let Some(v) = self.table.get(i) {
@@ -121,10 +196,7 @@ impl GodotCtx {
     }
 
     pub fn try_insert(&mut self, var: Variant) -> AnyResult<u32> {
-        let entry = self.table.vacant_entry();
-        let ret = u32::try_from(entry.key())?;
-        entry.insert(SendSyncWrapper::new(var));
-        Ok(ret)
+        self.table.insert(SendSyncWrapper::new(var))
     }
 
     pub fn set_var(&mut self, var: Variant) -> AnyResult<Option<WasmResource<Variant>>> {
@@ -146,6 +218,7 @@ impl GodotCtx {
 pub type GVar = Variant;
 
 pub mod bindgen {
+    pub use super::global::input::InputSnapshot;
     pub use super::GVar;
 
     wasmtime::component::bindgen!({
@@ -158,12 +231,24 @@ pub mod bindgen {
         trappable_imports: true,
         with: {
             "godot:core/core/godot-var": GVar,
+            "godot:global/input/input-state": InputSnapshot,
         },
     });
 }
 
 type ErrorRes<T = ()> = AnyResult<Result<T, bindgen::godot::core::core::Error>>;
 
+/// Converts filter denial into `err-unauthorized` for interfaces returning error.
+fn denied_error<T>(e: filter::FilterDeniedError) -> ErrorRes<T> {
+    if should_warn(
+        &format!("filter_denied.{}.{}", e.interface, e.method),
+        Some(Duration::from_secs(1)),
+    ) {
+        tracing::warn!(%e, "Filter denied");
+    }
+    Ok(Err(bindgen::godot::core::core::Error::ErrUnauthorized))
+}
+
 fn wrap_error(e: Error) -> ErrorRes {
     use bindgen::godot::core::core::Error as RetError;
     match e {
@@ -238,24 +323,24 @@ impl bindgen::godot::core::core::Host for GodotCtx {
         a: WasmResource<Variant>,
         b: WasmResource<Variant>,
     ) -> AnyResult<bool> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, core, var_equals)?;
+        filter_macro!(filter ctx self, godot_core, core, var_equals)?;
         Ok(self.get_var(a)? == self.get_var(b)?)
     }
 
     fn var_hash(&mut self, var: WasmResource<Variant>) -> AnyResult<i64> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, core, var_hash)?;
+        filter_macro!(filter ctx self, godot_core, core, var_hash)?;
         Ok(self.get_var(var)?.hash())
     }
 
     fn var_stringify(&mut self, var: WasmResource<Variant>) -> AnyResult<String> {
-        filter_macro!(filter self.filter.as_ref(), godot_core, core, var_stringify)?;
+        filter_macro!(filter ctx self, godot_core, core, var_stringify)?;
         Ok(self.get_var(var)?.to_string())
     }
 }
 
 impl bindgen::godot::reflection::this::Host for GodotCtx {
     fn get_this(&mut self) -> AnyResult<WasmResource<Variant>> {
-        filter_macro!(filter self.filter.as_ref(), godot_reflection, this, get_this)?;
+        filter_macro!(filter ctx self, godot_reflection, this, get_this)?;
         let Some(id) = self.inst_id else {
             bail_with_site!("Self instance ID is not set")
         };
@@ -296,13 +381,21 @@ pub fn add_to_linker<T, U: AsMut<GodotCtx> + 'static>(
     bindgen::godot::core::object::add_to_linker(&mut *linker, f)?;
     bindgen::godot::core::callable::add_to_linker(&mut *linker, f)?;
     bindgen::godot::core::signal::add_to_linker(&mut *linker, f)?;
+    bindgen::godot::core::transform::add_to_linker(&mut *linker, f)?;
+    bindgen::godot::core::task::add_to_linker(&mut *linker, f)?;
 
     bindgen::godot::global::globalscope::add_to_linker(&mut *linker, f)?;
     bindgen::godot::global::classdb::add_to_linker(&mut *linker, f)?;
     bindgen::godot::global::engine::add_to_linker(&mut *linker, f)?;
     bindgen::godot::global::input::add_to_linker(&mut *linker, f)?;
     bindgen::godot::global::input_map::add_to_linker(&mut *linker, f)?;
+    bindgen::godot::global::project_settings::add_to_linker(&mut *linker, f)?;
     bindgen::godot::global::ip::add_to_linker(&mut *linker, f)?;
+    bindgen::godot::global::canvas::add_to_linker(&mut *linker, f)?;
+    bindgen::godot::global::vfx::add_to_linker(&mut *linker, f)?;
+    bindgen::godot::global::noise::add_to_linker(&mut *linker, f)?;
+    bindgen::godot::global::translation::add_to_linker(&mut *linker, f)?;
+    bindgen::godot::global::display::add_to_linker(&mut *linker, f)?;
 
     bindgen::godot::reflection::this::add_to_linker(&mut *linker, f)
 }
@@ -0,0 +1,89 @@
+use anyhow::Result as AnyResult;
+use wasi_isolated_fs::nondet::NondetTap;
+
+use crate::bail_with_site;
+use crate::godot_component::bindgen::godot::core::primitive;
+use crate::godot_component::GodotCtx;
+
+/// Source name of Godot time query.
+pub const SOURCE_GODOT_TIME: &str = "godot.time";
+/// Source name of Godot random number generator.
+pub const SOURCE_GODOT_RANDOM: &str = "godot.random";
+/// Source name of Godot input query.
+pub const SOURCE_GODOT_INPUT: &str = "godot.input";
+
+/// Value that can be recorded by [`NondetTap`].
+pub trait NondetValue: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(v: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_nondet_num {
+    ($($t:ty),* $(,)?) => {$(
+        impl NondetValue for $t {
+            fn to_bytes(&self) -> Vec<u8> {
+                self.to_le_bytes().to_vec()
+            }
+
+            fn from_bytes(v: &[u8]) -> Option<Self> {
+                Some(Self::from_le_bytes(v.try_into().ok()?))
+            }
+        }
+    )*};
+}
+
+impl_nondet_num!(u64, i64, f32, f64);
+
+impl NondetValue for bool {
+    fn to_bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn from_bytes(v: &[u8]) -> Option<Self> {
+        match v {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl NondetValue for primitive::Vector2 {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut r = self.x.to_bytes();
+        r.extend_from_slice(&self.y.to_le_bytes());
+        r
+    }
+
+    fn from_bytes(v: &[u8]) -> Option<Self> {
+        let (x, y) = v.split_at_checked(4)?;
+        Some(Self {
+            x: f32::from_bytes(x)?,
+            y: f32::from_bytes(y)?,
+        })
+    }
+}
+
+impl GodotCtx {
+    /// Passes value from nondeterministic source through tap.
+    ///
+    /// Shares the same tap (and thus recording) with WASI context.
+    pub(crate) fn tap_nondet<T: NondetValue>(
+        &self,
+        source: &str,
+        f: impl FnOnce() -> T,
+    ) -> AnyResult<T> {
+        let Some(tap) = self.nondet.as_deref() else {
+            return Ok(f());
+        };
+        if let Some(v) = tap.replay(source)? {
+            let Some(v) = T::from_bytes(&v) else {
+                bail_with_site!("Recorded value of {source} is malformed");
+            };
+            return Ok(v);
+        }
+        let v = f();
+        tap.record(source, &v.to_bytes())?;
+        Ok(v)
+    }
+}
@@ -0,0 +1,213 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::mem::replace;
+
+use anyhow::{bail, Result as AnyResult};
+use slab::Slab;
+
+/// Minimum capacity of table before it's compacted.
+const COMPACT_MIN_CAPACITY: usize = 64;
+/// Table is compacted when less than `1 / COMPACT_RATIO` of it's capacity is used.
+const COMPACT_RATIO: usize = 4;
+/// Marks unused rep.
+const VACANT: u32 = u32::MAX;
+
+/// Table of values referenced by guest resources.
+///
+/// Guest handles (reps) index into indirection table, which in turn points into value slab.
+/// Values can then be moved by compaction without invalidating outstanding handles.
+/// Freed reps are reused lowest first, so they stay small.
+pub struct Table<T> {
+    values: Slab<(u32, T)>,
+    /// Maps rep into value slot.
+    slots: Vec<u32>,
+    free: BinaryHeap<Reverse<u32>>,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self {
+            values: Slab::new(),
+            slots: Vec::new(),
+            free: BinaryHeap::new(),
+        }
+    }
+}
+
+impl<T> Table<T> {
+    /// Number of live values.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Capacity of value slab.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.values.capacity()
+    }
+
+    /// Number of reps, including unused ones.
+    #[cfg(test)]
+    #[inline]
+    pub fn rep_len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn insert(&mut self, v: T) -> AnyResult<u32> {
+        let rep = match self.free.pop() {
+            Some(Reverse(rep)) => rep,
+            None => {
+                let Some(rep) = u32::try_from(self.slots.len())
+                    .ok()
+                    .filter(|&v| v != VACANT)
+                else {
+                    bail!("Too many values in table")
+                };
+                self.slots.push(VACANT);
+                rep
+            }
+        };
+        // Value slab is never longer than reps, so key fits.
+        self.slots[rep as usize] = self.values.insert((rep, v)) as u32;
+        Ok(rep)
+    }
+
+    pub fn get(&self, rep: u32) -> Option<&T> {
+        match self.slots.get(rep as usize) {
+            Some(&i) if i != VACANT => self.values.get(i as usize).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn remove(&mut self, rep: u32) -> Option<T> {
+        let i = self.slots.get_mut(rep as usize).filter(|i| **i != VACANT)?;
+        let (_, v) = self.values.remove(replace(i, VACANT) as usize);
+        self.free.push(Reverse(rep));
+        Some(v)
+    }
+
+    /// Compacts table if it's sparse enough. Returns `true` if it's compacted.
+    ///
+    /// Live values are moved into dense slab and trailing unused reps are released.
+    /// Reps that are still in use are never changed.
+    pub fn maybe_compact(&mut self) -> bool {
+        let sparse = |cap: usize| cap >= COMPACT_MIN_CAPACITY && self.len() * COMPACT_RATIO < cap;
+        // Reps can only be released if the last one is unused.
+        let trailing = self.slots.last() == Some(&VACANT);
+        if !(sparse(self.values.capacity()) || trailing && sparse(self.slots.len())) {
+            return false;
+        }
+
+        let before = self.capacity();
+        let slots = &mut self.slots;
+        self.values.compact(|(rep, _), _, to| {
+            slots[*rep as usize] = to as u32;
+            true
+        });
+
+        let n = slots
+            .iter()
+            .rposition(|&i| i != VACANT)
+            .map_or(0, |i| i + 1);
+        slots.truncate(n);
+        slots.shrink_to_fit();
+        self.free.retain(|&Reverse(rep)| (rep as usize) < n);
+        self.free.shrink_to_fit();
+        tracing::debug!(before, after = self.capacity(), reps = n, "Table compacted");
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rep_reuse() {
+        let mut table = Table::default();
+        for i in 0..4u64 {
+            assert_eq!(table.insert(i).unwrap(), i as u32);
+        }
+        assert_eq!(table.remove(2), Some(2));
+        assert_eq!(table.remove(0), Some(0));
+        assert_eq!(table.remove(0), None);
+        assert_eq!(table.get(2), None);
+        assert_eq!(table.get(100), None);
+
+        // Lowest rep is reused first.
+        assert_eq!(table.insert(10).unwrap(), 0);
+        assert_eq!(table.insert(12).unwrap(), 2);
+        assert_eq!(table.insert(14).unwrap(), 4);
+        assert_eq!(table.get(0), Some(&10));
+        assert_eq!(table.get(3), Some(&3));
+    }
+
+    #[test]
+    fn test_churn_compact() {
+        const N: u64 = 100_000;
+
+        let mut table = Table::default();
+        let mut live = Vec::new();
+        let mut prev = None;
+        for i in 0..N {
+            let rep = table.insert(i).unwrap();
+            // Keep every 1000th value, like long-lived handles.
+            if i % 1000 == 999 {
+                live.push((rep, i));
+            } else if let Some((p, v)) = prev.take() {
+                assert_eq!(table.remove(p), Some(v));
+                assert_eq!(table.remove(rep), Some(i));
+            } else {
+                prev = Some((rep, i));
+            }
+        }
+        if let Some((p, v)) = prev {
+            assert_eq!(table.remove(p), Some(v));
+        }
+        for i in 0..N {
+            let rep = table.insert(i).unwrap();
+            assert_eq!(table.remove(rep), Some(i));
+        }
+        assert_eq!(table.len(), live.len());
+        assert!(!table.maybe_compact());
+
+        // Spike of values, then mostly removed.
+        let spike = (0..N).map(|i| table.insert(i).unwrap()).collect::<Vec<_>>();
+        for &rep in &spike[..spike.len() - 10] {
+            table.remove(rep).unwrap();
+        }
+        let before = table.capacity();
+        assert!(before >= N as usize);
+
+        assert!(table.maybe_compact());
+        assert!(table.capacity() < 256, "capacity: {}", table.capacity());
+        assert!(table.capacity() < before);
+        // Reps of remaining spike values are still in use.
+        assert_eq!(table.rep_len(), *spike.last().unwrap() as usize + 1);
+        assert!(!table.maybe_compact());
+
+        // Outstanding handles stay valid.
+        for &(rep, v) in &live {
+            assert_eq!(table.get(rep), Some(&v));
+        }
+        for (&rep, v) in spike.iter().zip(0..).skip(spike.len() - 10) {
+            assert_eq!(table.get(rep), Some(&v));
+        }
+        for &rep in &spike[spike.len() - 10..] {
+            table.remove(rep).unwrap();
+        }
+
+        // Trailing reps are released.
+        assert!(table.maybe_compact());
+        let n = live.iter().map(|&(rep, _)| rep as usize + 1).max().unwrap();
+        assert_eq!(table.rep_len(), n);
+        let rep = table.insert(0).unwrap();
+        assert!(rep as usize <= n);
+        table.remove(rep);
+        for &(rep, v) in &live {
+            assert_eq!(table.remove(rep), Some(v));
+        }
+        assert_eq!(table.len(), 0);
+    }
+}
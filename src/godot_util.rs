@@ -1,16 +1,27 @@
 use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use std::marker::PhantomData;
+use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::str::from_utf8;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 
 use anyhow::Result as AnyResult;
 use godot::global::Error as GError;
 use godot::meta::PropertyHintInfo;
 use godot::prelude::*;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
 use smol_str::SmolStr;
 
+use crate::bail_with_site;
+use crate::wasm_config::ObjectCallPolicy;
+
 /// WARNING: Incredibly unsafe.
 /// It's just used as workaround to pass Godot objects across closure.
 /// (At least until it supports multi-threading)
@@ -55,6 +66,60 @@ pub fn from_var_any<T: FromGodot>(v: impl Borrow<Variant>) -> AnyResult<T> {
     v.borrow().try_to::<T>().map_err(|e| e.into_erased().into())
 }
 
+/// Duplicates container value (array, dictionary, and packed arrays).
+/// Other values are returned as is.
+///
+/// Packed arrays are copied by converting to typed array and back. Godot packed array storage
+/// is copy-on-write, so both copies share it until either side writes.
+/// (Cloning variant shares the array itself.)
+pub fn duplicate_variant(v: &Variant, deep: bool) -> Variant {
+    crate::variant_dispatch!(v {
+        ARRAY => (if deep { v.duplicate_deep() } else { v.duplicate_shallow() }).to_variant(),
+        DICTIONARY => (if deep { v.duplicate_deep() } else { v.duplicate_shallow() }).to_variant(),
+        PACKED_BYTE_ARRAY => v.to_variant(),
+        PACKED_INT32_ARRAY => v.to_variant(),
+        PACKED_INT64_ARRAY => v.to_variant(),
+        PACKED_FLOAT32_ARRAY => v.to_variant(),
+        PACKED_FLOAT64_ARRAY => v.to_variant(),
+        PACKED_STRING_ARRAY => v.to_variant(),
+        PACKED_VECTOR2_ARRAY => v.to_variant(),
+        PACKED_VECTOR3_ARRAY => v.to_variant(),
+        PACKED_COLOR_ARRAY => v.to_variant(),
+        _ => v.clone(),
+    })
+}
+
+/// Approximate size (in bytes) of value when marshaled.
+///
+/// Strings and packed arrays use their length. Arrays are only walked one level deep.
+pub fn marshal_size(v: &Variant) -> usize {
+    fn sized(v: &Variant) -> Option<usize> {
+        Some(crate::variant_dispatch!(v {
+            STRING => v.len(),
+            PACKED_BYTE_ARRAY => v.len(),
+            PACKED_INT32_ARRAY => v.len().saturating_mul(4),
+            PACKED_INT64_ARRAY => v.len().saturating_mul(8),
+            PACKED_FLOAT32_ARRAY => v.len().saturating_mul(4),
+            PACKED_FLOAT64_ARRAY => v.len().saturating_mul(8),
+            PACKED_STRING_ARRAY => v.len().saturating_mul(mem::size_of::<GString>()),
+            PACKED_VECTOR2_ARRAY => v.len().saturating_mul(mem::size_of::<Vector2>()),
+            PACKED_VECTOR3_ARRAY => v.len().saturating_mul(mem::size_of::<Vector3>()),
+            PACKED_COLOR_ARRAY => v.len().saturating_mul(mem::size_of::<Color>()),
+            _ => return None,
+        }))
+    }
+
+    sized(v).unwrap_or_else(|| {
+        crate::variant_dispatch!(v {
+            ARRAY => v
+                .iter_shared()
+                .map(|v| sized(&v).unwrap_or(mem::size_of::<Variant>()))
+                .fold(0, usize::saturating_add),
+            _ => mem::size_of::<Variant>(),
+        })
+    })
+}
+
 #[allow(dead_code)]
 pub fn gstring_from_maybe_utf8(buf: &[u8]) -> GString {
     match String::from_utf8_lossy(buf) {
@@ -81,6 +146,223 @@ pub fn variant_to_option<T: FromGodot>(v: Variant) -> AnyResult<Option<T>> {
     }
 }
 
+static MAIN_THREAD: OnceCell<ThreadId> = OnceCell::new();
+
+/// Records current thread as main thread. Should be called at extension initialization.
+pub fn init_main_thread() {
+    // Emulated main thread always claims it first.
+    #[cfg(test)]
+    Lazy::force(&tests::MAIN_QUEUE);
+    MAIN_THREAD.get_or_init(|| thread::current().id());
+}
+
+pub fn is_main_thread() -> bool {
+    MAIN_THREAD
+        .get()
+        .is_none_or(|&id| id == thread::current().id())
+}
+
+/// Emits signal, deferring it to main thread if called from other thread.
+pub fn emit_in_main_thread(signal: &Signal, args: &[Variant]) {
+    if is_main_thread() {
+        signal.emit(args);
+    } else {
+        emit_deferred(signal, args);
+    }
+}
+
+/// Emits signal at idle time of main thread, even if called from main thread.
+pub fn emit_deferred(signal: &Signal, args: &[Variant]) {
+    if let Some(mut obj) = signal.object() {
+        let mut v = Vec::with_capacity(args.len() + 1);
+        v.push(signal.name().to_variant());
+        v.extend_from_slice(args);
+        obj.call_deferred(&StringName::from(c"emit_signal"), &v);
+    }
+}
+
+/// Emission state of a rate-limited warning.
+struct WarnState {
+    last: Instant,
+    suppressed: u64,
+}
+
+/// Rate-limited warnings, keyed by warning key.
+static WARNINGS: Lazy<Mutex<HashMap<String, WarnState>>> = Lazy::new(Default::default);
+
+/// Returns `true` if warning with `key` should be emitted.
+///
+/// If `period` is `None`, warning is emitted only once. Otherwise it's emitted at most once per period.
+/// Suppressed warnings are counted, see [`suppressed_warning_counts`].
+pub fn should_warn(key: &str, period: Option<Duration>) -> bool {
+    should_warn_at(&mut WARNINGS.lock(), key, period, Instant::now())
+}
+
+fn should_warn_at(
+    map: &mut HashMap<String, WarnState>,
+    key: &str,
+    period: Option<Duration>,
+    now: Instant,
+) -> bool {
+    let Some(v) = map.get_mut(key) else {
+        map.insert(
+            key.to_owned(),
+            WarnState {
+                last: now,
+                suppressed: 0,
+            },
+        );
+        return true;
+    };
+    if period.is_some_and(|p| now.saturating_duration_since(v.last) >= p) {
+        v.last = now;
+        true
+    } else {
+        v.suppressed += 1;
+        false
+    }
+}
+
+/// Number of suppressed emissions of each warning key.
+pub fn suppressed_warning_counts() -> Vec<(String, u64)> {
+    WARNINGS
+        .lock()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.suppressed))
+        .collect()
+}
+
+/// Emits Godot warning only the first time for each key.
+#[macro_export]
+macro_rules! warn_once {
+    ($key:expr, $($t:tt)*) => {
+        if $crate::godot_util::should_warn(::std::convert::AsRef::<str>::as_ref(&$key), None) {
+            ::godot::prelude::godot_warn!($($t)*);
+        }
+    };
+}
+
+/// Emits Godot warning at most once per `per_seconds` for each key.
+#[macro_export]
+macro_rules! warn_rate_limited {
+    ($key:expr, $per_seconds:expr, $($t:tt)*) => {
+        if $crate::godot_util::should_warn(
+            ::std::convert::AsRef::<str>::as_ref(&$key),
+            Some(::std::time::Duration::from_secs_f64($per_seconds)),
+        ) {
+            ::godot::prelude::godot_warn!($($t)*);
+        }
+    };
+}
+
+/// Checks if calling object method is allowed in current thread.
+///
+/// Returns `true` if call must be deferred to main thread.
+pub fn check_object_call(policy: ObjectCallPolicy) -> AnyResult<bool> {
+    if is_main_thread() {
+        return Ok(false);
+    }
+
+    match policy {
+        ObjectCallPolicy::Defer => Ok(true),
+        ObjectCallPolicy::Error => bail_with_site!(
+            "Calling object method outside of main thread (thread {:?}) is not allowed. \
+            Set config \"threading.objectCalls\" to \"defer\" to run it in main thread instead.",
+            thread::current().id(),
+        ),
+    }
+}
+
+struct DeferredCall {
+    id: u64,
+    f: Option<SendSyncWrapper<Box<dyn FnOnce()>>>,
+}
+
+impl PartialEq for DeferredCall {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl std::hash::Hash for DeferredCall {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl Debug for DeferredCall {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("DeferredCall")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for DeferredCall {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "DeferredCall({})", self.id)
+    }
+}
+
+impl DeferredCall {
+    fn run(&mut self) {
+        if let Some(f) = self.f.take() {
+            (f.into_inner())();
+        }
+    }
+}
+
+impl RustCallable for DeferredCall {
+    fn invoke(&mut self, _: &[&Variant]) -> Result<Variant, ()> {
+        self.run();
+        Ok(Variant::nil())
+    }
+}
+
+/// Runs function in main thread, blocking until it returns.
+///
+/// **⚠ Deadlocks if main thread is waiting for current thread.**
+pub fn call_in_main_thread<F, R>(f: F) -> AnyResult<R>
+where
+    F: FnOnce() -> R,
+{
+    let (tx, rx) = mpsc::sync_channel(1);
+    // Tuple fields are dropped in order, so f is always dropped before tx.
+    let v = (SendSyncWrapper::new(f), tx);
+    let f: Box<dyn FnOnce() + '_> = Box::new(move || {
+        let (f, tx) = v;
+        let r = (f.into_inner())();
+        // Receiver may be gone, ignore it.
+        let _ = tx.send(SendSyncWrapper::new(r));
+    });
+    // SAFETY: Closure may borrow from caller's stack, so it must be gone before we return.
+    // We return only after receiving from rx, which happens when either:
+    // - Closure runs, at which point f is already consumed and only tx is left.
+    // - Closure is dropped without running. Captured f is dropped before tx (see above).
+    // Otherwise we block forever, thus borrow outlives every use of closure.
+    let f = unsafe { mem::transmute::<Box<dyn FnOnce() + '_>, Box<dyn FnOnce() + 'static>>(f) };
+    defer_to_main(f);
+
+    match rx.recv() {
+        Ok(v) => Ok(v.into_inner()),
+        Err(_) => bail_with_site!("Deferred call is dropped before running"),
+    }
+}
+
+/// Queues function to run at idle time of main thread.
+fn defer_to_main(f: Box<dyn FnOnce()>) {
+    static ID: AtomicU64 = AtomicU64::new(0);
+
+    let call = DeferredCall {
+        id: ID.fetch_add(1, Ordering::Relaxed),
+        f: Some(SendSyncWrapper::new(f)),
+    };
+    #[cfg(not(test))]
+    Callable::from_custom(call).call_deferred(&[]);
+    #[cfg(test)]
+    tests::MAIN_QUEUE.lock().send(call).unwrap();
+}
+
 pub struct PhantomProperty<T>(PhantomData<T>);
 
 impl<T: Default> Default for PhantomProperty<T> {
@@ -1028,3 +1310,98 @@ pub fn to_lower_inline_smol_str(chars: &[char]) -> Option<SmolStr> {
         from_utf8(&buf[..i]).expect("Concatenated utf8-encoded chars must be a string"),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Emulated main thread, runs deferred calls in order.
+    pub(super) static MAIN_QUEUE: Lazy<Mutex<mpsc::Sender<DeferredCall>>> = Lazy::new(|| {
+        let (tx, rx) = mpsc::channel::<DeferredCall>();
+        let (init_tx, init_rx) = mpsc::sync_channel(0);
+        thread::spawn(move || {
+            MAIN_THREAD.set(thread::current().id()).unwrap();
+            init_tx.send(()).unwrap();
+            for mut call in rx {
+                // Like Godot, panic does not take down main thread.
+                let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| call.run()));
+            }
+        });
+        init_rx.recv().unwrap();
+        Mutex::new(tx)
+    });
+
+    #[test]
+    fn test_object_call_thread() {
+        init_main_thread();
+
+        call_in_main_thread(|| {
+            assert!(is_main_thread());
+            assert!(!check_object_call(ObjectCallPolicy::Defer).unwrap());
+            assert!(!check_object_call(ObjectCallPolicy::Error).unwrap());
+        })
+        .unwrap();
+
+        // Test threads are never main thread.
+        assert!(!is_main_thread());
+        assert!(check_object_call(ObjectCallPolicy::Defer).unwrap());
+        let e = check_object_call(ObjectCallPolicy::Error).unwrap_err();
+        assert!(e.to_string().contains("threading.objectCalls"));
+    }
+
+    #[test]
+    fn test_call_in_main_thread() {
+        init_main_thread();
+
+        // Borrowed data is usable from main thread.
+        let name = String::from("Node");
+        let main = call_in_main_thread(|| (thread::current().id(), name.len())).unwrap();
+        assert_eq!(main.0, *MAIN_THREAD.get().unwrap());
+        assert_ne!(main.0, thread::current().id());
+        assert_eq!(main.1, 4);
+
+        // Panicking call does not block caller.
+        let e = call_in_main_thread::<_, ()>(|| panic!("Deferred call panicked")).unwrap_err();
+        assert!(e.to_string().contains("dropped before running"), "{e}");
+    }
+
+    #[test]
+    fn test_warn_suppression() {
+        let mut map = HashMap::new();
+        let t = Instant::now();
+
+        assert!(should_warn_at(&mut map, "once", None, t));
+        for i in 1..=3 {
+            let t = t + Duration::from_secs(i * 100);
+            assert!(!should_warn_at(&mut map, "once", None, t));
+        }
+        assert_eq!(map["once"].suppressed, 3);
+
+        let p = Some(Duration::from_secs(1));
+        assert!(should_warn_at(&mut map, "rate", p, t));
+        assert!(!should_warn_at(&mut map, "rate", p, t));
+        assert!(!should_warn_at(
+            &mut map,
+            "rate",
+            p,
+            t + Duration::from_millis(999)
+        ));
+        assert!(should_warn_at(
+            &mut map,
+            "rate",
+            p,
+            t + Duration::from_secs(1)
+        ));
+        assert!(!should_warn_at(
+            &mut map,
+            "rate",
+            p,
+            t + Duration::from_millis(1500)
+        ));
+        assert_eq!(map["rate"].suppressed, 3);
+
+        // Keys are independent.
+        assert!(should_warn_at(&mut map, "other", None, t));
+        assert_eq!(map["other"].suppressed, 0);
+    }
+}
@@ -8,13 +8,30 @@ mod preview2;
 mod rw_struct;
 #[cfg(feature = "wasi")]
 mod wasi_ctx;
+mod wasm_abi;
+mod wasm_audit;
+#[cfg(feature = "component-model")]
+mod wasm_bindgen_tool;
+mod wasm_call_group;
 mod wasm_config;
 mod wasm_engine;
+mod wasm_engine_config;
 #[cfg(feature = "object-registry-extern")]
 mod wasm_externref;
+#[cfg(feature = "memory-hash")]
+mod wasm_hash;
+// Editor classes are only registered at editor init level.
+mod wasm_importer;
 mod wasm_instance;
+mod wasm_memory;
 #[cfg(feature = "object-registry-compat")]
 mod wasm_objregistry;
+mod wasm_policy;
+#[cfg(feature = "component-model")]
+mod wasm_schema;
+mod wasm_sections;
+#[cfg(all(test, feature = "smoke-test"))]
+mod wasm_smoke;
 mod wasm_util;
 
 #[cfg(feature = "log")]
@@ -46,12 +63,26 @@ unsafe impl ExtensionLibrary for GodotWasm {
                 d.insert("godot", godot_log::GodotAppenderDeserializer);
                 init_file(PathBuf::from(v), d).unwrap();
             }
-            wasm_engine::init_engine();
+            wasm_engine_config::register_settings();
+            let config = wasm_engine_config::EngineInitConfig::from_project_settings();
+            wasm_engine::init_engine(config);
+            wasm_engine_config::watch_settings(config);
+            wasm_policy::init_policy();
+            wasm_engine::init_compile_strategy();
+            #[cfg(feature = "memory-limiter")]
+            wasm_engine::init_memory_budget();
+        } else if level == InitLevel::Scene {
+            wasm_engine::register_engine_singleton();
         }
     }
 
     fn on_level_deinit(level: InitLevel) {
-        if level == InitLevel::Servers {
+        if level == InitLevel::Scene {
+            wasm_engine::unregister_engine_singleton();
+        } else if level == InitLevel::Servers {
+            wasm_engine_config::unwatch_settings();
+            #[cfg(feature = "godot-component")]
+            godot_component::global::vfx::clear_presets();
             wasm_engine::deinit_engine();
         }
     }
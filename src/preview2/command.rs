@@ -15,20 +15,30 @@ use wasmtime::component::Linker;
 use wasmtime::{AsContextMut, Store};
 
 #[cfg(feature = "godot-component")]
-use crate::godot_component::filter::Filter;
+use crate::godot_component::compat::resolve_imports;
+#[cfg(feature = "godot-component")]
+use crate::godot_component::filter::{
+    deny_interface, filter_from_config, warn_denied_imports, Filter,
+};
+#[cfg(feature = "godot-component")]
+use crate::godot_component::global::engine::ServiceScope;
 #[cfg(feature = "godot-component")]
 use crate::godot_component::{add_to_linker as godot_add_to_linker, GodotCtx};
-use crate::godot_util::SendSyncWrapper;
+use crate::godot_util::{emit_in_main_thread, SendSyncWrapper};
 use crate::wasi_ctx::stdio::PackedByteArrayReader;
 use crate::wasi_ctx::WasiContext;
 use crate::wasm_config::{Config, PipeBindingType};
-use crate::wasm_engine::WasmModule;
+#[cfg(feature = "epoch-timeout")]
+use crate::wasm_engine::{arm_deadline, enter_priority, EpochDeadline};
+use crate::wasm_engine::{get_linker, LinkerKey, WasmModule};
 #[cfg(feature = "memory-limiter")]
 use crate::wasm_instance::MemoryLimit;
-use crate::wasm_instance::{InnerLock, InstanceData, InstanceType};
-use crate::wasm_util::HasEpochTimeout;
+use crate::wasm_instance::{
+    enter_guest, ActivityTracker, CallFrame, InnerLock, InstanceData, InstanceType,
+};
 #[cfg(feature = "epoch-timeout")]
 use crate::wasm_util::{config_store_epoch, reset_epoch};
+use crate::wasm_util::{config_store_fuel, format_error, open_nondet_tap, HasEpochTimeout};
 use crate::{bail_with_site, site_context};
 
 #[derive(Default)]
@@ -39,6 +49,16 @@ struct CommandConfig {
     use_comp_godot: bool,
     #[cfg(feature = "godot-component")]
     filter: Filter,
+    #[cfg(feature = "godot-component")]
+    warn_denied: bool,
+    #[cfg(feature = "godot-component")]
+    settings_prefixes: Vec<String>,
+    #[cfg(feature = "godot-component")]
+    strict_input_snapshot: bool,
+    #[cfg(feature = "godot-component")]
+    services_scope: ServiceScope,
+    #[cfg(feature = "godot-component")]
+    allow_missing_optional: bool,
 }
 
 impl Debug for CommandConfig {
@@ -71,8 +91,33 @@ impl FromGodot for CommandConfig {
                 .transpose()?
                 .unwrap_or_default(),
             #[cfg(feature = "godot-component")]
-            filter: via
-                .get("component.godot.filter")
+            filter: filter_from_config(&via)?,
+            #[cfg(feature = "godot-component")]
+            warn_denied: via
+                .get("component.godot.warnDeniedImports")
+                .or_else(|| via.get("filter.warn_denied_imports"))
+                .map(|v| v.try_to())
+                .transpose()?
+                .unwrap_or_default(),
+            #[cfg(feature = "godot-component")]
+            settings_prefixes: via
+                .get("component.settings_prefixes")
+                .map(|v| v.try_to::<PackedStringArray>())
+                .transpose()?
+                .map_or_else(Vec::new, |v| {
+                    v.as_slice().iter().map(|s| s.to_string()).collect()
+                }),
+            #[cfg(feature = "godot-component")]
+            strict_input_snapshot: via
+                .get("component.godot.strictInputSnapshot")
+                .map(|v| v.try_to())
+                .transpose()?
+                .unwrap_or_default(),
+            #[cfg(feature = "godot-component")]
+            services_scope: ServiceScope::from_config(&via)?,
+            #[cfg(feature = "godot-component")]
+            allow_missing_optional: via
+                .get("compat.allow_missing_optional")
                 .map(|v| v.try_to())
                 .transpose()?
                 .unwrap_or_default(),
@@ -87,6 +132,7 @@ impl FromGodot for CommandConfig {
 pub struct WasiCommand {
     base: Base<RefCounted>,
     data: OnceCell<CommandData>,
+    activity: Arc<ActivityTracker>,
 
     #[var(get = get_module)]
     #[allow(dead_code)]
@@ -107,6 +153,8 @@ pub struct CommandData {
 pub struct StoreData {
     #[cfg(feature = "epoch-timeout")]
     epoch_timeout: u64,
+    #[cfg(feature = "epoch-timeout")]
+    epoch_deadline: EpochDeadline,
 
     #[cfg(feature = "memory-limiter")]
     memory_limits: MemoryLimit,
@@ -166,6 +214,11 @@ impl HasEpochTimeout for StoreData {
         self.epoch_timeout
     }
 
+    #[cfg(feature = "epoch-timeout")]
+    fn get_epoch_deadline(&mut self) -> &mut EpochDeadline {
+        &mut self.epoch_deadline
+    }
+
     #[cfg(feature = "wasi")]
     fn get_wasi_ctx(&mut self) -> Option<&mut WasiCtx> {
         Some(&mut self.wasi_ctx)
@@ -177,6 +230,9 @@ fn instantiate(
     obj: &Gd<WasiCommand>,
     config: CommandConfig,
     module: Gd<WasmModule>,
+    #[cfg_attr(not(feature = "godot-component"), allow(unused_variables))] activity: Arc<
+        ActivityTracker,
+    >,
 ) -> Result<CommandData, Error> {
     let CommandConfig {
         config,
@@ -184,30 +240,59 @@ fn instantiate(
         use_comp_godot,
         #[cfg(feature = "godot-component")]
         filter,
+        #[cfg(feature = "godot-component")]
+        warn_denied,
+        #[cfg(feature = "godot-component")]
+        settings_prefixes,
+        #[cfg(feature = "godot-component")]
+        strict_input_snapshot,
+        #[cfg(feature = "godot-component")]
+        services_scope,
+        #[cfg(feature = "godot-component")]
+        allow_missing_optional,
     } = config;
+    if config.sandbox_pure {
+        bail_with_site!("Pure sandbox is only supported by WasmInstance");
+    }
     let comp = site_context!(module.bind().get_data()?.module.get_component())?.clone();
 
+    let nondet = open_nondet_tap(&config)?;
     let mut builder = WasiCtx::builder();
     if config.with_wasi {
-        if config.wasi_stdin == PipeBindingType::Instance {
+        let [stdin, stdout, stderr] = config.effective_stdio();
+        if stdin == PipeBindingType::Instance {
             if let Some(data) = config.wasi_stdin_data.clone() {
                 builder.stdin(Arc::new(PackedByteArrayReader::from(data)))
             } else {
                 let signal =
                     SendSyncWrapper::new(Signal::from_object_signal(obj, c"stdin_request"));
-                builder.stdin_signal(Box::new(move || signal.emit(&[])))
+                let f: Box<dyn Fn() + Send + Sync> = Box::new(move || signal.emit(&[]));
+                match config.wasi_stdin_max_bytes {
+                    Some(cap) => {
+                        let drained =
+                            SendSyncWrapper::new(Signal::from_object_signal(obj, c"stdin_drained"));
+                        builder.stdin_signal_limit(
+                            f,
+                            cap,
+                            Box::new(move || emit_in_main_thread(&drained, &[])),
+                        )
+                    }
+                    None => builder.stdin_signal(f),
+                }
             }?;
         }
-        if config.wasi_stdout == PipeBindingType::Instance {
+        if stdout == PipeBindingType::Instance {
             builder.stdout(WasiContext::make_host_stdout(
                 Signal::from_object_signal(obj, c"stdout_emit"),
                 config.wasi_stdout_buffer,
+                config.wasi_stdout_coalesce,
             ))?;
         }
-        if config.wasi_stderr == PipeBindingType::Instance {
+        if stderr == PipeBindingType::Instance {
             builder.stderr(WasiContext::make_host_stdout(
                 Signal::from_object_signal(obj, c"stderr_emit"),
                 config.wasi_stderr_buffer,
+                config.wasi_stderr_coalesce,
             ))?;
         }
 
@@ -215,14 +300,40 @@ fn instantiate(
             Some(ctx) => WasiContext::build_ctx(ctx, &mut builder, &config),
             None => WasiContext::init_ctx_no_context(&mut builder, &config),
         }?;
+        if config.wasi_tmp {
+            builder.instance_tmp(format!(".inst-{}", obj.instance_id()))?;
+        }
+        if let Some(tap) = nondet.clone() {
+            builder.nondet_tap(tap);
+        }
     }
     let wasi_ctx = builder.build()?;
     let wasi_stdin = wasi_ctx.stdin_provider().map(|v| v.dup());
 
     #[cfg(feature = "godot-component")]
     let godot_ctx = if use_comp_godot {
+        let mut filter = filter;
+        // Command has no canvas to draw into, so draw commands would pile up until limit.
+        deny_interface(&mut filter, "godot:global", "canvas");
+        if warn_denied {
+            warn_denied_imports(
+                module.instance_id(),
+                &filter,
+                &site_context!(module.bind().get_data()?.module.get_component_imports())?,
+            );
+        }
         let mut ctx = GodotCtx::new(obj.instance_id());
         ctx.filter = filter;
+        ctx.settings_prefixes = settings_prefixes;
+        ctx.nondet = nondet;
+        ctx.input.strict = strict_input_snapshot;
+        ctx.services.configure(
+            obj.instance_id(),
+            module.bind().get_data()?.name.to_string(),
+            services_scope,
+            config.wasi_context.as_ref().map(|c| c.instance_id()),
+        );
+        ctx.activity = Some(activity);
         Right(ctx)
     } else {
         Left(InnerLock::default())
@@ -236,9 +347,11 @@ fn instantiate(
             } else {
                 0
             },
+            #[cfg(feature = "epoch-timeout")]
+            epoch_deadline: EpochDeadline::default(),
 
             #[cfg(feature = "memory-limiter")]
-            memory_limits: MemoryLimit::from_config(&config),
+            memory_limits: MemoryLimit::from_config(&config, Some(obj.instance_id())),
 
             wasi_ctx,
             #[cfg(not(feature = "godot-component"))]
@@ -247,31 +360,56 @@ fn instantiate(
             godot_ctx,
         },
     );
+    let lock = AsMut::<InnerLock>::as_mut(store.data_mut());
+    lock.object_calls = config.object_calls;
+    if let Some(v) = config.max_reentrancy {
+        lock.reentrancy.max_depth = v;
+    }
+    config_store_fuel(&mut store)?;
     #[cfg(feature = "epoch-timeout")]
     config_store_epoch(&mut store, &config)?;
     #[cfg(feature = "memory-limiter")]
     store.limiter(|data| &mut data.memory_limits);
 
-    let mut linker = <Linker<StoreData>>::new(store.engine());
-    Command::add_to_linker(
-        &mut linker,
-        LinkOptions::default()
-            .cli_exit_with_code(true)
-            .clocks_timezone(true)
-            .network_error_code(true),
-        |v| &mut v.wasi_ctx,
-    )?;
-    #[cfg(feature = "godot-component")]
-    if use_comp_godot {
-        godot_add_to_linker(&mut linker, |v| {
-            v.godot_ctx
-                .as_mut()
-                .right()
-                .expect("Godot component is enabled, but no context is provided")
-        })?;
-    }
+    let key = LinkerKey {
+        wasi_command: true,
+        #[cfg(feature = "godot-component")]
+        godot_component: use_comp_godot,
+        ..LinkerKey::default()
+    };
+    let linker = get_linker(store.engine(), key, || {
+        let mut linker = <Linker<StoreData>>::new(store.engine());
+        Command::add_to_linker(
+            &mut linker,
+            LinkOptions::default()
+                .cli_exit_with_code(true)
+                .clocks_timezone(true)
+                .network_error_code(true),
+            |v| &mut v.wasi_ctx,
+        )?;
+        #[cfg(feature = "godot-component")]
+        if use_comp_godot {
+            godot_add_to_linker(&mut linker, |v| {
+                v.godot_ctx
+                    .as_mut()
+                    .right()
+                    .expect("Godot component is enabled, but no context is provided")
+            })?;
+        }
+        Ok(linker)
+    })?;
 
-    let bindings = Command::instantiate(&mut store, &comp, &linker)?;
+    #[cfg(feature = "godot-component")]
+    let stubbed = if use_comp_godot {
+        site_context!(resolve_imports(&linker, &comp, allow_missing_optional))?
+    } else {
+        None
+    };
+    #[cfg(feature = "godot-component")]
+    let linker = stubbed.as_ref().unwrap_or(&linker);
+    #[cfg(feature = "epoch-timeout")]
+    let _armed = config.with_epoch.then(arm_deadline);
+    let bindings = Command::instantiate(&mut store, &comp, &*linker)?;
 
     Ok(CommandData {
         instance: InstanceData {
@@ -310,7 +448,7 @@ impl WasiCommand {
         match self.get_data().and_then(f) {
             Ok(v) => Some(v),
             Err(e) => {
-                let s = format!("{e:?}");
+                let s = format_error(&e);
                 /*
                 error(
                     e.downcast_ref::<Site>()
@@ -336,12 +474,17 @@ impl WasiCommand {
                     None
                 }
             });
-            instantiate(&self.to_gd(), config.unwrap_or_default(), module)
+            instantiate(
+                &self.to_gd(),
+                config.unwrap_or_default(),
+                module,
+                self.activity.clone(),
+            )
         });
         match t {
             Ok(_) => true,
             Err(e) => {
-                let s = format!("{e:?}");
+                let s = format_error(&e);
                 godot_error!("{s}");
                 self.emit_error_wrapper(s);
                 false
@@ -364,6 +507,13 @@ impl WasiCommand {
     /// Emitted whenever WASI stdin is tried to be read. Only usable with WASI.
     #[signal]
     fn stdin_request();
+    /// Emitted when guest drains stdin buffer after `stdin_write` is refused. Only usable with WASI.
+    #[signal]
+    fn stdin_drained();
+    /// Emitted the first time guest calls each method blocked by filter.
+    /// Only usable with Godot component.
+    #[signal]
+    fn filter_denied(interface: GString, method: GString);
 
     /// Initialize and loads module.
     /// MUST be called for the first time and only once.
@@ -389,16 +539,34 @@ impl WasiCommand {
     #[instrument(ret)]
     fn run(&self) -> bool {
         self.unwrap_data(move |m| {
+            let _active = self.activity.enter();
             m.instance.acquire_store(move |_, mut store| {
+                #[cfg(feature = "epoch-timeout")]
+                let _guard = enter_priority(&store.data().epoch_deadline);
                 #[cfg(feature = "epoch-timeout")]
                 reset_epoch(store.as_context_mut());
 
-                Ok(m.bindings.wasi_cli_run().call_run(store)?.is_ok())
+                let frame = CallFrame::Guest {
+                    inst: self.base().instance_id(),
+                    name: StringName::from(c"run"),
+                };
+                enter_guest(store, frame, |store| {
+                    Ok(m.bindings.wasi_cli_run().call_run(store)?.is_ok())
+                })
             })
         })
         .unwrap_or_default()
     }
 
+    /// Requests running command to stop cooperatively.
+    ///
+    /// Guest polls it with `godot:core/task.cancelled`. Only usable with Godot component.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn request_cancel(&self) {
+        self.activity.cancel();
+    }
+
     /// Inserts a line to stdin. Only usable with WASI.
     #[func]
     #[instrument(skip(line), fields(line.len = line.len()))]
@@ -411,6 +579,35 @@ impl WasiCommand {
         });
     }
 
+    /// Writes data to stdin, without exceeding `wasi.stdin.maxBytes`. Only usable with WASI.
+    ///
+    /// Returns number of bytes accepted. If not all data is accepted,
+    /// `stdin_drained` is emitted once guest consumes half of the buffer.
+    #[func]
+    #[instrument(skip(data), fields(data.len = data.len()))]
+    fn stdin_write(&self, data: PackedByteArray) -> i64 {
+        self.unwrap_data(move |m| {
+            Ok(match &m.instance.wasi_stdin {
+                Some(stdin) => stdin.try_write(data.as_slice()) as i64,
+                None => 0,
+            })
+        })
+        .unwrap_or_default()
+    }
+
+    /// Returns number of bytes written to stdin, but not yet read by guest. Only usable with WASI.
+    #[func]
+    #[instrument(ret)]
+    fn stdin_buffered_bytes(&self) -> i64 {
+        self.unwrap_data(|m| {
+            Ok(m.instance
+                .wasi_stdin
+                .as_ref()
+                .map_or(0, |v| v.buffered() as i64))
+        })
+        .unwrap_or_default()
+    }
+
     /// Closes stdin. Only usable with WASI.
     #[func]
     #[instrument]
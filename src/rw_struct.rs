@@ -1,6 +1,7 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult, Write as _};
 use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Seek, Write};
+use std::sync::Arc;
 
 use anyhow::{Error as AnyError, Result as AnyResult};
 use godot::prelude::*;
@@ -9,6 +10,7 @@ use nom::combinator::{map, opt};
 use nom::error::{context, ContextError, ErrorKind, ParseError};
 use nom::sequence::pair;
 use nom::{AsChar, Compare, CompareResult, Err as NomErr, IResult, Input, Needed, Offset, Parser};
+use parking_lot::Mutex;
 
 use crate::godot_util::{from_var_any, StructPacking};
 use crate::{bail_with_site, site_context};
@@ -141,6 +143,7 @@ impl<'a> Input for CharSlice<'a> {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum DataType {
     Padding,
     SignedByte,
@@ -167,6 +170,7 @@ enum DataType {
     Transform3D(FloatSubtype),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum VectorSubtype {
     Float,
     Double,
@@ -189,6 +193,7 @@ where
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ColorSubtype {
     Float,
     Double,
@@ -209,6 +214,7 @@ where
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum FloatSubtype {
     Float,
     Double,
@@ -364,6 +370,115 @@ impl<I> ContextError<I> for SingleError<I> {
     }
 }
 
+/// Parsed struct format string.
+#[derive(Debug, Clone, Default)]
+pub struct StructFormat(Arc<[(usize, DataType)]>);
+
+#[cfg(test)]
+thread_local! {
+    static PARSE_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+impl StructFormat {
+    pub fn parse(format: &[char]) -> AnyResult<Self> {
+        #[cfg(test)]
+        PARSE_COUNT.set(PARSE_COUNT.get() + 1);
+
+        let mut i = CharSlice(format);
+        let mut ret = Vec::new();
+        let mut p_ = pair(opt(u32_), parse_datatype);
+        while !i.0.is_empty() {
+            let (i_, (n, t)) = match p_.parse_complete(i) {
+                Ok(v) => v,
+                Err(NomErr::Error(e) | NomErr::Failure(e)) => bail_with_site!(
+                    "Invalid struct format at character {}: {}",
+                    format.len() - e.input.0.len(),
+                    e.into_owned()
+                ),
+                Err(e) => return Err(e.map(SingleError::into_owned).into()),
+            };
+            i = i_;
+            ret.push((n.unwrap_or(1) as usize, t));
+        }
+
+        Ok(Self(ret.into()))
+    }
+}
+
+const FORMAT_CACHE_LEN: usize = 16;
+
+/// Small LRU cache of recently parsed format strings.
+static FORMAT_CACHE: Mutex<Vec<(Box<[char]>, StructFormat)>> = Mutex::new(Vec::new());
+
+/// Gets parsed format from cache, parsing it if not found.
+pub fn cached_format(format: &[char]) -> AnyResult<StructFormat> {
+    let mut cache = FORMAT_CACHE.lock();
+    let ret = if let Some(i) = cache.iter().position(|(k, _)| **k == *format) {
+        let v = cache.remove(i);
+        let ret = v.1.clone();
+        cache.push(v);
+        ret
+    } else {
+        let ret = StructFormat::parse(format)?;
+        if cache.len() >= FORMAT_CACHE_LEN {
+            cache.remove(0);
+        }
+        cache.push((format.into(), ret.clone()));
+        ret
+    };
+
+    Ok(ret)
+}
+
+/// Compiled struct format.
+///
+/// Use it in place of format string to skip parsing.
+#[derive(GodotClass)]
+#[class(base=RefCounted, init, tool)]
+pub struct WasmStructFormat {
+    base: Base<RefCounted>,
+    format: StructFormat,
+
+    /// Source format string.
+    #[var(get)]
+    source: GString,
+}
+
+impl WasmStructFormat {
+    pub fn new_gd(source: GString, format: StructFormat) -> Gd<Self> {
+        Gd::from_init_fn(move |base| Self {
+            base,
+            format,
+            source,
+        })
+    }
+}
+
+/// Struct format argument.
+enum FormatArg<'a> {
+    /// Format compiled by `compile_struct_format()`.
+    Compiled(&'a StructFormat),
+    /// Format string.
+    Source(&'a [char]),
+}
+
+impl FormatArg<'_> {
+    fn resolve(self) -> AnyResult<StructFormat> {
+        match self {
+            Self::Compiled(v) => Ok(v.clone()),
+            Self::Source(v) => cached_format(v),
+        }
+    }
+}
+
+/// Gets struct format from either format string or `WasmStructFormat`.
+pub fn to_struct_format(v: &Variant) -> AnyResult<StructFormat> {
+    if let Ok(v) = v.try_to::<Gd<WasmStructFormat>>() {
+        return FormatArg::Compiled(&v.bind().format).resolve();
+    }
+    FormatArg::Source(site_context!(from_var_any::<GString>(v))?.chars()).resolve()
+}
+
 fn io_to_any(err: IoError) -> AnyError {
     if !matches!(err.kind(), IoErrorKind::Other) {
         err.into()
@@ -374,7 +489,7 @@ fn io_to_any(err: IoError) -> AnyError {
     }
 }
 
-pub fn read_struct(data: impl Read + Seek, format: &[char]) -> AnyResult<VariantArray> {
+pub fn read_struct(data: impl Read + Seek, format: &StructFormat) -> AnyResult<VariantArray> {
     fn f<const N: usize, T: ToGodot>(
         (data, a): &mut (impl Read, VariantArray),
         n: usize,
@@ -389,16 +504,8 @@ pub fn read_struct(data: impl Read + Seek, format: &[char]) -> AnyResult<Variant
         Ok(())
     }
 
-    let mut format = CharSlice(format);
     let mut r = (data, Array::new());
-    let mut p_ = pair(opt(u32_), parse_datatype);
-    while !format.0.is_empty() {
-        let (i, (n, t)) = p_
-            .parse_complete(format)
-            .map_err(|e| e.map(SingleError::into_owned))?;
-        format = i;
-        let n = n.unwrap_or(1) as usize;
-
+    for &(n, t) in &*format.0 {
         match t {
             DataType::Padding => site_context!(r.0.seek_relative(n as _).map_err(io_to_any)),
             DataType::SignedByte => f::<1, _>(&mut r, n, |v| v[0] as i8 as i64),
@@ -518,7 +625,7 @@ pub fn read_struct(data: impl Read + Seek, format: &[char]) -> AnyResult<Variant
 
 pub fn write_struct(
     data: impl Write + Seek,
-    format: &[char],
+    format: &StructFormat,
     arr: VariantArray,
 ) -> AnyResult<usize> {
     fn f<const N: usize, T: FromGodot>(
@@ -543,16 +650,8 @@ pub fn write_struct(
         Ok(())
     }
 
-    let mut format = CharSlice(format);
     let mut r = (data, 0, arr.iter_shared());
-    let mut p_ = pair(opt(u32_), parse_datatype);
-    while !format.0.is_empty() {
-        let (i, (n, t)) = p_
-            .parse_complete(format)
-            .map_err(|e| e.map(SingleError::into_owned))?;
-        format = i;
-        let n = n.unwrap_or(1) as usize;
-
+    for &(n, t) in &*format.0 {
         match t {
             DataType::Padding => {
                 r.1 += n;
@@ -675,3 +774,65 @@ pub fn write_struct(
 
     Ok(r.1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_char_array(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn test_parse_format() {
+        let f = StructFormat::parse(&to_char_array("4xb2v3fCb")).unwrap();
+        assert_eq!(
+            *f.0,
+            [
+                (4, DataType::Padding),
+                (1, DataType::SignedByte),
+                (2, DataType::Vector3(VectorSubtype::Float)),
+                (1, DataType::Color(ColorSubtype::Byte)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_format_error() {
+        let e = StructFormat::parse(&to_char_array("ii2v5f")).unwrap_err();
+        let e = format!("{e}");
+        assert!(e.contains("at character 4"), "{e}");
+    }
+
+    #[test]
+    fn test_format_cache() {
+        let s = to_char_array("2ifv2dTd");
+        let start = PARSE_COUNT.get();
+        let f = cached_format(&s).unwrap();
+        assert_eq!(PARSE_COUNT.get(), start + 1);
+        for _ in 0..100 {
+            let v = cached_format(&s).unwrap();
+            assert!(Arc::ptr_eq(&f.0, &v.0));
+        }
+        assert_eq!(PARSE_COUNT.get(), start + 1);
+    }
+
+    #[test]
+    fn test_compiled_format() {
+        let s = to_char_array("4xb2v3fCb");
+        let start = PARSE_COUNT.get();
+        // Parsed once by compile_struct_format().
+        let f = StructFormat::parse(&s).unwrap();
+        assert_eq!(PARSE_COUNT.get(), start + 1);
+
+        for _ in 0..100 {
+            let v = FormatArg::Compiled(&f).resolve().unwrap();
+            assert!(Arc::ptr_eq(&f.0, &v.0));
+        }
+        assert_eq!(PARSE_COUNT.get(), start + 1);
+
+        // Format string with the same content is not shared.
+        let v = FormatArg::Source(&s).resolve().unwrap();
+        assert!(!Arc::ptr_eq(&f.0, &v.0));
+    }
+}
@@ -0,0 +1,82 @@
+# Generated by WasmBindgenTool, do not edit.
+#
+# Every call is routed through `WasmScriptLike.call_component(name, args)`.
+# It returns array of results, or null if call failed.
+extends RefCounted
+
+var world: World
+var test_fixture_api: TestFixtureApi
+
+
+func _init(target: WasmScriptLike) -> void:
+	world = World.new(target)
+	test_fixture_api = TestFixtureApi.new(target)
+
+
+class World:
+	var _target: WasmScriptLike
+
+	func _init(target: WasmScriptLike) -> void:
+		_target = target
+
+	func _call(name: String, args: Array) -> Variant:
+		var ret = _target.call_component(name, args)
+		if ret == null:
+			push_error("Failed to call %s" % name)
+		return ret
+
+	## count(items: list<string>) -> u32
+	func count(items: Array) -> int:
+		var ret = _call("count", [items])
+		if ret == null:
+			return 0
+		return ret[0]
+
+	## make(m: flags) -> own<thing>
+	func make(m) -> Variant:
+		var ret = _call("make", [m])
+		if ret == null:
+			return null
+		return ret[0]
+
+	## norm(p: record) -> f32
+	func norm(p: Dictionary) -> float:
+		var ret = _call("norm", [p])
+		if ret == null:
+			return 0.0
+		return ret[0]
+
+	## parse(s: string) -> result<tuple<u8, option<s64>>, enum>
+	func parse(s: String) -> Variant:
+		var ret = _call("parse", [s])
+		if ret == null:
+			return null
+		var r: Dictionary = ret[0]
+		if r.has("err"):
+			push_error("parse returned error: %s" % [r["err"]])
+			return null
+		return r.get("ok")
+
+	## thing-id(t: borrow<thing>) -> u64
+	func thing_id(t) -> int:
+		var ret = _call("thing-id", [t])
+		if ret == null:
+			return 0
+		return ret[0]
+
+
+class TestFixtureApi:
+	var _target: WasmScriptLike
+
+	func _init(target: WasmScriptLike) -> void:
+		_target = target
+
+	func _call(name: String, args: Array) -> Variant:
+		var ret = _target.call_component(name, args)
+		if ret == null:
+			push_error("Failed to call %s" % name)
+		return ret
+
+	## ping()
+	func ping() -> void:
+		_call("test:fixture/api@0.1.0#ping", [])
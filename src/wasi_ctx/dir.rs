@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{Error as AnyError, Result as AnyResult};
+use wasi_isolated_fs::fs_isolated::{DirEntryAccessor, Node};
+
+use crate::bail_with_site;
+
+/// Maximum number of open directory cursors per context.
+pub const MAX_CURSORS: usize = 1024;
+
+/// Directory cursors opened by host.
+///
+/// Cursors walk directory in key order and resume from the next key,
+/// so modifying directory between calls never skips or duplicates existing entries.
+#[derive(Default)]
+pub struct DirCursors {
+    cursors: HashMap<u32, DirEntryAccessor>,
+    next_id: u32,
+}
+
+impl DirCursors {
+    pub fn open(&mut self, it: DirEntryAccessor) -> AnyResult<u32> {
+        if self.cursors.len() >= MAX_CURSORS {
+            bail_with_site!("Too many open directory cursors (max {MAX_CURSORS})");
+        }
+        loop {
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
+            if !self.cursors.contains_key(&id) {
+                self.cursors.insert(id, it);
+                return Ok(id);
+            }
+        }
+    }
+
+    /// Gets up to `count` entries. Returns empty if cursor is exhausted.
+    pub fn next(&mut self, id: u32, count: usize) -> AnyResult<Vec<(Arc<str>, Arc<Node>)>> {
+        let Some(it) = self.cursors.get_mut(&id) else {
+            bail_with_site!("Directory cursor {id} does not exist");
+        };
+        it.by_ref()
+            .take(count)
+            .collect::<Result<_, _>>()
+            .map_err(AnyError::from)
+    }
+
+    /// Returns `false` if cursor does not exist.
+    pub fn close(&mut self, id: u32) -> bool {
+        self.cursors.remove(&id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasi_isolated_fs::fs_isolated::{AccessMode, CapWrapper, File, IsolatedFSController};
+
+    fn names(v: Vec<(Arc<str>, Arc<Node>)>) -> Vec<String> {
+        v.into_iter().map(|(k, _)| k.to_string()).collect()
+    }
+
+    #[test]
+    fn test_dir_cursor() {
+        let controller = IsolatedFSController::new(1 << 16, 64).unwrap();
+        let root = controller.root();
+        let add = |name: &str| {
+            root.try_dir()
+                .unwrap()
+                .add(name.to_string(), || -> AnyResult<_> {
+                    Ok(Arc::new(Node::from((
+                        File::new(&controller)?,
+                        Arc::downgrade(&root),
+                    ))))
+                })
+                .unwrap();
+        };
+        for name in ["a", "b", "c", "d", "e"] {
+            add(name);
+        }
+        let refs = Arc::strong_count(&root);
+
+        let mut cursors = DirCursors::default();
+        let dir = CapWrapper::new(root.clone(), AccessMode::RW);
+        let id = cursors.open(dir.read_directory().unwrap()).unwrap();
+        drop(dir);
+        assert_eq!(names(cursors.next(id, 2).unwrap()), ["a", "b"]);
+
+        // Removed entry is skipped, new entry before cursor is not visited twice.
+        assert!(root.try_dir().unwrap().remove("c"));
+        add("bb");
+        add("f");
+        assert_eq!(names(cursors.next(id, 10).unwrap()), ["d", "e", "f"]);
+        assert!(cursors.next(id, 10).unwrap().is_empty());
+
+        assert!(cursors.close(id));
+        assert!(!cursors.close(id));
+        assert!(cursors.next(id, 1).is_err());
+        assert!(cursors.cursors.is_empty());
+        assert_eq!(Arc::strong_count(&root), refs);
+    }
+
+    #[test]
+    fn test_dir_cursor_drop() {
+        let controller = IsolatedFSController::new(1 << 16, 64).unwrap();
+        let root = controller.root();
+        let refs = Arc::strong_count(&root);
+
+        let mut cursors = DirCursors::default();
+        for _ in 0..4 {
+            let dir = CapWrapper::new(root.clone(), AccessMode::RW);
+            cursors.open(dir.read_directory().unwrap()).unwrap();
+        }
+        assert_eq!(cursors.cursors.len(), 4);
+        assert!(Arc::strong_count(&root) > refs);
+
+        // Dropping context frees all cursors.
+        drop(cursors);
+        assert_eq!(Arc::strong_count(&root), refs);
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use anyhow::Result as AnyResult;
+
+use crate::bail_with_site;
+
+/// Kind of array element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementKind {
+    Int,
+    Float,
+}
+
+/// Element format of typed write, like `i32le` or `f64be`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementFormat {
+    pub kind: ElementKind,
+    pub signed: bool,
+    /// Size in bytes.
+    pub size: usize,
+    pub big_endian: bool,
+}
+
+impl ElementFormat {
+    /// Parses element format.
+    ///
+    /// Format is type (`i8`, `u8`, `i16`, `u16`, `i32`, `u32`, `i64`, `u64`, `f32`, `f64`)
+    /// followed by endianness (`le` or `be`). Single byte types has no endianness.
+    pub fn parse(s: &str) -> AnyResult<Self> {
+        let (ty, big_endian) = if let Some(v) = s.strip_suffix("le") {
+            (v, false)
+        } else if let Some(v) = s.strip_suffix("be") {
+            (v, true)
+        } else {
+            (s, false)
+        };
+
+        let (kind, signed, size) = match ty {
+            "i8" => (ElementKind::Int, true, 1),
+            "u8" => (ElementKind::Int, false, 1),
+            "i16" => (ElementKind::Int, true, 2),
+            "u16" => (ElementKind::Int, false, 2),
+            "i32" => (ElementKind::Int, true, 4),
+            "u32" => (ElementKind::Int, false, 4),
+            "i64" => (ElementKind::Int, true, 8),
+            "u64" => (ElementKind::Int, false, 8),
+            "f32" => (ElementKind::Float, true, 4),
+            "f64" => (ElementKind::Float, true, 8),
+            _ => bail_with_site!("Unknown element format {s:?}"),
+        };
+        if (size == 1) != (ty.len() == s.len()) {
+            bail_with_site!("Element format {s:?} has invalid endianness");
+        }
+
+        Ok(Self {
+            kind,
+            signed,
+            size,
+            big_endian,
+        })
+    }
+
+    /// Checks format against array element.
+    ///
+    /// Signedness is not checked, as the bytes are identical.
+    pub fn check(&self, array: &str, kind: ElementKind, size: usize) -> AnyResult<()> {
+        if self.kind != kind || self.size != size {
+            bail_with_site!(
+                "Element format {self} ({:?}, {} bytes) does not match {array} ({kind:?}, {size} bytes)",
+                self.kind,
+                self.size,
+            );
+        }
+        Ok(())
+    }
+
+    /// Converts little-endian element into requested endianness.
+    ///
+    /// Composite elements (eg. `Vector2`) are converted per component.
+    pub fn convert(&self, buf: &mut [u8]) {
+        if self.big_endian {
+            for c in buf.chunks_exact_mut(self.size) {
+                c.reverse();
+            }
+        }
+    }
+}
+
+impl Display for ElementFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let c = match (self.kind, self.signed) {
+            (ElementKind::Float, _) => 'f',
+            (ElementKind::Int, true) => 'i',
+            (ElementKind::Int, false) => 'u',
+        };
+        write!(f, "{c}{}", self.size * 8)?;
+        match (self.size, self.big_endian) {
+            (1, _) => Ok(()),
+            (_, false) => write!(f, "le"),
+            (_, true) => write!(f, "be"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_element_format_parse() {
+        for s in [
+            "i8", "u8", "i16le", "u16be", "i32le", "u32be", "i64le", "u64be", "f32le", "f64be",
+        ] {
+            assert_eq!(ElementFormat::parse(s).unwrap().to_string(), s);
+        }
+
+        let v = ElementFormat::parse("f64be").unwrap();
+        assert_eq!(v.kind, ElementKind::Float);
+        assert_eq!(v.size, 8);
+        assert!(v.big_endian);
+
+        for s in ["", "i32", "i8le", "f16le", "i32me", "le"] {
+            assert!(ElementFormat::parse(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_element_format_check() {
+        let v = ElementFormat::parse("i32le").unwrap();
+        v.check("PackedInt32Array", ElementKind::Int, 4).unwrap();
+        ElementFormat::parse("u32be")
+            .unwrap()
+            .check("PackedInt32Array", ElementKind::Int, 4)
+            .unwrap();
+
+        let e = v
+            .check("PackedInt64Array", ElementKind::Int, 8)
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("i32le"), "{e}");
+        assert!(e.contains("PackedInt64Array"), "{e}");
+        assert!(v
+            .check("PackedFloat32Array", ElementKind::Float, 4)
+            .is_err());
+    }
+
+    #[test]
+    fn test_element_format_convert() {
+        let mut buf = 0x0102_0304u32.to_le_bytes();
+        ElementFormat::parse("u32le").unwrap().convert(&mut buf);
+        assert_eq!(buf, 0x0102_0304u32.to_le_bytes());
+        ElementFormat::parse("u32be").unwrap().convert(&mut buf);
+        assert_eq!(buf, 0x0102_0304u32.to_be_bytes());
+
+        // Vector2 of f32.
+        let mut buf = [1, 2, 3, 4, 5, 6, 7, 8];
+        ElementFormat::parse("f32be").unwrap().convert(&mut buf);
+        assert_eq!(buf, [4, 3, 2, 1, 8, 7, 6, 5]);
+    }
+}
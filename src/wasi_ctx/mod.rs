@@ -1,35 +1,58 @@
+mod dir;
+mod element;
 pub mod stdio;
 
 use std::collections::HashMap;
 use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom, Write};
 use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Result as AnyResult;
 use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
 
+use godot::classes::{ProjectSettings, Time};
 use godot::prelude::*;
 use once_cell::sync::OnceCell;
-use parking_lot::{Mutex, MutexGuard};
-use wasi_isolated_fs::context::WasiContextBuilder;
+use parking_lot::{Mutex, RwLock};
+use regex::Regex;
+use wasi_isolated_fs::clock::ClockController;
+use wasi_isolated_fs::context::{validate_env, HostPreopenOptions, WasiContextBuilder};
+use wasi_isolated_fs::fs_host::{normalize_host_path, write_file_atomic};
+use wasi_isolated_fs::fs_import::ImportPlan;
 use wasi_isolated_fs::fs_isolated::{
-    AccessMode, CapWrapper, CreateParams, Dir, File, IsolatedFSController, Link, Node,
+    AccessMode, CapWrapper, CreateParams, Dir, File, IsolatedFSController, Link, Node, QuotaPolicy,
+    UnlinkOpenPolicy,
 };
 use wasi_isolated_fs::stdio::{
-    HostStdout, StderrBypass, StdoutBypass, StdoutCbBlockBuffered, StdoutCbLineBuffered,
+    HostStdout, Pipe, PipeHost, StderrBypass, StdoutBypass, StdoutCapture, StdoutCbBlockBuffered,
+    StdoutCbLineBuffered, StdoutCbLineCoalesced,
 };
 
 use crate::godot_util::{
-    from_var_any, option_to_variant, variant_to_option, PhantomProperty, SendSyncWrapper,
-    StructPacking,
+    emit_in_main_thread, from_var_any, option_to_variant, variant_to_option, PhantomProperty,
+    SendSyncWrapper, StructPacking,
+};
+use crate::rw_struct::{read_struct, to_struct_format, write_struct};
+use crate::wasi_ctx::dir::DirCursors;
+use crate::wasi_ctx::element::{ElementFormat, ElementKind};
+use crate::wasi_ctx::stdio::{
+    StdioLogFile, StdoutCbUnbuffered, StdoutFilter, StdoutFilterMode, StdoutTeeLog,
 };
-use crate::rw_struct::{read_struct, write_struct};
-use crate::wasi_ctx::stdio::StdoutCbUnbuffered;
 use crate::wasm_config::{Config, PipeBindingType, PipeBufferType};
-use crate::wasm_util::{FILE_DIR, FILE_FILE, FILE_LINK, FILE_NOTEXIST};
+use crate::wasm_util::{file_type_str, FILE_DIR, FILE_FILE, FILE_LINK, FILE_NOTEXIST};
 use crate::{bail_with_site, site_context, variant_dispatch};
 
+/// Batch size (in bytes) that forces coalesced stdout to emit.
+const COALESCE_MAX_SIZE: usize = 1 << 16;
+/// Minimum interval between progress signals of asynchronous import.
+const IMPORT_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+static NEXT_IMPORT_TICKET: AtomicI64 = AtomicI64::new(1);
+
 static ILLEGAL_CHARS: &[char] = &['\\', '/', ':', '*', '?', '\"', '\'', '<', '>', '|'];
 
 fn to_unix_time(time: SystemTime) -> i128 {
@@ -46,6 +69,56 @@ fn to_unix_time(time: SystemTime) -> i128 {
     }
 }
 
+fn node_filetype(n: &Node) -> u32 {
+    if n.is_link() {
+        FILE_LINK
+    } else if n.is_dir() {
+        FILE_DIR
+    } else {
+        FILE_FILE
+    }
+}
+
+fn write_elems<T, const N: usize>(
+    f: &mut File,
+    mut off: usize,
+    it: impl IntoIterator<Item = T>,
+    c: impl Fn(T, &mut [u8; N]),
+) -> AnyResult<()> {
+    let mut buf = [[0u8; N]; 4];
+    let mut i = 0;
+    for v in it {
+        c(v, &mut buf[i]);
+
+        i += 1;
+        if i == buf.len() {
+            i = 0;
+            f.write(buf.as_flattened(), off)?;
+            off += N * buf.len();
+        }
+    }
+
+    if i > 0 {
+        f.write(buf[..i].as_flattened(), off)?;
+    }
+    Ok(())
+}
+
+/// Gets name, kind, and size of element of numeric packed array.
+fn packed_element(ty: VariantType) -> Option<(&'static str, ElementKind, usize)> {
+    Some(match ty {
+        VariantType::PACKED_BYTE_ARRAY => ("PackedByteArray", ElementKind::Int, 1),
+        VariantType::PACKED_INT32_ARRAY => ("PackedInt32Array", ElementKind::Int, 4),
+        VariantType::PACKED_INT64_ARRAY => ("PackedInt64Array", ElementKind::Int, 8),
+        VariantType::PACKED_FLOAT32_ARRAY => ("PackedFloat32Array", ElementKind::Float, 4),
+        VariantType::PACKED_FLOAT64_ARRAY => ("PackedFloat64Array", ElementKind::Float, 8),
+        VariantType::PACKED_VECTOR2_ARRAY => ("PackedVector2Array", ElementKind::Float, 4),
+        VariantType::PACKED_VECTOR3_ARRAY => ("PackedVector3Array", ElementKind::Float, 4),
+        VariantType::PACKED_COLOR_ARRAY => ("PackedColorArray", ElementKind::Float, 4),
+        _ => return None,
+    })
+}
+
 fn from_unix_time(time: i64) -> Option<SystemTime> {
     if time >= 0 {
         SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(time as _))
@@ -64,9 +137,12 @@ fn from_unix_time(time: i64) -> Option<SystemTime> {
 ///
 /// 📌 Use `initialize()` to properly initialize object.
 /// **Uninitialized object should not be used.**
+///
+/// Once initialized, filesystem and environment methods can be called from any thread.
+/// Signals emitted outside of main thread are deferred to main thread.
 pub struct WasiContext {
     base: Base<RefCounted>,
-    data: OnceCell<Mutex<WasiContextInner>>,
+    data: OnceCell<WasiContextData>,
 
     /// Flag to pass through stdio into terminal.
     #[var(get = is_bypass_stdio, set = set_bypass_stdio)]
@@ -79,26 +155,159 @@ pub struct WasiContext {
     fs_readonly: PhantomProperty<bool>,
 }
 
+/// Shared state of context.
+///
+/// In-memory filesystem is internally synchronized, so filesystem methods
+/// do not lock the rest of the context.
+struct WasiContextData {
+    memfs_controller: IsolatedFSController,
+    dir_cursors: Mutex<DirCursors>,
+    warned_inferred_write: AtomicBool,
+    /// Cancellation flag of running asynchronous imports, keyed by ticket.
+    imports: Arc<Mutex<HashMap<i64, Arc<AtomicBool>>>>,
+    inner: RwLock<WasiContextInner>,
+}
+
 struct WasiContextInner {
     bypass_stdio: bool,
+    console_stdio: bool,
     fs_readonly: bool,
 
-    memfs_controller: IsolatedFSController,
-    physical_mount: HashMap<Utf8PathBuf, Utf8PathBuf>,
+    /// Monotonic clock shared by instances, if clock scope is context.
+    clock: Option<ClockController>,
+    physical_mount: HashMap<Utf8PathBuf, PhysicalMount>,
+    physical_file_mount: HashMap<Utf8PathBuf, PhysicalFileMount>,
     envs: HashMap<String, String>,
+    log_file: Option<Arc<StdioLogFile>>,
+    stdout_filter: Arc<StdoutFilter>,
+    pipes: Vec<ContextPipe>,
+    /// Stdout and stderr capture buffers, if enabled.
+    capture: Option<[Arc<StdoutCapture>; 2]>,
+}
+
+/// Host directory mounted with [`WasiContext::mount_physical_dir`].
+struct PhysicalMount {
+    /// Normalized host path.
+    host: PathBuf,
+    case_insensitive: bool,
+}
+
+/// Host file mounted with [`WasiContext::mount_physical_file`].
+struct PhysicalFileMount {
+    /// Normalized host path.
+    host: PathBuf,
+    readonly: bool,
+}
+
+impl WasiContextInner {
+    /// Finds innermost host mount containing guest path.
+    ///
+    /// Returns host directory and path relative to it.
+    fn find_mount(&self, path: &Utf8Path) -> Option<(PathBuf, Utf8PathBuf)> {
+        self.physical_mount
+            .iter()
+            .filter_map(|(guest, v)| Some((guest, v, path.strip_prefix(guest).ok()?)))
+            .max_by_key(|(guest, ..)| guest.components().count())
+            .map(|(_, v, rel)| (v.host.clone(), rel.to_owned()))
+    }
+}
+
+/// Filesystem access granted to instance.
+pub struct FsAccess {
+    pub readonly: bool,
+    pub mounts: Vec<FsMount>,
+    pub files: Vec<FsFileMount>,
+}
+
+/// Directory mounted into guest.
+pub struct FsMount {
+    pub guest: Utf8PathBuf,
+    /// Host path, or `None` if it's root of in-memory filesystem.
+    pub host: Option<PathBuf>,
+    /// Resolves host paths case-insensitively.
+    pub case_insensitive: bool,
+}
+
+/// Single host file mounted into guest.
+pub struct FsFileMount {
+    pub guest: Utf8PathBuf,
+    pub host: PathBuf,
+    pub readonly: bool,
+}
+
+impl WasiContextData {
+    /// Opens file in in-memory filesystem.
+    ///
+    /// If `create` is `true`, file is created if it does not exist.
+    fn open_file(
+        &self,
+        path: &Utf8Path,
+        follow_symlink: bool,
+        create: bool,
+    ) -> AnyResult<CapWrapper> {
+        site_context!(
+            CapWrapper::new(self.memfs_controller.root(), AccessMode::RW).open(
+                &self.memfs_controller,
+                path,
+                follow_symlink,
+                create.then(CreateParams::new),
+                AccessMode::RW,
+            )
+        )
+    }
+
+    /// Reads up to `length` bytes of file at offset.
+    fn read_file(
+        &self,
+        path: &Utf8Path,
+        follow_symlink: bool,
+        length: usize,
+        mut off: usize,
+    ) -> AnyResult<Vec<u8>> {
+        let f = self.open_file(path, follow_symlink, false)?;
+        let mut n = site_context!(f.node().try_file())?;
+
+        let mut l = length;
+        let mut ret = Vec::new();
+        while l > 0 {
+            let (v, n) = n.read(l, off);
+            if n == 0 {
+                break;
+            }
+            let i = ret.len();
+            ret.extend_from_slice(v);
+            ret.resize(i + n, 0);
+            l -= n;
+            off += n;
+        }
+
+        Ok(ret)
+    }
+
+    /// Gets context-wide environment variable.
+    fn get_env(&self, key: &str) -> Option<String> {
+        self.inner.read().envs.get(key).cloned()
+    }
+}
+
+/// Extra stream passed to every instance built with context.
+struct ContextPipe {
+    name: String,
+    /// Host side of each instance's pipe.
+    hosts: Vec<PipeHost>,
 }
 
 impl WasiContext {
-    fn get_data(&self) -> AnyResult<MutexGuard<'_, WasiContextInner>> {
+    fn get_data(&self) -> AnyResult<&WasiContextData> {
         if let Some(data) = self.data.get() {
-            Ok(data.lock())
+            Ok(data)
         } else {
-            bail_with_site!("Uninitialized instance")
+            bail_with_site!("Uninitialized instance (initialize() must complete before use)")
         }
     }
 
-    fn wrap_data<T>(&self, f: impl FnOnce(&mut WasiContextInner) -> AnyResult<T>) -> Option<T> {
-        match self.get_data().and_then(|mut v| f(&mut v)) {
+    fn unwrap_result<T>(r: AnyResult<T>) -> Option<T> {
+        match r {
             Ok(v) => Some(v),
             Err(e) => {
                 godot_error!("{}", e);
@@ -107,43 +316,179 @@ impl WasiContext {
         }
     }
 
+    fn wrap_data<T>(&self, f: impl FnOnce(&mut WasiContextInner) -> AnyResult<T>) -> Option<T> {
+        Self::unwrap_result(self.get_data().and_then(|v| f(&mut v.inner.write())))
+    }
+
+    fn wrap_data_ref<T>(&self, f: impl FnOnce(&WasiContextInner) -> AnyResult<T>) -> Option<T> {
+        Self::unwrap_result(self.get_data().and_then(|v| f(&v.inner.read())))
+    }
+
+    /// Like `wrap_data`, but only accesses filesystem and does not take context lock.
+    fn wrap_fs<T>(&self, f: impl FnOnce(&WasiContextData) -> AnyResult<T>) -> Option<T> {
+        Self::unwrap_result(self.get_data().and_then(f))
+    }
+
+    fn take_captured(&self, i: usize) -> PackedByteArray {
+        self.wrap_data_ref(|this| {
+            Ok(this
+                .capture
+                .as_ref()
+                .map(|c| PackedByteArray::from(c[i].take().0))
+                .unwrap_or_default())
+        })
+        .unwrap_or_default()
+    }
+
     pub fn emit_binary(signal: Signal) -> impl Fn(&[u8]) + Send + Sync + Clone + 'static {
         let signal = SendSyncWrapper::new(signal);
-        move |buf| signal.emit(&[PackedByteArray::from(buf).to_variant()])
+        move |buf| emit_in_main_thread(&signal, &[PackedByteArray::from(buf).to_variant()])
     }
 
     pub fn emit_string(signal: Signal) -> impl Fn(&str) + Send + Sync + Clone + 'static {
         let signal = SendSyncWrapper::new(signal);
-        move |buf| signal.emit(&[buf.to_variant()])
+        move |buf| emit_in_main_thread(&signal, &[buf.to_variant()])
+    }
+
+    /// Emits batch of lines as `PackedStringArray`.
+    pub fn emit_lines(signal: Signal) -> impl Fn(Vec<String>) + Send + Sync + Clone + 'static {
+        let signal = SendSyncWrapper::new(signal);
+        move |v| {
+            emit_in_main_thread(
+                &signal,
+                &[v.iter()
+                    .map(|s| GString::from(s.as_str()))
+                    .collect::<PackedStringArray>()
+                    .to_variant()],
+            )
+        }
     }
 
     pub fn make_host_stdout(
         signal: Signal,
         ty: PipeBufferType,
+        coalesce: Option<Duration>,
     ) -> Arc<dyn Send + Sync + HostStdout> {
-        match ty {
-            PipeBufferType::Unbuffered => {
+        match (ty, coalesce) {
+            (PipeBufferType::Unbuffered, _) => {
                 Arc::new(StdoutCbUnbuffered::new(Box::new(Self::emit_binary(signal))))
             }
-            PipeBufferType::BlockBuffer => Arc::new(StdoutCbBlockBuffered::new(Box::new(
+            (PipeBufferType::BlockBuffer, _) => Arc::new(StdoutCbBlockBuffered::new(Box::new(
                 Self::emit_binary(signal),
             ))),
-            PipeBufferType::LineBuffer => Arc::new(StdoutCbLineBuffered::new(Box::new(
+            (PipeBufferType::LineBuffer, None) => Arc::new(StdoutCbLineBuffered::new(Box::new(
                 Self::emit_string(signal),
             ))),
+            (PipeBufferType::LineBuffer, Some(interval)) => Arc::new(StdoutCbLineCoalesced::new(
+                Box::new(Self::emit_lines(signal)),
+                interval,
+                COALESCE_MAX_SIZE,
+            )),
+        }
+    }
+
+    /// Line-buffered stdout that skips lines rejected by filter.
+    fn make_filtered_stdout(
+        signal: Signal,
+        filter: Arc<StdoutFilter>,
+        coalesce: Option<Duration>,
+    ) -> Arc<dyn Send + Sync + HostStdout> {
+        if let Some(interval) = coalesce {
+            let emit = Self::emit_lines(signal);
+            return Arc::new(StdoutCbLineCoalesced::new(
+                Box::new(move |mut v: Vec<String>| {
+                    v.retain(|s| filter.check(s));
+                    if !v.is_empty() {
+                        emit(v)
+                    }
+                }),
+                interval,
+                COALESCE_MAX_SIZE,
+            ));
+        }
+
+        let emit = Self::emit_string(signal);
+        Arc::new(StdoutCbLineBuffered::new(Box::new(move |s: &str| {
+            if filter.check(s) {
+                emit(s)
+            }
+        })))
+    }
+
+    /// Routes complete lines to Godot console.
+    pub fn make_console_stdout(is_stderr: bool) -> Arc<dyn Send + Sync + HostStdout> {
+        Arc::new(StdoutCbLineBuffered::new(Box::new(move |s: &str| {
+            let s = s.strip_suffix('\n').unwrap_or(s);
+            if is_stderr {
+                godot_error!("{s}");
+            } else {
+                godot_print!("{s}");
+            }
+        })))
+    }
+
+    fn tee_log(
+        data: &WasiContextInner,
+        stdout: Arc<dyn Send + Sync + HostStdout>,
+    ) -> Arc<dyn Send + Sync + HostStdout> {
+        match &data.log_file {
+            Some(log) => Arc::new(StdoutTeeLog::new(stdout, log.clone())),
+            None => stdout,
         }
     }
 
+    fn open_log_file(name: &str, config: &Dictionary) -> AnyResult<Option<Arc<StdioLogFile>>> {
+        let Some(path) = config.get("stdio.log_file") else {
+            return Ok(None);
+        };
+        let path = site_context!(from_var_any::<GString>(path))?.to_string();
+        let max_bytes = site_context!(config
+            .get("stdio.log_max_bytes")
+            .map(from_var_any::<i64>)
+            .transpose())?
+        .map_or(1 << 20, |v| v.max(0) as u64);
+        let keep = site_context!(config
+            .get("stdio.log_keep")
+            .map(from_var_any::<i64>)
+            .transpose())?
+        .map_or(3, |v| v.max(0) as usize);
+
+        let date = Time::singleton().get_date_string_from_system().to_string();
+        let path = path.replace("{context}", name).replace("{date}", &date);
+        let path = ProjectSettings::singleton()
+            .globalize_path(&GString::from(path))
+            .to_string();
+
+        Ok(Some(Arc::new(site_context!(StdioLogFile::new(
+            path.into(),
+            max_bytes,
+            keep
+        ))?)))
+    }
+
     pub fn init_ctx_no_context(ctx: &mut WasiContextBuilder, config: &Config) -> AnyResult<()> {
-        if config.wasi_stdout == PipeBindingType::Bypass {
-            ctx.stdout(Arc::new(StdoutBypass::default()))?;
+        let [_, stdout, stderr] = config.effective_stdio();
+        match stdout {
+            PipeBindingType::Bypass => {
+                ctx.stdout(Arc::new(StdoutBypass::default()))?;
+            }
+            PipeBindingType::GodotConsole => {
+                ctx.stdout(Self::make_console_stdout(false))?;
+            }
+            _ => (),
         }
-        if config.wasi_stderr == PipeBindingType::Bypass {
-            ctx.stderr(Arc::new(StderrBypass::default()))?;
+        match stderr {
+            PipeBindingType::Bypass => {
+                ctx.stderr(Arc::new(StderrBypass::default()))?;
+            }
+            PipeBindingType::GodotConsole => {
+                ctx.stderr(Self::make_console_stdout(true))?;
+            }
+            _ => (),
         }
 
-        ctx.envs(config.wasi_envs.iter().map(|(k, v)| (k.clone(), v.clone())))
-            .args(config.wasi_args.iter().cloned());
+        site_context!(ctx.envs(config.wasi_envs.iter().map(|(k, v)| (k.clone(), v.clone()))))?;
+        site_context!(ctx.args(config.wasi_args.iter().cloned()))?;
         Ok(())
     }
 
@@ -153,43 +498,149 @@ impl WasiContext {
         config: &Config,
     ) -> AnyResult<()> {
         let o = this.bind();
-        let o = o.get_data()?;
+        let data = o.get_data()?;
+        let mut o = data.inner.write();
+
+        for p in &mut o.pipes {
+            let name = p.name.clone();
+            let emit = SendSyncWrapper::new(Signal::from_object_signal(this, c"pipe_emit"));
+            let closed = SendSyncWrapper::new(Signal::from_object_signal(this, c"pipe_closed"));
+            let (pipe, host) = Pipe::new(
+                Arc::new(StdoutCbUnbuffered::new(Box::new({
+                    let name = name.clone();
+                    move |buf: &[u8]| {
+                        emit_in_main_thread(
+                            &emit,
+                            &[name.to_variant(), PackedByteArray::from(buf).to_variant()],
+                        )
+                    }
+                }))),
+                Box::new(|| ()),
+                Some(Box::new(move || {
+                    emit_in_main_thread(&closed, &[name.to_variant()])
+                })),
+            );
+            ctx.push_extra_stream(pipe);
+            p.hosts.push(host);
+        }
 
-        if config.wasi_stdout == PipeBindingType::Context {
-            ctx.stdout(if o.bypass_stdio {
-                Arc::new(StdoutBypass::default())
-            } else {
-                Self::make_host_stdout(
-                    Signal::from_object_signal(this, c"stdout_emit"),
-                    config.wasi_stdout_buffer,
-                )
-            })?;
+        let [_, stdout, stderr] = config.effective_stdio();
+        if stdout == PipeBindingType::Context {
+            ctx.stdout(Self::tee_log(
+                &o,
+                if let Some([c, _]) = &o.capture {
+                    c.clone()
+                } else if o.console_stdio {
+                    Self::make_console_stdout(false)
+                } else if o.bypass_stdio {
+                    Arc::new(StdoutBypass::default())
+                } else if config.wasi_stdout_buffer == PipeBufferType::LineBuffer {
+                    Self::make_filtered_stdout(
+                        Signal::from_object_signal(this, c"stdout_emit"),
+                        o.stdout_filter.clone(),
+                        config.wasi_stdout_coalesce,
+                    )
+                } else {
+                    Self::make_host_stdout(
+                        Signal::from_object_signal(this, c"stdout_emit"),
+                        config.wasi_stdout_buffer,
+                        config.wasi_stdout_coalesce,
+                    )
+                },
+            ))?;
         }
-        if config.wasi_stderr == PipeBindingType::Context {
-            ctx.stderr(if o.bypass_stdio {
-                Arc::new(StderrBypass::default())
-            } else {
-                Self::make_host_stdout(
-                    Signal::from_object_signal(this, c"stderr_emit"),
-                    config.wasi_stderr_buffer,
-                )
-            })?;
+        if stderr == PipeBindingType::Context {
+            ctx.stderr(Self::tee_log(
+                &o,
+                if let Some([_, c]) = &o.capture {
+                    c.clone()
+                } else if o.console_stdio {
+                    Self::make_console_stdout(true)
+                } else if o.bypass_stdio {
+                    Arc::new(StderrBypass::default())
+                } else {
+                    Self::make_host_stdout(
+                        Signal::from_object_signal(this, c"stderr_emit"),
+                        config.wasi_stderr_buffer,
+                        config.wasi_stderr_coalesce,
+                    )
+                },
+            ))?;
         }
 
-        ctx.envs(o.envs.iter().map(|(k, v)| (k.clone(), v.clone())))
-            .fs_readonly(o.fs_readonly || config.wasi_fs_readonly);
+        let fs = Self::fs_access(&o, config);
+        site_context!(ctx.envs(o.envs.iter().map(|(k, v)| (k.clone(), v.clone()))))?;
+        ctx.fs_readonly(fs.readonly);
 
         Self::init_ctx_no_context(&mut *ctx, config)?;
 
-        site_context!(ctx.isolated_fs_controller(&o.memfs_controller))?;
-        site_context!(ctx.preopen_dir_isolated("/".parse().unwrap(), "/".parse().unwrap()))?;
-
-        for (guest, host) in o.physical_mount.iter() {
-            site_context!(ctx.preopen_dir_host(host.clone(), guest.clone()))?;
+        if let Some(clock) = &o.clock {
+            ctx.clock_controller(clock);
+        }
+        ctx.preopen_policy(config.wasi_preopen_policy.into());
+        site_context!(ctx.isolated_fs_controller(&data.memfs_controller))?;
+        for FsMount {
+            guest,
+            host,
+            case_insensitive,
+        } in fs.mounts
+        {
+            match host {
+                None => site_context!(ctx.preopen_dir_isolated("/".parse().unwrap(), guest))?,
+                Some(host) => site_context!(ctx.preopen_dir_host_with(
+                    host,
+                    guest,
+                    HostPreopenOptions { case_insensitive },
+                ))?,
+            };
+        }
+        for FsFileMount {
+            guest,
+            host,
+            readonly,
+        } in fs.files
+        {
+            site_context!(ctx.preopen_file_host(host, guest, readonly))?;
         }
 
         Ok(())
     }
+
+    /// Filesystem access granted to instance built with config.
+    fn fs_access(o: &WasiContextInner, config: &Config) -> FsAccess {
+        FsAccess {
+            readonly: o.fs_readonly || config.wasi_fs_readonly,
+            mounts: [FsMount {
+                guest: "/".into(),
+                host: None,
+                case_insensitive: false,
+            }]
+            .into_iter()
+            .chain(o.physical_mount.iter().map(|(guest, v)| FsMount {
+                guest: guest.clone(),
+                host: Some(v.host.clone()),
+                case_insensitive: v.case_insensitive,
+            }))
+            .collect(),
+            files: o
+                .physical_file_mount
+                .iter()
+                .map(|(guest, v)| FsFileMount {
+                    guest: guest.clone(),
+                    host: v.host.clone(),
+                    readonly: v.readonly,
+                })
+                .collect(),
+        }
+    }
+
+    /// Resolves filesystem access of instance built with context and config, without building it.
+    pub fn resolve_fs_access(this: &Gd<Self>, config: &Config) -> AnyResult<FsAccess> {
+        Ok(Self::fs_access(
+            &this.bind().get_data()?.inner.read(),
+            config,
+        ))
+    }
 }
 
 #[godot_api]
@@ -200,10 +651,37 @@ impl WasiContext {
     /// Emitted whenever WASI stderr is written. Only usable with WASI.
     #[signal]
     fn stderr_emit(message: Variant);
+    /// Emitted whenever instance writes to pipe. Only usable with WASI.
+    #[signal]
+    fn pipe_emit(name: GString, data: PackedByteArray);
+    /// Emitted when instance closes pipe, or is freed. Only usable with WASI.
+    #[signal]
+    fn pipe_closed(name: GString);
+    /// Emitted periodically while asynchronous `import_dir()` is running.
+    #[signal]
+    fn memfs_progress(ticket: i64, done_bytes: i64, total_bytes: i64);
+    /// Emitted when asynchronous `import_dir()` finishes.
+    #[signal]
+    fn memfs_finished(ticket: i64, ok: bool, error: GString);
+
+    /// File does not exist.
+    #[constant]
+    const FILE_TYPE_NOTEXIST: i64 = FILE_NOTEXIST as i64;
+    /// Is a regular file.
+    #[constant]
+    const FILE_TYPE_FILE: i64 = FILE_FILE as i64;
+    /// Is a directory.
+    #[constant]
+    const FILE_TYPE_DIR: i64 = FILE_DIR as i64;
+    /// Is a symbolic link.
+    #[constant]
+    const FILE_TYPE_LINK: i64 = FILE_LINK as i64;
 
     /// Initialize and instantiates context.
     ///
     /// **⚠ MUST BE CALLED FOR THE FIRST TIME AND ONLY ONCE.**
+    /// It must complete before context is shared with other threads.
+    /// Calling it again fails, but failed initialization can be retried.
     ///
     /// Returns itself if succeed, `null` otherwise.
     ///
@@ -211,45 +689,176 @@ impl WasiContext {
     /// - `config` : Configuration option. Is a dictionary with the following key/value:
     ///   - `memfs.max_size` : Maximum number of bytes allowed for in-memory filesystem. Defaults to uncapped.
     ///   - `memfs.max_node` : Maximum number of file objects allowed for in-memory filesystem. Defaults to uncapped.
+    ///   - `memfs.on_quota` : Behavior of writing file past `memfs.max_size`. Must be one of:
+    ///     - `"fail"` (default) : Write fails with no space error.
+    ///     - `"fail_and_truncate"` : Write fails, and the partially written file is removed.
+    ///     - `"evict_lru"` : Removes least recently accessed files that are not open, then retries once.
+    ///   - `memfs.chunk_min` : Minimum size of in-memory file chunk. Must be power of two and at least 32. Defaults to 4 KiB.
+    ///   - `memfs.chunk_max` : Maximum size of in-memory file chunk. Must be power of two and at most 1 GiB. Defaults to 64 KiB.
+    ///   - `memfs.max_link_depth` : Maximum number of symbolic links followed when resolving a path. Defaults to 10.
+    ///   - `memfs.dedup` : If `true`, files with identical content share storage once sealed. Defaults to `false`.
+    ///   - `clock.scope` : Scope of monotonic clock. Must be one of:
+    ///     - `"instance"` (default) : Every instance has it's own clock, starting at 0.
+    ///     - `"context"` : Clock is shared by all instances using this context, so it never goes backwards across reloads.
+    ///   - `fs.unlink_open` : Behavior of deleting file with open handles. Must be one of:
+    ///     - `"allow"` (default) : Deletes file, it's content is kept until all handles are closed.
+    ///     - `"deny"` : Fails with busy error.
+    ///   - `name` : Name of context. Defaults to object instance ID.
+    ///   - `stdio.log_file` : Path of file to log stdout/stderr into. `{context}` and `{date}` is substituted
+    ///     with context name and current date. Disabled by default.
+    ///   - `stdio.log_max_bytes` : Maximum size of log file before it's rotated. 0 disables rotation. Defaults to 1 MiB.
+    ///   - `stdio.log_keep` : Number of rotated log files kept. Defaults to 3.
+    ///   - `stdio.godot_console` : If `true`, route stdout/stderr lines to Godot console. Overrides `bypass_stdio`.
     #[func]
     fn initialize(&self, config: Variant) -> Option<Gd<WasiContext>> {
-        let r = self.data.get_or_try_init(move || -> AnyResult<_> {
+        let mut init = false;
+        let r = self.data.get_or_try_init(|| -> AnyResult<_> {
+            init = true;
             let config = site_context!(variant_to_option::<Dictionary>(config))?;
-
-            Ok(Mutex::new(WasiContextInner {
-                memfs_controller: site_context!(IsolatedFSController::new(
-                    site_context!(config
-                        .as_ref()
-                        .and_then(|c| c.get("memfs.max_size"))
-                        .map(from_var_any::<i64>)
-                        .transpose())?
-                    .map_or(isize::MAX as usize, |v| v as usize),
-                    site_context!(config
-                        .as_ref()
-                        .and_then(|c| c.get("memfs.max_node"))
-                        .map(from_var_any::<i64>)
-                        .transpose())?
-                    .map_or(isize::MAX as usize, |v| v as usize),
-                ))?,
+            let name = match config.as_ref().and_then(|c| c.get("name")) {
+                Some(v) => site_context!(from_var_any::<GString>(v))?.to_string(),
+                None => self.to_gd().instance_id().to_string(),
+            };
+            let log_file = match &config {
+                Some(c) => Self::open_log_file(&name, c)?,
+                None => None,
+            };
+            let console_stdio = site_context!(config
+                .as_ref()
+                .and_then(|c| c.get("stdio.godot_console"))
+                .map(from_var_any::<bool>)
+                .transpose())?
+            .unwrap_or(false);
+            let unlink_open = match config.as_ref().and_then(|c| c.get("fs.unlink_open")) {
+                None => UnlinkOpenPolicy::Allow,
+                Some(v) => match site_context!(from_var_any::<GString>(v))?
+                    .to_string()
+                    .as_str()
+                {
+                    "" | "allow" => UnlinkOpenPolicy::Allow,
+                    "deny" => UnlinkOpenPolicy::Deny,
+                    v => bail_with_site!("Unknown fs.unlink_open value {v:?}"),
+                },
+            };
+
+            let on_quota = match config.as_ref().and_then(|c| c.get("memfs.on_quota")) {
+                None => QuotaPolicy::Fail,
+                Some(v) => match site_context!(from_var_any::<GString>(v))?
+                    .to_string()
+                    .as_str()
+                {
+                    "" | "fail" => QuotaPolicy::Fail,
+                    "fail_and_truncate" => QuotaPolicy::FailAndTruncate,
+                    "evict_lru" => QuotaPolicy::EvictLru,
+                    v => bail_with_site!("Unknown memfs.on_quota value {v:?}"),
+                },
+            };
+
+            let clock = match config.as_ref().and_then(|c| c.get("clock.scope")) {
+                None => None,
+                Some(v) => match site_context!(from_var_any::<GString>(v))?
+                    .to_string()
+                    .as_str()
+                {
+                    "" | "instance" => None,
+                    "context" => Some(ClockController::new()),
+                    v => bail_with_site!("Unknown clock.scope value {v:?}"),
+                },
+            };
+
+            let memfs_controller = site_context!(IsolatedFSController::new(
+                site_context!(config
+                    .as_ref()
+                    .and_then(|c| c.get("memfs.max_size"))
+                    .map(from_var_any::<i64>)
+                    .transpose())?
+                .map_or(isize::MAX as usize, |v| v as usize),
+                site_context!(config
+                    .as_ref()
+                    .and_then(|c| c.get("memfs.max_node"))
+                    .map(from_var_any::<i64>)
+                    .transpose())?
+                .map_or(isize::MAX as usize, |v| v as usize),
+            ))?;
+            let inner = WasiContextInner {
+                clock,
                 physical_mount: HashMap::new(),
+                physical_file_mount: HashMap::new(),
                 envs: HashMap::new(),
+                log_file,
+                stdout_filter: Default::default(),
+                pipes: Vec::new(),
+                capture: None,
 
                 bypass_stdio: false,
+                console_stdio,
                 fs_readonly: false,
-            }))
+            };
+            memfs_controller.set_unlink_open(unlink_open);
+            memfs_controller.set_quota_policy(on_quota);
+
+            let chunk_min = site_context!(config
+                .as_ref()
+                .and_then(|c| c.get("memfs.chunk_min"))
+                .map(from_var_any::<i64>)
+                .transpose())?;
+            let chunk_max = site_context!(config
+                .as_ref()
+                .and_then(|c| c.get("memfs.chunk_max"))
+                .map(from_var_any::<i64>)
+                .transpose())?;
+            if chunk_min.is_some() || chunk_max.is_some() {
+                let (min, max) = memfs_controller.chunk_size();
+                site_context!(memfs_controller.set_chunk_size(
+                    chunk_min.map_or(min, |v| v.max(0) as usize),
+                    chunk_max.map_or(max, |v| v.max(0) as usize),
+                ))?;
+            }
+
+            if let Some(v) = site_context!(config
+                .as_ref()
+                .and_then(|c| c.get("memfs.max_link_depth"))
+                .map(from_var_any::<i64>)
+                .transpose())?
+            {
+                site_context!(memfs_controller.set_link_depth(v.max(0) as usize))?;
+            }
+
+            if let Some(v) = site_context!(config
+                .as_ref()
+                .and_then(|c| c.get("memfs.dedup"))
+                .map(from_var_any::<bool>)
+                .transpose())?
+            {
+                memfs_controller.set_dedup(v);
+            }
+
+            Ok(WasiContextData {
+                memfs_controller,
+                dir_cursors: Mutex::default(),
+                warned_inferred_write: AtomicBool::new(false),
+                imports: Arc::default(),
+                inner: RwLock::new(inner),
+            })
         });
 
-        if let Err(e) = r {
-            godot_error!("{e:?}");
-            None
-        } else {
-            Some(self.to_gd())
+        match r {
+            Err(e) => {
+                godot_error!("{e:?}");
+                None
+            }
+            Ok(_) if !init => {
+                godot_error!("Context is already initialized");
+                None
+            }
+            Ok(_) => Some(self.to_gd()),
         }
     }
 
     #[func]
     fn is_bypass_stdio(&self) -> bool {
-        self.wrap_data(|v| Ok(v.bypass_stdio)).unwrap_or_default()
+        self.wrap_data_ref(|v| Ok(v.bypass_stdio))
+            .unwrap_or_default()
     }
 
     #[func]
@@ -262,7 +871,8 @@ impl WasiContext {
 
     #[func]
     fn is_fs_readonly(&self) -> bool {
-        self.wrap_data(|v| Ok(v.fs_readonly)).unwrap_or_default()
+        self.wrap_data_ref(|v| Ok(v.fs_readonly))
+            .unwrap_or_default()
     }
 
     #[func]
@@ -273,11 +883,155 @@ impl WasiContext {
         });
     }
 
+    /// Filters stdout lines before they're emitted.
+    ///
+    /// Only applies to line-buffered `stdout_emit` signal. Mode must be one of:
+    /// - `"only_matching"` : Only emit lines matching `regex`.
+    /// - `"drop_matching"` : Drop lines matching `regex`.
+    ///
+    /// Trailing newline is not matched against. Empty `regex` removes filter.
+    /// Change takes effect from the next complete line.
+    #[func]
+    fn set_stdout_filter(&self, regex: GString, mode: GString) -> bool {
+        self.wrap_data(move |this| {
+            let regex = regex.to_string();
+            let filter = if regex.is_empty() {
+                None
+            } else {
+                let mode = match mode.to_string().as_str() {
+                    "only_matching" => StdoutFilterMode::OnlyMatching,
+                    "drop_matching" => StdoutFilterMode::DropMatching,
+                    v => bail_with_site!("Unknown filter mode {v:?}"),
+                };
+                Some((site_context!(Regex::new(&regex))?, mode))
+            };
+            this.stdout_filter.set(filter);
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Returns number of `emitted` and `suppressed` stdout lines by filter.
+    #[func]
+    fn get_stdout_filter_stats(&self) -> Variant {
+        option_to_variant(self.wrap_data_ref(|this| {
+            let (emitted, suppressed) = this.stdout_filter.stats();
+            let mut ret = Dictionary::new();
+            ret.set("emitted", emitted);
+            ret.set("suppressed", suppressed);
+            Ok(ret)
+        }))
+    }
+
+    /// Captures stdout and stderr into buffers instead of emitting signals.
+    ///
+    /// Each buffer holds up to `max_bytes`, further bytes are dropped and overflow flag is set.
+    /// Negative `max_bytes` disables capture.
+    /// Only affects instances created afterwards, existing capturing instances keep writing
+    /// into previous buffers.
+    #[func]
+    fn enable_stdout_capture(&self, max_bytes: i64) {
+        self.wrap_data(move |this| {
+            this.capture = usize::try_from(max_bytes).ok().map(|v| {
+                [
+                    Arc::new(StdoutCapture::new(v)),
+                    Arc::new(StdoutCapture::new(v)),
+                ]
+            });
+            Ok(())
+        });
+    }
+
+    /// Takes captured stdout bytes, draining buffer and resetting overflow flag.
+    #[func]
+    fn take_captured_stdout(&self) -> PackedByteArray {
+        self.take_captured(0)
+    }
+
+    /// Takes captured stderr bytes, draining buffer and resetting overflow flag.
+    #[func]
+    fn take_captured_stderr(&self) -> PackedByteArray {
+        self.take_captured(1)
+    }
+
+    /// Returns `true` if captured stdout dropped bytes since last take.
+    #[func]
+    fn is_stdout_capture_overflow(&self) -> bool {
+        self.wrap_data_ref(|this| Ok(this.capture.as_ref().is_some_and(|c| c[0].is_overflow())))
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` if captured stderr dropped bytes since last take.
+    #[func]
+    fn is_stderr_capture_overflow(&self) -> bool {
+        self.wrap_data_ref(|this| Ok(this.capture.as_ref().is_some_and(|c| c[1].is_overflow())))
+            .unwrap_or_default()
+    }
+
+    /// Adds extra stream named `name`. Returns file descriptor number seen by instances.
+    ///
+    /// Pipes are numbered from 3 in order they're added, before preopened directories.
+    /// Only affects instances created afterwards.
+    #[func]
+    fn add_pipe(&self, name: GString) -> i64 {
+        self.wrap_data(move |this| {
+            let name = name.to_string();
+            if this.pipes.iter().any(|p| p.name == name) {
+                bail_with_site!("Pipe {name:?} already exists");
+            }
+            this.pipes.push(ContextPipe {
+                name,
+                hosts: Vec::new(),
+            });
+            Ok(this.pipes.len() as i64 + 2)
+        })
+        .unwrap_or(-1)
+    }
+
+    /// Writes data to pipe of every instance.
+    #[func]
+    fn pipe_write(&self, name: GString, data: PackedByteArray) -> bool {
+        self.wrap_data(move |this| {
+            let name = name.to_string();
+            let Some(p) = this.pipes.iter().find(|p| p.name == name) else {
+                bail_with_site!("Pipe {name:?} does not exist");
+            };
+            for h in &p.hosts {
+                h.write(data.as_slice());
+            }
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Closes pipe of every instance.
+    ///
+    /// Instances reading it get end of file, and writing it fails with broken pipe error.
+    /// Instances created afterwards get a new open pipe.
+    #[func]
+    fn pipe_close(&self, name: GString) -> bool {
+        self.wrap_data(move |this| {
+            let name = name.to_string();
+            let Some(p) = this.pipes.iter_mut().find(|p| p.name == name) else {
+                bail_with_site!("Pipe {name:?} does not exist");
+            };
+            for h in p.hosts.drain(..) {
+                h.close();
+            }
+            Ok(())
+        })
+        .is_some()
+    }
+
     /// Sets context-wide environment variable.
+    ///
+    /// Key must not contain `=`, and both key and value must not contain NUL.
     #[func]
     fn add_env_variable(&self, key: GString, value: GString) {
         self.wrap_data(move |this| {
-            this.envs.insert(key.to_string(), value.to_string());
+            let (key, value) = (key.to_string(), value.to_string());
+            site_context!(validate_env(&key, &value))?;
+            this.envs.insert(key, value);
             Ok(())
         });
     }
@@ -286,7 +1040,7 @@ impl WasiContext {
     #[func]
     fn get_env_variable(&self, key: GString) -> Variant {
         option_to_variant(
-            self.wrap_data(move |this| Ok(this.envs.get(&key.to_string()).map(GString::from)))
+            self.wrap_fs(move |this| Ok(this.get_env(&key.to_string()).map(GString::from)))
                 .flatten(),
         )
     }
@@ -304,12 +1058,23 @@ impl WasiContext {
     ///
     /// Arguments:
     /// - `host_path` : Path to host directory. Does not accept Godot-specific paths (eg. `res://`).
+    ///   On Windows, UNC (`\\server\share`) and verbatim (`\\?\`) paths are supported,
+    ///   but drive-relative paths (eg. `D:foo`) are rejected.
     /// - `guest_path` : Absolute path in guest where it will be mounted. Path is unix-style (no drive letter).
+    /// - `options` : Optional dictionary of mount options:
+    ///   - `case_insensitive` : If `true`, resolve paths case-insensitively when opening. Defaults to `false`.
     #[func]
-    fn mount_physical_dir(&self, host_path: GString, guest_path: GString) {
+    fn mount_physical_dir(&self, host_path: GString, guest_path: GString, options: Variant) {
         self.wrap_data(move |this| {
-            let host_path = host_path.to_string();
+            let host_path = site_context!(normalize_host_path(Path::new(&host_path.to_string())))?;
             let guest_path = Utf8PathBuf::from(guest_path.to_string());
+            let options = site_context!(variant_to_option::<Dictionary>(options))?;
+            let case_insensitive = site_context!(options
+                .as_ref()
+                .and_then(|o| o.get("case_insensitive"))
+                .map(from_var_any::<bool>)
+                .transpose())?
+            .unwrap_or(false);
 
             let mut it = guest_path.components();
             if !matches!(it.next(), Some(Utf8Component::RootDir))
@@ -318,19 +1083,81 @@ impl WasiContext {
                 bail_with_site!("Guest path is not absolute");
             }
 
-            this.physical_mount.insert(guest_path, host_path.into());
+            this.physical_mount.insert(
+                guest_path,
+                PhysicalMount {
+                    host: host_path,
+                    case_insensitive,
+                },
+            );
             Ok(())
         });
     }
 
-    /// Gets all mounted paths.
+    /// Mounts single host file into guest.
+    ///
+    /// Parent directories of `guest_path` are created in in-memory filesystem.
+    /// Opening `guest_path` in guest opens host file instead.
+    ///
+    /// Arguments:
+    /// - `host_path` : Path to host file. Does not accept Godot-specific paths (eg. `res://`).
+    /// - `guest_path` : Absolute path in guest where it will be mounted.
+    /// - `readonly` : If `true`, guest can't write to the file.
     #[func]
-    fn get_mounts(&self) -> Variant {
+    fn mount_physical_file(&self, host_path: GString, guest_path: GString, readonly: bool) {
+        self.wrap_data(move |this| {
+            let host_path = site_context!(normalize_host_path(Path::new(&host_path.to_string())))?;
+            let guest_path = Utf8PathBuf::from(guest_path.to_string());
+
+            let mut it = guest_path.components();
+            if !matches!(it.next(), Some(Utf8Component::RootDir))
+                || it.any(|v| !matches!(v, Utf8Component::Normal(s) if !s.contains(ILLEGAL_CHARS)))
+            {
+                bail_with_site!("Guest path is not absolute");
+            } else if guest_path.file_name().is_none() {
+                bail_with_site!("Guest path is root directory");
+            }
+
+            this.physical_file_mount.insert(
+                guest_path,
+                PhysicalFileMount {
+                    host: host_path,
+                    readonly,
+                },
+            );
+            Ok(())
+        });
+    }
+
+    /// Unmounts host file.
+    ///
+    /// Arguments:
+    /// - `guest_path` : Absolute path in guest. Must be exact.
+    #[func]
+    fn unmount_physical_file(&mut self, guest_path: GString) -> Variant {
         option_to_variant(self.wrap_data(|this| {
+            Ok(this
+                .physical_file_mount
+                .remove(Utf8Path::new(&guest_path.to_string()))
+                .is_some())
+        }))
+    }
+
+    /// Gets all mounted paths, including mounted files.
+    #[func]
+    fn get_mounts(&self) -> Variant {
+        option_to_variant(self.wrap_data_ref(|this| {
             Ok(this
                 .physical_mount
                 .iter()
-                .map(|(k, v)| (GString::from(k.as_str()), GString::from(v.as_str())))
+                .map(|(k, v)| (k, &v.host))
+                .chain(this.physical_file_mount.iter().map(|(k, v)| (k, &v.host)))
+                .map(|(k, v)| {
+                    (
+                        GString::from(k.as_str()),
+                        GString::from(&*v.to_string_lossy()),
+                    )
+                })
                 .collect::<Dictionary>())
         }))
     }
@@ -349,14 +1176,14 @@ impl WasiContext {
         }))
     }
 
-    /// Returns `true` if file is exists.
+    /// Returns type of file (one of `FILE_TYPE_*`), or `FILE_TYPE_NOTEXIST` if it does not exist.
     ///
     /// Arguments:
     /// - `path` : Absolute path to file.
     /// - `follow_symlink` : If `true`, follow symbolic links.
     #[func]
     fn file_is_exist(&self, path: GString, follow_symlink: Variant) -> Variant {
-        option_to_variant(self.wrap_data(move |this| {
+        option_to_variant(self.wrap_fs(move |this| {
             match CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
                 &this.memfs_controller,
                 &Utf8PathBuf::from(path.to_string()),
@@ -364,16 +1191,7 @@ impl WasiContext {
                 None,
                 AccessMode::RW,
             ) {
-                Ok(f) => {
-                    let n = &**f.node();
-                    Ok(if n.is_link() {
-                        FILE_LINK
-                    } else if n.is_dir() {
-                        FILE_DIR
-                    } else {
-                        FILE_FILE
-                    })
-                }
+                Ok(f) => Ok(node_filetype(f.node())),
                 Err(e) if e.io().map(|e| e.kind()) == Some(ErrorKind::NotFound) => {
                     Ok(FILE_NOTEXIST)
                 }
@@ -382,6 +1200,201 @@ impl WasiContext {
         }))
     }
 
+    /// Gets name of file type returned by `file_is_exist`, for debugging.
+    ///
+    /// Returns `"unknown"` if code is invalid.
+    #[func]
+    fn file_type_name(code: i64) -> GString {
+        u32::try_from(code)
+            .ok()
+            .and_then(file_type_str)
+            .unwrap_or("unknown")
+            .into()
+    }
+
+    /// Returns number of open handles (descriptors and streams) to file.
+    ///
+    /// Arguments:
+    /// - `path` : Absolute path to file.
+    /// - `follow_symlink` : If `true`, follow symbolic links.
+    #[func]
+    fn file_handles(&self, path: GString, follow_symlink: Variant) -> Variant {
+        option_to_variant(self.wrap_fs(move |this| {
+            let f = site_context!(
+                CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
+                    &this.memfs_controller,
+                    &Utf8PathBuf::from(path.to_string()),
+                    site_context!(variant_to_option(follow_symlink))?.unwrap_or(false),
+                    None,
+                    AccessMode::RW,
+                )
+            )?;
+            // Exclude our own handle
+            Ok(f.node().handles().saturating_sub(1) as u64)
+        }))
+    }
+
+    /// Seals file, sharing it's content with identical sealed file if `memfs.dedup` is enabled.
+    ///
+    /// Files are also sealed when writable handle to it is closed.
+    /// Returns `true` if success.
+    ///
+    /// Arguments:
+    /// - `path` : Absolute path to file.
+    /// - `follow_symlink` : If `true`, follow symbolic links.
+    #[func]
+    fn file_seal(&self, path: GString, follow_symlink: Variant) -> bool {
+        self.wrap_fs(move |this| {
+            let path = Utf8PathBuf::from(path.to_string());
+            let f = site_context!(
+                CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
+                    &this.memfs_controller,
+                    &path,
+                    site_context!(variant_to_option(follow_symlink))?.unwrap_or(false),
+                    None,
+                    AccessMode::R,
+                )
+            )?;
+            if !f.node().is_file() {
+                bail_with_site!("{path} is not a file");
+            }
+            Ok(f.node().seal())
+        })
+        .unwrap_or_default()
+    }
+
+    /// Returns number of sealed `files` and bytes `saved` by content deduplication.
+    #[func]
+    fn get_memfs_dedup_stats(&self) -> Variant {
+        option_to_variant(self.wrap_fs(|this| {
+            let stats = this.memfs_controller.dedup_stats();
+            let mut ret = Dictionary::new();
+            ret.set("files", stats.files as u64);
+            ret.set("saved", stats.saved as u64);
+            Ok(ret)
+        }))
+    }
+
+    /// Imports host directory into in-memory filesystem.
+    ///
+    /// Directory is built detached from filesystem, then linked into place at once,
+    /// so guest never observes partially imported directory.
+    /// Symbolic links and special files are skipped.
+    ///
+    /// Returns 0 if synchronous import succeeds, or ticket of asynchronous import.
+    /// Returns -1 on error.
+    ///
+    /// Arguments:
+    /// - `host_path` : Path to host directory. Does not accept Godot-specific paths (eg. `res://`).
+    /// - `guest_path` : Absolute path of new directory in guest. Must not exist.
+    /// - `asynchronous` : If `true`, import runs in background thread.
+    ///   Progress is reported with `memfs_progress` signal, and completion with `memfs_finished` signal.
+    #[func]
+    fn import_dir(&self, host_path: GString, guest_path: GString, asynchronous: bool) -> i64 {
+        self.wrap_fs(move |this| {
+            let host = site_context!(normalize_host_path(Path::new(&host_path.to_string())))?;
+            let guest = Utf8PathBuf::from(guest_path.to_string());
+            let (Some(parent), Some(name)) = (guest.parent(), guest.file_name()) else {
+                bail_with_site!("Invalid guest path {guest}");
+            };
+            let parent = site_context!(CapWrapper::new(
+                this.memfs_controller.root(),
+                AccessMode::RW
+            )
+            .open(&this.memfs_controller, parent, false, None, AccessMode::RW,))?
+            .node()
+            .clone();
+            let name = name.to_string();
+
+            if !asynchronous {
+                let plan = site_context!(ImportPlan::scan(&host))?;
+                site_context!(plan.import(
+                    &this.memfs_controller,
+                    &parent,
+                    &name,
+                    &AtomicBool::new(false),
+                    |_| ()
+                ))?;
+                return Ok(0);
+            }
+
+            let ticket = NEXT_IMPORT_TICKET.fetch_add(1, Ordering::Relaxed);
+            let cancel = Arc::new(AtomicBool::new(false));
+            this.imports.lock().insert(ticket, cancel.clone());
+            let imports = this.imports.clone();
+            let controller = this.memfs_controller.dup();
+            let progress =
+                SendSyncWrapper::new(Signal::from_object_signal(&self.to_gd(), c"memfs_progress"));
+            let finished =
+                SendSyncWrapper::new(Signal::from_object_signal(&self.to_gd(), c"memfs_finished"));
+
+            let r = thread::Builder::new()
+                .name(format!("memfs-import-{ticket}"))
+                .spawn(move || {
+                    let emit_progress = |done: u64, total: u64| {
+                        emit_in_main_thread(
+                            &progress,
+                            &[
+                                ticket.to_variant(),
+                                (done as i64).to_variant(),
+                                (total as i64).to_variant(),
+                            ],
+                        )
+                    };
+                    let r = ImportPlan::scan(&host).and_then(|plan| {
+                        let total = plan.total_bytes();
+                        emit_progress(0, total);
+                        let mut last = Instant::now();
+                        plan.import(&controller, &parent, &name, &cancel, |done| {
+                            if last.elapsed() >= IMPORT_PROGRESS_INTERVAL {
+                                last = Instant::now();
+                                emit_progress(done, total);
+                            }
+                        })?;
+                        emit_progress(total, total);
+                        Ok(())
+                    });
+
+                    imports.lock().remove(&ticket);
+                    let error = match &r {
+                        Ok(()) => String::new(),
+                        Err(e) => format!("{e:#}"),
+                    };
+                    emit_in_main_thread(
+                        &finished,
+                        &[
+                            ticket.to_variant(),
+                            r.is_ok().to_variant(),
+                            error.to_variant(),
+                        ],
+                    );
+                });
+            if let Err(e) = r {
+                this.imports.lock().remove(&ticket);
+                return Err(e.into());
+            }
+            Ok(ticket)
+        })
+        .unwrap_or(-1)
+    }
+
+    /// Cancels asynchronous import. Imported data is discarded and it's quota returned.
+    ///
+    /// Returns `true` if import is running. It still emits `memfs_finished` signal.
+    #[func]
+    fn cancel_import(&self, ticket: i64) -> bool {
+        self.wrap_fs(|this| {
+            Ok(match this.imports.lock().get(&ticket) {
+                Some(v) => {
+                    v.store(true, Ordering::Relaxed);
+                    true
+                }
+                None => false,
+            })
+        })
+        .unwrap_or_default()
+    }
+
     /// Create a new directory.
     ///
     /// Returns `true` if success.
@@ -392,7 +1405,7 @@ impl WasiContext {
     /// - `follow_symlink` : If `true`, follow symbolic links.
     #[func]
     fn file_make_dir(&self, path: GString, name: GString, follow_symlink: Variant) -> bool {
-        self.wrap_data(move |this| {
+        self.wrap_fs(move |this| {
             let f = site_context!(
                 CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
                     &this.memfs_controller,
@@ -424,7 +1437,7 @@ impl WasiContext {
     /// - `follow_symlink` : If `true`, follow symbolic links.
     #[func]
     fn file_make_file(&self, path: GString, name: GString, follow_symlink: Variant) -> bool {
-        self.wrap_data(move |this| {
+        self.wrap_fs(move |this| {
             let f = site_context!(
                 CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
                     &this.memfs_controller,
@@ -463,7 +1476,7 @@ impl WasiContext {
         link: GString,
         follow_symlink: Variant,
     ) -> bool {
-        self.wrap_data(move |this| {
+        self.wrap_fs(move |this| {
             let f = site_context!(
                 CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
                     &this.memfs_controller,
@@ -495,7 +1508,7 @@ impl WasiContext {
     /// - `follow_symlink` : If `true`, follow symbolic links.
     #[func]
     fn file_delete_file(&self, path: GString, name: GString, follow_symlink: Variant) -> bool {
-        self.wrap_data(move |this| {
+        self.wrap_fs(move |this| {
             let f = site_context!(
                 CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
                     &this.memfs_controller,
@@ -512,6 +1525,50 @@ impl WasiContext {
         .unwrap_or_default()
     }
 
+    /// Clones a file without copying its content.
+    ///
+    /// Data is shared copy-on-write, so it's only copied when either file is written.
+    /// Returns `true` if success.
+    ///
+    /// Arguments:
+    /// - `src_path` : Absolute path to source file.
+    /// - `dst_path` : Absolute path to new file. Must not exist.
+    /// - `follow_symlink` : If `true`, follow symbolic links.
+    #[func]
+    fn file_clone(&self, src_path: GString, dst_path: GString, follow_symlink: Variant) -> bool {
+        self.wrap_fs(move |this| {
+            let follow_symlink = site_context!(variant_to_option(follow_symlink))?.unwrap_or(false);
+            let dst_path = Utf8PathBuf::from(dst_path.to_string());
+            let (Some(parent), Some(name)) = (dst_path.parent(), dst_path.file_name()) else {
+                bail_with_site!("Invalid destination path {dst_path}");
+            };
+
+            let root = CapWrapper::new(this.memfs_controller.root(), AccessMode::RW);
+            let src = site_context!(root.open(
+                &this.memfs_controller,
+                &Utf8PathBuf::from(src_path.to_string()),
+                follow_symlink,
+                None,
+                AccessMode::RW,
+            ))?;
+            let dst = site_context!(root.open(
+                &this.memfs_controller,
+                parent,
+                follow_symlink,
+                None,
+                AccessMode::RW,
+            ))?;
+
+            let file = site_context!(src.node().try_file()?.try_clone())?;
+            let mut n = site_context!(dst.node().try_dir())?;
+            site_context!(n.add(name, || -> AnyResult<_> {
+                Ok(Arc::new(Node::from((file, Arc::downgrade(dst.node())))))
+            }))
+            .map(|v| v.is_some())
+        })
+        .unwrap_or_default()
+    }
+
     /// List all files in a directory.
     ///
     /// Arguments:
@@ -519,7 +1576,7 @@ impl WasiContext {
     /// - `follow_symlink` : If `true`, follow symbolic links.
     #[func]
     fn file_dir_list(&self, path: GString, follow_symlink: Variant) -> Variant {
-        option_to_variant(self.wrap_data(move |this| {
+        option_to_variant(self.wrap_fs(move |this| {
             let f = site_context!(
                 CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
                     &this.memfs_controller,
@@ -537,6 +1594,87 @@ impl WasiContext {
         }))
     }
 
+    /// Opens cursor to iterate directory in batches.
+    ///
+    /// Unlike `file_dir_list`, it does not list the entire directory at once.
+    /// Entries are iterated in name order, modifying directory while iterating
+    /// never skips or duplicates existing entries.
+    /// Returns cursor handle, or -1 if failed.
+    /// Cursor must be closed with `dir_close`.
+    ///
+    /// Arguments:
+    /// - `path` : Absolute path to directory.
+    /// - `follow_symlink` : If `true`, follow symbolic links.
+    #[func]
+    fn dir_open(&self, path: GString, follow_symlink: Variant) -> i64 {
+        self.wrap_fs(move |this| {
+            let f = site_context!(
+                CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
+                    &this.memfs_controller,
+                    &Utf8PathBuf::from(path.to_string()),
+                    site_context!(variant_to_option(follow_symlink))?.unwrap_or(false),
+                    None,
+                    AccessMode::RW,
+                )
+            )?;
+            let it = site_context!(f.read_directory())?;
+
+            Ok(this.dir_cursors.lock().open(it)?.into())
+        })
+        .unwrap_or(-1)
+    }
+
+    /// Gets next entries of directory cursor.
+    ///
+    /// Returns array of up to `count` dictionaries, with keys:
+    /// - `name` : Name of entry.
+    /// - `type` : File type, same as `file_is_exist`.
+    /// - `size` : Size of file.
+    ///
+    /// Empty array means cursor is exhausted.
+    ///
+    /// Arguments:
+    /// - `handle` : Cursor handle from `dir_open`.
+    /// - `count` : Maximum number of entries.
+    #[func]
+    fn dir_next(&self, handle: i64, count: i64) -> VariantArray {
+        self.wrap_fs(move |this| {
+            let (Ok(handle), Ok(count)) = (u32::try_from(handle), usize::try_from(count)) else {
+                bail_with_site!("Invalid handle {handle} or count {count}");
+            };
+
+            Ok(this
+                .dir_cursors
+                .lock()
+                .next(handle, count)?
+                .into_iter()
+                .map(|(k, n)| {
+                    let mut ret = Dictionary::new();
+                    ret.set("name", &*k);
+                    ret.set("type", node_filetype(&n));
+                    ret.set("size", n.len_and_stamp().0 as u64);
+                    ret.to_variant()
+                })
+                .collect())
+        })
+        .unwrap_or_default()
+    }
+
+    /// Closes directory cursor.
+    ///
+    /// Returns `true` if cursor exists.
+    /// All cursors are closed when context is freed.
+    ///
+    /// Arguments:
+    /// - `handle` : Cursor handle from `dir_open`.
+    #[func]
+    fn dir_close(&self, handle: i64) -> bool {
+        self.wrap_fs(move |this| {
+            Ok(u32::try_from(handle).is_ok_and(|v| this.dir_cursors.lock().close(v)))
+        })
+        .unwrap_or_default()
+    }
+
     /// Gets file statistics.
     ///
     /// Arguments:
@@ -544,7 +1682,7 @@ impl WasiContext {
     /// - `follow_symlink` : If `true`, follow symbolic links.
     #[func]
     fn file_stat(&self, path: GString, follow_symlink: Variant) -> Variant {
-        option_to_variant(self.wrap_data(move |this| {
+        option_to_variant(self.wrap_fs(move |this| {
             let f = site_context!(
                 CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
                     &this.memfs_controller,
@@ -557,16 +1695,7 @@ impl WasiContext {
             let n = &**f.node();
 
             let mut ret = Dictionary::new();
-            ret.set(
-                "filetype",
-                if n.is_link() {
-                    FILE_LINK
-                } else if n.is_dir() {
-                    FILE_DIR
-                } else {
-                    FILE_FILE
-                },
-            );
+            ret.set("filetype", node_filetype(n));
             let (len, stamp) = n.len_and_stamp();
             ret.set("size", len as u64);
             ret.set("atime", to_unix_time(stamp.atime) as i64);
@@ -586,7 +1715,7 @@ impl WasiContext {
     /// - `follow_symlink` : If `true`, follow symbolic links.
     #[func]
     fn file_set_time(&self, path: GString, time: Dictionary, follow_symlink: Variant) -> bool {
-        self.wrap_data(move |this| {
+        self.wrap_fs(move |this| {
             let mtime = time
                 .get("mtime")
                 .map(variant_to_option::<i64>)
@@ -628,7 +1757,7 @@ impl WasiContext {
     /// - `follow_symlink` : If `true`, follow symbolic links.
     #[func]
     fn file_link_target(&self, path: GString, follow_symlink: Variant) -> Variant {
-        option_to_variant(self.wrap_data(move |this| {
+        option_to_variant(self.wrap_fs(move |this| {
             let p = Utf8PathBuf::from(path.to_string());
             let parent = p.parent().unwrap_or(&p);
             let name = site_context!(p
@@ -663,34 +1792,14 @@ impl WasiContext {
         offset: Variant,
         follow_symlink: Variant,
     ) -> Variant {
-        option_to_variant(self.wrap_data(move |this| {
-            let mut off = variant_to_option::<u64>(offset)?.unwrap_or(0) as usize;
-
-            let f = site_context!(
-                CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
-                    &this.memfs_controller,
-                    &Utf8PathBuf::from(path.to_string()),
-                    site_context!(variant_to_option(follow_symlink))?.unwrap_or(false),
-                    None,
-                    AccessMode::RW,
-                )
+        option_to_variant(self.wrap_fs(move |this| {
+            let off = variant_to_option::<u64>(offset)?.unwrap_or(0) as usize;
+            let ret = this.read_file(
+                &Utf8PathBuf::from(path.to_string()),
+                site_context!(variant_to_option(follow_symlink))?.unwrap_or(false),
+                length as usize,
+                off,
             )?;
-            let mut n = site_context!(f.node().try_file())?;
-
-            let mut l = length as usize;
-            let mut ret = Vec::new();
-            while l > 0 {
-                let (v, n) = n.read(l, off);
-                if n == 0 {
-                    break;
-                }
-                let i = ret.len();
-                ret.extend_from_slice(v);
-                ret.resize(i + n, 0);
-                l -= n;
-                off += n;
-            }
-
             Ok(PackedByteArray::from(ret))
         }))
     }
@@ -704,6 +1813,9 @@ impl WasiContext {
     ///   - `String` / `StringName` / `NodePath` : Text data to write (in utf-8).
     ///   - `PackedStringArray` : All items as utf-8 string, concatenated.
     ///   - `Packed*Array` : Formatted data to write.
+    ///
+    ///     Element width is inferred from array type, and written in little-endian.
+    ///     Use `file_write_typed` to make it explicit.
     /// - `offset` : Offset from start of file.
     /// - `truncate` : If `true`, truncate file before writing.
     /// - `follow_symlink` : If `true`, follow symbolic links.
@@ -716,42 +1828,13 @@ impl WasiContext {
         truncate: Variant,
         follow_symlink: Variant,
     ) -> bool {
-        fn write_it<T, const N: usize>(
-            f: &mut File,
-            mut off: usize,
-            it: impl IntoIterator<Item = T>,
-            c: impl Fn(T, &mut [u8; N]),
-        ) -> AnyResult<()> {
-            let mut buf = [[0u8; N]; 4];
-            let mut i = 0;
-            for v in it {
-                c(v, &mut buf[i]);
-
-                i += 1;
-                if i == buf.len() {
-                    i = 0;
-                    f.write(buf.as_flattened(), off)?;
-                    off += N * buf.len();
-                }
-            }
-
-            if i > 0 {
-                f.write(buf[..i].as_flattened(), off)?;
-            }
-            Ok(())
-        }
-
-        self.wrap_data(move |this| {
+        self.wrap_fs(move |this| {
             let mut off = variant_to_option::<u64>(offset)?.unwrap_or(0) as usize;
 
-            let f = site_context!(
-                CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
-                    &this.memfs_controller,
-                    &Utf8PathBuf::from(path.to_string()),
-                    site_context!(variant_to_option(follow_symlink))?.unwrap_or(false),
-                    Some(CreateParams::new()),
-                    AccessMode::RW,
-                )
+            let f = this.open_file(
+                &Utf8PathBuf::from(path.to_string()),
+                site_context!(variant_to_option(follow_symlink))?.unwrap_or(false),
+                true,
             )?;
             let mut n = site_context!(f.node().try_file())?;
 
@@ -759,6 +1842,16 @@ impl WasiContext {
                 site_context!(n.resize(0))?;
             }
 
+            let ty = data.get_type();
+            if ty != VariantType::PACKED_BYTE_ARRAY
+                && !this.warned_inferred_write.load(Ordering::Relaxed)
+            {
+                if let Some((name, _, size)) = packed_element(ty) {
+                    this.warned_inferred_write.store(true, Ordering::Relaxed);
+                    godot_warn!("file_write: {name} is written as {size}-byte little-endian elements. Use file_write_typed to specify element format.");
+                }
+            }
+
             variant_dispatch!{data {
                 PACKED_BYTE_ARRAY => site_context!(n.write(data.as_slice(), off))?,
                 STRING => site_context!(n.write(data.to_string().as_bytes(), off))?,
@@ -773,13 +1866,13 @@ impl WasiContext {
                         off += temp.len();
                     }
                 },
-                PACKED_INT32_ARRAY => site_context!(write_it(&mut n, off, data.as_slice(), |v, s| *s = v.to_le_bytes()))?,
-                PACKED_INT64_ARRAY => site_context!(write_it(&mut n, off, data.as_slice(), |v, s| *s = v.to_le_bytes()))?,
-                PACKED_FLOAT32_ARRAY => site_context!(write_it(&mut n, off, data.as_slice(), |v, s| *s = v.to_le_bytes()))?,
-                PACKED_FLOAT64_ARRAY => site_context!(write_it(&mut n, off, data.as_slice(), |v, s| *s = v.to_le_bytes()))?,
-                PACKED_VECTOR2_ARRAY => site_context!(write_it(&mut n, off, data.as_slice(), StructPacking::<f32>::write_array))?,
-                PACKED_VECTOR3_ARRAY => site_context!(write_it(&mut n, off, data.as_slice(), StructPacking::<f32>::write_array))?,
-                PACKED_COLOR_ARRAY => site_context!(write_it(&mut n, off, data.as_slice(), StructPacking::<f32>::write_array))?,
+                PACKED_INT32_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| *s = v.to_le_bytes()))?,
+                PACKED_INT64_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| *s = v.to_le_bytes()))?,
+                PACKED_FLOAT32_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| *s = v.to_le_bytes()))?,
+                PACKED_FLOAT64_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| *s = v.to_le_bytes()))?,
+                PACKED_VECTOR2_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), StructPacking::<f32>::write_array))?,
+                PACKED_VECTOR3_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), StructPacking::<f32>::write_array))?,
+                PACKED_COLOR_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), StructPacking::<f32>::write_array))?,
                 _ => bail_with_site!("Unknown value type {:?}", data.get_type()),
             }};
 
@@ -787,24 +1880,148 @@ impl WasiContext {
         }).is_some()
     }
 
+    /// Atomically writes file, replacing it's content.
+    ///
+    /// Data is written into temporary file next to it, which then replaces the file.
+    /// Readers never observe partially written file, even if the write fails midway.
+    /// Unlike other file methods, paths inside mounted host directories are written to host.
+    ///
+    /// Returns `true` if success.
+    ///
+    /// Arguments:
+    /// - `path` : Absolute path to file.
+    /// - `data` : `PackedByteArray` or `String` to write.
+    /// - `follow_symlink` : If `true`, follow symbolic links of parent directory. Ignored for host directories.
+    #[func]
+    fn file_write_atomic(&self, path: GString, data: Variant, follow_symlink: Variant) -> bool {
+        self.wrap_fs(move |this| {
+            let path = Utf8PathBuf::from(path.to_string());
+            let data = variant_dispatch!(data {
+                PACKED_BYTE_ARRAY => data.to_vec(),
+                STRING => data.to_string().into_bytes(),
+                _ => bail_with_site!("Unknown value type {:?}", data.get_type()),
+            });
+
+            let mount = this.inner.read().find_mount(&path);
+            if let Some((host, rel)) = mount {
+                return site_context!(write_file_atomic(&host, &rel, &data));
+            }
+
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                bail_with_site!("Invalid path {path}");
+            };
+            let dir = site_context!(
+                CapWrapper::new(this.memfs_controller.root(), AccessMode::RW).open(
+                    &this.memfs_controller,
+                    parent,
+                    site_context!(variant_to_option(follow_symlink))?.unwrap_or(false),
+                    None,
+                    AccessMode::RW,
+                )
+            )?;
+            site_context!(dir.write_file_atomic(&this.memfs_controller, name, &data))
+        })
+        .is_some()
+    }
+
+    /// Writes numeric array into file with explicit element format.
+    ///
+    /// Unlike `file_write`, element format must match the array element,
+    /// and elements are converted to the requested endianness.
+    ///
+    /// Arguments:
+    /// - `path` : Absolute path to file.
+    /// - `data` : Numeric `Packed*Array` to write. Vector and color arrays are written per component.
+    /// - `element_format` : Element type and endianness (eg. `"i32le"`, `"f64be"`, `"u8"`).
+    ///   Signedness is not checked.
+    /// - `offset` : Offset from start of file.
+    /// - `truncate` : If `true`, truncate file before writing.
+    /// - `follow_symlink` : If `true`, follow symbolic links.
+    #[func]
+    fn file_write_typed(
+        &self,
+        path: GString,
+        data: Variant,
+        element_format: GString,
+        offset: Variant,
+        truncate: Variant,
+        follow_symlink: Variant,
+    ) -> bool {
+        self.wrap_fs(move |this| {
+            let format = ElementFormat::parse(&element_format.to_string())?;
+            let Some((name, kind, size)) = packed_element(data.get_type()) else {
+                bail_with_site!("Value type {:?} is not numeric packed array", data.get_type());
+            };
+            format.check(name, kind, size)?;
+            let off = variant_to_option::<u64>(offset)?.unwrap_or(0) as usize;
+
+            let f = this.open_file(
+                &Utf8PathBuf::from(path.to_string()),
+                site_context!(variant_to_option(follow_symlink))?.unwrap_or(false),
+                true,
+            )?;
+            let mut n = site_context!(f.node().try_file())?;
+
+            if variant_to_option::<bool>(truncate)?.unwrap_or(false) {
+                site_context!(n.resize(0))?;
+            }
+
+            variant_dispatch!{data {
+                PACKED_BYTE_ARRAY => site_context!(n.write(data.as_slice(), off))?,
+                PACKED_INT32_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| {
+                    *s = v.to_le_bytes();
+                    format.convert(s);
+                }))?,
+                PACKED_INT64_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| {
+                    *s = v.to_le_bytes();
+                    format.convert(s);
+                }))?,
+                PACKED_FLOAT32_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| {
+                    *s = v.to_le_bytes();
+                    format.convert(s);
+                }))?,
+                PACKED_FLOAT64_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| {
+                    *s = v.to_le_bytes();
+                    format.convert(s);
+                }))?,
+                PACKED_VECTOR2_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| {
+                    StructPacking::<f32>::write_array(v, s);
+                    format.convert(s);
+                }))?,
+                PACKED_VECTOR3_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| {
+                    StructPacking::<f32>::write_array(v, s);
+                    format.convert(s);
+                }))?,
+                PACKED_COLOR_ARRAY => site_context!(write_elems(&mut n, off, data.as_slice(), |v, s| {
+                    StructPacking::<f32>::write_array(v, s);
+                    format.convert(s);
+                }))?,
+                _ => unreachable!(),
+            }};
+
+            Ok(())
+        }).is_some()
+    }
+
     /// Reads structured data from file.
     ///
     /// Similiar to `WasmInstance.read_struct`
     ///
     /// Arguments:
     /// - `path` : Absolute path to file.
-    /// - `format` : String defining the structure format.
+    /// - `format` : String or `WasmStructFormat` defining the structure format.
     /// - `offset` : Offset from start of file.
     /// - `follow_symlink` : If `true`, follow symbolic links.
     #[func]
     fn file_read_struct(
         &self,
         path: GString,
-        format: GString,
+        format: Variant,
         offset: Variant,
         follow_symlink: Variant,
     ) -> Variant {
-        option_to_variant(self.wrap_data(|this| {
+        option_to_variant(self.wrap_fs(|this| {
+            let format = to_struct_format(&format)?;
             let cursor = variant_to_option::<u64>(offset)?.unwrap_or(0) as usize;
 
             let f = site_context!(
@@ -818,7 +2035,7 @@ impl WasiContext {
             )?;
             let file = site_context!(f.node().try_file())?;
 
-            read_struct(FileWrapper { file, cursor }, format.chars())
+            read_struct(FileWrapper { file, cursor }, &format)
         }))
     }
 
@@ -828,7 +2045,7 @@ impl WasiContext {
     ///
     /// Arguments:
     /// - `path` : Absolute path to file.
-    /// - `format` : String defining the structure format.
+    /// - `format` : String or `WasmStructFormat` defining the structure format.
     /// - `arr` : Structured data array.
     /// - `offset` : Offset from start of file.
     /// - `truncate` : If `true`, truncate file before writing.
@@ -837,13 +2054,14 @@ impl WasiContext {
     fn file_write_struct(
         &self,
         path: GString,
-        format: GString,
+        format: Variant,
         arr: VariantArray,
         offset: Variant,
         truncate: Variant,
         follow_symlink: Variant,
     ) -> Variant {
-        option_to_variant(self.wrap_data(|this| {
+        option_to_variant(self.wrap_fs(|this| {
+            let format = to_struct_format(&format)?;
             let cursor = variant_to_option::<u64>(offset)?.unwrap_or(0) as usize;
 
             let f = site_context!(
@@ -861,7 +2079,7 @@ impl WasiContext {
                 site_context!(file.resize(0))?;
             }
 
-            write_struct(FileWrapper { file, cursor }, format.chars(), arr).map(|v| v as u64)
+            write_struct(FileWrapper { file, cursor }, &format, arr).map(|v| v as u64)
         }))
     }
 }
@@ -914,3 +2132,193 @@ impl<T: DerefMut<Target = File>> Seek for FileWrapper<T> {
         Ok(self.cursor as _)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::channel;
+    use std::thread::spawn;
+
+    fn new_data() -> WasiContextData {
+        WasiContextData {
+            memfs_controller: IsolatedFSController::new(1 << 20, 64).unwrap(),
+            dir_cursors: Mutex::default(),
+            warned_inferred_write: AtomicBool::new(false),
+            imports: Arc::default(),
+            inner: RwLock::new(WasiContextInner {
+                bypass_stdio: false,
+                console_stdio: false,
+                fs_readonly: false,
+                clock: None,
+                physical_mount: HashMap::new(),
+                physical_file_mount: HashMap::new(),
+                envs: HashMap::new(),
+                log_file: None,
+                stdout_filter: Default::default(),
+                pipes: Vec::new(),
+                capture: None,
+            }),
+        }
+    }
+
+    fn make_file(data: &WasiContextData, name: &str) {
+        let root = data.memfs_controller.root();
+        root.try_dir()
+            .unwrap()
+            .add(name.to_string(), || -> AnyResult<_> {
+                Ok(Arc::new(Node::from((
+                    File::new(&data.memfs_controller)?,
+                    Arc::downgrade(&root),
+                ))))
+            })
+            .unwrap();
+    }
+
+    fn write_read(data: &WasiContextData, name: &str, buf: &[u8]) -> Vec<u8> {
+        let f = CapWrapper::new(data.memfs_controller.root(), AccessMode::RW)
+            .open(
+                &data.memfs_controller,
+                Utf8Path::new(name),
+                false,
+                None,
+                AccessMode::RW,
+            )
+            .unwrap();
+        let mut file = f.node().try_file().unwrap();
+        file.write(buf, 0).unwrap();
+        file.read(buf.len(), 0).0.to_vec()
+    }
+
+    #[test]
+    fn test_fs_without_context_lock() {
+        let data = Arc::new(new_data());
+        make_file(&data, "a");
+
+        let _guard = data.inner.write();
+        let (tx, rx) = channel();
+        let d = data.clone();
+        spawn(move || tx.send(write_read(&d, "/a", b"hello")).unwrap());
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(10))
+                .expect("file access blocked by context lock"),
+            b"hello"
+        );
+    }
+
+    #[test]
+    fn test_concurrent_access() {
+        const ITER: usize = 400;
+
+        let data = Arc::new(new_data());
+        for name in ["f0", "f1"] {
+            make_file(&data, name);
+        }
+
+        let (tx, rx) = channel();
+        for i in 0..2 {
+            let (data, tx) = (data.clone(), tx.clone());
+            spawn(move || {
+                let name = format!("/f{i}");
+                for j in 0..ITER {
+                    let buf = [(i * ITER + j) as u8; 256];
+                    assert_eq!(write_read(&data, &name, &buf), buf);
+
+                    let it = CapWrapper::new(data.memfs_controller.root(), AccessMode::RW)
+                        .read_directory()
+                        .unwrap();
+                    let mut cursors = data.dir_cursors.lock();
+                    let id = cursors.open(it).unwrap();
+                    assert_eq!(cursors.next(id, 10).unwrap().len(), 2);
+                    assert!(cursors.close(id));
+                }
+                tx.send(()).unwrap();
+            });
+        }
+        {
+            let (data, tx) = (data.clone(), tx.clone());
+            spawn(move || {
+                for j in 0..ITER {
+                    let guest = Utf8PathBuf::from(format!("/mnt{}", j % 4));
+                    let mut inner = data.inner.write();
+                    if inner.physical_mount.remove(&guest).is_none() {
+                        inner.physical_mount.insert(
+                            guest,
+                            PhysicalMount {
+                                host: "/tmp".into(),
+                                case_insensitive: false,
+                            },
+                        );
+                    }
+                }
+                tx.send(()).unwrap();
+            });
+        }
+        {
+            let (data, tx) = (data.clone(), tx.clone());
+            spawn(move || {
+                let config = Config::default();
+                for _ in 0..ITER {
+                    let access = WasiContext::fs_access(&data.inner.read(), &config);
+                    assert!(!access.readonly);
+                    assert!((1..=5).contains(&access.mounts.len()));
+                    assert!(access.mounts[0].host.is_none());
+                }
+                tx.send(()).unwrap();
+            });
+        }
+        drop(tx);
+
+        for _ in 0..4 {
+            rx.recv_timeout(Duration::from_secs(30))
+                .expect("thread panicked or deadlocked");
+        }
+        // Every mount is toggled an even number of times.
+        assert!(data.inner.read().physical_mount.is_empty());
+    }
+
+    #[test]
+    fn test_threaded_file_env() {
+        const ITER: usize = 400;
+
+        let data = Arc::new(new_data());
+        data.inner
+            .write()
+            .envs
+            .insert("KEY".to_string(), "value".to_string());
+
+        let (tx, rx) = channel();
+        for i in 0..4 {
+            let (data, tx) = (data.clone(), tx.clone());
+            spawn(move || {
+                let path = Utf8PathBuf::from(format!("/t{i}"));
+                let other = Utf8PathBuf::from(format!("/t{}", (i + 1) % 4));
+                for j in 0..ITER {
+                    let (buf, off) = ([(i * ITER + j) as u8; 64], j % 4 * 64);
+                    let f = data.open_file(&path, false, true).unwrap();
+                    f.node().try_file().unwrap().write(&buf, off).unwrap();
+                    drop(f);
+                    assert_eq!(data.read_file(&path, false, 64, off).unwrap(), buf);
+
+                    // Every write is atomic, so blocks are never torn.
+                    if let Ok(v) = data.read_file(&other, false, 256, 0) {
+                        for b in v.chunks(64) {
+                            assert!(b.iter().all(|&c| c == b[0]), "torn write {b:?}");
+                        }
+                    }
+
+                    assert_eq!(data.get_env("KEY").as_deref(), Some("value"));
+                    assert_eq!(data.get_env("MISSING"), None);
+                }
+                assert_eq!(data.read_file(&path, false, 1024, 0).unwrap().len(), 256);
+                tx.send(()).unwrap();
+            });
+        }
+        drop(tx);
+
+        for _ in 0..4 {
+            rx.recv_timeout(Duration::from_secs(30))
+                .expect("thread panicked or deadlocked");
+        }
+    }
+}
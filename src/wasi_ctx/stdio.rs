@@ -1,11 +1,18 @@
 use std::fmt::{Debug, Formatter, Result as FmtResult};
-use std::io::Result as IoResult;
+use std::fs::{create_dir_all, remove_file, rename, File, OpenOptions};
+use std::io::{Result as IoResult, Write};
+use std::mem::take;
 use std::ops::Range;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Instant;
 
 use godot::prelude::*;
+use parking_lot::{Mutex, RwLock};
+use regex::Regex;
+use tracing::error;
 use wasi_isolated_fs::stdio::{HostStdin, HostStdout};
 
 use crate::godot_util::SendSyncWrapper;
@@ -125,3 +132,279 @@ where
         Ok(())
     }
 }
+
+/// Maximum amount of buffered log data before it's forcibly written.
+const LOG_BUF_LEN: usize = 4096;
+
+/// Persistent log file with size-based rotation.
+///
+/// Data is written on newline, when buffer is full, and on drop.
+pub struct StdioLogFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    inner: Mutex<StdioLogFileInner>,
+}
+
+struct StdioLogFileInner {
+    file: Option<File>,
+    size: u64,
+    buf: Vec<u8>,
+}
+
+impl Debug for StdioLogFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("StdioLogFile")
+            .field("path", &self.path)
+            .field("max_bytes", &self.max_bytes)
+            .field("keep", &self.keep)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for StdioLogFile {
+    fn drop(&mut self) {
+        // Might be dropped after Godot is gone, so don't print to Godot console.
+        if let Err(e) = self.flush() {
+            error!(path = %self.path.display(), err = %e, "Error writing log file");
+        }
+    }
+}
+
+impl StdioLogFile {
+    /// Creates new log file. `max_bytes` of 0 disables rotation.
+    pub fn new(path: PathBuf, max_bytes: u64, keep: usize) -> IoResult<Self> {
+        if let Some(p) = path.parent() {
+            create_dir_all(p)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_bytes,
+            keep,
+            inner: Mutex::new(StdioLogFileInner {
+                file: Some(file),
+                size,
+                buf: Vec::new(),
+            }),
+        })
+    }
+
+    fn rotated_path(&self, i: usize) -> PathBuf {
+        let mut p = self.path.clone().into_os_string();
+        p.push(format!(".{i}"));
+        p.into()
+    }
+
+    fn rotate(&self, inner: &mut StdioLogFileInner) -> IoResult<()> {
+        inner.file = None;
+
+        if self.keep == 0 {
+            remove_file(&self.path)?;
+        } else {
+            let last = self.rotated_path(self.keep);
+            if last.exists() {
+                remove_file(&last)?;
+            }
+            for i in (1..self.keep).rev() {
+                let p = self.rotated_path(i);
+                if p.exists() {
+                    rename(&p, self.rotated_path(i + 1))?;
+                }
+            }
+            rename(&self.path, self.rotated_path(1))?;
+        }
+
+        inner.file = Some(File::create(&self.path)?);
+        inner.size = 0;
+        Ok(())
+    }
+
+    fn write_file(&self, inner: &mut StdioLogFileInner, buf: &[u8]) -> IoResult<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        if self.max_bytes > 0
+            && inner.size > 0
+            && inner.size.saturating_add(buf.len() as u64) > self.max_bytes
+        {
+            self.rotate(inner)?;
+        }
+
+        if inner.file.is_none() {
+            inner.file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+        inner.file.as_mut().unwrap().write_all(buf)?;
+        inner.size = inner.size.saturating_add(buf.len() as u64);
+        Ok(())
+    }
+
+    pub fn write(&self, buf: &[u8]) -> IoResult<()> {
+        let mut guard = self.inner.lock();
+        let inner = &mut *guard;
+        inner.buf.extend_from_slice(buf);
+
+        let n = match inner.buf.iter().rposition(|&c| c == b'\n') {
+            Some(i) => i + 1,
+            None if inner.buf.len() >= LOG_BUF_LEN => inner.buf.len(),
+            None => return Ok(()),
+        };
+        let mut data = take(&mut inner.buf);
+        inner.buf = data.split_off(n);
+        self.write_file(inner, &data)
+    }
+
+    pub fn flush(&self) -> IoResult<()> {
+        let mut guard = self.inner.lock();
+        let inner = &mut *guard;
+        let data = take(&mut inner.buf);
+        self.write_file(inner, &data)?;
+        if let Some(f) = &mut inner.file {
+            f.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Tees output into log file before passing it to inner stdout.
+#[derive(Debug)]
+pub struct StdoutTeeLog {
+    inner: Arc<dyn Send + Sync + HostStdout>,
+    log: Arc<StdioLogFile>,
+}
+
+impl StdoutTeeLog {
+    pub fn new(inner: Arc<dyn Send + Sync + HostStdout>, log: Arc<StdioLogFile>) -> Self {
+        Self { inner, log }
+    }
+}
+
+impl HostStdout for StdoutTeeLog {
+    fn write(&self, buf: &[u8]) -> IoResult<()> {
+        // Logging error should not stop output.
+        if let Err(e) = self.log.write(buf) {
+            error!(path = %self.log.path().display(), err = %e, "Error writing log file");
+            godot_error!("Error writing log file {}: {e}", self.log.path().display());
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&self) -> IoResult<()> {
+        if let Err(e) = self.log.flush() {
+            error!(path = %self.log.path().display(), err = %e, "Error writing log file");
+            godot_error!("Error writing log file {}: {e}", self.log.path().display());
+        }
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdoutFilterMode {
+    /// Only emit lines matching the regex.
+    OnlyMatching,
+    /// Emit all lines except those matching the regex.
+    DropMatching,
+}
+
+/// Line filter for line-buffered output.
+///
+/// Filter can be changed at any time, it applies to the next complete line.
+#[derive(Debug, Default)]
+pub struct StdoutFilter {
+    filter: RwLock<Option<(Regex, StdoutFilterMode)>>,
+    emitted: AtomicU64,
+    suppressed: AtomicU64,
+}
+
+impl StdoutFilter {
+    pub fn set(&self, filter: Option<(Regex, StdoutFilterMode)>) {
+        *self.filter.write() = filter;
+    }
+
+    /// Returns `true` if line should be emitted.
+    pub fn check(&self, line: &str) -> bool {
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let ret = match &*self.filter.read() {
+            None => true,
+            Some((re, StdoutFilterMode::OnlyMatching)) => re.is_match(line),
+            Some((re, StdoutFilterMode::DropMatching)) => !re.is_match(line),
+        };
+        if ret { &self.emitted } else { &self.suppressed }.fetch_add(1, Ordering::Relaxed);
+        ret
+    }
+
+    /// Returns number of emitted and suppressed lines.
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.emitted.load(Ordering::Relaxed),
+            self.suppressed.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{read, remove_dir_all};
+
+    #[test]
+    fn test_log_rotation() {
+        let dir = std::env::temp_dir().join(format!("godot-wasm-log-{}", std::process::id()));
+        let path = dir.join("test.log");
+
+        {
+            let log = StdioLogFile::new(path.clone(), 8, 2).unwrap();
+            log.write(b"abc").unwrap();
+            // Not flushed yet.
+            assert_eq!(read(&path).unwrap(), b"");
+            log.write(b"def\n").unwrap();
+            assert_eq!(read(&path).unwrap(), b"abcdef\n");
+            log.write(b"ghi\n").unwrap();
+            log.write(b"jkl\n").unwrap();
+            log.write(b"mno").unwrap();
+        }
+
+        assert_eq!(read(&path).unwrap(), b"mno");
+        assert_eq!(read(dir.join("test.log.1")).unwrap(), b"ghi\njkl\n");
+        assert_eq!(read(dir.join("test.log.2")).unwrap(), b"abcdef\n");
+
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stdout_filter() {
+        let filter = StdoutFilter::default();
+        assert!(filter.check("anything\n"));
+
+        filter.set(Some((
+            Regex::new("^ERR").unwrap(),
+            StdoutFilterMode::OnlyMatching,
+        )));
+        assert!(filter.check("ERR: bad\n"));
+        assert!(!filter.check("info\n"));
+
+        filter.set(Some((
+            Regex::new("spam$").unwrap(),
+            StdoutFilterMode::DropMatching,
+        )));
+        assert!(!filter.check("spam\n"));
+        assert!(filter.check("spam eggs\n"));
+
+        filter.set(None);
+        assert!(filter.check("spam\n"));
+        assert_eq!(filter.stats(), (4, 2));
+    }
+}
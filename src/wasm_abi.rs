@@ -0,0 +1,622 @@
+use std::collections::HashMap;
+use std::str::from_utf8;
+
+use anyhow::Result as AnyResult;
+use godot::prelude::*;
+use tracing::{debug, instrument, warn};
+use wasmtime::{ExternType, ValType};
+
+use crate::wasm_instance::WasmInstance;
+use crate::{bail_with_site, site_context};
+
+/// Export returning ABI version `(major, minor)`.
+pub const ABI_VERSION_EXPORT: &str = "__abi_version";
+/// Export returning layout descriptor `(ptr, len)`.
+pub const ABI_LAYOUT_EXPORT: &str = "__abi_layout";
+/// Default export allocating guest memory, `malloc(size) -> ptr`.
+pub const ABI_MALLOC_EXPORT: &str = "malloc";
+/// Default export freeing guest memory, `free(ptr)`.
+pub const ABI_FREE_EXPORT: &str = "free";
+
+/// Field of guest struct layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Parses layout descriptor.
+///
+/// Each non-empty line is a field formatted as `name:offset:size`.
+pub fn parse_layout(s: &str) -> AnyResult<Vec<Field>> {
+    let mut ret = Vec::new();
+    for line in s.lines().map(str::trim).filter(|v| !v.is_empty()) {
+        let mut it = line.rsplitn(3, ':');
+        let (Some(size), Some(offset), Some(name)) = (it.next(), it.next(), it.next()) else {
+            bail_with_site!("Invalid layout field {line:?}");
+        };
+        ret.push(Field {
+            name: name.to_string(),
+            offset: site_context!(offset.parse())?,
+            size: site_context!(size.parse())?,
+        });
+    }
+    Ok(ret)
+}
+
+/// Difference between expected and actual layout.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LayoutDiff {
+    /// Fields not in expected layout.
+    pub added: Vec<String>,
+    /// Fields not in actual layout.
+    pub removed: Vec<String>,
+    /// Fields with different offset or size.
+    pub moved: Vec<String>,
+}
+
+impl LayoutDiff {
+    pub fn new(expected: &[Field], actual: &[Field]) -> Self {
+        let find = |v: &[Field], name: &str| v.iter().find(|f| f.name == name).cloned();
+        let mut ret = Self::default();
+
+        for f in expected {
+            match find(actual, &f.name) {
+                None => ret.removed.push(f.name.clone()),
+                Some(v) if v != *f => ret.moved.push(f.name.clone()),
+                Some(_) => (),
+            }
+        }
+        for f in actual {
+            if find(expected, &f.name).is_none() {
+                ret.added.push(f.name.clone());
+            }
+        }
+
+        ret
+    }
+
+    /// Added fields are compatible, as host does not read them.
+    pub fn is_compatible(&self) -> bool {
+        self.removed.is_empty() && self.moved.is_empty()
+    }
+}
+
+/// FNV-1a hash of layout descriptor. Stable across builds.
+pub fn layout_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbiStatus {
+    Match,
+    /// Same major version, different minor version.
+    MinorMismatch,
+    MajorMismatch,
+    /// Guest does not export version.
+    Missing,
+}
+
+impl AbiStatus {
+    pub fn new(expected: (u32, u32), actual: Option<(u32, u32)>) -> Self {
+        match actual {
+            None => Self::Missing,
+            Some((major, _)) if major != expected.0 => Self::MajorMismatch,
+            Some((_, minor)) if minor != expected.1 => Self::MinorMismatch,
+            Some(_) => Self::Match,
+        }
+    }
+
+    pub fn is_compatible(self) -> bool {
+        matches!(self, Self::Match | Self::MinorMismatch)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Match => "match",
+            Self::MinorMismatch => "minor_mismatch",
+            Self::MajorMismatch => "major_mismatch",
+            Self::Missing => "missing",
+        }
+    }
+}
+
+/// Calls export returning pair of `u32`. Returns `None` if export does not exist.
+///
+/// Export either returns the pair directly, or a pointer to it.
+fn call_pair(inst: &WasmInstance, name: &str) -> AnyResult<Option<(u32, u32)>> {
+    let ty = {
+        let m = inst.get_data()?.module.bind();
+        match site_context!(m.get_data()?.module.get_core())?.get_export(name) {
+            Some(ExternType::Func(f)) => f,
+            Some(_) => bail_with_site!("Export {name} is not a function"),
+            None => return Ok(None),
+        }
+    };
+    if ty.params().next().is_some() || !ty.results().all(|t| matches!(t, ValType::I32)) {
+        bail_with_site!("Export {name} has invalid signature {ty}");
+    }
+
+    let r = inst.call_export(StringName::from(name), VariantArray::new())?;
+    let get = |i: usize| -> AnyResult<u32> {
+        Ok(r.at(i).try_to::<i64>().map_err(|e| e.into_erased())? as u32)
+    };
+    let ret = match r.len() {
+        2 => (get(0)?, get(1)?),
+        1 => {
+            let p = get(0)? as usize;
+            inst.try_memory(|mem| match mem.get(p..p + 8) {
+                Some(s) => Ok((
+                    u32::from_le_bytes(s[..4].try_into().unwrap()),
+                    u32::from_le_bytes(s[4..].try_into().unwrap()),
+                )),
+                None => bail_with_site!("Index out of bound {}-{}", p, p + 8),
+            })?
+        }
+        _ => bail_with_site!("Export {name} has invalid signature {ty}"),
+    };
+    debug!(name, ?ret);
+    Ok(Some(ret))
+}
+
+/// Checks guest ABI against expected version and layout.
+#[instrument(skip(inst, expected))]
+pub fn check_abi(inst: &WasmInstance, expected: &Dictionary) -> AnyResult<Dictionary> {
+    let get = |k: &str| expected.get(k).filter(|v| !v.is_nil());
+    let Some(major) = get("major") else {
+        bail_with_site!("Expected ABI has no major version");
+    };
+    let major = major.try_to::<i64>().map_err(|e| e.into_erased())? as u32;
+    let minor = match get("minor") {
+        Some(v) => v.try_to::<i64>().map_err(|e| e.into_erased())? as u32,
+        None => 0,
+    };
+
+    let version = call_pair(inst, ABI_VERSION_EXPORT)?;
+    let status = AbiStatus::new((major, minor), version);
+    let mut compatible = status.is_compatible();
+
+    let mut ret = Dictionary::new();
+    ret.set("status", status.as_str());
+    match version {
+        Some((major, minor)) => ret.set("version", varray![major, minor]),
+        None => ret.set("version", Variant::nil()),
+    }
+
+    match call_pair(inst, ABI_LAYOUT_EXPORT)? {
+        Some((p, n)) => {
+            let (p, n) = (p as usize, n as usize);
+            let data = inst.try_memory(|mem| match mem.get(p..p + n) {
+                Some(s) => Ok(s.to_vec()),
+                None => bail_with_site!("Index out of bound {}-{}", p, p + n),
+            })?;
+
+            let mut layout = Dictionary::new();
+            layout.set("hash", layout_hash(&data) as i64);
+            if let Some(v) = get("layout") {
+                let expected = parse_layout(
+                    &v.try_to::<GString>()
+                        .map_err(|e| e.into_erased())?
+                        .to_string(),
+                )?;
+                let actual = parse_layout(site_context!(from_utf8(&data))?)?;
+                let diff = LayoutDiff::new(&expected, &actual);
+                compatible &= diff.is_compatible();
+
+                let f = |v: Vec<String>| {
+                    v.into_iter()
+                        .map(|v| GString::from(v.as_str()))
+                        .collect::<PackedStringArray>()
+                };
+                layout.set("added", f(diff.added));
+                layout.set("removed", f(diff.removed));
+                layout.set("moved", f(diff.moved));
+            }
+            ret.set("layout", layout);
+        }
+        None => ret.set("layout", Variant::nil()),
+    }
+
+    ret.set("compatible", compatible);
+    Ok(ret)
+}
+
+/// Guest allocator used by host, configured by `abi.malloc` and `abi.free`.
+#[derive(Debug, Default)]
+pub struct GuestAllocator {
+    malloc: Option<String>,
+    free: Option<String>,
+    /// Outstanding host-made allocations (pointer to size), if tracking is enabled.
+    tracked: Option<HashMap<u64, u64>>,
+}
+
+impl GuestAllocator {
+    pub fn new(malloc: Option<String>, free: Option<String>, track: bool) -> Self {
+        Self {
+            malloc,
+            free,
+            tracked: track.then(HashMap::new),
+        }
+    }
+
+    pub fn malloc_name(&self) -> &str {
+        self.malloc.as_deref().unwrap_or(ABI_MALLOC_EXPORT)
+    }
+
+    pub fn free_name(&self) -> &str {
+        self.free.as_deref().unwrap_or(ABI_FREE_EXPORT)
+    }
+
+    pub fn record_alloc(&mut self, ptr: u64, size: u64) {
+        if let Some(t) = &mut self.tracked {
+            t.insert(ptr, size);
+        }
+    }
+
+    /// Returns `false` if tracking is enabled and pointer is not allocated by host.
+    pub fn record_free(&mut self, ptr: u64) -> bool {
+        match &mut self.tracked {
+            Some(t) => t.remove(&ptr).is_some(),
+            None => true,
+        }
+    }
+
+    /// Returns `true` if `abi.free` is configured.
+    pub fn has_free(&self) -> bool {
+        self.free.is_some()
+    }
+
+    /// Number and total size of outstanding allocations, if tracking is enabled.
+    pub fn outstanding(&self) -> Option<(usize, u64)> {
+        self.tracked.as_ref().map(|t| (t.len(), t.values().sum()))
+    }
+}
+
+/// Gets allocator export and whether it uses 64-bit pointers.
+///
+/// Export must take `n` pointer-sized parameters and return `r` pointer-sized results.
+fn alloc_export(inst: &WasmInstance, name: &str, n: usize, r: usize) -> AnyResult<bool> {
+    let m = inst.get_data()?.module.bind();
+    let ty = match site_context!(m.get_data()?.module.get_core())?.get_export(name) {
+        Some(ExternType::Func(f)) => f,
+        Some(_) => bail_with_site!("Export {name} is not a function"),
+        None => bail_with_site!("Guest allocator export {name} does not exists"),
+    };
+    let is64 = matches!(ty.params().next(), Some(ValType::I64));
+    let check = |v: Vec<ValType>, c: usize| {
+        v.len() == c
+            && v.iter().all(|v| match v {
+                ValType::I64 => is64,
+                ValType::I32 => !is64,
+                _ => false,
+            })
+    };
+    if !check(ty.params().collect(), n) || !check(ty.results().collect(), r) {
+        bail_with_site!("Export {name} has invalid signature {ty}");
+    }
+    Ok(is64)
+}
+
+/// Converts pointer into argument of allocator export.
+fn ptr_arg(v: u64, is64: bool) -> AnyResult<i64> {
+    if is64 {
+        Ok(v as i64)
+    } else if let Ok(v) = u32::try_from(v) {
+        Ok(v as i32 as i64)
+    } else {
+        bail_with_site!("Value {v} is too large for 32-bit guest")
+    }
+}
+
+/// Allocates guest memory with guest `malloc` export.
+#[instrument(skip(inst), ret)]
+pub fn guest_alloc(inst: &WasmInstance, size: u64) -> AnyResult<u64> {
+    let name = inst
+        .get_data()?
+        .acquire_store(|_, store| store.data().guest_alloc.malloc_name().to_string());
+    let is64 = alloc_export(inst, &name, 1, 1)?;
+
+    let r = inst.call_export(StringName::from(&name), varray![ptr_arg(size, is64)?])?;
+    let v = r.at(0).try_to::<i64>().map_err(|e| e.into_erased())?;
+    let ptr = if is64 { v as u64 } else { v as u32 as u64 };
+    if ptr == 0 {
+        bail_with_site!("Guest allocator {name} failed to allocate {size} bytes");
+    }
+
+    inst.get_data()?
+        .acquire_store(|_, mut store| store.data_mut().guest_alloc.record_alloc(ptr, size));
+    Ok(ptr)
+}
+
+/// Calls guest `free` export without tracking.
+fn call_free(inst: &WasmInstance, ptr: u64) -> AnyResult<()> {
+    let name = inst
+        .get_data()?
+        .acquire_store(|_, store| store.data().guest_alloc.free_name().to_string());
+    let is64 = alloc_export(inst, &name, 1, 0)?;
+
+    inst.call_export(StringName::from(&name), varray![ptr_arg(ptr, is64)?])?;
+    Ok(())
+}
+
+/// Frees guest memory with guest `free` export.
+#[instrument(skip(inst))]
+pub fn guest_free(inst: &WasmInstance, ptr: u64) -> AnyResult<()> {
+    call_free(inst, ptr)?;
+
+    if !inst
+        .get_data()?
+        .acquire_store(|_, mut store| store.data_mut().guest_alloc.record_free(ptr))
+    {
+        warn!(ptr, "Freed pointer is not allocated by host");
+    }
+    Ok(())
+}
+
+/// Copies data into newly allocated guest memory.
+pub fn guest_alloc_bytes(inst: &WasmInstance, data: &[u8]) -> AnyResult<u64> {
+    let ptr = guest_alloc(inst, data.len() as u64)?;
+    let r = inst.try_memory(|mem| {
+        let i = ptr as usize;
+        match i.checked_add(data.len()).and_then(|e| mem.get_mut(i..e)) {
+            Some(s) => {
+                s.copy_from_slice(data);
+                Ok(())
+            }
+            None => bail_with_site!("Index out of bound {}-{}", i, i.saturating_add(data.len())),
+        }
+    });
+    if let Err(e) = r {
+        // Don't leak allocation, but original error is more important.
+        if let Err(e) = guest_free(inst, ptr) {
+            warn!(%e, "Cannot free allocation");
+        }
+        return Err(e);
+    }
+    Ok(ptr)
+}
+
+/// Convention of export returning buffer in guest memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnConvention {
+    /// UTF-8 string, as single `i64` with pointer in low half and length in high half.
+    Utf8PtrLenI64,
+    /// UTF-8 string, as two `i32` of pointer and length.
+    Utf8PtrLenPair,
+    /// Bytes, as two `i32` of pointer and length.
+    BytesPtrLenPair,
+}
+
+impl ReturnConvention {
+    pub fn from_name(s: &str) -> Option<Self> {
+        Some(match s {
+            "utf8_ptr_len_i64" => Self::Utf8PtrLenI64,
+            "utf8_ptr_len_pair" => Self::Utf8PtrLenPair,
+            "bytes_ptr_len_pair" => Self::BytesPtrLenPair,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Utf8PtrLenI64 => "utf8_ptr_len_i64",
+            Self::Utf8PtrLenPair => "utf8_ptr_len_pair",
+            Self::BytesPtrLenPair => "bytes_ptr_len_pair",
+        }
+    }
+
+    pub fn is_utf8(self) -> bool {
+        matches!(self, Self::Utf8PtrLenI64 | Self::Utf8PtrLenPair)
+    }
+
+    /// Unpacks pointer and length from return values.
+    pub fn unpack(self, ret: &[i64]) -> AnyResult<(u32, u32)> {
+        let i32_arg = |v: i64| match i32::try_from(v) {
+            Ok(v) => Ok(v as u32),
+            Err(_) => bail_with_site!("Value {v} is not i32 (convention {})", self.as_str()),
+        };
+        match (self, ret) {
+            (Self::Utf8PtrLenI64, &[v]) => Ok((v as u32, (v >> 32) as u32)),
+            (Self::Utf8PtrLenPair | Self::BytesPtrLenPair, &[p, n]) => {
+                Ok((i32_arg(p)?, i32_arg(n)?))
+            }
+            _ => bail_with_site!(
+                "Expected {} return values for convention {}, got {}",
+                if self == Self::Utf8PtrLenI64 { 1 } else { 2 },
+                self.as_str(),
+                ret.len(),
+            ),
+        }
+    }
+}
+
+/// Gets slice of guest memory, failing if out of bound.
+pub fn guest_slice(mem: &[u8], ptr: u32, len: u32) -> AnyResult<&[u8]> {
+    let (i, n) = (ptr as usize, len as usize);
+    match i.checked_add(n).and_then(|e| mem.get(i..e)) {
+        Some(s) => Ok(s),
+        None => bail_with_site!("Index out of bound {}-{}", i, i.saturating_add(n)),
+    }
+}
+
+/// Reads buffer returned by export and converts it by convention.
+///
+/// Buffer is freed with `abi.free` export if configured.
+#[instrument(skip(inst, ret))]
+pub fn convert_return(
+    inst: &WasmInstance,
+    conv: ReturnConvention,
+    ret: &VariantArray,
+) -> AnyResult<Variant> {
+    let ret = ret
+        .iter_shared()
+        .map(|v| v.try_to::<i64>().map_err(|e| e.into_erased()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (ptr, len) = conv.unpack(&ret)?;
+
+    let v = inst.try_memory(|mem| {
+        let s = guest_slice(mem, ptr, len)?;
+        Ok(if conv.is_utf8() {
+            site_context!(from_utf8(s))?.to_variant()
+        } else {
+            PackedByteArray::from(s).to_variant()
+        })
+    })?;
+
+    if inst
+        .get_data()?
+        .acquire_store(|_, store| store.data().guest_alloc.has_free())
+    {
+        call_free(inst, ptr as u64)?;
+    }
+    Ok(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_layout() {
+        let v = parse_layout("width:0:4\n\n  height:4:4  \nns:name:8:8\n").unwrap();
+        assert_eq!(
+            v,
+            [
+                Field {
+                    name: "width".into(),
+                    offset: 0,
+                    size: 4
+                },
+                Field {
+                    name: "height".into(),
+                    offset: 4,
+                    size: 4
+                },
+                Field {
+                    name: "ns:name".into(),
+                    offset: 8,
+                    size: 8
+                },
+            ]
+        );
+
+        assert!(parse_layout("width:0").is_err());
+        assert!(parse_layout("width:a:4").is_err());
+    }
+
+    #[test]
+    fn test_layout_diff() {
+        let expected = parse_layout("a:0:4\nb:4:4\nc:8:4\nd:12:4").unwrap();
+
+        let diff = LayoutDiff::new(&expected, &expected);
+        assert_eq!(diff, LayoutDiff::default());
+        assert!(diff.is_compatible());
+
+        let actual = parse_layout("a:0:4\nb:4:4\nc:8:4\nd:12:4\ne:16:4").unwrap();
+        let diff = LayoutDiff::new(&expected, &actual);
+        assert_eq!(diff.added, ["e"]);
+        assert!(diff.is_compatible());
+
+        let actual = parse_layout("a:0:4\nc:4:4\nd:8:8\ne:16:4").unwrap();
+        let diff = LayoutDiff::new(&expected, &actual);
+        assert_eq!(diff.added, ["e"]);
+        assert_eq!(diff.removed, ["b"]);
+        assert_eq!(diff.moved, ["c", "d"]);
+        assert!(!diff.is_compatible());
+    }
+
+    #[test]
+    fn test_abi_status() {
+        assert_eq!(AbiStatus::new((1, 2), Some((1, 2))), AbiStatus::Match);
+        assert_eq!(
+            AbiStatus::new((1, 2), Some((1, 3))),
+            AbiStatus::MinorMismatch
+        );
+        assert_eq!(
+            AbiStatus::new((1, 2), Some((1, 0))),
+            AbiStatus::MinorMismatch
+        );
+        assert_eq!(
+            AbiStatus::new((1, 2), Some((2, 2))),
+            AbiStatus::MajorMismatch
+        );
+        assert_eq!(AbiStatus::new((1, 2), None), AbiStatus::Missing);
+        assert!(AbiStatus::MinorMismatch.is_compatible());
+        assert!(!AbiStatus::MajorMismatch.is_compatible());
+        assert!(!AbiStatus::Missing.is_compatible());
+    }
+
+    #[test]
+    fn test_layout_hash() {
+        assert_eq!(layout_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(layout_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_guest_allocator() {
+        let a = GuestAllocator::default();
+        assert_eq!(a.malloc_name(), ABI_MALLOC_EXPORT);
+        assert_eq!(a.free_name(), ABI_FREE_EXPORT);
+        assert_eq!(a.outstanding(), None);
+
+        let mut a = GuestAllocator::new(Some("my_alloc".into()), Some("my_free".into()), true);
+        assert_eq!(a.malloc_name(), "my_alloc");
+        assert_eq!(a.free_name(), "my_free");
+        assert_eq!(a.outstanding(), Some((0, 0)));
+
+        a.record_alloc(16, 8);
+        a.record_alloc(64, 32);
+        assert_eq!(a.outstanding(), Some((2, 40)));
+        assert!(a.record_free(16));
+        assert_eq!(a.outstanding(), Some((1, 32)));
+        // Double free and guest-made pointers are detected.
+        assert!(!a.record_free(16));
+        assert!(!a.record_free(128));
+        assert!(a.record_free(64));
+        assert_eq!(a.outstanding(), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_ptr_arg() {
+        assert_eq!(ptr_arg(16, false).unwrap(), 16);
+        assert_eq!(ptr_arg(0xffff_fff0, false).unwrap(), -16);
+        assert!(ptr_arg(1 << 32, false).is_err());
+        assert_eq!(ptr_arg(1 << 32, true).unwrap(), 1 << 32);
+    }
+
+    #[test]
+    fn test_return_convention() {
+        for c in [
+            ReturnConvention::Utf8PtrLenI64,
+            ReturnConvention::Utf8PtrLenPair,
+            ReturnConvention::BytesPtrLenPair,
+        ] {
+            assert_eq!(ReturnConvention::from_name(c.as_str()), Some(c));
+        }
+        assert_eq!(ReturnConvention::from_name("utf8"), None);
+
+        let c = ReturnConvention::Utf8PtrLenI64;
+        assert_eq!(c.unpack(&[(5 << 32) | 16]).unwrap(), (16, 5));
+        assert_eq!(c.unpack(&[-1]).unwrap(), (u32::MAX, u32::MAX));
+        assert!(c.unpack(&[16, 5]).is_err());
+
+        let c = ReturnConvention::Utf8PtrLenPair;
+        assert_eq!(c.unpack(&[16, 5]).unwrap(), (16, 5));
+        assert_eq!(c.unpack(&[-16, 5]).unwrap(), (-16i32 as u32, 5));
+        assert!(c.unpack(&[(5 << 32) | 16]).is_err());
+        assert!(c.unpack(&[1 << 40, 5]).is_err());
+    }
+
+    #[test]
+    fn test_guest_slice() {
+        let mem = b"hello world";
+        assert_eq!(guest_slice(mem, 6, 5).unwrap(), b"world");
+        assert_eq!(guest_slice(mem, 11, 0).unwrap(), b"");
+        assert!(guest_slice(mem, 6, 6).is_err());
+        assert!(guest_slice(mem, 12, 0).is_err());
+        assert!(guest_slice(mem, u32::MAX, u32::MAX).is_err());
+    }
+}
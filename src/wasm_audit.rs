@@ -0,0 +1,150 @@
+use anyhow::Result as AnyResult;
+use godot::prelude::*;
+use tracing::instrument;
+
+#[cfg(feature = "godot-component")]
+use crate::godot_component::filter::{filter_from_config, partition_imports};
+use crate::godot_util::from_var_any;
+#[cfg(feature = "godot-component")]
+use crate::godot_util::variant_to_option;
+use crate::site_context;
+#[cfg(feature = "wasi")]
+use crate::wasi_ctx::{FsFileMount, FsMount, WasiContext};
+use crate::wasm_config::Config;
+#[cfg(feature = "godot-component")]
+use crate::wasm_engine::ComponentImport;
+use crate::wasm_engine::{ModuleType, WasmModule};
+
+/// Reports what module could access if instantiated with config, without instantiating it.
+///
+/// Config is parsed and resolved with the same functions used by instantiation.
+#[instrument(skip(config))]
+pub fn audit_permissions(module: &Gd<WasmModule>, config: &Variant) -> AnyResult<Dictionary> {
+    #[cfg_attr(not(feature = "wasi"), allow(unused_variables))]
+    let parsed: Config = if config.is_nil() {
+        Config::default()
+    } else {
+        site_context!(from_var_any(config))?
+    };
+    let m = module.bind();
+    let m = m.get_data()?;
+
+    let (imports, allowed, denied, network): (PackedStringArray, _, _, _) = match &m.module {
+        ModuleType::Core(v) => (
+            v.imports()
+                .map(|i| GString::from(format!("{}.{}", i.module(), i.name())))
+                .collect(),
+            PackedStringArray::new(),
+            PackedStringArray::new(),
+            false,
+        ),
+        #[cfg(feature = "component-model")]
+        ModuleType::Component(_) => {
+            let comp_imports = m.module.get_component_imports()?;
+
+            #[cfg(feature = "godot-component")]
+            let (allowed, denied, network) = {
+                let filter = match site_context!(variant_to_option::<Dictionary>(config.clone()))? {
+                    Some(d) => filter_from_config(&d).map_err(|e| e.into_erased())?,
+                    None => Default::default(),
+                };
+                let (allowed, denied) = partition_imports(&filter, &comp_imports);
+                let network = allowed
+                    .iter()
+                    .any(|v| v.module == "godot:global" && v.interface == "ip");
+                let f = |v: Vec<&ComponentImport>| {
+                    v.into_iter()
+                        .map(|v| GString::from(v.to_string()))
+                        .collect::<PackedStringArray>()
+                };
+                (f(allowed), f(denied), network)
+            };
+            #[cfg(not(feature = "godot-component"))]
+            let (allowed, denied, network) =
+                (PackedStringArray::new(), PackedStringArray::new(), false);
+
+            (
+                comp_imports
+                    .iter()
+                    .map(|v| GString::from(v.to_string()))
+                    .collect(),
+                allowed,
+                denied,
+                network,
+            )
+        }
+    };
+    let mut interfaces = Dictionary::new();
+    interfaces.set("allowed", allowed);
+    interfaces.set("denied", denied);
+
+    #[cfg(feature = "wasi")]
+    let (mounts, readonly, stdio) = if parsed.with_wasi {
+        wasi_access(&parsed)?
+    } else {
+        (VariantArray::new(), false, Dictionary::new())
+    };
+    #[cfg(not(feature = "wasi"))]
+    let (mounts, readonly, stdio) = (VariantArray::new(), false, Dictionary::new());
+    let mut filesystem = Dictionary::new();
+    filesystem.set("mounts", mounts);
+    filesystem.set("readonly", readonly);
+
+    let mut ret = Dictionary::new();
+    ret.set("imports", imports);
+    #[cfg(feature = "wasi")]
+    ret.set("wasi", parsed.with_wasi);
+    #[cfg(not(feature = "wasi"))]
+    ret.set("wasi", false);
+    ret.set("filesystem", filesystem);
+    ret.set("network", network);
+    ret.set("godot_interfaces", interfaces);
+    ret.set("stdio", stdio);
+    Ok(ret)
+}
+
+/// Returns filesystem mounts, readonly flag, and stdio of WASI context.
+#[cfg(feature = "wasi")]
+fn wasi_access(parsed: &Config) -> AnyResult<(VariantArray, bool, Dictionary)> {
+    let mut mounts = VariantArray::new();
+    let mut readonly = false;
+    if let Some(ctx) = &parsed.wasi_context {
+        let fs = WasiContext::resolve_fs_access(ctx, parsed)?;
+        readonly = fs.readonly;
+        for FsMount {
+            guest,
+            host,
+            case_insensitive,
+        } in fs.mounts
+        {
+            let mut d = Dictionary::new();
+            d.set("guest", guest.as_str());
+            match host {
+                Some(host) => d.set("host", &*host.to_string_lossy()),
+                None => d.set("host", Variant::nil()),
+            }
+            d.set("case_insensitive", case_insensitive);
+            mounts.push(&d.to_variant());
+        }
+        for FsFileMount {
+            guest,
+            host,
+            readonly,
+        } in fs.files
+        {
+            let mut d = Dictionary::new();
+            d.set("guest", guest.as_str());
+            d.set("host", &*host.to_string_lossy());
+            d.set("file", true);
+            d.set("readonly", readonly);
+            mounts.push(&d.to_variant());
+        }
+    }
+
+    let [stdin, stdout, stderr] = parsed.effective_stdio();
+    let mut stdio = Dictionary::new();
+    stdio.set("stdin", stdin);
+    stdio.set("stdout", stdout);
+    stdio.set("stderr", stderr);
+    Ok((mounts, readonly, stdio))
+}
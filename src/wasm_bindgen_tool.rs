@@ -0,0 +1,60 @@
+use anyhow::Result as AnyResult;
+use godot::classes::file_access::ModeFlags;
+use godot::classes::{Engine, FileAccess};
+use godot::prelude::*;
+use tracing::instrument;
+
+use crate::bail_with_site;
+use crate::wasm_engine::{ModuleType, WasmModule};
+use crate::wasm_schema::ComponentExports;
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init, tool)]
+/// Generates typed GDScript wrapper of component exports.
+///
+/// Only usable in editor.
+pub struct WasmBindgenTool {
+    base: Base<RefCounted>,
+}
+
+fn generate(module: &Gd<WasmModule>, out_path: &GString) -> AnyResult<()> {
+    if !Engine::singleton().is_editor_hint() {
+        bail_with_site!("Bindings can only be generated in editor");
+    }
+
+    let src = {
+        let m = module.bind();
+        let ModuleType::Component(comp) = &m.get_data()?.module else {
+            bail_with_site!("Module is not a component");
+        };
+        ComponentExports::new(comp).gdscript_bindings()
+    };
+
+    let Some(mut file) = FileAccess::open(out_path, ModeFlags::WRITE) else {
+        bail_with_site!(
+            "Cannot open {out_path} ({:?})",
+            FileAccess::get_open_error()
+        );
+    };
+    file.store_string(&GString::from(src));
+    file.close();
+    Ok(())
+}
+
+#[godot_api]
+impl WasmBindgenTool {
+    /// Generates GDScript wrapper of component exports and writes it into `out_path`.
+    ///
+    /// Returns `true` if succeed.
+    #[func]
+    #[instrument(ret)]
+    fn generate(module: Gd<WasmModule>, out_path: GString) -> bool {
+        match generate(&module, &out_path) {
+            Ok(()) => true,
+            Err(e) => {
+                godot_error!("{e:?}");
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result as AnyResult;
+use godot::prelude::*;
+use once_cell::sync::OnceCell;
+use parking_lot::{Condvar, Mutex};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use tracing::{debug, error, instrument, Level};
+
+use crate::godot_util::{is_main_thread, option_to_variant, SendSyncWrapper};
+use crate::wasm_instance::{CallToken, WasmInstance};
+use crate::{bail_with_site, site_context};
+
+/// Worker threads of all call groups.
+///
+/// It's separate from global pool, because calls might block waiting for main thread.
+static CALL_POOL: OnceCell<ThreadPool> = OnceCell::new();
+
+fn call_pool() -> AnyResult<&'static ThreadPool> {
+    CALL_POOL.get_or_try_init(|| {
+        site_context!(ThreadPoolBuilder::new()
+            .thread_name(|i| format!("wasm-call-group-{i}"))
+            .build())
+    })
+}
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init, tool)]
+/// Runs multiple WASM calls concurrently and waits for all of them.
+///
+/// Add calls with `add()`, then run them with `start()`. Results can be awaited
+/// with `wait()` (outside of main thread) or `finished` signal.
+pub struct WasmCallGroup {
+    base: Base<RefCounted>,
+    inner: Arc<GroupInner>,
+}
+
+impl Debug for WasmCallGroup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("WasmCallGroup")
+            .field("id", &self.base().instance_id())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Default)]
+struct GroupInner {
+    state: Mutex<GroupState>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct GroupState {
+    calls: Vec<GroupCall>,
+    started: bool,
+    cancelled: bool,
+    remaining: usize,
+}
+
+struct GroupCall {
+    instance: SendSyncWrapper<Gd<WasmInstance>>,
+    token: Arc<CallToken>,
+    name: SendSyncWrapper<StringName>,
+    args: SendSyncWrapper<VariantArray>,
+    status: CallStatus,
+    /// Call is cancelled by `cancel_call()`.
+    cancelled: bool,
+}
+
+enum CallStatus {
+    Pending,
+    Running,
+    Done(Result<SendSyncWrapper<VariantArray>, String>),
+}
+
+impl GroupState {
+    fn to_dictionary(&self) -> Dictionary {
+        let mut ret = Dictionary::new();
+        for (i, c) in self.calls.iter().enumerate() {
+            let CallStatus::Done(r) = &c.status else {
+                continue;
+            };
+            let mut d = Dictionary::new();
+            match r {
+                Ok(v) => {
+                    d.set("ok", true);
+                    d.set("result", (**v).clone());
+                }
+                Err(e) => {
+                    d.set("ok", false);
+                    d.set("error", e.as_str());
+                }
+            }
+            ret.set(i as i64, d);
+        }
+        ret
+    }
+}
+
+impl GroupInner {
+    /// Runs a single call of the group.
+    fn run(&self, i: usize) -> AnyResult<VariantArray> {
+        let (instance, name, args, token) = {
+            let mut guard = self.state.lock();
+            if guard.cancelled || guard.calls[i].cancelled {
+                bail_with_site!("Call is cancelled");
+            }
+            let call = &mut guard.calls[i];
+            call.status = CallStatus::Running;
+            (
+                (*call.instance).clone(),
+                (*call.name).clone(),
+                (*call.args).clone(),
+                call.token.clone(),
+            )
+        };
+        instance.bind().call_export_with(name, args, Some(token))
+    }
+
+    /// Records call result. Returns `true` if it's the last call to finish.
+    fn finish(&self, i: usize, r: AnyResult<VariantArray>) -> bool {
+        if let Err(e) = &r {
+            error!(i, "{e:?}");
+        }
+        let mut guard = self.state.lock();
+        guard.calls[i].status =
+            CallStatus::Done(r.map(SendSyncWrapper::new).map_err(|e| format!("{e:?}")));
+        guard.remaining -= 1;
+        let ret = guard.remaining == 0;
+        if ret {
+            self.cond.notify_all();
+        }
+        ret
+    }
+}
+
+impl WasmCallGroup {
+    fn emit_finished(&self) {
+        let results = self.inner.state.lock().to_dictionary();
+        Callable::from_object_method(&self.to_gd(), &StringName::from(c"emit_signal"))
+            .call_deferred(&[
+                StringName::from(c"finished").to_variant(),
+                results.to_variant(),
+            ]);
+    }
+
+    fn wrap_result<T>(r: AnyResult<T>) -> Option<T> {
+        match r {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!("{e:?}");
+                godot_error!("{e:?}");
+                None
+            }
+        }
+    }
+}
+
+#[godot_api]
+impl WasmCallGroup {
+    /// Emitted in main thread once all calls are finished.
+    ///
+    /// Results is a dictionary keyed by call ID, see `wait()`.
+    #[signal]
+    fn finished(results: Dictionary);
+
+    /// Adds a call to `name` export of `instance`.
+    ///
+    /// Returns call ID, or -1 if group has been started.
+    #[func]
+    #[instrument(level = Level::DEBUG, skip(args), fields(args.len = args.len()))]
+    fn add(&self, instance: Gd<WasmInstance>, name: StringName, args: VariantArray) -> i64 {
+        Self::wrap_result((|| -> AnyResult<_> {
+            let mut guard = self.inner.state.lock();
+            if guard.started {
+                bail_with_site!("Group is already started");
+            }
+            guard.calls.push(GroupCall {
+                instance: SendSyncWrapper::new(instance),
+                token: Arc::default(),
+                name: SendSyncWrapper::new(name),
+                args: SendSyncWrapper::new(args),
+                status: CallStatus::Pending,
+                cancelled: false,
+            });
+            Ok(guard.calls.len() as i64 - 1)
+        })())
+        .unwrap_or(-1)
+    }
+
+    /// Starts all calls in worker threads.
+    ///
+    /// Can only be called once.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn start(&self) -> bool {
+        Self::wrap_result((|| -> AnyResult<_> {
+            let pool = call_pool()?;
+            let n = {
+                let mut guard = self.inner.state.lock();
+                if guard.started {
+                    bail_with_site!("Group is already started");
+                }
+                guard.started = true;
+                guard.remaining = guard.calls.len();
+                guard.calls.len()
+            };
+            if n == 0 {
+                self.emit_finished();
+                return Ok(());
+            }
+
+            for i in 0..n {
+                let this = SendSyncWrapper::new(self.to_gd());
+                let inner = self.inner.clone();
+                pool.spawn(move || {
+                    let r = inner.run(i);
+                    if inner.finish(i, r) {
+                        debug!("Call group finished");
+                        this.bind().emit_finished();
+                    }
+                });
+            }
+            Ok(())
+        })())
+        .is_some()
+    }
+
+    /// Blocks until all calls are finished, or timeout (in milliseconds) is reached.
+    /// Negative timeout waits indefinitely.
+    ///
+    /// **⚠ Errors if called from main thread.**
+    ///
+    /// Returns a dictionary of call ID to:
+    /// - `ok` : `true` if call succeeds.
+    /// - `result` : Array of return values, if call succeeds.
+    /// - `error` : Error message, if call fails (eg. traps or is cancelled).
+    ///
+    /// Returns `null` if timed out.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn wait(&self, timeout_ms: i64) -> Variant {
+        option_to_variant(
+            Self::wrap_result((|| -> AnyResult<_> {
+                if is_main_thread() {
+                    bail_with_site!("Waiting call group in main thread is not allowed");
+                }
+                let deadline = u64::try_from(timeout_ms)
+                    .ok()
+                    .map(|t| Instant::now() + Duration::from_millis(t));
+
+                let mut guard = self.inner.state.lock();
+                if !guard.started {
+                    bail_with_site!("Group is not started");
+                }
+                while guard.remaining > 0 {
+                    match deadline {
+                        Some(t) => {
+                            if self.inner.cond.wait_until(&mut guard, t).timed_out() {
+                                return Ok(None);
+                            }
+                        }
+                        None => self.inner.cond.wait(&mut guard),
+                    }
+                }
+                Ok(Some(guard.to_dictionary()))
+            })())
+            .flatten(),
+        )
+    }
+
+    /// Returns `true` if all calls are finished.
+    #[func]
+    fn is_finished(&self) -> bool {
+        let guard = self.inner.state.lock();
+        guard.started && guard.remaining == 0
+    }
+
+    /// Cancels all outstanding calls.
+    ///
+    /// Calls that have not started fails immediately. Running calls are interrupted
+    /// at next epoch deadline check, requires `epoch.enable` config.
+    /// Other calls into the same instances are not affected.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn cancel(&self) {
+        let mut guard = self.inner.state.lock();
+        guard.cancelled = true;
+        for c in &guard.calls {
+            if let CallStatus::Running = c.status {
+                c.token.interrupt();
+            }
+        }
+    }
+
+    /// Cancels a single call by it's ID.
+    ///
+    /// Call that has not started fails immediately. If `cooperative` is `true`,
+    /// running call is requested to stop (guest polls it like `WasmInstance.request_cancel()`).
+    /// Otherwise it's interrupted at next epoch deadline check, like `cancel()`.
+    ///
+    /// Returns `false` if call does not exist or is already finished.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn cancel_call(&self, id: i64, cooperative: bool) -> bool {
+        let mut guard = self.inner.state.lock();
+        let Some(c) = usize::try_from(id)
+            .ok()
+            .and_then(|i| guard.calls.get_mut(i))
+        else {
+            return false;
+        };
+        match c.status {
+            CallStatus::Done(_) => return false,
+            CallStatus::Running if cooperative => c.token.cancel(),
+            CallStatus::Running => c.token.interrupt(),
+            CallStatus::Pending => (),
+        }
+        c.cancelled = true;
+        true
+    }
+}
@@ -1,15 +1,22 @@
 #[cfg(feature = "wasi")]
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter, Result as FmtResult};
+#[cfg(feature = "wasi")]
+use std::time::Duration;
 
 use godot::prelude::*;
 use tracing::warn;
+use wasmtime::SharedMemory;
 
+#[cfg(any(feature = "object-registry-compat", feature = "object-registry-extern"))]
+use crate::godot_util::duplicate_variant;
 use crate::godot_util::to_lower_inline_smol_str;
-#[cfg(feature = "epoch-timeout")]
 use crate::variant_dispatch;
 #[cfg(feature = "wasi")]
+use crate::warn_once;
+#[cfg(feature = "wasi")]
 use crate::wasi_ctx::WasiContext;
+use crate::wasm_memory::WasmMemory;
 #[cfg(feature = "epoch-timeout")]
 use crate::wasm_util::{EPOCH_DEADLINE, EPOCH_MULTIPLIER};
 
@@ -21,12 +28,24 @@ pub struct Config {
     pub epoch_autoreset: bool,
     #[cfg(feature = "epoch-timeout")]
     pub epoch_timeout: u64,
+    #[cfg(feature = "epoch-timeout")]
+    pub epoch_priority: EpochPriority,
 
     #[cfg(feature = "memory-limiter")]
     pub max_memory: Option<u64>,
     #[cfg(feature = "memory-limiter")]
     pub max_entries: Option<u64>,
 
+    pub memory_imports: Vec<(String, String, SharedMemory)>,
+
+    /// Objects whose methods are bound as host functions.
+    pub host_objects: Vec<HostObject>,
+
+    /// Instantiate without any imports, host state, or WASI.
+    pub sandbox_pure: bool,
+    /// Instantiate module dependencies on first call.
+    pub deps_lazy: bool,
+
     #[cfg(feature = "wasi")]
     pub with_wasi: bool,
     #[cfg(feature = "wasi")]
@@ -38,6 +57,10 @@ pub struct Config {
     #[cfg(feature = "wasi")]
     pub wasi_fs_readonly: bool,
     #[cfg(feature = "wasi")]
+    pub wasi_preopen_policy: PreopenPolicy,
+    #[cfg(feature = "wasi")]
+    pub wasi_tmp: bool,
+    #[cfg(feature = "wasi")]
     pub wasi_stdin: PipeBindingType,
     #[cfg(feature = "wasi")]
     pub wasi_stdout: PipeBindingType,
@@ -48,13 +71,44 @@ pub struct Config {
     #[cfg(feature = "wasi")]
     pub wasi_stderr_buffer: PipeBufferType,
     #[cfg(feature = "wasi")]
+    pub wasi_stdout_coalesce: Option<Duration>,
+    #[cfg(feature = "wasi")]
+    pub wasi_stderr_coalesce: Option<Duration>,
+    #[cfg(feature = "wasi")]
     pub wasi_stdin_data: Option<PackedByteArray>,
+    #[cfg(feature = "wasi")]
+    pub wasi_stdin_max_bytes: Option<usize>,
     //#[cfg(feature = "wasi")]
     //pub wasi_stdin_file: Option<String>,
 
     // Not worth cfg() it
     #[allow(dead_code)]
     pub extern_bind: ExternBindingType,
+    /// Skips functions with unconvertible reference types, instead of failing instantiation.
+    pub lenient_reference_types: bool,
+    pub copy_mode: CopyMode,
+    pub marshal_limits: MarshalLimits,
+
+    pub object_calls: ObjectCallPolicy,
+
+    pub max_reentrancy: Option<usize>,
+
+    /// Name of guest allocator exports.
+    pub abi_malloc: Option<String>,
+    pub abi_free: Option<String>,
+    /// Counts outstanding host-made guest allocations.
+    pub abi_track_allocations: bool,
+
+    /// Maximum number of calls queued until instance is ready, if enabled.
+    pub queue_calls: Option<usize>,
+
+    /// Seed of instance random generator. Seeded from OS entropy if unset.
+    pub rand_seed: Option<u64>,
+
+    #[cfg(any(feature = "wasi", feature = "godot-component"))]
+    pub record_path: Option<String>,
+    #[cfg(any(feature = "wasi", feature = "godot-component"))]
+    pub replay_path: Option<String>,
 }
 
 impl Debug for Config {
@@ -66,12 +120,26 @@ impl Debug for Config {
         f.field("epoch_autoreset", &self.epoch_autoreset);
         #[cfg(feature = "epoch-timeout")]
         f.field("epoch_timeout", &self.epoch_timeout);
+        #[cfg(feature = "epoch-timeout")]
+        f.field("epoch_priority", &self.epoch_priority);
 
         #[cfg(feature = "memory-limiter")]
         f.field("max_memory", &self.max_memory);
         #[cfg(feature = "memory-limiter")]
         f.field("max_entries", &self.max_entries);
 
+        f.field(
+            "memory_imports",
+            &self
+                .memory_imports
+                .iter()
+                .map(|(m, n, _)| (m, n))
+                .collect::<Vec<_>>(),
+        );
+        f.field("host_objects", &self.host_objects);
+        f.field("sandbox_pure", &self.sandbox_pure);
+        f.field("deps_lazy", &self.deps_lazy);
+
         #[cfg(feature = "wasi")]
         f.field("with_wasi", &self.with_wasi);
         #[cfg(feature = "wasi")]
@@ -81,6 +149,10 @@ impl Debug for Config {
         #[cfg(feature = "wasi")]
         f.field("wasi_fs_readonly", &self.wasi_fs_readonly);
         #[cfg(feature = "wasi")]
+        f.field("wasi_preopen_policy", &self.wasi_preopen_policy);
+        #[cfg(feature = "wasi")]
+        f.field("wasi_tmp", &self.wasi_tmp);
+        #[cfg(feature = "wasi")]
         f.field("wasi_stdin", &self.wasi_stdin);
         #[cfg(feature = "wasi")]
         f.field("wasi_stdout", &self.wasi_stdout);
@@ -91,12 +163,32 @@ impl Debug for Config {
         #[cfg(feature = "wasi")]
         f.field("wasi_stderr_buffer", &self.wasi_stderr_buffer);
         #[cfg(feature = "wasi")]
+        f.field("wasi_stdout_coalesce", &self.wasi_stdout_coalesce);
+        #[cfg(feature = "wasi")]
+        f.field("wasi_stderr_coalesce", &self.wasi_stderr_coalesce);
+        #[cfg(feature = "wasi")]
         f.field(
             "wasi_stdin_data_len",
             &self.wasi_stdin_data.as_ref().map(|v| v.len()),
         );
+        #[cfg(feature = "wasi")]
+        f.field("wasi_stdin_max_bytes", &self.wasi_stdin_max_bytes);
 
         f.field("extern_bind", &self.extern_bind);
+        f.field("lenient_reference_types", &self.lenient_reference_types);
+        f.field("copy_mode", &self.copy_mode);
+        f.field("marshal_limits", &self.marshal_limits);
+        f.field("object_calls", &self.object_calls);
+        f.field("max_reentrancy", &self.max_reentrancy);
+        f.field("abi_malloc", &self.abi_malloc);
+        f.field("abi_free", &self.abi_free);
+        f.field("abi_track_allocations", &self.abi_track_allocations);
+        f.field("queue_calls", &self.queue_calls);
+        f.field("rand_seed", &self.rand_seed);
+        #[cfg(any(feature = "wasi", feature = "godot-component"))]
+        f.field("record_path", &self.record_path);
+        #[cfg(any(feature = "wasi", feature = "godot-component"))]
+        f.field("replay_path", &self.replay_path);
         f.finish_non_exhaustive()
     }
 }
@@ -129,6 +221,91 @@ fn compute_epoch(v: Option<Variant>) -> Result<u64, ConvertError> {
     .max(1))
 }
 
+/// Parses random seed, either `int` or `String` of unsigned integer (decimal or `0x` hexadecimal).
+///
+/// Used by every config that seeds a random generator.
+pub fn get_seed(v: Option<Variant>) -> Result<Option<u64>, ConvertError> {
+    let Some(v) = v else { return Ok(None) };
+    Ok(Some(variant_dispatch!(v {
+        NIL => return Ok(None),
+        INT => v as u64,
+        STRING => {
+            let s = v.to_string();
+            let r = match s.strip_prefix("0x") {
+                Some(h) => u64::from_str_radix(h, 16),
+                None => s.parse(),
+            };
+            match r {
+                Ok(v) => v,
+                Err(_) => return Err(ConvertError::with_error_value("Invalid seed", s)),
+            }
+        }
+        _ => return Err(ConvertError::with_error_value("Unknown value", v)),
+    })))
+}
+
+fn get_memory_imports(
+    v: Option<Variant>,
+) -> Result<Vec<(String, String, SharedMemory)>, ConvertError> {
+    let v = match v {
+        Some(v) => v.try_to::<Dictionary>()?,
+        None => return Ok(Vec::new()),
+    };
+    let mut ret = Vec::with_capacity(v.len());
+    for (k, v) in v.iter_shared() {
+        let k = k.try_to::<String>()?;
+        let Some((module, name)) = k.rsplit_once('.') else {
+            return Err(ConvertError::with_error_value(
+                "Import name must be module.name",
+                k,
+            ));
+        };
+        let Ok(mem) = v.try_to::<Gd<WasmMemory>>()?.bind().get_memory().cloned() else {
+            return Err(ConvertError::with_error_value("Uninitialized memory", v));
+        };
+        ret.push((module.to_string(), name.to_string(), mem));
+    }
+    Ok(ret)
+}
+
+fn get_host_objects(v: Option<Variant>) -> Result<Vec<HostObject>, ConvertError> {
+    let v = match v {
+        Some(v) => v.try_to::<Dictionary>()?,
+        None => return Ok(Vec::new()),
+    };
+    let mut ret = Vec::with_capacity(v.len());
+    for (k, v) in v.iter_shared() {
+        let namespace = k.try_to::<String>()?;
+        let (object, methods) = match v.try_to::<Dictionary>() {
+            Ok(d) => {
+                let Some(object) = d.get("object") else {
+                    return Err(ConvertError::with_error_value(
+                        "Key \"object\" does not exist",
+                        d,
+                    ));
+                };
+                let methods = match d.get("methods") {
+                    Some(m) => Some(
+                        m.try_to::<VariantArray>()?
+                            .iter_shared()
+                            .map(|v| v.try_to::<String>())
+                            .collect::<Result<_, _>>()?,
+                    ),
+                    None => None,
+                };
+                (object.try_to()?, methods)
+            }
+            Err(_) => (v.try_to()?, None),
+        };
+        ret.push(HostObject {
+            namespace,
+            object,
+            methods,
+        });
+    }
+    Ok(ret)
+}
+
 #[cfg(feature = "wasi")]
 fn get_wasi_args(v: Option<Variant>) -> Result<Vec<String>, ConvertError> {
     let v = match v {
@@ -156,6 +333,25 @@ fn get_wasi_envs(v: Option<Variant>) -> Result<HashMap<String, String>, ConvertE
 }
 
 impl Config {
+    /// Effective binding of stdin, stdout, and stderr when building WASI context.
+    ///
+    /// Stdin can only be bound to instance, and context binding without context is unbound.
+    #[cfg(feature = "wasi")]
+    pub fn effective_stdio(&self) -> [PipeBindingType; 3] {
+        let f = |v| match v {
+            PipeBindingType::Context if self.wasi_context.is_none() => PipeBindingType::Unbound,
+            v => v,
+        };
+        [
+            match self.wasi_stdin {
+                PipeBindingType::Instance => PipeBindingType::Instance,
+                _ => PipeBindingType::Unbound,
+            },
+            f(self.wasi_stdout),
+            f(self.wasi_stderr),
+        ]
+    }
+
     fn convert(dict: Dictionary) -> Result<Self, ConvertError> {
         Ok(Self {
             #[cfg(feature = "epoch-timeout")]
@@ -168,6 +364,8 @@ impl Config {
                 dict.get("epoch.timeout")
                     .or_else(|| dict.get("engine.epoch_timeout")),
             )?,
+            #[cfg(feature = "epoch-timeout")]
+            epoch_priority: get_field(&dict, ["epoch.priority"])?.unwrap_or_default(),
 
             #[cfg(feature = "memory-limiter")]
             max_memory: get_field::<i64>(&dict, ["memory.maxGrowBytes", "engine.max_memory"])?
@@ -176,6 +374,15 @@ impl Config {
             max_entries: get_field::<i64>(&dict, ["table.maxGrowEntries", "engine.max_entries"])?
                 .map(|v| v as _),
 
+            memory_imports: get_memory_imports(dict.get("memory.imports"))?,
+
+            host_objects: get_host_objects(
+                dict.get("host.objects").or_else(|| dict.get("host_object")),
+            )?,
+
+            sandbox_pure: get_field(&dict, ["sandbox.pure"])?.unwrap_or_default(),
+            deps_lazy: get_field(&dict, ["deps.lazy"])?.unwrap_or_default(),
+
             #[cfg(feature = "wasi")]
             with_wasi: get_field(&dict, ["wasi.enable", "engine.use_wasi"])?.unwrap_or_default(),
             #[cfg(feature = "wasi")]
@@ -188,11 +395,22 @@ impl Config {
             wasi_fs_readonly: get_field(&dict, ["wasi.fsReadonly", "wasi.fs_readonly"])?
                 .unwrap_or_default(),
             #[cfg(feature = "wasi")]
+            wasi_preopen_policy: get_field(&dict, ["wasi.preopenPolicy", "wasi.preopen_policy"])?
+                .unwrap_or_default(),
+            #[cfg(feature = "wasi")]
+            wasi_tmp: get_field(&dict, ["wasi.tmp"])?.unwrap_or(true),
+            #[cfg(feature = "wasi")]
             wasi_stdin: get_field::<PipeBindingType>(&dict, ["wasi.stdin.bindMode", "wasi.stdin"])?
                 .inspect(|&v| {
-                    if let PipeBindingType::Bypass | PipeBindingType::Context = v {
+                    if let PipeBindingType::Bypass
+                    | PipeBindingType::Context
+                    | PipeBindingType::GodotConsole = v
+                    {
                         warn!(binding = ?v, "Stdin binding type is unsupported.");
-                        godot_warn!("Stdin binding type {v:?} is unsupported.");
+                        warn_once!(
+                            format!("stdin_binding.{v:?}"),
+                            "Stdin binding type {v:?} is unsupported."
+                        );
                     }
                 })
                 .unwrap_or(PipeBindingType::Unbound),
@@ -209,11 +427,74 @@ impl Config {
             wasi_stderr_buffer: get_field(&dict, ["wasi.stderr.bufferMode", "wasi.stderr_buffer"])?
                 .unwrap_or_default(),
             #[cfg(feature = "wasi")]
+            wasi_stdout_coalesce: get_field::<i64>(&dict, ["wasi.stdout.coalesceMs"])?
+                .filter(|&v| v > 0)
+                .map(|v| Duration::from_millis(v as _)),
+            #[cfg(feature = "wasi")]
+            wasi_stderr_coalesce: get_field::<i64>(&dict, ["wasi.stderr.coalesceMs"])?
+                .filter(|&v| v > 0)
+                .map(|v| Duration::from_millis(v as _)),
+            #[cfg(feature = "wasi")]
             wasi_stdin_data: get_field(&dict, ["wasi.stdin.inputData", "wasi.stdin_data"])?,
+            #[cfg(feature = "wasi")]
+            wasi_stdin_max_bytes: get_field::<i64>(
+                &dict,
+                ["wasi.stdin.maxBytes", "stdio.stdin_max_bytes"],
+            )?
+            .filter(|&v| v > 0)
+            .map(|v| v as _),
             //#[cfg(feature = "wasi")]
             //wasi_stdin_file: get_field(&dict, ["wasi.stdin.inputFile", "wasi.stdin_file"])?,
             extern_bind: get_field(&dict, ["extern.bindMode", "godot.extern_binding"])?
                 .unwrap_or_default(),
+            lenient_reference_types: get_field(
+                &dict,
+                ["extern.lenientReferenceTypes", "lenient_reference_types"],
+            )?
+            .unwrap_or_default(),
+
+            copy_mode: get_field(&dict, ["marshal.copyMode", "marshal.copy_mode"])?
+                .unwrap_or_default(),
+            marshal_limits: MarshalLimits {
+                max_arg_bytes: get_field::<i64>(
+                    &dict,
+                    ["marshal.maxArgBytes", "marshal.max_arg_bytes"],
+                )?
+                .map_or(MARSHAL_MAX_BYTES, |v| v.max(0) as _),
+                max_ret_bytes: get_field::<i64>(
+                    &dict,
+                    ["marshal.maxRetBytes", "marshal.max_ret_bytes"],
+                )?
+                .map_or(MARSHAL_MAX_BYTES, |v| v.max(0) as _),
+            },
+
+            object_calls: get_field(&dict, ["threading.objectCalls", "threading.object_calls"])?
+                .unwrap_or_default(),
+
+            max_reentrancy: get_field::<i64>(&dict, ["reentrancy.maxDepth", "max_reentrancy"])?
+                .map(|v| v.max(1) as _),
+
+            abi_malloc: get_field(&dict, ["abi.malloc"])?,
+            abi_free: get_field(&dict, ["abi.free"])?,
+            abi_track_allocations: get_field(&dict, ["abi.trackAllocations"])?.unwrap_or_default(),
+
+            queue_calls: if get_field(&dict, ["queueCalls.untilReady", "queue_calls_until_ready"])?
+                .unwrap_or(false)
+            {
+                Some(
+                    get_field::<i64>(&dict, ["queueCalls.maxSize", "queue_calls_max_size"])?
+                        .map_or(DEFAULT_QUEUE_CALLS, |v| v.max(0) as _),
+                )
+            } else {
+                None
+            },
+
+            rand_seed: get_seed(dict.get("rand.seed"))?,
+
+            #[cfg(any(feature = "wasi", feature = "godot-component"))]
+            record_path: get_field(&dict, ["record.path"])?,
+            #[cfg(any(feature = "wasi", feature = "godot-component"))]
+            replay_path: get_field(&dict, ["replay.path"])?,
         })
     }
 }
@@ -287,6 +568,176 @@ impl ToGodot for ExternBindingType {
     }
 }
 
+/// How container values are passed into guest.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CopyMode {
+    /// Pass reference, host mutation is visible to guest.
+    #[default]
+    Reference,
+    /// Deep copy containers.
+    Copy,
+    /// Shallow copy containers. Nested containers are still shared.
+    ///
+    /// Arrays and dictionaries are copied eagerly, packed arrays share storage until written.
+    Shallow,
+}
+
+impl CopyMode {
+    /// Gets mode from config value. Name must be lowercase.
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "" | "reference" => Self::Reference,
+            "copy" => Self::Copy,
+            "shallow" | "copy_on_write" | "cow" => Self::Shallow,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Reference => "reference",
+            Self::Copy => "copy",
+            Self::Shallow => "shallow",
+        }
+    }
+
+    #[cfg(any(feature = "object-registry-compat", feature = "object-registry-extern"))]
+    pub fn apply(self, v: Variant) -> Variant {
+        match self {
+            Self::Reference => v,
+            Self::Copy => duplicate_variant(&v, true),
+            Self::Shallow => duplicate_variant(&v, false),
+        }
+    }
+}
+
+impl GodotConvert for CopyMode {
+    type Via = GString;
+}
+
+impl FromGodot for CopyMode {
+    fn try_from_godot(via: Self::Via) -> Result<Self, ConvertError> {
+        match to_lower_inline_smol_str(via.chars()).and_then(|v| Self::from_name(&v)) {
+            Some(v) => Ok(v),
+            None => Err(ConvertError::with_error_value("Unknown value", via)),
+        }
+    }
+}
+
+impl ToGodot for CopyMode {
+    type ToVia<'a> = Self::Via;
+
+    fn to_godot(&self) -> Self::ToVia<'_> {
+        self.name().into()
+    }
+}
+
+/// Object whose methods are bound as host functions of a namespace.
+#[derive(Clone, Debug)]
+pub struct HostObject {
+    pub namespace: String,
+    pub object: Gd<Object>,
+    /// Methods allowed to be imported. If `None`, every method with signature is allowed.
+    pub methods: Option<Vec<String>>,
+}
+
+/// Default maximum size of marshaled data.
+pub const MARSHAL_MAX_BYTES: usize = 64 << 20;
+
+/// Default maximum number of calls queued until instance is ready.
+pub const DEFAULT_QUEUE_CALLS: usize = 64;
+
+/// Maximum size (in bytes) of data passed between host and guest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MarshalLimits {
+    /// Limit of data going into guest.
+    pub max_arg_bytes: usize,
+    /// Limit of data coming out of guest.
+    pub max_ret_bytes: usize,
+}
+
+impl Default for MarshalLimits {
+    fn default() -> Self {
+        Self {
+            max_arg_bytes: MARSHAL_MAX_BYTES,
+            max_ret_bytes: MARSHAL_MAX_BYTES,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ObjectCallPolicy {
+    Defer,
+    #[default]
+    Error,
+}
+
+impl GodotConvert for ObjectCallPolicy {
+    type Via = GString;
+}
+
+impl FromGodot for ObjectCallPolicy {
+    fn try_from_godot(via: Self::Via) -> Result<Self, ConvertError> {
+        Ok(match to_lower_inline_smol_str(via.chars()).as_deref() {
+            Some("defer") => Self::Defer,
+            Some("" | "error") => Self::Error,
+            _ => return Err(ConvertError::with_error_value("Unknown value", via)),
+        })
+    }
+}
+
+impl ToGodot for ObjectCallPolicy {
+    type ToVia<'a> = Self::Via;
+
+    fn to_godot(&self) -> Self::ToVia<'_> {
+        match self {
+            Self::Defer => "defer",
+            Self::Error => "error",
+        }
+        .into()
+    }
+}
+
+#[cfg(feature = "epoch-timeout")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum EpochPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[cfg(feature = "epoch-timeout")]
+impl GodotConvert for EpochPriority {
+    type Via = GString;
+}
+
+#[cfg(feature = "epoch-timeout")]
+impl FromGodot for EpochPriority {
+    fn try_from_godot(via: Self::Via) -> Result<Self, ConvertError> {
+        Ok(match to_lower_inline_smol_str(via.chars()).as_deref() {
+            Some("low") => Self::Low,
+            Some("" | "normal") => Self::Normal,
+            Some("high") => Self::High,
+            _ => return Err(ConvertError::with_error_value("Unknown value", via)),
+        })
+    }
+}
+
+#[cfg(feature = "epoch-timeout")]
+impl ToGodot for EpochPriority {
+    type ToVia<'a> = Self::Via;
+
+    fn to_godot(&self) -> Self::ToVia<'_> {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+        }
+        .into()
+    }
+}
+
 #[cfg(feature = "wasi")]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[non_exhaustive]
@@ -295,6 +746,7 @@ pub enum PipeBindingType {
     Bypass,
     Instance,
     Context,
+    GodotConsole,
 }
 
 #[cfg(feature = "wasi")]
@@ -317,6 +769,7 @@ impl FromGodot for PipeBindingType {
             Some("bypass") => Self::Bypass,
             Some("instance") => Self::Instance,
             Some("context") => Self::Context,
+            Some("godot_console") => Self::GodotConsole,
             _ => return Err(ConvertError::with_error_value("Unknown value", via)),
         })
     }
@@ -332,6 +785,147 @@ impl ToGodot for PipeBindingType {
             Self::Bypass => "bypass",
             Self::Instance => "instance",
             Self::Context => "context",
+            Self::GodotConsole => "godot_console",
+        }
+        .into()
+    }
+}
+
+/// Module compilation strategy.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Default)]
+pub enum CompileStrategy {
+    /// Winch for small modules, Cranelift otherwise.
+    Auto,
+    /// Optimizing compiler.
+    #[default]
+    Cranelift,
+    /// Baseline compiler. Compiles much faster, but runs slower.
+    Winch,
+}
+
+impl CompileStrategy {
+    /// Maximum module size (in bytes) to be compiled with Winch by `Auto` strategy.
+    pub const WINCH_AUTO_MAX_SIZE: usize = 1 << 20;
+
+    /// Resolves `Auto` strategy by module size.
+    pub fn resolve(self, size: usize) -> Self {
+        match self {
+            Self::Auto if size <= Self::WINCH_AUTO_MAX_SIZE => Self::Winch,
+            Self::Auto => Self::Cranelift,
+            v => v,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Cranelift => "cranelift",
+            Self::Winch => "winch",
+        }
+    }
+}
+
+impl GodotConvert for CompileStrategy {
+    type Via = GString;
+}
+
+impl FromGodot for CompileStrategy {
+    fn try_from_godot(via: Self::Via) -> Result<Self, ConvertError> {
+        Ok(match to_lower_inline_smol_str(via.chars()).as_deref() {
+            Some("auto") => Self::Auto,
+            Some("" | "cranelift") => Self::Cranelift,
+            Some("winch") => Self::Winch,
+            _ => return Err(ConvertError::with_error_value("Unknown value", via)),
+        })
+    }
+}
+
+impl ToGodot for CompileStrategy {
+    type ToVia<'a> = Self::Via;
+
+    fn to_godot(&self) -> Self::ToVia<'_> {
+        self.name().into()
+    }
+}
+
+/// How compiled code is executed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Default)]
+pub enum ExecutionBackend {
+    /// Native code.
+    #[default]
+    Jit,
+    /// Pulley interpreter bytecode. Used where executable memory is forbidden.
+    Pulley,
+}
+
+impl GodotConvert for ExecutionBackend {
+    type Via = GString;
+}
+
+impl FromGodot for ExecutionBackend {
+    fn try_from_godot(via: Self::Via) -> Result<Self, ConvertError> {
+        Ok(match to_lower_inline_smol_str(via.chars()).as_deref() {
+            Some("" | "jit") => Self::Jit,
+            Some("pulley") => Self::Pulley,
+            _ => return Err(ConvertError::with_error_value("Unknown value", via)),
+        })
+    }
+}
+
+impl ToGodot for ExecutionBackend {
+    type ToVia<'a> = Self::Via;
+
+    fn to_godot(&self) -> Self::ToVia<'_> {
+        match self {
+            Self::Jit => "jit",
+            Self::Pulley => "pulley",
+        }
+        .into()
+    }
+}
+
+#[cfg(feature = "wasi")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum PreopenPolicy {
+    #[default]
+    Strict,
+    SkipMissing,
+}
+
+#[cfg(feature = "wasi")]
+impl From<PreopenPolicy> for wasi_isolated_fs::context::PreopenPolicy {
+    fn from(v: PreopenPolicy) -> Self {
+        match v {
+            PreopenPolicy::Strict => Self::Strict,
+            PreopenPolicy::SkipMissing => Self::SkipMissing,
+        }
+    }
+}
+
+#[cfg(feature = "wasi")]
+impl GodotConvert for PreopenPolicy {
+    type Via = GString;
+}
+
+#[cfg(feature = "wasi")]
+impl FromGodot for PreopenPolicy {
+    fn try_from_godot(via: Self::Via) -> Result<Self, ConvertError> {
+        Ok(match to_lower_inline_smol_str(via.chars()).as_deref() {
+            Some("" | "strict") => Self::Strict,
+            Some("skip_missing" | "skip-missing") => Self::SkipMissing,
+            _ => return Err(ConvertError::with_error_value("Unknown value", via)),
+        })
+    }
+}
+
+#[cfg(feature = "wasi")]
+impl ToGodot for PreopenPolicy {
+    type ToVia<'a> = Self::Via;
+
+    fn to_godot(&self) -> Self::ToVia<'_> {
+        match self {
+            Self::Strict => "strict",
+            Self::SkipMissing => "skip_missing",
         }
         .into()
     }
@@ -385,3 +979,22 @@ impl ToGodot for PipeBufferType {
         .into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_mode() {
+        assert_eq!(CopyMode::from_name(""), Some(CopyMode::default()));
+        for v in [CopyMode::Reference, CopyMode::Copy, CopyMode::Shallow] {
+            assert_eq!(CopyMode::from_name(v.name()), Some(v));
+        }
+        assert_eq!(
+            CopyMode::from_name("copy_on_write"),
+            Some(CopyMode::Shallow)
+        );
+        assert_eq!(CopyMode::from_name("cow"), Some(CopyMode::Shallow));
+        assert_eq!(CopyMode::from_name("deep"), None);
+    }
+}
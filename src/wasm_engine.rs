@@ -1,26 +1,48 @@
+use std::any::{Any, TypeId};
 use std::collections::HashMap;
 use std::error::Error;
+use std::ffi::CStr;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
-use std::path::PathBuf;
+use std::hash::{DefaultHasher, Hash, Hasher};
 #[cfg(feature = "epoch-timeout")]
+use std::sync::atomic::AtomicUsize;
+#[cfg(any(feature = "epoch-timeout", feature = "memory-limiter"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
 use std::{thread, time};
 
 use anyhow::{bail, Result as AnyResult};
 use cfg_if::cfg_if;
-use godot::classes::FileAccess;
+use godot::classes::{Engine as GodotEngine, FileAccess, ProjectSettings};
 use godot::prelude::*;
-use once_cell::sync::OnceCell;
-use parking_lot::RwLock;
-use tracing::{debug, debug_span, error, info, info_span, instrument, trace, Level};
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::{Condvar, Mutex, RwLock};
+use tracing::{debug, debug_span, error, info, info_span, instrument, trace, warn, Level};
+#[cfg(feature = "component-model")]
+use wasmtime::component::types::ComponentItem;
 #[cfg(feature = "component-model")]
 use wasmtime::component::Component;
-use wasmtime::{Config, Engine, ExternType, Module, Precompiled, ResourcesRequired};
+use wasmtime::{
+    Config, Engine, ExternType, InstanceAllocationStrategy, Module, Precompiled, ResourcesRequired,
+};
 
-use crate::godot_util::{from_var_any, variant_to_option, PhantomProperty};
-use crate::wasm_instance::WasmInstance;
-use crate::wasm_util::from_signature;
+#[cfg(feature = "memory-limiter")]
+use crate::godot_util::emit_deferred;
+use crate::godot_util::{from_var_any, variant_to_option, PhantomProperty, SendSyncWrapper};
+#[cfg(feature = "epoch-timeout")]
+use crate::wasm_config::EpochPriority;
+use crate::wasm_config::{CompileStrategy, ExecutionBackend};
+use crate::wasm_engine_config::EngineInitConfig;
+use crate::wasm_instance::{ActivityTracker, WasmInstance};
+use crate::wasm_policy::{check_module, freeze_policy, get_policy, set_policy};
+#[cfg(feature = "component-model")]
+use crate::wasm_schema::ComponentExports;
+use crate::wasm_sections::{
+    read_custom_sections, CustomSection, DEFAULT_MAX_BYTES as DEFAULT_SECTION_MAX_BYTES,
+};
+use crate::wasm_util::{self, from_signature, unsupported_ref_type};
 #[cfg(feature = "epoch-timeout")]
-use crate::wasm_util::EPOCH_INTERVAL;
+use crate::wasm_util::{EPOCH_INTERVAL, EPOCH_MULTIPLIER};
 use crate::{bail_with_site, display_option, site_context, variant_dispatch};
 
 cfg_if! {
@@ -32,6 +54,82 @@ cfg_if! {
 }
 
 static ENGINE: RwLock<Option<EngineData>> = RwLock::new(None);
+/// Engine with Winch compiler. `None` if Winch is unsupported.
+///
+/// It's epoch is incremented in lockstep with main engine.
+static WINCH_ENGINE: RwLock<Option<Engine>> = RwLock::new(None);
+
+const SETTING_COMPILE_STRATEGY: &str = "godot_wasm/compile/strategy";
+
+/// Engine-wide compile strategy. Can be overriden per module.
+static COMPILE_STRATEGY: RwLock<CompileStrategy> = RwLock::new(CompileStrategy::Cranelift);
+
+/// Backend of main engine.
+static EXECUTION_BACKEND: RwLock<ExecutionBackend> = RwLock::new(ExecutionBackend::Jit);
+
+/// Effective engine configuration. `None` if engine is uninitialized.
+static ENGINE_CONFIG: RwLock<Option<EngineInitConfig>> = RwLock::new(None);
+
+/// Pulley target matching host pointer width.
+#[cfg(feature = "pulley")]
+const PULLEY_TARGET: &str = if cfg!(target_pointer_width = "64") {
+    "pulley64"
+} else {
+    "pulley32"
+};
+
+/// Mirrors engine epoch, since wasmtime does not expose it.
+#[cfg(feature = "epoch-timeout")]
+static EPOCH_COUNTER: AtomicU64 = AtomicU64::new(0);
+/// Number of running calls, indexed by priority.
+#[cfg(feature = "epoch-timeout")]
+static ACTIVE_CALLS: [AtomicUsize; 3] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// Demand state of epoch thread.
+#[cfg(feature = "epoch-timeout")]
+struct EpochDemand {
+    /// Number of running calls with armed deadline.
+    armed: usize,
+    /// `true` if epoch thread is waiting for a deadline.
+    parked: bool,
+    /// Number of times epoch thread is parked.
+    park_count: u64,
+}
+
+#[cfg(feature = "epoch-timeout")]
+static EPOCH_DEMAND: Mutex<EpochDemand> = Mutex::new(EpochDemand {
+    armed: 0,
+    parked: false,
+    park_count: 0,
+});
+/// Notified when first deadline is armed, or engine is deinitialized.
+#[cfg(feature = "epoch-timeout")]
+static EPOCH_WAKE: Condvar = Condvar::new();
+
+/// Interval between idle reaper scans.
+const IDLE_REAPER_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+struct IdleEntry {
+    activity: Weak<ActivityTracker>,
+    timeout: time::Duration,
+    /// Activity timestamp of last emitted request.
+    notified: Option<u64>,
+    callable: SendSyncWrapper<Callable>,
+}
+
+struct IdleReaper {
+    entries: Vec<IdleEntry>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+static IDLE_REAPER: Mutex<IdleReaper> = Mutex::new(IdleReaper {
+    entries: Vec::new(),
+    handle: None,
+});
 
 #[instrument(level = Level::TRACE, err)]
 pub fn get_engine() -> Result<Engine, EngineUninitError> {
@@ -52,46 +150,235 @@ pub fn get_engine() -> Result<Engine, EngineUninitError> {
     ret.ok_or(EngineUninitError)
 }
 
+/// Returns `true` if `engine` is the main engine (and not Winch engine).
+pub fn is_main_engine(engine: &Engine) -> bool {
+    get_engine().is_ok_and(|e| Engine::same(&e, engine))
+}
+
+/// Returns `true` if engine is configured with fuel metering.
+pub fn is_fuel_enabled() -> bool {
+    ENGINE_CONFIG.read().is_some_and(|c| c.consume_fuel)
+}
+
+/// Gets idle time after which instances are requested to unload, if enabled.
+pub fn idle_unload_timeout() -> Option<time::Duration> {
+    ENGINE_CONFIG
+        .read()
+        .and_then(|c| c.idle_unload_seconds)
+        .map(|v| time::Duration::from_secs(v as u64))
+}
+
+/// Gets Winch engine, if supported.
+pub fn get_winch_engine() -> Option<Engine> {
+    WINCH_ENGINE.read().clone()
+}
+
+/// Resolves requested compile strategy with strategy of imported modules.
+///
+/// Imported modules share engine with importing module, so their strategy is used
+/// unless other strategy is explicitly requested.
+fn deps_strategy(
+    requested: Option<CompileStrategy>,
+    deps: Option<CompileStrategy>,
+) -> AnyResult<Option<CompileStrategy>> {
+    Ok(match (requested, deps) {
+        (Some(r), Some(d)) if r != CompileStrategy::Auto && r != d => bail_with_site!(
+            "Module is requested to be compiled with {}, but it's imported modules are compiled with {}",
+            r.name(),
+            d.name()
+        ),
+        (r, None) => r,
+        (_, d) => d,
+    })
+}
+
+/// Reads engine-wide compile strategy from project settings.
+#[instrument]
+pub fn init_compile_strategy() {
+    let settings = ProjectSettings::singleton();
+    let k = GString::from(SETTING_COMPILE_STRATEGY);
+    if !settings.has_setting(&k) {
+        return;
+    }
+
+    match from_var_any::<CompileStrategy>(&settings.get_setting(&k)) {
+        Ok(v) => {
+            info!(strategy = ?v, "Compile strategy initialized");
+            *COMPILE_STRATEGY.write() = v;
+        }
+        Err(e) => {
+            error!(err = %e, "Malformed compile strategy");
+            godot_error!("Malformed compile strategy: {e:?}");
+        }
+    }
+}
+
+/// Applies engine init config into wasmtime config.
+///
+/// Guest exceeding it's stack traps with stack overflow, which does not invalidate the store.
+#[instrument(skip(config))]
+fn apply_init_config(config: &mut Config, init: &EngineInitConfig) {
+    if let Some(v) = init.wasm_stack_bytes {
+        info!(v, "Maximum WASM stack size");
+        config.max_wasm_stack(v);
+    }
+    if let Some(v) = init.async_stack_bytes {
+        info!(v, "Async stack size");
+        config.async_stack_size(v);
+    }
+    if init.pooling_allocator {
+        config.allocation_strategy(InstanceAllocationStrategy::pooling());
+    }
+    config.consume_fuel(init.consume_fuel);
+}
+
+pub fn engine_config() -> Config {
+    let mut config = Config::new();
+    config
+        .cranelift_opt_level(wasmtime::OptLevel::Speed)
+        .cranelift_nan_canonicalization(cfg!(feature = "deterministic-wasm"))
+        .epoch_interruption(true)
+        .debug_info(true)
+        .wasm_reference_types(true)
+        .wasm_function_references(true)
+        .wasm_gc(true)
+        .wasm_simd(true)
+        .wasm_relaxed_simd(true)
+        .relaxed_simd_deterministic(cfg!(feature = "deterministic-wasm"))
+        .wasm_tail_call(true)
+        .wasm_bulk_memory(true)
+        .wasm_multi_value(true)
+        .wasm_multi_memory(true)
+        .wasm_memory64(true)
+        .wasm_threads(true)
+        .wasm_custom_page_sizes(true)
+        .wasm_extended_const(true)
+        .wasm_wide_arithmetic(true);
+    #[cfg(feature = "component-model")]
+    config
+        .wasm_component_model(true)
+        .wasm_component_model_more_flags(true)
+        .wasm_component_model_multiple_returns(true);
+    config
+}
+
+/// Derives Winch engine configuration.
+///
+/// Features unsupported by Winch are disabled, so modules using them fail to compile.
+#[cfg(feature = "winch")]
+fn winch_config(mut config: Config) -> Config {
+    config
+        .strategy(wasmtime::Strategy::Winch)
+        .debug_info(false)
+        .wasm_gc(false)
+        .wasm_function_references(false)
+        .wasm_simd(false)
+        .wasm_relaxed_simd(false)
+        .wasm_tail_call(false)
+        .wasm_threads(false);
+    config
+}
+
+/// Derives Pulley engine configuration.
+///
+/// Features unsupported by Pulley are disabled, so modules using them fail to compile.
+#[cfg(feature = "pulley")]
+fn pulley_config(mut config: Config) -> AnyResult<Config> {
+    config
+        .target(PULLEY_TARGET)?
+        .debug_info(false)
+        .wasm_simd(false)
+        .wasm_relaxed_simd(false)
+        .wasm_threads(false);
+    Ok(config)
+}
+
+/// Returns `true` if engine can run native code.
+///
+/// Some platforms (eg. iOS and consoles) forbid mapping executable memory.
+#[cfg(feature = "pulley")]
+fn jit_available(engine: &Engine) -> bool {
+    Module::new(engine, r#"(module (func (export "f")))"#).is_ok()
+}
+
+/// Constructs main engine with requested backend.
+///
+/// Falls back to Pulley if JIT is unavailable.
+fn new_engine(config: &Config, backend: ExecutionBackend) -> AnyResult<(Engine, ExecutionBackend)> {
+    #[cfg(feature = "pulley")]
+    if backend == ExecutionBackend::Pulley {
+        return Ok((
+            Engine::new(&pulley_config(config.clone())?)?,
+            ExecutionBackend::Pulley,
+        ));
+    }
+
+    let engine = Engine::new(config)?;
+    #[cfg(feature = "pulley")]
+    if !jit_available(&engine) {
+        warn!("JIT is unavailable, falling back to Pulley");
+        godot_warn!("JIT is unavailable, falling back to Pulley interpreter");
+        return Ok((
+            Engine::new(&pulley_config(config.clone())?)?,
+            ExecutionBackend::Pulley,
+        ));
+    }
+    Ok((engine, backend))
+}
+
 #[instrument]
-pub fn init_engine() {
+pub fn init_engine(mut init: EngineInitConfig) {
     let mut guard = ENGINE.write();
     if guard.is_none() {
         eprintln!("Initializing godot-wasm engine");
-        let mut config = Config::new();
-        config
-            .cranelift_opt_level(wasmtime::OptLevel::Speed)
-            .cranelift_nan_canonicalization(cfg!(feature = "deterministic-wasm"))
-            .epoch_interruption(true)
-            .debug_info(true)
-            .wasm_reference_types(true)
-            .wasm_function_references(true)
-            .wasm_gc(true)
-            .wasm_simd(true)
-            .wasm_relaxed_simd(true)
-            .relaxed_simd_deterministic(cfg!(feature = "deterministic-wasm"))
-            .wasm_tail_call(true)
-            .wasm_bulk_memory(true)
-            .wasm_multi_value(true)
-            .wasm_multi_memory(true)
-            .wasm_memory64(true)
-            .wasm_threads(true)
-            .wasm_custom_page_sizes(true)
-            .wasm_extended_const(true)
-            .wasm_wide_arithmetic(true);
-        #[cfg(feature = "component-model")]
-        config
-            .wasm_component_model(true)
-            .wasm_component_model_more_flags(true)
-            .wasm_component_model_multiple_returns(true);
+        crate::godot_util::init_main_thread();
+        let mut config = engine_config();
+        apply_init_config(&mut config, &init);
 
         info!(?config, "Engine configuration");
-        let e = match Engine::new(&config) {
+        let (e, backend) = match new_engine(&config, init.backend) {
             Ok(v) => v,
+            Err(e) if init.pooling_allocator => {
+                warn!(err = %e, "Failed to construct engine with pooling allocator");
+                godot_warn!("Pooling allocator is unavailable ({e}), using on-demand allocator");
+                init.pooling_allocator = false;
+                config.allocation_strategy(InstanceAllocationStrategy::OnDemand);
+                match new_engine(&config, init.backend) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!(err = %e, "Failed to construct engine");
+                        panic!("Failed to construct engine: {e}");
+                    }
+                }
+            }
             Err(e) => {
                 error!(err = %e, "Failed to construct engine");
                 panic!("Failed to construct engine: {e}");
             }
         };
+        info!(?backend, "Execution backend");
+        *EXECUTION_BACKEND.write() = backend;
+        init.backend = backend;
+        *ENGINE_CONFIG.write() = Some(init);
+        #[cfg(feature = "winch")]
+        {
+            // Winch does not canonicalize NaN.
+            *WINCH_ENGINE.write() = if cfg!(feature = "deterministic-wasm") {
+                info!("Winch engine is disabled with deterministic-wasm");
+                None
+            } else if backend == ExecutionBackend::Pulley {
+                info!("Winch engine is disabled with Pulley backend");
+                None
+            } else {
+                match Engine::new(&winch_config(config)) {
+                    Ok(v) => Some(v),
+                    Err(e) => {
+                        info!(err = %e, "Winch engine is unavailable");
+                        None
+                    }
+                }
+            };
+        }
         cfg_if! {
             if #[cfg(feature = "epoch-timeout")] {
                 *guard = Some((e, None));
@@ -105,6 +392,26 @@ pub fn init_engine() {
 #[instrument]
 pub fn deinit_engine() {
     eprintln!("Deinitializing godot-wasm engine");
+    *ENGINE_CONFIG.write() = None;
+    let handle = {
+        let mut guard = IDLE_REAPER.lock();
+        guard.entries.clear();
+        guard.handle.take()
+    };
+    if let Some(handle) = handle {
+        let _s = info_span!("deinit_engine.idle_reaper").entered();
+        handle.thread().unpark();
+        debug!("Joining idle reaper thread");
+        handle.join().unwrap();
+    }
+    LINKER_CACHE.write().clear();
+
+    if let Some(_engine) = WINCH_ENGINE.write().take() {
+        #[cfg(feature = "epoch-timeout")]
+        for _ in 0..100 {
+            _engine.increment_epoch();
+        }
+    }
     cfg_if! {
         if #[cfg(feature = "epoch-timeout")] {
             if let Some((engine, Some(handle))) = ENGINE.write().take() {
@@ -113,7 +420,10 @@ pub fn deinit_engine() {
                 for _ in 0..100 {
                     engine.increment_epoch();
                 }
+                EPOCH_COUNTER.fetch_add(100, Ordering::Release);
                 drop(engine);
+                // Parked thread should notice engine is gone.
+                wake_epoch();
                 debug!("Joining epoch thread");
                 handle.join().unwrap();
             }
@@ -123,6 +433,13 @@ pub fn deinit_engine() {
     }
 }
 
+/// Wakes parked epoch thread, so it rechecks engine.
+#[cfg(feature = "epoch-timeout")]
+fn wake_epoch() {
+    let _guard = EPOCH_DEMAND.lock();
+    EPOCH_WAKE.notify_all();
+}
+
 #[cfg(feature = "epoch-timeout")]
 #[instrument(level = Level::DEBUG, err)]
 pub fn start_epoch() -> AnyResult<()> {
@@ -130,6 +447,23 @@ pub fn start_epoch() -> AnyResult<()> {
     fn epoch_thread() {
         let mut timeout = time::Instant::now();
         loop {
+            {
+                let mut demand = EPOCH_DEMAND.lock();
+                if demand.armed == 0 {
+                    debug!("Epoch thread parked");
+                    demand.parked = true;
+                    demand.park_count += 1;
+                    while demand.armed == 0 && matches!(ENGINE.try_read().as_deref(), Some(Some(_)))
+                    {
+                        EPOCH_WAKE.wait(&mut demand);
+                    }
+                    demand.parked = false;
+                    debug!("Epoch thread resumed");
+                    // Epoch does not advance while parked.
+                    timeout = time::Instant::now();
+                }
+            }
+
             if let Some(d) = (timeout + EPOCH_INTERVAL).checked_duration_since(time::Instant::now())
             {
                 trace!(duration = ?d, "Epoch thread sleeping");
@@ -142,15 +476,23 @@ pub fn start_epoch() -> AnyResult<()> {
             let Some((engine, _)) = guard.as_ref() else {
                 break;
             };
+            let winch = WINCH_ENGINE.read();
             let t = time::Instant::now();
             while timeout < t {
                 trace!("Epoch");
                 engine.increment_epoch();
+                if let Some(e) = &*winch {
+                    e.increment_epoch();
+                }
+                EPOCH_COUNTER.fetch_add(1, Ordering::Release);
                 timeout += EPOCH_INTERVAL;
             }
         }
     }
 
+    if ENGINE_CONFIG.read().is_some_and(|c| !c.epoch_ticker) {
+        return Ok(());
+    }
     let mut guard = ENGINE.write();
     let (_, handle) = guard.as_mut().ok_or(EngineUninitError)?;
     if handle.is_none() {
@@ -161,6 +503,503 @@ pub fn start_epoch() -> AnyResult<()> {
     Ok(())
 }
 
+/// Registers instance activity to idle reaper.
+///
+/// Once `activity` is idle for longer than `timeout`, reaper calls `callable` (deferred)
+/// with idle time in seconds.
+/// It is called at most once per idle period, and never while a call is in flight.
+/// Reaper never frees the instance by itself.
+#[instrument(level = Level::DEBUG, skip(activity, callable), err)]
+pub fn register_idle_unload(
+    activity: &Arc<ActivityTracker>,
+    timeout: time::Duration,
+    callable: Callable,
+) -> AnyResult<()> {
+    #[instrument]
+    fn reaper_thread() {
+        loop {
+            thread::park_timeout(IDLE_REAPER_INTERVAL);
+
+            let mut guard = IDLE_REAPER.lock();
+            guard.entries.retain_mut(|e| {
+                let Some(activity) = e.activity.upgrade() else {
+                    return false;
+                };
+                let last = activity.last();
+                if activity.is_busy() || e.notified == Some(last) {
+                    return true;
+                }
+                let idle = activity.idle_time();
+                if idle >= e.timeout {
+                    debug!(?idle, "Requesting idle unload");
+                    e.notified = Some(last);
+                    e.callable.call_deferred(&[idle.as_secs_f64().to_variant()]);
+                }
+                true
+            });
+
+            if guard.entries.is_empty() {
+                trace!("No more instances, stopping idle reaper");
+                guard.handle = None;
+                break;
+            }
+        }
+    }
+
+    let mut guard = IDLE_REAPER.lock();
+    guard.entries.push(IdleEntry {
+        activity: Arc::downgrade(activity),
+        timeout,
+        notified: None,
+        callable: SendSyncWrapper::new(callable),
+    });
+    if guard.handle.is_none() {
+        let _s = info_span!("register_idle_unload.thread").entered();
+        let builder = thread::Builder::new().name("idle-reaper".to_string());
+        guard.handle = Some(builder.spawn(reaper_thread)?);
+    }
+    Ok(())
+}
+
+/// Config bits that change how a linker is built.
+///
+/// Component filter and host modules are not part of it, since filter is checked
+/// at call time and host modules are bound to per-instance callables.
+///
+/// Only WASI linker of core modules is cached. Object registry, externref, and host functions
+/// are wrapped per store (and only if imported), so they can't be shared between instances.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LinkerKey {
+    /// WASI preview 1 functions.
+    pub wasi: bool,
+    /// WASI preview 2 command interfaces.
+    pub wasi_command: bool,
+    /// Godot component interfaces.
+    pub godot_component: bool,
+}
+
+struct LinkerEntry {
+    engine: Engine,
+    linker: Arc<dyn Any + Send + Sync>,
+}
+
+/// Linkers shared between instances, keyed by linker type and [`LinkerKey`].
+///
+/// Each engine (main and Winch) has it's own linker.
+static LINKER_CACHE: Lazy<RwLock<HashMap<(TypeId, LinkerKey), Vec<LinkerEntry>>>> =
+    Lazy::new(Default::default);
+
+/// Gets cached linker for `engine` and `key`, building it with `f` if there is none.
+#[instrument(level = Level::DEBUG, skip(engine, f), err)]
+pub fn get_linker<L, F>(engine: &Engine, key: LinkerKey, f: F) -> AnyResult<Arc<L>>
+where
+    L: Any + Send + Sync,
+    F: FnOnce() -> AnyResult<L>,
+{
+    let k = (TypeId::of::<L>(), key);
+    if let Some(v) = LINKER_CACHE.read().get(&k) {
+        if let Some(e) = v.iter().find(|e| Engine::same(&e.engine, engine)) {
+            if let Ok(v) = e.linker.clone().downcast::<L>() {
+                return Ok(v);
+            }
+        }
+    }
+
+    debug!("Building linker");
+    let v = Arc::new(f()?);
+    let mut guard = LINKER_CACHE.write();
+    let entries = guard.entry(k).or_default();
+    entries.retain(|e| !Engine::same(&e.engine, engine));
+    entries.push(LinkerEntry {
+        engine: engine.clone(),
+        linker: v.clone(),
+    });
+    Ok(v)
+}
+
+#[cfg(feature = "memory-limiter")]
+const SETTING_GLOBAL_MAX_BYTES: &str = "godot_wasm/memory/global_max_bytes";
+
+/// Memory budget shared by multiple reservations.
+#[cfg(feature = "memory-limiter")]
+#[derive(Debug)]
+pub struct MemoryBudget {
+    /// Total bytes reserved.
+    used: AtomicU64,
+    /// Maximum bytes that can be reserved.
+    max: AtomicU64,
+}
+
+#[cfg(feature = "memory-limiter")]
+impl MemoryBudget {
+    pub const fn new() -> Self {
+        Self {
+            used: AtomicU64::new(0),
+            max: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// Total bytes reserved.
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Acquire)
+    }
+
+    /// Maximum bytes that can be reserved, `None` means unlimited.
+    pub fn max(&self) -> Option<u64> {
+        match self.max.load(Ordering::Acquire) {
+            u64::MAX => None,
+            v => Some(v),
+        }
+    }
+
+    /// Sets maximum bytes that can be reserved, `None` means unlimited.
+    ///
+    /// Existing reservations are not affected, even if they are over the new maximum.
+    pub fn set_max(&self, v: Option<u64>) {
+        self.max.store(v.unwrap_or(u64::MAX), Ordering::Release);
+    }
+}
+
+#[cfg(feature = "memory-limiter")]
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Memory reserved by all stores and shared memories.
+#[cfg(feature = "memory-limiter")]
+static GLOBAL_MEMORY: MemoryBudget = MemoryBudget::new();
+/// Largest linear memory size observed in any instance.
+#[cfg(feature = "memory-limiter")]
+static GLOBAL_LINEAR_MEMORY_PEAK: AtomicU64 = AtomicU64::new(0);
+/// Emitted when reservation of global budget is denied.
+#[cfg(feature = "memory-limiter")]
+static MEMORY_PRESSURE_SIGNAL: Mutex<Option<SendSyncWrapper<Signal>>> = Mutex::new(None);
+
+/// Portion of memory budget. Released on drop.
+#[cfg(feature = "memory-limiter")]
+#[derive(Debug)]
+pub struct MemoryReservation {
+    budget: &'static MemoryBudget,
+    size: u64,
+}
+
+/// Reserves from engine-wide budget.
+#[cfg(feature = "memory-limiter")]
+impl Default for MemoryReservation {
+    fn default() -> Self {
+        Self::new(&GLOBAL_MEMORY)
+    }
+}
+
+#[cfg(feature = "memory-limiter")]
+impl MemoryReservation {
+    pub const fn new(budget: &'static MemoryBudget) -> Self {
+        Self { budget, size: 0 }
+    }
+
+    /// Reserves `delta` more bytes.
+    ///
+    /// If budget is exceeded, `global_memory_pressure` signal is emitted with `instance`
+    /// and `false` is returned.
+    pub fn grow(&mut self, delta: u64, instance: Option<InstanceId>) -> bool {
+        let budget = self.budget.max.load(Ordering::Acquire);
+        let r = self
+            .budget
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |v| {
+                v.checked_add(delta).filter(|&v| v <= budget)
+            });
+        match r {
+            Ok(_) => {
+                self.size += delta;
+                true
+            }
+            Err(used) => {
+                tracing::warn!(
+                    used,
+                    budget,
+                    delta,
+                    ?instance,
+                    "Global memory budget exceeded"
+                );
+                if std::ptr::eq(self.budget, &GLOBAL_MEMORY) {
+                    if let Some(s) = &*MEMORY_PRESSURE_SIGNAL.lock() {
+                        emit_deferred(
+                            s,
+                            &[
+                                used.to_variant(),
+                                budget.to_variant(),
+                                instance.map_or(0, |v| v.to_i64()).to_variant(),
+                            ],
+                        );
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    /// Number of bytes reserved.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+#[cfg(feature = "memory-limiter")]
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        if self.size > 0 {
+            self.budget.used.fetch_sub(self.size, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Records linear memory size into engine-wide peak.
+#[cfg(feature = "memory-limiter")]
+pub fn record_memory_peak(size: u64) {
+    GLOBAL_LINEAR_MEMORY_PEAK.fetch_max(size, Ordering::AcqRel);
+}
+
+/// Largest linear memory size observed in any instance.
+#[cfg(feature = "memory-limiter")]
+pub fn memory_peak() -> u64 {
+    GLOBAL_LINEAR_MEMORY_PEAK.load(Ordering::Acquire)
+}
+
+/// Sets engine-wide memory budget. `None` means unlimited.
+///
+/// Existing reservations are not affected, even if they are over the new budget.
+#[cfg(feature = "memory-limiter")]
+pub fn set_global_memory_budget(v: Option<u64>) {
+    GLOBAL_MEMORY.set_max(v);
+}
+
+/// Reads engine-wide memory budget from project settings.
+#[cfg(feature = "memory-limiter")]
+#[instrument]
+pub fn init_memory_budget() {
+    let settings = ProjectSettings::singleton();
+    let k = GString::from(SETTING_GLOBAL_MAX_BYTES);
+    if !settings.has_setting(&k) {
+        return;
+    }
+
+    match from_var_any::<i64>(&settings.get_setting(&k)) {
+        Ok(v) => {
+            info!(budget = v, "Global memory budget initialized");
+            set_global_memory_budget(u64::try_from(v).ok());
+        }
+        Err(e) => {
+            error!(err = %e, "Malformed global memory budget");
+            godot_error!("Malformed global memory budget: {e:?}");
+        }
+    }
+}
+
+/// Per-store epoch deadline state.
+#[cfg(feature = "epoch-timeout")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EpochDeadline {
+    pub priority: EpochPriority,
+    /// Epoch at which the store times out.
+    pub end: u64,
+    /// Store is configured with epoch timeout, so it's calls arm deadline.
+    pub timed: bool,
+}
+
+#[cfg(feature = "epoch-timeout")]
+impl EpochDeadline {
+    /// Ticks until next deadline callback, given current epoch.
+    ///
+    /// Returns `None` if timeout is reached.
+    pub fn next_slice(&self, epoch: u64) -> Option<u64> {
+        match self.end.checked_sub(epoch) {
+            Some(v @ 1..) => Some(v.min(epoch_slice(self.priority))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "epoch-timeout")]
+#[inline]
+pub fn current_epoch() -> u64 {
+    EPOCH_COUNTER.load(Ordering::Acquire)
+}
+
+/// Gets how many ticks a store of given priority may run before it is preempted.
+#[cfg(feature = "epoch-timeout")]
+pub fn epoch_slice(priority: EpochPriority) -> u64 {
+    let ms = match priority {
+        EpochPriority::Low => 1,
+        EpochPriority::Normal => 5,
+        EpochPriority::High => 10,
+    };
+    (ms * EPOCH_MULTIPLIER / 1000).max(1)
+}
+
+/// Keeps epoch thread running until dropped.
+#[cfg(feature = "epoch-timeout")]
+#[must_use]
+pub struct DeadlineGuard(());
+
+#[cfg(feature = "epoch-timeout")]
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        EPOCH_DEMAND.lock().armed -= 1;
+    }
+}
+
+/// Arms a deadline, waking epoch thread if it's parked.
+///
+/// Epoch thread only ticks while there is an armed deadline,
+/// so it does not consume CPU while no guest is running.
+#[cfg(feature = "epoch-timeout")]
+pub fn arm_deadline() -> DeadlineGuard {
+    let mut demand = EPOCH_DEMAND.lock();
+    demand.armed += 1;
+    if demand.armed == 1 {
+        EPOCH_WAKE.notify_all();
+    }
+    DeadlineGuard(())
+}
+
+/// Marks a call of given priority as running until dropped.
+///
+/// Timed call also arms it's deadline.
+#[cfg(feature = "epoch-timeout")]
+#[must_use]
+pub struct PriorityGuard {
+    priority: EpochPriority,
+    _armed: Option<DeadlineGuard>,
+}
+
+#[cfg(feature = "epoch-timeout")]
+impl Drop for PriorityGuard {
+    fn drop(&mut self) {
+        ACTIVE_CALLS[self.priority as usize].fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(feature = "epoch-timeout")]
+pub fn enter_priority(deadline: &EpochDeadline) -> PriorityGuard {
+    let priority = deadline.priority;
+    ACTIVE_CALLS[priority as usize].fetch_add(1, Ordering::AcqRel);
+    PriorityGuard {
+        priority,
+        _armed: deadline.timed.then(arm_deadline),
+    }
+}
+
+/// Yields current thread if there is a running call with higher priority.
+///
+/// Waits at most the slice of that higher priority.
+/// Returns `true` if it yields.
+#[cfg(feature = "epoch-timeout")]
+#[instrument(level = Level::TRACE, ret)]
+pub fn yield_to_priority(priority: EpochPriority) -> bool {
+    let Some(p) = [EpochPriority::High, EpochPriority::Normal]
+        .into_iter()
+        .filter(|&p| p > priority)
+        .find(|&p| ACTIVE_CALLS[p as usize].load(Ordering::Acquire) > 0)
+    else {
+        return false;
+    };
+
+    let end = time::Instant::now() + EPOCH_INTERVAL * epoch_slice(p) as u32;
+    while ACTIVE_CALLS[p as usize].load(Ordering::Acquire) > 0 && time::Instant::now() < end {
+        thread::sleep(EPOCH_INTERVAL.min(time::Duration::from_millis(1)));
+    }
+    true
+}
+
+/// Gets engine information as dictionary.
+pub fn engine_info() -> Dictionary {
+    let mut ret = Dictionary::new();
+    ret.set("initialized", get_engine().is_ok());
+    ret.set("winch", get_winch_engine().is_some());
+    ret.set("compile_strategy", *COMPILE_STRATEGY.read());
+    ret.set("backend", *EXECUTION_BACKEND.read());
+    if let Some(v) = &*ENGINE_CONFIG.read() {
+        ret.set("config", v.to_dict());
+    }
+    if let Ok(v) = artifact_key() {
+        ret.set("artifact_key", v);
+    }
+
+    #[cfg(feature = "memory-limiter")]
+    {
+        ret.set("memory_used", GLOBAL_MEMORY.used());
+        ret.set("linear_memory_peak", memory_peak());
+        ret.set(
+            "memory_budget",
+            GLOBAL_MEMORY.max().map_or(-1, |v| v as i64),
+        );
+    }
+
+    #[cfg(feature = "epoch-timeout")]
+    {
+        ret.set(
+            "epoch_running",
+            matches!(ENGINE.try_read().as_deref(), Some(Some((_, Some(_))))),
+        );
+        ret.set("epoch", current_epoch());
+        {
+            let demand = EPOCH_DEMAND.lock();
+            ret.set("epoch_parked", demand.parked);
+            ret.set("epoch_park_count", demand.park_count);
+            ret.set("armed_deadlines", demand.armed as u64);
+        }
+        ret.set("epoch_interval", EPOCH_INTERVAL.as_secs_f64());
+        ret.set(
+            "priorities",
+            [
+                EpochPriority::Low,
+                EpochPriority::Normal,
+                EpochPriority::High,
+            ]
+            .into_iter()
+            .map(|p| {
+                let slice = epoch_slice(p);
+                (
+                    p.to_variant(),
+                    [
+                        ("deadline_ticks", slice.to_variant()),
+                        (
+                            "deadline",
+                            (EPOCH_INTERVAL * slice as u32).as_secs_f64().to_variant(),
+                        ),
+                        (
+                            "active_calls",
+                            (ACTIVE_CALLS[p as usize].load(Ordering::Acquire) as u64).to_variant(),
+                        ),
+                    ]
+                    .into_iter()
+                    .collect::<Dictionary>()
+                    .to_variant(),
+                )
+            })
+            .collect::<Dictionary>(),
+        );
+    }
+
+    ret
+}
+
+/// Key of precompiled artifacts compatible with main engine.
+///
+/// Artifacts of different backend (or engine configuration) have different key.
+pub fn artifact_key() -> Result<String, EngineUninitError> {
+    let mut h = DefaultHasher::new();
+    get_engine()?.precompile_compatibility_hash().hash(&mut h);
+    Ok(format!(
+        "{}-{:016x}",
+        EXECUTION_BACKEND.read().to_godot(),
+        h.finish()
+    ))
+}
+
 pub struct EngineUninitError;
 
 impl Debug for EngineUninitError {
@@ -177,6 +1016,90 @@ impl Display for EngineUninitError {
 
 impl Error for EngineUninitError {}
 
+#[derive(GodotClass)]
+#[class(base=Object, init, tool)]
+/// Engine-wide settings and events.
+///
+/// Methods are static. Signals are emitted by `WasmEngine` singleton.
+pub struct WasmEngine {
+    base: Base<Object>,
+}
+
+/// Name of `WasmEngine` singleton.
+const ENGINE_SINGLETON: &CStr = c"WasmEngine";
+
+/// Registers `WasmEngine` singleton. Must be called at scene init level.
+pub fn register_engine_singleton() {
+    let obj = WasmEngine::new_alloc();
+    #[cfg(feature = "memory-limiter")]
+    {
+        *MEMORY_PRESSURE_SIGNAL.lock() = Some(SendSyncWrapper::new(Signal::from_object_signal(
+            &obj,
+            c"global_memory_pressure",
+        )));
+    }
+    GodotEngine::singleton().register_singleton(&StringName::from(ENGINE_SINGLETON), &obj);
+}
+
+/// Unregisters and frees `WasmEngine` singleton.
+pub fn unregister_engine_singleton() {
+    #[cfg(feature = "memory-limiter")]
+    {
+        *MEMORY_PRESSURE_SIGNAL.lock() = None;
+    }
+    let mut engine = GodotEngine::singleton();
+    let name = StringName::from(ENGINE_SINGLETON);
+    if let Some(obj) = engine.get_singleton(&name) {
+        engine.unregister_singleton(&name);
+        obj.free();
+    }
+}
+
+#[godot_api]
+impl WasmEngine {
+    /// Emitted (deferred) whenever memory growth is denied by engine-wide budget.
+    ///
+    /// Instance ID is 0 if it's not caused by an instance.
+    #[signal]
+    fn global_memory_pressure(used: i64, budget: i64, instance_id: i64);
+
+    /// Sets engine-wide module policy. Modules are checked before compilation/deserialization.
+    ///
+    /// Returns `false` if policy is malformed or frozen.
+    ///
+    /// Arguments:
+    /// - `policy` : Dictionary with the following keys:
+    ///   - `allowed_hashes` : Array of hex-encoded SHA-256 hash of allowed modules.
+    ///   - `allowed_paths` : Array of allowed path prefixes (eg. `"res://wasm/"`).
+    ///   - `deny_unsigned` : If `true`, module must be in `allowed_hashes` regardless of it's path.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn set_module_policy(policy: Dictionary) -> bool {
+        match set_policy(policy) {
+            Ok(()) => true,
+            Err(e) => {
+                godot_error!("{:?}", e);
+                false
+            }
+        }
+    }
+
+    /// Freezes module policy. Once frozen, it cannot be changed until engine restarts.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn freeze_module_policy() {
+        freeze_policy();
+    }
+
+    /// Gets current module policy.
+    ///
+    /// It has the same keys as `set_module_policy()` along with `frozen`.
+    #[func]
+    fn get_module_policy() -> Dictionary {
+        get_policy()
+    }
+}
+
 #[derive(GodotClass)]
 #[class(base=Resource, init, tool)]
 /// Class for WebAssembly module.
@@ -227,9 +1150,13 @@ impl Debug for WasmModule {
 }
 
 pub struct ModuleData {
-    name: GString,
+    pub name: GString,
     pub module: ModuleType,
+    /// Strategy actually used to compile module.
+    pub strategy: CompileStrategy,
     pub imports: HashMap<String, Gd<WasmModule>>,
+    /// Custom sections of outermost module. Empty if module is deserialized.
+    pub custom_sections: Vec<CustomSection>,
 }
 
 #[derive(Clone)]
@@ -250,6 +1177,14 @@ impl Debug for ModuleType {
 }
 
 impl ModuleType {
+    pub fn engine(&self) -> &Engine {
+        match self {
+            Self::Core(m) => m.engine(),
+            #[cfg(feature = "component-model")]
+            Self::Component(m) => m.engine(),
+        }
+    }
+
     pub fn get_core(&self) -> AnyResult<&Module> {
         #[allow(irrefutable_let_patterns)]
         if let Self::Core(m) = self {
@@ -267,6 +1202,51 @@ impl ModuleType {
             bail!("Module is not a component")
         }
     }
+
+    /// Gets all functions imported by component, from its type section.
+    #[cfg(feature = "component-model")]
+    pub fn get_component_imports(&self) -> AnyResult<Vec<ComponentImport>> {
+        let comp = self.get_component()?;
+        let engine = comp.engine();
+        let mut ret = Vec::new();
+        for (name, item) in comp.component_type().imports(engine) {
+            let ComponentItem::ComponentInstance(inst) = item else {
+                continue;
+            };
+            // Interface name is formatted as namespace:package/interface@version
+            let name = name.split_once('@').map_or(name, |(v, _)| v);
+            let Some((module, interface)) = name.split_once('/') else {
+                continue;
+            };
+            for (method, item) in inst.exports(engine) {
+                if let ComponentItem::ComponentFunc(_) = item {
+                    ret.push(ComponentImport {
+                        module: module.into(),
+                        interface: interface.into(),
+                        method: method.into(),
+                    });
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+}
+
+/// Function imported by component.
+#[cfg(feature = "component-model")]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ComponentImport {
+    pub module: String,
+    pub interface: String,
+    pub method: String,
+}
+
+#[cfg(feature = "component-model")]
+impl Display for ComponentImport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}.{}.{}", self.module, self.interface, self.method)
+    }
 }
 
 impl WasmModule {
@@ -300,28 +1280,114 @@ impl WasmModule {
         }
     }
 
-    #[instrument(skip(bytes), fields(bytes.len = bytes.len()), ret)]
-    fn load_module(bytes: &[u8]) -> AnyResult<ModuleType> {
+    #[instrument(skip(engine, bytes), fields(bytes.len = bytes.len()), ret)]
+    fn compile(engine: &Engine, bytes: &[u8]) -> AnyResult<ModuleType> {
         cfg_if! {
             if #[cfg(feature = "component-model")] {
                 let bytes = site_context!(wat::parse_bytes(bytes))?;
                 if wasmparser::Parser::is_component(&bytes) {
                     Ok(ModuleType::Component(site_context!(
-                        Component::from_binary(&get_engine()?, &bytes,)
+                        Component::from_binary(engine, &bytes)
                     )?))
                 } else {
                     Ok(ModuleType::Core(site_context!(Module::from_binary(
-                        &get_engine()?, &bytes
+                        engine, &bytes
                     ))?))
                 }
             } else {
                 Ok(ModuleType::Core(site_context!(Module::new(
-                    &get_engine()?, bytes
+                    engine, bytes
                 ))?))
             }
         }
     }
 
+    /// Compiles module with requested strategy (or engine-wide strategy if `None`).
+    ///
+    /// Falls back to Cranelift if Winch is unavailable or fails to compile.
+    #[instrument(skip(bytes), fields(bytes.len = bytes.len()), ret)]
+    fn load_module(
+        bytes: &[u8],
+        strategy: Option<CompileStrategy>,
+    ) -> AnyResult<(ModuleType, CompileStrategy)> {
+        let strategy = strategy
+            .unwrap_or_else(|| *COMPILE_STRATEGY.read())
+            .resolve(bytes.len());
+        if strategy == CompileStrategy::Winch {
+            match get_winch_engine() {
+                Some(engine) => match Self::compile(&engine, bytes) {
+                    Ok(v) => return Ok((v, CompileStrategy::Winch)),
+                    Err(e) => {
+                        warn!(err = %e, "Winch failed to compile module, falling back to Cranelift");
+                        godot_warn!(
+                            "Winch failed to compile module, falling back to Cranelift: {e}"
+                        );
+                    }
+                },
+                None => {
+                    warn!("Winch is unavailable, falling back to Cranelift");
+                    godot_warn!("Winch is unavailable, falling back to Cranelift");
+                }
+            }
+        }
+
+        Ok((
+            Self::compile(&get_engine()?, bytes)?,
+            CompileStrategy::Cranelift,
+        ))
+    }
+
+    /// Deserializes module with whichever engine accepts it.
+    fn deserialize_any(
+        f: impl Fn(&Engine) -> AnyResult<ModuleType>,
+    ) -> AnyResult<(ModuleType, CompileStrategy)> {
+        let engine = site_context!(get_engine())?;
+        let e = match f(&engine) {
+            Ok(v) => return Ok((v, CompileStrategy::Cranelift)),
+            Err(e) => e,
+        };
+        match get_winch_engine().map(|engine| f(&engine)) {
+            Some(Ok(v)) => Ok((v, CompileStrategy::Winch)),
+            _ => Err(e),
+        }
+    }
+
+    fn deserialize_data(data: &[u8]) -> AnyResult<(ModuleType, CompileStrategy)> {
+        // SAFETY: Assume the supplied data is safe to deserialize.
+        Self::deserialize_any(|engine| unsafe {
+            Ok(match engine.detect_precompiled(data) {
+                Some(Precompiled::Module) => {
+                    ModuleType::Core(site_context!(Module::deserialize(engine, data))?)
+                }
+                #[cfg(feature = "component-model")]
+                Some(Precompiled::Component) => {
+                    ModuleType::Component(site_context!(Component::deserialize(engine, data))?)
+                }
+                _ => bail_with_site!("Unsupported data content"),
+            })
+        })
+    }
+
+    /// Gets compile strategy of imported modules, `None` if there is no imports.
+    ///
+    /// Imported modules are instantiated in the same store, so all of them must share an engine.
+    fn imports_strategy(imports: Option<&Dictionary>) -> AnyResult<Option<CompileStrategy>> {
+        let mut ret = None;
+        for (k, v) in imports.into_iter().flat_map(|v| v.iter_shared()) {
+            let v = site_context!(from_var_any::<Gd<WasmModule>>(v))?;
+            let s = v.bind().get_data()?.strategy;
+            match ret {
+                Some(r) if r != s => bail_with_site!(
+                    "Imported module {k} is compiled with {}, but other imported modules are compiled with {}",
+                    s.name(),
+                    r.name()
+                ),
+                _ => ret = Some(s),
+            }
+        }
+        Ok(ret)
+    }
+
     #[instrument(skip(imports), fields(imports = %display_option(&imports)), ret)]
     fn process_deps_map(
         module: &ModuleType,
@@ -332,14 +1398,24 @@ impl WasmModule {
             return Ok(deps_map);
         };
         #[allow(irrefutable_let_patterns)]
-        if let ModuleType::Core(_module) = &module {
+        if let ModuleType::Core(module_) = &module {
             deps_map = imports
                 .iter_shared()
                 .map(|(k, v)| -> AnyResult<_> {
-                    Ok((
-                        site_context!(from_var_any::<String>(k))?,
-                        site_context!(from_var_any::<Gd<WasmModule>>(v))?,
-                    ))
+                    let k = site_context!(from_var_any::<String>(k))?;
+                    let v = site_context!(from_var_any::<Gd<WasmModule>>(v))?;
+                    // Imported modules are instantiated in the same store.
+                    {
+                        let dep = v.bind();
+                        let dep = dep.get_data()?;
+                        if !Engine::same(module_.engine(), dep.module.engine()) {
+                            bail_with_site!(
+                                "Imported module {k} is compiled with {}, which uses different engine",
+                                dep.strategy.name()
+                            );
+                        }
+                    }
+                    Ok((k, v))
                 })
                 .collect::<AnyResult<_>>()?;
         }
@@ -363,18 +1439,55 @@ impl WasmModule {
         .map_or_else(GString::new, GString::from)
     }
 
+    /// Reads custom sections of binary module. WAT text has none.
+    fn custom_sections(bytes: &[u8], max_bytes: usize) -> AnyResult<Vec<CustomSection>> {
+        if bytes.starts_with(b"\0asm") {
+            read_custom_sections(bytes, max_bytes)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
     #[instrument(skip(self, data, imports), ret(level = Level::DEBUG))]
-    fn _initialize(&self, data: Variant, imports: Option<Dictionary>) -> bool {
+    fn _initialize(
+        &self,
+        data: Variant,
+        imports: Option<Dictionary>,
+        strategy: Option<CompileStrategy>,
+        section_max_bytes: usize,
+    ) -> bool {
         let r = self.data.get_or_try_init(move || -> AnyResult<_> {
-            let module = variant_dispatch!(data {
-                PACKED_BYTE_ARRAY => Self::load_module(data.as_slice())?,
-                STRING => Self::load_module(data.to_string().as_bytes())?,
+            let strategy = deps_strategy(strategy, Self::imports_strategy(imports.as_ref())?)?;
+            let ((module, strategy), custom_sections) = variant_dispatch!(data {
+                PACKED_BYTE_ARRAY => {
+                    check_module(data.as_slice(), None)?;
+                    (
+                        Self::load_module(data.as_slice(), strategy)?,
+                        Self::custom_sections(data.as_slice(), section_max_bytes)?,
+                    )
+                }
+                STRING => {
+                    let data = data.to_string();
+                    check_module(data.as_bytes(), None)?;
+                    (Self::load_module(data.as_bytes(), strategy)?, Vec::new())
+                }
                 OBJECT => match data
                     .try_cast::<FileAccess>()
                     .map_err(|v| v.try_cast::<WasmModule>())
                 {
-                    Ok(v) => Self::load_module(v.get_buffer(v.get_length() as _).as_slice())?,
-                    Err(Ok(v)) => v.bind().get_data()?.module.clone(),
+                    Ok(v) => {
+                        let data = v.get_buffer(v.get_length() as _);
+                        check_module(data.as_slice(), Some(&v.get_path_absolute().to_string()))?;
+                        (
+                            Self::load_module(data.as_slice(), strategy)?,
+                            Self::custom_sections(data.as_slice(), section_max_bytes)?,
+                        )
+                    }
+                    Err(Ok(v)) => {
+                        let v = v.bind();
+                        let v = v.get_data()?;
+                        ((v.module.clone(), v.strategy), v.custom_sections.clone())
+                    }
                     Err(Err(v)) => bail_with_site!("Unknown module value {}", v),
                 },
                 _ => bail_with_site!("Unknown module value {}", data),
@@ -385,7 +1498,9 @@ impl WasmModule {
             Ok(ModuleData {
                 name: Self::name_from_module(&module),
                 module,
+                strategy,
                 imports,
+                custom_sections,
             })
         });
         if let Err(e) = r {
@@ -399,28 +1514,18 @@ impl WasmModule {
     #[instrument(skip(self, data, imports), fields(data.len = data.len()), ret(level = Level::DEBUG))]
     fn _deserialize(&self, data: PackedByteArray, imports: Option<Dictionary>) -> bool {
         let r = self.data.get_or_try_init(move || -> AnyResult<_> {
-            let engine = site_context!(get_engine())?;
             let data = data.as_slice();
-            // SAFETY: Assume the supplied data is safe to deserialize.
-            let module = unsafe {
-                match engine.detect_precompiled(data) {
-                    Some(Precompiled::Module) => {
-                        ModuleType::Core(site_context!(Module::deserialize(&engine, data))?)
-                    }
-                    #[cfg(feature = "component-model")]
-                    Some(Precompiled::Component) => {
-                        ModuleType::Component(site_context!(Component::deserialize(&engine, data))?)
-                    }
-                    _ => bail_with_site!("Unsupported data content"),
-                }
-            };
+            check_module(data, None)?;
+            let (module, strategy) = Self::deserialize_data(data)?;
 
             let imports = Self::process_deps_map(&module, imports)?;
 
             Ok(ModuleData {
                 name: Self::name_from_module(&module),
                 module,
+                strategy,
                 imports,
+                custom_sections: Vec::new(),
             })
         });
         if let Err(e) = r {
@@ -434,28 +1539,19 @@ impl WasmModule {
     #[instrument(skip(self, imports), ret(level = Level::DEBUG))]
     fn _deserialize_file(&self, path: String, imports: Option<Dictionary>) -> bool {
         let r = self.data.get_or_try_init(move || -> AnyResult<_> {
-            let engine = site_context!(get_engine())?;
-            let path = PathBuf::from(path);
-            // SAFETY: Assume the supplied file is safe to deserialize.
-            let module = unsafe {
-                match site_context!(engine.detect_precompiled_file(&path))? {
-                    Some(Precompiled::Module) => {
-                        ModuleType::Core(site_context!(Module::deserialize_file(&engine, path))?)
-                    }
-                    #[cfg(feature = "component-model")]
-                    Some(Precompiled::Component) => ModuleType::Component(site_context!(
-                        Component::deserialize_file(&engine, path)
-                    )?),
-                    _ => bail_with_site!("Unsupported data content"),
-                }
-            };
+            // Read file once, so the checked bytes are the ones being deserialized.
+            let data = site_context!(std::fs::read(&path))?;
+            check_module(&data, Some(&path))?;
+            let (module, strategy) = Self::deserialize_data(&data)?;
 
             let imports = Self::process_deps_map(&module, imports)?;
 
             Ok(ModuleData {
                 name: Self::name_from_module(&module),
                 module,
+                strategy,
                 imports,
+                custom_sections: Vec::new(),
             })
         });
         if let Err(e) = r {
@@ -469,6 +1565,25 @@ impl WasmModule {
 
 #[godot_api]
 impl WasmModule {
+    /// 32-bit integer value type.
+    #[constant]
+    const TYPE_I32: i64 = wasm_util::TYPE_I32;
+    /// 64-bit integer value type.
+    #[constant]
+    const TYPE_I64: i64 = wasm_util::TYPE_I64;
+    /// 32-bit float value type.
+    #[constant]
+    const TYPE_F32: i64 = wasm_util::TYPE_F32;
+    /// 64-bit float value type.
+    #[constant]
+    const TYPE_F64: i64 = wasm_util::TYPE_F64;
+    /// Variant (externref) value type. Requires feature `object-registry-extern`.
+    #[constant]
+    const TYPE_VARIANT: i64 = wasm_util::TYPE_VARIANT;
+    /// 128-bit vector value type.
+    #[constant]
+    const TYPE_V128: i64 = wasm_util::TYPE_V128;
+
     /// Initialize and loads module.
     ///
     /// **⚠ MUST BE CALLED FOR THE FIRST TIME AND ONLY ONCE.**
@@ -494,13 +1609,68 @@ impl WasmModule {
     #[func]
     #[instrument(level = Level::DEBUG, skip(data, imports))]
     fn initialize(&self, data: Variant, imports: Dictionary) -> Option<Gd<WasmModule>> {
-        if self._initialize(data, Some(imports)) {
+        if self._initialize(data, Some(imports), None, DEFAULT_SECTION_MAX_BYTES) {
             Some(self.to_gd())
         } else {
             None
         }
     }
 
+    /// Like `initialize`, but with module config.
+    ///
+    /// Config keys:
+    /// - `compile.strategy` : One of `"cranelift"`, `"winch"`, or `"auto"`.
+    ///   Defaults to engine-wide strategy.
+    /// - `custom_sections.max_bytes` : Maximum size of custom section content that is retained.
+    ///   Defaults to 1 MiB.
+    #[func]
+    #[instrument(level = Level::DEBUG, skip(data, imports))]
+    fn initialize_with_config(
+        &self,
+        data: Variant,
+        imports: Dictionary,
+        config: Dictionary,
+    ) -> Option<Gd<WasmModule>> {
+        let r = (|| -> AnyResult<_> {
+            let strategy = config
+                .get("compile.strategy")
+                .map(|v| site_context!(from_var_any::<CompileStrategy>(v)))
+                .transpose()?;
+            let max_bytes = match config.get("custom_sections.max_bytes") {
+                Some(v) => site_context!(usize::try_from(from_var_any::<i64>(v)?))?,
+                None => DEFAULT_SECTION_MAX_BYTES,
+            };
+            Ok((strategy, max_bytes))
+        })();
+        let (strategy, max_bytes) = match r {
+            Ok(v) => v,
+            Err(e) => {
+                godot_error!("{e:?}");
+                return None;
+            }
+        };
+        if self._initialize(data, Some(imports), strategy, max_bytes) {
+            Some(self.to_gd())
+        } else {
+            None
+        }
+    }
+
+    /// Gets module information.
+    ///
+    /// Returns dictionary with:
+    /// - `strategy` : Compile strategy used, either `"cranelift"` or `"winch"`.
+    #[func]
+    #[instrument]
+    fn get_info(&self) -> Dictionary {
+        self.unwrap_data(|m| {
+            let mut ret = Dictionary::new();
+            ret.set("strategy", m.strategy);
+            Ok(ret)
+        })
+        .unwrap_or_default()
+    }
+
     /// Gets the module name, if exists.
     #[func]
     #[instrument(ret)]
@@ -541,6 +1711,95 @@ impl WasmModule {
         .unwrap_or_default()
     }
 
+    /// Gets all functions imported by component, formatted as `module.interface.method`.
+    ///
+    /// Returns empty array if module is not a component.
+    #[func]
+    #[instrument]
+    fn get_component_imports(&self) -> PackedStringArray {
+        cfg_if! {
+            if #[cfg(feature = "component-model")] {
+                self.unwrap_data(|m| {
+                    Ok(match m.module {
+                        ModuleType::Component(_) => m
+                            .module
+                            .get_component_imports()?
+                            .into_iter()
+                            .map(|v| GString::from(v.to_string()))
+                            .collect(),
+                        _ => PackedStringArray::new(),
+                    })
+                })
+                .unwrap_or_default()
+            } else {
+                PackedStringArray::new()
+            }
+        }
+    }
+
+    /// Gets names of custom sections, in order of appearance.
+    ///
+    /// Only sections of outermost module or component are listed.
+    /// Deserialized modules and modules loaded from WAT text have no custom sections.
+    #[func]
+    #[instrument]
+    fn list_custom_sections(&self) -> PackedStringArray {
+        self.unwrap_data(|m| {
+            Ok(m.custom_sections
+                .iter()
+                .map(|s| GString::from(&s.name))
+                .collect())
+        })
+        .unwrap_or_default()
+    }
+
+    /// Gets content of custom section.
+    ///
+    /// If there are multiple sections with the same name, the first one is returned.
+    /// Errors if section does not exist or it's larger than `custom_sections.max_bytes`.
+    ///
+    #[func]
+    #[instrument]
+    fn get_custom_section(&self, name: GString) -> PackedByteArray {
+        self.unwrap_data(|m| {
+            let name = name.to_string();
+            let Some(s) = m.custom_sections.iter().find(|s| s.name == name) else {
+                bail_with_site!("Custom section {name:?} not found")
+            };
+            match &s.data {
+                Some(v) => Ok(PackedByteArray::from(&v[..])),
+                None => bail_with_site!("Custom section {name:?} is too large ({} bytes)", s.size),
+            }
+        })
+        .unwrap_or_default()
+    }
+
+    /// Generates filter dictionary containing all imported filterable methods.
+    ///
+    /// Arguments:
+    /// - `default_allow` : Value of every entry in the filter.
+    #[func]
+    #[instrument]
+    fn generate_filter_template(&self, default_allow: bool) -> Dictionary {
+        cfg_if! {
+            if #[cfg(feature = "godot-component")] {
+                self.unwrap_data(|m| {
+                    Ok(match m.module {
+                        ModuleType::Component(_) => crate::godot_component::filter::filter_template(
+                            &m.module.get_component_imports()?,
+                            default_allow,
+                        ),
+                        _ => Dictionary::new(),
+                    })
+                })
+                .unwrap_or_default()
+            } else {
+                let _ = default_allow;
+                Dictionary::new()
+            }
+        }
+    }
+
     /// Deserialize compiled module data.
     ///
     /// **⚠ DO NOT USE THIS WITH UNTRUSTED DATA**
@@ -611,6 +1870,8 @@ impl WasmModule {
     /// with the value is a struct of the following:
     /// - `params` : Array of parameter types.
     /// - `results` : Array of result types.
+    ///
+    /// For component, function name is fully-qualified and types are described as dictionary.
     #[func]
     #[instrument]
     fn get_exports(&self) -> Dictionary {
@@ -619,10 +1880,27 @@ impl WasmModule {
             let mut ret = Dictionary::new();
             let params_str = StringName::from(c"params");
             let results_str = StringName::from(c"results");
-            for i in site_context!(m.module.get_core())?.exports() {
+            let module = match &m.module {
+                ModuleType::Core(v) => v,
+                #[cfg(feature = "component-model")]
+                ModuleType::Component(v) => {
+                    let exports = ComponentExports::new(v);
+                    for (name, f) in &exports.funcs {
+                        debug!(name, "Exported component function");
+                        ret.set(name.as_str(), exports.func_schema(f).to_variant());
+                    }
+                    return Ok(ret);
+                }
+            };
+            for i in module.exports() {
                 let ExternType::Func(f) = i.ty() else {
                     continue;
                 };
+                // Skip functions that can never be called in this build.
+                if unsupported_ref_type(&f, cfg!(feature = "object-registry-extern")).is_some() {
+                    debug!(name = i.name(), "type" = %f, "Skipping unconvertible function");
+                    continue;
+                }
                 debug!(name = i.name(), "type" = %f, "Exported function");
 
                 let (p, r) = from_signature(&f);
@@ -700,23 +1978,42 @@ impl WasmModule {
     #[instrument(ret)]
     fn has_function(&self, name: StringName) -> bool {
         self.unwrap_data(|m| {
-            Ok(matches!(
-                site_context!(m.module.get_core())?.get_export(&name.to_string()),
-                Some(ExternType::Func(_))
-            ))
+            Ok(match &m.module {
+                ModuleType::Core(v) => {
+                    matches!(v.get_export(&name.to_string()), Some(ExternType::Func(_)))
+                }
+                #[cfg(feature = "component-model")]
+                ModuleType::Component(v) => {
+                    ComponentExports::new(v).get(&name.to_string()).is_some()
+                }
+            })
         })
         .unwrap_or_default()
     }
 
     /// Gets the signature of exported function.
+    ///
+    /// For component, parameters are array of `name` and `type`,
+    /// and types are described as dictionary.
     #[func]
     #[instrument]
     fn get_signature(&self, name: StringName) -> Dictionary {
         self.unwrap_data(|m| {
             let _s = debug_span!("get_signature.inner").entered();
-            let Some(ExternType::Func(f)) =
-                site_context!(m.module.get_core())?.get_export(&name.to_string())
-            else {
+            let module = match &m.module {
+                ModuleType::Core(v) => v,
+                #[cfg(feature = "component-model")]
+                ModuleType::Component(v) => {
+                    let exports = ComponentExports::new(v);
+                    let Some(f) = exports.get(&name.to_string()) else {
+                        bail_with_site!("No function named {}", name);
+                    };
+                    return Ok(Dictionary::from_variant(
+                        &exports.func_schema(f).to_variant(),
+                    ));
+                }
+            };
+            let Some(ExternType::Func(f)) = module.get_export(&name.to_string()) else {
                 bail_with_site!("No function named {}", name);
             };
             debug!(signature = %f);
@@ -857,3 +2154,479 @@ impl WasmModule {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "wasi")]
+    use std::cell::Cell;
+
+    #[cfg(feature = "wasi")]
+    use wasi_isolated_fs::bindings::wasi_snapshot_preview1::add_to_linker;
+    #[cfg(feature = "wasi")]
+    use wasmtime::{Linker, Store};
+
+    #[cfg(feature = "wasi")]
+    use crate::wasm_instance::StoreData;
+
+    /// Serializes tests using epoch thread and priority counters, which are global.
+    #[cfg(feature = "epoch-timeout")]
+    static EPOCH_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    #[cfg(feature = "epoch-timeout")]
+    #[test]
+    fn test_epoch_slice() {
+        assert!(epoch_slice(EpochPriority::Low) <= epoch_slice(EpochPriority::Normal));
+        assert!(epoch_slice(EpochPriority::Normal) <= epoch_slice(EpochPriority::High));
+
+        let v = EpochDeadline {
+            priority: EpochPriority::High,
+            end: 100,
+            timed: true,
+        };
+        assert_eq!(v.next_slice(100), None);
+        assert_eq!(v.next_slice(101), None);
+        assert_eq!(v.next_slice(99), Some(1));
+        assert_eq!(v.next_slice(0), Some(epoch_slice(EpochPriority::High)));
+    }
+
+    #[cfg(feature = "epoch-timeout")]
+    #[test]
+    fn test_low_priority_yield() {
+        let _lock = EPOCH_TEST_LOCK.lock();
+
+        // Highest priority call never yields.
+        assert!(!yield_to_priority(EpochPriority::High));
+
+        let guard = enter_priority(&EpochDeadline {
+            priority: EpochPriority::High,
+            ..EpochDeadline::default()
+        });
+        // Lower priority call yields while higher priority call is running,
+        // but only for bounded time.
+        assert!(thread::spawn(|| yield_to_priority(EpochPriority::Normal))
+            .join()
+            .unwrap());
+        assert!(!yield_to_priority(EpochPriority::High));
+        drop(guard);
+        assert!(!yield_to_priority(EpochPriority::Normal));
+    }
+
+    #[cfg(feature = "epoch-timeout")]
+    #[test]
+    fn test_epoch_park() {
+        const WAT: &str = r#"(module (func (export "spin") (loop br 0)))"#;
+
+        let _lock = EPOCH_TEST_LOCK.lock();
+        let wait = |f: &dyn Fn(&EpochDemand) -> bool| {
+            let end = time::Instant::now() + time::Duration::from_secs(5);
+            while !f(&EPOCH_DEMAND.lock()) {
+                assert!(
+                    time::Instant::now() < end,
+                    "Timed out waiting for epoch thread"
+                );
+                thread::sleep(EPOCH_INTERVAL);
+            }
+        };
+
+        *ENGINE.write() = Some((Engine::new(&engine_config()).unwrap(), None));
+        start_epoch().unwrap();
+
+        // Idle ticker parks itself.
+        wait(&|d| d.parked);
+        let parks = EPOCH_DEMAND.lock().park_count;
+        let epoch = current_epoch();
+        {
+            let _guard = arm_deadline();
+            wait(&|d| !d.parked);
+            let end = time::Instant::now() + time::Duration::from_secs(5);
+            while current_epoch() < epoch + 2 {
+                assert!(time::Instant::now() < end, "Epoch does not advance");
+                thread::sleep(EPOCH_INTERVAL);
+            }
+        }
+        wait(&|d| d.parked && d.park_count > parks);
+
+        // Untimed call does not arm deadline.
+        {
+            let _guard = enter_priority(&EpochDeadline::default());
+            assert_eq!(EPOCH_DEMAND.lock().armed, 0);
+        }
+
+        // Timed call wakes parked ticker and times out.
+        let engine = get_engine().unwrap();
+        let module = Module::new(&engine, WAT).unwrap();
+        let mut store = wasmtime::Store::new(&engine, ());
+        store.epoch_deadline_trap();
+        let f = wasmtime::Instance::new(&mut store, &module, &[])
+            .unwrap()
+            .get_typed_func::<(), ()>(&mut store, "spin")
+            .unwrap();
+        let e = {
+            let _guard = enter_priority(&EpochDeadline {
+                timed: true,
+                ..EpochDeadline::default()
+            });
+            store.set_epoch_deadline(2);
+            f.call(&mut store, ()).unwrap_err()
+        };
+        assert_eq!(
+            e.downcast_ref::<wasmtime::Trap>(),
+            Some(&wasmtime::Trap::Interrupt)
+        );
+        wait(&|d| d.parked);
+
+        if let Some((_, Some(handle))) = ENGINE.write().take() {
+            wake_epoch();
+            handle.join().unwrap();
+        }
+    }
+
+    #[cfg(feature = "epoch-timeout")]
+    #[test]
+    fn test_priority_preemption() {
+        const WAT: &str = r#"(module
+            (func (export "spin") (loop br 0))
+            (func (export "count") (param i32) (result i32)
+                (loop $l
+                    (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                    (br_if $l (local.get 0)))
+                local.get 0))"#;
+
+        let _lock = EPOCH_TEST_LOCK.lock();
+        *ENGINE.write() = Some((Engine::new(&engine_config()).unwrap(), None));
+        start_epoch().unwrap();
+        let engine = get_engine().unwrap();
+        let module = Module::new(&engine, WAT).unwrap();
+
+        let timed_store = |priority, timeout: time::Duration| {
+            let mut store = wasmtime::Store::new(
+                &engine,
+                crate::wasm_instance::StoreData {
+                    epoch_timeout: (timeout.as_nanos() / EPOCH_INTERVAL.as_nanos()) as u64,
+                    ..Default::default()
+                },
+            );
+            let config = crate::wasm_config::Config {
+                with_epoch: true,
+                epoch_priority: priority,
+                ..Default::default()
+            };
+            wasm_util::config_store_epoch(&mut store, &config).unwrap();
+            store
+        };
+
+        // Low priority call spins in worker thread until interrupted.
+        let mut low = timed_store(EpochPriority::Low, time::Duration::from_secs(60));
+        let activity = low.data().activity.clone();
+        let spin = wasmtime::Instance::new(&mut low, &module, &[])
+            .unwrap()
+            .get_typed_func::<(), ()>(&mut low, "spin")
+            .unwrap();
+        let worker = thread::spawn(move || {
+            let _guard = enter_priority(&low.data().epoch_deadline);
+            spin.call(&mut low, ()).unwrap_err()
+        });
+        let end = time::Instant::now() + time::Duration::from_secs(5);
+        while ACTIVE_CALLS[EpochPriority::Low as usize].load(Ordering::Acquire) == 0 {
+            assert!(
+                time::Instant::now() < end,
+                "Low priority call does not start"
+            );
+            thread::sleep(EPOCH_INTERVAL);
+        }
+
+        // High priority call is not starved by it, and finishes well before it's deadline.
+        let mut high = timed_store(EpochPriority::High, time::Duration::from_secs(30));
+        let count = wasmtime::Instance::new(&mut high, &module, &[])
+            .unwrap()
+            .get_typed_func::<i32, i32>(&mut high, "count")
+            .unwrap();
+        let start = time::Instant::now();
+        {
+            let _guard = enter_priority(&high.data().epoch_deadline);
+            assert_eq!(count.call(&mut high, 50_000_000).unwrap(), 0);
+        }
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed < time::Duration::from_secs(10),
+            "High priority call takes too long ({elapsed:?})"
+        );
+
+        assert!(!worker.is_finished());
+        activity.interrupt();
+        let e = worker.join().unwrap();
+        assert_eq!(
+            e.downcast_ref::<wasmtime::Trap>(),
+            Some(&wasmtime::Trap::Interrupt)
+        );
+
+        if let Some((_, Some(handle))) = ENGINE.write().take() {
+            wake_epoch();
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_linker_key_distinct() {
+        let keys = [
+            LinkerKey::default(),
+            LinkerKey {
+                wasi: true,
+                ..LinkerKey::default()
+            },
+            LinkerKey {
+                wasi_command: true,
+                ..LinkerKey::default()
+            },
+            LinkerKey {
+                wasi_command: true,
+                godot_component: true,
+                ..LinkerKey::default()
+            },
+            LinkerKey {
+                godot_component: true,
+                ..LinkerKey::default()
+            },
+        ];
+        for (i, a) in keys.iter().enumerate() {
+            for (j, b) in keys.iter().enumerate() {
+                assert_eq!(i == j, a == b, "{a:?} {b:?}");
+            }
+        }
+    }
+
+    #[cfg(feature = "memory-limiter")]
+    #[test]
+    fn test_global_memory_budget() {
+        use wasmtime::ResourceLimiter;
+
+        use crate::wasm_instance::MemoryLimit;
+
+        // Separate budget, so other tests are not affected.
+        static BUDGET: MemoryBudget = MemoryBudget::new();
+        BUDGET.set_max(Some(1000));
+        let limit = |max_memory| MemoryLimit {
+            max_memory,
+            reserved: MemoryReservation::new(&BUDGET),
+            ..MemoryLimit::default()
+        };
+
+        let mut a = limit(u64::MAX);
+        let mut b = limit(300);
+        assert!(a.memory_growing(0, 600, None).unwrap());
+        // Denied by global budget.
+        assert!(!a.memory_growing(600, 1200, None).unwrap());
+        // Denied by local limit, global budget is not consumed.
+        assert!(!b.memory_growing(0, 400, None).unwrap());
+        assert_eq!(BUDGET.used(), 600);
+        assert!(b.memory_growing(0, 300, None).unwrap());
+        assert_eq!(BUDGET.used(), 900);
+
+        // Dropping store releases it's memory.
+        drop(a);
+        assert_eq!(BUDGET.used(), 300);
+        let mut r = MemoryReservation::new(&BUDGET);
+        assert!(r.grow(700, None));
+        assert!(!r.grow(1, None));
+        assert_eq!(r.size(), 700);
+
+        drop((b, r));
+        assert_eq!(BUDGET.used(), 0);
+
+        // Lowering budget does not affect existing reservation.
+        let mut r = MemoryReservation::new(&BUDGET);
+        assert!(r.grow(500, None));
+        BUDGET.set_max(Some(100));
+        assert_eq!(BUDGET.max(), Some(100));
+        assert!(!r.grow(1, None));
+        assert_eq!(r.size(), 500);
+        drop(r);
+        BUDGET.set_max(None);
+        assert_eq!(BUDGET.max(), None);
+        assert!(MemoryReservation::new(&BUDGET).grow(u64::MAX, None));
+    }
+
+    #[cfg(feature = "wasi")]
+    #[test]
+    fn test_linker_cache() {
+        const N: usize = 100;
+        const WAT: &str = r#"(module
+  (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+)"#;
+        let key = LinkerKey {
+            wasi: true,
+            ..LinkerKey::default()
+        };
+        let engine = Engine::default();
+        let module = Module::new(&engine, WAT).unwrap();
+        let built = Cell::new(0);
+        let build = || -> AnyResult<_> {
+            built.set(built.get() + 1);
+            let mut r = <Linker<StoreData>>::new(&engine);
+            add_to_linker(&mut r, |data: &mut StoreData| {
+                data.wasi_ctx.as_mut().unwrap()
+            })?;
+            Ok(r)
+        };
+
+        for _ in 0..N {
+            let mut store = Store::new(&engine, StoreData::default());
+            get_linker(&engine, key, &build)
+                .unwrap()
+                .instantiate(&mut store, &module)
+                .unwrap();
+        }
+        assert_eq!(built.get(), 1);
+
+        // Different engine must not reuse linker.
+        let engine2 = Engine::default();
+        get_linker(&engine2, key, || Ok(<Linker<StoreData>>::new(&engine2))).unwrap();
+        assert!(Engine::same(
+            get_linker::<Linker<StoreData>, _>(&engine2, key, || unreachable!())
+                .unwrap()
+                .engine(),
+            &engine2
+        ));
+        // Nor evict linker of other engine.
+        get_linker(&engine, key, &build).unwrap();
+        assert_eq!(built.get(), 1);
+    }
+
+    #[test]
+    fn test_compile_strategy_resolve() {
+        let max = CompileStrategy::WINCH_AUTO_MAX_SIZE;
+        assert_eq!(CompileStrategy::Auto.resolve(max), CompileStrategy::Winch);
+        assert_eq!(
+            CompileStrategy::Auto.resolve(max + 1),
+            CompileStrategy::Cranelift
+        );
+        assert_eq!(
+            CompileStrategy::Cranelift.resolve(0),
+            CompileStrategy::Cranelift
+        );
+        assert_eq!(
+            CompileStrategy::Winch.resolve(max + 1),
+            CompileStrategy::Winch
+        );
+    }
+
+    #[test]
+    fn test_deps_strategy() {
+        use CompileStrategy::*;
+
+        assert_eq!(deps_strategy(None, None).unwrap(), None);
+        assert_eq!(deps_strategy(Some(Auto), None).unwrap(), Some(Auto));
+        // Imported modules decide strategy.
+        assert_eq!(deps_strategy(None, Some(Winch)).unwrap(), Some(Winch));
+        assert_eq!(deps_strategy(Some(Auto), Some(Winch)).unwrap(), Some(Winch));
+        assert_eq!(
+            deps_strategy(Some(Cranelift), Some(Cranelift)).unwrap(),
+            Some(Cranelift)
+        );
+        let e = deps_strategy(Some(Cranelift), Some(Winch))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            e.contains("requested to be compiled with cranelift")
+                && e.contains("compiled with winch"),
+            "{e}"
+        );
+    }
+
+    #[cfg(all(
+        feature = "winch",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn test_compile_strategy() {
+        use wasmtime::{Instance, Store};
+
+        const WAT: &str = r#"(module
+  (memory (export "memory") 1)
+  (func (export "sum") (param $n i32) (result i32) (local $r i32)
+    (block $end
+      (loop $loop
+        (br_if $end (i32.eqz (local.get $n)))
+        (local.set $r (i32.add (local.get $r) (local.get $n)))
+        (i32.store (i32.const 0) (local.get $r))
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br $loop)))
+    (i32.load (i32.const 0)))
+)"#;
+        const WAT_SHARED: &str = r#"(module (memory 1 1 shared))"#;
+
+        let cranelift = Engine::new(&engine_config()).unwrap();
+        let winch = Engine::new(&winch_config(engine_config())).unwrap();
+        for engine in [&cranelift, &winch] {
+            let module = Module::new(engine, WAT).unwrap();
+            let mut store = Store::new(engine, ());
+            store.set_epoch_deadline(1 << 32);
+            let inst = Instance::new(&mut store, &module, &[]).unwrap();
+            let f = inst.get_typed_func::<i32, i32>(&mut store, "sum").unwrap();
+            assert_eq!(f.call(&mut store, 100).unwrap(), 5050);
+        }
+
+        // Unsupported feature must fail, so loader can fall back to Cranelift.
+        Module::new(&cranelift, WAT_SHARED).unwrap();
+        assert!(Module::new(&winch, WAT_SHARED).is_err());
+    }
+
+    #[cfg(feature = "pulley")]
+    #[test]
+    fn test_pulley() {
+        use wasmtime::{Instance, Store};
+
+        const WAT: &str = r#"(module
+  (memory (export "memory") 1)
+  (table 2 funcref)
+  (elem (i32.const 0) $add $mul)
+  (type $op (func (param i64 i64) (result i64)))
+  (func $add (type $op) (i64.add (local.get 0) (local.get 1)))
+  (func $mul (type $op) (i64.mul (local.get 0) (local.get 1)))
+  (func (export "fold") (param $op i32) (param $n i64) (result i64) (local $r i64)
+    (local.set $r (i64.extend_i32_u (local.get $op)))
+    (block $end
+      (loop $loop
+        (br_if $end (i64.eqz (local.get $n)))
+        (local.set $r (call_indirect (type $op) (local.get $r) (local.get $n) (local.get $op)))
+        (i64.store (i32.const 8) (local.get $r))
+        (local.set $n (i64.sub (local.get $n) (i64.const 1)))
+        (br $loop)))
+    (i64.load (i32.const 8)))
+)"#;
+
+        let jit = Engine::new(&engine_config()).unwrap();
+        assert!(jit_available(&jit));
+        let (pulley, backend) = new_engine(&engine_config(), ExecutionBackend::Pulley).unwrap();
+        assert_eq!(backend, ExecutionBackend::Pulley);
+
+        for engine in [&jit, &pulley] {
+            let module = Module::new(engine, WAT).unwrap();
+            let mut store = Store::new(engine, ());
+            store.set_epoch_deadline(1 << 32);
+            let inst = Instance::new(&mut store, &module, &[]).unwrap();
+            let f = inst
+                .get_typed_func::<(i32, i64), i64>(&mut store, "fold")
+                .unwrap();
+            assert_eq!(f.call(&mut store, (0, 100)).unwrap(), 5050);
+            assert_eq!(f.call(&mut store, (1, 10)).unwrap(), 3628800);
+        }
+
+        // Artifacts are not interchangeable between backends.
+        let key = |engine: &Engine| {
+            let mut h = DefaultHasher::new();
+            engine.precompile_compatibility_hash().hash(&mut h);
+            h.finish()
+        };
+        assert_ne!(key(&jit), key(&pulley));
+        let data = Module::new(&jit, WAT).unwrap().serialize().unwrap();
+        // SAFETY: Data is produced by wasmtime.
+        assert!(unsafe { Module::deserialize(&pulley, &data) }.is_err());
+        let data = Module::new(&pulley, WAT).unwrap().serialize().unwrap();
+        // SAFETY: Data is produced by wasmtime.
+        unsafe { Module::deserialize(&pulley, &data) }.unwrap();
+    }
+}
@@ -0,0 +1,494 @@
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+use godot::classes::ProjectSettings;
+use godot::global::PropertyHint;
+use godot::prelude::*;
+use parking_lot::Mutex;
+use tracing::{info, instrument, warn};
+
+use crate::variant_dispatch;
+use crate::wasm_config::ExecutionBackend;
+
+pub const SETTING_EXECUTION_BACKEND: &str = "godot_wasm/execution/backend";
+pub const SETTING_WASM_STACK_BYTES: &str = "godot_wasm/limits/wasm_stack_bytes";
+pub const SETTING_ASYNC_STACK_BYTES: &str = "godot_wasm/limits/async_stack_bytes";
+pub const SETTING_EPOCH_TICKER: &str = "godot_wasm/engine/epoch_ticker";
+pub const SETTING_POOLING_ALLOCATOR: &str = "godot_wasm/engine/pooling_allocator";
+pub const SETTING_CONSUME_FUEL: &str = "godot_wasm/engine/consume_fuel";
+pub const SETTING_IDLE_UNLOAD_SECONDS: &str = "godot_wasm/engine/idle_unload_seconds";
+
+/// Project setting value, decoupled from Godot variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    /// Value of any other type, with it's type name.
+    Other(String),
+}
+
+impl SettingValue {
+    pub fn from_variant(v: &Variant) -> Self {
+        variant_dispatch!(v {
+            BOOL => Self::Bool(v),
+            INT => Self::Int(v),
+            STRING => Self::String(v.to_string()),
+            STRING_NAME => Self::String(v.to_string()),
+            _ => Self::Other(format!("{:?}", v.get_type())),
+        })
+    }
+}
+
+impl Display for SettingValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Bool(v) => write!(f, "{v}"),
+            Self::Int(v) => write!(f, "{v}"),
+            Self::String(v) => write!(f, "{v:?}"),
+            Self::Other(t) => write!(f, "<{t}>"),
+        }
+    }
+}
+
+/// Engine configuration, read once from project settings before engine is constructed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineInitConfig {
+    pub backend: ExecutionBackend,
+    /// Maximum WASM stack size. `None` uses wasmtime default.
+    pub wasm_stack_bytes: Option<usize>,
+    /// Stack size of asynchronous calls. `None` uses wasmtime default.
+    pub async_stack_bytes: Option<usize>,
+    /// Runs epoch ticker thread. Without it, epoch timeouts never fire.
+    pub epoch_ticker: bool,
+    /// Uses pooling instance allocator.
+    pub pooling_allocator: bool,
+    /// Enables fuel metering.
+    pub consume_fuel: bool,
+    /// Idle time before instances are requested to unload. `None` disables idle reaper.
+    pub idle_unload_seconds: Option<usize>,
+}
+
+impl Default for EngineInitConfig {
+    fn default() -> Self {
+        Self {
+            backend: ExecutionBackend::Jit,
+            wasm_stack_bytes: None,
+            async_stack_bytes: None,
+            epoch_ticker: true,
+            pooling_allocator: false,
+            consume_fuel: false,
+            idle_unload_seconds: None,
+        }
+    }
+}
+
+fn parse_bool(name: &str, v: SettingValue, warnings: &mut Vec<String>) -> Option<bool> {
+    match v {
+        SettingValue::Bool(v) => Some(v),
+        v => {
+            warnings.push(format!("{name} must be a bool (got {v})"));
+            None
+        }
+    }
+}
+
+fn parse_size(name: &str, v: SettingValue, warnings: &mut Vec<String>) -> Option<Option<usize>> {
+    match v {
+        SettingValue::Int(0) => Some(None),
+        SettingValue::Int(v @ 1..) => match usize::try_from(v) {
+            Ok(v) => Some(Some(v)),
+            Err(_) => {
+                warnings.push(format!("{name} is too large (got {v})"));
+                None
+            }
+        },
+        v => {
+            warnings.push(format!("{name} must be a non-negative int (got {v})"));
+            None
+        }
+    }
+}
+
+fn parse_backend(
+    name: &str,
+    v: SettingValue,
+    warnings: &mut Vec<String>,
+) -> Option<ExecutionBackend> {
+    if let SettingValue::String(s) = &v {
+        match &*s.to_lowercase() {
+            "" | "jit" => return Some(ExecutionBackend::Jit),
+            "pulley" if cfg!(feature = "pulley") => return Some(ExecutionBackend::Pulley),
+            "pulley" => {
+                warnings.push(format!("{name}: Pulley backend requires feature pulley"));
+                return None;
+            }
+            _ => (),
+        }
+    }
+    warnings.push(format!("{name} must be \"jit\" or \"pulley\" (got {v})"));
+    None
+}
+
+impl EngineInitConfig {
+    /// Parses config from settings getter.
+    ///
+    /// Invalid value falls back to default, with a warning for each of them.
+    pub fn parse(get: impl Fn(&str) -> Option<SettingValue>) -> (Self, Vec<String>) {
+        let mut ret = Self::default();
+        let mut warnings = Vec::new();
+        let w = &mut warnings;
+
+        macro_rules! field {
+            ($f:ident, $k:expr, $p:ident) => {
+                if let Some(v) = get($k).and_then(|v| $p($k, v, w)) {
+                    ret.$f = v;
+                }
+            };
+        }
+        field!(backend, SETTING_EXECUTION_BACKEND, parse_backend);
+        field!(wasm_stack_bytes, SETTING_WASM_STACK_BYTES, parse_size);
+        field!(async_stack_bytes, SETTING_ASYNC_STACK_BYTES, parse_size);
+        field!(epoch_ticker, SETTING_EPOCH_TICKER, parse_bool);
+        field!(pooling_allocator, SETTING_POOLING_ALLOCATOR, parse_bool);
+        field!(consume_fuel, SETTING_CONSUME_FUEL, parse_bool);
+        field!(idle_unload_seconds, SETTING_IDLE_UNLOAD_SECONDS, parse_size);
+
+        (ret, warnings)
+    }
+
+    /// Reads config from project settings, emitting warnings of invalid values.
+    #[instrument(ret)]
+    pub fn from_project_settings() -> Self {
+        let (ret, warnings) = Self::parse(read_setting);
+        for w in warnings {
+            warn!("{w}, using default");
+            godot_warn!("{w}, using default");
+        }
+        ret
+    }
+
+    /// Names of settings that differs from `other`.
+    pub fn diff(&self, other: &Self) -> Vec<&'static str> {
+        let mut ret = Vec::new();
+        if self.backend != other.backend {
+            ret.push(SETTING_EXECUTION_BACKEND);
+        }
+        if self.wasm_stack_bytes != other.wasm_stack_bytes {
+            ret.push(SETTING_WASM_STACK_BYTES);
+        }
+        if self.async_stack_bytes != other.async_stack_bytes {
+            ret.push(SETTING_ASYNC_STACK_BYTES);
+        }
+        if self.epoch_ticker != other.epoch_ticker {
+            ret.push(SETTING_EPOCH_TICKER);
+        }
+        if self.pooling_allocator != other.pooling_allocator {
+            ret.push(SETTING_POOLING_ALLOCATOR);
+        }
+        if self.consume_fuel != other.consume_fuel {
+            ret.push(SETTING_CONSUME_FUEL);
+        }
+        if self.idle_unload_seconds != other.idle_unload_seconds {
+            ret.push(SETTING_IDLE_UNLOAD_SECONDS);
+        }
+        ret
+    }
+
+    pub fn to_dict(&self) -> Dictionary {
+        let mut ret = Dictionary::new();
+        ret.set("backend", self.backend);
+        ret.set(
+            "wasm_stack_bytes",
+            self.wasm_stack_bytes.map_or(0, |v| v as i64),
+        );
+        ret.set(
+            "async_stack_bytes",
+            self.async_stack_bytes.map_or(0, |v| v as i64),
+        );
+        ret.set("epoch_ticker", self.epoch_ticker);
+        ret.set("pooling_allocator", self.pooling_allocator);
+        ret.set("consume_fuel", self.consume_fuel);
+        ret.set(
+            "idle_unload_seconds",
+            self.idle_unload_seconds.map_or(0, |v| v as i64),
+        );
+        ret
+    }
+}
+
+fn read_setting(name: &str) -> Option<SettingValue> {
+    let settings = ProjectSettings::singleton();
+    let k = GString::from(name);
+    if settings.has_setting(&k) {
+        Some(SettingValue::from_variant(&settings.get_setting(&k)))
+    } else {
+        None
+    }
+}
+
+/// Registers engine settings, so they show in project settings dialog.
+#[instrument]
+pub fn register_settings() {
+    const SIZE_HINT: &str = "0,1073741824,1,or_greater,suffix:B";
+    let infos: [(&str, Variant, VariantType, PropertyHint, &str); 7] = [
+        (
+            SETTING_EXECUTION_BACKEND,
+            "jit".to_variant(),
+            VariantType::STRING,
+            PropertyHint::ENUM,
+            "jit,pulley",
+        ),
+        (
+            SETTING_WASM_STACK_BYTES,
+            0.to_variant(),
+            VariantType::INT,
+            PropertyHint::RANGE,
+            SIZE_HINT,
+        ),
+        (
+            SETTING_ASYNC_STACK_BYTES,
+            0.to_variant(),
+            VariantType::INT,
+            PropertyHint::RANGE,
+            SIZE_HINT,
+        ),
+        (
+            SETTING_EPOCH_TICKER,
+            true.to_variant(),
+            VariantType::BOOL,
+            PropertyHint::NONE,
+            "",
+        ),
+        (
+            SETTING_POOLING_ALLOCATOR,
+            false.to_variant(),
+            VariantType::BOOL,
+            PropertyHint::NONE,
+            "",
+        ),
+        (
+            SETTING_CONSUME_FUEL,
+            false.to_variant(),
+            VariantType::BOOL,
+            PropertyHint::NONE,
+            "",
+        ),
+        (
+            SETTING_IDLE_UNLOAD_SECONDS,
+            0.to_variant(),
+            VariantType::INT,
+            PropertyHint::RANGE,
+            "0,86400,1,or_greater,suffix:s",
+        ),
+    ];
+
+    let mut settings = ProjectSettings::singleton();
+    for (name, default, ty, hint, hint_string) in infos {
+        let k = GString::from(name);
+        if !settings.has_setting(&k) {
+            settings.set_setting(&k, &default);
+        }
+        settings.set_initial_value(&k, &default);
+        settings.set_restart_if_changed(&k, true);
+
+        let mut info = Dictionary::new();
+        info.set("name", k);
+        info.set("type", ty.ord());
+        info.set("hint", hint.ord());
+        info.set("hint_string", hint_string);
+        settings.add_property_info(&info);
+    }
+}
+
+struct WatchState {
+    /// Config used to construct engine.
+    requested: EngineInitConfig,
+    /// Last config warned about.
+    warned: EngineInitConfig,
+}
+
+static WATCH: Mutex<Option<WatchState>> = Mutex::new(None);
+
+/// Starts warning whenever settings are changed from `requested`.
+pub fn watch_settings(requested: EngineInitConfig) {
+    *WATCH.lock() = Some(WatchState {
+        requested,
+        warned: requested,
+    });
+    ProjectSettings::singleton().connect(
+        &StringName::from(c"settings_changed"),
+        &Callable::from_custom(SettingsWatcher),
+    );
+}
+
+pub fn unwatch_settings() {
+    if WATCH.lock().take().is_some() {
+        ProjectSettings::singleton().disconnect(
+            &StringName::from(c"settings_changed"),
+            &Callable::from_custom(SettingsWatcher),
+        );
+    }
+}
+
+/// Returns names of changed settings, if it has not been warned yet.
+fn check_changed(state: &mut WatchState, current: EngineInitConfig) -> Option<Vec<&'static str>> {
+    if current == state.warned {
+        return None;
+    }
+    state.warned = current;
+    let diff = current.diff(&state.requested);
+    if diff.is_empty() {
+        None
+    } else {
+        Some(diff)
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct SettingsWatcher;
+
+impl Debug for SettingsWatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("SettingsWatcher")
+    }
+}
+
+impl Display for SettingsWatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("SettingsWatcher")
+    }
+}
+
+impl RustCallable for SettingsWatcher {
+    fn invoke(&mut self, _: &[&Variant]) -> Result<Variant, ()> {
+        let mut guard = WATCH.lock();
+        let Some(state) = &mut *guard else {
+            return Ok(Variant::nil());
+        };
+        let (current, _) = EngineInitConfig::parse(read_setting);
+        if let Some(diff) = check_changed(state, current) {
+            let diff = diff.join(", ");
+            info!(settings = diff, "Engine settings changed");
+            godot_warn!("Engine settings changed ({diff}), restart is required to apply them");
+        }
+        Ok(Variant::nil())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    fn parse(v: &[(&str, SettingValue)]) -> (EngineInitConfig, Vec<String>) {
+        let m = v.iter().cloned().collect::<HashMap<_, _>>();
+        EngineInitConfig::parse(|k| m.get(k).cloned())
+    }
+
+    #[test]
+    fn test_parse_default() {
+        let (c, w) = parse(&[]);
+        assert_eq!(c, EngineInitConfig::default());
+        assert!(w.is_empty());
+    }
+
+    #[test]
+    fn test_parse_valid() {
+        let (c, w) = parse(&[
+            (
+                SETTING_EXECUTION_BACKEND,
+                SettingValue::String("JIT".into()),
+            ),
+            (SETTING_WASM_STACK_BYTES, SettingValue::Int(1 << 20)),
+            (SETTING_ASYNC_STACK_BYTES, SettingValue::Int(0)),
+            (SETTING_EPOCH_TICKER, SettingValue::Bool(false)),
+            (SETTING_POOLING_ALLOCATOR, SettingValue::Bool(true)),
+            (SETTING_CONSUME_FUEL, SettingValue::Bool(true)),
+            (SETTING_IDLE_UNLOAD_SECONDS, SettingValue::Int(300)),
+        ]);
+        assert!(w.is_empty(), "{w:?}");
+        assert_eq!(
+            c,
+            EngineInitConfig {
+                backend: ExecutionBackend::Jit,
+                wasm_stack_bytes: Some(1 << 20),
+                async_stack_bytes: None,
+                epoch_ticker: false,
+                pooling_allocator: true,
+                consume_fuel: true,
+                idle_unload_seconds: Some(300),
+            }
+        );
+
+        let (c, w) = parse(&[(
+            SETTING_EXECUTION_BACKEND,
+            SettingValue::String("pulley".into()),
+        )]);
+        if cfg!(feature = "pulley") {
+            assert_eq!(c.backend, ExecutionBackend::Pulley);
+            assert!(w.is_empty());
+        } else {
+            assert_eq!(c.backend, ExecutionBackend::Jit);
+            assert_eq!(w.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let cases = [
+            (
+                SETTING_EXECUTION_BACKEND,
+                SettingValue::String("llvm".into()),
+            ),
+            (SETTING_EXECUTION_BACKEND, SettingValue::Int(1)),
+            (SETTING_WASM_STACK_BYTES, SettingValue::Int(-1)),
+            (SETTING_WASM_STACK_BYTES, SettingValue::String("1M".into())),
+            (SETTING_ASYNC_STACK_BYTES, SettingValue::Bool(true)),
+            (SETTING_EPOCH_TICKER, SettingValue::Int(0)),
+            (
+                SETTING_POOLING_ALLOCATOR,
+                SettingValue::String("true".into()),
+            ),
+            (SETTING_CONSUME_FUEL, SettingValue::Other("Vector2".into())),
+            (SETTING_IDLE_UNLOAD_SECONDS, SettingValue::Int(-5)),
+        ];
+        for (k, v) in cases {
+            let (c, w) = parse(&[(k, v.clone())]);
+            assert_eq!(c, EngineInitConfig::default(), "{k} = {v}");
+            assert_eq!(w.len(), 1, "{k} = {v}");
+            assert!(w[0].contains(k), "{w:?}");
+        }
+
+        // Invalid values do not affect valid ones.
+        let (c, w) = parse(&[
+            (SETTING_EPOCH_TICKER, SettingValue::Int(0)),
+            (SETTING_CONSUME_FUEL, SettingValue::Bool(true)),
+        ]);
+        assert_eq!(w.len(), 1);
+        assert!(c.epoch_ticker);
+        assert!(c.consume_fuel);
+    }
+
+    #[test]
+    fn test_changed_warns_once() {
+        let requested = EngineInitConfig::default();
+        let mut state = WatchState {
+            requested,
+            warned: requested,
+        };
+        assert_eq!(check_changed(&mut state, requested), None);
+
+        let changed = EngineInitConfig {
+            consume_fuel: true,
+            ..requested
+        };
+        assert_eq!(
+            check_changed(&mut state, changed),
+            Some(vec![SETTING_CONSUME_FUEL])
+        );
+        assert_eq!(check_changed(&mut state, changed), None);
+        // Reverting does not warn.
+        assert_eq!(check_changed(&mut state, requested), None);
+    }
+}
@@ -6,7 +6,7 @@ use wasmtime::{
 
 use crate::godot_util::from_var_any;
 use crate::wasm_externref::{externref_to_variant, variant_to_externref};
-use crate::wasm_instance::StoreData;
+use crate::wasm_instance::{CallFrame, StoreData};
 use crate::{func_registry, site_context};
 
 func_registry! {
@@ -48,7 +48,7 @@ func_registry! {
             }
         }
 
-        let r = ctx.data_mut().as_mut().release_store(move || c.call(&v));
+        let r = ctx.data_mut().as_mut().release_store_call(CallFrame::Callable(c.clone()), move || c.call(&v))?;
         variant_to_externref(ctx.as_context_mut(), r)
     },
     call_deferred => |mut ctx: Caller<'_, T>, v: Option<Rooted<ExternRef>>, f: Option<Func>| -> AnyResult<()> {
@@ -66,14 +66,14 @@ func_registry! {
             }
         }
 
-        ctx.data_mut().as_mut().release_store(move || c.call_deferred(&v));
+        ctx.data_mut().as_mut().release_store_main(move || c.call_deferred(&v))?;
         Ok(())
     },
     callv => |mut ctx: Caller<'_, T>, v: Option<Rooted<ExternRef>>, args: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
         let v = site_context!(from_var_any::<Callable>(&externref_to_variant(ctx.as_context(), v)?))?;
         let a = site_context!(from_var_any::<VariantArray>(&externref_to_variant(ctx.as_context(), args)?))?;
 
-        let r = ctx.data_mut().as_mut().release_store(move || v.callv(&a));
+        let r = ctx.data_mut().as_mut().release_store_call(CallFrame::Callable(v.clone()), move || v.callv(&a))?;
         variant_to_externref(ctx.as_context_mut(), r)
     },
     bindv => |mut ctx: Caller<'_, _>, v: Option<Rooted<ExternRef>>, args: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
@@ -9,6 +9,7 @@ mod primitive;
 mod signal;
 mod string;
 mod typeis;
+mod variant;
 
 use wasmtime::{Func, StoreContextMut};
 
@@ -37,7 +38,9 @@ macro_rules! register{
     };
 }
 
-register![array, callable, compat, dict, object, pool_array, primitive, signal, string, typeis];
+register![
+    array, callable, compat, dict, object, pool_array, primitive, signal, string, typeis, variant
+];
 
 #[cfg(not(feature = "object-registry-compat"))]
 mod compat {
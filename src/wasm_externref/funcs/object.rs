@@ -1,15 +1,33 @@
+use std::str::from_utf8;
+
 use anyhow::Result as AnyResult;
 use godot::global::Error as GError;
 use godot::prelude::*;
 use wasmtime::{
-    AsContext, AsContextMut, Caller, ExternRef, Func, Rooted, StoreContextMut, TypedFunc,
+    AsContext, AsContextMut, Caller, Extern, ExternRef, Func, Rooted, StoreContextMut, TypedFunc,
 };
 
 use crate::godot_util::{from_var_any, ErrorWrapper};
 use crate::wasm_externref::{externref_to_variant, variant_to_externref};
-use crate::wasm_instance::StoreData;
+use crate::wasm_instance::{CallFrame, StoreData};
 use crate::{bail_with_site, func_registry, site_context};
 
+/// Checks if object has property.
+///
+/// Property with non-null value always exists,
+/// otherwise property list is searched.
+/// Callers remember found properties, so it's searched once per object.
+fn has_property(obj: &Gd<Object>, name: &StringName, value: &Variant) -> bool {
+    if !value.is_nil() {
+        return true;
+    }
+    obj.get_property_list().iter_shared().any(|d| {
+        d.get("name")
+            .and_then(|v| v.try_to::<StringName>().ok())
+            .is_some_and(|v| v == *name)
+    })
+}
+
 func_registry! {
     "object.",
     from_instance_id => |mut ctx: Caller<'_, _>, id: i64| -> AnyResult<Option<Rooted<ExternRef>>> {
@@ -24,28 +42,28 @@ func_registry! {
     },
     get_property_list => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
         let obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
-        let r = ctx.data_mut().as_mut().release_store(move || obj.get_property_list());
+        let r = ctx.data_mut().as_mut().release_store_main(move || obj.get_property_list())?;
         variant_to_externref(ctx.as_context_mut(), r.to_variant())
     },
     get_method_list => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
         let obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
-        let r = ctx.data_mut().as_mut().release_store(move || obj.get_method_list());
+        let r = ctx.data_mut().as_mut().release_store_main(move || obj.get_method_list())?;
         variant_to_externref(ctx.as_context_mut(), r.to_variant())
     },
     get_signal_list => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
         let obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
-        let r = ctx.data_mut().as_mut().release_store(move || obj.get_signal_list());
+        let r = ctx.data_mut().as_mut().release_store_main(move || obj.get_signal_list())?;
         variant_to_externref(ctx.as_context_mut(), r.to_variant())
     },
     has_method => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, name: Option<Rooted<ExternRef>>| -> AnyResult<u32> {
         let obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
         let name = site_context!(from_var_any::<StringName>(&externref_to_variant(ctx.as_context(), name)?))?;
-        ctx.data_mut().as_mut().release_store(move || Ok(obj.has_method(&name) as _))
+        ctx.data_mut().as_mut().release_store_main(move || obj.has_method(&name) as u32)
     },
     has_signal => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, name: Option<Rooted<ExternRef>>| -> AnyResult<u32> {
         let obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
         let name = site_context!(from_var_any::<StringName>(&externref_to_variant(ctx.as_context(), name)?))?;
-        ctx.data_mut().as_mut().release_store(move || Ok(obj.has_signal(&name) as _))
+        ctx.data_mut().as_mut().release_store_main(move || obj.has_signal(&name) as u32)
     },
     call => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, name: Option<Rooted<ExternRef>>, f: Option<Func>| -> AnyResult<Option<Rooted<ExternRef>>> {
         let mut obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
@@ -63,7 +81,8 @@ func_registry! {
             }
         }
 
-        let r = ctx.data_mut().as_mut().release_store(move || site_context!(obj.try_call(&name, &v)))?;
+        let frame = CallFrame::Method { obj: obj.instance_id(), method: name.clone() };
+        let r = ctx.data_mut().as_mut().release_store_call(frame, move || site_context!(obj.try_call(&name, &v)))??;
         variant_to_externref(ctx.as_context_mut(), r)
     },
     call_deferred => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, name: Option<Rooted<ExternRef>>, f: Option<Func>| -> AnyResult<Option<Rooted<ExternRef>>> {
@@ -82,7 +101,7 @@ func_registry! {
             }
         }
 
-        let r = ctx.data_mut().as_mut().release_store(move || site_context!(obj.try_call_deferred(&name, &v)))?;
+        let r = ctx.data_mut().as_mut().release_store_main(move || site_context!(obj.try_call_deferred(&name, &v)))??;
         variant_to_externref(ctx.as_context_mut(), r)
     },
     callv => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, name: Option<Rooted<ExternRef>>, args: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
@@ -90,14 +109,15 @@ func_registry! {
         let name = site_context!(from_var_any::<StringName>(&externref_to_variant(ctx.as_context(), name)?))?;
         let args = site_context!(from_var_any::<VariantArray>(&externref_to_variant(ctx.as_context(), args)?))?;
 
-        let r = ctx.data_mut().as_mut().release_store(move || obj.callv(&name, &args));
+        let frame = CallFrame::Method { obj: obj.instance_id(), method: name.clone() };
+        let r = ctx.data_mut().as_mut().release_store_call(frame, move || obj.callv(&name, &args))?;
         variant_to_externref(ctx.as_context_mut(), r)
     },
     get => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, name: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
         let obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
         let name = site_context!(from_var_any::<StringName>(&externref_to_variant(ctx.as_context(), name)?))?;
 
-        let r = ctx.data_mut().as_mut().release_store(move || obj.get(&name));
+        let r = ctx.data_mut().as_mut().release_store_main(move || obj.get(&name))?;
         variant_to_externref(ctx.as_context_mut(), r)
     },
     set => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, name: Option<Rooted<ExternRef>>, value: Option<Rooted<ExternRef>>| -> AnyResult<u32> {
@@ -105,7 +125,7 @@ func_registry! {
         let name = site_context!(from_var_any::<StringName>(&externref_to_variant(ctx.as_context(), name)?))?;
         let value = externref_to_variant(ctx.as_context(), value)?;
 
-        ctx.data_mut().as_mut().release_store(move || obj.set(&name, &value));
+        ctx.data_mut().as_mut().release_store_main(move || obj.set(&name, &value))?;
         Ok(1)
     },
     set_deferred => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, name: Option<Rooted<ExternRef>>, value: Option<Rooted<ExternRef>>| -> AnyResult<u32> {
@@ -113,14 +133,72 @@ func_registry! {
         let name = site_context!(from_var_any::<StringName>(&externref_to_variant(ctx.as_context(), name)?))?;
         let value = externref_to_variant(ctx.as_context(), value)?;
 
-        ctx.data_mut().as_mut().release_store(move || obj.set_deferred(&name, &value));
+        ctx.data_mut().as_mut().release_store_main(move || obj.set_deferred(&name, &value))?;
         Ok(1)
     },
+    intern_name => |mut ctx: Caller<'_, T>, p: u32, n: u32| -> AnyResult<u32> {
+        let mem = match ctx.get_export("memory") {
+            Some(Extern::Memory(v)) => v,
+            _ => bail_with_site!("No memory exported"),
+        };
+
+        let (mem, data) = mem.data_and_store_mut(&mut ctx);
+        let name = match mem.get(p as usize..(p as usize).saturating_add(n as _)) {
+            Some(s) => site_context!(from_utf8(s))?,
+            None => bail_with_site!("Invalid memory range ({}..{})", p, p as u64 + n as u64),
+        };
+        data.as_mut().extern_names.intern(name)
+    },
+    get_cached => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, handle: u32| -> AnyResult<(Option<Rooted<ExternRef>>, i32)> {
+        let obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
+        let id = obj.instance_id();
+        let names = &ctx.data().as_ref().extern_names;
+        let name = names.get(handle)?.clone();
+        let found = names.is_found(id, handle);
+
+        let r = ctx.data_mut().as_mut().release_store_main(move || {
+            let r = obj.get(&name);
+            if found || has_property(&obj, &name, &r) {
+                Ok(r)
+            } else {
+                Err(GError::ERR_DOES_NOT_EXIST)
+            }
+        })?;
+        match r {
+            Ok(r) => {
+                if !found {
+                    ctx.data_mut().as_mut().extern_names.set_found(id, handle);
+                }
+                Ok((variant_to_externref(ctx.as_context_mut(), r)?, 0))
+            }
+            Err(e) => Ok((None, e.ord() as _)),
+        }
+    },
+    set_cached => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, handle: u32, value: Option<Rooted<ExternRef>>| -> AnyResult<i32> {
+        let mut obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
+        let id = obj.instance_id();
+        let names = &ctx.data().as_ref().extern_names;
+        let name = names.get(handle)?.clone();
+        let found = names.is_found(id, handle);
+        let value = externref_to_variant(ctx.as_context(), value)?;
+
+        let r = ctx.data_mut().as_mut().release_store_main(move || {
+            if !found && !has_property(&obj, &name, &obj.get(&name)) {
+                return GError::ERR_DOES_NOT_EXIST.ord() as _;
+            }
+            obj.set(&name, &value);
+            0
+        })?;
+        if r == 0 && !found {
+            ctx.data_mut().as_mut().extern_names.set_found(id, handle);
+        }
+        Ok(r)
+    },
     get_indexed => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, path: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
         let obj = site_context!(from_var_any::<Gd<Object>>(&externref_to_variant(ctx.as_context(), obj)?))?;
         let path = site_context!(from_var_any::<NodePath>(&externref_to_variant(ctx.as_context(), path)?))?;
 
-        let r = ctx.data_mut().as_mut().release_store(move || obj.get_indexed(&path));
+        let r = ctx.data_mut().as_mut().release_store_main(move || obj.get_indexed(&path))?;
         variant_to_externref(ctx.as_context_mut(), r)
     },
     set_indexed => |mut ctx: Caller<'_, T>, obj: Option<Rooted<ExternRef>>, path: Option<Rooted<ExternRef>>, value: Option<Rooted<ExternRef>>| -> AnyResult<u32> {
@@ -128,7 +206,7 @@ func_registry! {
         let path = site_context!(from_var_any::<NodePath>(&externref_to_variant(ctx.as_context(), path)?))?;
         let value = externref_to_variant(ctx.as_context(), value)?;
 
-        ctx.data_mut().as_mut().release_store(move || obj.set_indexed(&path, &value));
+        ctx.data_mut().as_mut().release_store_main(move || obj.set_indexed(&path, &value))?;
         Ok(1)
     },
     connect => |ctx: Caller<'_, _>, obj: Option<Rooted<ExternRef>>, signal: Option<Rooted<ExternRef>>, target: Option<Rooted<ExternRef>>, flags: u32| -> AnyResult<()> {
@@ -172,7 +250,7 @@ func_registry! {
             }
         }
 
-        site_context!(match ctx.data_mut().as_mut().release_store(move || site_context!(obj.try_emit_signal(&name, &v)))? {
+        site_context!(match ctx.data_mut().as_mut().release_store_main(move || site_context!(obj.try_emit_signal(&name, &v)))?? {
             GError::OK => Ok(()),
             e => Err(ErrorWrapper::from(e)),
         })
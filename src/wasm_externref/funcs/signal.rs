@@ -7,7 +7,7 @@ use wasmtime::{
 
 use crate::godot_util::{from_var_any, ErrorWrapper};
 use crate::wasm_externref::{externref_to_variant, variant_to_externref};
-use crate::wasm_instance::StoreData;
+use crate::wasm_instance::{CallFrame, StoreData};
 use crate::{func_registry, site_context};
 
 func_registry! {
@@ -62,7 +62,7 @@ func_registry! {
             }
         }
 
-        ctx.data_mut().as_mut().release_store(move || c.emit(&v));
+        ctx.data_mut().as_mut().release_store_call(CallFrame::Signal(c.clone()), move || c.emit(&v))?;
         Ok(())
     },
     connections => |mut ctx: Caller<'_, _>, v: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
@@ -8,6 +8,7 @@ use wasmtime::{AsContext, AsContextMut, Caller, Extern, ExternRef, Func, Rooted,
 use crate::godot_util::from_var_any;
 use crate::wasm_externref::{externref_to_variant, variant_to_externref};
 use crate::wasm_instance::StoreData;
+use crate::wasm_util::decode_utf16_lossy;
 use crate::{bail_with_site, func_registry, site_context};
 
 func_registry! {
@@ -42,6 +43,20 @@ func_registry! {
         };
         variant_to_externref(ctx.as_context_mut(), v)
     },
+    new_utf16 => |mut ctx: Caller<'_, T>, p: u32, n: u32| -> AnyResult<Option<Rooted<ExternRef>>> {
+        let mem = match ctx.get_export("memory") {
+            Some(Extern::Memory(v)) => v,
+            _ => return Ok(None),
+        };
+
+        let e = p as usize + n as usize * 2;
+        let (v, errors) = match mem.data(&mut ctx).get(p as _..e) {
+            Some(s) => decode_utf16_lossy(s),
+            None => bail_with_site!("Invalid memory range ({}..{})", p, e),
+        };
+        ctx.data_mut().as_mut().string_errors += errors as u64;
+        variant_to_externref(ctx.as_context_mut(), v.to_variant())
+    },
     to_string_name => |mut ctx: Caller<'_, T>, v: Option<Rooted<ExternRef>>| -> AnyResult<Option<Rooted<ExternRef>>> {
         let v = site_context!(from_var_any::<GString>(&externref_to_variant(ctx.as_context(), v)?))?;
         variant_to_externref(ctx.as_context_mut(), StringName::from(v).to_variant())
@@ -0,0 +1,15 @@
+use anyhow::Result as AnyResult;
+use wasmtime::{AsContext, AsContextMut, Caller, ExternRef, Func, Rooted, StoreContextMut};
+
+use crate::func_registry;
+use crate::godot_util::duplicate_variant;
+use crate::wasm_externref::{externref_to_variant, variant_to_externref};
+use crate::wasm_instance::StoreData;
+
+func_registry! {
+    "variant.",
+    duplicate => |mut ctx: Caller<'_, T>, v: Option<Rooted<ExternRef>>, deep: u32| -> AnyResult<Option<Rooted<ExternRef>>> {
+        let v = externref_to_variant(ctx.as_context(), v)?;
+        variant_to_externref(ctx.as_context_mut(), duplicate_variant(&v, deep != 0))
+    },
+}
@@ -1,5 +1,7 @@
 mod funcs;
 
+use std::collections::{HashMap, HashSet};
+
 use anyhow::Result as AnyResult;
 use godot::prelude::*;
 use wasmtime::{ExternRef, Rooted, StoreContext, StoreContextMut};
@@ -34,3 +36,153 @@ pub fn variant_to_externref<T>(
         site_context!(ExternRef::new(ctx, SendSyncWrapper::new(v)).map(Some))
     }
 }
+
+/// Maximum number of interned names per instance.
+pub const MAX_NAMES: usize = 1 << 16;
+/// Maximum number of remembered object properties, cleared when exceeded.
+const MAX_FOUND: usize = 1 << 16;
+
+/// Table of property names interned by guest.
+///
+/// Guest converts name once, then refer to it with handle,
+/// avoiding UTF-8 to [`StringName`] conversion on every access.
+pub struct NameTable<N = StringName> {
+    names: Vec<SendSyncWrapper<N>>,
+    index: HashMap<Box<str>, u32>,
+    /// Object properties known to exist, so nil value does not search property list again.
+    found: HashSet<(InstanceId, u32)>,
+}
+
+impl<N> Default for NameTable<N> {
+    fn default() -> Self {
+        Self {
+            names: Vec::new(),
+            index: HashMap::new(),
+            found: HashSet::new(),
+        }
+    }
+}
+
+impl<N> NameTable<N>
+where
+    for<'a> N: From<&'a str>,
+{
+    /// Interns name, returning it's handle.
+    ///
+    /// Name is only converted if it has not been interned before.
+    pub fn intern(&mut self, name: &str) -> AnyResult<u32> {
+        if let Some(&i) = self.index.get(name) {
+            return Ok(i);
+        }
+        if self.names.len() >= MAX_NAMES {
+            bail_with_site!("Too many interned names (maximum {MAX_NAMES})");
+        }
+
+        let i = self.names.len() as u32;
+        self.names.push(SendSyncWrapper::new(N::from(name)));
+        self.index.insert(name.into(), i);
+        Ok(i)
+    }
+}
+
+impl<N> NameTable<N> {
+    /// Gets interned name.
+    pub fn get(&self, handle: u32) -> AnyResult<&N> {
+        match self.names.get(handle as usize) {
+            Some(v) => Ok(&**v),
+            None => bail_with_site!("Invalid name handle {handle}"),
+        }
+    }
+
+    /// Returns `true` if object is known to have property.
+    pub fn is_found(&self, obj: InstanceId, handle: u32) -> bool {
+        self.found.contains(&(obj, handle))
+    }
+
+    /// Remembers that object has property.
+    pub fn set_found(&mut self, obj: InstanceId, handle: u32) {
+        if self.found.len() >= MAX_FOUND {
+            self.found.clear();
+        }
+        self.found.insert((obj, handle));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    thread_local! {
+        static CONVERT_COUNT: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Name which counts conversions into it.
+    #[derive(Debug, PartialEq)]
+    struct Counted(String);
+
+    impl From<&str> for Counted {
+        fn from(s: &str) -> Self {
+            CONVERT_COUNT.set(CONVERT_COUNT.get() + 1);
+            Self(s.into())
+        }
+    }
+
+    const FRAMES: usize = 60;
+    const PROPS: &[&str] = &["position", "rotation", "scale", "modulate"];
+
+    #[test]
+    fn test_name_table_conversions() {
+        let mut table = NameTable::<Counted>::default();
+        let start = CONVERT_COUNT.get();
+
+        // intern_name converts every name once.
+        let handles = PROPS
+            .iter()
+            .map(|p| table.intern(p).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(CONVERT_COUNT.get(), start + PROPS.len());
+
+        // get_cached/set_cached only use handles, re-interning does not convert.
+        for _ in 0..FRAMES {
+            for (&h, &p) in handles.iter().zip(PROPS) {
+                assert_eq!(table.get(h).unwrap().0, p);
+                assert_eq!(table.intern(p).unwrap(), h);
+            }
+        }
+        assert_eq!(CONVERT_COUNT.get(), start + PROPS.len());
+    }
+
+    #[test]
+    fn test_name_table_invalid() {
+        let mut table = NameTable::<Counted>::default();
+        assert!(table.get(0).is_err());
+        let h = table.intern("a").unwrap();
+        let start = CONVERT_COUNT.get();
+        assert_eq!(table.intern("a").unwrap(), h);
+        assert_eq!(CONVERT_COUNT.get(), start);
+        assert!(table.get(h + 1).is_err());
+        assert_eq!(table.names.len(), 1);
+    }
+
+    #[test]
+    fn test_name_table_found() {
+        let mut table = NameTable::<Counted>::default();
+        let (a, b) = (InstanceId::from_i64(1), InstanceId::from_i64(2));
+        let h = table.intern("a").unwrap();
+        assert!(!table.is_found(a, h));
+        table.set_found(a, h);
+        assert!(table.is_found(a, h));
+        assert!(!table.is_found(b, h));
+
+        // Cleared once full.
+        for i in 1..MAX_FOUND as u32 {
+            table.set_found(a, h + i);
+        }
+        assert!(table.is_found(a, h));
+        table.set_found(b, h);
+        assert!(!table.is_found(a, h));
+        assert!(table.is_found(b, h));
+    }
+}
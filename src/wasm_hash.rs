@@ -0,0 +1,172 @@
+use anyhow::Result as AnyResult;
+use sha2::{Digest, Sha256};
+use wasmtime::{AsContextMut, Extern, Instance as InstanceWasm, Val};
+use xxhash_rust::xxh64::{xxh64, Xxh64};
+
+use crate::bail_with_site;
+
+/// Size of data fed into hasher at a time.
+pub const HASH_CHUNK_SIZE: usize = 64 << 10;
+
+/// Hash algorithm of `hash_memory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// XXH64 with seed 0, big-endian 8 bytes.
+    Xxhash64,
+    /// SHA-256, 32 bytes.
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub fn from_name(s: &str) -> AnyResult<Self> {
+        match s {
+            "xxhash64" | "xxh64" => Ok(Self::Xxhash64),
+            "sha256" => Ok(Self::Sha256),
+            _ => bail_with_site!("Unknown hash algorithm {s:?}"),
+        }
+    }
+
+    /// Hashes data, streaming it in chunks of [`HASH_CHUNK_SIZE`].
+    pub fn hash(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Xxhash64 => {
+                let mut h = Xxh64::new(0);
+                for c in data.chunks(HASH_CHUNK_SIZE) {
+                    h.update(c);
+                }
+                h.digest().to_be_bytes().to_vec()
+            }
+            Self::Sha256 => {
+                let mut h = Sha256::new();
+                for c in data.chunks(HASH_CHUNK_SIZE) {
+                    h.update(c);
+                }
+                h.finalize().to_vec()
+            }
+        }
+    }
+}
+
+/// Hashes value of exported globals and size of exported memories.
+///
+/// Reference-typed globals are skipped.
+pub fn hash_exports(mut store: impl AsContextMut, instance: &InstanceWasm) -> Vec<(String, u64)> {
+    let exports = instance
+        .exports(&mut store)
+        .map(|e| (e.name().to_string(), e.into_extern()))
+        .collect::<Vec<_>>();
+
+    let mut buf = Vec::with_capacity(17);
+    exports
+        .into_iter()
+        .filter_map(|(name, e)| {
+            buf.clear();
+            match e {
+                Extern::Global(g) => {
+                    let (tag, v): (u8, u128) = match g.get(&mut store) {
+                        Val::I32(v) => (1, (v as u32).into()),
+                        Val::I64(v) => (2, (v as u64).into()),
+                        Val::F32(v) => (3, v.into()),
+                        Val::F64(v) => (4, v.into()),
+                        Val::V128(v) => (7, v.as_u128()),
+                        _ => return None,
+                    };
+                    buf.push(tag);
+                    buf.extend_from_slice(&v.to_le_bytes());
+                }
+                Extern::Memory(m) => {
+                    buf.push(0x80);
+                    buf.extend_from_slice(&(m.data_size(&store) as u64).to_le_bytes());
+                }
+                Extern::SharedMemory(m) => {
+                    buf.push(0x80);
+                    buf.extend_from_slice(&(m.data_size() as u64).to_le_bytes());
+                }
+                _ => return None,
+            }
+            Some((name, xxh64(&buf, 0)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasmtime::{Engine, Module, Store};
+
+    fn hex(v: &[u8]) -> String {
+        v.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn test_hash_vectors() {
+        let xxh = HashAlgorithm::from_name("xxhash64").unwrap();
+        assert_eq!(hex(&xxh.hash(b"")), "ef46db3751d8e999");
+        assert_eq!(hex(&xxh.hash(b"abc")), "44bc2cf5ad770999");
+
+        let sha = HashAlgorithm::from_name("sha256").unwrap();
+        assert_eq!(
+            hex(&sha.hash(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha.hash(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        assert!(HashAlgorithm::from_name("md5").is_err());
+    }
+
+    #[test]
+    fn test_hash_chunked() {
+        let data = (0..HASH_CHUNK_SIZE as u32 * 3 + 17)
+            .map(|i| (i * 7) as u8)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            HashAlgorithm::Xxhash64.hash(&data),
+            xxh64(&data, 0).to_be_bytes()
+        );
+        assert_eq!(
+            HashAlgorithm::Sha256.hash(&data),
+            Sha256::digest(&data).as_slice()
+        );
+    }
+
+    #[test]
+    fn test_hash_exports() {
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (memory (export "memory") 1)
+                (global (export "a") (mut i32) (i32.const 5))
+                (global (export "b") i64 (i64.const 5))
+                (global (export "r") funcref (ref.null func))
+                (func (export "f")))"#,
+        )
+        .unwrap();
+        let mut store = Store::new(&engine, ());
+        let inst = InstanceWasm::new(&mut store, &module, &[]).unwrap();
+
+        let v = hash_exports(&mut store, &inst);
+        let names = v.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["memory", "a", "b"]);
+        // Same value of different type hashes differently.
+        assert_ne!(v[1].1, v[2].1);
+        assert_eq!(hash_exports(&mut store, &inst), v);
+
+        inst.get_global(&mut store, "a")
+            .unwrap()
+            .set(&mut store, Val::I32(6))
+            .unwrap();
+        inst.get_memory(&mut store, "memory")
+            .unwrap()
+            .grow(&mut store, 1)
+            .unwrap();
+        let w = hash_exports(&mut store, &inst);
+        assert_ne!(w[0].1, v[0].1);
+        assert_ne!(w[1].1, v[1].1);
+        assert_eq!(w[2].1, v[2].1);
+    }
+}
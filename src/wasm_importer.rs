@@ -0,0 +1,225 @@
+use anyhow::{bail, Result as AnyResult};
+use godot::classes::{
+    EditorImportPlugin, FileAccess, IEditorImportPlugin, Resource, ResourceSaver,
+};
+use godot::global::{bytes_to_var_with_objects, Error as GError};
+use godot::prelude::*;
+use once_cell::sync::OnceCell;
+use tracing::{error, instrument};
+
+use crate::bail_with_site;
+use crate::wasm_engine::WasmModule;
+use crate::wasm_instance::WasmInstance;
+
+/// Editor import plugin implemented by WASM module.
+///
+/// Module must export these functions:
+/// - `importer_get_extensions() -> (ptr, len)` : Comma-separated list of recognized extensions.
+/// - `importer_scratch(len) -> ptr` : Gets scratch buffer with at least `len` bytes.
+///   Buffer is only valid until next call into module.
+/// - `importer_import(ptr, len) -> (ptr, len)` : Imports file content in scratch buffer.
+///   Returns resource serialized with `var_to_bytes_with_objects()`.
+///   If `len` is negative, import fails with error message of `-len` bytes instead.
+///
+/// Only available in editor.
+#[derive(GodotClass)]
+#[class(base=EditorImportPlugin, tool)]
+pub struct WasmImporterPlugin {
+    base: Base<EditorImportPlugin>,
+    instance: OnceCell<Gd<WasmInstance>>,
+
+    /// Module implementing the importer.
+    #[var]
+    module: Option<Gd<WasmModule>>,
+    /// Unique name of the importer.
+    #[var]
+    importer_name: GString,
+    /// Name shown in import dock.
+    #[var]
+    visible_name: GString,
+    /// Type of imported resource.
+    #[var]
+    resource_type: GString,
+    /// Extension of imported resource file.
+    #[var]
+    save_extension: GString,
+    /// Priority of importer.
+    #[var]
+    priority: f32,
+}
+
+impl WasmImporterPlugin {
+    fn get_instance(&self) -> AnyResult<&Gd<WasmInstance>> {
+        self.instance.get_or_try_init(|| {
+            let Some(module) = &self.module else {
+                bail_with_site!("Importer has no module")
+            };
+            let inst = WasmInstance::new_gd();
+            if inst.bind().initialize_(module.clone(), None, None) {
+                Ok(inst)
+            } else {
+                bail_with_site!("Cannot instantiate importer module")
+            }
+        })
+    }
+
+    fn read_string(inst: &Gd<WasmInstance>, ptr: u32, len: u32) -> AnyResult<String> {
+        let ptr = ptr as usize;
+        let len = len as usize;
+        inst.bind().try_memory(|mem| match mem.get(ptr..ptr + len) {
+            Some(s) => Ok(String::from_utf8_lossy(s).into_owned()),
+            None => bail_with_site!("Index out of bound {}-{}", ptr, ptr + len),
+        })
+    }
+
+    fn import_(&self, source_file: GString, save_path: GString) -> AnyResult<()> {
+        let inst = self.get_instance()?;
+        let data = FileAccess::get_file_as_bytes(&source_file);
+        match FileAccess::get_open_error() {
+            GError::OK => (),
+            e => bail_with_site!("Cannot open {source_file} ({e:?})"),
+        }
+        let Ok(len) = u32::try_from(data.len()) else {
+            bail_with_site!("File too large ({} bytes)", data.len())
+        };
+
+        let ptr = inst
+            .bind()
+            .call_typed::<u32, u32>("importer_scratch", len)?;
+        inst.bind().try_memory(|mem| {
+            let p = ptr as usize;
+            match mem.get_mut(p..p + data.len()) {
+                Some(s) => s.copy_from_slice(data.as_slice()),
+                None => bail_with_site!("Index out of bound {}-{}", p, p + data.len()),
+            }
+            Ok(())
+        })?;
+
+        let (ptr, len) = inst
+            .bind()
+            .call_typed::<(u32, u32), (u32, i32)>("importer_import", (ptr, len))?;
+        if len < 0 {
+            bail!("{}", Self::read_string(inst, ptr, len.unsigned_abs())?);
+        }
+        let ptr = ptr as usize;
+        let len = len as usize;
+        let buf = inst
+            .bind()
+            .try_memory(|mem| match mem.get(ptr..ptr + len) {
+                Some(s) => Ok(PackedByteArray::from(s)),
+                None => bail_with_site!("Index out of bound {}-{}", ptr, ptr + len),
+            })?;
+
+        let v = bytes_to_var_with_objects(&buf);
+        let Ok(res) = v.try_to::<Gd<Resource>>() else {
+            bail_with_site!("Imported value is not a resource ({:?})", v.get_type())
+        };
+        let path = GString::from(format!("{save_path}.{}", self.save_extension));
+        match ResourceSaver::singleton().save_ex(&res).path(&path).done() {
+            GError::OK => Ok(()),
+            e => bail_with_site!("Cannot save resource to {path} ({e:?})"),
+        }
+    }
+}
+
+#[godot_api]
+impl IEditorImportPlugin for WasmImporterPlugin {
+    fn init(base: Base<EditorImportPlugin>) -> Self {
+        Self {
+            base,
+            instance: OnceCell::new(),
+            module: None,
+            importer_name: GString::new(),
+            visible_name: GString::new(),
+            resource_type: "Resource".into(),
+            save_extension: "res".into(),
+            priority: 1.0,
+        }
+    }
+
+    fn get_importer_name(&self) -> GString {
+        self.importer_name.clone()
+    }
+
+    fn get_visible_name(&self) -> GString {
+        self.visible_name.clone()
+    }
+
+    fn get_recognized_extensions(&self) -> PackedStringArray {
+        let r = self.get_instance().and_then(|inst| {
+            let (ptr, len) = inst
+                .bind()
+                .call_typed::<(), (u32, u32)>("importer_get_extensions", ())?;
+            Self::read_string(inst, ptr, len)
+        });
+        match r {
+            Ok(s) => s
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(GString::from)
+                .collect(),
+            Err(e) => {
+                error!(importer = %self.importer_name, "{e:?}");
+                godot_error!("{e:?}");
+                PackedStringArray::new()
+            }
+        }
+    }
+
+    fn get_save_extension(&self) -> GString {
+        self.save_extension.clone()
+    }
+
+    fn get_resource_type(&self) -> GString {
+        self.resource_type.clone()
+    }
+
+    fn get_priority(&self) -> f32 {
+        self.priority
+    }
+
+    fn get_import_order(&self) -> i32 {
+        0
+    }
+
+    fn get_preset_count(&self) -> i32 {
+        1
+    }
+
+    fn get_preset_name(&self, _preset_index: i32) -> GString {
+        "Default".into()
+    }
+
+    fn get_import_options(&self, _path: GString, _preset_index: i32) -> Array<Dictionary> {
+        Array::new()
+    }
+
+    fn get_option_visibility(
+        &self,
+        _path: GString,
+        _option_name: StringName,
+        _options: Dictionary,
+    ) -> bool {
+        true
+    }
+
+    #[instrument(skip(self, _options, _platform_variants, _gen_files))]
+    fn import(
+        &self,
+        source_file: GString,
+        save_path: GString,
+        _options: Dictionary,
+        _platform_variants: Array<GString>,
+        _gen_files: Array<GString>,
+    ) -> GError {
+        match self.import_(source_file.clone(), save_path) {
+            Ok(()) => GError::OK,
+            Err(e) => {
+                error!(importer = %self.importer_name, "{e:?}");
+                godot_error!("Importing {source_file} failed: {e}");
+                GError::ERR_FILE_CORRUPT
+            }
+        }
+    }
+}
@@ -1,16 +1,24 @@
+use std::borrow::Cow;
 use std::collections::hash_map::{Entry, HashMap};
+use std::collections::VecDeque;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use std::hash::{Hash, Hasher};
 use std::io::Cursor;
-#[cfg(feature = "wasi")]
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 use std::{ffi, mem, ptr};
 
-use anyhow::{bail, Result as AnyResult};
+use anyhow::{bail, Error as AnyError, Result as AnyResult};
 use cfg_if::cfg_if;
+use godot::classes::image::Format as ImageFormat;
+use godot::classes::{Engine, Image, ImageTexture, PackedScene, SceneTree};
 use godot::prelude::*;
-use once_cell::sync::OnceCell;
+use once_cell::sync::{Lazy, OnceCell};
 use parking_lot::{lock_api::RawMutex as RawMutexTrait, Mutex, RawMutex};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
 use rayon::prelude::*;
 use scopeguard::guard;
 use tracing::{debug, debug_span, error, info, instrument, trace_span, warn, Level};
@@ -19,45 +27,69 @@ use wasi_isolated_fs::bindings::wasi_snapshot_preview1::add_to_linker;
 #[cfg(feature = "wasi")]
 use wasi_isolated_fs::context::WasiContext as WasiCtx;
 #[cfg(feature = "wasi")]
-use wasi_isolated_fs::stdio::StdinProvider;
+use wasi_isolated_fs::errors::ProcessExit;
+#[cfg(feature = "wasi")]
+use wasi_isolated_fs::stdio::{Pipe, StdinProvider};
 #[cfg(feature = "component-model")]
 use wasmtime::component::Instance as InstanceComp;
-#[cfg(feature = "wasi")]
 use wasmtime::Linker;
 #[cfg(feature = "memory-limiter")]
 use wasmtime::ResourceLimiter;
 use wasmtime::{
-    AsContextMut, Extern, Func, FuncType, Instance as InstanceWasm, Memory, SharedMemory, Store,
-    StoreContextMut,
+    AsContextMut, Caller, Extern, ExternType, Func, FuncType, Instance as InstanceWasm, Memory,
+    Module, SharedMemory, Store, StoreContextMut, WasmParams, WasmResults,
 };
 
 use crate::godot_util::{
-    option_to_variant, variant_to_option, PackedArrayLike, PhantomProperty, SendSyncWrapper,
-    StructPacking,
+    call_in_main_thread, check_object_call, emit_deferred, emit_in_main_thread, is_main_thread,
+    marshal_size, option_to_variant, suppressed_warning_counts, variant_to_option, PackedArrayLike,
+    PhantomProperty, SendSyncWrapper, StructPacking,
+};
+use crate::rw_struct::{
+    read_struct, to_struct_format, write_struct, StructFormat, WasmStructFormat,
 };
-use crate::rw_struct::{read_struct, write_struct};
 #[cfg(feature = "wasi")]
 use crate::wasi_ctx::stdio::PackedByteArrayReader;
 #[cfg(feature = "wasi")]
 use crate::wasi_ctx::WasiContext;
-use crate::wasm_config::Config;
+use crate::wasm_abi::{self, check_abi, GuestAllocator, ReturnConvention};
+use crate::wasm_audit::audit_permissions;
 #[cfg(any(feature = "object-registry-compat", feature = "object-registry-extern"))]
 use crate::wasm_config::ExternBindingType;
 #[cfg(feature = "wasi")]
 use crate::wasm_config::PipeBindingType;
-use crate::wasm_engine::{get_engine, ModuleData, ModuleType, WasmModule};
+use crate::wasm_config::{get_seed, Config, CopyMode, MarshalLimits, ObjectCallPolicy};
+#[cfg(feature = "epoch-timeout")]
+use crate::wasm_engine::{arm_deadline, enter_priority, EpochDeadline};
+use crate::wasm_engine::{
+    engine_info, idle_unload_timeout, is_main_engine, register_idle_unload, ModuleData, ModuleType,
+    WasmModule,
+};
+#[cfg(feature = "wasi")]
+use crate::wasm_engine::{get_linker, LinkerKey};
+#[cfg(feature = "memory-limiter")]
+use crate::wasm_engine::{record_memory_peak, set_global_memory_budget, MemoryReservation};
 #[cfg(feature = "object-registry-extern")]
-use crate::wasm_externref::Funcs as ExternrefFuncs;
+use crate::wasm_externref::{Funcs as ExternrefFuncs, NameTable};
+#[cfg(feature = "memory-hash")]
+use crate::wasm_hash::{self, HashAlgorithm};
 #[cfg(feature = "object-registry-compat")]
 use crate::wasm_objregistry::{Funcs as ObjregistryFuncs, ObjectRegistry};
+#[cfg(feature = "wasi")]
+use crate::wasm_util::open_nondet_tap;
 #[cfg(feature = "epoch-timeout")]
 use crate::wasm_util::reset_epoch;
 #[cfg(feature = "object-registry-extern")]
 use crate::wasm_util::EXTERNREF_MODULE;
 #[cfg(feature = "object-registry-compat")]
 use crate::wasm_util::OBJREGISTRY_MODULE;
+#[cfg(feature = "wasi")]
+use crate::wasm_util::PIPE_CAPACITY;
 use crate::wasm_util::{
-    config_store_common, raw_call, HasEpochTimeout, HostModuleCache, MEMORY_EXPORT,
+    check_reference_types, config_store_common, decode_latin1, decode_utf16_lossy,
+    encode_latin1_lossy, encode_utf16, format_error, raw_call, unsupported_import_stub,
+    unsupported_ref_type, CpuStopwatch, HasEpochTimeout, HostModuleCache, HOST_FRAME_COUNT,
+    HOST_IS_CANCELLED, HOST_MODULE, HOST_PHYSICS_TICK, HOST_RAND, MEMORY_EXPORT,
 };
 use crate::{bail_with_site, site_context, variant_dispatch};
 
@@ -129,6 +161,15 @@ pub struct WasmInstance {
     base: Base<RefCounted>,
     data: OnceCell<InstanceData<StoreData>>,
     memory: Option<MemoryType>,
+    activity: Arc<ActivityTracker>,
+    /// Exit code, if guest has exited.
+    exit_code: OnceCell<u32>,
+    /// Lifecycle state.
+    state: StateMachine,
+    /// Calls made before instance is ready.
+    queued_calls: Mutex<CallQueue>,
+    /// Instance ID in live instance registry, set once it's initialized.
+    registered: OnceCell<InstanceId>,
 
     /// Reference to the module that is used to instantiate this object.
     #[var(get = get_module)]
@@ -142,6 +183,175 @@ impl Debug for WasmInstance {
     }
 }
 
+impl Drop for WasmInstance {
+    fn drop(&mut self) {
+        if let Some(id) = self.registered.get() {
+            LIVE_INSTANCES.lock().retain(|v| v != id);
+        }
+    }
+}
+
+/// Live initialized instances, in order of initialization.
+///
+/// Only IDs are stored, so it does not keep instances alive.
+static LIVE_INSTANCES: Mutex<Vec<InstanceId>> = Mutex::new(Vec::new());
+
+/// Iterates live instances. Instances freed while iterating are skipped.
+fn live_instances() -> impl Iterator<Item = Gd<WasmInstance>> {
+    let ids = LIVE_INSTANCES.lock().clone();
+    ids.into_iter()
+        .filter_map(|id| Gd::<WasmInstance>::try_from_instance_id(id).ok())
+}
+
+/// Calls queued until instance is ready, see `queueCalls.untilReady` config.
+struct CallQueue<T = (StringName, VariantArray)> {
+    /// Maximum number of queued calls. `None` if queueing is disabled.
+    limit: Option<usize>,
+    /// Thread running queued calls.
+    runner: Option<ThreadId>,
+    calls: VecDeque<T>,
+}
+
+impl<T> Default for CallQueue<T> {
+    fn default() -> Self {
+        Self {
+            limit: None,
+            runner: None,
+            calls: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> CallQueue<T> {
+    /// Returns `true` if calls are queued.
+    ///
+    /// It stays enabled until all queued calls are finished, so newer calls can't overtake them.
+    fn is_active(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    /// Queues call if queueing is enabled. Fails if queue is full.
+    ///
+    /// Returns the call back if it should run immediately.
+    fn push(&mut self, call: T) -> AnyResult<Option<T>> {
+        let Some(limit) = self.limit else {
+            return Ok(Some(call));
+        };
+        // Nested call made by queued call runs immediately.
+        if self.runner == Some(thread::current().id()) {
+            return Ok(Some(call));
+        }
+        if self.calls.len() >= limit {
+            bail_with_site!("Call queue is full ({limit} calls)");
+        }
+        self.calls.push_back(call);
+        Ok(None)
+    }
+
+    /// Pops next queued call to be run by current thread.
+    ///
+    /// Queueing is disabled once there is no more call.
+    fn pop(&mut self) -> Option<T> {
+        let ret = self.calls.pop_front();
+        if ret.is_some() {
+            self.runner = Some(thread::current().id());
+        } else {
+            self.limit = None;
+            self.runner = None;
+        }
+        ret
+    }
+}
+
+/// Runs queued calls in order until queue is empty, including calls queued while running.
+fn drain_queue<T>(queue: &Mutex<CallQueue<T>>, mut f: impl FnMut(T)) {
+    loop {
+        let next = queue.lock().pop();
+        let Some(call) = next else { break };
+        f(call);
+    }
+}
+
+/// Lifecycle state of instance, see `get_state()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum InstanceState {
+    Uninitialized = 0,
+    Compiling = 1,
+    Ready = 2,
+    Running = 3,
+    Exited = 4,
+    Errored = 5,
+    FreedPending = 6,
+}
+
+impl Display for InstanceState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(match self {
+            Self::Uninitialized => "uninitialized",
+            Self::Compiling => "compiling",
+            Self::Ready => "ready",
+            Self::Running => "running",
+            Self::Exited => "exited",
+            Self::Errored => "errored",
+            Self::FreedPending => "freed-pending",
+        })
+    }
+}
+
+impl InstanceState {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            0 => Self::Uninitialized,
+            1 => Self::Compiling,
+            2 => Self::Ready,
+            3 => Self::Running,
+            4 => Self::Exited,
+            5 => Self::Errored,
+            6 => Self::FreedPending,
+            _ => unreachable!("Invalid instance state {v}"),
+        }
+    }
+
+    /// Returns `true` if state can transition into `to`.
+    fn can_transition(self, to: Self) -> bool {
+        use InstanceState::*;
+
+        matches!(
+            (self, to),
+            (Uninitialized | Errored, Compiling)
+                | (Compiling, Ready | Errored)
+                | (Ready | FreedPending, Running)
+                | (Running, Ready | Exited)
+                | (Ready, FreedPending)
+        )
+    }
+}
+
+/// Atomic lifecycle state machine.
+#[derive(Debug, Default)]
+struct StateMachine(AtomicU8);
+
+impl StateMachine {
+    fn get(&self) -> InstanceState {
+        InstanceState::from_u8(self.0.load(Ordering::Acquire))
+    }
+
+    /// Transitions into `to`, returning previous state.
+    ///
+    /// Returns `None` if transition is invalid from current state.
+    fn transition(&self, to: InstanceState) -> Option<InstanceState> {
+        self.0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |v| {
+                InstanceState::from_u8(v)
+                    .can_transition(to)
+                    .then_some(to as u8)
+            })
+            .ok()
+            .map(InstanceState::from_u8)
+    }
+}
+
 pub struct InstanceData<T> {
     pub store: Mutex<Store<T>>,
     pub instance: InstanceType,
@@ -179,19 +389,348 @@ impl InstanceType {
     }
 }
 
-pub struct InnerLock {
+pub struct InnerLock<C = CallFrame> {
     mutex_raw: *const RawMutex,
+    pub object_calls: ObjectCallPolicy,
+    pub reentrancy: Reentrancy<C>,
+    /// Number of outermost guest calls.
+    pub calls: u64,
+    /// CPU time spent in outermost guest calls, in microseconds.
+    pub cpu_usec: u64,
+    /// Frame counters of current outermost call, sampled on first use.
+    frame_info: Option<FrameInfo>,
 }
 
 // SAFETY: Store data is safely contained within instance data?
-unsafe impl Send for InnerLock {}
-unsafe impl Sync for InnerLock {}
+unsafe impl<C> Send for InnerLock<C> {}
+unsafe impl<C> Sync for InnerLock<C> {}
 
-impl Default for InnerLock {
+impl<C> Default for InnerLock<C> {
     fn default() -> Self {
         Self {
             mutex_raw: ptr::null(),
+            object_calls: ObjectCallPolicy::default(),
+            reentrancy: Reentrancy::default(),
+            calls: 0,
+            cpu_usec: 0,
+            frame_info: None,
+        }
+    }
+}
+
+/// Main loop counters, as observed by guest.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameInfo {
+    pub frames_drawn: i32,
+    pub process_frames: u64,
+    pub physics_frames: u64,
+    pub process_delta: f64,
+    pub physics_delta: f64,
+}
+
+impl FrameInfo {
+    /// Samples counters from `Engine` singleton. Deltas are 0 if main loop is not a `SceneTree`.
+    pub fn sample() -> Self {
+        let engine = Engine::singleton();
+        let root = engine
+            .get_main_loop()
+            .and_then(|v| v.try_cast::<SceneTree>().ok())
+            .and_then(|v| v.get_root());
+        Self {
+            frames_drawn: engine.get_frames_drawn(),
+            process_frames: engine.get_process_frames(),
+            physics_frames: engine.get_physics_frames(),
+            process_delta: root.as_ref().map_or(0.0, |v| v.get_process_delta_time()),
+            physics_delta: root.map_or(0.0, |v| v.get_physics_process_delta_time()),
+        }
+    }
+}
+
+/// Default maximum depth of host↔guest call chain.
+pub const DEFAULT_MAX_REENTRANCY: usize = 16;
+
+/// Frame of host↔guest call chain.
+pub enum CallFrame {
+    /// Call into guest export.
+    Guest { inst: InstanceId, name: StringName },
+    /// Host callable invoked by guest.
+    Callable(Callable),
+    /// Host object method invoked by guest.
+    Method { obj: InstanceId, method: StringName },
+    /// Host signal emitted by guest.
+    Signal(Signal),
+}
+
+impl Display for CallFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Guest { inst, name } => write!(f, "export {name} of instance {inst}"),
+            Self::Callable(c) => write!(f, "callable {c}"),
+            Self::Method { obj, method } => write!(f, "method {method} of object {obj}"),
+            Self::Signal(s) => write!(f, "signal {s}"),
+        }
+    }
+}
+
+/// Tracks nested host↔guest calls.
+pub struct Reentrancy<F = CallFrame> {
+    /// Frames with their serial number.
+    chain: Vec<(u64, F)>,
+    next_serial: u64,
+    pub max_depth: usize,
+}
+
+impl<F> Debug for Reentrancy<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("Reentrancy")
+            .field("depth", &self.chain.len())
+            .field("max_depth", &self.max_depth)
+            .finish()
+    }
+}
+
+impl<F> Default for Reentrancy<F> {
+    fn default() -> Self {
+        Self {
+            chain: Vec::new(),
+            next_serial: 0,
+            max_depth: DEFAULT_MAX_REENTRANCY,
+        }
+    }
+}
+
+impl<F: Display> Reentrancy<F> {
+    /// Current depth of call chain.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.chain.len()
+    }
+
+    /// Pushes a frame, failing if it exceeds maximum depth.
+    ///
+    /// Error message lists the entire call chain.
+    pub fn enter(&mut self, frame: F) -> AnyResult<()> {
+        if self.chain.len() >= self.max_depth {
+            let mut msg = format!(
+                "Maximum reentrancy depth ({}) exceeded when entering {frame}\nCall chain:",
+                self.max_depth,
+            );
+            for (i, (_, v)) in self.chain.iter().enumerate() {
+                msg.push_str(&format!("\n  {i}: {v}"));
+            }
+            bail_with_site!("{msg}");
+        }
+        self.chain.push((self.next_serial, frame));
+        self.next_serial = self.next_serial.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Pops last frame.
+    #[inline]
+    pub fn exit(&mut self) {
+        self.chain.pop();
+    }
+
+    /// Serial number of last frame. Every entered frame gets unique serial.
+    #[inline]
+    pub fn current(&self) -> Option<u64> {
+        self.chain.last().map(|&(v, _)| v)
+    }
+
+    /// Returns `true` if frame with serial has not exited yet.
+    pub fn is_live(&self, serial: u64) -> bool {
+        self.chain.iter().any(|&(v, _)| v == serial)
+    }
+}
+
+/// Runs `f` with guest call frame pushed.
+///
+/// CPU time of outermost call is accumulated, including host calls made by guest.
+pub fn enter_guest<T, C, R>(
+    mut store: StoreContextMut<'_, T>,
+    frame: C,
+    f: impl FnOnce(StoreContextMut<'_, T>) -> AnyResult<R>,
+) -> AnyResult<R>
+where
+    T: AsMut<InnerLock<C>>,
+    C: Display,
+{
+    let lock = store.data_mut().as_mut();
+    let outer = lock.reentrancy.depth() == 0;
+    lock.reentrancy.enter(frame)?;
+    if outer {
+        lock.reset_frame_info();
+    }
+    let timer = outer.then(CpuStopwatch::start);
+    let r = f(store.as_context_mut());
+    let lock = store.data_mut().as_mut();
+    lock.reentrancy.exit();
+    if let Some(t) = timer {
+        lock.calls += 1;
+        lock.cpu_usec = lock.cpu_usec.saturating_add(t.elapsed_usec());
+    }
+    r
+}
+
+/// Creates builtin `host` module import, if it exists.
+///
+/// - `is_cancelled` : Polled by guest to check cancellation.
+/// - `frame_count` : Process frame count, sampled once per call.
+/// - `physics_tick` : Physics frame count, sampled once per call.
+/// - `rand` : Fills `len` bytes of exported memory at `ptr` with instance random generator.
+fn host_builtin_func<T>(store: impl AsContextMut<Data = T>, name: &str) -> Option<Func>
+where
+    T: 'static + AsRef<StoreData> + AsMut<StoreData>,
+{
+    Some(match name {
+        HOST_IS_CANCELLED => Func::wrap(store, |ctx: Caller<'_, T>| -> i32 {
+            ctx.data().as_ref().is_cancelled().into()
+        }),
+        HOST_FRAME_COUNT => Func::wrap(store, |mut ctx: Caller<'_, T>| -> i64 {
+            let lock = &mut ctx.data_mut().as_mut().inner_lock;
+            lock.frame_info().process_frames as i64
+        }),
+        HOST_PHYSICS_TICK => Func::wrap(store, |mut ctx: Caller<'_, T>| -> i64 {
+            let lock = &mut ctx.data_mut().as_mut().inner_lock;
+            lock.frame_info().physics_frames as i64
+        }),
+        HOST_RAND => Func::wrap(
+            store,
+            |mut ctx: Caller<'_, T>, p: u32, n: u32| -> AnyResult<()> {
+                let Some(Extern::Memory(mem)) = ctx.get_export(MEMORY_EXPORT) else {
+                    bail_with_site!("No memory exported")
+                };
+                let (data, store) = mem.data_and_store_mut(&mut ctx);
+                let Some(buf) = data.get_mut(p as usize..p as usize + n as usize) else {
+                    bail_with_site!("Pointer out of bound ({p:#x}, {n})")
+                };
+                let rng = store.as_mut().rng.get_or_insert_with(StdRng::from_os_rng);
+                rng.fill_bytes(buf);
+                Ok(())
+            },
+        ),
+        _ => return None,
+    })
+}
+
+/// Reference point of activity timestamps.
+static ACTIVITY_START: Lazy<Instant> = Lazy::new(Instant::now);
+
+#[inline]
+fn activity_now() -> u64 {
+    ACTIVITY_START.elapsed().as_millis() as u64
+}
+
+/// Tracks last guest activity of an instance.
+#[derive(Debug)]
+pub struct ActivityTracker {
+    /// Timestamp of last activity, in milliseconds since [`ACTIVITY_START`].
+    last: AtomicU64,
+    /// Number of calls in flight.
+    in_flight: AtomicUsize,
+    /// Interrupt requested for calls in flight.
+    interrupt: AtomicBool,
+    /// Cooperative cancellation requested, polled by guest.
+    cancel: AtomicBool,
+}
+
+impl Default for ActivityTracker {
+    fn default() -> Self {
+        Self {
+            last: AtomicU64::new(activity_now()),
+            in_flight: AtomicUsize::new(0),
+            interrupt: AtomicBool::new(false),
+            cancel: AtomicBool::new(false),
+        }
+    }
+}
+
+impl ActivityTracker {
+    /// Records activity.
+    #[inline]
+    pub fn touch(&self) {
+        self.last.store(activity_now(), Ordering::Relaxed);
+    }
+
+    /// Timestamp of last activity.
+    pub fn last(&self) -> u64 {
+        self.last.load(Ordering::Relaxed)
+    }
+
+    /// Time elapsed since last activity.
+    pub fn idle_time(&self) -> Duration {
+        Duration::from_millis(activity_now().saturating_sub(self.last()))
+    }
+
+    /// Returns `true` if any call is in flight.
+    pub fn is_busy(&self) -> bool {
+        self.in_flight.load(Ordering::Acquire) != 0
+    }
+
+    /// Marks a call in flight until returned guard is dropped.
+    ///
+    /// Cancellation flag is reset when outermost call starts.
+    /// Pending interrupt is cleared once no more call is in flight.
+    pub fn enter(&self) -> impl Drop + '_ {
+        if self.in_flight.fetch_add(1, Ordering::AcqRel) == 0 {
+            self.cancel.store(false, Ordering::Release);
         }
+        guard(self, |this| {
+            if this.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+                this.interrupt.store(false, Ordering::Release);
+            }
+        })
+    }
+
+    /// Requests calls in flight to trap at next epoch deadline check.
+    pub fn interrupt(&self) {
+        self.interrupt.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if interrupt is requested.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupt.load(Ordering::Acquire)
+    }
+
+    /// Requests calls in flight to stop. Guest must poll it with `is_cancelled`.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if cancellation is requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Acquire)
+    }
+}
+
+/// Cancellation state of a single call, see [`WasmInstance::call_export_with`].
+///
+/// Unlike [`ActivityTracker`], it does not affect other calls into the same instance.
+#[derive(Debug, Default)]
+pub struct CallToken {
+    interrupt: AtomicBool,
+    cancel: AtomicBool,
+}
+
+impl CallToken {
+    /// Requests call to trap at next epoch deadline check.
+    pub fn interrupt(&self) {
+        self.interrupt.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if interrupt is requested.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupt.load(Ordering::Acquire)
+    }
+
+    /// Requests call to stop. Guest must poll it with `is_cancelled`.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if cancellation is requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Acquire)
     }
 }
 
@@ -199,11 +738,28 @@ impl Default for InnerLock {
 pub struct StoreData {
     inner_lock: InnerLock,
     pub error_signal: Option<String>,
+    pub activity: Arc<ActivityTracker>,
+    /// Token of running call, set by [`WasmInstance::call_export_with`].
+    pub call_token: Option<Arc<CallToken>>,
+    pub copy_mode: CopyMode,
+    pub marshal_limits: MarshalLimits,
+    /// Overrides both marshal limits, set by `with_marshal_limit`.
+    pub marshal_override: Option<usize>,
+    /// Number of characters replaced while converting strings.
+    pub string_errors: u64,
+    /// Guest allocator used by host.
+    pub guest_alloc: GuestAllocator,
+    /// Return conventions of exports, set by `declare_return_convention`.
+    pub return_conventions: HashMap<String, ReturnConvention>,
+    /// Random generator of `host.rand`. Seeded from OS entropy on first use if unset.
+    pub rng: Option<StdRng>,
 
     #[cfg(feature = "epoch-timeout")]
     pub epoch_timeout: u64,
     #[cfg(feature = "epoch-timeout")]
     pub epoch_autoreset: bool,
+    #[cfg(feature = "epoch-timeout")]
+    pub epoch_deadline: EpochDeadline,
 
     #[cfg(feature = "memory-limiter")]
     pub memory_limits: MemoryLimit,
@@ -213,9 +769,14 @@ pub struct StoreData {
 
     #[cfg(feature = "object-registry-extern")]
     pub use_extern: bool,
+    #[cfg(feature = "object-registry-extern")]
+    pub extern_names: NameTable,
 
     #[cfg(feature = "wasi")]
     pub wasi_ctx: Option<WasiCtx>,
+
+    /// Lazy dependencies of root module, keyed by import name.
+    pub lazy_deps: HashMap<String, Arc<LazyDep>>,
 }
 
 impl AsRef<Self> for StoreData {
@@ -248,6 +809,17 @@ impl HasEpochTimeout for StoreData {
         self.epoch_timeout
     }
 
+    #[cfg(feature = "epoch-timeout")]
+    fn get_epoch_deadline(&mut self) -> &mut EpochDeadline {
+        &mut self.epoch_deadline
+    }
+
+    #[cfg(feature = "epoch-timeout")]
+    fn is_interrupted(&self) -> bool {
+        self.activity.is_interrupted()
+            || self.call_token.as_ref().is_some_and(|t| t.is_interrupted())
+    }
+
     #[cfg(feature = "wasi")]
     fn get_wasi_ctx(&mut self) -> Option<&mut WasiCtx> {
         self.wasi_ctx.as_mut()
@@ -258,6 +830,14 @@ impl HasEpochTimeout for StoreData {
 pub struct MemoryLimit {
     pub max_memory: u64,
     pub max_table_entries: u64,
+    /// Instance reported on global memory pressure.
+    pub instance: Option<InstanceId>,
+    /// Memory reserved from engine-wide budget.
+    pub reserved: MemoryReservation,
+    /// Largest linear memory size observed.
+    pub peak: u64,
+    /// Unix time of peak, in seconds.
+    pub peak_time: f64,
 }
 
 #[cfg(feature = "memory-limiter")]
@@ -266,14 +846,21 @@ impl Default for MemoryLimit {
         Self {
             max_memory: u64::MAX,
             max_table_entries: u64::MAX,
+            instance: None,
+            reserved: MemoryReservation::default(),
+            peak: 0,
+            peak_time: 0.0,
         }
     }
 }
 
 #[cfg(feature = "memory-limiter")]
 impl MemoryLimit {
-    pub fn from_config(config: &Config) -> Self {
-        let mut ret = Self::default();
+    pub fn from_config(config: &Config, instance: Option<InstanceId>) -> Self {
+        let mut ret = Self {
+            instance,
+            ..Self::default()
+        };
         if let Some(v) = config.max_memory {
             ret.max_memory = v;
         }
@@ -282,6 +869,24 @@ impl MemoryLimit {
         }
         ret
     }
+
+    /// Updates peak memory size. Only called when memory grows.
+    fn record_peak(&mut self, size: u64) {
+        if size <= self.peak {
+            return;
+        }
+        self.peak = size;
+        self.peak_time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0.0, |v| v.as_secs_f64());
+        record_memory_peak(size);
+    }
+
+    /// Resets peak memory size to `current`.
+    pub fn reset_peak(&mut self, current: u64) {
+        self.peak = current;
+        self.peak_time = 0.0;
+    }
 }
 
 #[cfg(feature = "memory-limiter")]
@@ -294,17 +899,22 @@ impl ResourceLimiter for MemoryLimit {
     ) -> AnyResult<bool> {
         if max.is_some_and(|max| desired > max) {
             return Ok(false);
-        } else if self.max_memory == u64::MAX {
-            return Ok(true);
         }
 
         let delta = (desired - current) as u64;
-        if let Some(v) = self.max_memory.checked_sub(delta) {
-            self.max_memory = v;
-            Ok(true)
+        let rest = if self.max_memory == u64::MAX {
+            u64::MAX
+        } else if let Some(v) = self.max_memory.checked_sub(delta) {
+            v
         } else {
-            Ok(false)
+            return Ok(false);
+        };
+        if !self.reserved.grow(delta, self.instance) {
+            return Ok(false);
         }
+        self.max_memory = rest;
+        self.record_peak(desired as u64);
+        Ok(true)
     }
 
     fn table_growing(
@@ -340,12 +950,94 @@ struct InstanceArgs<'a, T> {
     #[cfg(feature = "object-registry-extern")]
     externref_funcs: ExternrefFuncs,
     #[cfg(feature = "wasi")]
-    wasi_linker: Option<Linker<T>>,
+    wasi_linker: Option<Arc<Linker<T>>>,
+    /// Lazy dependencies. `None` marks dependency in the middle of resolving imports.
+    lazy: HashMap<InstanceId, Option<Arc<LazyDep>>>,
+    /// Resolves imports not found anywhere else.
+    fallback: Option<&'a Linker<T>>,
+}
+
+enum LazyState {
+    Pending,
+    Initializing,
+    Ready(InstanceWasm),
+    Failed,
+}
+
+/// Dependency module instantiated on first use.
+///
+/// Imports are resolved eagerly, only instantiation (and start function) is deferred.
+pub struct LazyDep {
+    name: String,
+    module: Module,
+    imports: Vec<Extern>,
+    state: Mutex<LazyState>,
+}
+
+impl LazyDep {
+    fn new(name: String, module: Module, imports: Vec<Extern>) -> Self {
+        Self {
+            name,
+            module,
+            imports,
+            state: Mutex::new(LazyState::Pending),
+        }
+    }
+
+    /// Returns `true` if dependency is instantiated.
+    pub fn is_ready(&self) -> bool {
+        matches!(*self.state.lock(), LazyState::Ready(_))
+    }
+
+    /// Gets dependency instance, instantiating it if needed.
+    ///
+    /// Lock is not held while instantiating, so reentrant call during instantiation fails.
+    pub fn get(&self, mut store: impl AsContextMut) -> AnyResult<InstanceWasm> {
+        {
+            let mut state = self.state.lock();
+            match &*state {
+                LazyState::Ready(v) => return Ok(*v),
+                LazyState::Initializing => {
+                    bail_with_site!("Circular lazy initialization of dependency {:?}", self.name)
+                }
+                LazyState::Failed => {
+                    bail_with_site!("Dependency {:?} failed to instantiate", self.name)
+                }
+                LazyState::Pending => *state = LazyState::Initializing,
+            }
+        }
+
+        let _s = debug_span!("LazyDep.get", name = %self.name).entered();
+        let r = InstanceWasm::new(store.as_context_mut(), &self.module, &self.imports);
+        *self.state.lock() = match &r {
+            Ok(v) => LazyState::Ready(*v),
+            Err(_) => LazyState::Failed,
+        };
+        r
+    }
+
+    /// Creates function that instantiates dependency and forwards call to it's export.
+    fn trampoline<T: 'static>(
+        self: &Arc<Self>,
+        store: impl AsContextMut<Data = T>,
+        name: &str,
+        ty: FuncType,
+    ) -> Func {
+        let this = self.clone();
+        let name = name.to_string();
+        Func::new(store, ty, move |mut caller, args, rets| {
+            let inst = this.get(&mut caller)?;
+            let Some(f) = inst.get_func(&mut caller, &name) else {
+                bail_with_site!("Dependency {:?} does not export {name:?}", this.name)
+            };
+            f.call(&mut caller, args, rets)
+        })
+    }
 }
 
 impl<T> InstanceData<T>
 where
-    T: Send + AsRef<StoreData> + AsMut<StoreData> + HasEpochTimeout,
+    T: 'static + Send + AsRef<StoreData> + AsMut<StoreData> + HasEpochTimeout,
 {
     #[instrument(level = Level::DEBUG, skip_all, fields(?obj, ?module))]
     pub fn instantiate<C: GodotClass>(
@@ -356,6 +1048,9 @@ where
         host: Option<Dictionary>,
     ) -> AnyResult<Self> {
         config_store_common(&mut store, config)?;
+        if config.sandbox_pure {
+            return Self::instantiate_pure(store, config, module, host);
+        }
 
         #[cfg(feature = "wasi")]
         let mut wasi_stdin = None;
@@ -367,27 +1062,50 @@ where
             let _s = debug_span!("instantiate.wasi").entered();
             let mut builder = WasiCtx::builder();
 
-            let StoreData { wasi_ctx, .. } = store.data_mut().as_mut();
+            let StoreData {
+                wasi_ctx, activity, ..
+            } = store.data_mut().as_mut();
 
-            if config.wasi_stdin == PipeBindingType::Instance {
+            let [stdin, stdout, stderr] = config.effective_stdio();
+            if stdin == PipeBindingType::Instance {
                 if let Some(data) = config.wasi_stdin_data.clone() {
                     builder.stdin(Arc::new(PackedByteArrayReader::from(data)))
                 } else {
                     let signal =
                         SendSyncWrapper::new(Signal::from_object_signal(obj, c"stdin_request"));
-                    builder.stdin_signal(Box::new(move || signal.emit(&[])))
+                    let activity = activity.clone();
+                    let f: Box<dyn Fn() + Send + Sync> = Box::new(move || {
+                        activity.touch();
+                        signal.emit(&[])
+                    });
+                    match config.wasi_stdin_max_bytes {
+                        Some(cap) => {
+                            let drained = SendSyncWrapper::new(Signal::from_object_signal(
+                                obj,
+                                c"stdin_drained",
+                            ));
+                            builder.stdin_signal_limit(
+                                f,
+                                cap,
+                                Box::new(move || emit_in_main_thread(&drained, &[])),
+                            )
+                        }
+                        None => builder.stdin_signal(f),
+                    }
                 }?;
             }
-            if config.wasi_stdout == PipeBindingType::Instance {
+            if stdout == PipeBindingType::Instance {
                 builder.stdout(WasiContext::make_host_stdout(
                     Signal::from_object_signal(obj, c"stdout_emit"),
                     config.wasi_stdout_buffer,
+                    config.wasi_stdout_coalesce,
                 ))?;
             }
-            if config.wasi_stderr == PipeBindingType::Instance {
+            if stderr == PipeBindingType::Instance {
                 builder.stderr(WasiContext::make_host_stdout(
                     Signal::from_object_signal(obj, c"stderr_emit"),
                     config.wasi_stderr_buffer,
+                    config.wasi_stderr_coalesce,
                 ))?;
             }
 
@@ -395,17 +1113,29 @@ where
                 Some(ctx) => WasiContext::build_ctx(ctx, &mut builder, config),
                 None => WasiContext::init_ctx_no_context(&mut builder, config),
             }?;
+            if config.wasi_tmp {
+                builder.instance_tmp(format!(".inst-{}", obj.instance_id()))?;
+            }
+            if let Some(tap) = open_nondet_tap(config)? {
+                builder.nondet_tap(tap);
+            }
             let ctx = builder.build()?;
             wasi_stdin = ctx.stdin_provider().map(|v| v.dup());
             *wasi_ctx = Some(ctx);
-            let mut r = <Linker<T>>::new(store.engine());
-            add_to_linker(&mut r, |data| {
-                data.as_mut()
-                    .wasi_ctx
-                    .as_mut()
-                    .expect("WASI context required, but none supplied")
-            })?;
-            wasi_linker = Some(r);
+            let key = LinkerKey {
+                wasi: true,
+                ..LinkerKey::default()
+            };
+            wasi_linker = Some(get_linker(store.engine(), key, || {
+                let mut r = <Linker<T>>::new(store.engine());
+                add_to_linker(&mut r, |data| {
+                    data.as_mut()
+                        .wasi_ctx
+                        .as_mut()
+                        .expect("WASI context required, but none supplied")
+                })?;
+                Ok(r)
+            })?);
         }
 
         #[cfg(feature = "object-registry-compat")]
@@ -417,19 +1147,43 @@ where
             store.data_mut().as_mut().use_extern = config.extern_bind == ExternBindingType::Native;
         }
 
-        let instance = InstanceArgs {
-            store: store.as_context_mut(),
-            config,
-            insts: HashMap::new(),
-            host: host.map(HostModuleCache::new).transpose()?,
-            #[cfg(feature = "object-registry-compat")]
-            objregistry_funcs: ObjregistryFuncs::default(),
-            #[cfg(feature = "object-registry-extern")]
-            externref_funcs: ExternrefFuncs::default(),
-            #[cfg(feature = "wasi")]
-            wasi_linker,
-        }
-        .instantiate_wasm(module.bind().get_data()?)?;
+        let host = match host {
+            None if config.host_objects.is_empty() => None,
+            h => Some(HostModuleCache::new(
+                store.engine(),
+                h.unwrap_or_default(),
+                config.host_objects.clone(),
+            )?),
+        };
+        let (instance, lazy) = {
+            let data = module.bind();
+            let data = data.get_data()?;
+            let mut args = InstanceArgs {
+                store: store.as_context_mut(),
+                config,
+                insts: HashMap::new(),
+                host,
+                #[cfg(feature = "object-registry-compat")]
+                objregistry_funcs: ObjregistryFuncs::default(),
+                #[cfg(feature = "object-registry-extern")]
+                externref_funcs: ExternrefFuncs::default(),
+                #[cfg(feature = "wasi")]
+                wasi_linker,
+                lazy: HashMap::new(),
+                fallback: None,
+            };
+            let instance = args.instantiate_wasm(data)?;
+            let lazy = data
+                .imports
+                .iter()
+                .filter_map(|(k, v)| match args.lazy.get(&v.instance_id()) {
+                    Some(Some(v)) => Some((k.clone(), v.clone())),
+                    _ => None,
+                })
+                .collect();
+            (instance, lazy)
+        };
+        store.data_mut().as_mut().lazy_deps = lazy;
 
         Ok(Self {
             instance: InstanceType::Core(instance),
@@ -439,33 +1193,245 @@ where
             wasi_stdin,
         })
     }
-}
 
-impl<T> InstanceArgs<'_, T>
-where
-    T: Send + AsRef<StoreData> + AsMut<StoreData> + HasEpochTimeout,
-{
-    #[instrument(skip_all, fields(?module.module))]
-    fn instantiate_wasm(&mut self, module: &ModuleData) -> AnyResult<InstanceWasm> {
-        #[allow(irrefutable_let_patterns)]
-        let ModuleType::Core(module_) = &module.module
-        else {
-            bail_with_site!("Cannot instantiate component")
+    /// Instantiates module without any imports.
+    ///
+    /// WASI context, object registry, and host modules are never created.
+    #[instrument(level = Level::DEBUG, skip_all, fields(?module))]
+    fn instantiate_pure(
+        mut store: Store<T>,
+        config: &Config,
+        module: Gd<WasmModule>,
+        host: Option<Dictionary>,
+    ) -> AnyResult<Self> {
+        if host.is_some_and(|v| !v.is_empty()) {
+            bail_with_site!("Pure sandbox does not allow host imports");
+        }
+        check_pure_config(config)?;
+
+        let instance = {
+            let m = module.bind();
+            #[allow(irrefutable_let_patterns)]
+            let ModuleType::Core(module_) = &m.get_data()?.module
+            else {
+                bail_with_site!("Cannot instantiate component")
+            };
+            check_pure_module(module_)?;
+            #[cfg(feature = "epoch-timeout")]
+            let _armed = config.with_epoch.then(arm_deadline);
+            site_context!(InstanceWasm::new(&mut store, module_, &[]))?
         };
 
-        let imports = module_
-            .imports()
-            .map(|i| {
-                let _s = debug_span!("instantiate_wasm.import", import = ?i).entered();
-                if let Some(v) = &mut self.host {
-                    if let Some(v) =
+        Ok(Self {
+            instance: InstanceType::Core(instance),
+            module,
+            store: Mutex::new(store),
+            #[cfg(feature = "wasi")]
+            wasi_stdin: None,
+        })
+    }
+}
+
+/// Checks that config does not need any import or host state for pure sandbox.
+fn check_pure_config(config: &Config) -> AnyResult<()> {
+    if !config.host_objects.is_empty() {
+        bail_with_site!("Pure sandbox does not allow host imports");
+    }
+    #[cfg(feature = "wasi")]
+    if config.with_wasi {
+        bail_with_site!("Pure sandbox does not allow WASI");
+    }
+    if !config.memory_imports.is_empty() {
+        bail_with_site!("Pure sandbox does not allow memory imports");
+    }
+    Ok(())
+}
+
+/// Checks that module has no imports for pure sandbox.
+fn check_pure_module(module: &Module) -> AnyResult<()> {
+    let imports = module
+        .imports()
+        .map(|i| format!("{}.{}", i.module(), i.name()))
+        .collect::<Vec<_>>();
+    if !imports.is_empty() {
+        bail_with_site!(
+            "Pure sandbox does not allow imports: {}",
+            imports.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Instantiates core module without any Godot object.
+///
+/// Imports are resolved like [`InstanceData::instantiate`], minus dependencies, host dictionary, and WASI.
+/// Imports not found are taken from `fallback`. Used by headless smoke tests.
+#[cfg(all(test, feature = "smoke-test"))]
+pub fn instantiate_headless<T>(
+    store: &mut Store<T>,
+    config: &Config,
+    module: &Module,
+    fallback: &Linker<T>,
+) -> AnyResult<InstanceWasm>
+where
+    T: 'static + Send + AsRef<StoreData> + AsMut<StoreData> + HasEpochTimeout,
+{
+    config_store_common(store, config)?;
+    #[cfg(feature = "wasi")]
+    if config.with_wasi {
+        bail_with_site!("WASI is not supported in headless instantiation");
+    }
+
+    let mut args = InstanceArgs {
+        store: store.as_context_mut(),
+        config,
+        insts: HashMap::new(),
+        host: None,
+        #[cfg(feature = "object-registry-compat")]
+        objregistry_funcs: ObjregistryFuncs::default(),
+        #[cfg(feature = "object-registry-extern")]
+        externref_funcs: ExternrefFuncs::default(),
+        #[cfg(feature = "wasi")]
+        wasi_linker: None,
+        lazy: HashMap::new(),
+        fallback: Some(fallback),
+    };
+    let imports = args.resolve_imports(module, &HashMap::new())?;
+    site_context!(InstanceWasm::new(&mut args.store, module, &imports))
+}
+
+impl<T> InstanceArgs<'_, T>
+where
+    T: 'static + Send + AsRef<StoreData> + AsMut<StoreData> + HasEpochTimeout,
+{
+    #[instrument(skip_all, fields(?module.module))]
+    fn instantiate_wasm(&mut self, module: &ModuleData) -> AnyResult<InstanceWasm> {
+        let module_ = module.module.get_core()?;
+        if !self.config.lenient_reference_types {
+            let unsupported =
+                check_reference_types(module_, self.use_extern(), |m| self.is_host_module(m));
+            if !unsupported.is_empty() {
+                bail_with_site!(
+                    "Module uses unsupported reference types \
+                    (set extern.lenientReferenceTypes to skip these functions):\n{}",
+                    unsupported.join("\n")
+                );
+            }
+        }
+        let imports = self.resolve_imports(module_, &module.imports)?;
+        // Start function runs with deadline too.
+        #[cfg(feature = "epoch-timeout")]
+        let _armed = self.config.with_epoch.then(arm_deadline);
+        InstanceWasm::new(&mut self.store, module_, &imports)
+    }
+
+    fn use_extern(&self) -> bool {
+        self.store.data().as_ref().is_extern_native()
+    }
+
+    fn is_host_module(&self, module: &str) -> bool {
+        self.host.as_ref().is_some_and(|h| h.has_module(module))
+    }
+
+    /// Gets lazy dependency, resolving it's imports if it's not yet created.
+    fn lazy_dep(&mut self, name: &str, o: &Gd<WasmModule>) -> AnyResult<Arc<LazyDep>> {
+        let id = o.instance_id();
+        match self.lazy.entry(id) {
+            Entry::Occupied(v) => match v.get() {
+                Some(v) => return Ok(v.clone()),
+                None => bail_with_site!("Recursive data structure"),
+            },
+            Entry::Vacant(v) => v.insert(None),
+        };
+
+        let o = o.bind();
+        let data = o.get_data()?;
+        let module = data.module.get_core()?;
+        let imports = self.resolve_imports(module, &data.imports)?;
+        let v = Arc::new(LazyDep::new(name.to_string(), module.clone(), imports));
+        self.lazy.insert(id, Some(v.clone()));
+        Ok(v)
+    }
+
+    /// Resolves imports of module.
+    ///
+    /// Takes dependencies separately from [`ModuleData`], so module can be resolved without `WasmModule`.
+    #[instrument(skip_all, fields(?module))]
+    fn resolve_imports(
+        &mut self,
+        module: &Module,
+        deps: &HashMap<String, Gd<WasmModule>>,
+    ) -> AnyResult<Vec<Extern>> {
+        module
+            .imports()
+            .map(|i| {
+                let _s = debug_span!("instantiate_wasm.import", import = ?i).entered();
+                if let ExternType::Func(ty) = i.ty() {
+                    if let Some(e) = unsupported_ref_type(&ty, self.use_extern())
+                        .filter(|_| self.is_host_module(i.module()))
+                    {
+                        let msg =
+                            format!("Host import {}.{} is skipped: {e}", i.module(), i.name());
+                        return Ok(unsupported_import_stub(&mut self.store, ty, msg).into());
+                    }
+                }
+
+                if let Some(v) = &mut self.host {
+                    if let Some(v) =
                         v.get_extern(self.store.as_context_mut(), i.module(), i.name())?
                     {
                         return Ok(v);
                     }
                 }
 
-                if let Some(o) = module.imports.get(i.module()) {
+                if i.module() == HOST_MODULE {
+                    if let Some(v) = host_builtin_func(&mut self.store, i.name()) {
+                        return Ok(v.into());
+                    }
+                }
+
+                if let Some((.., mem)) = self
+                    .config
+                    .memory_imports
+                    .iter()
+                    .find(|(m, n, _)| m == i.module() && n == i.name())
+                {
+                    // Shared memory is created with main engine.
+                    if !is_main_engine(self.store.engine()) {
+                        bail_with_site!(
+                            "Shared memory {}.{} cannot be imported by module compiled with winch",
+                            i.module(),
+                            i.name()
+                        );
+                    }
+                    return Ok(Extern::SharedMemory(mem.clone()));
+                }
+
+                if self.config.deps_lazy {
+                    if let Some(o) = deps.get(i.module()) {
+                        let _s = debug_span!("instantiate_wasm.import.lazy", ?o).entered();
+                        let dep = self.lazy_dep(i.module(), o)?;
+                        // Other externs can't be deferred.
+                        return match i.ty() {
+                            ExternType::Func(ty) => {
+                                Ok(dep.trampoline(&mut self.store, i.name(), ty).into())
+                            }
+                            _ => match dep
+                                .get(&mut self.store)?
+                                .get_export(&mut self.store, i.name())
+                            {
+                                Some(v) => Ok(v),
+                                None => bail_with_site!(
+                                    "Unknown import {:?}.{:?}",
+                                    i.module(),
+                                    i.name()
+                                ),
+                            },
+                        };
+                    }
+                }
+
+                if let Some(o) = deps.get(i.module()) {
                     let _s = debug_span!("instantiate_wasm.import.recursive", ?o).entered();
                     let id = o.instance_id();
                     let mut v = match self.insts.entry(id) {
@@ -520,11 +1486,15 @@ where
                     }
                 }
 
+                if let Some(v) = self.fallback {
+                    if let Some(v) = v.get_by_import(&mut self.store, &i) {
+                        return Ok(v);
+                    }
+                }
+
                 bail_with_site!("Unknown import {:?}.{:?}", i.module(), i.name());
             })
-            .collect::<AnyResult<Vec<_>>>()?;
-
-        InstanceWasm::new(&mut self.store, module_, &imports)
+            .collect()
     }
 }
 
@@ -554,7 +1524,21 @@ where
     }
 }
 
-impl InnerLock {
+impl<C> InnerLock<C> {
+    /// Gets frame counters. They're sampled once per outermost call, so repeated reads are consistent.
+    pub fn frame_info(&mut self) -> FrameInfo {
+        self.frame_info_with(FrameInfo::sample)
+    }
+
+    fn frame_info_with(&mut self, sample: impl FnOnce() -> FrameInfo) -> FrameInfo {
+        *self.frame_info.get_or_insert_with(sample)
+    }
+
+    /// Discards sampled frame counters, called at the start of outermost call.
+    fn reset_frame_info(&mut self) {
+        self.frame_info = None;
+    }
+
     #[instrument(skip(self, f))]
     pub fn release_store<F, R>(&mut self, f: F) -> R
     where
@@ -577,9 +1561,44 @@ impl InnerLock {
 
         f()
     }
+
+    /// Like `release_store`, but makes sure `f` runs in main thread.
+    ///
+    /// Use it for calls that invokes object methods.
+    #[instrument(skip(self, f))]
+    pub fn release_store_main<F, R>(&mut self, f: F) -> AnyResult<R>
+    where
+        F: FnOnce() -> R,
+    {
+        if check_object_call(self.object_calls)? {
+            debug!("Deferring call to main thread");
+            self.release_store(move || call_in_main_thread(f))
+        } else {
+            Ok(self.release_store(f))
+        }
+    }
+
+    /// Like `release_store_main`, but records host callable frame.
+    ///
+    /// Use it for calls that might reenter guest.
+    pub fn release_store_call<F, R>(&mut self, frame: C, f: F) -> AnyResult<R>
+    where
+        F: FnOnce() -> R,
+        C: Display,
+    {
+        self.reentrancy.enter(frame)?;
+        let r = self.release_store_main(f);
+        self.reentrancy.exit();
+        r
+    }
 }
 
 impl StoreData {
+    /// Returns `true` if running call is requested to stop, either by instance or by it's token.
+    pub fn is_cancelled(&self) -> bool {
+        self.activity.is_cancelled() || self.call_token.as_ref().is_some_and(|t| t.is_cancelled())
+    }
+
     #[inline]
     pub(crate) fn release_store<F, R>(&mut self, f: F) -> R
     where
@@ -588,6 +1607,83 @@ impl StoreData {
         self.inner_lock.release_store(f)
     }
 
+    #[inline]
+    pub(crate) fn release_store_main<F, R>(&mut self, f: F) -> AnyResult<R>
+    where
+        F: FnOnce() -> R,
+    {
+        self.inner_lock.release_store_main(f)
+    }
+
+    /// Returns `true` if externref is converted into variant.
+    #[inline]
+    pub fn is_extern_native(&self) -> bool {
+        cfg_if! {
+            if #[cfg(feature = "object-registry-extern")] {
+                self.use_extern
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Returns approximate size of host state,
+    /// and whether WASI context and object registry is allocated.
+    pub fn host_state_usage(&self) -> (usize, bool, bool) {
+        let mut size = mem::size_of::<Self>();
+
+        #[cfg(feature = "wasi")]
+        let wasi_context = self.wasi_ctx.is_some();
+        #[cfg(not(feature = "wasi"))]
+        let wasi_context = false;
+        #[cfg(feature = "wasi")]
+        if wasi_context {
+            size += mem::size_of::<WasiCtx>();
+        }
+
+        #[cfg(feature = "object-registry-compat")]
+        let object_registry = self.object_registry.as_ref().map(|v| v.heap_size());
+        #[cfg(not(feature = "object-registry-compat"))]
+        let object_registry: Option<usize> = None;
+        size += object_registry.unwrap_or(0);
+
+        (size, wasi_context, object_registry.is_some())
+    }
+
+    /// Fails if data going into guest exceeds marshal limit.
+    pub fn check_arg_size(&self, size: usize) -> AnyResult<()> {
+        let limit = self
+            .marshal_override
+            .unwrap_or(self.marshal_limits.max_arg_bytes);
+        if size > limit {
+            bail_with_site!(
+                "Argument size ({size} bytes) exceeds marshal.maxArgBytes limit ({limit} bytes)"
+            );
+        }
+        Ok(())
+    }
+
+    /// Fails if data coming out of guest exceeds marshal limit.
+    pub fn check_ret_size(&self, size: usize) -> AnyResult<()> {
+        let limit = self
+            .marshal_override
+            .unwrap_or(self.marshal_limits.max_ret_bytes);
+        if size > limit {
+            bail_with_site!(
+                "Return size ({size} bytes) exceeds marshal.maxRetBytes limit ({limit} bytes)"
+            );
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) fn release_store_call<F, R>(&mut self, frame: CallFrame, f: F) -> AnyResult<R>
+    where
+        F: FnOnce() -> R,
+    {
+        self.inner_lock.release_store_call(frame, f)
+    }
+
     #[cfg(feature = "object-registry-compat")]
     pub fn get_registry(&self) -> AnyResult<&ObjectRegistry> {
         match self.object_registry.as_ref() {
@@ -631,7 +1727,7 @@ impl WasmInstance {
         match self.get_data().and_then(f) {
             Ok(v) => Some(v),
             Err(e) => {
-                let s = format!("{e:?}");
+                let s = format_error(&e);
                 /*
                 error(
                     e.downcast_ref::<Site>()
@@ -647,27 +1743,69 @@ impl WasmInstance {
         }
     }
 
+    fn parse_config(config: Option<Variant>) -> Config {
+        match config {
+            Some(v) => match Config::try_from_variant(&v) {
+                Ok(v) => v,
+                Err(e) => {
+                    godot_error!("{:?}", e);
+                    Config::default()
+                }
+            },
+            None => Config::default(),
+        }
+    }
+
+    /// Marks instance as compiling and enables call queue, if it's not initialized yet.
+    ///
+    /// Returns `true` if instance is already initialized.
+    fn begin_initialize(&self, config: &Config) -> bool {
+        let was_ready = self.data.get().is_some();
+        if !was_ready {
+            if let Some(v) = config.queue_calls {
+                self.queued_calls.lock().limit = Some(v);
+            }
+            self.set_state(InstanceState::Compiling);
+        }
+        was_ready
+    }
+
     #[instrument(level = Level::DEBUG, skip_all, fields(?self, ?module))]
     pub fn initialize_(
         &self,
         module: Gd<WasmModule>,
         host: Option<Dictionary>,
         config: Option<Variant>,
+    ) -> bool {
+        let config = Self::parse_config(config);
+        let was_ready = self.begin_initialize(&config);
+        self.finish_initialize(module, host, config, was_ready)
+    }
+
+    /// Instantiates module, then runs queued calls.
+    fn finish_initialize(
+        &self,
+        module: Gd<WasmModule>,
+        host: Option<Dictionary>,
+        config: Config,
+        was_ready: bool,
     ) -> bool {
         let r = self.data.get_or_try_init(move || -> AnyResult<_> {
+            // Module must be instantiated with the engine it's compiled with.
+            let engine = module.bind().get_data()?.module.engine().clone();
+            let store_data = StoreData {
+                activity: self.activity.clone(),
+                #[cfg(feature = "memory-limiter")]
+                memory_limits: MemoryLimit {
+                    instance: Some(self.base().instance_id()),
+                    ..MemoryLimit::default()
+                },
+                ..StoreData::default()
+            };
             let mut ret = InstanceData::instantiate(
                 &self.to_gd(),
-                Store::new(&site_context!(get_engine())?, StoreData::default()),
-                &match config {
-                    Some(v) => match Config::try_from_variant(&v) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            godot_error!("{:?}", e);
-                            Config::default()
-                        }
-                    },
-                    None => Config::default(),
-                },
+                Store::new(&engine, store_data),
+                &config,
                 module,
                 host,
             )?;
@@ -686,18 +1824,98 @@ impl WasmInstance {
                     _ => None,
                 };
             }
+
+            if let Some(t) = idle_unload_timeout() {
+                register_idle_unload(
+                    &self.activity,
+                    t,
+                    Callable::from_object_method(
+                        &self.to_gd(),
+                        &StringName::from(c"_on_idle_unload"),
+                    ),
+                )?;
+            }
             Ok(ret)
         });
-        if let Err(e) = r {
+        let ret = if let Err(e) = r {
             let s = format!("{e:?}");
             godot_error!("{s}");
             self.emit_error_wrapper(s);
+            self.set_state(InstanceState::Errored);
             false
         } else {
+            if !was_ready {
+                let id = self.base().instance_id();
+                if self.registered.set(id).is_ok() {
+                    LIVE_INSTANCES.lock().push(id);
+                }
+                self.set_state(InstanceState::Ready);
+            }
             true
+        };
+        self.run_queued_calls(ret);
+        // Emitted after queued calls, so calls made by handler are not queued.
+        if ret && !was_ready {
+            emit_in_main_thread(&Signal::from_object_signal(&self.to_gd(), c"ready"), &[]);
+        }
+        ret
+    }
+
+    /// Queues call if instance is initializing and queueing is enabled.
+    ///
+    /// Returns the call back if it should run immediately.
+    fn queue_call(
+        &self,
+        name: StringName,
+        args: VariantArray,
+    ) -> AnyResult<Option<(StringName, VariantArray)>> {
+        let mut q = self.queued_calls.lock();
+        if !q.is_active() {
+            return Ok(Some((name, args)));
+        }
+        let context = format!("Cannot queue call to {name}");
+        q.push((name, args)).map_err(|e| e.context(context))
+    }
+
+    /// Calls export by name, converting return values by declared convention.
+    fn call_wasm_(&self, name: StringName, args: VariantArray) -> Variant {
+        let r = self.call_export(name.clone(), args).and_then(|v| {
+            let conv = self.get_data()?.acquire_store(|_, store| {
+                store
+                    .data()
+                    .return_conventions
+                    .get(&name.to_string())
+                    .copied()
+            });
+            match conv {
+                Some(conv) => wasm_abi::convert_return(self, conv, &v),
+                None => Ok(v.to_variant()),
+            }
+        });
+        match r {
+            Ok(v) => v,
+            Err(e) => {
+                self.report_call_error(e);
+                Variant::nil()
+            }
         }
     }
 
+    /// Runs queued calls in order, then disables queueing.
+    ///
+    /// If instantiation failed, calls are dropped with `null` result.
+    fn run_queued_calls(&self, ready: bool) {
+        let signal = Signal::from_object_signal(&self.to_gd(), c"call_finished");
+        drain_queue(&self.queued_calls, |(name, args)| {
+            let ret = if ready {
+                self.call_wasm_(name.clone(), args)
+            } else {
+                Variant::nil()
+            };
+            emit_in_main_thread(&signal, &[name.to_variant(), ret]);
+        });
+    }
+
     #[instrument(level = Level::TRACE, skip(f))]
     pub fn acquire_store<F, R>(&self, f: F) -> Option<R>
     where
@@ -711,13 +1929,167 @@ impl WasmInstance {
         })
     }
 
+    /// Transitions lifecycle state, emitting `state_changed` (deferred) if succeed.
+    fn set_state(&self, to: InstanceState) -> bool {
+        let Some(from) = self.state.transition(to) else {
+            return false;
+        };
+        debug!(%from, %to, "State changed");
+        emit_deferred(
+            &Signal::from_object_signal(&self.to_gd(), c"state_changed"),
+            &[(from as i64).to_variant(), (to as i64).to_variant()],
+        );
+        true
+    }
+
+    /// Fails if guest can't be called in current state.
+    fn check_callable(&self) -> AnyResult<()> {
+        match self.state.get() {
+            InstanceState::Exited => match self.exit_code.get() {
+                Some(code) => bail_with_site!("Instance has exited (code {code})"),
+                None => bail_with_site!("Instance has exited"),
+            },
+            s @ (InstanceState::Uninitialized
+            | InstanceState::Compiling
+            | InstanceState::Errored) => bail_with_site!("Cannot call instance, it is {s}"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Marks instance running until returned guard is dropped. Nested calls does nothing.
+    fn enter_running(&self) -> impl Drop + '_ {
+        let entered = self.set_state(InstanceState::Running);
+        guard(entered, |entered| {
+            // Fails if guest has exited.
+            if entered {
+                self.set_state(InstanceState::Ready);
+            }
+        })
+    }
+
+    /// Records guest exit, if error is caused by it.
+    fn record_exit(&self, e: AnyError) -> AnyError {
+        #[cfg(feature = "wasi")]
+        if let Some(v) = e.downcast_ref::<ProcessExit>() {
+            let _ = self.exit_code.set(v.code);
+            self.set_state(InstanceState::Exited);
+        }
+        e
+    }
+
+    /// Reports error of `call_wasm`. Guest exit is not treated as error.
+    fn report_call_error(&self, e: AnyError) {
+        #[cfg(feature = "wasi")]
+        if let Some(v) = e.downcast_ref::<ProcessExit>() {
+            if !v.is_success() {
+                godot_warn!("{v}");
+                self.to_gd().emit_signal(
+                    &StringName::from(c"exited"),
+                    &[(v.code as i64).to_variant()],
+                );
+            }
+            return;
+        }
+
+        let s = format_error(&e);
+        godot_error!("{s}");
+        self.emit_error_wrapper(s);
+    }
+
+    /// Calls export by name. Unlike `call_wasm`, error is returned.
+    pub fn call_export(&self, name: StringName, args: VariantArray) -> AnyResult<VariantArray> {
+        self.call_export_with(name, args, None)
+    }
+
+    /// Like `call_export`, but call can be cancelled with `token`.
+    ///
+    /// Token is only checked once the instance is locked, so cancelling it never affects other calls.
+    pub fn call_export_with(
+        &self,
+        name: StringName,
+        args: VariantArray,
+        token: Option<Arc<CallToken>>,
+    ) -> AnyResult<VariantArray> {
+        self.check_callable()?;
+        self.get_data()?.acquire_store(move |m, mut store| {
+            let _s = debug_span!("call_wasm.inner").entered();
+            self.check_callable()?;
+            if token.as_ref().is_some_and(|t| t.is_interrupted()) {
+                bail_with_site!("Call is cancelled");
+            }
+
+            let export = name.clone();
+            let name = name.to_string();
+            let f = match site_context!(m.instance.get_core())?.get_export(&mut store, &name) {
+                Some(Extern::Func(f)) => f,
+                Some(_) => bail_with_site!("Export {name} is not a function"),
+                None => bail_with_site!("Export {name} does not exists"),
+            };
+            let ty = f.ty(&store);
+            if let Some(e) = unsupported_ref_type(&ty, store.data().is_extern_native()) {
+                bail_with_site!("Export {name} cannot be called: {e}");
+            }
+
+            let _active = self.activity.enter();
+            let _running = self.enter_running();
+            #[cfg(feature = "epoch-timeout")]
+            let _guard = enter_priority(&store.data().epoch_deadline);
+            #[cfg(feature = "epoch-timeout")]
+            reset_epoch(store.as_context_mut());
+
+            // Nested call without token keeps token of outer call.
+            let prev = token.map(|t| store.data_mut().call_token.replace(t));
+            let ret = enter_guest(
+                store.as_context_mut(),
+                CallFrame::Guest {
+                    inst: self.base().instance_id(),
+                    name: export,
+                },
+                |store| unsafe { raw_call(store, &f, &ty, args.iter_shared()) },
+            );
+            if let Some(prev) = prev {
+                store.data_mut().call_token = prev;
+            }
+            let ret = ret.map_err(|e| self.record_exit(e))?;
+            self.activity.touch();
+            info!(ret.len = ret.len());
+            Ok(ret)
+        })
+    }
+
+    /// Returns `true` if instance exports function `name`.
+    fn has_func_export(&self, name: &str) -> bool {
+        let Ok(m) = self.get_data() else {
+            return false;
+        };
+        m.acquire_store(|m, mut store| {
+            m.instance.get_core().is_ok_and(|inst| {
+                matches!(inst.get_export(&mut store, name), Some(Extern::Func(_)))
+            })
+        })
+    }
+
     #[instrument(level = Level::TRACE, skip(f))]
     fn get_memory<F, R>(&self, f: F) -> Option<R>
+    where
+        for<'a> F: FnOnce(&'a mut [u8]) -> AnyResult<R>,
+    {
+        self.get_memory_checked(|_| Ok(()), f)
+    }
+
+    /// Like `get_memory`, but runs `check` against store data first.
+    #[instrument(level = Level::TRACE, skip(check, f))]
+    fn get_memory_checked<F, R>(
+        &self,
+        check: impl FnOnce(&StoreData) -> AnyResult<()>,
+        f: F,
+    ) -> Option<R>
     where
         for<'a> F: FnOnce(&'a mut [u8]) -> AnyResult<R>,
     {
         self.acquire_store(move |store| {
             let _s = debug_span!("get_memory.inner", ?self).entered();
+            check(store.data())?;
             f(match &self.memory {
                 Some(MemoryType::Memory(mem)) => mem.data_mut(store),
                 // SAFETY: Externalize concurrent access to user
@@ -730,32 +2102,120 @@ impl WasmInstance {
         })
     }
 
-    #[instrument(level = Level::DEBUG, skip(f))]
-    fn read_memory<F, R>(&self, i: usize, n: usize, f: F) -> Option<R>
+    /// Calls export with static signature. Unlike `call_wasm`, error is returned.
+    pub fn call_typed<P, R>(&self, name: &str, args: P) -> AnyResult<R>
     where
-        F: FnOnce(&[u8]) -> AnyResult<R>,
+        P: WasmParams,
+        R: WasmResults,
     {
-        self.get_memory(|data| match data.get(i..i + n) {
-            Some(s) => f(s),
-            None => bail_with_site!("Index out of bound {}-{}", i, i + n),
+        self.get_data()?.acquire_store(move |m, mut store| {
+            let f = site_context!(m
+                .instance
+                .get_core()?
+                .get_typed_func::<P, R>(&mut store, name))?;
+
+            let _active = self.activity.enter();
+            #[cfg(feature = "epoch-timeout")]
+            let _guard = enter_priority(&store.data().epoch_deadline);
+            #[cfg(feature = "epoch-timeout")]
+            reset_epoch(store.as_context_mut());
+
+            let ret = enter_guest(
+                store,
+                CallFrame::Guest {
+                    inst: self.base().instance_id(),
+                    name: StringName::from(name),
+                },
+                |store| f.call(store, args),
+            )?;
+            self.activity.touch();
+            Ok(ret)
         })
     }
 
-    #[instrument(level = Level::DEBUG, skip(f))]
-    fn write_memory<F, R>(&self, i: usize, n: usize, f: F) -> Option<R>
+    /// Accesses memory. Unlike `get_memory`, error is returned.
+    pub fn try_memory<F, R>(&self, f: F) -> AnyResult<R>
     where
         for<'a> F: FnOnce(&'a mut [u8]) -> AnyResult<R>,
     {
-        self.get_memory(|data| match data.get_mut(i..i + n) {
-            Some(s) => f(s),
-            None => bail_with_site!("Index out of bound {}-{}", i, i + n),
-        })
-    }
-}
-
-struct WasmCallable {
-    name: StringName,
-    ty: FuncType,
+        self.get_data()?.acquire_store(move |_, store| {
+            f(match &self.memory {
+                Some(MemoryType::Memory(mem)) => mem.data_mut(store),
+                // SAFETY: Externalize concurrent access to user
+                #[allow(mutable_transmutes)]
+                Some(MemoryType::SharedMemory(mem)) => unsafe {
+                    mem::transmute::<&[_], &mut [u8]>(mem.data())
+                },
+                None => bail_with_site!("No memory exported"),
+            })
+        })
+    }
+
+    #[instrument(level = Level::DEBUG, skip(f))]
+    fn read_memory<F, R>(&self, i: usize, n: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&[u8]) -> AnyResult<R>,
+    {
+        self.get_memory_checked(
+            |store| store.check_ret_size(n),
+            |data| match data.get(i..i + n) {
+                Some(s) => f(s),
+                None => bail_with_site!("Index out of bound {}-{}", i, i + n),
+            },
+        )
+    }
+
+    /// Reads guest pixel buffer. `current` is format of existing image data, if any.
+    #[instrument(level = Level::DEBUG)]
+    fn read_pixels(
+        &self,
+        ptr: i64,
+        width: i64,
+        height: i64,
+        format: GString,
+        current: Option<ImageFormat>,
+    ) -> Option<(PackedByteArray, i32, i32, ImageFormat)> {
+        self.get_memory(move |data| {
+            let (fmt, bpp) = pixel_format(&format.to_string())?;
+            if let Some(current) = current.filter(|&v| v != fmt) {
+                bail_with_site!("Format mismatch (image is {current:?}, buffer is {fmt:?})");
+            }
+            let (w, h) = image_size(width, height)?;
+            let s = pixel_region(data, ptr, w, h, 0, bpp)?;
+
+            Ok((PackedByteArray::from(&*s), w, h, fmt))
+        })
+    }
+
+    fn add_string_errors(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        warn!(n, "Invalid characters replaced in string");
+        self.acquire_store(|mut store| {
+            store.data_mut().string_errors += n as u64;
+            Ok(())
+        });
+    }
+
+    #[instrument(level = Level::DEBUG, skip(f))]
+    fn write_memory<F, R>(&self, i: usize, n: usize, f: F) -> Option<R>
+    where
+        for<'a> F: FnOnce(&'a mut [u8]) -> AnyResult<R>,
+    {
+        self.get_memory_checked(
+            |store| store.check_arg_size(n),
+            |data| match data.get_mut(i..i + n) {
+                Some(s) => f(s),
+                None => bail_with_site!("Index out of bound {}-{}", i, i + n),
+            },
+        )
+    }
+}
+
+struct WasmCallable {
+    name: StringName,
+    ty: FuncType,
     ptr: *mut ffi::c_void,
     this: SendSyncWrapper<Gd<WasmInstance>>,
 }
@@ -824,16 +2284,30 @@ impl Display for WasmCallable {
 impl RustCallable for WasmCallable {
     #[instrument(skip(args), fields(args.len = args.len()))]
     fn invoke(&mut self, args: &[&Variant]) -> Result<Variant, ()> {
-        let r = self.this.bind().acquire_store(|mut store| {
+        let this = self.this.bind();
+        let r = this.acquire_store(|mut store| {
             let _s = debug_span!("invoke.inner").entered();
+            this.check_callable()?;
+            let _active = this.activity.enter();
+            let _running = this.enter_running();
+            #[cfg(feature = "epoch-timeout")]
+            let _guard = enter_priority(&store.data().epoch_deadline);
             #[cfg(feature = "epoch-timeout")]
             reset_epoch(store.as_context_mut());
 
             // SAFETY: Function pointer is valid.
-            let ret = unsafe {
-                let f = Func::from_raw(store.as_context_mut(), self.ptr).expect("Pointer is null");
-                raw_call(store, &f, &self.ty, args.iter().copied())?
-            };
+            let f = unsafe { Func::from_raw(store.as_context_mut(), self.ptr) }
+                .expect("Pointer is null");
+            let ret = enter_guest(
+                store,
+                CallFrame::Guest {
+                    inst: self.this.instance_id(),
+                    name: self.name.clone(),
+                },
+                |store| unsafe { raw_call(store, &f, &self.ty, args.iter().copied()) },
+            )
+            .map_err(|e| this.record_exit(e))?;
+            this.activity.touch();
             info!(ret.len = ret.len());
             Ok(ret)
         });
@@ -858,6 +2332,49 @@ impl WasmInstance {
     /// Emitted whenever WASI stdin is tried to be read. Only usable with WASI.
     #[signal]
     fn stdin_request();
+    /// Emitted when guest drains stdin buffer after `stdin_write` is refused. Only usable with WASI.
+    #[signal]
+    fn stdin_drained();
+    /// Emitted when instance is idle for longer than
+    /// `godot_wasm/engine/idle_unload_seconds` project setting.
+    /// Instance is not freed, it's up to the handler to drop it.
+    #[signal]
+    fn idle_unload_requested(idle_seconds: f64);
+    /// Emitted when guest exits with nonzero code during `call_wasm`. Only usable with WASI.
+    #[signal]
+    fn exited(code: i64);
+    /// Emitted once instance is initialized.
+    #[signal]
+    fn ready();
+    /// Emitted when call queued before instance is ready finishes.
+    /// Result is `null` if call fails, or instance failed to initialize.
+    #[signal]
+    fn call_finished(name: StringName, result: Variant);
+    /// Emitted (deferred) when lifecycle state changes. See `get_state()`.
+    #[signal]
+    fn state_changed(old: i64, new: i64);
+
+    /// Instance is not initialized yet.
+    #[constant]
+    const STATE_UNINITIALIZED: i64 = 0;
+    /// Instance is being compiled and instantiated.
+    #[constant]
+    const STATE_COMPILING: i64 = 1;
+    /// Instance is ready to be called.
+    #[constant]
+    const STATE_READY: i64 = 2;
+    /// Guest is running.
+    #[constant]
+    const STATE_RUNNING: i64 = 3;
+    /// Guest has exited. Instance can't be called anymore.
+    #[constant]
+    const STATE_EXITED: i64 = 4;
+    /// Instance failed to initialize.
+    #[constant]
+    const STATE_ERRORED: i64 = 5;
+    /// Instance is idle and requested to be unloaded.
+    #[constant]
+    const STATE_FREED_PENDING: i64 = 6;
 
     /// Initialize and instantiates module.
     ///
@@ -907,6 +2424,42 @@ impl WasmInstance {
         }
     }
 
+    /// Initialize and loads module in worker thread.
+    /// MUST be called for the first time and only once.
+    ///
+    /// Returns itself immediately, `ready` or `error_happened` is emitted once it's finished.
+    /// With `queueCalls.untilReady` config, calls made in the meantime are queued.
+    ///
+    /// Arguments:
+    /// - `module` : A `WasmModule` object.
+    /// - `host` : Host function bindings.
+    /// - `config` : Instance configuration.
+    #[func]
+    #[instrument(skip(host, config))]
+    fn initialize_deferred(
+        &self,
+        module: Gd<WasmModule>,
+        host: Variant,
+        config: Variant,
+    ) -> Option<Gd<WasmInstance>> {
+        let Ok(host) = variant_to_option::<Dictionary>(host) else {
+            error!("Host is not a dictionary!");
+            godot_error!("Host is not a dictionary!");
+            return None;
+        };
+        let config = Self::parse_config(if config.is_nil() { None } else { Some(config) });
+        // Queue is enabled before returning, so calls made right after it are queued.
+        let was_ready = self.begin_initialize(&config);
+
+        let args = SendSyncWrapper::new((self.to_gd(), module, host, config));
+        rayon::spawn(move || {
+            let (this, module, host, config) = args.into_inner();
+            this.bind()
+                .finish_initialize(module, host, config, was_ready);
+        });
+        Some(self.to_gd())
+    }
+
     /// Gets the module used to instantiate this object.
     #[func]
     #[instrument(ret)]
@@ -914,6 +2467,137 @@ impl WasmInstance {
         self.unwrap_data(|m| Ok(m.module.clone()))
     }
 
+    /// Returns `true` if instance is initialized and finished running queued calls.
+    #[func]
+    fn is_ready(&self) -> bool {
+        self.data.get().is_some() && !self.queued_calls.lock().is_active()
+    }
+
+    /// Returns lifecycle state of instance, one of `STATE_*` constants.
+    #[func]
+    fn get_state(&self) -> i64 {
+        self.state.get() as i64
+    }
+
+    /// Called by idle reaper. Use `idle_unload_requested` signal instead.
+    #[func]
+    fn _on_idle_unload(&self, idle_seconds: f64) {
+        self.set_state(InstanceState::FreedPending);
+        self.to_gd().emit_signal(
+            &StringName::from(c"idle_unload_requested"),
+            &[idle_seconds.to_variant()],
+        );
+    }
+
+    /// Returns number of seconds since last guest activity.
+    ///
+    /// Activity is recorded after every successful call and on WASI stdin activity.
+    /// Returns 0 while a call is in flight.
+    #[func]
+    #[instrument(ret)]
+    fn get_idle_seconds(&self) -> f64 {
+        if self.activity.is_busy() {
+            0.0
+        } else {
+            self.activity.idle_time().as_secs_f64()
+        }
+    }
+
+    /// Requests running call to stop cooperatively.
+    ///
+    /// Guest polls it with `host.is_cancelled` import (or `godot:core/task.cancelled` for components).
+    /// The flag is reset when the next call starts.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn request_cancel(&self) {
+        self.activity.cancel();
+    }
+
+    /// Returns current depth of host↔guest call chain.
+    ///
+    /// Every call into WASM and every host callable invoked by WASM adds one level.
+    /// Exceeding `reentrancy.maxDepth` traps, listing the entire chain.
+    #[func]
+    #[instrument(ret)]
+    fn get_reentrancy_depth(&self) -> i64 {
+        self.acquire_store(|store| Ok(store.data().inner_lock.reentrancy.depth() as i64))
+            .unwrap_or_default()
+    }
+
+    /// Returns call statistics of instance.
+    ///
+    /// - `calls` : Number of calls into WASM, excluding nested calls.
+    /// - `cpu_usec_total` : CPU time spent in calls into WASM (including host calls made by it), in microseconds.
+    ///   Falls back to wall time where thread CPU clock is unavailable.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn get_call_stats(&self) -> Dictionary {
+        let mut ret = Dictionary::new();
+        if let Some((calls, cpu_usec)) = self.acquire_store(|store| {
+            let v = &store.data().inner_lock;
+            Ok((v.calls, v.cpu_usec))
+        }) {
+            ret.set("calls", calls as i64);
+            ret.set("cpu_usec_total", cpu_usec as i64);
+        }
+        ret
+    }
+
+    /// Returns memory used by instance, in bytes.
+    ///
+    /// - `linear_memory` : Size of exported memory.
+    /// - `host_state` : Approximate size of host-side state (store data, WASI context, and object registry).
+    /// - `wasi_context` : `true` if WASI context is allocated.
+    /// - `object_registry` : `true` if object registry is allocated.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn get_memory_usage(&self) -> Dictionary {
+        let mut ret = Dictionary::new();
+        ret.set(
+            "linear_memory",
+            self.get_memory(|data| Ok(data.len() as i64))
+                .unwrap_or_default(),
+        );
+        let Some((host_state, wasi_context, object_registry)) =
+            self.acquire_store(|store| Ok(store.data().host_state_usage()))
+        else {
+            return ret;
+        };
+        ret.set("host_state", host_state as i64);
+        ret.set("wasi_context", wasi_context);
+        ret.set("object_registry", object_registry);
+        #[cfg(feature = "memory-limiter")]
+        if let Some((peak, time)) = self.acquire_store(|store| {
+            let v = &store.data().memory_limits;
+            Ok((v.peak, v.peak_time))
+        }) {
+            ret.set("linear_memory_peak", peak as i64);
+            ret.set("linear_memory_peak_time", time);
+        }
+        ret
+    }
+
+    /// Resets peak counters of `get_memory_usage()` to current value.
+    #[func]
+    #[instrument]
+    fn reset_peak_counters(&self) {
+        cfg_if! {
+            if #[cfg(feature = "memory-limiter")] {
+                self.acquire_store(move |mut store| {
+                    let current = match &self.memory {
+                        Some(MemoryType::Memory(mem)) => mem.data_size(&store),
+                        Some(MemoryType::SharedMemory(mem)) => mem.data_size(),
+                        None => 0,
+                    };
+                    store.data_mut().memory_limits.reset_peak(current as _);
+                    Ok(())
+                });
+            } else {
+                godot_error!("Feature memory-limiter not enabled!");
+            }
+        }
+    }
+
     /// Calls into WASM.
     ///
     /// Arguments:
@@ -921,29 +2605,21 @@ impl WasmInstance {
     /// - `args` : Array of parameters.
     ///
     /// Returns an array of results, or `null` if failed.
+    /// If return convention is declared with `declare_return_convention()`, returns converted value instead.
+    ///
+    /// If `queueCalls.untilReady` is enabled and instance is still initializing,
+    /// call is queued and `null` is returned. It's result is emitted with `call_finished`.
     #[func]
     #[instrument(skip(args), fields(args.len = args.len()))]
     fn call_wasm(&self, name: StringName, args: VariantArray) -> Variant {
-        option_to_variant(self.unwrap_data(move |m| {
-            m.acquire_store(move |m, mut store| {
-                let _s = debug_span!("call_wasm.inner").entered();
-
-                let name = name.to_string();
-                let f = match site_context!(m.instance.get_core())?.get_export(&mut store, &name) {
-                    Some(Extern::Func(f)) => f,
-                    Some(_) => bail_with_site!("Export {name} is not a function"),
-                    None => bail_with_site!("Export {name} does not exists"),
-                };
-                let ty = f.ty(&store);
-
-                #[cfg(feature = "epoch-timeout")]
-                reset_epoch(store.as_context_mut());
-
-                let ret = unsafe { raw_call(store, &f, &ty, args.iter_shared())? };
-                info!(ret.len = ret.len());
-                Ok(ret)
-            })
-        }))
+        match self.queue_call(name, args) {
+            Ok(Some((name, args))) => self.call_wasm_(name, args),
+            Ok(None) => Variant::nil(),
+            Err(e) => {
+                self.report_call_error(e);
+                Variant::nil()
+            }
+        }
     }
 
     /// Binds WASM function into a `Callable`.
@@ -966,6 +2642,11 @@ impl WasmInstance {
                         None => bail_with_site!("Export {name} does not exists"),
                     }
                 };
+                if let Some(e) =
+                    unsupported_ref_type(&f.ty(&store), store.data().is_extern_native())
+                {
+                    bail_with_site!("Export {name} cannot be called: {e}");
+                }
 
                 Ok(Callable::from_custom(WasmCallable {
                     name,
@@ -979,6 +2660,72 @@ impl WasmInstance {
         .unwrap_or_else(Callable::invalid)
     }
 
+    /// Calls `callable` with both marshal limits raised to `bytes`.
+    ///
+    /// Arguments:
+    /// - `bytes` : Limit (in bytes) used during the call.
+    /// - `callable` : Callable to be called.
+    ///
+    /// Returns return value of `callable`.
+    #[func]
+    #[instrument(skip(callable))]
+    fn with_marshal_limit(&self, bytes: i64, callable: Callable) -> Variant {
+        let Some(prev) = self.acquire_store(|mut store| {
+            Ok(mem::replace(
+                &mut store.data_mut().marshal_override,
+                Some(bytes.max(0) as usize),
+            ))
+        }) else {
+            return Variant::nil();
+        };
+        let _guard = guard((), |_| {
+            self.acquire_store(|mut store| {
+                store.data_mut().marshal_override = prev;
+                Ok(())
+            });
+        });
+
+        callable.callv(&VariantArray::new())
+    }
+
+    /// Instantiates lazy dependency ahead of first call.
+    /// Only works with `deps.lazy` config.
+    ///
+    /// Arguments:
+    /// - `name` : Import name of the dependency.
+    ///
+    /// Returns `true` if succeed.
+    #[func]
+    #[instrument(ret)]
+    fn preload_dep(&self, name: GString) -> bool {
+        self.unwrap_data(move |m| {
+            m.acquire_store(move |_, mut store| {
+                let name = name.to_string();
+                let Some(dep) = store.data().lazy_deps.get(&name).cloned() else {
+                    bail_with_site!("Lazy dependency {name:?} does not exist")
+                };
+
+                let _active = self.activity.enter();
+                #[cfg(feature = "epoch-timeout")]
+                let _guard = enter_priority(&store.data().epoch_deadline);
+                #[cfg(feature = "epoch-timeout")]
+                reset_epoch(store.as_context_mut());
+
+                enter_guest(
+                    store,
+                    CallFrame::Guest {
+                        inst: self.base().instance_id(),
+                        name: StringName::from(name.as_str()),
+                    },
+                    |store| dep.get(store),
+                )?;
+                self.activity.touch();
+                Ok(true)
+            })
+        })
+        .unwrap_or_default()
+    }
+
     /// Emits trap when returning from host. Should only be used from imported host functions.
     ///
     /// Returns previous error message, if any.
@@ -1021,13 +2768,185 @@ impl WasmInstance {
         }
     }
 
+    /// Gets engine-wide information.
+    ///
+    /// Returns a dictionary of the following:
+    /// - `initialized` : `true` if engine is initialized.
+    /// - `memory_used` : Bytes of memory reserved by all instances. Requires `memory-limiter` feature.
+    /// - `memory_budget` : Engine-wide memory budget, or -1 if unlimited. Requires `memory-limiter` feature.
+    /// - `linear_memory_peak` : Largest linear memory of any instance. Requires `memory-limiter` feature.
+    /// - `epoch_running` : `true` if epoch thread is running. Requires `epoch-timeout` feature.
+    /// - `epoch` : Current epoch tick. Requires `epoch-timeout` feature.
+    /// - `epoch_interval` : Duration of an epoch tick in seconds. Requires `epoch-timeout` feature.
+    /// - `priorities` : Mapping of priority class (see `epoch.priority` config) into:
+    ///   - `deadline_ticks` : Number of ticks before instance is preempted.
+    ///   - `deadline` : Effective deadline in seconds.
+    ///   - `active_calls` : Number of running calls.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn get_engine_info() -> Dictionary {
+        engine_info()
+    }
+
+    /// Registers effect preset that component guests can spawn with `godot:global/vfx`.
+    /// Only usable with godot-component.
+    ///
+    /// Presets are shared by all instances. Passing `null` scene unregisters it.
+    ///
+    /// Arguments:
+    /// - `name` : Name of the preset.
+    /// - `scene` : Scene to be instantiated.
+    #[func]
+    #[instrument(level = Level::DEBUG, skip(_scene))]
+    fn register_vfx_preset(_name: GString, _scene: Option<Gd<PackedScene>>) {
+        cfg_if! {
+            if #[cfg(feature = "godot-component")] {
+                crate::godot_component::global::vfx::register_preset(_name.to_string(), _scene);
+            } else {
+                godot_error!("Feature godot-component not enabled!");
+            }
+        }
+    }
+
+    /// Returns all live initialized instances, in order of initialization.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn get_instances() -> Array<Gd<WasmInstance>> {
+        live_instances().collect()
+    }
+
+    /// Calls export `name` of every live instance, one after another in main thread.
+    ///
+    /// Failure of an instance does not affect other instances.
+    /// If `only_if_exported` is `true`, instances without the export are skipped.
+    /// Returns a dictionary of instance ID to:
+    /// - `ok` : `true` if call succeeds.
+    /// - `result` : Array of return values, if call succeeds.
+    /// - `error` : Error message, if call fails.
+    ///
+    /// Returns empty dictionary if not called from main thread.
+    #[func]
+    #[instrument(level = Level::DEBUG, skip(args))]
+    fn broadcast(name: StringName, args: VariantArray, only_if_exported: bool) -> Dictionary {
+        let mut ret = Dictionary::new();
+        if !is_main_thread() {
+            godot_error!("Broadcast must be called from main thread");
+            return ret;
+        }
+
+        let export = name.to_string();
+        // Instances are resolved lazily, so those freed by earlier calls are skipped.
+        for inst in live_instances() {
+            let id = inst.instance_id();
+            let r = {
+                let this = inst.bind();
+                if only_if_exported && !this.has_func_export(&export) {
+                    continue;
+                }
+                this.call_export(name.clone(), args.clone())
+            };
+
+            let mut d = Dictionary::new();
+            match r {
+                Ok(v) => {
+                    d.set("ok", true);
+                    d.set("result", v);
+                }
+                Err(e) => {
+                    d.set("ok", false);
+                    d.set("error", format_error(&e));
+                }
+            }
+            ret.set(id.to_i64(), d);
+        }
+        ret
+    }
+
+    /// Returns number of suppressed emissions of each rate-limited warning, keyed by warning key.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn get_suppressed_warning_counts() -> Dictionary {
+        let mut ret = Dictionary::new();
+        for (k, v) in suppressed_warning_counts() {
+            ret.set(k, v as i64);
+        }
+        ret
+    }
+
+    /// Reports what module could do if instantiated with config, without instantiating it.
+    ///
+    /// Config is resolved exactly like instantiation does. Returns dictionary with:
+    /// - `imports` : All imports of module, formatted as `module.name` (or `module.interface.method` for component).
+    /// - `wasi` : `true` if WASI is enabled.
+    /// - `filesystem` : Dictionary with:
+    ///   - `mounts` : Array of mounts, each is dictionary of `guest` path, `host` path (null for in-memory filesystem), and `case_insensitive`.
+    ///   - `readonly` : `true` if filesystem is read-only.
+    /// - `network` : `true` if any network-capable interface is allowed.
+    /// - `godot_interfaces` : Dictionary with `allowed` and `denied` filterable imports.
+    /// - `stdio` : Effective binding of `stdin`, `stdout`, and `stderr`. Empty if WASI is disabled.
+    ///
+    /// Returns `null` if module is uninitialized or config is invalid.
+    #[func]
+    #[instrument(level = Level::DEBUG, skip(config))]
+    fn audit_permissions(module: Gd<WasmModule>, config: Variant) -> Variant {
+        match audit_permissions(&module, &config) {
+            Ok(v) => v.to_variant(),
+            Err(e) => {
+                godot_error!("{e:?}");
+                Variant::nil()
+            }
+        }
+    }
+
+    /// Checks guest ABI against expected version and struct layout.
+    ///
+    /// Expected ABI is a dictionary with:
+    /// - `major` : Major version.
+    /// - `minor` : Minor version (optional).
+    /// - `layout` : Layout descriptor string (optional).
+    ///
+    /// Returns empty dictionary on error.
+    #[func]
+    #[instrument(skip(expected))]
+    fn check_abi(&self, expected: Dictionary) -> Dictionary {
+        match check_abi(self, &expected) {
+            Ok(v) => v,
+            Err(e) => {
+                let s = format!("{e:?}");
+                godot_error!("{s}");
+                self.emit_error_wrapper(s);
+                Dictionary::new()
+            }
+        }
+    }
+
+    /// Sets engine-wide memory budget in bytes. Negative value removes the budget.
+    ///
+    /// Memory growth that exceeds the budget fails and emits `WasmEngine.global_memory_pressure`.
+    /// Requires `memory-limiter` feature.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn set_global_memory_budget(_budget: i64) {
+        cfg_if! {
+            if #[cfg(feature = "memory-limiter")] {
+                set_global_memory_budget(u64::try_from(_budget).ok());
+            } else {
+                godot_error!("Feature memory-limiter not enabled!");
+            }
+        }
+    }
+
     /// Registers value and returns it's index. Only usable with object registry.
     #[func]
     #[instrument(skip(_obj))]
     fn register_object(&self, _obj: Variant) -> Variant {
         cfg_if! {
             if #[cfg(feature = "object-registry-compat")] {
-                option_to_variant(self.acquire_store(move |mut store| Ok(store.data_mut().get_registry_mut()?.register(_obj) as u64)))
+                option_to_variant(self.acquire_store(move |mut store| {
+                    let data = store.data_mut();
+                    let v = data.copy_mode.apply(_obj);
+                    Ok(data.get_registry_mut()?.register(v) as u64)
+                }))
             } else {
                 godot_error!("Feature object-registry-compat not enabled!");
                 Variant::nil()
@@ -1146,6 +3065,7 @@ impl WasmInstance {
             if #[cfg(feature = "wasi")] {
                 self.unwrap_data(move |m| {
                     if let Some(stdin) = &m.wasi_stdin {
+                        self.activity.touch();
                         stdin.write(_line.to_string().as_bytes());
                     }
                     Ok(())
@@ -1156,20 +3076,158 @@ impl WasmInstance {
         }
     }
 
-    /// Closes stdin. Only usable with WASI.
+    /// Writes data to stdin, without exceeding `wasi.stdin.maxBytes`. Only usable with WASI.
+    ///
+    /// Returns number of bytes accepted. If not all data is accepted,
+    /// `stdin_drained` is emitted once guest consumes half of the buffer.
     #[func]
-    #[instrument]
-    fn stdin_close(&self) {
+    #[instrument(skip(_data), fields(data.len = _data.len()))]
+    fn stdin_write(&self, _data: PackedByteArray) -> i64 {
         cfg_if! {
             if #[cfg(feature = "wasi")] {
-                self.unwrap_data(|m| {
-                    if let Some(stdin) = &m.wasi_stdin {
-                        stdin.close();
-                    }
-                    Ok(())
-                });
+                self.unwrap_data(move |m| {
+                    Ok(match &m.wasi_stdin {
+                        Some(stdin) => {
+                            self.activity.touch();
+                            stdin.try_write(_data.as_slice()) as i64
+                        }
+                        None => 0,
+                    })
+                })
+                .unwrap_or_default()
             } else {
                 godot_error!("Feature wasi not enabled!");
+                0
+            }
+        }
+    }
+
+    /// Returns number of bytes written to stdin, but not yet read by guest. Only usable with WASI.
+    #[func]
+    #[instrument(ret)]
+    fn stdin_buffered_bytes(&self) -> i64 {
+        cfg_if! {
+            if #[cfg(feature = "wasi")] {
+                self.unwrap_data(|m| Ok(m.wasi_stdin.as_ref().map_or(0, |v| v.buffered() as i64)))
+                    .unwrap_or_default()
+            } else {
+                godot_error!("Feature wasi not enabled!");
+                0
+            }
+        }
+    }
+
+    /// Closes stdin. Only usable with WASI.
+    #[func]
+    #[instrument]
+    fn stdin_close(&self) {
+        cfg_if! {
+            if #[cfg(feature = "wasi")] {
+                self.unwrap_data(|m| {
+                    if let Some(stdin) = &m.wasi_stdin {
+                        self.activity.touch();
+                        stdin.close();
+                    }
+                    Ok(())
+                });
+            } else {
+                godot_error!("Feature wasi not enabled!");
+            }
+        }
+    }
+
+    /// Returns guest path of all applied preopens. Only usable with WASI.
+    #[func]
+    #[instrument]
+    fn get_wasi_preopens(&self) -> PackedStringArray {
+        cfg_if! {
+            if #[cfg(feature = "wasi")] {
+                self.acquire_store(|store| {
+                    Ok(match &store.data().wasi_ctx {
+                        Some(ctx) => ctx.preopen_paths().map(|p| GString::from(p.as_str())).collect(),
+                        None => PackedStringArray::new(),
+                    })
+                })
+                .unwrap_or_default()
+            } else {
+                godot_error!("Feature wasi not enabled!");
+                PackedStringArray::new()
+            }
+        }
+    }
+
+    /// Connects this instance with other instance through bidirectional pipe.
+    /// Only usable with WASI.
+    #[func]
+    #[instrument(skip(_other))]
+    fn connect_pipe(
+        &self,
+        _other: Gd<WasmInstance>,
+        _this_fd_name: GString,
+        _other_fd_name: GString,
+    ) -> bool {
+        cfg_if! {
+            if #[cfg(feature = "wasi")] {
+                let (this_name, other_name) = (_this_fd_name.to_string(), _other_fd_name.to_string());
+                if _other.instance_id() == self.base().instance_id() {
+                    let s = "Cannot connect pipe to the same instance".to_string();
+                    godot_error!("{s}");
+                    self.emit_error_wrapper(s);
+                    return false;
+                }
+                let other = _other.bind();
+
+                // Check other first, so that no dangling stream is left in this instance.
+                let r = other.acquire_store(|store| match &store.data().wasi_ctx {
+                    Some(ctx) if ctx.stream_fd(&other_name).is_some() => {
+                        bail_with_site!("Stream {other_name:?} already exists")
+                    }
+                    Some(_) => Ok(()),
+                    None => bail_with_site!("Instance has no WASI context"),
+                });
+                if r.is_none() {
+                    return false;
+                }
+
+                let (a, b) = Pipe::pair(PIPE_CAPACITY);
+                let r = self.acquire_store(move |mut store| match &mut store.data_mut().wasi_ctx {
+                    Some(ctx) => ctx.insert_stream(this_name, a),
+                    None => bail_with_site!("Instance has no WASI context"),
+                });
+                if r.is_none() {
+                    return false;
+                }
+                other
+                    .acquire_store(move |mut store| match &mut store.data_mut().wasi_ctx {
+                        Some(ctx) => ctx.insert_stream(other_name, b),
+                        None => bail_with_site!("Instance has no WASI context"),
+                    })
+                    .is_some()
+            } else {
+                godot_error!("Feature wasi not enabled!");
+                false
+            }
+        }
+    }
+
+    /// Gets file descriptor of pipe created with `connect_pipe`. Only usable with WASI.
+    #[func]
+    #[instrument(ret)]
+    fn get_pipe_fd(&self, _name: GString) -> i64 {
+        cfg_if! {
+            if #[cfg(feature = "wasi")] {
+                self.acquire_store(|store| {
+                    Ok(store
+                        .data()
+                        .wasi_ctx
+                        .as_ref()
+                        .and_then(|ctx| ctx.stream_fd(&_name.to_string())))
+                })
+                .flatten()
+                .map_or(-1, i64::from)
+            } else {
+                godot_error!("Feature wasi not enabled!");
+                -1
             }
         }
     }
@@ -1190,6 +3248,63 @@ impl WasmInstance {
             .unwrap_or_default()
     }
 
+    /// Hashes a chunk of memory, without copying it.
+    ///
+    /// Arguments:
+    /// - `i` : Start offset.
+    /// - `n` : Length of chunk. Negative value hashes until the end of memory.
+    /// - `algorithm` : `"xxhash64"` or `"sha256"`.
+    #[func]
+    #[instrument]
+    fn hash_memory(&self, i: i64, n: i64, algorithm: GString) -> PackedByteArray {
+        cfg_if! {
+            if #[cfg(feature = "memory-hash")] {
+                self.get_memory(|data| {
+                    let alg = HashAlgorithm::from_name(&algorithm.to_string())?;
+                    let Ok(start) = usize::try_from(i) else {
+                        bail_with_site!("Negative offset {}", i)
+                    };
+                    let end = match usize::try_from(n) {
+                        Ok(n) => start.checked_add(n),
+                        Err(_) => Some(data.len()),
+                    };
+                    match end.and_then(|end| data.get(start..end)) {
+                        Some(s) => Ok(PackedByteArray::from(&alg.hash(s)[..])),
+                        None => bail_with_site!("Index out of bound {}-{}", i, i.saturating_add(n)),
+                    }
+                })
+                .unwrap_or_default()
+            } else {
+                let _ = (i, n, algorithm);
+                godot_error!("Feature memory-hash not enabled!");
+                PackedByteArray::new()
+            }
+        }
+    }
+
+    /// Returns hash of each exported global value and memory size, keyed by export name.
+    #[func]
+    #[instrument]
+    fn hash_exports(&self) -> Dictionary {
+        cfg_if! {
+            if #[cfg(feature = "memory-hash")] {
+                self.unwrap_data(|m| {
+                    m.acquire_store(|m, store| {
+                        let mut ret = Dictionary::new();
+                        for (k, v) in wasm_hash::hash_exports(store, m.instance.get_core()?) {
+                            ret.set(k, v as i64);
+                        }
+                        Ok(ret)
+                    })
+                })
+                .unwrap_or_default()
+            } else {
+                godot_error!("Feature memory-hash not enabled!");
+                Dictionary::new()
+            }
+        }
+    }
+
     /// Writes a chunk of memory.
     #[func]
     #[instrument(skip(a), fields(a.len = a.len()), ret)]
@@ -1201,6 +3316,165 @@ impl WasmInstance {
         .is_some()
     }
 
+    /// Reads UTF-16 string, `n` is number of code units.
+    #[func]
+    #[instrument]
+    fn read_string_utf16(&self, i: i64, n: i64) -> GString {
+        self.read_memory(i as _, (n as usize).saturating_mul(2), |s| {
+            Ok(decode_utf16_lossy(s))
+        })
+        .map(|(s, e)| {
+            self.add_string_errors(e);
+            GString::from(s)
+        })
+        .unwrap_or_default()
+    }
+
+    /// Reads Latin-1 string.
+    #[func]
+    #[instrument]
+    fn read_string_latin1(&self, i: i64, n: i64) -> GString {
+        self.read_memory(i as _, n as _, |s| Ok(GString::from(decode_latin1(s))))
+            .unwrap_or_default()
+    }
+
+    /// Writes UTF-16 string. Returns number of bytes written.
+    #[func]
+    #[instrument(ret)]
+    fn write_string_utf16(&self, i: i64, s: GString) -> i64 {
+        let v = encode_utf16(&s.to_string());
+        self.write_memory(i as _, v.len(), |d| {
+            d.copy_from_slice(&v);
+            Ok(d.len() as i64)
+        })
+        .unwrap_or_default()
+    }
+
+    /// Writes Latin-1 string. Returns number of bytes written.
+    #[func]
+    #[instrument(ret)]
+    fn write_string_latin1(&self, i: i64, s: GString) -> i64 {
+        let (v, e) = encode_latin1_lossy(&s.to_string());
+        self.write_memory(i as _, v.len(), |d| {
+            d.copy_from_slice(&v);
+            Ok(d.len() as i64)
+        })
+        .map(|n| {
+            self.add_string_errors(e);
+            n
+        })
+        .unwrap_or_default()
+    }
+
+    /// Declares how `call_wasm` converts return values of export.
+    ///
+    /// Arguments:
+    /// - `name` : Name of the exported function.
+    /// - `convention` : Must be one of:
+    ///   - `"utf8_ptr_len_i64"` : Returns `String` from single `i64`, with pointer in low half and length in high half.
+    ///   - `"utf8_ptr_len_pair"` : Returns `String` from two `i32` of pointer and length.
+    ///   - `"bytes_ptr_len_pair"` : Returns `PackedByteArray` from two `i32` of pointer and length.
+    ///   - `""` : Removes declared convention.
+    ///
+    /// Buffer is freed with guest allocator export if `abi.free` is configured.
+    /// Out of bound buffer or invalid UTF-8 fails the call.
+    ///
+    /// Returns `true` if succeed.
+    #[func]
+    #[instrument(ret)]
+    fn declare_return_convention(&self, name: StringName, convention: GString) -> bool {
+        self.acquire_store(move |mut store| {
+            let (name, convention) = (name.to_string(), convention.to_string());
+            let v = &mut store.data_mut().return_conventions;
+            if convention.is_empty() {
+                v.remove(&name);
+            } else if let Some(c) = ReturnConvention::from_name(&convention) {
+                v.insert(name, c);
+            } else {
+                bail_with_site!("Unknown return convention {convention:?}");
+            }
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Allocates guest memory with guest allocator export (`abi.malloc`, defaults to `malloc`).
+    /// Returns 0 if allocation failed.
+    #[func]
+    #[instrument(ret)]
+    fn guest_alloc(&self, size: i64) -> i64 {
+        self.unwrap_data(|_| {
+            let Ok(size) = u64::try_from(size) else {
+                bail_with_site!("Invalid allocation size {size}");
+            };
+            wasm_abi::guest_alloc(self, size)
+        })
+        .unwrap_or_default() as _
+    }
+
+    /// Frees guest memory with guest allocator export (`abi.free`, defaults to `free`).
+    #[func]
+    #[instrument(ret)]
+    fn guest_free(&self, ptr: i64) -> bool {
+        self.unwrap_data(|_| wasm_abi::guest_free(self, ptr as _))
+            .is_some()
+    }
+
+    /// Writes null-terminated UTF-8 string into newly allocated guest memory.
+    /// Returns 0 if failed. Memory must be freed with `guest_free()`.
+    #[func]
+    #[instrument(ret)]
+    fn write_string_to_guest(&self, text: GString) -> i64 {
+        let mut v = text.to_string().into_bytes();
+        v.push(0);
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, store| store.data().check_arg_size(v.len()))?;
+            wasm_abi::guest_alloc_bytes(self, &v)
+        })
+        .unwrap_or_default() as _
+    }
+
+    /// Returns outstanding guest allocations made by host.
+    /// Only counted if `abi.trackAllocations` is enabled.
+    #[func]
+    fn get_guest_allocations(&self) -> Dictionary {
+        self.acquire_store(|store| {
+            let mut ret = Dictionary::new();
+            let v = store.data().guest_alloc.outstanding();
+            ret.set("tracking", v.is_some());
+            let (count, bytes) = v.unwrap_or_default();
+            ret.set("count", count as i64);
+            ret.set("bytes", bytes as i64);
+            Ok(ret)
+        })
+        .unwrap_or_default()
+    }
+
+    /// Reseeds random generator of `host.rand`.
+    ///
+    /// Seed is parsed like `rand.seed` config, `null` reseeds from OS entropy.
+    /// Returns `true` if succeed.
+    #[func]
+    #[instrument(ret)]
+    fn reseed_rng(&self, seed: Variant) -> bool {
+        self.acquire_store(move |mut store| {
+            store.data_mut().rng =
+                Some(match get_seed(Some(seed)).map_err(|e| e.into_erased())? {
+                    Some(v) => StdRng::seed_from_u64(v),
+                    None => StdRng::from_os_rng(),
+                });
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Returns number of characters replaced while converting strings.
+    #[func]
+    fn get_string_errors(&self) -> i64 {
+        self.acquire_store(|store| Ok(store.data().string_errors as i64))
+            .unwrap_or_default()
+    }
+
     /// Reads an unsigned 8-bit integer.
     #[func]
     #[instrument(level = Level::DEBUG, ret)]
@@ -1342,6 +3616,14 @@ impl WasmInstance {
             Ok(())
         }
 
+        let size = marshal_size(&v);
+        if self
+            .acquire_store(|store| store.data().check_arg_size(size))
+            .is_none()
+        {
+            return false;
+        }
+
         self.get_memory(move |data| {
             let i = i as usize;
             variant_dispatch!(v {
@@ -1397,6 +3679,27 @@ impl WasmInstance {
             Ok(r.to_variant())
         }
 
+        let size = match t {
+            VariantType::PACKED_BYTE_ARRAY => 1,
+            VariantType::PACKED_INT32_ARRAY | VariantType::PACKED_FLOAT32_ARRAY => 4,
+            VariantType::PACKED_INT64_ARRAY
+            | VariantType::PACKED_FLOAT64_ARRAY
+            | VariantType::PACKED_VECTOR2_ARRAY => 8,
+            VariantType::PACKED_VECTOR3_ARRAY => 12,
+            VariantType::PACKED_COLOR_ARRAY => 16,
+            _ => 0,
+        };
+        if self
+            .acquire_store(|store| {
+                store
+                    .data()
+                    .check_ret_size((n as usize).saturating_mul(size))
+            })
+            .is_none()
+        {
+            return Variant::nil();
+        }
+
         option_to_variant(self.get_memory(move |data| {
             let data = &*data;
             let (i, n) = (i as usize, n as usize);
@@ -1435,28 +3738,997 @@ impl WasmInstance {
         }))
     }
 
+    /// Copies guest pixel buffer into image, replacing it's data.
+    #[func]
+    #[instrument(skip(image), ret)]
+    fn blit_to_image(
+        &self,
+        ptr: i64,
+        width: i64,
+        height: i64,
+        format: GString,
+        mut image: Gd<Image>,
+    ) -> bool {
+        let current = if image.is_empty() {
+            None
+        } else {
+            Some(image.get_format())
+        };
+        let Some((data, w, h, fmt)) = self.read_pixels(ptr, width, height, format, current) else {
+            return false;
+        };
+
+        image.set_data(w, h, false, fmt, &data);
+        true
+    }
+
+    /// Copies guest pixel buffer into texture.
+    /// Texture is reallocated if size or format changes.
+    #[func]
+    #[instrument(skip(texture), ret)]
+    fn update_texture(
+        &self,
+        ptr: i64,
+        width: i64,
+        height: i64,
+        format: GString,
+        mut texture: Gd<ImageTexture>,
+    ) -> bool {
+        let Some((data, w, h, fmt)) = self.read_pixels(ptr, width, height, format, None) else {
+            return false;
+        };
+        let Some(image) = Image::create_from_data(w, h, false, fmt, &data) else {
+            return false;
+        };
+
+        if texture.get_width() == w && texture.get_height() == h && texture.get_format() == fmt {
+            texture.update(&image);
+        } else {
+            texture.set_image(&image);
+        }
+        true
+    }
+
+    /// Copies region of memory into new image, for debug visualization.
+    /// Rows are `stride` bytes apart (0 means tightly packed).
+    /// If `heatmap` is `true`, first byte of every pixel is mapped into color gradient
+    /// and the returned image is `RGBA8`.
+    #[func]
+    #[instrument(ret)]
+    fn debug_memory_image(
+        &self,
+        offset: i64,
+        width: i64,
+        height: i64,
+        stride: i64,
+        format: GString,
+        heatmap: Variant,
+    ) -> Option<Gd<Image>> {
+        let (data, w, h, fmt) = self.get_memory(move |data| {
+            let heatmap = variant_to_option::<bool>(heatmap)?.unwrap_or_default();
+            let (fmt, bpp) = pixel_format(&format.to_string())?;
+            let (w, h) = image_size(width, height)?;
+            let s = pixel_region(data, offset, w, h, stride, bpp)?;
+
+            Ok(if heatmap {
+                let s = s
+                    .chunks(bpp)
+                    .flat_map(|p| heatmap_color(p[0]))
+                    .collect::<Vec<_>>();
+                (PackedByteArray::from(&*s), w, h, ImageFormat::RGBA8)
+            } else {
+                (PackedByteArray::from(&*s), w, h, fmt)
+            })
+        })?;
+
+        Image::create_from_data(w, h, false, fmt, &data)
+    }
+
+    /// Parses struct format string, to be used with `read_struct()` and `write_struct()`.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn compile_struct_format(&self, format: GString) -> Option<Gd<WasmStructFormat>> {
+        match StructFormat::parse(format.chars()) {
+            Ok(v) => Some(WasmStructFormat::new_gd(format, v)),
+            Err(e) => {
+                let s = format!("{e:?}");
+                godot_error!("{s}");
+                self.emit_error_wrapper(s);
+                None
+            }
+        }
+    }
+
     /// Reads a structured data.
+    ///
+    /// Format can be a string or `WasmStructFormat`.
     #[func]
     #[instrument(level = Level::DEBUG)]
-    fn read_struct(&self, format: GString, p: u64) -> Variant {
+    fn read_struct(&self, format: Variant, p: u64) -> Variant {
         option_to_variant(self.get_memory(move |data| {
+            let format = to_struct_format(&format)?;
             let mut f = Cursor::new(data);
             f.set_position(p);
-            let ret = read_struct(f, format.chars())?;
+            let ret = read_struct(f, &format)?;
             info!(ret.len = ret.len());
             Ok(ret)
         }))
     }
 
     /// Writes a structured data.
+    ///
+    /// Format can be a string or `WasmStructFormat`.
     #[func]
     #[instrument(level = Level::DEBUG, skip(arr), fields(arr.len = arr.len()), ret)]
-    fn write_struct(&self, format: GString, p: u64, arr: VariantArray) -> u64 {
-        self.get_memory(move |data| {
-            let mut f = Cursor::new(data);
-            f.set_position(p);
-            write_struct(f, format.chars(), arr)
-        })
+    fn write_struct(&self, format: Variant, p: u64, arr: VariantArray) -> u64 {
+        let size = marshal_size(&arr.to_variant());
+        self.get_memory_checked(
+            |store| store.check_arg_size(size),
+            move |data| {
+                let format = to_struct_format(&format)?;
+                let mut f = Cursor::new(data);
+                f.set_position(p);
+                write_struct(f, &format, arr)
+            },
+        )
         .unwrap_or_default() as _
     }
 }
+
+/// Gets image format and bytes per pixel of pixel format name.
+fn pixel_format(format: &str) -> AnyResult<(ImageFormat, usize)> {
+    Ok(match &*format.to_ascii_uppercase() {
+        "RGBA8" => (ImageFormat::RGBA8, 4),
+        "RGB8" => (ImageFormat::RGB8, 3),
+        "RG8" => (ImageFormat::RG8, 2),
+        "L8" => (ImageFormat::L8, 1),
+        _ => bail_with_site!("Unsupported pixel format {format:?}"),
+    })
+}
+
+/// Validates image size.
+fn image_size(width: i64, height: i64) -> AnyResult<(i32, i32)> {
+    match (i32::try_from(width), i32::try_from(height)) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => bail_with_site!("Invalid image size {width}x{height}"),
+    }
+}
+
+/// Gets pixel region of memory, rows are `stride` bytes apart (0 means tightly packed).
+fn pixel_region(
+    data: &[u8],
+    ptr: i64,
+    w: i32,
+    h: i32,
+    stride: i64,
+    bpp: usize,
+) -> AnyResult<Cow<'_, [u8]>> {
+    let Some(row) = (w as usize).checked_mul(bpp) else {
+        bail_with_site!("Image size {w}x{h} overflows");
+    };
+    let stride = match stride {
+        0 => row,
+        s if s < 0 || (s as u64) < row as u64 => {
+            bail_with_site!("Stride {s} is smaller than row size {row}")
+        }
+        s => s as usize,
+    };
+    // Last row does not need to be padded.
+    let Some(n) = (h as usize - 1)
+        .checked_mul(stride)
+        .and_then(|v| v.checked_add(row))
+    else {
+        bail_with_site!("Image size {w}x{h} overflows");
+    };
+    let i = usize::try_from(ptr).unwrap_or(usize::MAX);
+    let Some(s) = i.checked_add(n).and_then(|e| data.get(i..e)) else {
+        bail_with_site!("Index out of bound {}-{}", i, i.saturating_add(n));
+    };
+
+    Ok(if stride == row {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(s.chunks(stride).flat_map(|r| &r[..row]).copied().collect())
+    })
+}
+
+/// Maps byte value into heatmap color (black, blue, green, yellow, red).
+fn heatmap_color(v: u8) -> [u8; 4] {
+    const STOPS: [(u8, [u8; 3]); 5] = [
+        (0, [0, 0, 0]),
+        (64, [0, 0, 255]),
+        (128, [0, 255, 0]),
+        (192, [255, 255, 0]),
+        (255, [255, 0, 0]),
+    ];
+
+    let i = STOPS.iter().rposition(|&(s, _)| s <= v).unwrap_or_default();
+    let (s0, c0) = STOPS[i];
+    let Some(&(s1, c1)) = STOPS.get(i + 1) else {
+        return [c0[0], c0[1], c0[2], 255];
+    };
+    let t = (v - s0) as u32;
+    let d = (s1 - s0) as u32;
+    let f = |a: u8, b: u8| ((a as u32 * (d - t) + b as u32 * t) / d) as u8;
+    [f(c0[0], c1[0]), f(c0[1], c1[1]), f(c0[2], c1[2]), 255]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasmtime::{Engine, TypedFunc, Val, ValType};
+
+    use crate::wasm_config::MARSHAL_MAX_BYTES;
+    use crate::wasm_util::error_kind;
+
+    // Mimics guest export calling GDScript callable which calls back into guest.
+    fn guest(r: &mut Reentrancy<String>, n: usize) -> AnyResult<usize> {
+        r.enter(format!("guest {n}"))?;
+        let ret = host(r, n);
+        r.exit();
+        ret
+    }
+
+    fn host(r: &mut Reentrancy<String>, n: usize) -> AnyResult<usize> {
+        r.enter(format!("callable {n}"))?;
+        let ret = guest(r, n + 1);
+        r.exit();
+        ret
+    }
+
+    #[test]
+    fn test_reentrancy_limit() {
+        let mut r = Reentrancy::<String>::default();
+        let e = guest(&mut r, 0).unwrap_err().to_string();
+        assert_eq!(r.depth(), 0);
+        assert!(e.contains(&format!(
+            "depth ({DEFAULT_MAX_REENTRANCY}) exceeded when entering guest 8"
+        )));
+        for i in 0..DEFAULT_MAX_REENTRANCY / 2 {
+            assert!(e.contains(&format!("guest {i}\n")));
+            assert!(e.contains(&format!("callable {i}")));
+        }
+    }
+
+    // Calls guest export with guest frame pushed.
+    // Store data with named frames, as call frames can't be made without Godot.
+    #[derive(Default)]
+    struct PingData(InnerLock<String>);
+
+    impl AsMut<InnerLock<String>> for PingData {
+        fn as_mut(&mut self) -> &mut InnerLock<String> {
+            &mut self.0
+        }
+    }
+
+    fn ping(
+        mut store: impl AsContextMut<Data = PingData>,
+        f: &TypedFunc<i32, i32>,
+        n: i32,
+    ) -> AnyResult<i32> {
+        enter_guest(store.as_context_mut(), format!("guest {n}"), |store| {
+            f.call(store, n)
+        })
+    }
+
+    #[test]
+    fn test_reentrancy_guest() {
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (import "host" "pong" (func $pong (param i32) (result i32)))
+                (func (export "ping") (param i32) (result i32)
+                    (if (result i32) (i32.eqz (local.get 0))
+                        (then (i32.const 0))
+                        (else
+                            (i32.add
+                                (call $pong (i32.sub (local.get 0) (i32.const 1)))
+                                (i32.const 1))))))"#,
+        )
+        .unwrap();
+        let mut store = Store::new(&engine, PingData::default());
+        // Host callable which calls back into guest.
+        let pong = Func::wrap(
+            &mut store,
+            |mut caller: Caller<'_, PingData>, n: i32| -> AnyResult<i32> {
+                let f = match caller.get_export("ping") {
+                    Some(Extern::Func(f)) => f.typed::<i32, i32>(&caller)?,
+                    _ => bail_with_site!("No ping export"),
+                };
+                let p = &mut caller as *mut Caller<'_, PingData>;
+                // SAFETY: Mimics callable reacquiring released store, like instance does.
+                unsafe {
+                    (*p).data_mut()
+                        .0
+                        .release_store_call(format!("callable {n}"), || ping(&mut *p, &f, n))
+                }?
+            },
+        );
+        let instance = InstanceWasm::new(&mut store, &module, &[pong.into()]).unwrap();
+        let f = instance
+            .get_typed_func::<i32, i32>(&mut store, "ping")
+            .unwrap();
+
+        // Deepest chain is 1 + 2 * n frames.
+        let n = (DEFAULT_MAX_REENTRANCY as i32 - 1) / 2;
+        assert_eq!(ping(&mut store, &f, n).unwrap(), n);
+        assert_eq!(store.data().0.reentrancy.depth(), 0);
+        // Only outermost call is counted.
+        assert_eq!(store.data().0.calls, 1);
+
+        let e = format!("{:#}", ping(&mut store, &f, n + 1).unwrap_err());
+        assert!(
+            e.contains(&format!(
+                "depth ({DEFAULT_MAX_REENTRANCY}) exceeded when entering guest 0"
+            )),
+            "{e}"
+        );
+        for i in 0..=n {
+            assert!(e.contains(&format!("guest {}\n", i + 1)), "{e}");
+            assert!(e.contains(&format!("callable {i}")), "{e}");
+        }
+        assert_eq!(store.data().0.reentrancy.depth(), 0);
+        assert_eq!(store.data().0.calls, 2);
+
+        // Store is still usable afterwards.
+        assert_eq!(ping(&mut store, &f, 1).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_reentrancy_custom_depth() {
+        let mut r = Reentrancy::<String> {
+            max_depth: 3,
+            ..Reentrancy::default()
+        };
+        for i in 0..3 {
+            r.enter(i.to_string()).unwrap();
+            assert_eq!(r.depth(), i + 1);
+        }
+        assert!(r.enter("3".into()).is_err());
+        assert_eq!(r.depth(), 3);
+        r.exit();
+        r.enter("3".into()).unwrap();
+    }
+
+    #[test]
+    fn test_call_token() {
+        let token = Arc::new(CallToken::default());
+        let mut data = StoreData {
+            call_token: Some(token.clone()),
+            ..StoreData::default()
+        };
+
+        // Cancelling other call does not affect running call.
+        let other = CallToken::default();
+        other.cancel();
+        other.interrupt();
+        assert!(!data.is_cancelled());
+        #[cfg(feature = "epoch-timeout")]
+        assert!(!data.is_interrupted());
+
+        token.cancel();
+        assert!(data.is_cancelled());
+        #[cfg(feature = "epoch-timeout")]
+        {
+            token.interrupt();
+            assert!(data.is_interrupted());
+        }
+
+        // Instance-wide request applies to every call.
+        data.call_token = None;
+        assert!(!data.is_cancelled());
+        data.activity.cancel();
+        assert!(data.is_cancelled());
+    }
+
+    #[test]
+    fn test_reentrancy_serial() {
+        let mut r = Reentrancy::<String>::default();
+        assert_eq!(r.current(), None);
+
+        r.enter("a".into()).unwrap();
+        let a = r.current().unwrap();
+        r.enter("b".into()).unwrap();
+        let b = r.current().unwrap();
+        assert_ne!(a, b);
+        assert!(r.is_live(a) && r.is_live(b));
+
+        r.exit();
+        assert!(!r.is_live(b));
+        r.enter("c".into()).unwrap();
+        assert_ne!(r.current(), Some(b));
+        r.exit();
+        r.exit();
+        assert!(!r.is_live(a));
+    }
+
+    #[test]
+    fn test_object_call_policy() {
+        crate::godot_util::init_main_thread();
+
+        // Stands in for Node.get_name, which is only valid in main thread.
+        let get_name = || {
+            assert!(is_main_thread());
+            "Node"
+        };
+
+        let mut lock = InnerLock {
+            object_calls: ObjectCallPolicy::Defer,
+            ..InnerLock::default()
+        };
+        assert_eq!(lock.release_store_main(get_name).unwrap(), "Node");
+
+        lock.object_calls = ObjectCallPolicy::Error;
+        let e = lock.release_store_main(get_name).unwrap_err();
+        assert!(e.to_string().contains("threading.objectCalls"), "{e}");
+
+        // Main thread calls directly under both policies.
+        call_in_main_thread(|| {
+            for p in [ObjectCallPolicy::Defer, ObjectCallPolicy::Error] {
+                lock.object_calls = p;
+                assert_eq!(lock.release_store_main(get_name).unwrap(), "Node");
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_marshal_limits() {
+        let mut data = StoreData {
+            marshal_limits: MarshalLimits {
+                max_arg_bytes: 16,
+                max_ret_bytes: 32,
+            },
+            ..StoreData::default()
+        };
+
+        data.check_arg_size(16).unwrap();
+        let e = data.check_arg_size(17).unwrap_err().to_string();
+        assert!(
+            e.contains("(17 bytes)") && e.contains("maxArgBytes limit (16 bytes)"),
+            "{e}"
+        );
+        data.check_ret_size(32).unwrap();
+        let e = data.check_ret_size(33).unwrap_err().to_string();
+        assert!(
+            e.contains("(33 bytes)") && e.contains("maxRetBytes limit (32 bytes)"),
+            "{e}"
+        );
+
+        data.marshal_override = Some(1 << 20);
+        data.check_arg_size(1 << 20).unwrap();
+        data.check_ret_size(1 << 20).unwrap();
+        data.check_arg_size((1 << 20) + 1).unwrap_err();
+
+        data.marshal_override = None;
+        data.check_arg_size(17).unwrap_err();
+        assert_eq!(
+            StoreData::default().marshal_limits.max_arg_bytes,
+            MARSHAL_MAX_BYTES
+        );
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut config = wasmtime::Config::new();
+        config.max_wasm_stack(64 << 10);
+        let engine = Engine::new(&config).unwrap();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (func $rec (export "rec") (param i32) (result i32)
+                    local.get 0
+                    i32.const 1
+                    i32.add
+                    call $rec
+                    i32.const 1
+                    i32.add)
+                (func (export "one") (result i32) i32.const 1)
+                (func (export "trap") unreachable))"#,
+        )
+        .unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = InstanceWasm::new(&mut store, &module, &[]).unwrap();
+
+        let e = instance
+            .get_typed_func::<i32, i32>(&mut store, "rec")
+            .unwrap()
+            .call(&mut store, 0)
+            .unwrap_err()
+            .context("Calling rec");
+        assert_eq!(error_kind(&e), Some("stack_overflow"));
+        assert!(format_error(&e).starts_with("[stack_overflow] "));
+
+        // Store is still usable afterwards.
+        let one = instance
+            .get_typed_func::<(), i32>(&mut store, "one")
+            .unwrap();
+        for _ in 0..3 {
+            assert_eq!(one.call(&mut store, ()).unwrap(), 1);
+        }
+
+        // Other traps have no kind.
+        let e = instance
+            .get_typed_func::<(), ()>(&mut store, "trap")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap_err();
+        assert_eq!(error_kind(&e), None);
+        assert!(!format_error(&e).starts_with('['));
+    }
+
+    #[test]
+    fn test_pixel_format() {
+        assert_eq!(pixel_format("RGBA8").unwrap(), (ImageFormat::RGBA8, 4));
+        assert_eq!(pixel_format("rgb8").unwrap(), (ImageFormat::RGB8, 3));
+        assert_eq!(pixel_format("L8").unwrap(), (ImageFormat::L8, 1));
+        assert_eq!(pixel_format("rg8").unwrap(), (ImageFormat::RG8, 2));
+        assert!(pixel_format("RGBAF").is_err());
+    }
+
+    #[cfg(feature = "memory-limiter")]
+    #[test]
+    fn test_memory_peak() {
+        const PAGE: u64 = 65536;
+
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "grow") (param i32) (result i32)
+                    (memory.grow (local.get 0))))"#,
+        )
+        .unwrap();
+        let mut store = Store::new(&engine, MemoryLimit::default());
+        store.limiter(|v| v);
+        let instance = InstanceWasm::new(&mut store, &module, &[]).unwrap();
+        let grow = instance
+            .get_typed_func::<i32, i32>(&mut store, "grow")
+            .unwrap();
+
+        // Initial allocation counts.
+        assert_eq!(store.data().peak, PAGE);
+        assert!(store.data().peak_time > 0.0);
+
+        grow.call(&mut store, 2).unwrap();
+        assert_eq!(store.data().peak, 3 * PAGE);
+        grow.call(&mut store, 0).unwrap();
+        assert_eq!(store.data().peak, 3 * PAGE);
+
+        store.data_mut().reset_peak(PAGE);
+        assert_eq!(store.data().peak, PAGE);
+        assert_eq!(store.data().peak_time, 0.0);
+        grow.call(&mut store, 1).unwrap();
+        assert_eq!(store.data().peak, 4 * PAGE);
+        assert!(store.data().peak_time > 0.0);
+        assert!(crate::wasm_engine::memory_peak() >= 4 * PAGE);
+    }
+
+    #[test]
+    fn test_debug_memory_image() {
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "fill") (param $p i32) (param $n i32) (local $i i32)
+                    (loop $l
+                        (i32.store8
+                            (i32.add (local.get $p) (local.get $i))
+                            (local.get $i))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br_if $l (i32.lt_u (local.get $i) (local.get $n))))))"#,
+        )
+        .unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = InstanceWasm::new(&mut store, &module, &[]).unwrap();
+        instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, "fill")
+            .unwrap()
+            .call(&mut store, (16, 256))
+            .unwrap();
+        let data = instance
+            .get_memory(&mut store, "memory")
+            .unwrap()
+            .data(&store);
+
+        // L8 with padded rows.
+        let (w, h) = image_size(4, 3).unwrap();
+        let s = pixel_region(data, 16, w, h, 8, 1).unwrap();
+        assert_eq!(&*s, &[0, 1, 2, 3, 8, 9, 10, 11, 16, 17, 18, 19]);
+
+        // RG8 tightly packed.
+        let (w, h) = image_size(2, 2).unwrap();
+        let s = pixel_region(data, 16, w, h, 0, 2).unwrap();
+        assert_eq!(&*s, &[0, 1, 2, 3, 4, 5, 6, 7]);
+
+        // Heatmap of first channel.
+        let s = pixel_region(data, 16, 256, 1, 0, 1).unwrap();
+        let heat = s.iter().map(|&v| heatmap_color(v)).collect::<Vec<_>>();
+        assert_eq!(heat[0], [0, 0, 0, 255]);
+        assert_eq!(heat[32], [0, 0, 127, 255]);
+        assert_eq!(heat[64], [0, 0, 255, 255]);
+        assert_eq!(heat[128], [0, 255, 0, 255]);
+        assert_eq!(heat[192], [255, 255, 0, 255]);
+        assert_eq!(heat[255], [255, 0, 0, 255]);
+
+        // Bounds validation.
+        let len = data.len() as i64;
+        assert!(image_size(0, 1).is_err());
+        assert!(image_size(1, i64::MAX).is_err());
+        assert!(pixel_region(data, len - 2, 4, 1, 0, 1).is_err());
+        assert!(pixel_region(data, -1, 4, 1, 0, 1).is_err());
+        assert!(pixel_region(data, 16, 4, 2, 3, 1).is_err());
+        assert!(pixel_region(data, 0, 4, 2, -8, 1).is_err());
+        assert!(pixel_region(data, len - 4, 4, 1, 0, 1).is_ok());
+    }
+
+    const LAZY_DEP_COUNT: usize = 10;
+
+    fn lazy_fixture(engine: &Engine) -> (Vec<Module>, Module) {
+        let deps = (0..LAZY_DEP_COUNT)
+            .map(|i| {
+                Module::new(
+                    engine,
+                    format!(
+                        r#"(module
+                            (memory 4)
+                            (func $start
+                                (local $i i32)
+                                (loop $l
+                                    (i32.store (local.get $i) (local.get $i))
+                                    (local.tee $i (i32.add (local.get $i) (i32.const 4)))
+                                    (br_if $l (i32.lt_u (i32.const 262144)))))
+                            (start $start)
+                            (func (export "f") (result i32) i32.const {i}))"#
+                    ),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let mut main = String::from("(module\n");
+        for i in 0..LAZY_DEP_COUNT {
+            main += &format!("(import \"dep{i}\" \"f\" (func $f{i} (result i32)))\n");
+        }
+        main += "(func (export \"run\") (result i32) call $f3))";
+        (deps, Module::new(engine, main).unwrap())
+    }
+
+    /// Counts memories created in store, one per instantiated dependency.
+    #[derive(Default)]
+    struct MemoryCount(usize);
+
+    impl wasmtime::ResourceLimiter for MemoryCount {
+        fn memory_growing(
+            &mut self,
+            current: usize,
+            _: usize,
+            _: Option<usize>,
+        ) -> AnyResult<bool> {
+            if current == 0 {
+                self.0 += 1;
+            }
+            Ok(true)
+        }
+
+        fn table_growing(&mut self, _: usize, _: usize, _: Option<usize>) -> AnyResult<bool> {
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_lazy_deps() {
+        let engine = Engine::default();
+        let (deps, main) = lazy_fixture(&engine);
+        let ty = FuncType::new(&engine, [], [ValType::I32]);
+
+        let mut store = Store::new(&engine, MemoryCount::default());
+        store.limiter(|v| v);
+        let imports = deps
+            .iter()
+            .map(|m| {
+                InstanceWasm::new(&mut store, m, &[])
+                    .unwrap()
+                    .get_export(&mut store, "f")
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let inst = InstanceWasm::new(&mut store, &main, &imports).unwrap();
+        // Every dependency is instantiated eagerly.
+        assert_eq!(store.data().0, LAZY_DEP_COUNT);
+        let f = inst.get_typed_func::<(), i32>(&mut store, "run").unwrap();
+        assert_eq!(f.call(&mut store, ()).unwrap(), 3);
+
+        let mut store = Store::new(&engine, MemoryCount::default());
+        store.limiter(|v| v);
+        let lazy = deps
+            .iter()
+            .enumerate()
+            .map(|(i, m)| Arc::new(LazyDep::new(format!("dep{i}"), m.clone(), Vec::new())))
+            .collect::<Vec<_>>();
+        let imports = lazy
+            .iter()
+            .map(|d| d.trampoline(&mut store, "f", ty.clone()).into())
+            .collect::<Vec<Extern>>();
+        let inst = InstanceWasm::new(&mut store, &main, &imports).unwrap();
+        // No dependency is instantiated yet.
+        assert_eq!(store.data().0, 0);
+        assert!(lazy.iter().all(|d| !d.is_ready()));
+
+        // Only called dependency is instantiated, and only once.
+        let f = inst.get_typed_func::<(), i32>(&mut store, "run").unwrap();
+        assert_eq!(f.call(&mut store, ()).unwrap(), 3);
+        assert_eq!(store.data().0, 1);
+        assert_eq!(f.call(&mut store, ()).unwrap(), 3);
+        assert_eq!(store.data().0, 1);
+        let ready = lazy.iter().filter(|d| d.is_ready()).count();
+        assert_eq!(ready, 1);
+        assert!(lazy[3].is_ready());
+    }
+
+    #[test]
+    fn test_lazy_deps_circular() {
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (import "host" "f" (func $f (result i32)))
+                (func $start (drop (call $f)))
+                (start $start)
+                (func (export "f") (result i32) i32.const 1))"#,
+        )
+        .unwrap();
+        let ty = FuncType::new(&engine, [], [ValType::I32]);
+
+        let mut store = Store::new(&engine, ());
+        // Host function calls back into the dependency while it's starting.
+        let cell = Arc::new(OnceCell::<Func>::new());
+        let host = Func::new(&mut store, ty.clone(), {
+            let cell = cell.clone();
+            move |mut caller, args, rets| cell.get().unwrap().call(&mut caller, args, rets)
+        });
+        let dep = Arc::new(LazyDep::new("dep".into(), module, vec![host.into()]));
+        let f = dep.trampoline(&mut store, "f", ty);
+        cell.set(f).unwrap();
+
+        let mut rets = [Val::I32(0)];
+        let e = f.call(&mut store, &[], &mut rets).unwrap_err();
+        assert!(format!("{e:?}").contains("Circular lazy initialization"));
+        assert!(!dep.is_ready());
+
+        let e = f.call(&mut store, &[], &mut rets).unwrap_err();
+        assert!(format!("{e:?}").contains("failed to instantiate"));
+    }
+
+    #[test]
+    fn test_call_queue() {
+        let queue = Mutex::new(CallQueue::<u32>::default());
+        // Call runs immediately if queue is disabled.
+        assert_eq!(queue.lock().push(0).unwrap(), Some(0));
+
+        queue.lock().limit = Some(3);
+        for i in 1..=3 {
+            assert_eq!(queue.lock().push(i).unwrap(), None);
+        }
+        let e = queue.lock().push(4).unwrap_err();
+        assert!(
+            e.to_string().contains("Call queue is full (3 calls)"),
+            "{e}"
+        );
+
+        // Calls made by other thread while queue is running are queued after it,
+        // while nested call made by queued call runs immediately.
+        let mut finished = Vec::new();
+        drain_queue(&queue, |i| {
+            if i == 1 {
+                thread::scope(|s| {
+                    s.spawn(|| assert_eq!(queue.lock().push(10).unwrap(), None));
+                });
+                assert_eq!(queue.lock().push(11).unwrap(), Some(11));
+            }
+            assert!(queue.lock().is_active());
+            finished.push(i);
+        });
+        assert_eq!(finished, [1, 2, 3, 10]);
+
+        // Queue is disabled once drained.
+        assert!(!queue.lock().is_active());
+        assert_eq!(queue.lock().push(5).unwrap(), Some(5));
+        drain_queue(&queue, |_| unreachable!());
+    }
+
+    #[test]
+    fn test_cooperative_cancel() {
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (import "host" "is_cancelled" (func $c (result i32)))
+                (func (export "poll") (result i32) call $c)
+                (func (export "spin") (result i32)
+                    (local $n i32)
+                    (loop $l
+                        (local.set $n (i32.add (local.get $n) (i32.const 1)))
+                        (br_if $l (i32.eqz (call $c))))
+                    local.get $n))"#,
+        )
+        .unwrap();
+
+        let mut store = Store::new(&engine, StoreData::default());
+        let activity = store.data().activity.clone();
+        let f = host_builtin_func(&mut store, HOST_IS_CANCELLED).unwrap();
+        let inst = InstanceWasm::new(&mut store, &module, &[f.into()]).unwrap();
+        let poll = inst.get_typed_func::<(), i32>(&mut store, "poll").unwrap();
+        let spin = inst.get_typed_func::<(), i32>(&mut store, "spin").unwrap();
+
+        // Cancellation requested before the call is reset.
+        activity.cancel();
+        {
+            let _active = activity.enter();
+            assert_eq!(poll.call(&mut store, ()).unwrap(), 0);
+        }
+
+        let n = {
+            let _active = activity.enter();
+            let t = std::thread::spawn({
+                let activity = activity.clone();
+                move || {
+                    std::thread::sleep(Duration::from_millis(20));
+                    activity.cancel();
+                }
+            });
+            let n = spin.call(&mut store, ()).unwrap();
+            t.join().unwrap();
+            n
+        };
+        assert!(n > 1);
+
+        // Nested calls does not reset it.
+        {
+            let _active = activity.enter();
+            activity.cancel();
+            let _nested = activity.enter();
+            assert_eq!(poll.call(&mut store, ()).unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn test_frame_info_per_call() {
+        let mut lock = InnerLock::default();
+        let mut frame = 0;
+        let mut sample = || {
+            frame += 1;
+            FrameInfo {
+                frames_drawn: frame as _,
+                process_frames: frame,
+                physics_frames: frame * 2,
+                process_delta: 1.0 / 60.0,
+                physics_delta: 1.0 / 60.0,
+            }
+        };
+
+        let mut prev = 0;
+        for _ in 0..3 {
+            lock.reset_frame_info();
+            let a = lock.frame_info_with(&mut sample);
+            // Repeated reads within a call are consistent.
+            for _ in 0..4 {
+                assert_eq!(lock.frame_info_with(&mut sample), a);
+            }
+            assert!(a.process_frames > prev);
+            prev = a.process_frames;
+        }
+        // Sampled once per call only.
+        assert_eq!(frame, 3);
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        use InstanceState::*;
+
+        let state = StateMachine::default();
+        assert_eq!(state.get(), Uninitialized);
+
+        let mut emitted = Vec::new();
+        let mut walk = |to| match state.transition(to) {
+            Some(from) => {
+                emitted.push((from, to));
+                true
+            }
+            None => false,
+        };
+
+        assert!(!walk(Running));
+        assert!(walk(Compiling));
+        assert!(!walk(Running));
+        assert!(walk(Errored));
+        assert!(walk(Compiling));
+        assert!(walk(Ready));
+        assert!(walk(Running));
+        // Nested call does not transition.
+        assert!(!walk(Running));
+        assert!(walk(Ready));
+        assert!(walk(FreedPending));
+        assert!(walk(Running));
+        assert!(walk(Exited));
+        // Exit is final.
+        for s in [Ready, Running, Compiling, FreedPending] {
+            assert!(!walk(s));
+        }
+
+        assert_eq!(
+            emitted,
+            [
+                (Uninitialized, Compiling),
+                (Compiling, Errored),
+                (Errored, Compiling),
+                (Compiling, Ready),
+                (Ready, Running),
+                (Running, Ready),
+                (Ready, FreedPending),
+                (FreedPending, Running),
+                (Running, Exited),
+            ]
+        );
+        assert_eq!(state.get(), Exited);
+    }
+
+    #[test]
+    fn test_pure_sandbox() {
+        check_pure_config(&Config::default()).unwrap();
+        #[cfg(feature = "wasi")]
+        {
+            let config = Config {
+                with_wasi: true,
+                ..Config::default()
+            };
+            let e = check_pure_config(&config).unwrap_err().to_string();
+            assert!(e.contains("does not allow WASI"), "{e}");
+        }
+
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (import "host" "f" (func))
+                (import "env" "memory" (memory 1)))"#,
+        )
+        .unwrap();
+        let e = check_pure_module(&module).unwrap_err().to_string();
+        assert!(
+            e.contains("does not allow imports: host.f, env.memory"),
+            "{e}"
+        );
+
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "add") (param i32 i32) (result i32)
+                    (i32.add (local.get 0) (local.get 1))))"#,
+        )
+        .unwrap();
+        check_pure_module(&module).unwrap();
+        let mut store = Store::new(&engine, StoreData::default());
+        let instance = InstanceWasm::new(&mut store, &module, &[]).unwrap();
+        let f = instance
+            .get_typed_func::<(i32, i32), i32>(&mut store, "add")
+            .unwrap();
+        assert_eq!(f.call(&mut store, (1, 2)).unwrap(), 3);
+
+        // No WASI context or object registry is allocated.
+        assert_eq!(
+            store.data().host_state_usage(),
+            (mem::size_of::<StoreData>(), false, false)
+        );
+        #[cfg(feature = "object-registry-compat")]
+        {
+            store.data_mut().object_registry = Some(ObjectRegistry::default());
+            assert!(store.data().host_state_usage().2);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::Cursor;
+use std::mem;
+
+use anyhow::Result as AnyResult;
+use godot::prelude::*;
+use once_cell::sync::OnceCell;
+use tracing::{error, info, instrument, Level};
+use wasmtime::{MemoryType, SharedMemory};
+
+use crate::godot_util::option_to_variant;
+use crate::rw_struct::{read_struct, to_struct_format, write_struct};
+use crate::wasm_engine::get_engine;
+#[cfg(feature = "memory-limiter")]
+use crate::wasm_engine::MemoryReservation;
+use crate::{bail_with_site, site_context};
+
+/// Size of default WebAssembly page.
+#[cfg(feature = "memory-limiter")]
+const WASM_PAGE_SIZE: u64 = 65536;
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init, tool)]
+/// Host-owned WebAssembly memory.
+///
+/// Created with `WasmMemory.create()`. It can be imported into multiple instances
+/// using `memory.imports` config, and read/written directly from GDScript.
+/// Memory is kept alive as long as any instance or reference to it exists.
+pub struct WasmMemory {
+    base: Base<RefCounted>,
+    memory: OnceCell<SharedMemory>,
+    /// Shared memory can grow outside of any store, so it's maximum size is reserved upfront.
+    #[cfg(feature = "memory-limiter")]
+    reserved: OnceCell<MemoryReservation>,
+}
+
+impl Debug for WasmMemory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("WasmMemory")
+            .field("id", &self.base().instance_id())
+            .field("size", &self.memory.get().map(|v| v.data_size()))
+            .finish()
+    }
+}
+
+impl WasmMemory {
+    pub fn get_memory(&self) -> AnyResult<&SharedMemory> {
+        match self.memory.get() {
+            Some(v) => Ok(v),
+            None => bail_with_site!("Uninitialized memory"),
+        }
+    }
+
+    fn unwrap_memory<F, R>(&self, f: F) -> Option<R>
+    where
+        for<'a> F: FnOnce(&'a mut [u8]) -> AnyResult<R>,
+    {
+        let r = self.get_memory().and_then(|mem| {
+            // SAFETY: Externalize concurrent access to user
+            #[allow(mutable_transmutes)]
+            f(unsafe { mem::transmute::<&[_], &mut [u8]>(mem.data()) })
+        });
+        match r {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!("{e:?}");
+                godot_error!("{e:?}");
+                None
+            }
+        }
+    }
+
+    fn read_memory<F, R>(&self, i: usize, n: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&[u8]) -> AnyResult<R>,
+    {
+        self.unwrap_memory(|data| match data.get(i..i.saturating_add(n)) {
+            Some(s) => f(s),
+            None => bail_with_site!("Index out of bound {}-{}", i, i.saturating_add(n)),
+        })
+    }
+
+    fn write_memory<F, R>(&self, i: usize, n: usize, f: F) -> Option<R>
+    where
+        for<'a> F: FnOnce(&'a mut [u8]) -> AnyResult<R>,
+    {
+        self.unwrap_memory(|data| match data.get_mut(i..i.saturating_add(n)) {
+            Some(s) => f(s),
+            None => bail_with_site!("Index out of bound {}-{}", i, i.saturating_add(n)),
+        })
+    }
+}
+
+#[godot_api]
+impl WasmMemory {
+    /// Creates new memory with `min_pages` initial size and `max_pages` maximum size.
+    ///
+    /// Only shared memory is supported, as non-shared memory is bound to a single instance.
+    /// Importing module must declare the memory as shared.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn create(min_pages: i64, max_pages: i64, shared: bool) -> Option<Gd<WasmMemory>> {
+        let ret = WasmMemory::new_gd();
+        let r = (|| -> AnyResult<_> {
+            if !shared {
+                bail_with_site!("Non-shared memory cannot be shared between instances")
+            }
+            let max_pages = site_context!(u32::try_from(max_pages))?;
+            let ty = MemoryType::shared(site_context!(u32::try_from(min_pages))?, max_pages);
+            let this = ret.bind();
+
+            #[cfg(feature = "memory-limiter")]
+            {
+                let mut reserved = MemoryReservation::default();
+                if !reserved.grow(u64::from(max_pages) * WASM_PAGE_SIZE, None) {
+                    bail_with_site!("Global memory budget exceeded");
+                }
+                this.reserved.set(reserved).unwrap();
+            }
+
+            this.memory
+                .set(site_context!(SharedMemory::new(&get_engine()?, ty))?)
+                .unwrap();
+            Ok(())
+        })();
+
+        match r {
+            Ok(()) => Some(ret),
+            Err(e) => {
+                error!("{e:?}");
+                godot_error!("{e:?}");
+                None
+            }
+        }
+    }
+
+    /// Returns memory size in bytes.
+    #[func]
+    #[instrument(ret)]
+    fn memory_size(&self) -> i64 {
+        self.unwrap_memory(|data| Ok(data.len() as i64))
+            .unwrap_or_default()
+    }
+
+    /// Grows memory by `delta` pages. Returns previous size in pages, or -1 if it fails.
+    #[func]
+    #[instrument(ret)]
+    fn grow(&self, delta: i64) -> i64 {
+        let r = self.get_memory().and_then(|mem| {
+            Ok(site_context!(mem.grow(site_context!(u64::try_from(delta))?))? as i64)
+        });
+        match r {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{e:?}");
+                godot_error!("{e:?}");
+                -1
+            }
+        }
+    }
+
+    /// Reads a chunk of memory.
+    #[func]
+    #[instrument]
+    fn memory_read(&self, i: i64, n: i64) -> PackedByteArray {
+        self.read_memory(i as _, n as _, |s| Ok(PackedByteArray::from(s)))
+            .unwrap_or_default()
+    }
+
+    /// Writes a chunk of memory.
+    #[func]
+    #[instrument(skip(a), fields(a.len = a.len()), ret)]
+    fn memory_write(&self, i: i64, a: PackedByteArray) -> bool {
+        self.write_memory(i as _, a.len(), move |s| {
+            s.copy_from_slice(a.as_slice());
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Reads an unsigned 8-bit integer.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn get_8(&self, i: i64) -> i64 {
+        self.read_memory(i as _, 1, |s| Ok(s[0]))
+            .unwrap_or_default()
+            .into()
+    }
+
+    /// Writes an unsigned 8-bit integer.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn put_8(&self, i: i64, v: i64) -> bool {
+        self.write_memory(i as _, 1, |s| {
+            s[0] = (v & 255) as _;
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Reads an unsigned 16-bit integer.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn get_16(&self, i: i64) -> i64 {
+        self.read_memory(i as _, 2, |s| Ok(u16::from_le_bytes(s.try_into().unwrap())))
+            .unwrap_or_default()
+            .into()
+    }
+
+    /// Writes an unsigned 16-bit integer.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn put_16(&self, i: i64, v: i64) -> bool {
+        self.write_memory(i as _, 2, |s| {
+            s.copy_from_slice(&((v & 0xffff) as u16).to_le_bytes());
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Reads an unsigned 32-bit integer.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn get_32(&self, i: i64) -> i64 {
+        self.read_memory(i as _, 4, |s| Ok(u32::from_le_bytes(s.try_into().unwrap())))
+            .unwrap_or_default()
+            .into()
+    }
+
+    /// Writes an unsigned 32-bit integer.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn put_32(&self, i: i64, v: i64) -> bool {
+        self.write_memory(i as _, 4, |s| {
+            s.copy_from_slice(&((v & 0xffffffff) as u32).to_le_bytes());
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Reads a signed 64-bit integer.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn get_64(&self, i: i64) -> i64 {
+        self.read_memory(i as _, 8, |s| Ok(i64::from_le_bytes(s.try_into().unwrap())))
+            .unwrap_or_default()
+    }
+
+    /// Writes a signed 64-bit integer.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn put_64(&self, i: i64, v: i64) -> bool {
+        self.write_memory(i as _, 8, |s| {
+            s.copy_from_slice(&v.to_le_bytes());
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Reads a 32-bit floating-point number.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn get_float(&self, i: i64) -> f64 {
+        self.read_memory(i as _, 4, |s| Ok(f32::from_le_bytes(s.try_into().unwrap())))
+            .unwrap_or_default()
+            .into()
+    }
+
+    /// Writes a 32-bit floating-point number.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn put_float(&self, i: i64, v: f64) -> bool {
+        self.write_memory(i as _, 4, |s| {
+            s.copy_from_slice(&(v as f32).to_le_bytes());
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Reads a 64-bit floating-point number.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn get_double(&self, i: i64) -> f64 {
+        self.read_memory(i as _, 8, |s| Ok(f64::from_le_bytes(s.try_into().unwrap())))
+            .unwrap_or_default()
+    }
+
+    /// Writes a 64-bit floating-point number.
+    #[func]
+    #[instrument(level = Level::DEBUG, ret)]
+    fn put_double(&self, i: i64, v: f64) -> bool {
+        self.write_memory(i as _, 8, |s| {
+            s.copy_from_slice(&v.to_le_bytes());
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Reads a structured data.
+    ///
+    /// Format can be a string or `WasmStructFormat`.
+    #[func]
+    #[instrument(level = Level::DEBUG)]
+    fn read_struct(&self, format: Variant, p: u64) -> Variant {
+        option_to_variant(self.unwrap_memory(move |data| {
+            let format = to_struct_format(&format)?;
+            let mut f = Cursor::new(data);
+            f.set_position(p);
+            let ret = read_struct(f, &format)?;
+            info!(ret.len = ret.len());
+            Ok(ret)
+        }))
+    }
+
+    /// Writes a structured data.
+    ///
+    /// Format can be a string or `WasmStructFormat`.
+    #[func]
+    #[instrument(level = Level::DEBUG, skip(arr), fields(arr.len = arr.len()), ret)]
+    fn write_struct(&self, format: Variant, p: u64, arr: VariantArray) -> u64 {
+        self.unwrap_memory(move |data| {
+            let format = to_struct_format(&format)?;
+            let mut f = Cursor::new(data);
+            f.set_position(p);
+            write_struct(f, &format, arr)
+        })
+        .unwrap_or_default() as _
+    }
+}
@@ -5,6 +5,7 @@ mod pool_array;
 mod primitive;
 mod string;
 mod typeis;
+mod variant;
 
 use wasmtime::{Func, StoreContextMut};
 
@@ -33,4 +34,4 @@ macro_rules! register{
     };
 }
 
-register![array, dict, other, pool_array, primitive, string, typeis];
+register![array, dict, other, pool_array, primitive, string, typeis, variant];
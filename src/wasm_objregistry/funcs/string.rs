@@ -7,6 +7,7 @@ use wasmtime::{Caller, Extern, Func, StoreContextMut};
 
 use crate::godot_util::from_var_any;
 use crate::wasm_instance::StoreData;
+use crate::wasm_util::decode_utf16_lossy;
 use crate::{bail_with_site, func_registry, site_context};
 
 func_registry! {
@@ -58,6 +59,21 @@ func_registry! {
         };
         Ok(ctx.data_mut().as_mut().get_registry_mut()?.register(v) as _)
     },
+    new_utf16 => |mut ctx: Caller<'_, T>, p: u32, n: u32| -> Result<u32, Error> {
+        let mem = match ctx.get_export("memory") {
+            Some(Extern::Memory(v)) => v,
+            _ => return Ok(0),
+        };
+
+        let e = p as usize + n as usize * 2;
+        let (v, errors) = match mem.data(&mut ctx).get(p as _..e) {
+            Some(s) => decode_utf16_lossy(s),
+            None => bail_with_site!("Invalid memory bounds ({}..{})", p, e),
+        };
+        let data = ctx.data_mut().as_mut();
+        data.string_errors += errors as u64;
+        Ok(data.get_registry_mut()?.register(v.to_variant()) as _)
+    },
     to_string_name => |mut ctx: Caller<'_, T>, i: u32| -> Result<(), Error> {
         let v = site_context!(from_var_any::<GString>(
             &ctx.data().as_ref().get_registry()?.get_or_nil(i as _)
@@ -0,0 +1,15 @@
+use anyhow::Error;
+use wasmtime::{Caller, Func, StoreContextMut};
+
+use crate::func_registry;
+use crate::godot_util::duplicate_variant;
+use crate::wasm_instance::StoreData;
+
+func_registry! {
+    "variant.",
+    duplicate => |mut ctx: Caller<'_, T>, i: u32, deep: u32| -> Result<u32, Error> {
+        let reg = ctx.data_mut().as_mut().get_registry_mut()?;
+        let v = duplicate_variant(&reg.get_or_nil(i as _), deep != 0);
+        Ok(reg.register(v) as _)
+    },
+}
@@ -8,6 +8,7 @@ use slab::Slab;
 pub use funcs::Funcs;
 
 use crate::godot_util::SendSyncWrapper;
+use crate::warn_rate_limited;
 
 pub struct ObjectRegistry {
     slab: Slab<SendSyncWrapper<Variant>>,
@@ -56,8 +57,24 @@ impl ObjectRegistry {
             .map(|p| mem::replace(p, SendSyncWrapper::new(v)).into_inner())
     }
 
+    /// Gets object, or nil if index is 0 or invalid. Invalid index is warned (rate-limited).
     #[inline]
     pub fn get_or_nil(&self, ix: usize) -> Variant {
-        self.get(ix).unwrap_or_default()
+        self.get(ix).unwrap_or_else(|| {
+            if ix != 0 {
+                warn_rate_limited!(
+                    "objregistry.invalid_index",
+                    1.0,
+                    "Invalid object registry index {ix}, treated as null"
+                );
+            }
+            Variant::nil()
+        })
+    }
+
+    /// Approximate heap size of registry, in bytes.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        self.slab.capacity() * mem::size_of::<SendSyncWrapper<Variant>>()
     }
 }
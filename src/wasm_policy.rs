@@ -0,0 +1,411 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult, Write as _};
+
+use anyhow::Result as AnyResult;
+use godot::classes::ProjectSettings;
+use godot::prelude::*;
+use parking_lot::RwLock;
+use sha2::{Digest, Sha256};
+use tracing::{error, info, instrument, Level};
+
+use crate::{bail_with_site, site_context, variant_dispatch};
+
+/// Engine-wide module policy.
+static POLICY: RwLock<ModulePolicy> = RwLock::new(ModulePolicy::new());
+
+const SETTING_ALLOWED_HASHES: &str = "godot_wasm/module_policy/allowed_hashes";
+const SETTING_ALLOWED_PATHS: &str = "godot_wasm/module_policy/allowed_paths";
+const SETTING_DENY_UNSIGNED: &str = "godot_wasm/module_policy/deny_unsigned";
+const SETTING_FREEZE: &str = "godot_wasm/module_policy/freeze";
+
+pub type Hash = [u8; 32];
+
+/// Rule that rejects module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyRule {
+    /// Hash is not allowed and `deny_unsigned` is set.
+    DenyUnsigned,
+    /// Hash is not allowed.
+    AllowedHashes,
+    /// Hash is not allowed and path does not match any allowed prefixes.
+    AllowedPaths,
+}
+
+impl Display for PolicyRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(match self {
+            Self::DenyUnsigned => "deny_unsigned",
+            Self::AllowedHashes => "allowed_hashes",
+            Self::AllowedPaths => "allowed_paths",
+        })
+    }
+}
+
+pub struct ModuleRejectedError {
+    pub rule: PolicyRule,
+    pub hash: Hash,
+    pub path: Option<String>,
+}
+
+impl Debug for ModuleRejectedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for ModuleRejectedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "module rejected by policy rule {} (sha256: {}",
+            self.rule,
+            to_hex(&self.hash)
+        )?;
+        match &self.path {
+            Some(p) => write!(f, ", path: {p})"),
+            None => write!(f, ")"),
+        }
+    }
+}
+
+impl Error for ModuleRejectedError {}
+
+pub struct PolicyFrozenError;
+
+impl Debug for PolicyFrozenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "module policy is frozen")
+    }
+}
+
+impl Display for PolicyFrozenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Debug::fmt(self, f)
+    }
+}
+
+impl Error for PolicyFrozenError {}
+
+/// Allow/deny list of modules.
+///
+/// Empty policy allows everything.
+#[derive(Debug, Default)]
+pub struct ModulePolicy {
+    allowed_hashes: BTreeSet<Hash>,
+    allowed_paths: Vec<String>,
+    deny_unsigned: bool,
+    frozen: bool,
+}
+
+impl ModulePolicy {
+    pub const fn new() -> Self {
+        Self {
+            allowed_hashes: BTreeSet::new(),
+            allowed_paths: Vec::new(),
+            deny_unsigned: false,
+            frozen: false,
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Locks policy, preventing further changes.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        self.allowed_hashes.is_empty() && self.allowed_paths.is_empty() && !self.deny_unsigned
+    }
+
+    /// Replaces policy rules.
+    pub fn set(
+        &mut self,
+        allowed_hashes: impl IntoIterator<Item = Hash>,
+        allowed_paths: impl IntoIterator<Item = String>,
+        deny_unsigned: bool,
+    ) -> Result<(), PolicyFrozenError> {
+        if self.frozen {
+            return Err(PolicyFrozenError);
+        }
+
+        self.allowed_hashes = allowed_hashes.into_iter().collect();
+        self.allowed_paths = allowed_paths.into_iter().collect();
+        self.deny_unsigned = deny_unsigned;
+        Ok(())
+    }
+
+    /// Checks module data against policy.
+    pub fn check(&self, data: &[u8], path: Option<&str>) -> Result<(), ModuleRejectedError> {
+        if self.is_unrestricted() {
+            return Ok(());
+        }
+
+        let hash: Hash = Sha256::digest(data).into();
+        if self.allowed_hashes.contains(&hash) {
+            return Ok(());
+        }
+
+        let rule = if self.deny_unsigned {
+            PolicyRule::DenyUnsigned
+        } else if self.allowed_paths.is_empty() {
+            PolicyRule::AllowedHashes
+        } else if path.is_some_and(|p| self.allowed_paths.iter().any(|v| p.starts_with(&**v))) {
+            return Ok(());
+        } else {
+            PolicyRule::AllowedPaths
+        };
+        Err(ModuleRejectedError {
+            rule,
+            hash,
+            path: path.map(String::from),
+        })
+    }
+
+    pub fn to_dict(&self) -> Dictionary {
+        let mut ret = Dictionary::new();
+        ret.set(
+            "allowed_hashes",
+            self.allowed_hashes
+                .iter()
+                .map(|v| GString::from(to_hex(v)))
+                .collect::<PackedStringArray>(),
+        );
+        ret.set(
+            "allowed_paths",
+            self.allowed_paths
+                .iter()
+                .map(GString::from)
+                .collect::<PackedStringArray>(),
+        );
+        ret.set("deny_unsigned", self.deny_unsigned);
+        ret.set("frozen", self.is_frozen());
+        ret
+    }
+}
+
+fn to_hex(v: &[u8]) -> String {
+    let mut s = String::with_capacity(v.len() * 2);
+    for b in v {
+        write!(s, "{b:02x}").unwrap();
+    }
+    s
+}
+
+fn parse_hash(s: &str) -> AnyResult<Hash> {
+    let s = s.trim();
+    if s.len() != 64 || !s.is_ascii() {
+        bail_with_site!("Invalid SHA-256 hash {s:?}");
+    }
+
+    let mut ret = [0u8; 32];
+    for (o, i) in ret.iter_mut().zip(s.as_bytes().chunks(2)) {
+        match std::str::from_utf8(i).map(|i| u8::from_str_radix(i, 16)) {
+            Ok(Ok(v)) => *o = v,
+            _ => bail_with_site!("Invalid SHA-256 hash {s:?}"),
+        }
+    }
+    Ok(ret)
+}
+
+fn get_strings(v: Option<Variant>) -> AnyResult<Vec<String>> {
+    let Some(v) = v else { return Ok(Vec::new()) };
+    Ok(variant_dispatch!(v {
+        NIL => Vec::new(),
+        PACKED_STRING_ARRAY => v.as_slice().iter().map(|v| v.to_string()).collect(),
+        ARRAY => v
+            .iter_shared()
+            .map(|v| Ok(v.try_to::<GString>()?.to_string()))
+            .collect::<AnyResult<_>>()?,
+        _ => bail_with_site!("Unknown value {v}"),
+    }))
+}
+
+fn get_bool(v: Option<Variant>) -> AnyResult<bool> {
+    let Some(v) = v else { return Ok(false) };
+    Ok(variant_dispatch!(v {
+        NIL => false,
+        BOOL => v,
+        _ => bail_with_site!("Unknown value {v}"),
+    }))
+}
+
+/// Sets engine-wide policy from dictionary.
+///
+/// Dictionary keys:
+/// - `allowed_hashes` : Array of hex-encoded SHA-256 hash.
+/// - `allowed_paths` : Array of path prefix.
+/// - `deny_unsigned` : If `true`, module hash must be in `allowed_hashes` regardless of it's path.
+#[instrument(level = Level::DEBUG, err)]
+pub fn set_policy(dict: Dictionary) -> AnyResult<()> {
+    let hashes = get_strings(dict.get("allowed_hashes"))?
+        .iter()
+        .map(|s| parse_hash(s))
+        .collect::<AnyResult<Vec<_>>>()?;
+    let paths = get_strings(dict.get("allowed_paths"))?;
+    let deny_unsigned = get_bool(dict.get("deny_unsigned"))?;
+
+    POLICY.write().set(hashes, paths, deny_unsigned)?;
+    Ok(())
+}
+
+pub fn freeze_policy() {
+    POLICY.write().freeze();
+}
+
+pub fn get_policy() -> Dictionary {
+    POLICY.read().to_dict()
+}
+
+fn localize_path(path: &str) -> String {
+    ProjectSettings::singleton()
+        .localize_path(&GString::from(path))
+        .to_string()
+}
+
+/// Checks module data against engine-wide policy.
+pub fn check_module(data: &[u8], path: Option<&str>) -> AnyResult<()> {
+    let policy = POLICY.read();
+    if policy.is_unrestricted() {
+        return Ok(());
+    }
+
+    Ok(policy.check(data, path.map(localize_path).as_deref())?)
+}
+
+/// Reads policy from project settings.
+///
+/// If settings are malformed, every module is denied.
+#[instrument]
+pub fn init_policy() {
+    let settings = ProjectSettings::singleton();
+    let get = |k: &str| {
+        let k = GString::from(k);
+        if settings.has_setting(&k) {
+            Some(settings.get_setting(&k))
+        } else {
+            None
+        }
+    };
+
+    let r = (|| -> AnyResult<_> {
+        let mut dict = Dictionary::new();
+        if let Some(v) = get(SETTING_ALLOWED_HASHES) {
+            dict.set("allowed_hashes", v);
+        }
+        if let Some(v) = get(SETTING_ALLOWED_PATHS) {
+            dict.set("allowed_paths", v);
+        }
+        if let Some(v) = get(SETTING_DENY_UNSIGNED) {
+            dict.set("deny_unsigned", v);
+        }
+        set_policy(dict)?;
+        get_bool(get(SETTING_FREEZE))
+    })();
+
+    let mut policy = POLICY.write();
+    match r {
+        Ok(freeze) => {
+            if freeze {
+                policy.freeze();
+            }
+            info!(?policy, "Module policy initialized");
+        }
+        Err(e) => {
+            error!(err = %e, "Malformed module policy, denying all modules");
+            godot_error!("Malformed module policy, denying all modules: {e:?}");
+            *policy = ModulePolicy {
+                deny_unsigned: true,
+                frozen: true,
+                ..ModulePolicy::new()
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE_A: &[u8] = b"\0asm\x01\0\0\0";
+    const MODULE_B: &[u8] = b"\0asm\x01\0\0\0\x00\x01\x00";
+
+    fn hash(data: &[u8]) -> Hash {
+        Sha256::digest(data).into()
+    }
+
+    #[test]
+    fn test_unrestricted() {
+        let policy = ModulePolicy::new();
+        assert!(policy.is_unrestricted());
+        policy.check(MODULE_A, None).unwrap();
+        policy.check(MODULE_B, Some("user://mod.wasm")).unwrap();
+    }
+
+    #[test]
+    fn test_allowed_hash() {
+        let mut policy = ModulePolicy::new();
+        policy.set([hash(MODULE_A)], [], true).unwrap();
+
+        policy.check(MODULE_A, None).unwrap();
+        policy.check(MODULE_A, Some("user://mod.wasm")).unwrap();
+    }
+
+    #[test]
+    fn test_denied_hash() {
+        let mut policy = ModulePolicy::new();
+        policy.set([hash(MODULE_A)], [], false).unwrap();
+
+        let e = policy.check(MODULE_B, None).unwrap_err();
+        assert_eq!(e.rule, PolicyRule::AllowedHashes);
+        assert_eq!(e.hash, hash(MODULE_B));
+        assert!(e.to_string().contains(&to_hex(&hash(MODULE_B))));
+
+        policy.set([hash(MODULE_A)], [], true).unwrap();
+        let e = policy
+            .check(MODULE_B, Some("res://wasm/b.wasm"))
+            .unwrap_err();
+        assert_eq!(e.rule, PolicyRule::DenyUnsigned);
+        assert!(e.to_string().contains("res://wasm/b.wasm"));
+    }
+
+    #[test]
+    fn test_allowed_paths() {
+        let mut policy = ModulePolicy::new();
+        policy
+            .set([hash(MODULE_A)], ["res://wasm/".to_string()], false)
+            .unwrap();
+
+        policy.check(MODULE_B, Some("res://wasm/b.wasm")).unwrap();
+        policy.check(MODULE_A, Some("user://a.wasm")).unwrap();
+        let e = policy.check(MODULE_B, Some("user://b.wasm")).unwrap_err();
+        assert_eq!(e.rule, PolicyRule::AllowedPaths);
+        let e = policy.check(MODULE_B, None).unwrap_err();
+        assert_eq!(e.rule, PolicyRule::AllowedPaths);
+    }
+
+    #[test]
+    fn test_frozen() {
+        let mut policy = ModulePolicy::new();
+        policy.set([hash(MODULE_A)], [], true).unwrap();
+        policy.freeze();
+        assert!(policy.is_frozen());
+
+        assert!(policy.set([hash(MODULE_B)], [], false).is_err());
+        assert!(policy.set([], [], false).is_err());
+        policy.check(MODULE_A, None).unwrap();
+        assert!(policy.check(MODULE_B, None).is_err());
+    }
+
+    #[test]
+    fn test_parse_hash() {
+        let h = hash(MODULE_A);
+        assert_eq!(parse_hash(&to_hex(&h)).unwrap(), h);
+        assert_eq!(parse_hash(&to_hex(&h).to_uppercase()).unwrap(), h);
+        assert!(parse_hash("abcd").is_err());
+        assert!(parse_hash(&"zz".repeat(32)).is_err());
+    }
+}
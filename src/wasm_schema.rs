@@ -0,0 +1,658 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult, Write as _};
+
+use godot::prelude::*;
+use wasmtime::component::types::{ComponentFunc, ComponentItem, ResourceType, Type};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+/// Component type description.
+///
+/// Converted into Godot value, or printed as JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    Nil,
+    Str(String),
+    List(Vec<Schema>),
+    /// Ordered map.
+    Map(Vec<(String, Schema)>),
+}
+
+impl Schema {
+    fn ty(name: &str) -> Self {
+        Self::Map(vec![("type".into(), Self::Str(name.into()))])
+    }
+
+    fn names<'a>(it: impl Iterator<Item = &'a str>) -> Self {
+        Self::List(it.map(|v| Self::Str(v.into())).collect())
+    }
+
+    fn with(mut self, key: &str, value: Self) -> Self {
+        if let Self::Map(v) = &mut self {
+            v.push((key.into(), value));
+        }
+        self
+    }
+
+    pub fn to_variant(&self) -> Variant {
+        match self {
+            Self::Nil => Variant::nil(),
+            Self::Str(v) => GString::from(v.as_str()).to_variant(),
+            Self::List(v) => v
+                .iter()
+                .map(|v| v.to_variant())
+                .collect::<VariantArray>()
+                .to_variant(),
+            Self::Map(v) => v
+                .iter()
+                .map(|(k, v)| (GString::from(k.as_str()), v.to_variant()))
+                .collect::<Dictionary>()
+                .to_variant(),
+        }
+    }
+
+    fn write_json(&self, f: &mut Formatter<'_>, indent: usize) -> FmtResult {
+        match self {
+            Self::Nil => write!(f, "null"),
+            Self::Str(v) => write!(f, "{v:?}"),
+            Self::List(v) if v.is_empty() => write!(f, "[]"),
+            Self::Map(v) if v.is_empty() => write!(f, "{{}}"),
+            Self::List(v) => {
+                writeln!(f, "[")?;
+                for (i, e) in v.iter().enumerate() {
+                    write!(f, "{:1$}", "", indent + 2)?;
+                    e.write_json(f, indent + 2)?;
+                    writeln!(f, "{}", if i + 1 < v.len() { "," } else { "" })?;
+                }
+                write!(f, "{:1$}]", "", indent)
+            }
+            Self::Map(v) => {
+                writeln!(f, "{{")?;
+                for (i, (k, e)) in v.iter().enumerate() {
+                    write!(f, "{:1$}{k:?}: ", "", indent + 2)?;
+                    e.write_json(f, indent + 2)?;
+                    writeln!(f, "{}", if i + 1 < v.len() { "," } else { "" })?;
+                }
+                write!(f, "{:1$}}}", "", indent)
+            }
+        }
+    }
+}
+
+impl Display for Schema {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        self.write_json(f, 0)
+    }
+}
+
+/// Names of resources imported and exported by component.
+#[derive(Default)]
+pub struct ResourceNames(Vec<(ResourceType, String)>);
+
+impl ResourceNames {
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|(_, v)| v.as_str())
+    }
+
+    fn name(&self, ty: &ResourceType) -> String {
+        self.0
+            .iter()
+            .find(|(k, _)| k == ty)
+            .map_or_else(|| "<unknown>".into(), |(_, v)| v.clone())
+    }
+}
+
+/// Exported functions of component.
+pub struct ComponentExports {
+    /// Functions with fully-qualified name, formatted as `interface#function`.
+    pub funcs: Vec<(String, ComponentFunc)>,
+    pub resources: ResourceNames,
+}
+
+impl ComponentExports {
+    pub fn new(comp: &Component) -> Self {
+        let engine = comp.engine();
+        let ty = comp.component_type();
+        let mut ret = Self {
+            funcs: Vec::new(),
+            resources: ResourceNames::default(),
+        };
+
+        for (name, item) in ty.imports(engine) {
+            ret.add(engine, None, name, item, false);
+        }
+        for (name, item) in ty.exports(engine) {
+            ret.add(engine, None, name, item, true);
+        }
+
+        ret
+    }
+
+    fn add(
+        &mut self,
+        engine: &Engine,
+        prefix: Option<&str>,
+        name: &str,
+        item: ComponentItem,
+        export: bool,
+    ) {
+        let qualified = || match prefix {
+            Some(p) => format!("{p}#{name}"),
+            None => name.to_string(),
+        };
+
+        match item {
+            ComponentItem::ComponentFunc(f) if export => self.funcs.push((qualified(), f)),
+            ComponentItem::Resource(v) => self.resources.0.push((v, qualified())),
+            ComponentItem::ComponentInstance(v) if prefix.is_none() => {
+                for (k, item) in v.exports(engine) {
+                    self.add(engine, Some(name), k, item, export);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ComponentFunc> {
+        self.funcs.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+    }
+
+    /// Gets signature of function.
+    ///
+    /// - `params` : Array of parameters, each with `name` and `type`.
+    /// - `results` : Array of result types.
+    pub fn func_schema(&self, f: &ComponentFunc) -> Schema {
+        Schema::Map(vec![
+            (
+                "params".into(),
+                Schema::List(
+                    f.params()
+                        .map(|(name, ty)| {
+                            Schema::Map(vec![
+                                ("name".into(), Schema::Str(name.into())),
+                                ("type".into(), self.type_schema(&ty)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "results".into(),
+                Schema::List(f.results().map(|ty| self.type_schema(&ty)).collect()),
+            ),
+        ])
+    }
+
+    /// Gets description of type.
+    ///
+    /// It's a dictionary with `type` key, and the remaining keys depends on it.
+    pub fn type_schema(&self, ty: &Type) -> Schema {
+        let opt = |v: Option<Type>| v.map_or(Schema::Nil, |v| self.type_schema(&v));
+
+        match ty {
+            Type::Bool => Schema::ty("bool"),
+            Type::S8 => Schema::ty("s8"),
+            Type::U8 => Schema::ty("u8"),
+            Type::S16 => Schema::ty("s16"),
+            Type::U16 => Schema::ty("u16"),
+            Type::S32 => Schema::ty("s32"),
+            Type::U32 => Schema::ty("u32"),
+            Type::S64 => Schema::ty("s64"),
+            Type::U64 => Schema::ty("u64"),
+            Type::Float32 => Schema::ty("f32"),
+            Type::Float64 => Schema::ty("f64"),
+            Type::Char => Schema::ty("char"),
+            Type::String => Schema::ty("string"),
+            Type::List(v) => Schema::ty("list").with("element", self.type_schema(&v.ty())),
+            Type::Record(v) => Schema::ty("record").with(
+                "fields",
+                Schema::Map(
+                    v.fields()
+                        .map(|f| (f.name.into(), self.type_schema(&f.ty)))
+                        .collect(),
+                ),
+            ),
+            Type::Tuple(v) => Schema::ty("tuple").with(
+                "types",
+                Schema::List(v.types().map(|v| self.type_schema(&v)).collect()),
+            ),
+            Type::Variant(v) => Schema::ty("variant").with(
+                "cases",
+                Schema::Map(v.cases().map(|c| (c.name.into(), opt(c.ty))).collect()),
+            ),
+            Type::Enum(v) => Schema::ty("enum").with("names", Schema::names(v.names())),
+            Type::Flags(v) => Schema::ty("flags").with("names", Schema::names(v.names())),
+            Type::Option(v) => Schema::ty("option").with("some", self.type_schema(&v.ty())),
+            Type::Result(v) => Schema::ty("result")
+                .with("ok", opt(v.ok()))
+                .with("err", opt(v.err())),
+            Type::Own(v) => Schema::ty("own").with("resource", Schema::Str(self.resources.name(v))),
+            Type::Borrow(v) => {
+                Schema::ty("borrow").with("resource", Schema::Str(self.resources.name(v)))
+            }
+        }
+    }
+
+    /// Gets signature of all functions, keyed by it's name.
+    pub fn schema(&self) -> Schema {
+        Schema::Map(
+            self.funcs
+                .iter()
+                .map(|(k, f)| (k.clone(), self.func_schema(f)))
+                .collect(),
+        )
+    }
+
+    /// Formats type in WIT syntax, for documentation.
+    fn wit_type(&self, ty: &Type) -> String {
+        let opt = |v: Option<Type>| v.map_or_else(|| "_".into(), |v| self.wit_type(&v));
+
+        match ty {
+            Type::Bool => "bool".into(),
+            Type::S8 => "s8".into(),
+            Type::U8 => "u8".into(),
+            Type::S16 => "s16".into(),
+            Type::U16 => "u16".into(),
+            Type::S32 => "s32".into(),
+            Type::U32 => "u32".into(),
+            Type::S64 => "s64".into(),
+            Type::U64 => "u64".into(),
+            Type::Float32 => "f32".into(),
+            Type::Float64 => "f64".into(),
+            Type::Char => "char".into(),
+            Type::String => "string".into(),
+            Type::List(v) => format!("list<{}>", self.wit_type(&v.ty())),
+            Type::Record(_) => "record".into(),
+            Type::Tuple(v) => format!(
+                "tuple<{}>",
+                v.types()
+                    .map(|v| self.wit_type(&v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Type::Variant(_) => "variant".into(),
+            Type::Enum(_) => "enum".into(),
+            Type::Flags(_) => "flags".into(),
+            Type::Option(v) => format!("option<{}>", self.wit_type(&v.ty())),
+            Type::Result(v) => match (v.ok(), v.err()) {
+                (None, None) => "result".into(),
+                (ok, err) => format!("result<{}, {}>", opt(ok), opt(err)),
+            },
+            Type::Own(v) => format!("own<{}>", self.resources.name(v)),
+            Type::Borrow(v) => format!("borrow<{}>", self.resources.name(v)),
+        }
+    }
+
+    /// Generates GDScript wrapper of all functions.
+    ///
+    /// There is one inner class per interface (functions outside of interface goes to `World`).
+    /// Classes and methods are sorted by name, so the output is stable.
+    pub fn gdscript_bindings(&self) -> String {
+        let mut groups = BTreeMap::<&str, Vec<(&str, &str, &ComponentFunc)>>::new();
+        for (name, f) in &self.funcs {
+            let name = name.as_str();
+            let (iface, method) = name.split_once('#').unwrap_or(("", name));
+            groups.entry(iface).or_default().push((name, method, f));
+        }
+        let classes = groups
+            .into_iter()
+            .map(|(iface, mut funcs)| {
+                funcs.sort_by_key(|&(_, m, _)| m);
+                if iface.is_empty() {
+                    ("World".to_string(), "world".to_string(), funcs)
+                } else {
+                    (gd_class(iface), gd_field(iface), funcs)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut ret = String::from(GD_HEADER);
+        for (class, field, _) in &classes {
+            writeln!(ret, "var {field}: {class}").unwrap();
+        }
+        ret += "\n\nfunc _init(target: WasmScriptLike) -> void:\n";
+        if classes.is_empty() {
+            ret += "\tpass\n";
+        }
+        for (class, field, _) in &classes {
+            writeln!(ret, "\t{field} = {class}.new(target)").unwrap();
+        }
+
+        for (class, _, funcs) in &classes {
+            write!(ret, "\n\nclass {class}:\n{GD_CLASS_BODY}").unwrap();
+            for &(name, method, f) in funcs {
+                self.gdscript_method(&mut ret, name, method, f);
+            }
+        }
+
+        ret
+    }
+
+    fn gdscript_method(&self, out: &mut String, name: &str, method: &str, f: &ComponentFunc) {
+        let params = f.params().collect::<Vec<_>>();
+        let results = f.results().collect::<Vec<_>>();
+
+        let sig = params
+            .iter()
+            .map(|(n, ty)| format!("{n}: {}", self.wit_type(ty)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sig_ret = match &results[..] {
+            [] => String::new(),
+            [ty] => format!(" -> {}", self.wit_type(ty)),
+            v => format!(
+                " -> ({})",
+                v.iter()
+                    .map(|ty| self.wit_type(ty))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let args = params
+            .iter()
+            .map(|(n, _)| gd_ident(n))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let typed_params = params
+            .iter()
+            .map(|(n, ty)| match gd_type(ty) {
+                Some((t, _)) => format!("{}: {t}", gd_ident(n)),
+                None => gd_ident(n),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let (ret_ty, default) = match &results[..] {
+            [] => ("void", ""),
+            [Type::Option(_) | Type::Result(_)] => ("Variant", "null"),
+            [ty] => gd_type(ty).unwrap_or(("Variant", "null")),
+            _ => ("Array", "[]"),
+        };
+
+        let ident = gd_ident(method);
+        writeln!(out, "\n\t## {method}({sig}){sig_ret}").unwrap();
+        writeln!(out, "\tfunc {ident}({typed_params}) -> {ret_ty}:").unwrap();
+        if results.is_empty() {
+            writeln!(out, "\t\t_call({name:?}, [{args}])").unwrap();
+            return;
+        }
+
+        writeln!(out, "\t\tvar ret = _call({name:?}, [{args}])").unwrap();
+        writeln!(out, "\t\tif ret == null:\n\t\t\treturn {default}").unwrap();
+        match &results[..] {
+            [Type::Result(_)] => {
+                writeln!(out, "\t\tvar r: Dictionary = ret[0]").unwrap();
+                writeln!(out, "\t\tif r.has(\"err\"):").unwrap();
+                writeln!(
+                    out,
+                    "\t\t\tpush_error(\"{method} returned error: %s\" % [r[\"err\"]])"
+                )
+                .unwrap();
+                writeln!(out, "\t\t\treturn null\n\t\treturn r.get(\"ok\")").unwrap();
+            }
+            [_] => writeln!(out, "\t\treturn ret[0]").unwrap(),
+            _ => writeln!(out, "\t\treturn ret").unwrap(),
+        }
+    }
+}
+
+const GD_HEADER: &str = "\
+# Generated by WasmBindgenTool, do not edit.
+#
+# Every call is routed through `WasmScriptLike.call_component(name, args)`.
+# It returns array of results, or null if call failed.
+extends RefCounted
+
+";
+
+const GD_CLASS_BODY: &str = "\
+\tvar _target: WasmScriptLike
+
+\tfunc _init(target: WasmScriptLike) -> void:
+\t\t_target = target
+
+\tfunc _call(name: String, args: Array) -> Variant:
+\t\tvar ret = _target.call_component(name, args)
+\t\tif ret == null:
+\t\t\tpush_error(\"Failed to call %s\" % name)
+\t\treturn ret
+";
+
+const GD_KEYWORDS: &[&str] = &[
+    "and",
+    "as",
+    "assert",
+    "await",
+    "break",
+    "breakpoint",
+    "class",
+    "class_name",
+    "const",
+    "continue",
+    "elif",
+    "else",
+    "enum",
+    "extends",
+    "for",
+    "func",
+    "if",
+    "in",
+    "is",
+    "match",
+    "namespace",
+    "not",
+    "or",
+    "pass",
+    "preload",
+    "return",
+    "self",
+    "signal",
+    "static",
+    "super",
+    "trait",
+    "var",
+    "void",
+    "when",
+    "while",
+    "yield",
+];
+
+/// Converts WIT name into GDScript identifier.
+///
+/// Resource functions are prefixed with resource name (eg. `[method]thing.get-id` into `thing_get_id`).
+fn gd_ident(name: &str) -> String {
+    let name = match name.split_once(']') {
+        Some(("[constructor", res)) => format!("{res}.new"),
+        Some((_, name)) => name.to_string(),
+        None => name.to_string(),
+    };
+    let mut ret = name.replace(['-', '.'], "_");
+    if GD_KEYWORDS.contains(&&*ret) {
+        ret.push('_');
+    }
+    ret
+}
+
+/// Splits interface name (without version) into words.
+fn interface_words(iface: &str) -> impl Iterator<Item = &str> {
+    let iface = iface.split_once('@').map_or(iface, |(v, _)| v);
+    iface
+        .split([':', '/', '-', '.', '_'])
+        .filter(|s| !s.is_empty())
+}
+
+/// Converts interface name into class name (eg. `wasi:cli/run` into `WasiCliRun`).
+fn gd_class(iface: &str) -> String {
+    interface_words(iface)
+        .map(|s| {
+            let mut it = s.chars();
+            it.next()
+                .map_or_else(String::new, |c| c.to_uppercase().chain(it).collect())
+        })
+        .collect()
+}
+
+/// Converts interface name into field name (eg. `wasi:cli/run` into `wasi_cli_run`).
+fn gd_field(iface: &str) -> String {
+    interface_words(iface)
+        .map(|s| s.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Gets GDScript type of component type and it's default value.
+///
+/// Returns `None` if there is no matching type.
+fn gd_type(ty: &Type) -> Option<(&'static str, &'static str)> {
+    Some(match ty {
+        Type::Bool => ("bool", "false"),
+        Type::S8
+        | Type::U8
+        | Type::S16
+        | Type::U16
+        | Type::S32
+        | Type::U32
+        | Type::S64
+        | Type::U64 => ("int", "0"),
+        Type::Float32 | Type::Float64 => ("float", "0.0"),
+        Type::Char | Type::String => ("String", "\"\""),
+        Type::List(v) if matches!(v.ty(), Type::U8) => ("PackedByteArray", "PackedByteArray()"),
+        Type::List(_) | Type::Tuple(_) => ("Array", "[]"),
+        Type::Record(_) => ("Dictionary", "{}"),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasmtime::Config;
+
+    const FIXTURE: &str = r#"
+(component
+  (core module $m
+    (memory (export "memory") 1)
+    (func (export "realloc") (param i32 i32 i32 i32) (result i32) unreachable)
+    (func (export "norm") (param f32 f32) (result f32) unreachable)
+    (func (export "count") (param i32 i32) (result i32) unreachable)
+    (func (export "parse") (param i32 i32) (result i32) unreachable)
+    (func (export "make") (param i32) (result i32) unreachable)
+    (func (export "thing-id") (param i32) (result i64) unreachable)
+    (func (export "ping") unreachable)
+  )
+  (core instance $i (instantiate $m))
+  (alias core export $i "memory" (core memory $mem))
+  (alias core export $i "realloc" (core func $realloc))
+
+  (type $point' (record (field "x" f32) (field "y" f32)))
+  (export $point "point" (type $point'))
+  (type $error' (enum "empty" "invalid"))
+  (export $error "error" (type $error'))
+  (type $mode' (flags "read" "write"))
+  (export $mode "mode" (type $mode'))
+  (type $thing' (resource (rep i32)))
+  (export $thing "thing" (type $thing'))
+
+  (func (export "norm") (param "p" $point) (result f32)
+    (canon lift (core func $i "norm")))
+  (func (export "count") (param "items" (list string)) (result u32)
+    (canon lift (core func $i "count") (memory $mem) (realloc $realloc)))
+  (func (export "parse") (param "s" string) (result (result (tuple u8 (option s64)) (error $error)))
+    (canon lift (core func $i "parse") (memory $mem) (realloc $realloc)))
+  (func (export "make") (param "m" $mode) (result (own $thing))
+    (canon lift (core func $i "make")))
+  (func (export "thing-id") (param "t" (borrow $thing)) (result u64)
+    (canon lift (core func $i "thing-id")))
+
+  (func $ping (canon lift (core func $i "ping")))
+  (instance $api (export "ping" (func $ping)))
+  (export "test:fixture/api@0.1.0" (instance $api))
+)
+"#;
+
+    #[test]
+    fn test_component_schema() {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config).unwrap();
+        let comp = Component::new(&engine, FIXTURE).unwrap();
+        let exports = ComponentExports::new(&comp);
+
+        assert_eq!(
+            exports.funcs.iter().map(|(k, _)| &**k).collect::<Vec<_>>(),
+            [
+                "test:fixture/api@0.1.0#ping",
+                "norm",
+                "count",
+                "parse",
+                "make",
+                "thing-id",
+            ]
+        );
+        assert!(exports.get("ping").is_none());
+        assert_eq!(
+            format!("{}\n", exports.schema()),
+            include_str!("testdata/component_schema.json")
+        );
+    }
+
+    #[test]
+    fn test_gdscript_bindings() {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config).unwrap();
+        let comp = Component::new(&engine, FIXTURE).unwrap();
+        let exports = ComponentExports::new(&comp);
+
+        let out = exports.gdscript_bindings();
+        assert_eq!(out, include_str!("testdata/component_bindings.gd"));
+        assert_eq!(out, ComponentExports::new(&comp).gdscript_bindings());
+
+        assert_eq!(gd_class("wasi:cli/run@0.2.0"), "WasiCliRun");
+        assert_eq!(gd_field("test:my-pkg/api"), "test_my_pkg_api");
+        assert_eq!(gd_ident("class"), "class_");
+        assert_eq!(gd_ident("[constructor]my-thing"), "my_thing_new");
+        assert_eq!(gd_ident("[method]thing.get-id"), "thing_get_id");
+        assert_eq!(gd_ident("[static]thing.make"), "thing_make");
+    }
+
+    #[test]
+    fn test_gdscript_bindings_calls() {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config).unwrap();
+        let comp = Component::new(&engine, FIXTURE).unwrap();
+        let exports = ComponentExports::new(&comp);
+        let out = exports.gdscript_bindings();
+
+        // Every exported function is called once, by it's qualified name and with all parameters.
+        let mut calls = out
+            .lines()
+            .filter_map(|l| {
+                l.trim()
+                    .trim_start_matches("var ret = ")
+                    .strip_prefix("_call(")
+            })
+            .collect::<Vec<_>>();
+        calls.sort();
+        let mut expect = exports
+            .funcs
+            .iter()
+            .map(|(name, f)| {
+                let args = f
+                    .params()
+                    .map(|(n, _)| gd_ident(n))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{name:?}, [{args}])")
+            })
+            .collect::<Vec<_>>();
+        expect.sort();
+        assert_eq!(calls, expect);
+
+        // Wrapper only calls methods of WasmScriptLike.
+        assert!(!out.contains(": Object"));
+        assert_eq!(out.matches("_target.").count(), 2);
+        assert_eq!(out.matches("_target.call_component(name, args)").count(), 2);
+    }
+}
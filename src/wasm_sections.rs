@@ -0,0 +1,101 @@
+use anyhow::Result as AnyResult;
+use wasmparser::{Parser, Payload};
+
+use crate::site_context;
+
+/// Default maximum size of retained custom section.
+pub const DEFAULT_MAX_BYTES: usize = 1 << 20;
+
+/// Custom section of module or component.
+#[derive(Debug, Clone)]
+pub struct CustomSection {
+    pub name: String,
+    pub size: usize,
+    /// Section content. `None` if it's larger than the cap.
+    pub data: Option<Vec<u8>>,
+}
+
+/// Reads custom sections of outermost module or component.
+///
+/// Sections of nested modules and components are skipped.
+/// Content of sections larger than `max_bytes` is not retained.
+pub fn read_custom_sections(bytes: &[u8], max_bytes: usize) -> AnyResult<Vec<CustomSection>> {
+    let mut ret = Vec::new();
+    let mut depth = 0usize;
+    for p in Parser::new(0).parse_all(bytes) {
+        match site_context!(p)? {
+            Payload::ModuleSection { .. } | Payload::ComponentSection { .. } => depth += 1,
+            Payload::End(_) => depth = depth.saturating_sub(1),
+            Payload::CustomSection(r) if depth == 0 => {
+                let data = r.data();
+                ret.push(CustomSection {
+                    name: r.name().to_string(),
+                    size: data.len(),
+                    data: (data.len() <= max_bytes).then(|| data.to_vec()),
+                });
+            }
+            _ => (),
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE_HEADER: &[u8] = b"\0asm\x01\0\0\0";
+    const COMPONENT_HEADER: &[u8] = b"\0asm\x0d\0\x01\0";
+
+    fn section(id: u8, content: &[u8]) -> Vec<u8> {
+        assert!(content.len() < 128);
+        let mut v = vec![id, content.len() as u8];
+        v.extend_from_slice(content);
+        v
+    }
+
+    fn custom(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut v = vec![name.len() as u8];
+        v.extend_from_slice(name.as_bytes());
+        v.extend_from_slice(data);
+        section(0, &v)
+    }
+
+    #[test]
+    fn test_core_sections() {
+        let meta = br#"{"name":"test","version":"1.0.0","permissions":[]}"#;
+        let mut m = MODULE_HEADER.to_vec();
+        m.extend(custom("godot-mod-meta", meta));
+        m.extend(custom("big", &[7; 100]));
+
+        let v = read_custom_sections(&m, 64).unwrap();
+        let names = v.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["godot-mod-meta", "big"]);
+        assert_eq!(v[0].data.as_deref(), Some(&meta[..]));
+        assert_eq!(v[1].size, 100);
+        assert_eq!(v[1].data, None);
+    }
+
+    #[test]
+    fn test_component_sections() {
+        let mut inner = MODULE_HEADER.to_vec();
+        inner.extend(custom("inner", b"skipped"));
+        let mut c = COMPONENT_HEADER.to_vec();
+        c.extend(custom("godot-mod-meta", b"outer"));
+        c.extend(section(1, &inner));
+        c.extend(custom("after", b""));
+
+        let v = read_custom_sections(&c, 64).unwrap();
+        let names = v.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["godot-mod-meta", "after"]);
+        assert_eq!(v[0].data.as_deref(), Some(&b"outer"[..]));
+        assert_eq!(v[1].data.as_deref(), Some(&b""[..]));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut m = MODULE_HEADER.to_vec();
+        m.extend([0, 10, 1]);
+        read_custom_sections(&m, 64).unwrap_err();
+    }
+}
@@ -0,0 +1,288 @@
+//! Headless smoke tests of example guests.
+//!
+//! Every built example in `example/wasm` is instantiated with default config
+//! and it's known entry points are called. No Godot object is created, so it runs with plain `cargo test`.
+//! Build examples first (`just deploy-wasm`), missing ones are skipped.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context, Result as AnyResult};
+use wasmtime::{Engine, ExternType, FuncType, Linker, Module, Store, Val, ValType};
+
+use crate::wasm_config::Config;
+use crate::wasm_engine::engine_config;
+use crate::wasm_instance::{instantiate_headless, StoreData};
+
+/// Known entry points, called in order if exported.
+const ENTRY_POINTS: &[&str] = &["config", "init", "setup", "process", "make_move", "main"];
+/// Number of times `process` is called.
+const PROCESS_STEPS: usize = 10;
+/// Maximum linear memory size after running all entry points.
+const MAX_MEMORY: usize = 512 << 20;
+/// Maximum bytes passed to host (or written to stdout/stderr).
+const MAX_OUTPUT: usize = 1 << 20;
+
+/// Finds built example modules.
+///
+/// Looks into deployed modules, workspace target directory, and per-example target directory.
+fn example_modules() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let examples = root.join("example/wasm");
+
+    let mut dirs = vec![examples.clone()];
+    let targets = [root.to_owned()].into_iter().chain(
+        fs::read_dir(&examples)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.path()),
+    );
+    for t in targets {
+        for p in ["debug", "release"] {
+            dirs.push(t.join("target/wasm32-unknown-unknown").join(p));
+        }
+    }
+
+    let mut ret = dirs
+        .into_iter()
+        .filter_map(|d| fs::read_dir(d).ok())
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "wasm"))
+        .collect::<Vec<_>>();
+    ret.sort();
+    ret
+}
+
+/// Gets arguments of entry point. Returns `None` if signature is unknown.
+fn entry_args(name: &str, ty: &FuncType) -> Option<Vec<Val>> {
+    let f64s = |v: &[f64]| v.iter().map(|v| Val::F64(v.to_bits())).collect();
+    let params = ty.params().collect::<Vec<_>>();
+    Some(match (name, &params[..]) {
+        ("config" | "init" | "main", []) => Vec::new(),
+        // Board width and height.
+        ("init", [ValType::I64, ValType::I64]) => vec![Val::I64(7), Val::I64(6)],
+        // Demo index.
+        ("init", [ValType::I64]) => vec![Val::I64(0)],
+        // Masses, lengths, timestep, and initial state.
+        ("setup", p) if p.len() == 9 && p.iter().all(|t| matches!(t, ValType::F64)) => {
+            f64s(&[1.0, 1.0, 1.0, 1.0, 1e-3, 1.0, 0.0, 0.5, 0.0])
+        }
+        ("process", [ValType::F64]) => f64s(&[1.0 / 60.0]),
+        ("make_move", [ValType::I64]) => vec![Val::I64(3)],
+        _ => return None,
+    })
+}
+
+/// Creates stub for every function import.
+///
+/// Stubs return zeroes. Calls with `(i32, i32)` arguments are treated as `(ptr, len)`,
+/// and `len` is added to `output`.
+fn stub_linker(
+    engine: &Engine,
+    module: &Module,
+    output: &Arc<AtomicUsize>,
+) -> AnyResult<Linker<StoreData>> {
+    let mut linker = Linker::new(engine);
+    for i in module.imports() {
+        let ExternType::Func(ty) = i.ty() else {
+            continue;
+        };
+        let zeroes = ty
+            .results()
+            .map(|t| {
+                Ok(match t {
+                    ValType::I32 => Val::I32(0),
+                    ValType::I64 => Val::I64(0),
+                    ValType::F32 => Val::F32(0),
+                    ValType::F64 => Val::F64(0),
+                    t => bail!(
+                        "Unsupported return type {t:?} of {}.{}",
+                        i.module(),
+                        i.name()
+                    ),
+                })
+            })
+            .collect::<AnyResult<Vec<_>>>()?;
+        let output = output.clone();
+        linker.func_new(i.module(), i.name(), ty, move |_, args, rets| {
+            if let [Val::I32(_), Val::I32(n)] = args {
+                output.fetch_add(*n as u32 as usize, Ordering::Relaxed);
+            }
+            rets.clone_from_slice(&zeroes);
+            Ok(())
+        })?;
+    }
+    Ok(linker)
+}
+
+fn run_core(engine: &Engine, module: &Module) -> AnyResult<()> {
+    let output = Arc::new(AtomicUsize::new(0));
+    let linker = stub_linker(engine, module, &output)?;
+    let mut store = Store::new(engine, StoreData::default());
+    // Epoch is never incremented, any deadline far enough works.
+    store.set_epoch_deadline(1 << 32);
+    let inst = instantiate_headless(&mut store, &Config::default(), module, &linker)?;
+    let memory = inst.get_memory(&mut store, "memory");
+
+    for &name in ENTRY_POINTS {
+        let Some(f) = inst.get_func(&mut store, name) else {
+            continue;
+        };
+        let ty = f.ty(&store);
+        let Some(args) = entry_args(name, &ty) else {
+            bail!("Unknown signature of {name:?}: {ty:?}")
+        };
+        let mut rets = ty.results().map(|_| Val::I32(0)).collect::<Vec<_>>();
+        let n = if name == "process" { PROCESS_STEPS } else { 1 };
+        for _ in 0..n {
+            f.call(&mut store, &args, &mut rets)
+                .with_context(|| format!("Calling {name:?}"))?;
+
+            // i32 returns are pointers into memory.
+            if let Some(m) = memory {
+                for r in &rets {
+                    if let Val::I32(p) = *r {
+                        ensure!(
+                            (p as u32 as usize) < m.data_size(&store),
+                            "{name:?} returns out of bound pointer {p:#x}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(m) = memory {
+        let n = m.data_size(&store);
+        ensure!(n <= MAX_MEMORY, "Memory grows too large ({n} bytes)");
+    }
+    let n = output.load(Ordering::Relaxed);
+    ensure!(n <= MAX_OUTPUT, "Output too large ({n} bytes)");
+    Ok(())
+}
+
+/// Runs WASI command component. Returns `false` if component needs Godot.
+#[cfg(feature = "wasi-preview2")]
+fn run_component(engine: &Engine, bytes: &[u8]) -> AnyResult<bool> {
+    use wasi_isolated_fs::bindings::{Command, LinkOptions};
+    use wasi_isolated_fs::context::WasiContext as WasiCtx;
+    use wasi_isolated_fs::stdio::StdoutCapture;
+    use wasmtime::component::{Component, Linker};
+
+    let comp = Component::from_binary(engine, bytes)?;
+    if comp
+        .component_type()
+        .imports(engine)
+        .any(|(n, _)| n.starts_with("godot:"))
+    {
+        return Ok(false);
+    }
+
+    let stdout = Arc::new(StdoutCapture::new(MAX_OUTPUT));
+    let stderr = Arc::new(StdoutCapture::new(MAX_OUTPUT));
+    let mut builder = WasiCtx::builder();
+    builder.stdout(stdout.clone())?.stderr(stderr.clone())?;
+    let mut store = Store::new(engine, builder.build()?);
+    // Epoch is never incremented, any deadline far enough works.
+    store.set_epoch_deadline(1 << 32);
+
+    let mut linker = Linker::new(engine);
+    Command::add_to_linker(&mut linker, &LinkOptions::default(), |v| v)?;
+    let cmd = Command::instantiate(&mut store, &comp, &linker)?;
+    if cmd.wasi_cli_run().call_run(&mut store)?.is_err() {
+        bail!("Command exited with error");
+    }
+
+    ensure!(!stdout.is_overflow(), "Stdout too large");
+    ensure!(!stderr.is_overflow(), "Stderr too large");
+    Ok(true)
+}
+
+fn run_example(engine: &Engine, path: &Path) -> AnyResult<()> {
+    let bytes = fs::read(path)?;
+
+    #[cfg(feature = "component-model")]
+    if wasmparser::Parser::is_component(&bytes) {
+        #[cfg(feature = "wasi-preview2")]
+        if !run_component(engine, &bytes)? {
+            println!("Skipping {}: needs Godot", path.display());
+        }
+        #[cfg(not(feature = "wasi-preview2"))]
+        println!("Skipping {}: needs feature wasi-preview2", path.display());
+        return Ok(());
+    }
+
+    run_core(engine, &Module::from_binary(engine, &bytes)?)
+}
+
+#[test]
+fn test_smoke_examples() {
+    let paths = example_modules();
+    if paths.is_empty() {
+        println!("No example module found, build them first");
+        return;
+    }
+
+    let engine = Engine::new(&engine_config()).unwrap();
+    let errors = paths
+        .iter()
+        .filter_map(|p| {
+            println!("Running {}", p.display());
+            run_example(&engine, p)
+                .err()
+                .map(|e| format!("{}: {e:?}", p.display()))
+        })
+        .collect::<Vec<_>>();
+    assert!(errors.is_empty(), "{}", errors.join("\n"));
+}
+
+/// Renders first frame of particle demo of `two_d_render`, returning it's pixels.
+fn particles_frame(engine: &Engine, module: &Module, seed: u64) -> AnyResult<Vec<u8>> {
+    let output = Arc::new(AtomicUsize::new(0));
+    let linker = stub_linker(engine, module, &output)?;
+    let mut store = Store::new(engine, StoreData::default());
+    store.set_epoch_deadline(1 << 32);
+    let config = Config {
+        rand_seed: Some(seed),
+        ..Config::default()
+    };
+    let inst = instantiate_headless(&mut store, &config, module, &linker)?;
+
+    inst.get_typed_func::<u64, ()>(&mut store, "init")?
+        .call(&mut store, 2)?;
+    let p = inst
+        .get_typed_func::<f64, u32>(&mut store, "process")?
+        .call(&mut store, 1.0 / 60.0)? as usize;
+
+    // ExportState is (width, height, colors_ptr, colors_cnt), all u32.
+    let data = inst.get_memory(&mut store, "memory").unwrap().data(&store);
+    let field = |i: usize| -> AnyResult<usize> {
+        let v = data.get(p + i * 4..p + i * 4 + 4).context("Out of bound")?;
+        Ok(u32::from_le_bytes(v.try_into()?) as usize)
+    };
+    let (ptr, n) = (field(2)?, field(3)?);
+    Ok(data.get(ptr..ptr + n * 4).context("Out of bound")?.to_vec())
+}
+
+#[test]
+fn test_rand_seed() {
+    let Some(path) = example_modules()
+        .into_iter()
+        .find(|p| p.file_name().is_some_and(|n| n == "two_d_render.wasm"))
+    else {
+        println!("two_d_render.wasm not found, build it first");
+        return;
+    };
+
+    let engine = Engine::new(&engine_config()).unwrap();
+    let module = Module::from_file(&engine, path).unwrap();
+    let a = particles_frame(&engine, &module, 42).unwrap();
+    assert!(!a.is_empty());
+    assert_eq!(a, particles_frame(&engine, &module, 42).unwrap());
+    assert_ne!(a, particles_frame(&engine, &module, 43).unwrap());
+}
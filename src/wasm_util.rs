@@ -1,40 +1,60 @@
 use std::borrow::Borrow;
 use std::cell::{Cell, UnsafeCell};
+use std::collections::hash_map::{Entry, HashMap};
+#[cfg(any(feature = "wasi", feature = "godot-component"))]
+use std::fs::{create_dir_all, File};
+#[cfg(any(feature = "wasi", feature = "godot-component"))]
+use std::io::{BufReader, BufWriter};
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 use std::ops::{Deref, DerefMut};
+#[cfg(any(feature = "wasi", feature = "godot-component"))]
+use std::path::Path;
 use std::ptr::NonNull;
 use std::rc::Rc;
+#[cfg(any(feature = "wasi", feature = "godot-component"))]
+use std::sync::Arc;
 #[cfg(feature = "epoch-timeout")]
 use std::time;
 
 use anyhow::{Error, Result as AnyResult};
 use cfg_if::cfg_if;
+#[cfg(any(feature = "wasi", feature = "godot-component"))]
+use godot::classes::ProjectSettings;
 use godot::classes::WeakRef;
 use godot::prelude::*;
-use tracing::{debug, info_span, instrument, Level};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tracing::{debug, info_span, instrument, warn, Level};
 #[cfg(feature = "wasi")]
 use wasi_isolated_fs::context::WasiContext as WasiCtx;
+#[cfg(any(feature = "wasi", feature = "godot-component"))]
+use wasi_isolated_fs::nondet::{NondetRecorder, NondetReplayer, NondetTap};
 #[cfg(feature = "epoch-timeout")]
 use wasmtime::UpdateDeadline;
 use wasmtime::{
-    AsContext, AsContextMut, Caller, Extern, Func, FuncType, Linker, RootScope, Store,
-    StoreContextMut, ValRaw, ValType,
+    AsContext, AsContextMut, Caller, Engine, Extern, ExternType, Func, FuncType, HeapType, Linker,
+    Module, RootScope, Store, StoreContextMut, Trap, ValRaw, ValType,
 };
 #[cfg(feature = "object-registry-extern")]
-use wasmtime::{ExternRef, HeapType, RefType};
+use wasmtime::{ExternRef, RefType};
 
+#[cfg(feature = "object-registry-extern")]
+use crate::godot_util::marshal_size;
 use crate::godot_util::{from_var_any, SendSyncWrapper};
-use crate::variant_dispatch;
-use crate::wasm_config::Config;
-use crate::wasm_engine::get_engine;
+use crate::wasm_abi::GuestAllocator;
+use crate::wasm_config::{Config, HostObject};
+use crate::wasm_engine::is_fuel_enabled;
 #[cfg(feature = "epoch-timeout")]
-use crate::wasm_engine::start_epoch;
+use crate::wasm_engine::{
+    current_epoch, epoch_slice, start_epoch, yield_to_priority, EpochDeadline,
+};
 #[cfg(feature = "object-registry-extern")]
 use crate::wasm_externref::{externref_to_variant, variant_to_externref};
 #[cfg(feature = "memory-limiter")]
 use crate::wasm_instance::MemoryLimit;
-use crate::wasm_instance::StoreData;
+use crate::wasm_instance::{CallFrame, InnerLock, StoreData};
+use crate::{variant_dispatch, warn_once};
 
 #[cfg(all(feature = "epoch-timeout", feature = "more-precise-timer"))]
 pub const EPOCH_MULTIPLIER: u64 = 1000;
@@ -54,6 +74,22 @@ pub const FILE_DIR: u32 = 2;
 #[cfg(feature = "wasi")]
 pub const FILE_LINK: u32 = 3;
 
+/// Gets name of file type, as returned by `WasiContext.file_is_exist`.
+#[cfg(feature = "wasi")]
+pub fn file_type_str(v: u32) -> Option<&'static str> {
+    Some(match v {
+        FILE_NOTEXIST => "notexist",
+        FILE_FILE => "file",
+        FILE_DIR => "dir",
+        FILE_LINK => "link",
+        _ => return None,
+    })
+}
+
+/// Buffer size of each direction of pipe created by `WasmInstance.connect_pipe`.
+#[cfg(feature = "wasi")]
+pub const PIPE_CAPACITY: usize = 1 << 16;
+
 pub const TYPE_I32: i64 = 1;
 pub const TYPE_I64: i64 = 2;
 pub const TYPE_F32: i64 = 3;
@@ -69,6 +105,17 @@ pub const EXTERNREF_MODULE: &str = "godot_object_v2";
 
 pub const MEMORY_EXPORT: &str = "memory";
 
+/// Module of builtin host imports. User-defined host module of the same name takes precedence.
+pub const HOST_MODULE: &str = "host";
+/// Builtin import returning nonzero if cancellation is requested.
+pub const HOST_IS_CANCELLED: &str = "is_cancelled";
+/// Builtin import returning process frame count.
+pub const HOST_FRAME_COUNT: &str = "frame_count";
+/// Builtin import returning physics frame count.
+pub const HOST_PHYSICS_TICK: &str = "physics_tick";
+/// Builtin import filling guest memory with random bytes.
+pub const HOST_RAND: &str = "rand";
+
 #[macro_export]
 macro_rules! bail_with_site {
     ($($t:tt)*) => {
@@ -104,6 +151,30 @@ pub fn add_site(e: Error, site: Site<'static>) -> Error {
 }
 */
 
+/// Identifiable kind of error, if any.
+///
+/// Guest stack overflow is `"stack_overflow"`,
+/// call blocked by Godot component filter is `"permission_denied"`.
+pub fn error_kind(e: &Error) -> Option<&'static str> {
+    #[cfg(feature = "godot-component")]
+    if e.is::<crate::godot_component::filter::FilterDeniedError>() {
+        return Some("permission_denied");
+    }
+
+    match e.downcast_ref::<Trap>()? {
+        Trap::StackOverflow => Some("stack_overflow"),
+        _ => None,
+    }
+}
+
+/// Formats error for reporting, prefixed by it's kind (eg. `[stack_overflow]`).
+pub fn format_error(e: &Error) -> String {
+    match error_kind(e) {
+        Some(k) => format!("[{k}] {e:?}"),
+        None => format!("{e:?}"),
+    }
+}
+
 #[macro_export]
 macro_rules! func_registry{
     ($head:literal, $($t:tt)*) => {
@@ -158,8 +229,78 @@ pub fn from_signature(sig: &FuncType) -> (PackedByteArray, PackedByteArray) {
     (params, results)
 }
 
-#[instrument(level = Level::TRACE, skip(params, results), ret)]
-pub fn to_signature(params: Variant, results: Variant, use_extern: bool) -> AnyResult<FuncType> {
+/// Describes the first reference type in signature that can't be converted from/into variant.
+///
+/// Externref is only convertible with feature `object-registry-extern` and native extern binding.
+/// Other reference types are never convertible.
+pub fn unsupported_ref_type(ty: &FuncType, _use_extern: bool) -> Option<String> {
+    ty.params().chain(ty.results()).find_map(|t| {
+        let ValType::Ref(r) = t else {
+            return None;
+        };
+        Some(match r.heap_type() {
+            #[cfg(feature = "object-registry-extern")]
+            HeapType::Extern if _use_extern => return None,
+            #[cfg(feature = "object-registry-extern")]
+            HeapType::Extern => {
+                "externref requires config extern.bindMode = \"native\"".to_string()
+            }
+            #[cfg(not(feature = "object-registry-extern"))]
+            HeapType::Extern => "externref requires feature object-registry-extern \
+                and config extern.bindMode = \"native\""
+                .to_string(),
+            HeapType::Func => "funcref is not supported".to_string(),
+            _ => format!("{r} is not supported"),
+        })
+    })
+}
+
+/// Finds exports and host imports of module whose signature has unconvertible reference type.
+///
+/// Other imports are linked as is, so they're not checked.
+/// Returns description of each of them.
+pub fn check_reference_types(
+    module: &Module,
+    use_extern: bool,
+    is_host: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    let imports = module.imports().filter_map(|i| match i.ty() {
+        ExternType::Func(ty) if is_host(i.module()) => {
+            Some((format!("import {}.{}", i.module(), i.name()), ty))
+        }
+        _ => None,
+    });
+    let exports = module.exports().filter_map(|i| match i.ty() {
+        ExternType::Func(ty) => Some((format!("export {}", i.name()), ty)),
+        _ => None,
+    });
+    imports
+        .chain(exports)
+        .filter_map(|(name, ty)| {
+            Some(format!(
+                "{name}: {}",
+                unsupported_ref_type(&ty, use_extern)?
+            ))
+        })
+        .collect()
+}
+
+/// Creates function that always fails, in place of host import with unconvertible signature.
+pub fn unsupported_import_stub<T: 'static>(
+    store: impl AsContextMut<Data = T>,
+    ty: FuncType,
+    msg: String,
+) -> Func {
+    Func::new(store, ty, move |_, _, _| Err(Error::msg(msg.clone())))
+}
+
+#[instrument(level = Level::TRACE, skip(engine, params, results), ret)]
+pub fn to_signature(
+    engine: &Engine,
+    params: Variant,
+    results: Variant,
+    use_extern: bool,
+) -> AnyResult<FuncType> {
     fn f(
         it: impl Iterator<Item = Result<i64, Error>>,
         _use_extern: bool,
@@ -202,7 +343,7 @@ pub fn to_signature(params: Variant, results: Variant, use_extern: bool) -> AnyR
         _ => bail_with_site!("Unconvertible value {results}"),
     })?;
 
-    Ok(FuncType::new(&site_context!(get_engine())?, p, r))
+    Ok(FuncType::new(engine, p, r))
 }
 
 // Mark this unsafe for future proofing.
@@ -233,13 +374,13 @@ pub unsafe fn to_raw<T: AsRef<StoreData>>(
         ValType::Ref(r)
             if matches!(r.heap_type(), HeapType::Extern) && _ctx.data().as_ref().use_extern =>
         {
-            ValRaw::externref(
-                match variant_to_externref(_ctx.as_context_mut(), v.clone())? {
-                    Some(v) => v.to_raw(_ctx)?,
-                    None if r.is_nullable() => 0,
-                    None => bail_with_site!("Converting null into non-nullable WASM type"),
-                },
-            )
+            site_context!(_ctx.data().as_ref().check_arg_size(marshal_size(v)))?;
+            let v = _ctx.data().as_ref().copy_mode.apply(v.clone());
+            ValRaw::externref(match variant_to_externref(_ctx.as_context_mut(), v)? {
+                Some(v) => v.to_raw(_ctx)?,
+                None if r.is_nullable() => 0,
+                None => bail_with_site!("Converting null into non-nullable WASM type"),
+            })
         }
         _ => bail_with_site!("Unsupported WASM type conversion {}", t),
     })
@@ -435,11 +576,21 @@ where
                     Ok(obj) => site_context!(from_var_any(obj.get_ref()))?,
                     Err(obj) => obj,
                 };
+                if !obj.is_instance_valid() {
+                    bail_with_site!("Host object freed");
+                }
+                let frame = CallFrame::Method {
+                    obj: obj.instance_id(),
+                    method: method.clone(),
+                };
                 ctx.data_mut()
                     .as_mut()
-                    .release_store(move || site_context!(obj.try_call(method, &p)))?
+                    .release_store_call(frame, move || site_context!(obj.try_call(method, &p)))??
             }
-            CallableEnum::Callable(c) => ctx.data_mut().as_mut().release_store(move || c.call(&p)),
+            CallableEnum::Callable(c) => ctx
+                .data_mut()
+                .as_mut()
+                .release_store_call(CallFrame::Callable(c.clone()), move || c.call(&p))?,
         };
 
         if let Some(msg) = ctx.data_mut().as_mut().error_signal.take() {
@@ -473,7 +624,7 @@ where
     unsafe { Func::new_unchecked(ctx, ty_cloned, f) }
 }
 
-fn process_func(dict: Dictionary, use_extern: bool) -> AnyResult<(FuncType, CallableEnum)> {
+fn process_signature(engine: &Engine, dict: &Dictionary, use_extern: bool) -> AnyResult<FuncType> {
     let Some(params) = dict.get(StringName::from(c"params")) else {
         bail_with_site!("Key \"params\" does not exist")
     };
@@ -481,6 +632,16 @@ fn process_func(dict: Dictionary, use_extern: bool) -> AnyResult<(FuncType, Call
         bail_with_site!("Key \"results\" does not exist")
     };
 
+    to_signature(engine, params, results, use_extern)
+}
+
+fn process_func(
+    engine: &Engine,
+    dict: Dictionary,
+    use_extern: bool,
+) -> AnyResult<(FuncType, CallableEnum)> {
+    let ty = process_signature(engine, &dict, use_extern)?;
+
     let callable = if let Some(c) = dict.get(StringName::from(c"callable")) {
         CallableEnum::Callable(site_context!(from_var_any(c))?)
     } else {
@@ -501,22 +662,32 @@ fn process_func(dict: Dictionary, use_extern: bool) -> AnyResult<(FuncType, Call
         )
     };
 
-    Ok((to_signature(params, results, use_extern)?, callable))
+    Ok((ty, callable))
 }
 
 pub struct HostModuleCache<T> {
     cache: Linker<T>,
     host: Dictionary,
+    objects: Vec<HostObject>,
+    /// Result of `get_wasm_signatures()` of host objects, keyed by namespace.
+    signatures: HashMap<String, Dictionary>,
 }
 
 impl<T: AsRef<StoreData> + AsMut<StoreData> + HasEpochTimeout> HostModuleCache<T> {
-    pub fn new(host: Dictionary) -> AnyResult<Self> {
+    pub fn new(engine: &Engine, host: Dictionary, objects: Vec<HostObject>) -> AnyResult<Self> {
         Ok(Self {
-            cache: Linker::new(&site_context!(get_engine())?),
+            cache: Linker::new(engine),
             host,
+            objects,
+            signatures: HashMap::new(),
         })
     }
 
+    /// Returns `true` if imports of module are provided by host dictionary or host object.
+    pub fn has_module(&self, module: &str) -> bool {
+        self.host.contains_key(module) || self.objects.iter().any(|o| o.namespace == module)
+    }
+
     pub fn get_extern(
         &mut self,
         mut ctx: StoreContextMut<'_, T>,
@@ -524,30 +695,80 @@ impl<T: AsRef<StoreData> + AsMut<StoreData> + HasEpochTimeout> HostModuleCache<T
         name: &str,
     ) -> AnyResult<Option<Extern>> {
         if let r @ Some(_) = self.cache.get(ctx.as_context_mut(), module, name) {
-            Ok(r)
-        } else if let Some(data) = self
+            return Ok(r);
+        }
+
+        cfg_if! {
+            if #[cfg(feature = "object-registry-extern")] {
+                let use_extern = ctx.as_context_mut().data().as_ref().use_extern;
+            } else {
+                let use_extern = false;
+            }
+        }
+        let (sig, callable) = if let Some(data) = self
             .host
             .get(module)
             .map(|d| site_context!(from_var_any::<Dictionary>(d)))
             .transpose()?
             .and_then(|d| d.get(name))
         {
-            cfg_if! {
-                if #[cfg(feature = "object-registry-extern")] {
-                    let use_extern = ctx.as_context_mut().data().as_ref().use_extern;
-                } else {
-                    let use_extern = false;
+            process_func(
+                ctx.engine(),
+                site_context!(from_var_any::<Dictionary>(data))?,
+                use_extern,
+            )?
+        } else if let Some(o) = self.objects.iter().find(|o| o.namespace == module) {
+            if o.methods
+                .as_ref()
+                .is_some_and(|v| !v.iter().any(|v| v == name))
+            {
+                return Ok(None);
+            }
+
+            let sigs = match self.signatures.entry(module.to_string()) {
+                Entry::Occupied(v) => v.into_mut(),
+                Entry::Vacant(v) => {
+                    let mut obj = o.object.clone();
+                    let method = StringName::from(c"get_wasm_signatures");
+                    if !obj.has_method(&method) {
+                        bail_with_site!("Host object {module:?} has no get_wasm_signatures()");
+                    }
+                    v.insert(site_context!(from_var_any::<Dictionary>(
+                        obj.call(&method, &[])
+                    ))?)
                 }
+            };
+            let Some(data) = sigs.get(name) else {
+                warn!(
+                    module,
+                    name, "Host object method has no signature, skipping."
+                );
+                warn_once!(
+                    format!("host_signature.{module}.{name}"),
+                    "Host object method {module}.{name} has no signature, skipping."
+                );
+                return Ok(None);
+            };
+            let method = StringName::from(name);
+            if !o.object.has_method(&method) {
+                bail_with_site!("Host object {module:?} has no method {name:?}");
             }
-            let (sig, callable) =
-                process_func(site_context!(from_var_any::<Dictionary>(data))?, use_extern)?;
 
-            let v = Extern::from(wrap_godot_method(ctx.as_context_mut(), sig, callable));
-            self.cache.define(ctx, module, name, v.clone())?;
-            Ok(Some(v))
+            (
+                process_signature(
+                    ctx.engine(),
+                    &site_context!(from_var_any::<Dictionary>(data))?,
+                    use_extern,
+                )?,
+                CallableEnum::ObjectMethod(o.object.clone(), method),
+            )
         } else {
-            Ok(None)
-        }
+            return Ok(None);
+        };
+
+        let v = Extern::from(wrap_godot_method(ctx.as_context_mut(), sig, callable));
+        self.cache.define(ctx, module, name, v.clone())?;
+        Ok(Some(v))
     }
 }
 
@@ -558,20 +779,46 @@ pub fn config_store_epoch<T: HasEpochTimeout>(
     config: &Config,
 ) -> AnyResult<()> {
     if config.with_epoch {
-        store.epoch_deadline_trap();
+        // Deadline is split into slices, so lower priority store can yield to higher priority one.
+        store.epoch_deadline_callback(|mut ctx| {
+            if ctx.data().is_interrupted() {
+                return Err(Trap::Interrupt.into());
+            }
+            let v = *ctx.data_mut().get_epoch_deadline();
+            let Some(t) = v.next_slice(current_epoch()) else {
+                return Err(Trap::Interrupt.into());
+            };
+            yield_to_priority(v.priority);
+            Ok(UpdateDeadline::Continue(t))
+        });
         site_context!(start_epoch())?;
     } else {
         store.epoch_deadline_callback(|_| Ok(UpdateDeadline::Continue(EPOCH_DEADLINE)));
     }
+    let deadline = store.data_mut().get_epoch_deadline();
+    deadline.priority = config.epoch_priority;
+    deadline.timed = config.with_epoch;
     reset_epoch(store.as_context_mut());
     Ok(())
 }
 
+/// Fills store with fuel, if engine is configured to consume it.
+///
+/// Fuel is not used as limit (yet), so store gets as much as possible.
+pub fn config_store_fuel<T>(store: &mut Store<T>) -> AnyResult<()> {
+    if is_fuel_enabled() {
+        store.set_fuel(u64::MAX)?;
+    }
+    Ok(())
+}
+
 #[instrument(level = Level::TRACE, skip_all)]
 pub fn config_store_common<T>(_store: &mut Store<T>, _config: &Config) -> AnyResult<()>
 where
     T: AsRef<StoreData> + AsMut<StoreData> + HasEpochTimeout,
 {
+    config_store_fuel(&mut *_store)?;
+
     #[cfg(feature = "epoch-timeout")]
     {
         config_store_epoch(&mut *_store, _config)?;
@@ -584,18 +831,74 @@ where
         data.epoch_autoreset = _config.epoch_autoreset;
     }
 
+    {
+        let data: &mut StoreData = _store.data_mut().as_mut();
+        data.copy_mode = _config.copy_mode;
+        data.rng = _config.rand_seed.map(StdRng::seed_from_u64);
+        data.marshal_limits = _config.marshal_limits;
+        data.guest_alloc = GuestAllocator::new(
+            _config.abi_malloc.clone(),
+            _config.abi_free.clone(),
+            _config.abi_track_allocations,
+        );
+        let lock = AsMut::<InnerLock>::as_mut(data);
+        lock.object_calls = _config.object_calls;
+        if let Some(v) = _config.max_reentrancy {
+            lock.reentrancy.max_depth = v;
+        }
+    }
+
     #[cfg(feature = "memory-limiter")]
     {
-        _store.data_mut().as_mut().memory_limits = MemoryLimit::from_config(_config);
+        let data = _store.data_mut().as_mut();
+        data.memory_limits = MemoryLimit::from_config(_config, data.memory_limits.instance);
         _store.limiter(|data| &mut data.as_mut().memory_limits);
     }
 
     Ok(())
 }
 
+#[cfg(any(feature = "wasi", feature = "godot-component"))]
+#[instrument(level = Level::DEBUG, skip(config))]
+pub fn open_nondet_tap(config: &Config) -> AnyResult<Option<Arc<dyn NondetTap>>> {
+    let globalize = |p: &str| {
+        ProjectSettings::singleton()
+            .globalize_path(&GString::from(p))
+            .to_string()
+    };
+
+    match (&config.record_path, &config.replay_path) {
+        (Some(_), Some(_)) => bail_with_site!("Cannot record and replay at the same time"),
+        (Some(path), None) => {
+            let path = globalize(path);
+            if let Some(p) = Path::new(&path).parent() {
+                site_context!(create_dir_all(p))?;
+            }
+            let f = site_context!(File::create(&path))?;
+            Ok(Some(Arc::new(site_context!(NondetRecorder::new(
+                BufWriter::new(f)
+            ))?)))
+        }
+        (None, Some(path)) => {
+            let f = site_context!(File::open(globalize(path)))?;
+            Ok(Some(Arc::new(site_context!(NondetReplayer::new(
+                BufReader::new(f)
+            ))?)))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
 pub trait HasEpochTimeout {
     #[cfg(feature = "epoch-timeout")]
     fn get_epoch_timeout(&self) -> u64;
+    #[cfg(feature = "epoch-timeout")]
+    fn get_epoch_deadline(&mut self) -> &mut EpochDeadline;
+    /// Returns `true` if running call should trap at next deadline check.
+    #[cfg(feature = "epoch-timeout")]
+    fn is_interrupted(&self) -> bool {
+        false
+    }
     #[cfg(feature = "wasi")]
     fn get_wasi_ctx(&mut self) -> Option<&mut WasiCtx>;
 }
@@ -616,5 +919,239 @@ pub fn reset_epoch<T: HasEpochTimeout>(mut ctx: StoreContextMut<'_, T>) {
         ctx.set_timeout(time::Instant::now() + (d + EPOCH_INTERVAL));
     }
 
-    ctx.set_epoch_deadline(t);
+    let deadline = v.get_epoch_deadline();
+    deadline.end = current_epoch().saturating_add(t);
+    let slice = epoch_slice(deadline.priority);
+    ctx.set_epoch_deadline(t.min(slice));
+}
+
+/// CPU time consumed by current thread.
+///
+/// Returns `None` if thread CPU clock is unavailable.
+#[cfg(unix)]
+fn thread_cpu_time() -> Option<std::time::Duration> {
+    let mut t = MaybeUninit::<libc::timespec>::uninit();
+    // SAFETY: Pointer is valid for write.
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, t.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: Initialized by clock_gettime.
+    let t = unsafe { t.assume_init() };
+    Some(std::time::Duration::new(t.tv_sec as _, t.tv_nsec as _))
+}
+
+#[cfg(not(unix))]
+fn thread_cpu_time() -> Option<std::time::Duration> {
+    None
+}
+
+/// Measures CPU time of current thread.
+///
+/// Falls back to wall time if thread CPU clock is unavailable.
+/// Must be stopped in the same thread it's started.
+pub struct CpuStopwatch {
+    cpu: Option<std::time::Duration>,
+    wall: std::time::Instant,
+}
+
+impl CpuStopwatch {
+    pub fn start() -> Self {
+        Self {
+            cpu: thread_cpu_time(),
+            wall: std::time::Instant::now(),
+        }
+    }
+
+    /// Time elapsed since start, in microseconds.
+    pub fn elapsed_usec(&self) -> u64 {
+        let d = match (self.cpu, thread_cpu_time()) {
+            (Some(s), Some(e)) => e.saturating_sub(s),
+            _ => self.wall.elapsed(),
+        };
+        d.as_micros().try_into().unwrap_or(u64::MAX)
+    }
+}
+
+/// Decodes little-endian UTF-16 bytes. Unpaired surrogates are replaced with U+FFFD.
+///
+/// Returns decoded string and number of replaced characters.
+pub fn decode_utf16_lossy(s: &[u8]) -> (String, usize) {
+    let mut errors = 0;
+    let ret = char::decode_utf16(s.chunks_exact(2).map(|v| u16::from_le_bytes([v[0], v[1]])))
+        .map(|c| {
+            c.unwrap_or_else(|_| {
+                errors += 1;
+                char::REPLACEMENT_CHARACTER
+            })
+        })
+        .collect();
+    (ret, errors)
+}
+
+/// Encodes string into little-endian UTF-16 bytes.
+pub fn encode_utf16(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Decodes Latin-1 (ISO 8859-1) bytes.
+pub fn decode_latin1(s: &[u8]) -> String {
+    s.iter().map(|&c| char::from(c)).collect()
+}
+
+/// Encodes string into Latin-1 (ISO 8859-1) bytes. Unrepresentable characters are replaced with `?`.
+///
+/// Returns encoded bytes and number of replaced characters.
+pub fn encode_latin1_lossy(s: &str) -> (Vec<u8>, usize) {
+    let mut errors = 0;
+    let ret = s
+        .chars()
+        .map(|c| {
+            u8::try_from(c).unwrap_or_else(|_| {
+                errors += 1;
+                b'?'
+            })
+        })
+        .collect();
+    (ret, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf16_roundtrip() {
+        for s in ["", "abc", "héllo wörld", "日本語", "🦀 crab 🎉"] {
+            assert_eq!(decode_utf16_lossy(&encode_utf16(s)), (s.to_string(), 0));
+        }
+    }
+
+    #[test]
+    fn test_utf16_invalid() {
+        // Lone high surrogate, lone low surrogate, reversed pair.
+        let units: [u16; 6] = [0xd83e, 0x61, 0xdd80, 0x62, 0xdd80, 0xd83e];
+        let b: Vec<u8> = units.iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(
+            decode_utf16_lossy(&b),
+            ("\u{fffd}a\u{fffd}b\u{fffd}\u{fffd}".to_string(), 4)
+        );
+
+        // Trailing odd byte is ignored.
+        assert_eq!(decode_utf16_lossy(b"a\0b"), ("a".to_string(), 0));
+    }
+
+    #[test]
+    fn test_latin1() {
+        let b: Vec<u8> = (0..=255).collect();
+        let s = decode_latin1(&b);
+        assert_eq!(s.chars().count(), 256);
+        assert_eq!(encode_latin1_lossy(&s), (b, 0));
+
+        assert_eq!(
+            encode_latin1_lossy("café €1 🦀"),
+            (b"caf\xe9 ?1 ?".to_vec(), 2)
+        );
+    }
+
+    #[cfg(feature = "wasi")]
+    #[test]
+    fn test_file_type_str() {
+        assert_eq!(file_type_str(FILE_NOTEXIST), Some("notexist"));
+        assert_eq!(file_type_str(FILE_FILE), Some("file"));
+        assert_eq!(file_type_str(FILE_DIR), Some("dir"));
+        assert_eq!(file_type_str(FILE_LINK), Some("link"));
+        assert_eq!(file_type_str(4), None);
+    }
+
+    #[test]
+    fn test_cpu_stopwatch() {
+        let wall = std::time::Instant::now();
+        let t = CpuStopwatch::start();
+        let mut v = 0u64;
+        while wall.elapsed() < std::time::Duration::from_millis(200) {
+            v = std::hint::black_box(v.wrapping_add(1));
+        }
+        let (busy, busy_wall) = (t.elapsed_usec(), wall.elapsed().as_micros() as u64);
+        assert!(busy >= busy_wall / 2, "busy {busy} wall {busy_wall}");
+
+        let wall = std::time::Instant::now();
+        let t = CpuStopwatch::start();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let (idle, idle_wall) = (t.elapsed_usec(), wall.elapsed().as_micros() as u64);
+        if cfg!(unix) {
+            assert!(idle < idle_wall / 4, "idle {idle} wall {idle_wall}");
+        }
+    }
+
+    /// Module using reference types in host import, dependency import, and exports.
+    const WAT_REFTYPES: &str = r#"(module
+        (import "host" "take" (func $take (param externref)))
+        (import "dep" "take" (func (param externref)))
+        (func (export "call_take") (call $take (ref.null extern)))
+        (func (export "pass") (param externref) (result externref) local.get 0)
+        (func (export "get_func") (result funcref) ref.null func)
+        (func (export "add") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1)))
+    )"#;
+
+    #[test]
+    fn test_reference_types_strict() {
+        let engine = Engine::default();
+        let module = Module::new(&engine, WAT_REFTYPES).unwrap();
+
+        let r = check_reference_types(&module, false, |m| m == "host");
+        assert_eq!(r.len(), 3, "{r:?}");
+        assert!(
+            r[0].starts_with("import host.take: externref requires"),
+            "{r:?}"
+        );
+        assert!(r[1].starts_with("export pass: externref requires"), "{r:?}");
+        assert_eq!(r[2], "export get_func: funcref is not supported");
+
+        // Externref is only convertible with native binding.
+        let r = check_reference_types(&module, true, |m| m == "host");
+        if cfg!(feature = "object-registry-extern") {
+            assert_eq!(r, ["export get_func: funcref is not supported"]);
+        } else {
+            assert_eq!(r.len(), 3, "{r:?}");
+        }
+    }
+
+    #[test]
+    fn test_reference_types_lenient() {
+        let engine = Engine::default();
+        let module = Module::new(&engine, WAT_REFTYPES).unwrap();
+        let mut store = Store::new(&engine, ());
+
+        let imports = module
+            .imports()
+            .map(|i| {
+                let ExternType::Func(ty) = i.ty() else {
+                    unreachable!()
+                };
+                let msg = format!("Host import {}.{} is skipped", i.module(), i.name());
+                unsupported_import_stub(&mut store, ty, msg).into()
+            })
+            .collect::<Vec<Extern>>();
+        let inst = wasmtime::Instance::new(&mut store, &module, &imports).unwrap();
+
+        let e = inst
+            .get_typed_func::<(), ()>(&mut store, "call_take")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap_err();
+        assert!(
+            format!("{e:?}").contains("Host import host.take is skipped"),
+            "{e:?}"
+        );
+
+        // Other functions still work.
+        let f = inst
+            .get_typed_func::<(i32, i32), i32>(&mut store, "add")
+            .unwrap();
+        assert_eq!(f.call(&mut store, (1, 2)).unwrap(), 3);
+        assert!(unsupported_ref_type(&f.func().ty(&store), false).is_none());
+        let f = inst.get_func(&mut store, "pass").unwrap();
+        assert!(unsupported_ref_type(&f.ty(&store), false).is_some());
+    }
 }